@@ -87,6 +87,12 @@
 // Env variable to override lnd binary set:
 pub const FM_LND_BASE_EXECUTABLE_ENV: &str = "FM_LND_BASE_EXECUTABLE";
 
+// Env variable to override lightningd binary set:
+pub const FM_LIGHTNINGD_BASE_EXECUTABLE_ENV: &str = "FM_LIGHTNINGD_BASE_EXECUTABLE";
+
+// Env variable to override fedimint-cln-plugin binary set:
+pub const FM_CLN_PLUGIN_BASE_EXECUTABLE_ENV: &str = "FM_CLN_PLUGIN_BASE_EXECUTABLE";
+
 // Env variable to override esplora binary set:
 pub const FM_ESPLORA_BASE_EXECUTABLE_ENV: &str = "FM_ESPLORA_BASE_EXECUTABLE";
 
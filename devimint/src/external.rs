@@ -681,6 +681,60 @@ pub async fn settle_hold_invoice(
     }
 }
 
+#[derive(Clone)]
+pub struct Cln {
+    pub(crate) process: ProcessHandle,
+    pub(crate) _bitcoind: Bitcoind,
+}
+
+impl Cln {
+    pub async fn new(process_mgr: &ProcessManager, bitcoind: Bitcoind) -> Result<Self> {
+        let process = Cln::start(process_mgr).await?;
+        Ok(Self {
+            _bitcoind: bitcoind,
+            process,
+        })
+    }
+
+    pub async fn start(process_mgr: &ProcessManager) -> Result<ProcessHandle> {
+        let plugin = crate::util::get_cln_plugin_path()?;
+        let conf = format!(
+            include_str!("cfg/cln.conf"),
+            listen_port = process_mgr.globals.FM_PORT_CLN_LISTEN,
+            btc_rpc_port = process_mgr.globals.FM_PORT_BTC_RPC,
+            plugin = utf8(&plugin),
+        );
+        write_overwrite_async(process_mgr.globals.FM_CLN_DIR.join("config"), conf).await?;
+        let cmd = cmd!(
+            crate::util::Lightningd,
+            format!("--lightning-dir={}", utf8(&process_mgr.globals.FM_CLN_DIR))
+        );
+
+        let process = process_mgr.spawn_daemon("lightningd", cmd).await?;
+        let cln_rpc_path = &process_mgr.globals.FM_CLN_RPC_PATH;
+        poll("wait for lightningd rpc socket", || async {
+            if fs::try_exists(cln_rpc_path)
+                .await
+                .context("lightningd rpc socket")
+                .map_err(ControlFlow::Continue)?
+            {
+                Ok(())
+            } else {
+                Err(ControlFlow::Continue(anyhow!(
+                    "lightningd rpc socket not found"
+                )))
+            }
+        })
+        .await?;
+
+        Ok(process)
+    }
+
+    pub async fn terminate(self) -> Result<()> {
+        self.process.terminate().await
+    }
+}
+
 pub type NamedGateway<'a> = (&'a Gatewayd, &'a str);
 
 #[allow(clippy::similar_names)]
@@ -790,6 +844,7 @@ async fn wait_for_ready_channel_on_gateway_with_counterparty(
 #[derive(Clone)]
 pub enum LightningNode {
     Lnd(Lnd),
+    Cln(Cln),
     Ldk {
         name: String,
         gw_port: u16,
@@ -802,6 +857,7 @@ impl LightningNode {
     pub fn ln_type(&self) -> LightningNodeType {
         match self {
             LightningNode::Lnd(_) => LightningNodeType::Lnd,
+            LightningNode::Cln(_) => LightningNodeType::Cln,
             LightningNode::Ldk {
                 name: _,
                 gw_port: _,
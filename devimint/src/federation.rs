@@ -28,6 +28,7 @@
 use fedimint_testing_core::node_type::LightningNodeType;
 use fedimint_wallet_client::WalletClientModule;
 use fedimint_wallet_client::config::WalletClientConfig;
+use fedimintd_envs::{FM_BITCOIN_P2P_PEERS_ENV, FM_BITCOIND_URL_ENV, FM_ESPLORA_URL_ENV};
 use fs_lock::FileLock;
 use futures::future::{join_all, try_join_all};
 use tokio::task::{JoinSet, spawn_blocking};
@@ -715,6 +716,30 @@ pub async fn start_server(&mut self, process_mgr: &ProcessManager, peer: usize)
         Ok(())
     }
 
+    /// Starts the peer such that it follows the chain of our bitcoind via
+    /// compact block filters over the bitcoin P2P network instead of its RPC.
+    pub async fn start_server_with_bitcoin_p2p(
+        &mut self,
+        process_mgr: &ProcessManager,
+        peer: usize,
+    ) -> Result<()> {
+        if self.members.contains_key(&peer) {
+            bail!("fedimintd-{peer} already running");
+        }
+        self.members.insert(
+            peer,
+            Fedimintd::new_with_bitcoin_p2p(
+                process_mgr,
+                self.bitcoind.clone(),
+                peer,
+                &self.vars[&peer],
+                "default".to_string(),
+            )
+            .await?,
+        );
+        Ok(())
+    }
+
     pub async fn terminate_server(&mut self, peer_id: usize) -> Result<()> {
         let Some((_, fedimintd)) = self.members.remove_entry(&peer_id) else {
             bail!("fedimintd-{peer_id} does not exist");
@@ -1435,13 +1460,50 @@ pub async fn new(
         peer_id: usize,
         env: &vars::Fedimintd,
         fed_name: String,
+    ) -> Result<Self> {
+        Self::spawn(
+            process_mgr,
+            bitcoind,
+            peer_id,
+            fed_name,
+            cmd!(FedimintdCmd).envs(env.vars()),
+        )
+        .await
+    }
+
+    pub async fn new_with_bitcoin_p2p(
+        process_mgr: &ProcessManager,
+        bitcoind: Bitcoind,
+        peer_id: usize,
+        env: &vars::Fedimintd,
+        fed_name: String,
+    ) -> Result<Self> {
+        let p2p_peer = format!("[::1]:{}", process_mgr.globals.FM_PORT_BTC_P2P);
+
+        Self::spawn(
+            process_mgr,
+            bitcoind,
+            peer_id,
+            fed_name,
+            cmd!(FedimintdCmd)
+                .envs(env.vars())
+                .env_remove(FM_BITCOIND_URL_ENV)
+                .env_remove(FM_ESPLORA_URL_ENV)
+                .env(FM_BITCOIN_P2P_PEERS_ENV, p2p_peer),
+        )
+        .await
+    }
+
+    async fn spawn(
+        process_mgr: &ProcessManager,
+        bitcoind: Bitcoind,
+        peer_id: usize,
+        fed_name: String,
+        cmd: Command,
     ) -> Result<Self> {
         debug!(target: LOG_DEVIMINT, "Starting fedimintd-{fed_name}-{peer_id}");
         let process = process_mgr
-            .spawn_daemon(
-                &format!("fedimintd-{fed_name}-{peer_id}"),
-                cmd!(FedimintdCmd).envs(env.vars()),
-            )
+            .spawn_daemon(&format!("fedimintd-{fed_name}-{peer_id}"), cmd)
             .await?;
 
         Ok(Self {
@@ -835,6 +835,12 @@ pub async fn new(
                 process_mgr.globals.FM_PORT_LND_LISTEN,
                 process_mgr.globals.FM_PORT_GW_LND_METRICS,
             ),
+            LightningNode::Cln(_) => (
+                "gatewayd-cln".to_string(),
+                process_mgr.globals.FM_PORT_GW_CLN,
+                process_mgr.globals.FM_PORT_CLN_LISTEN,
+                process_mgr.globals.FM_PORT_GW_CLN_METRICS,
+            ),
             LightningNode::Ldk {
                 name,
                 gw_port,
@@ -984,6 +990,7 @@ pub async fn stop_lightning_node(&mut self) -> Result<()> {
         info!(target: LOG_DEVIMINT, "Stopping lightning node");
         match self.ln.clone() {
             LightningNode::Lnd(lnd) => lnd.terminate().await,
+            LightningNode::Cln(cln) => cln.terminate().await,
             LightningNode::Ldk {
                 name: _,
                 gw_port: _,
@@ -18,6 +18,7 @@ pub struct Recurringdv2 {
 
 impl Recurringdv2 {
     pub async fn new(process_mgr: &ProcessManager) -> Result<Self> {
+        let test_dir = &process_mgr.globals.FM_TEST_DIR;
         let port = process_mgr.globals.FM_PORT_RECURRINGDV2;
         let bind_address = format!("127.0.0.1:{port}");
         let api_url = SafeUrl::parse(&format!("http://{bind_address}/")).expect("Valid URL");
@@ -30,7 +31,9 @@ pub async fn new(process_mgr: &ProcessManager) -> Result<Self> {
                     "--bind-api",
                     bind_address.clone(),
                     "--api-address",
-                    api_url.to_string()
+                    api_url.to_string(),
+                    "--data-dir",
+                    format!("{}/recurringdv2", test_dir.display())
                 ),
             )
             .await?;
@@ -1860,7 +1860,7 @@ pub async fn do_try_create_and_pay_invoice(
     .invoice;
 
     match &gw_lnd.ln.ln_type() {
-        LightningNodeType::Lnd => {
+        LightningNodeType::Lnd | LightningNodeType::Cln => {
             // Pay the invoice using LDK
             gw_ldk
                 .client()
@@ -1868,7 +1868,7 @@ pub async fn do_try_create_and_pay_invoice(
                 .await?;
         }
         LightningNodeType::Ldk => {
-            unimplemented!("do_try_create_and_pay_invoice not implemented for LDK yet");
+            bail!("do_try_create_and_pay_invoice requires a gateway that is not backed by LDK");
         }
     }
     Ok(())
@@ -2021,6 +2021,36 @@ pub async fn reconnect_test(dev_fed: DevFed, process_mgr: &ProcessManager) -> Re
     Ok(())
 }
 
+pub async fn bitcoin_p2p_backend_test(dev_fed: DevFed, process_mgr: &ProcessManager) -> Result<()> {
+    log_binary_versions().await?;
+
+    let DevFed {
+        bitcoind, mut fed, ..
+    } = dev_fed;
+
+    bitcoind.mine_blocks(110).await?;
+    fed.await_block_sync().await?;
+    fed.await_all_peers().await?;
+
+    // switch peer 0 over to compact block filters and take peer 3 offline, so
+    // consensus on the chain can only be reached with the p2p backed peer
+    fed.terminate_server(0).await?;
+    fed.start_server_with_bitcoin_p2p(process_mgr, 0).await?;
+    fed.terminate_server(3).await?;
+
+    fed.mine_then_wait_blocks_sync(10).await?;
+    info!(target: LOG_DEVIMINT, "Server 0 follows the chain over bitcoin p2p");
+
+    let client = fed.new_joined_client("bitcoin-p2p-test-client").await?;
+    fed.pegin_client(10_000, &client).await?;
+
+    fed.start_server(process_mgr, 3).await?;
+    fed.await_all_peers().await?;
+
+    info!(target: LOG_DEVIMINT, "fm success: bitcoin-p2p-backend-test");
+    Ok(())
+}
+
 pub async fn recoverytool_test(dev_fed: DevFed) -> Result<()> {
     log_binary_versions().await?;
 
@@ -2829,6 +2859,9 @@ pub enum TestCmd {
     /// `devfed` then kills and restarts most of the Guardian nodes in a 4 node
     /// fedimint
     ReconnectTest,
+    /// `devfed` then restarts a Guardian with the bitcoin P2P compact block
+    /// filter backend and pegs in while it is required for consensus
+    BitcoinP2pBackendTest,
     /// `devfed` then tests a bunch of the fedimint-cli commands
     CliTests,
     /// `devfed` then tests guardian metadata functionality
@@ -2929,6 +2962,11 @@ pub async fn handle_command(cmd: TestCmd, common_args: CommonArgs) -> Result<()>
             let dev_fed = dev_fed(&process_mgr).await?;
             reconnect_test(dev_fed, &process_mgr).await?;
         }
+        TestCmd::BitcoinP2pBackendTest => {
+            let (process_mgr, _) = setup(common_args).await?;
+            let dev_fed = dev_fed(&process_mgr).await?;
+            bitcoin_p2p_backend_test(dev_fed, &process_mgr).await?;
+        }
         TestCmd::CliTests => {
             let (process_mgr, _) = setup(common_args).await?;
             let dev_fed = dev_fed(&process_mgr).await?;
@@ -31,13 +31,14 @@
 use crate::envs::{
     FM_BACKWARDS_COMPATIBILITY_TEST_ENV, FM_BITCOIN_CLI_BASE_EXECUTABLE_ENV,
     FM_BITCOIND_BASE_EXECUTABLE_ENV, FM_BTC_CLIENT_ENV, FM_CLIENT_DIR_ENV,
-    FM_DEVIMINT_CMD_INHERIT_STDERR_ENV, FM_DEVIMINT_FAUCET_BASE_EXECUTABLE_ENV,
-    FM_ESPLORA_BASE_EXECUTABLE_ENV, FM_FEDIMINT_CLI_BASE_EXECUTABLE_ENV,
-    FM_FEDIMINT_DBTOOL_BASE_EXECUTABLE_ENV, FM_FEDIMINTD_BASE_EXECUTABLE_ENV,
-    FM_GATEWAY_CLI_BASE_EXECUTABLE_ENV, FM_GATEWAYD_BASE_EXECUTABLE_ENV, FM_GWCLI_LDK_ENV,
-    FM_GWCLI_LND_ENV, FM_LNCLI_BASE_EXECUTABLE_ENV, FM_LNCLI_ENV, FM_LND_BASE_EXECUTABLE_ENV,
-    FM_LOAD_TEST_TOOL_BASE_EXECUTABLE_ENV, FM_LOGS_DIR_ENV, FM_MINT_CLIENT_ENV,
-    FM_RECOVERYTOOL_BASE_EXECUTABLE_ENV, FM_RECURRINGD_BASE_EXECUTABLE_ENV,
+    FM_CLN_PLUGIN_BASE_EXECUTABLE_ENV, FM_DEVIMINT_CMD_INHERIT_STDERR_ENV,
+    FM_DEVIMINT_FAUCET_BASE_EXECUTABLE_ENV, FM_ESPLORA_BASE_EXECUTABLE_ENV,
+    FM_FEDIMINT_CLI_BASE_EXECUTABLE_ENV, FM_FEDIMINT_DBTOOL_BASE_EXECUTABLE_ENV,
+    FM_FEDIMINTD_BASE_EXECUTABLE_ENV, FM_GATEWAY_CLI_BASE_EXECUTABLE_ENV,
+    FM_GATEWAYD_BASE_EXECUTABLE_ENV, FM_GWCLI_LDK_ENV, FM_GWCLI_LND_ENV,
+    FM_LIGHTNINGD_BASE_EXECUTABLE_ENV, FM_LNCLI_BASE_EXECUTABLE_ENV, FM_LNCLI_ENV,
+    FM_LND_BASE_EXECUTABLE_ENV, FM_LOAD_TEST_TOOL_BASE_EXECUTABLE_ENV, FM_LOGS_DIR_ENV,
+    FM_MINT_CLIENT_ENV, FM_RECOVERYTOOL_BASE_EXECUTABLE_ENV, FM_RECURRINGD_BASE_EXECUTABLE_ENV,
 };
 
 // If a binary doesn't provide a clap version, default to the first stable
@@ -239,6 +240,14 @@ pub fn envs<I, K, V>(mut self, env: I) -> Self
         self
     }
 
+    pub fn env_remove<K>(mut self, key: K) -> Self
+    where
+        K: AsRef<OsStr>,
+    {
+        self.cmd.env_remove(key);
+        self
+    }
+
     pub fn kill_on_drop(mut self, kill: bool) -> Self {
         self.cmd.kill_on_drop(kill);
         self
@@ -606,6 +615,27 @@ pub fn get_bitcoin_cli_path() -> Vec<String> {
 
 const LND_FALLBACK: &str = "lnd";
 
+const LIGHTNINGD_FALLBACK: &str = "lightningd";
+
+const CLN_PLUGIN_FALLBACK: &str = "fedimint-cln-plugin";
+
+/// Returns the path of `fedimint-cln-plugin`, which `lightningd` needs in full
+/// as it does not search `PATH` for plugins.
+pub fn get_cln_plugin_path() -> Result<PathBuf> {
+    let plugin =
+        get_command_str_for_alias(&[FM_CLN_PLUGIN_BASE_EXECUTABLE_ENV], &[CLN_PLUGIN_FALLBACK])
+            .join(" ");
+
+    if plugin.contains(std::path::MAIN_SEPARATOR) {
+        return Ok(PathBuf::from(plugin));
+    }
+
+    env::split_paths(&env::var_os("PATH").unwrap_or_default())
+        .map(|dir| dir.join(&plugin))
+        .find(|path| path.is_file())
+        .with_context(|| format!("{plugin} not found in PATH"))
+}
+
 const ESPLORA_FALLBACK: &str = "esplora";
 
 const RECOVERYTOOL_FALLBACK: &str = "fedimint-recoverytool";
@@ -919,6 +949,16 @@ pub fn cmd(self) -> Command {
     }
 }
 
+pub struct Lightningd;
+impl Lightningd {
+    pub fn cmd(self) -> Command {
+        to_command(get_command_str_for_alias(
+            &[FM_LIGHTNINGD_BASE_EXECUTABLE_ENV],
+            &[LIGHTNINGD_FALLBACK],
+        ))
+    }
+}
+
 pub struct Esplora;
 impl Esplora {
     pub fn cmd(self) -> Command {
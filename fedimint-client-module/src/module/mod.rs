@@ -31,6 +31,7 @@
 use futures::{Stream, StreamExt};
 use serde::Serialize;
 use serde::de::DeserializeOwned;
+use tokio::sync::watch;
 use tracing::warn;
 
 use self::init::ClientModuleInit;
@@ -118,6 +119,8 @@ async fn await_primary_module_outputs(
 
     fn db(&self) -> &Database;
 
+    fn log_event_added_rx(&self) -> watch::Receiver<()>;
+
     fn executor(&self) -> &(maybe_add_send_sync!(dyn IExecutor + 'static));
 
     async fn invite_code(&self, peer: PeerId) -> Option<InviteCode>;
@@ -517,6 +520,22 @@ pub async fn get_event_log(
             .await
     }
 
+    /// Returns the id that the next entry appended to the client's event log
+    /// will be assigned.
+    pub async fn get_next_event_log_id(&self) -> EventLogId {
+        self.global_db()
+            .begin_transaction_nc()
+            .await
+            .get_next_event_log_id()
+            .await
+    }
+
+    /// Get a receiver that signals when new events are added to the client's
+    /// event log.
+    pub fn log_event_added_rx(&self) -> watch::Receiver<()> {
+        self.client.get().log_event_added_rx()
+    }
+
     pub async fn has_active_states(&self, op_id: OperationId) -> bool {
         self.client.get().has_active_states(op_id).await
     }
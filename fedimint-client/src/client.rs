@@ -2918,6 +2918,10 @@ fn db(&self) -> &Database {
         Client::db(self)
     }
 
+    fn log_event_added_rx(&self) -> watch::Receiver<()> {
+        Client::log_event_added_rx(self)
+    }
+
     fn executor(&self) -> &(maybe_add_send_sync!(dyn IExecutor + 'static)) {
         Client::executor(self)
     }
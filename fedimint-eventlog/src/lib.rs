@@ -494,6 +494,12 @@ fn from(value: u64) -> Self {
     }
 }
 
+impl From<EventLogTrimableId> for u64 {
+    fn from(value: EventLogTrimableId) -> Self {
+        value.0.0
+    }
+}
+
 #[derive(Clone, Debug, Encodable, Decodable)]
 pub struct EventLogTrimableIdPrefixAll;
 
@@ -61,6 +61,78 @@ pub fn pay_request_tag() -> String {
     "payRequest".to_string()
 }
 
+pub fn withdraw_request_tag() -> String {
+    "withdrawRequest".to_string()
+}
+
+pub fn channel_request_tag() -> String {
+    "channelRequest".to_string()
+}
+
+/// Response of LNURL callbacks that only report success, e.g. LNURL-withdraw
+/// and LNURL-channel callbacks
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StatusResponse {
+    pub status: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+impl StatusResponse {
+    pub fn ok() -> Self {
+        Self {
+            status: "OK".to_string(),
+            reason: None,
+        }
+    }
+
+    pub fn error(reason: impl Into<String>) -> Self {
+        Self {
+            status: "ERROR".to_string(),
+            reason: Some(reason.into()),
+        }
+    }
+
+    pub fn into_result(self) -> Result<(), String> {
+        if self.status.eq_ignore_ascii_case("OK") {
+            return Ok(());
+        }
+
+        Err(self
+            .reason
+            .unwrap_or_else(|| "Unknown lnurl error".to_string()))
+    }
+}
+
+/// Metadata of an LNURL-pay response (LUD-06), which the invoices created by
+/// the callback commit to via their description hash
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PayMetadata {
+    entries: Vec<(String, String)>,
+}
+
+impl PayMetadata {
+    /// Metadata with the mandatory short description of the payment
+    pub fn text(description: impl Into<String>) -> Self {
+        Self {
+            entries: vec![("text/plain".to_string(), description.into())],
+        }
+    }
+
+    /// Adds the internet identifier of a lightning address (LUD-16)
+    pub fn with_identifier(mut self, address: impl Into<String>) -> Self {
+        self.entries
+            .push(("text/identifier".to_string(), address.into()));
+        self
+    }
+
+    /// Encodes the metadata as the JSON array of entries that is served in
+    /// the `metadata` field of the [`PayResponse`]
+    pub fn encode(&self) -> String {
+        serde_json::to_string(&self.entries).expect("Serializing strings cannot fail")
+    }
+}
+
 /// LNURL-pay response (LUD-06)
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -70,6 +142,38 @@ pub struct PayResponse {
     pub metadata: String,
     pub min_sendable: u64,
     pub max_sendable: u64,
+    /// Maximum length of a comment the payer may attach (LUD-12)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub comment_allowed: Option<u64>,
+    /// Whether the service publishes zap receipts (NIP-57)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub allows_nostr: Option<bool>,
+    /// Hex encoded x-only public key signing the zap receipts (NIP-57)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nostr_pubkey: Option<String>,
+}
+
+/// LNURL-withdraw response (LUD-03)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WithdrawResponse {
+    pub tag: String,
+    pub callback: String,
+    /// Secret identifying the withdrawal, passed back in the callback
+    pub k1: String,
+    pub default_description: String,
+    pub min_withdrawable: u64,
+    pub max_withdrawable: u64,
+}
+
+/// LNURL-channel response (LUD-02)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChannelResponse {
+    pub tag: String,
+    /// Node uri of the service in the form `pubkey@host:port`
+    pub uri: String,
+    pub callback: String,
+    pub k1: String,
 }
 
 /// Response when requesting an invoice from LNURL-pay callback
@@ -145,6 +249,117 @@ pub async fn get_invoice(
     Ok(invoice)
 }
 
+/// Fetch and parse an LNURL-withdraw response
+pub async fn request_withdraw(url: &str) -> Result<WithdrawResponse, String> {
+    let response = reqwest::get(url)
+        .await
+        .map_err(|_| "Failed to fetch lnurl withdraw response".to_string())?
+        .json::<LnurlResponse<WithdrawResponse>>()
+        .await
+        .map_err(|_| "Failed to parse lnurl withdraw response".to_string())?
+        .into_result()?;
+
+    if response.tag != withdraw_request_tag() {
+        return Err("Lnurl is not a withdraw request".to_string());
+    }
+
+    Ok(response)
+}
+
+/// Submit an invoice to an LNURL-withdraw callback for the service to pay.
+/// The service pays the invoice asynchronously, so success only means the
+/// service accepted the invoice.
+pub async fn submit_withdraw_invoice(
+    response: &WithdrawResponse,
+    invoice: &Bolt11Invoice,
+) -> Result<(), String> {
+    let amount_msat = invoice
+        .amount_milli_satoshis()
+        .ok_or("Invoice is missing an amount".to_string())?;
+
+    if amount_msat < response.min_withdrawable {
+        return Err(format!(
+            "Minimum amount is {} sats",
+            response.min_withdrawable / 1000
+        ));
+    }
+
+    if amount_msat > response.max_withdrawable {
+        return Err(format!(
+            "Maximum amount is {} sats",
+            response.max_withdrawable / 1000
+        ));
+    }
+
+    let separator = if response.callback.contains('?') {
+        '&'
+    } else {
+        '?'
+    };
+
+    let callback_url = format!(
+        "{}{}k1={}&pr={}",
+        response.callback, separator, response.k1, invoice
+    );
+
+    reqwest::get(callback_url)
+        .await
+        .map_err(|_| "Failed to fetch lnurl withdraw callback response".to_string())?
+        .json::<StatusResponse>()
+        .await
+        .map_err(|_| "Failed to parse lnurl withdraw callback response".to_string())?
+        .into_result()
+}
+
+/// Fetch and parse an LNURL-channel response
+pub async fn request_channel(url: &str) -> Result<ChannelResponse, String> {
+    let response = reqwest::get(url)
+        .await
+        .map_err(|_| "Failed to fetch lnurl channel response".to_string())?
+        .json::<LnurlResponse<ChannelResponse>>()
+        .await
+        .map_err(|_| "Failed to parse lnurl channel response".to_string())?
+        .into_result()?;
+
+    if response.tag != channel_request_tag() {
+        return Err("Lnurl is not a channel request".to_string());
+    }
+
+    Ok(response)
+}
+
+/// Ask the service of an LNURL-channel to open a channel to the node with the
+/// given hex encoded public key. The node has to be connected to the uri of
+/// the service beforehand.
+pub async fn open_channel(
+    response: &ChannelResponse,
+    remote_id: &str,
+    private: bool,
+) -> Result<(), String> {
+    let separator = if response.callback.contains('?') {
+        '&'
+    } else {
+        '?'
+    };
+
+    let callback_url = format!(
+        "{}{}k1={}&remoteid={}&private={}",
+        response.callback,
+        separator,
+        response.k1,
+        remote_id,
+        u8::from(private)
+    );
+
+    reqwest::get(callback_url)
+        .await
+        .map_err(|_| "Failed to fetch lnurl channel callback response".to_string())?
+        .json::<StatusResponse>()
+        .await
+        .map_err(|_| "Failed to parse lnurl channel callback response".to_string())?
+        .into_result()
+}
+
 /// Verify a payment using LUD-21
 pub async fn verify_invoice(url: &str) -> Result<VerifyResponse, String> {
     reqwest::get(url)
@@ -184,6 +399,75 @@ fn parse_pay_response_lud_06() {
     assert_eq!(pay.max_sendable, 100000000);
 }
 
+#[test]
+fn parse_pay_response_lud_12_nip_57() {
+    let json = r#"{
+        "callback": "https://example.com/lnurl/pay/callback",
+        "maxSendable": 100000000,
+        "minSendable": 1000,
+        "metadata": "[[\"text/plain\",\"Pay to example.com\"]]",
+        "tag": "payRequest",
+        "commentAllowed": 255,
+        "allowsNostr": true,
+        "nostrPubkey": "9630f464cca6a5147aa8a35f0bcdd3ce485324e732fd39e09233b1d848238f31"
+    }"#;
+
+    let response: LnurlResponse<PayResponse> = serde_json::from_str(json).unwrap();
+
+    let pay = response.into_result().unwrap();
+
+    assert_eq!(pay.comment_allowed, Some(255));
+    assert_eq!(pay.allows_nostr, Some(true));
+    assert!(pay.nostr_pubkey.is_some());
+}
+
+#[test]
+fn parse_withdraw_response_lud_03() {
+    let json = r#"{
+        "tag": "withdrawRequest",
+        "callback": "https://example.com/lnurl/withdraw/callback",
+        "k1": "e2af6254a8df433264fa23f67eb8188635d15ce883e8fc020989d5f82ae6f11e",
+        "defaultDescription": "Withdraw from example.com",
+        "minWithdrawable": 1000,
+        "maxWithdrawable": 100000
+    }"#;
+
+    let response: LnurlResponse<WithdrawResponse> = serde_json::from_str(json).unwrap();
+
+    let withdraw = response.into_result().unwrap();
+
+    assert_eq!(withdraw.tag, withdraw_request_tag());
+    assert_eq!(withdraw.default_description, "Withdraw from example.com");
+    assert_eq!(withdraw.min_withdrawable, 1000);
+    assert_eq!(withdraw.max_withdrawable, 100000);
+}
+
+#[test]
+fn parse_channel_response_lud_02() {
+    let json = r#"{
+        "tag": "channelRequest",
+        "uri": "03a5ac3f9f9d9c8b2e5d1e9cf3f1f3a1c4f0a7d0e1b1c2d3e4f5a6b7c8d9e0f1a2@127.0.0.1:9735",
+        "callback": "https://example.com/lnurl/channel/callback",
+        "k1": "e2af6254a8df433264fa23f67eb8188635d15ce883e8fc020989d5f82ae6f11e"
+    }"#;
+
+    let response: LnurlResponse<ChannelResponse> = serde_json::from_str(json).unwrap();
+
+    assert_eq!(response.into_result().unwrap().tag, channel_request_tag());
+}
+
+#[test]
+fn parse_status_response() {
+    let ok: StatusResponse = serde_json::from_str(r#"{"status": "OK"}"#).unwrap();
+
+    assert_eq!(ok.into_result(), Ok(()));
+
+    let error: StatusResponse =
+        serde_json::from_str(r#"{"status": "ERROR", "reason": "Link already used"}"#).unwrap();
+
+    assert_eq!(error.into_result().unwrap_err(), "Link already used");
+}
+
 #[test]
 fn parse_error_response() {
     let json = r#"{"status": "ERROR", "reason": "Invalid request"}"#;
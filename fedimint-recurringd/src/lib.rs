@@ -264,6 +264,9 @@ pub async fn lnurl_pay(
             min_sendable: 1,
             tag: pay_request_tag(),
             metadata: meta,
+            comment_allowed: None,
+            allows_nostr: None,
+            nostr_pubkey: None,
         })
     }
 
@@ -0,0 +1,471 @@
+//! Lightning addresses (LUD-16) backed by lnv2 receive keys, with support for
+//! comments (LUD-12) and zap requests (NIP-57). Zap receipts are not sent to
+//! the relays named in the zap request, they are only served from our own
+//! endpoint, which nostr clients have to query to see the zap.
+
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use anyhow::{Context, bail, ensure};
+use axum::Json;
+use axum::extract::{ConnectInfo, Path, Query, State};
+use bitcoin::hashes::{Hash, sha256};
+use fedimint_core::db::IDatabaseTransactionOpsCoreTyped;
+use fedimint_core::time::duration_since_epoch;
+use fedimint_lnurl::{InvoiceResponse, LnurlResponse, PayResponse, pay_request_tag};
+use fedimint_lnv2_common::Bolt11InvoiceDescription;
+use fedimint_lnv2_common::lnurl::{
+    AddressComment, AddressCommentsRequest, RegisterAddressRequest, address_comments_message,
+    is_valid_username, register_address_message,
+};
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use tokio::sync::OwnedSemaphorePermit;
+use tracing::{info, warn};
+
+use crate::db::{
+    AddressKey, CommentKey, CommentUsernamePrefix, PendingPayment, PendingPaymentKey,
+    PendingPaymentPrefix, PendingPaymentUsernamePrefix, ZapReceiptKey, ZapReceiptUsernamePrefix,
+};
+use crate::nostr::{NostrEvent, parse_zap_request, zap_receipt};
+use crate::{
+    AppState, INVOICE_EXPIRY_SECS, MAX_SENDABLE_MSAT, MIN_SENDABLE_MSAT,
+    create_contract_and_fetch_invoice,
+};
+
+/// Maximum length of a comment a payer may attach to a payment.
+const MAX_COMMENT_LENGTH: u64 = 255;
+
+/// Maximum age of a signed request for the comments of an address.
+const MAX_REQUEST_AGE_SECS: u64 = 300;
+
+/// Maximum number of payments with a comment or zap request awaiting
+/// settlement per lightning address.
+const MAX_PENDING_PAYMENTS_PER_ADDRESS: usize = 100;
+
+/// Binds a username to the lnurl payload of the recipient, or updates the
+/// payload if the username is already registered by the same recipient.
+pub async fn register(
+    ConnectInfo(client): ConnectInfo<SocketAddr>,
+    State(state): State<AppState>,
+    Json(request): Json<RegisterAddressRequest>,
+) -> Json<LnurlResponse<String>> {
+    if !state.register_rate_limiter.try_acquire(client.ip()) {
+        return Json(LnurlResponse::error("Too many requests"));
+    }
+
+    Json(match register_address(&state, request).await {
+        Ok(address) => LnurlResponse::Ok(address),
+        Err(e) => LnurlResponse::error(e.to_string()),
+    })
+}
+
+async fn register_address(
+    state: &AppState,
+    request: RegisterAddressRequest,
+) -> anyhow::Result<String> {
+    ensure!(is_valid_username(&request.username), "Invalid username");
+
+    ensure!(
+        !request.request.gateways.is_empty(),
+        "No gateways specified"
+    );
+
+    bitcoin::secp256k1::SECP256K1
+        .verify_schnorr(
+            &request.signature,
+            &register_address_message(&request.username, &request.request),
+            &request.request.recipient_pk.x_only_public_key().0,
+        )
+        .context("Invalid signature")?;
+
+    let mut dbtx = state.db.begin_transaction().await;
+
+    if let Some(registered) = dbtx.get_value(&AddressKey(request.username.clone())).await {
+        ensure!(
+            registered.recipient_pk == request.request.recipient_pk,
+            "Username is already taken"
+        );
+    }
+
+    dbtx.insert_entry(&AddressKey(request.username.clone()), &request.request)
+        .await;
+
+    dbtx.commit_tx_result().await?;
+
+    info!(username = %request.username, "Registered lightning address");
+
+    Ok(format!("{}@{}", request.username, state.domain()))
+}
+
+/// Answers the first request of the LNURL-pay protocol for a lightning
+/// address.
+pub async fn well_known(
+    State(state): State<AppState>,
+    Path(username): Path<String>,
+) -> Json<LnurlResponse<PayResponse>> {
+    let username = username.to_lowercase();
+
+    if !state.is_registered(&username).await {
+        return Json(LnurlResponse::error("Unknown lightning address"));
+    }
+
+    Json(LnurlResponse::Ok(PayResponse {
+        callback: state
+            .api_address
+            .join_path(&format!("address/{username}/invoice"))
+            .to_string(),
+        max_sendable: MAX_SENDABLE_MSAT,
+        min_sendable: MIN_SENDABLE_MSAT,
+        tag: pay_request_tag(),
+        metadata: state.metadata(&username),
+        comment_allowed: Some(MAX_COMMENT_LENGTH),
+        allows_nostr: Some(true),
+        nostr_pubkey: Some(fedimint_core::hex::encode(
+            state.nostr_keypair.x_only_public_key().0.serialize(),
+        )),
+    }))
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AddressInvoiceParams {
+    amount: u64,
+    comment: Option<String>,
+    /// A zap request (NIP-57) as a json encoded nostr event.
+    nostr: Option<String>,
+}
+
+pub async fn invoice(
+    ConnectInfo(client): ConnectInfo<SocketAddr>,
+    State(state): State<AppState>,
+    Path(username): Path<String>,
+    Query(params): Query<AddressInvoiceParams>,
+) -> Json<LnurlResponse<InvoiceResponse>> {
+    if !state.invoice_rate_limiter.try_acquire(client.ip()) {
+        return Json(LnurlResponse::error("Too many requests"));
+    }
+
+    Json(match address_invoice(&state, username, params).await {
+        Ok(response) => LnurlResponse::Ok(response),
+        Err(e) => LnurlResponse::error(e.to_string()),
+    })
+}
+
+async fn address_invoice(
+    state: &AppState,
+    username: String,
+    params: AddressInvoiceParams,
+) -> anyhow::Result<InvoiceResponse> {
+    let username = username.to_lowercase();
+
+    let Some(request) = state
+        .db
+        .begin_transaction_nc()
+        .await
+        .get_value(&AddressKey(username.clone()))
+        .await
+    else {
+        bail!("Unknown lightning address");
+    };
+
+    ensure!(
+        (MIN_SENDABLE_MSAT..=MAX_SENDABLE_MSAT).contains(&params.amount),
+        "Amount must be between {MIN_SENDABLE_MSAT} and {MAX_SENDABLE_MSAT}"
+    );
+
+    if let Some(comment) = &params.comment {
+        ensure!(
+            comment.chars().count() as u64 <= MAX_COMMENT_LENGTH,
+            "Comment exceeds {MAX_COMMENT_LENGTH} characters"
+        );
+    }
+
+    // The invoice commits to the zap request for zaps and to the metadata
+    // otherwise, as required by NIP-57 and LUD-06 respectively.
+    let description_hash = match &params.nostr {
+        Some(zap_request) => {
+            parse_zap_request(zap_request, params.amount)?;
+
+            sha256::Hash::hash(zap_request.as_bytes())
+        }
+        None => sha256::Hash::hash(state.metadata(&username).as_bytes()),
+    };
+
+    // Payments with a comment or zap request are tracked until they settle or
+    // their invoice expires, so their number is bounded before the invoice is
+    // created.
+    let permit = if params.comment.is_some() || params.nostr.is_some() {
+        let pending_for_address = state
+            .db
+            .begin_transaction_nc()
+            .await
+            .find_by_prefix(&PendingPaymentUsernamePrefix {
+                username: username.clone(),
+            })
+            .await
+            .take(MAX_PENDING_PAYMENTS_PER_ADDRESS)
+            .count()
+            .await;
+
+        ensure!(
+            pending_for_address < MAX_PENDING_PAYMENTS_PER_ADDRESS,
+            "Too many pending payments to this address, try again without a comment"
+        );
+
+        Some(
+            state
+                .pending_payments
+                .clone()
+                .try_acquire_owned()
+                .context("Too many pending payments, try again without a comment")?,
+        )
+    } else {
+        None
+    };
+
+    let (gateway, invoice) = create_contract_and_fetch_invoice(
+        request.federation_id,
+        request.recipient_pk,
+        request.aggregate_pk,
+        request.gateways,
+        params.amount,
+        Bolt11InvoiceDescription::Hash(description_hash),
+        INVOICE_EXPIRY_SECS,
+        &state.gateway_conn,
+    )
+    .await?;
+
+    let verify_url = gateway
+        .join_path(&format!("verify/{}", invoice.payment_hash()))
+        .to_string();
+
+    if let Some(permit) = permit {
+        let payment_hash = *invoice.payment_hash();
+
+        let pending = PendingPayment {
+            username: username.clone(),
+            invoice: invoice.clone(),
+            verify_url: verify_url.clone(),
+            comment: params.comment,
+            zap_request: params.nostr,
+        };
+
+        let mut dbtx = state.db.begin_transaction().await;
+
+        dbtx.insert_entry(
+            &PendingPaymentKey {
+                username: username.clone(),
+                payment_hash,
+            },
+            &pending,
+        )
+        .await;
+
+        dbtx.commit_tx_result().await?;
+
+        tokio::spawn(await_settlement(
+            state.clone(),
+            payment_hash,
+            pending,
+            Some(permit),
+        ));
+    }
+
+    info!(%params.amount, %gateway, %username, "Created lightning address invoice");
+
+    Ok(InvoiceResponse {
+        pr: invoice,
+        verify: Some(verify_url),
+    })
+}
+
+/// Resumes waiting for the settlement of all payments that were pending when
+/// the service was stopped.
+pub async fn resume_pending_payments(state: &AppState) {
+    let pending_payments = state
+        .db
+        .begin_transaction_nc()
+        .await
+        .find_by_prefix(&PendingPaymentPrefix)
+        .await
+        .collect::<Vec<_>>()
+        .await;
+
+    for (key, pending) in pending_payments {
+        // A permit is not available if the limit was lowered since the
+        // payment was accepted, in which case the payment is awaited anyway.
+        let permit = state.pending_payments.clone().try_acquire_owned().ok();
+
+        tokio::spawn(await_settlement(
+            state.clone(),
+            key.payment_hash,
+            pending,
+            permit,
+        ));
+    }
+}
+
+/// Waits for a payment with a comment or zap request to settle and records the
+/// comment and the zap receipt for the recipient. The pending payment is
+/// dropped once its invoice has expired, releasing its permit.
+async fn await_settlement(
+    state: AppState,
+    payment_hash: sha256::Hash,
+    pending: PendingPayment,
+    _permit: Option<OwnedSemaphorePermit>,
+) {
+    let expires_at_secs = pending
+        .invoice
+        .expires_at()
+        .map_or(u64::MAX, |expires_at| expires_at.as_secs());
+
+    let preimage = loop {
+        if duration_since_epoch().as_secs() > expires_at_secs {
+            break None;
+        }
+
+        match fedimint_lnurl::verify_invoice(&format!("{}?wait", pending.verify_url)).await {
+            Ok(response) if response.settled => break response.preimage,
+            Ok(..) => {}
+            Err(e) => {
+                warn!(%payment_hash, err = %e, "Failed to verify lightning address payment");
+
+                tokio::time::sleep(Duration::from_secs(5)).await;
+            }
+        }
+    };
+
+    let mut dbtx = state.db.begin_transaction().await;
+
+    dbtx.remove_entry(&PendingPaymentKey {
+        username: pending.username.clone(),
+        payment_hash,
+    })
+    .await;
+
+    if let Some(preimage) = preimage {
+        let paid_at = duration_since_epoch().as_secs();
+
+        let amount_msat = pending.invoice.amount_milli_satoshis().unwrap_or_default();
+
+        if let Some(comment) = pending.comment {
+            dbtx.insert_entry(
+                &CommentKey {
+                    username: pending.username.clone(),
+                    payment_hash,
+                },
+                &AddressComment {
+                    payment_hash,
+                    amount_msat,
+                    comment,
+                    created_at_secs: paid_at,
+                },
+            )
+            .await;
+        }
+
+        if let Some(zap_request_json) = pending.zap_request
+            && let Ok(zap_request) = parse_zap_request(&zap_request_json, amount_msat)
+        {
+            let receipt = zap_receipt(
+                &state.nostr_keypair,
+                &zap_request,
+                zap_request_json,
+                pending.invoice.to_string(),
+                preimage,
+                paid_at,
+            );
+
+            dbtx.insert_entry(
+                &ZapReceiptKey {
+                    username: pending.username.clone(),
+                    payment_hash,
+                },
+                &serde_json::to_string(&receipt).expect("Serialization cannot fail"),
+            )
+            .await;
+        }
+    }
+
+    if let Err(e) = dbtx.commit_tx_result().await {
+        warn!(%payment_hash, err = %e, "Failed to record lightning address payment");
+    }
+}
+
+/// Returns the comments attached to settled payments to an address since the
+/// given time. Only the recipient may read them, which it proves by signing
+/// the request with the key the address is registered to.
+pub async fn comments(
+    State(state): State<AppState>,
+    Json(request): Json<AddressCommentsRequest>,
+) -> Json<LnurlResponse<Vec<AddressComment>>> {
+    Json(match address_comments(&state, request).await {
+        Ok(comments) => LnurlResponse::Ok(comments),
+        Err(e) => LnurlResponse::error(e.to_string()),
+    })
+}
+
+async fn address_comments(
+    state: &AppState,
+    request: AddressCommentsRequest,
+) -> anyhow::Result<Vec<AddressComment>> {
+    ensure!(
+        duration_since_epoch()
+            .as_secs()
+            .abs_diff(request.timestamp_secs)
+            <= MAX_REQUEST_AGE_SECS,
+        "Request has expired"
+    );
+
+    let username = request.username.to_lowercase();
+
+    let mut dbtx = state.db.begin_transaction_nc().await;
+
+    let Some(registered) = dbtx.get_value(&AddressKey(username.clone())).await else {
+        bail!("Unknown lightning address");
+    };
+
+    bitcoin::secp256k1::SECP256K1
+        .verify_schnorr(
+            &request.signature,
+            &address_comments_message(&username, request.since_secs, request.timestamp_secs),
+            &registered.recipient_pk.x_only_public_key().0,
+        )
+        .context("Invalid signature")?;
+
+    let mut comments = dbtx
+        .find_by_prefix(&CommentUsernamePrefix { username })
+        .await
+        .map(|(_, comment)| comment)
+        .filter(|comment| std::future::ready(comment.created_at_secs >= request.since_secs))
+        .collect::<Vec<_>>()
+        .await;
+
+    comments.sort_by_key(|comment| comment.created_at_secs);
+
+    Ok(comments)
+}
+
+/// Serves the zap receipts of an address. Zap receipts are not sent to the
+/// relays listed in the zap requests; this endpoint stands in for a relay that
+/// nostr clients have to query instead.
+pub async fn zap_receipts(
+    State(state): State<AppState>,
+    Path(username): Path<String>,
+) -> Json<Vec<NostrEvent>> {
+    let mut receipts = state
+        .db
+        .begin_transaction_nc()
+        .await
+        .find_by_prefix(&ZapReceiptUsernamePrefix {
+            username: username.to_lowercase(),
+        })
+        .await
+        .filter_map(|(_, receipt)| std::future::ready(serde_json::from_str(&receipt).ok()))
+        .collect::<Vec<NostrEvent>>()
+        .await;
+
+    receipts.sort_by_key(|receipt| receipt.created_at);
+
+    Json(receipts)
+}
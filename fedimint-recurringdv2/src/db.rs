@@ -0,0 +1,115 @@
+use bitcoin::hashes::sha256;
+use bitcoin::secp256k1::SecretKey;
+use fedimint_core::encoding::{Decodable, Encodable};
+use fedimint_core::{impl_db_lookup, impl_db_record};
+use fedimint_lnv2_common::lnurl::{AddressComment, LnurlRequest};
+use lightning_invoice::Bolt11Invoice;
+
+#[repr(u8)]
+#[derive(Clone, Debug)]
+pub enum DbKeyPrefix {
+    Address = 0x01,
+    PendingPayment = 0x02,
+    Comment = 0x03,
+    ZapReceipt = 0x04,
+    NostrKey = 0x05,
+}
+
+/// The lnurl payload a lightning address username is bound to.
+#[derive(Debug, Encodable, Decodable)]
+pub struct AddressKey(pub String);
+
+impl_db_record!(
+    key = AddressKey,
+    value = LnurlRequest,
+    db_prefix = DbKeyPrefix::Address,
+);
+
+/// A payment to a lightning address whose invoice has been handed out but not
+/// settled yet. Comments and zap receipts are only recorded once the payment
+/// settles, so unpaid invoices cannot be used to spam the recipient. The
+/// pending payment is removed once its invoice has expired.
+#[derive(Debug, Encodable, Decodable)]
+pub struct PendingPaymentKey {
+    pub username: String,
+    pub payment_hash: sha256::Hash,
+}
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct PendingPaymentPrefix;
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct PendingPaymentUsernamePrefix {
+    pub username: String,
+}
+
+#[derive(Debug, Clone, Encodable, Decodable)]
+pub struct PendingPayment {
+    pub username: String,
+    pub invoice: Bolt11Invoice,
+    pub verify_url: String,
+    pub comment: Option<String>,
+    /// The zap request (NIP-57) as sent by the payer.
+    pub zap_request: Option<String>,
+}
+
+impl_db_record!(
+    key = PendingPaymentKey,
+    value = PendingPayment,
+    db_prefix = DbKeyPrefix::PendingPayment,
+);
+
+impl_db_lookup!(
+    key = PendingPaymentKey,
+    query_prefix = PendingPaymentPrefix,
+    query_prefix = PendingPaymentUsernamePrefix
+);
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct CommentKey {
+    pub username: String,
+    pub payment_hash: sha256::Hash,
+}
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct CommentUsernamePrefix {
+    pub username: String,
+}
+
+impl_db_record!(
+    key = CommentKey,
+    value = AddressComment,
+    db_prefix = DbKeyPrefix::Comment,
+);
+
+impl_db_lookup!(key = CommentKey, query_prefix = CommentUsernamePrefix);
+
+/// A published zap receipt, serialized as a nostr event.
+#[derive(Debug, Encodable, Decodable)]
+pub struct ZapReceiptKey {
+    pub username: String,
+    pub payment_hash: sha256::Hash,
+}
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct ZapReceiptUsernamePrefix {
+    pub username: String,
+}
+
+impl_db_record!(
+    key = ZapReceiptKey,
+    value = String,
+    db_prefix = DbKeyPrefix::ZapReceipt,
+);
+
+impl_db_lookup!(key = ZapReceiptKey, query_prefix = ZapReceiptUsernamePrefix);
+
+/// The key signing our zap receipts, generated on first start.
+#[derive(Debug, Encodable, Decodable)]
+pub struct NostrKeyKey;
+
+impl_db_record!(
+    key = NostrKeyKey,
+    value = SecretKey,
+    db_prefix = DbKeyPrefix::NostrKey,
+);
@@ -1,21 +1,29 @@
+mod address;
+mod db;
+mod nostr;
+mod rate_limit;
+
 use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
 
 use anyhow::{bail, ensure};
-use axum::extract::{Path, Query, State};
+use axum::extract::{ConnectInfo, Path, Query, State};
 use axum::response::IntoResponse;
-use axum::routing::get;
+use axum::routing::{get, post};
 use axum::{Json, Router};
 use bitcoin::hashes::sha256;
-use bitcoin::secp256k1::{self, PublicKey};
+use bitcoin::secp256k1::{self, Keypair, PublicKey, SecretKey};
 use clap::Parser;
 use fedimint_connectors::ConnectorRegistry;
 use fedimint_core::base32::{FEDIMINT_PREFIX, decode_prefixed};
 use fedimint_core::config::FederationId;
+use fedimint_core::db::{Database, IDatabaseTransactionOpsCoreTyped};
 use fedimint_core::encoding::Encodable;
 use fedimint_core::secp256k1::Scalar;
 use fedimint_core::util::SafeUrl;
 use fedimint_core::{Amount, BitcoinHash};
-use fedimint_lnurl::{InvoiceResponse, LnurlResponse, PayResponse, pay_request_tag};
+use fedimint_lnurl::{InvoiceResponse, LnurlResponse, PayMetadata, PayResponse, pay_request_tag};
 use fedimint_lnv2_common::contracts::{IncomingContract, PaymentImage, fee_encoded_expiration};
 use fedimint_lnv2_common::gateway_api::{
     GatewayConnection, PaymentFee, RealGatewayConnection, RoutingInfo,
@@ -25,16 +33,34 @@
     Bolt11InvoiceDescription, GatewayApi, MINIMUM_INCOMING_CONTRACT_AMOUNT, tweak,
 };
 use fedimint_logging::TracingSetup;
+use fedimint_rocksdb::RocksDb;
 use lightning_invoice::Bolt11Invoice;
 use serde::{Deserialize, Serialize};
 use tokio::net::TcpListener;
+use tokio::sync::Semaphore;
 use tower_http::cors;
 use tower_http::cors::CorsLayer;
 use tpe::AggregatePublicKey;
 use tracing::{info, warn};
 
+use crate::rate_limit::IpRateLimiter;
+
 const MAX_SENDABLE_MSAT: u64 = 100_000_000_000;
 const MIN_SENDABLE_MSAT: u64 = 100_000;
+const INVOICE_EXPIRY_SECS: u32 = 3600;
+
+/// Burst and refill rate of the invoices a client ip address may request.
+const INVOICE_RATE_LIMIT_BURST: u32 = 50;
+const INVOICE_RATE_LIMIT_PER_SECOND: f64 = 5.0;
+
+/// Burst and refill rate of the lightning addresses a client ip address may
+/// register, one per minute.
+const REGISTER_RATE_LIMIT_BURST: u32 = 10;
+const REGISTER_RATE_LIMIT_PER_SECOND: f64 = 1.0 / 60.0;
+
+/// Maximum number of payments with a comment or zap request awaiting
+/// settlement, across all lightning addresses.
+const MAX_PENDING_PAYMENTS: usize = 10_000;
 
 #[derive(Debug, Parser)]
 struct CliOpts {
@@ -50,14 +76,59 @@ struct CliOpts {
     /// Used to construct the LNURL-pay callback URLs returned to payers, so it
     /// must be the exact URL payers reach this service at. Should be an
     /// `https` URL in production.
+    ///
+    /// Lightning addresses are of the form `username@host` and resolve to
+    /// `https://host/.well-known/lnurlp/username`, so they only work if this
+    /// URL has no path.
     #[arg(long, env = "FM_API_ADDRESS")]
     api_address: SafeUrl,
+    /// Directory of the database storing lightning address registrations,
+    /// payment comments and zap receipts
+    #[arg(long, env = "FM_DATA_DIR")]
+    data_dir: PathBuf,
 }
 
 #[derive(Clone)]
 struct AppState {
     api_address: SafeUrl,
     gateway_conn: RealGatewayConnection,
+    db: Database,
+    nostr_keypair: Keypair,
+    invoice_rate_limiter: Arc<IpRateLimiter>,
+    register_rate_limiter: Arc<IpRateLimiter>,
+    /// Permits for payments awaiting settlement, each held until its payment
+    /// settles or its invoice expires.
+    pending_payments: Arc<Semaphore>,
+}
+
+impl AppState {
+    /// The domain part of our lightning addresses.
+    fn domain(&self) -> String {
+        let host = self.api_address.host_str().unwrap_or_default();
+
+        match self.api_address.port() {
+            Some(port) => format!("{host}:{port}"),
+            None => host.to_string(),
+        }
+    }
+
+    /// The LNURL-pay metadata of a lightning address as defined in LUD-16.
+    fn metadata(&self, username: &str) -> String {
+        let address = format!("{username}@{}", self.domain());
+
+        PayMetadata::text(format!("Pay to {address}"))
+            .with_identifier(address)
+            .encode()
+    }
+
+    async fn is_registered(&self, username: &str) -> bool {
+        self.db
+            .begin_transaction_nc()
+            .await
+            .get_value(&db::AddressKey(username.to_string()))
+            .await
+            .is_some()
+    }
 }
 
 #[tokio::main]
@@ -78,13 +149,33 @@ async fn main() -> anyhow::Result<()> {
         );
     }
 
+    let db = Database::new(
+        RocksDb::build(cli_opts.data_dir).open().await?,
+        Default::default(),
+    );
+
+    let nostr_keypair = load_or_generate_nostr_keypair(&db).await?;
+
     let state = AppState {
         api_address: cli_opts.api_address.clone(),
         gateway_conn: RealGatewayConnection {
             api: GatewayApi::new(None, connector_registry),
         },
+        db,
+        nostr_keypair,
+        invoice_rate_limiter: Arc::new(IpRateLimiter::new(
+            INVOICE_RATE_LIMIT_BURST,
+            INVOICE_RATE_LIMIT_PER_SECOND,
+        )),
+        register_rate_limiter: Arc::new(IpRateLimiter::new(
+            REGISTER_RATE_LIMIT_BURST,
+            REGISTER_RATE_LIMIT_PER_SECOND,
+        )),
+        pending_payments: Arc::new(Semaphore::new(MAX_PENDING_PAYMENTS)),
     };
 
+    address::resume_pending_payments(&state).await;
+
     let cors = CorsLayer::new()
         .allow_origin(cors::Any)
         .allow_methods(cors::Any)
@@ -94,6 +185,11 @@ async fn main() -> anyhow::Result<()> {
         .route("/", get(health_check))
         .route("/pay/{payload}", get(pay))
         .route("/invoice/{payload}", get(invoice))
+        .route("/register", post(address::register))
+        .route("/.well-known/lnurlp/{username}", get(address::well_known))
+        .route("/address/{username}/invoice", get(address::invoice))
+        .route("/address/comments", post(address::comments))
+        .route("/nostr/receipts/{username}", get(address::zap_receipts))
         .layer(cors)
         .with_state(state);
 
@@ -105,11 +201,35 @@ async fn main() -> anyhow::Result<()> {
 
     let listener = TcpListener::bind(cli_opts.bind_api).await?;
 
-    axum::serve(listener, app).await?;
+    // The rate limits are applied per client ip address
+    axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .await?;
 
     Ok(())
 }
 
+async fn load_or_generate_nostr_keypair(db: &Database) -> anyhow::Result<Keypair> {
+    let mut dbtx = db.begin_transaction().await;
+
+    let secret_key = match dbtx.get_value(&db::NostrKeyKey).await {
+        Some(secret_key) => secret_key,
+        None => {
+            let secret_key = SecretKey::new(&mut fedimint_core::secp256k1::rand::thread_rng());
+
+            dbtx.insert_new_entry(&db::NostrKeyKey, &secret_key).await;
+
+            secret_key
+        }
+    };
+
+    dbtx.commit_tx_result().await?;
+
+    Ok(Keypair::from_secret_key(secp256k1::SECP256K1, &secret_key))
+}
+
 async fn health_check(State(state): State<AppState>) -> impl IntoResponse {
     format!("recurringdv2 is up and running at {}", state.api_address)
 }
@@ -127,6 +247,9 @@ async fn pay(
         min_sendable: MIN_SENDABLE_MSAT,
         tag: pay_request_tag(),
         metadata: "[[\"text/plain\", \"Pay to Recurringd\"]]".to_string(),
+        comment_allowed: None,
+        allows_nostr: None,
+        nostr_pubkey: None,
     }))
 }
 
@@ -136,10 +259,15 @@ struct GetInvoiceParams {
 }
 
 async fn invoice(
+    ConnectInfo(client): ConnectInfo<SocketAddr>,
     Path(payload): Path<String>,
     Query(params): Query<GetInvoiceParams>,
     State(state): State<AppState>,
 ) -> Json<LnurlResponse<InvoiceResponse>> {
+    if !state.invoice_rate_limiter.try_acquire(client.ip()) {
+        return Json(LnurlResponse::error("Too many requests"));
+    }
+
     let Ok(request) = decode_prefixed::<LnurlRequest>(FEDIMINT_PREFIX, &payload) else {
         return Json(LnurlResponse::error("Failed to decode payload"));
     };
@@ -157,7 +285,8 @@ async fn invoice(
         request.aggregate_pk,
         request.gateways,
         params.amount,
-        3600, // standard expiry time of one hour
+        Bolt11InvoiceDescription::Direct("LNURL Payment".to_string()),
+        INVOICE_EXPIRY_SECS,
         &state.gateway_conn,
     )
     .await
@@ -187,6 +316,7 @@ async fn create_contract_and_fetch_invoice(
     aggregate_pk: AggregatePublicKey,
     gateways: Vec<SafeUrl>,
     amount: u64,
+    description: Bolt11InvoiceDescription,
     expiry_secs: u32,
     gateway_conn: &RealGatewayConnection,
 ) -> anyhow::Result<(SafeUrl, Bolt11Invoice)> {
@@ -247,7 +377,7 @@ async fn create_contract_and_fetch_invoice(
             federation_id,
             contract.clone(),
             Amount::from_msats(amount),
-            Bolt11InvoiceDescription::Direct("LNURL Payment".to_string()),
+            description,
             expiry_secs,
         )
         .await?;
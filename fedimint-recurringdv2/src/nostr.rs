@@ -0,0 +1,396 @@
+//! The subset of nostr needed to support zaps (NIP-57): verifying the zap
+//! requests payers attach to a payment and signing the zap receipts we serve
+//! once the payment has settled.
+
+use anyhow::{Context, ensure};
+use bitcoin::hashes::{Hash, sha256};
+use bitcoin::secp256k1::{Keypair, Message, SECP256K1, XOnlyPublicKey, schnorr};
+use fedimint_core::hex;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+/// Kind of the event a payer creates to request a zap.
+pub const ZAP_REQUEST_KIND: u64 = 9734;
+
+/// Kind of the event we serve once a zap has been paid.
+pub const ZAP_RECEIPT_KIND: u64 = 9735;
+
+/// A nostr event as defined by NIP-01.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NostrEvent {
+    pub id: String,
+    pub pubkey: String,
+    pub created_at: u64,
+    pub kind: u64,
+    pub tags: Vec<Vec<String>>,
+    pub content: String,
+    pub sig: String,
+}
+
+impl NostrEvent {
+    /// Creates an event signed by the keypair.
+    pub fn sign(
+        keypair: &Keypair,
+        created_at: u64,
+        kind: u64,
+        tags: Vec<Vec<String>>,
+        content: String,
+    ) -> Self {
+        let pubkey = hex::encode(keypair.x_only_public_key().0.serialize());
+
+        let id = event_id(&pubkey, created_at, kind, &tags, &content);
+
+        let sig = SECP256K1.sign_schnorr(&Message::from_digest(id.to_byte_array()), keypair);
+
+        Self {
+            id: hex::encode(id.to_byte_array()),
+            pubkey,
+            created_at,
+            kind,
+            tags,
+            content,
+            sig: hex::encode(sig.serialize()),
+        }
+    }
+
+    /// Checks that the id commits to the content of the event and that the
+    /// event is signed by its public key.
+    pub fn verify(&self) -> anyhow::Result<()> {
+        let id = event_id(
+            &self.pubkey,
+            self.created_at,
+            self.kind,
+            &self.tags,
+            &self.content,
+        );
+
+        ensure!(
+            hex::encode(id.to_byte_array()) == self.id,
+            "Event id does not match its content"
+        );
+
+        let pubkey = XOnlyPublicKey::from_slice(&hex::decode(&self.pubkey)?)
+            .context("Invalid event public key")?;
+
+        let sig = schnorr::Signature::from_slice(&hex::decode(&self.sig)?)
+            .context("Invalid event signature")?;
+
+        SECP256K1
+            .verify_schnorr(&sig, &Message::from_digest(id.to_byte_array()), &pubkey)
+            .context("Event signature is invalid")?;
+
+        Ok(())
+    }
+
+    /// Returns the values of all tags with the given name.
+    pub fn tags(&self, name: &str) -> Vec<&[String]> {
+        self.tags
+            .iter()
+            .filter(|tag| tag.first().is_some_and(|n| n == name))
+            .map(|tag| &tag[1..])
+            .collect()
+    }
+
+    /// Returns the first value of the only tag with the given name, if any.
+    fn single_tag_value(&self, name: &str) -> anyhow::Result<Option<&String>> {
+        let tags = self.tags(name);
+
+        ensure!(tags.len() <= 1, "Zap request has multiple {name} tags");
+
+        Ok(tags.first().and_then(|values| values.first()))
+    }
+}
+
+/// Computes the id of an event, the hash of its canonical serialization.
+fn event_id(
+    pubkey: &str,
+    created_at: u64,
+    kind: u64,
+    tags: &[Vec<String>],
+    content: &str,
+) -> sha256::Hash {
+    let serialized = json!([0, pubkey, created_at, kind, tags, content]).to_string();
+
+    sha256::Hash::hash(serialized.as_bytes())
+}
+
+/// Parses and validates a zap request as described in Appendix D of NIP-57.
+pub fn parse_zap_request(zap_request: &str, amount_msat: u64) -> anyhow::Result<NostrEvent> {
+    let event: NostrEvent = serde_json::from_str(zap_request).context("Invalid zap request")?;
+
+    ensure!(
+        event.kind == ZAP_REQUEST_KIND,
+        "Zap request has the wrong kind"
+    );
+
+    event.verify()?;
+
+    ensure!(
+        event.tags("p").len() == 1,
+        "Zap request must have exactly one p tag"
+    );
+
+    event.single_tag_value("e")?;
+
+    ensure!(
+        !event.tags("relays").is_empty(),
+        "Zap request has no relays"
+    );
+
+    if let Some(amount) = event.single_tag_value("amount")? {
+        ensure!(
+            amount.parse::<u64>().ok() == Some(amount_msat),
+            "Zap request amount does not match the invoice amount"
+        );
+    }
+
+    Ok(event)
+}
+
+/// Creates the zap receipt for a paid zap request.
+pub fn zap_receipt(
+    keypair: &Keypair,
+    zap_request: &NostrEvent,
+    zap_request_json: String,
+    bolt11: String,
+    preimage: [u8; 32],
+    paid_at: u64,
+) -> NostrEvent {
+    let mut tags = Vec::new();
+
+    for name in ["p", "e", "a"] {
+        for values in zap_request.tags(name) {
+            if let Some(value) = values.first() {
+                tags.push(vec![name.to_string(), value.clone()]);
+            }
+        }
+    }
+
+    tags.push(vec!["P".to_string(), zap_request.pubkey.clone()]);
+    tags.push(vec!["bolt11".to_string(), bolt11]);
+    tags.push(vec!["description".to_string(), zap_request_json]);
+    tags.push(vec!["preimage".to_string(), hex::encode(preimage)]);
+
+    NostrEvent::sign(keypair, paid_at, ZAP_RECEIPT_KIND, tags, String::new())
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::secp256k1::{Keypair, SECP256K1, SecretKey};
+    use fedimint_core::hex;
+    use serde_json::json;
+
+    use super::{NostrEvent, ZAP_RECEIPT_KIND, ZAP_REQUEST_KIND, parse_zap_request, zap_receipt};
+
+    const PUBKEY: &str = "5f25baa9a1638756ce047cb23364471870e02e84776ca14159896cdd9c1f3c1f";
+
+    /// An event whose id and BIP-340 signature were computed independently of
+    /// this implementation. The content exercises the escaping rules of the
+    /// canonical serialization.
+    fn test_vector() -> NostrEvent {
+        NostrEvent {
+            id: "a8b475be94abca3636bc9c06d903daf140d5939567351868dfc1e71cb2171167".to_string(),
+            pubkey: PUBKEY.to_string(),
+            created_at: 1_700_000_000,
+            kind: 1,
+            tags: vec![
+                vec![
+                    "e".to_string(),
+                    "5c83da77af1dec6d7289834998ad7aafbd9e2191396d75ec3cc27f5a77226f36".to_string(),
+                    "wss://relay.example.com".to_string(),
+                ],
+                vec!["p".to_string(), PUBKEY.to_string()],
+            ],
+            content: "Hello \"nostr\"\né⚡ \\ /".to_string(),
+            sig: "c91ff7c4310b91c017a68c02e75378103da087a213789b5aae6474250062e7f0\
+                  7baa10a4e9d1cb67f484e16ecc9beb8f01b799d9a2e5b699ad4f1d235c18c63a"
+                .to_string(),
+        }
+    }
+
+    fn keypair() -> Keypair {
+        let secret_key = SecretKey::from_slice(
+            &hex::decode("988b478b0235b2a03e82118bda7d96a52dd9654bff6caab7aec68b93a408060f")
+                .unwrap(),
+        )
+        .unwrap();
+
+        Keypair::from_secret_key(SECP256K1, &secret_key)
+    }
+
+    fn zap_request(tags: Vec<Vec<String>>) -> String {
+        serde_json::to_string(&NostrEvent::sign(
+            &keypair(),
+            1_700_000_000,
+            ZAP_REQUEST_KIND,
+            tags,
+            "Great post".to_string(),
+        ))
+        .unwrap()
+    }
+
+    fn tag(values: &[&str]) -> Vec<String> {
+        values.iter().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn verifies_test_vector() {
+        test_vector().verify().expect("Test vector must verify");
+    }
+
+    #[test]
+    fn sign_matches_test_vector_id() {
+        let vector = test_vector();
+
+        let event = NostrEvent::sign(
+            &keypair(),
+            vector.created_at,
+            vector.kind,
+            vector.tags.clone(),
+            vector.content.clone(),
+        );
+
+        assert_eq!(event.pubkey, vector.pubkey);
+        assert_eq!(event.id, vector.id);
+
+        event.verify().expect("Signed event must verify");
+    }
+
+    #[test]
+    fn rejects_tampered_events() {
+        let mut event = test_vector();
+        event.content.push('!');
+        assert!(event.verify().is_err());
+
+        let mut event = test_vector();
+        event.created_at += 1;
+        assert!(event.verify().is_err());
+
+        let mut event = test_vector();
+        event.sig = event.sig.replace('c', "d");
+        assert!(event.verify().is_err());
+
+        // A valid signature of the same content by another key
+        let vector = test_vector();
+        let other = NostrEvent::sign(
+            &Keypair::from_secret_key(SECP256K1, &SecretKey::from_slice(&[42; 32]).unwrap()),
+            vector.created_at,
+            vector.kind,
+            vector.tags.clone(),
+            vector.content.clone(),
+        );
+        let event = NostrEvent {
+            sig: other.sig,
+            ..vector
+        };
+        assert!(event.verify().is_err());
+    }
+
+    #[test]
+    fn parses_valid_zap_request() {
+        let request = zap_request(vec![
+            tag(&["p", PUBKEY]),
+            tag(&["relays", "wss://relay.example.com"]),
+            tag(&["amount", "21000"]),
+        ]);
+
+        let event = parse_zap_request(&request, 21_000).expect("Zap request must be valid");
+
+        assert_eq!(event.content, "Great post");
+        assert_eq!(event.tags("p"), vec![&[PUBKEY.to_string()][..]]);
+    }
+
+    #[test]
+    fn rejects_invalid_zap_requests() {
+        let valid_tags = || {
+            vec![
+                tag(&["p", PUBKEY]),
+                tag(&["relays", "wss://relay.example.com"]),
+            ]
+        };
+
+        assert!(parse_zap_request("not json", 21_000).is_err());
+
+        let wrong_kind = serde_json::to_string(&NostrEvent::sign(
+            &keypair(),
+            1_700_000_000,
+            1,
+            valid_tags(),
+            String::new(),
+        ))
+        .unwrap();
+        assert!(parse_zap_request(&wrong_kind, 21_000).is_err());
+
+        let mut tampered: NostrEvent = serde_json::from_str(&zap_request(valid_tags())).unwrap();
+        tampered.content = "Tampered".to_string();
+        assert!(parse_zap_request(&serde_json::to_string(&tampered).unwrap(), 21_000).is_err());
+
+        let no_p_tag = zap_request(vec![tag(&["relays", "wss://relay.example.com"])]);
+        assert!(parse_zap_request(&no_p_tag, 21_000).is_err());
+
+        let mut two_p_tags = valid_tags();
+        two_p_tags.push(tag(&["p", PUBKEY]));
+        assert!(parse_zap_request(&zap_request(two_p_tags), 21_000).is_err());
+
+        let mut two_e_tags = valid_tags();
+        two_e_tags.push(tag(&["e", "a"]));
+        two_e_tags.push(tag(&["e", "b"]));
+        assert!(parse_zap_request(&zap_request(two_e_tags), 21_000).is_err());
+
+        let no_relays = zap_request(vec![tag(&["p", PUBKEY])]);
+        assert!(parse_zap_request(&no_relays, 21_000).is_err());
+
+        let mut wrong_amount = valid_tags();
+        wrong_amount.push(tag(&["amount", "1000"]));
+        assert!(parse_zap_request(&zap_request(wrong_amount), 21_000).is_err());
+    }
+
+    #[test]
+    fn zap_receipt_commits_to_request() {
+        let request_json = zap_request(vec![
+            tag(&["p", PUBKEY]),
+            tag(&[
+                "e",
+                "5c83da77af1dec6d7289834998ad7aafbd9e2191396d75ec3cc27f5a77226f36",
+            ]),
+            tag(&["relays", "wss://relay.example.com"]),
+        ]);
+
+        let request = parse_zap_request(&request_json, 21_000).unwrap();
+
+        let receiver =
+            Keypair::from_secret_key(SECP256K1, &SecretKey::from_slice(&[42; 32]).unwrap());
+
+        let receipt = zap_receipt(
+            &receiver,
+            &request,
+            request_json.clone(),
+            "lnbc210n1...".to_string(),
+            [7; 32],
+            1_700_000_100,
+        );
+
+        receipt.verify().expect("Zap receipt must verify");
+
+        assert_eq!(receipt.kind, ZAP_RECEIPT_KIND);
+        assert_eq!(receipt.created_at, 1_700_000_100);
+        assert_eq!(
+            receipt.pubkey,
+            hex::encode(receiver.x_only_public_key().0.serialize())
+        );
+
+        let expected = json!([
+            ["p", PUBKEY],
+            [
+                "e",
+                "5c83da77af1dec6d7289834998ad7aafbd9e2191396d75ec3cc27f5a77226f36"
+            ],
+            ["P", PUBKEY],
+            ["bolt11", "lnbc210n1..."],
+            ["description", request_json],
+            ["preimage", hex::encode([7; 32])],
+        ]);
+
+        assert_eq!(serde_json::to_value(&receipt.tags).unwrap(), expected);
+    }
+}
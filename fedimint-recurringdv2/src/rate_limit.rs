@@ -0,0 +1,119 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+use fedimint_core::time::now;
+
+/// The number of clients tracked before buckets that have refilled completely
+/// are dropped. A full bucket is indistinguishable from a new one, so dropping
+/// it does not grant a client any additional requests.
+const MAX_TRACKED_CLIENTS: usize = 10_000;
+
+/// Token buckets limiting the rate of unauthenticated requests per client ip
+/// address, such that a single client cannot fill the database with
+/// registrations or pending payments.
+///
+/// Every bucket holds at most `burst` tokens and refills at
+/// `refill_per_second`; each request takes one token and is rejected if none is
+/// available.
+#[derive(Debug)]
+pub struct IpRateLimiter {
+    burst: f64,
+    refill_per_second: f64,
+    buckets: Mutex<HashMap<IpAddr, Bucket>>,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    last_refill: SystemTime,
+}
+
+impl IpRateLimiter {
+    pub fn new(burst: u32, refill_per_second: f64) -> Self {
+        Self {
+            burst: f64::from(burst),
+            refill_per_second,
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Takes a token from the bucket of the client if one is available,
+    /// returning whether the request may proceed.
+    pub fn try_acquire(&self, ip: IpAddr) -> bool {
+        self.try_acquire_at(ip, now())
+    }
+
+    fn try_acquire_at(&self, ip: IpAddr, now: SystemTime) -> bool {
+        let mut buckets = self
+            .buckets
+            .lock()
+            .expect("No code holding the lock can panic");
+
+        if buckets.len() >= MAX_TRACKED_CLIENTS {
+            buckets.retain(|_, bucket| self.refill(bucket, now) < self.burst);
+        }
+
+        let bucket = buckets.entry(ip).or_insert(Bucket {
+            tokens: self.burst,
+            last_refill: now,
+        });
+
+        bucket.tokens = self.refill(bucket, now);
+        bucket.last_refill = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Returns the tokens of the bucket at the given time. `SystemTime` is not
+    /// monotonic; if the clock moved backwards, nothing is refilled.
+    fn refill(&self, bucket: &Bucket, now: SystemTime) -> f64 {
+        let elapsed = now
+            .duration_since(bucket.last_refill)
+            .unwrap_or_default()
+            .as_secs_f64();
+
+        (bucket.tokens + elapsed * self.refill_per_second).min(self.burst)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{IpAddr, Ipv4Addr};
+    use std::time::Duration;
+
+    use fedimint_core::time::now;
+
+    use super::IpRateLimiter;
+
+    const ALICE: IpAddr = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1));
+    const BOB: IpAddr = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2));
+
+    #[test]
+    fn clients_are_limited_independently() {
+        let limiter = IpRateLimiter::new(2, 1.0);
+        let start = now();
+
+        assert!(limiter.try_acquire_at(ALICE, start));
+        assert!(limiter.try_acquire_at(ALICE, start));
+        assert!(!limiter.try_acquire_at(ALICE, start));
+
+        assert!(limiter.try_acquire_at(BOB, start));
+    }
+
+    #[test]
+    fn tokens_refill_over_time() {
+        let limiter = IpRateLimiter::new(1, 0.1);
+        let start = now();
+
+        assert!(limiter.try_acquire_at(ALICE, start));
+        assert!(!limiter.try_acquire_at(ALICE, start + Duration::from_secs(5)));
+        assert!(limiter.try_acquire_at(ALICE, start + Duration::from_secs(10)));
+    }
+}
@@ -67,9 +67,16 @@ async fn get_block(&self, hash: &BlockHash) -> anyhow::Result<bitcoin::Block> {
     }
 
     async fn get_feerate(&self) -> anyhow::Result<Option<Feerate>> {
+        self.get_feerate_for_target(1).await
+    }
+
+    async fn get_feerate_for_target(
+        &self,
+        confirmation_target: u16,
+    ) -> anyhow::Result<Option<Feerate>> {
         let feerate = block_in_place(|| {
             self.client
-                .estimate_smart_fee(1, Some(EstimateMode::Conservative))
+                .estimate_smart_fee(confirmation_target, Some(EstimateMode::Conservative))
         })?
         .fee_rate
         .map(|per_kb| Feerate {
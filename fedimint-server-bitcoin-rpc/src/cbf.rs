@@ -0,0 +1,1214 @@
+//! Bitcoin backend that follows the chain through the bitcoin P2P network and
+//! verifies blocks against BIP157/158 compact block filters, such that a
+//! guardian does not have to run or trust a full-node-sized service.
+//!
+//! We sync the headers of the chain with the most work announced by our peers
+//! and the basic filter headers committing to the compact filter of every
+//! block. Headers are checked for their proof of work and difficulty
+//! adjustments, and on mainnet the chain has to contain [`checkpoint`].
+//! Blocks are only downloaded when requested via
+//! [`IServerBitcoinRpc::get_block`] and are checked against both their header
+//! and their compact filter. If given a data directory, the chain state is
+//! persisted such that a restart only syncs the blocks mined meanwhile.
+//!
+//! Fee rates are estimated from the average fee rate paid in recent blocks, see
+//! [`estimate_feerate`].
+
+use std::collections::{BTreeMap, HashMap};
+use std::io::SeekFrom;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+use anyhow::{Context, anyhow, bail, ensure};
+use bitcoin::bip158::{BlockFilter, FilterHeader};
+use bitcoin::block::Header;
+use bitcoin::consensus::encode::{deserialize, serialize};
+use bitcoin::hashes::Hash;
+use bitcoin::p2p::message::{NetworkMessage, RawNetworkMessage};
+use bitcoin::p2p::message_blockdata::{GetHeadersMessage, Inventory};
+use bitcoin::p2p::message_filter::{GetCFHeaders, GetCFilters};
+use bitcoin::p2p::message_network::VersionMessage;
+use bitcoin::p2p::{Address, Magic, ServiceFlags};
+use bitcoin::params::Params;
+use bitcoin::{Block, BlockHash, CompactTarget, Network, Transaction};
+use fedimint_core::envs::BitcoinRpcConfig;
+use fedimint_core::time::duration_since_epoch;
+use fedimint_core::util::{FmtCompactAnyhow as _, SafeUrl};
+use fedimint_core::{ChainId, Feerate};
+use fedimint_logging::LOG_SERVER;
+use fedimint_server_core::bitcoin_rpc::IServerBitcoinRpc;
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::sync::Mutex;
+use tracing::{debug, info, warn};
+
+const PEER_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The maximum number of headers a peer returns per `getheaders` request
+const MAX_HEADERS_PER_MESSAGE: usize = 2000;
+
+/// The maximum number of filter headers a peer returns per `getcfheaders`
+/// request
+const MAX_FILTER_HEADERS_PER_MESSAGE: usize = 2000;
+
+/// BIP158 basic filter type
+const BASIC_FILTER_TYPE: u8 = 0;
+
+/// Number of recent blocks we estimate fee rates from
+const FEE_ESTIMATION_WINDOW: usize = 12;
+
+/// The fee rate we never estimate below, 1 sat/vB
+const MIN_FEERATE_SATS_PER_KVB: u64 = 1000;
+
+/// Number of blocks whose median timestamp a new header has to exceed
+const MEDIAN_TIME_SPAN: usize = 11;
+
+/// Files in the data directory the headers and filter headers of our chain are
+/// persisted to, as consecutive 80 and 32 byte records starting at genesis.
+const HEADERS_FILE: &str = "headers.bin";
+const FILTER_HEADERS_FILE: &str = "filter_headers.bin";
+
+const HEADER_LEN: usize = 80;
+const FILTER_HEADER_LEN: usize = 32;
+
+/// A block the best chain has to contain, such that peers cannot feed us a
+/// chain that forks off before it. Proof of work alone would allow a peer to
+/// present a cheap chain with the low difficulty of the early years.
+fn checkpoint(network: Network) -> Option<(usize, BlockHash)> {
+    match network {
+        Network::Bitcoin => Some((
+            840_000,
+            BlockHash::from_str("0000000000000000000320283a032748cef8227873ff4872689bf23f1cda83a5")
+                .expect("Valid block hash"),
+        )),
+        _ => None,
+    }
+}
+
+#[derive(Debug)]
+pub struct CompactFilterClient {
+    url: SafeUrl,
+    state: Mutex<ChainState>,
+}
+
+impl CompactFilterClient {
+    /// Creates a client following the chain via the given peers. The chain
+    /// state is persisted to `data_dir` if given.
+    pub fn new(
+        peers: Vec<SocketAddr>,
+        network: Network,
+        data_dir: Option<PathBuf>,
+    ) -> anyhow::Result<Self> {
+        ensure!(
+            !peers.is_empty(),
+            "At least one bitcoin P2P peer is required"
+        );
+
+        let url = SafeUrl::parse(&format!("p2p://{}", peers[0]))?;
+
+        info!(
+            target: LOG_SERVER,
+            %url,
+            num_peers = peers.len(),
+            "Initializing bitcoin compact block filter backend"
+        );
+
+        Ok(Self {
+            url,
+            state: Mutex::new(ChainState::new(peers, network, data_dir)),
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl IServerBitcoinRpc for CompactFilterClient {
+    fn get_bitcoin_rpc_config(&self) -> BitcoinRpcConfig {
+        BitcoinRpcConfig {
+            kind: "cbf".to_string(),
+            url: self.url.clone(),
+        }
+    }
+
+    fn get_url(&self) -> SafeUrl {
+        self.url.clone()
+    }
+
+    async fn get_block_count(&self) -> anyhow::Result<u64> {
+        let mut state = self.state.lock().await;
+
+        state.sync().await?;
+
+        Ok(state.headers.len() as u64)
+    }
+
+    async fn get_block_hash(&self, height: u64) -> anyhow::Result<BlockHash> {
+        let mut state = self.state.lock().await;
+
+        let height = usize::try_from(height)?;
+
+        if state.headers.len() <= height {
+            state.sync().await?;
+        }
+
+        state
+            .headers
+            .get(height)
+            .map(Header::block_hash)
+            .context("Block height is beyond the chain tip")
+    }
+
+    async fn get_block(&self, block_hash: &BlockHash) -> anyhow::Result<Block> {
+        let mut state = self.state.lock().await;
+
+        if !state.heights.contains_key(block_hash) {
+            state.sync().await?;
+        }
+
+        let height = *state
+            .heights
+            .get(block_hash)
+            .context("Block is not in the best chain")?;
+
+        state.fetch_block(height).await
+    }
+
+    async fn get_feerate(&self) -> anyhow::Result<Option<Feerate>> {
+        self.get_feerate_for_target(1).await
+    }
+
+    async fn get_feerate_for_target(
+        &self,
+        confirmation_target: u16,
+    ) -> anyhow::Result<Option<Feerate>> {
+        let mut state = self.state.lock().await;
+
+        state.sync().await?;
+
+        let tip = state.headers.len();
+
+        let mut block_feerates = vec![];
+
+        for height in tip.saturating_sub(FEE_ESTIMATION_WINDOW)..tip {
+            block_feerates.push(state.block_feerate(height).await?);
+        }
+
+        Ok(estimate_feerate(block_feerates, confirmation_target)
+            .map(|sats_per_kvb| Feerate { sats_per_kvb }))
+    }
+
+    async fn submit_transaction(&self, transaction: Transaction) -> anyhow::Result<()> {
+        let mut state = self.state.lock().await;
+
+        let result = match state.peer().await {
+            Ok(peer) => peer.send(NetworkMessage::Tx(transaction)).await,
+            Err(e) => Err(e),
+        };
+
+        result.inspect_err(|_| state.disconnect())
+    }
+
+    async fn get_sync_progress(&self) -> anyhow::Result<Option<f64>> {
+        // We only report blocks once we have synced their headers
+        Ok(None)
+    }
+
+    async fn get_chain_id(&self) -> anyhow::Result<ChainId> {
+        self.get_block_hash(1).await.map(ChainId::new)
+    }
+}
+
+#[derive(Debug)]
+struct ChainState {
+    peers: Vec<SocketAddr>,
+    network: Network,
+    params: Params,
+    next_peer: usize,
+    connection: Option<PeerConnection>,
+    /// Headers of the best chain indexed by height, starting with genesis
+    headers: Vec<Header>,
+    heights: HashMap<BlockHash, usize>,
+    /// Basic filter headers of the best chain indexed by height
+    filter_headers: Vec<FilterHeader>,
+    /// Average fee rate in sats/kvB paid in the blocks we have fetched
+    block_feerates: BTreeMap<BlockHash, u64>,
+    data_dir: Option<PathBuf>,
+    /// Whether we loaded the chain state persisted in `data_dir`
+    loaded: bool,
+    /// Number of leading headers that are persisted and still in our chain
+    persisted_headers: usize,
+    /// Number of leading filter headers that are persisted and still in our
+    /// chain
+    persisted_filter_headers: usize,
+}
+
+impl ChainState {
+    fn new(peers: Vec<SocketAddr>, network: Network, data_dir: Option<PathBuf>) -> Self {
+        let genesis = bitcoin::constants::genesis_block(network).header;
+
+        Self {
+            peers,
+            network,
+            params: Params::new(network),
+            next_peer: 0,
+            connection: None,
+            headers: vec![genesis],
+            heights: HashMap::from([(genesis.block_hash(), 0)]),
+            filter_headers: vec![],
+            block_feerates: BTreeMap::new(),
+            data_dir,
+            loaded: false,
+            persisted_headers: 0,
+            persisted_filter_headers: 0,
+        }
+    }
+
+    fn disconnect(&mut self) {
+        self.connection = None;
+    }
+
+    /// Returns the connection to our current peer, connecting to the next
+    /// reachable one of our peers if necessary.
+    async fn peer(&mut self) -> anyhow::Result<&mut PeerConnection> {
+        if self.connection.is_none() {
+            for _ in 0..self.peers.len() {
+                let addr = self.peers[self.next_peer % self.peers.len()];
+
+                self.next_peer += 1;
+
+                match PeerConnection::connect(addr, self.network).await {
+                    Ok(connection) => {
+                        self.connection = Some(connection);
+
+                        break;
+                    }
+                    Err(e) => warn!(
+                        target: LOG_SERVER,
+                        %addr,
+                        err = %e.fmt_compact_anyhow(),
+                        "Failed to connect to bitcoin peer"
+                    ),
+                }
+            }
+        }
+
+        self.connection
+            .as_mut()
+            .context("Failed to connect to any bitcoin peer")
+    }
+
+    async fn sync(&mut self) -> anyhow::Result<()> {
+        if !self.loaded {
+            self.load().await;
+
+            self.loaded = true;
+        }
+
+        let result = self.sync_inner().await;
+
+        if let Err(e) = self.persist().await {
+            warn!(
+                target: LOG_SERVER,
+                err = %e.fmt_compact_anyhow(),
+                "Failed to persist bitcoin chain state"
+            );
+        }
+
+        result.inspect_err(|_| self.disconnect())
+    }
+
+    /// Loads the chain state persisted in our data directory. The headers are
+    /// validated as if a peer had sent them, a corrupted state is discarded.
+    async fn load(&mut self) {
+        let Some(data_dir) = self.data_dir.clone() else {
+            return;
+        };
+
+        if let Err(e) = self.load_inner(&data_dir).await {
+            warn!(
+                target: LOG_SERVER,
+                err = %e.fmt_compact_anyhow(),
+                "Discarding persisted bitcoin chain state"
+            );
+
+            *self = Self::new(self.peers.clone(), self.network, Some(data_dir));
+        }
+    }
+
+    async fn load_inner(&mut self, data_dir: &Path) -> anyhow::Result<()> {
+        let headers = match tokio::fs::read(data_dir.join(HEADERS_FILE)).await {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e.into()),
+        };
+
+        // A partially written record at the end is dropped
+        let headers = headers
+            .chunks_exact(HEADER_LEN)
+            .map(deserialize::<Header>)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        ensure!(
+            headers.first() == self.headers.first(),
+            "Persisted headers do not start at our genesis block"
+        );
+
+        for batch in headers[1..].chunks(MAX_HEADERS_PER_MESSAGE) {
+            self.connect_headers(batch.to_vec())?;
+        }
+
+        let filter_headers = tokio::fs::read(data_dir.join(FILTER_HEADERS_FILE))
+            .await
+            .unwrap_or_default();
+
+        self.filter_headers = filter_headers
+            .chunks_exact(FILTER_HEADER_LEN)
+            .take(self.headers.len())
+            .map(|chunk| {
+                FilterHeader::from_byte_array(chunk.try_into().expect("Chunk of 32 bytes"))
+            })
+            .collect();
+
+        self.persisted_headers = self.headers.len();
+        self.persisted_filter_headers = self.filter_headers.len();
+
+        info!(
+            target: LOG_SERVER,
+            tip_height = self.headers.len() - 1,
+            "Loaded persisted bitcoin chain state"
+        );
+
+        Ok(())
+    }
+
+    /// Persists the headers and filter headers of our chain that are not yet
+    /// persisted, dropping persisted ones that were reorganized out.
+    async fn persist(&mut self) -> anyhow::Result<()> {
+        let Some(data_dir) = self.data_dir.clone() else {
+            return Ok(());
+        };
+
+        tokio::fs::create_dir_all(&data_dir).await?;
+
+        let headers = self.headers[self.persisted_headers..]
+            .iter()
+            .flat_map(serialize)
+            .collect::<Vec<u8>>();
+
+        write_records(
+            &data_dir.join(HEADERS_FILE),
+            self.persisted_headers * HEADER_LEN,
+            &headers,
+        )
+        .await?;
+
+        self.persisted_headers = self.headers.len();
+
+        let filter_headers = self.filter_headers[self.persisted_filter_headers..]
+            .iter()
+            .flat_map(|filter_header| filter_header.to_byte_array())
+            .collect::<Vec<u8>>();
+
+        write_records(
+            &data_dir.join(FILTER_HEADERS_FILE),
+            self.persisted_filter_headers * FILTER_HEADER_LEN,
+            &filter_headers,
+        )
+        .await?;
+
+        self.persisted_filter_headers = self.filter_headers.len();
+
+        Ok(())
+    }
+
+    async fn sync_inner(&mut self) -> anyhow::Result<()> {
+        self.sync_headers().await?;
+
+        self.sync_filter_headers().await
+    }
+
+    /// Block locator as defined for `getheaders`, dense for recent blocks and
+    /// exponentially sparser towards genesis.
+    fn locator(&self) -> Vec<BlockHash> {
+        let mut locator = vec![];
+        let mut height = self.headers.len() - 1;
+        let mut step = 1;
+
+        loop {
+            locator.push(self.headers[height].block_hash());
+
+            if height == 0 {
+                return locator;
+            }
+
+            if locator.len() >= 10 {
+                step *= 2;
+            }
+
+            height = height.saturating_sub(step);
+        }
+    }
+
+    async fn sync_headers(&mut self) -> anyhow::Result<()> {
+        // Headers of a fork that does not have more work than our chain yet
+        let mut fork: Vec<Header> = vec![];
+
+        loop {
+            let mut locator = self.locator();
+
+            if let Some(fork_tip) = fork.last() {
+                locator.insert(0, fork_tip.block_hash());
+            }
+
+            let headers = self
+                .peer()
+                .await?
+                .request(
+                    NetworkMessage::GetHeaders(GetHeadersMessage::new(
+                        locator,
+                        BlockHash::all_zeros(),
+                    )),
+                    |message| match message {
+                        NetworkMessage::Headers(headers) => Some(headers),
+                        _ => None,
+                    },
+                )
+                .await?;
+
+            if headers.is_empty() {
+                return Ok(());
+            }
+
+            let batch_size = headers.len();
+
+            self.receive_headers(&mut fork, headers)?;
+
+            if batch_size < MAX_HEADERS_PER_MESSAGE {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Connects a batch of headers a peer sent us. A fork can diverge further
+    /// back than one batch reaches, so the headers of a fork are collected
+    /// across batches until it has more work than our chain or the peer has
+    /// no more headers.
+    fn receive_headers(
+        &mut self,
+        fork: &mut Vec<Header>,
+        headers: Vec<Header>,
+    ) -> anyhow::Result<()> {
+        let last_batch = headers.len() < MAX_HEADERS_PER_MESSAGE;
+
+        if fork
+            .last()
+            .is_none_or(|fork_tip| fork_tip.block_hash() != headers[0].prev_blockhash)
+        {
+            fork.clear();
+        }
+
+        fork.extend(headers);
+
+        if self.connect_headers(fork.clone())? {
+            fork.clear();
+        } else if last_batch {
+            debug!(
+                target: LOG_SERVER,
+                fork_len = fork.len(),
+                "Ignoring headers of a fork with less work"
+            );
+
+            fork.clear();
+        }
+
+        Ok(())
+    }
+
+    /// Connects consecutive headers to our chain, reorganizing if they fork
+    /// off with more work than our chain after the fork point. Returns whether
+    /// the headers were connected.
+    fn connect_headers(&mut self, headers: Vec<Header>) -> anyhow::Result<bool> {
+        let fork_height = *self
+            .heights
+            .get(&headers[0].prev_blockhash)
+            .context("Peer sent headers that do not connect to our chain")?;
+
+        if let Some((checkpoint_height, _)) = checkpoint(self.network) {
+            ensure!(
+                checkpoint_height <= fork_height || self.headers.len() <= checkpoint_height,
+                "Peer sent headers that fork off before the checkpoint"
+            );
+        }
+
+        // The header at the given height of the chain the batch extends
+        let header_at = |height: usize| match height.checked_sub(fork_height + 1) {
+            Some(index) => headers[index],
+            None => self.headers[height],
+        };
+
+        let mut prev_blockhash = headers[0].prev_blockhash;
+
+        for (height, header) in (fork_height + 1..).zip(&headers) {
+            ensure!(
+                header.prev_blockhash == prev_blockhash,
+                "Peer sent headers that are not consecutive"
+            );
+
+            self.check_header(height, header, header_at)?;
+
+            prev_blockhash = header
+                .validate_pow(header.target())
+                .map_err(|e| anyhow!("Peer sent header with invalid proof of work: {e}"))?;
+        }
+
+        let our_work = self.headers[fork_height + 1..]
+            .iter()
+            .map(Header::work)
+            .reduce(|a, b| a + b);
+
+        let their_work = headers
+            .iter()
+            .map(Header::work)
+            .reduce(|a, b| a + b)
+            .expect("Headers are not empty");
+
+        if our_work.is_some_and(|our_work| their_work <= our_work) {
+            return Ok(false);
+        }
+
+        if fork_height + 1 < self.headers.len() {
+            warn!(
+                target: LOG_SERVER,
+                fork_height,
+                tip_height = self.headers.len() - 1,
+                "Bitcoin chain reorganization"
+            );
+
+            for header in self.headers.drain(fork_height + 1..) {
+                self.heights.remove(&header.block_hash());
+            }
+
+            self.filter_headers.truncate(fork_height + 1);
+
+            self.persisted_headers = self.persisted_headers.min(fork_height + 1);
+            self.persisted_filter_headers = self.persisted_filter_headers.min(fork_height + 1);
+        }
+
+        for header in headers {
+            self.heights.insert(header.block_hash(), self.headers.len());
+            self.headers.push(header);
+        }
+
+        Ok(true)
+    }
+
+    /// Checks the header at the given height against the rules of its
+    /// network, given the headers of the chain it extends via `header_at`.
+    fn check_header(
+        &self,
+        height: usize,
+        header: &Header,
+        header_at: impl Fn(usize) -> Header,
+    ) -> anyhow::Result<()> {
+        if let Some((checkpoint_height, checkpoint_hash)) = checkpoint(self.network) {
+            ensure!(
+                height != checkpoint_height || header.block_hash() == checkpoint_hash,
+                "Peer sent a chain that does not contain the checkpoint"
+            );
+        }
+
+        let mut timestamps = (height.saturating_sub(MEDIAN_TIME_SPAN)..height)
+            .map(|height| header_at(height).time)
+            .collect::<Vec<u32>>();
+
+        timestamps.sort_unstable();
+
+        ensure!(
+            timestamps[timestamps.len() / 2] < header.time,
+            "Peer sent header with a timestamp below the median time past"
+        );
+
+        ensure!(
+            header.bits == self.expected_bits(height, header, &header_at),
+            "Peer sent header with unexpected difficulty at height {height}"
+        );
+
+        Ok(())
+    }
+
+    /// The difficulty the header at the given height has to commit to.
+    fn expected_bits(
+        &self,
+        height: usize,
+        header: &Header,
+        header_at: impl Fn(usize) -> Header,
+    ) -> CompactTarget {
+        let interval = usize::try_from(self.params.difficulty_adjustment_interval())
+            .expect("Interval fits into usize");
+
+        let prev = header_at(height - 1);
+
+        if self.params.no_pow_retargeting {
+            return prev.bits;
+        }
+
+        if height % interval == 0 {
+            let first = header_at(height - interval);
+
+            // BIP94 bases the new difficulty on the first block of the period,
+            // such that blocks at minimum difficulty cannot lower it
+            let last_bits = match self.network {
+                Network::Testnet4 => first.bits,
+                _ => prev.bits,
+            };
+
+            return CompactTarget::from_next_work_required(
+                last_bits,
+                u64::from(prev.time.saturating_sub(first.time)),
+                &self.params,
+            );
+        }
+
+        if self.params.allow_min_difficulty_blocks {
+            let min_difficulty = self.params.max_attainable_target.to_compact_lossy();
+
+            // A block more than twenty minutes after its predecessor may be
+            // mined at minimum difficulty
+            if u64::from(header.time) > u64::from(prev.time) + 2 * self.params.pow_target_spacing {
+                return min_difficulty;
+            }
+
+            // Otherwise it has the difficulty of the last block not mined at
+            // minimum difficulty
+            let mut height = height - 1;
+
+            while height % interval != 0 && header_at(height).bits == min_difficulty {
+                height -= 1;
+            }
+
+            return header_at(height).bits;
+        }
+
+        prev.bits
+    }
+
+    async fn sync_filter_headers(&mut self) -> anyhow::Result<()> {
+        while self.filter_headers.len() < self.headers.len() {
+            let start_height = self.filter_headers.len();
+
+            let stop_height =
+                (start_height + MAX_FILTER_HEADERS_PER_MESSAGE - 1).min(self.headers.len() - 1);
+
+            let stop_hash = self.headers[stop_height].block_hash();
+
+            let cf_headers = self
+                .peer()
+                .await?
+                .request(
+                    NetworkMessage::GetCFHeaders(GetCFHeaders {
+                        filter_type: BASIC_FILTER_TYPE,
+                        start_height: u32::try_from(start_height)?,
+                        stop_hash,
+                    }),
+                    |message| match message {
+                        NetworkMessage::CFHeaders(cf_headers)
+                            if cf_headers.stop_hash == stop_hash =>
+                        {
+                            Some(cf_headers)
+                        }
+                        _ => None,
+                    },
+                )
+                .await?;
+
+            let expected_previous = self
+                .filter_headers
+                .last()
+                .copied()
+                .unwrap_or(FilterHeader::all_zeros());
+
+            ensure!(
+                cf_headers.previous_filter_header == expected_previous,
+                "Peer sent filter headers that do not connect to our chain"
+            );
+
+            ensure!(
+                cf_headers.filter_hashes.len() == stop_height - start_height + 1,
+                "Peer sent an unexpected number of filter headers"
+            );
+
+            for filter_hash in cf_headers.filter_hashes {
+                let previous = self
+                    .filter_headers
+                    .last()
+                    .copied()
+                    .unwrap_or(FilterHeader::all_zeros());
+
+                self.filter_headers
+                    .push(filter_hash.filter_header(&previous));
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn fetch_block(&mut self, height: usize) -> anyhow::Result<Block> {
+        let result = self.fetch_block_inner(height).await;
+
+        result.inspect_err(|_| self.disconnect())
+    }
+
+    async fn fetch_block_inner(&mut self, height: usize) -> anyhow::Result<Block> {
+        let block_hash = self.headers[height].block_hash();
+
+        let block = self
+            .peer()
+            .await?
+            .request(
+                NetworkMessage::GetData(vec![Inventory::WitnessBlock(block_hash)]),
+                |message| match message {
+                    NetworkMessage::Block(block) if block.block_hash() == block_hash => {
+                        Some(Ok(block))
+                    }
+                    NetworkMessage::NotFound(_) => Some(Err(anyhow!("Peer does not have block"))),
+                    _ => None,
+                },
+            )
+            .await??;
+
+        ensure!(
+            block.check_merkle_root(),
+            "Block has an invalid merkle root"
+        );
+
+        ensure!(
+            block.check_witness_commitment(),
+            "Block has an invalid witness commitment"
+        );
+
+        let filter = self.fetch_filter(height).await?;
+
+        let scripts = block
+            .txdata
+            .iter()
+            .flat_map(|tx| &tx.output)
+            .map(|tx_out| tx_out.script_pubkey.as_bytes())
+            .filter(|script| !script.is_empty() && script.first() != Some(&0x6a));
+
+        ensure!(
+            filter.match_all(block_hash, scripts)?,
+            "Block does not match its compact filter"
+        );
+
+        Ok(block)
+    }
+
+    /// Fetches the basic filter of the block at the given height and verifies
+    /// it against our filter header chain.
+    async fn fetch_filter(&mut self, height: usize) -> anyhow::Result<BlockFilter> {
+        let block_hash = self.headers[height].block_hash();
+
+        let cf_filter = self
+            .peer()
+            .await?
+            .request(
+                NetworkMessage::GetCFilters(GetCFilters {
+                    filter_type: BASIC_FILTER_TYPE,
+                    start_height: u32::try_from(height)?,
+                    stop_hash: block_hash,
+                }),
+                |message| match message {
+                    NetworkMessage::CFilter(cf_filter) if cf_filter.block_hash == block_hash => {
+                        Some(cf_filter)
+                    }
+                    _ => None,
+                },
+            )
+            .await?;
+
+        let filter = BlockFilter::new(&cf_filter.filter);
+
+        let previous = match height {
+            0 => FilterHeader::all_zeros(),
+            height => self.filter_headers[height - 1],
+        };
+
+        ensure!(
+            filter.filter_header(&previous) == self.filter_headers[height],
+            "Peer sent a filter that does not match our filter header chain"
+        );
+
+        Ok(filter)
+    }
+
+    /// Average fee rate paid in the block at the given height, cached since we
+    /// have to download the block to compute it.
+    async fn block_feerate(&mut self, height: usize) -> anyhow::Result<u64> {
+        let block_hash = self.headers[height].block_hash();
+
+        if let Some(feerate) = self.block_feerates.get(&block_hash) {
+            return Ok(*feerate);
+        }
+
+        let block = self.fetch_block(height).await?;
+
+        let coinbase = block.coinbase().context("Block has no coinbase")?;
+
+        let fees = coinbase
+            .output
+            .iter()
+            .map(|tx_out| tx_out.value.to_sat())
+            .sum::<u64>()
+            .saturating_sub(block_subsidy(height as u64, self.network));
+
+        let feerate = fees * 1000 / block.weight().to_vbytes_ceil();
+
+        self.block_feerates.insert(block_hash, feerate);
+
+        // Only the blocks in the estimation window are ever looked up again
+        let tip = self.headers.len();
+
+        self.block_feerates.retain(|block_hash, _| {
+            self.heights
+                .get(block_hash)
+                .is_some_and(|height| height + FEE_ESTIMATION_WINDOW >= tip)
+        });
+
+        Ok(feerate)
+    }
+}
+
+/// Estimates the fee rate to confirm within `confirmation_target` blocks from
+/// the average fee rates paid in recent blocks. The longer we can wait, the
+/// lower the share of recent blocks we need to outbid.
+fn estimate_feerate(mut block_feerates: Vec<u64>, confirmation_target: u16) -> Option<u64> {
+    if block_feerates.is_empty() {
+        return None;
+    }
+
+    block_feerates.sort_unstable();
+
+    let percentile = match confirmation_target {
+        0..=1 => 90,
+        2..=6 => 50,
+        _ => 10,
+    };
+
+    let feerate = block_feerates[(block_feerates.len() - 1) * percentile / 100];
+
+    Some(feerate.max(MIN_FEERATE_SATS_PER_KVB))
+}
+
+/// Truncates the file to `len` bytes and appends `records`.
+async fn write_records(path: &Path, len: usize, records: &[u8]) -> anyhow::Result<()> {
+    let mut file = tokio::fs::OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(path)
+        .await?;
+
+    file.set_len(u64::try_from(len)?).await?;
+
+    file.seek(SeekFrom::End(0)).await?;
+
+    file.write_all(records).await?;
+
+    file.sync_data().await?;
+
+    Ok(())
+}
+
+fn block_subsidy(height: u64, network: Network) -> u64 {
+    let halving_interval = match network {
+        Network::Regtest => 150,
+        _ => 210_000,
+    };
+
+    50 * 100_000_000u64
+        .checked_shr(u32::try_from(height / halving_interval).unwrap_or(u32::MAX))
+        .unwrap_or(0)
+}
+
+#[derive(Debug)]
+struct PeerConnection {
+    stream: TcpStream,
+    magic: Magic,
+}
+
+impl PeerConnection {
+    async fn connect(addr: SocketAddr, network: Network) -> anyhow::Result<Self> {
+        let stream = tokio::time::timeout(PEER_TIMEOUT, TcpStream::connect(addr))
+            .await
+            .context("Connecting to peer timed out")??;
+
+        let mut connection = Self {
+            stream,
+            magic: Magic::from(network),
+        };
+
+        let now = duration_since_epoch();
+
+        let version = connection
+            .request(
+                NetworkMessage::Version(VersionMessage::new(
+                    ServiceFlags::NONE,
+                    i64::try_from(now.as_secs())?,
+                    Address::new(&addr, ServiceFlags::NONE),
+                    Address::new(&SocketAddr::from(([0, 0, 0, 0], 0)), ServiceFlags::NONE),
+                    // The nonce only serves to detect connections to ourselves
+                    u64::from(now.subsec_nanos()),
+                    "/fedimint/".to_string(),
+                    0,
+                )),
+                |message| match message {
+                    NetworkMessage::Version(version) => Some(version),
+                    _ => None,
+                },
+            )
+            .await?;
+
+        connection.send(NetworkMessage::Verack).await?;
+
+        ensure!(
+            version
+                .services
+                .has(ServiceFlags::WITNESS | ServiceFlags::COMPACT_FILTERS),
+            "Peer does not serve witness blocks and compact block filters"
+        );
+
+        debug!(
+            target: LOG_SERVER,
+            %addr,
+            user_agent = %version.user_agent,
+            "Connected to bitcoin peer"
+        );
+
+        Ok(connection)
+    }
+
+    async fn send(&mut self, message: NetworkMessage) -> anyhow::Result<()> {
+        let bytes = serialize(&RawNetworkMessage::new(self.magic, message));
+
+        tokio::time::timeout(PEER_TIMEOUT, self.stream.write_all(&bytes))
+            .await
+            .context("Sending to peer timed out")??;
+
+        Ok(())
+    }
+
+    async fn receive(&mut self) -> anyhow::Result<NetworkMessage> {
+        // Magic, command, payload length and checksum
+        let mut bytes = vec![0; 24];
+
+        self.stream.read_exact(&mut bytes).await?;
+
+        let payload_len = u32::from_le_bytes(bytes[16..20].try_into().expect("Four bytes"));
+
+        ensure!(
+            payload_len <= 32 * 1024 * 1024,
+            "Peer sent an oversized message"
+        );
+
+        bytes.resize(24 + payload_len as usize, 0);
+
+        self.stream.read_exact(&mut bytes[24..]).await?;
+
+        let message: RawNetworkMessage = deserialize(&bytes)?;
+
+        if *message.magic() != self.magic {
+            bail!("Peer sent a message for a different network");
+        }
+
+        Ok(message.into_payload())
+    }
+
+    /// Sends the request and waits for the first message selected as its
+    /// response, answering pings in the meantime.
+    async fn request<T>(
+        &mut self,
+        request: NetworkMessage,
+        mut response: impl FnMut(NetworkMessage) -> Option<T>,
+    ) -> anyhow::Result<T> {
+        self.send(request).await?;
+
+        tokio::time::timeout(PEER_TIMEOUT, async {
+            loop {
+                match self.receive().await? {
+                    NetworkMessage::Ping(nonce) => self.send(NetworkMessage::Pong(nonce)).await?,
+                    message => {
+                        if let Some(response) = response(message) {
+                            return Ok(response);
+                        }
+                    }
+                }
+            }
+        })
+        .await
+        .context("Peer did not respond in time")?
+    }
+}
+
+#[test]
+fn test_estimate_feerate() {
+    assert_eq!(estimate_feerate(vec![], 1), None);
+
+    let block_feerates = (1..=11).map(|i| i * 2000).collect::<Vec<u64>>();
+
+    assert_eq!(estimate_feerate(block_feerates.clone(), 1), Some(20_000));
+    assert_eq!(estimate_feerate(block_feerates.clone(), 6), Some(12_000));
+    assert_eq!(estimate_feerate(block_feerates, 144), Some(2_000));
+
+    assert_eq!(
+        estimate_feerate(vec![0, 0, 0], 1),
+        Some(MIN_FEERATE_SATS_PER_KVB)
+    );
+}
+
+#[test]
+fn test_block_subsidy() {
+    assert_eq!(block_subsidy(0, Network::Bitcoin), 50 * 100_000_000);
+    assert_eq!(block_subsidy(210_000, Network::Bitcoin), 25 * 100_000_000);
+    assert_eq!(block_subsidy(150, Network::Regtest), 25 * 100_000_000);
+    assert_eq!(block_subsidy(150 * 64, Network::Regtest), 0);
+}
+
+#[cfg(test)]
+fn mine_header(prev: &Header, time: u32, bits: CompactTarget) -> Header {
+    let mut header = Header {
+        version: prev.version,
+        prev_blockhash: prev.block_hash(),
+        merkle_root: prev.merkle_root,
+        time,
+        bits,
+        nonce: 0,
+    };
+
+    while header.validate_pow(header.target()).is_err() {
+        header.nonce += 1;
+    }
+
+    header
+}
+
+#[test]
+fn test_connect_headers_checks_difficulty_and_time() {
+    let mut state = ChainState::new(vec![], Network::Regtest, None);
+
+    let genesis = state.headers[0];
+    let first = mine_header(&genesis, genesis.time + 600, genesis.bits);
+    let second = mine_header(&first, first.time + 600, genesis.bits);
+
+    state
+        .connect_headers(vec![first, second])
+        .expect("Valid headers connect");
+
+    assert_eq!(state.headers.len(), 3);
+
+    // Regtest never retargets, a header has to keep the difficulty
+    let harder = CompactTarget::from_consensus(genesis.bits.to_consensus() - 1);
+
+    assert!(
+        state
+            .connect_headers(vec![mine_header(&second, second.time + 600, harder)])
+            .is_err()
+    );
+
+    assert!(
+        state
+            .connect_headers(vec![mine_header(&second, genesis.time, genesis.bits)])
+            .is_err()
+    );
+
+    assert_eq!(state.headers.len(), 3);
+}
+
+#[tokio::test]
+async fn test_chain_state_is_persisted() {
+    let data_dir = tempfile::tempdir().expect("Temporary directory");
+
+    let mut state = ChainState::new(vec![], Network::Regtest, Some(data_dir.path().into()));
+
+    let genesis = state.headers[0];
+    let mut headers = vec![mine_header(&genesis, genesis.time + 600, genesis.bits)];
+
+    for _ in 0..4 {
+        let prev = headers[headers.len() - 1];
+
+        headers.push(mine_header(&prev, prev.time + 600, genesis.bits));
+    }
+
+    state
+        .connect_headers(headers.clone())
+        .expect("Valid headers connect");
+    state.persist().await.expect("Chain state is persisted");
+
+    // A fork with more work replaces the last two persisted headers
+    let mut fork = vec![];
+    let mut prev = headers[2];
+
+    for _ in 0..3 {
+        prev = mine_header(&prev, prev.time + 601, genesis.bits);
+        fork.push(prev);
+    }
+
+    state.connect_headers(fork).expect("Fork connects");
+    state.persist().await.expect("Chain state is persisted");
+
+    let mut restarted = ChainState::new(vec![], Network::Regtest, Some(data_dir.path().into()));
+
+    restarted.load().await;
+
+    assert_eq!(restarted.headers, state.headers);
+    assert_eq!(restarted.headers.len(), 7);
+}
+
+#[test]
+fn test_fork_with_more_work_is_collected_across_batches() {
+    let mut state = ChainState::new(vec![], Network::Regtest, None);
+
+    let genesis = state.headers[0];
+
+    let mine_chain = |time_offset: u32, len: usize| {
+        let mut headers: Vec<Header> = vec![];
+
+        for _ in 0..len {
+            let prev = headers.last().copied().unwrap_or(genesis);
+
+            headers.push(mine_header(
+                &prev,
+                prev.time + 600 + time_offset,
+                genesis.bits,
+            ));
+        }
+
+        headers
+    };
+
+    let chain = mine_chain(0, MAX_HEADERS_PER_MESSAGE + 1);
+
+    for batch in chain.chunks(MAX_HEADERS_PER_MESSAGE) {
+        state
+            .connect_headers(batch.to_vec())
+            .expect("Valid headers connect");
+    }
+
+    // The first batch of the fork has less work than our chain after genesis
+    let fork = mine_chain(1, MAX_HEADERS_PER_MESSAGE + 2);
+    let mut pending = vec![];
+
+    state
+        .receive_headers(&mut pending, fork[..MAX_HEADERS_PER_MESSAGE].to_vec())
+        .expect("Valid headers are received");
+
+    assert_eq!(state.headers[1..], chain[..]);
+    assert_eq!(pending.len(), MAX_HEADERS_PER_MESSAGE);
+
+    state
+        .receive_headers(&mut pending, fork[MAX_HEADERS_PER_MESSAGE..].to_vec())
+        .expect("Valid headers are received");
+
+    assert_eq!(state.headers[1..], fork[..]);
+    assert!(pending.is_empty());
+}
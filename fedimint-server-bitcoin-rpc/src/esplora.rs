@@ -71,9 +71,18 @@ async fn get_block(&self, block_hash: &BlockHash) -> anyhow::Result<bitcoin::Blo
     }
 
     async fn get_feerate(&self) -> anyhow::Result<Option<Feerate>> {
+        self.get_feerate_for_target(1).await
+    }
+
+    async fn get_feerate_for_target(
+        &self,
+        confirmation_target: u16,
+    ) -> anyhow::Result<Option<Feerate>> {
         let fee_estimates: HashMap<u16, f64> = self.client.get_fee_estimates().await?;
 
-        let fee_rate_vb = esplora_client::convert_fee_rate(1, fee_estimates).unwrap_or(1.0);
+        let fee_rate_vb =
+            esplora_client::convert_fee_rate(usize::from(confirmation_target), fee_estimates)
+                .unwrap_or(1.0);
 
         let fee_rate_kvb = fee_rate_vb * 1_000f32;
 
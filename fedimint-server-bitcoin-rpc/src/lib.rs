@@ -1,4 +1,5 @@
 pub mod bitcoind;
+pub mod cbf;
 pub mod esplora;
 pub mod metrics;
 pub mod tracked;
@@ -112,6 +113,27 @@ async fn get_feerate(&self) -> Result<Option<Feerate>> {
         }
     }
 
+    async fn get_feerate_for_target(&self, confirmation_target: u16) -> Result<Option<Feerate>> {
+        match self
+            .bitcoind_client
+            .get_feerate_for_target(confirmation_target)
+            .await
+        {
+            Ok(feerate) => Ok(feerate),
+            Err(e) => {
+                warn!(
+                    target: LOG_SERVER,
+                    error = %e.fmt_compact_anyhow(),
+                    confirmation_target,
+                    "BitcoindClient failed for get_feerate_for_target, falling back to EsploraClient"
+                );
+                self.esplora_client
+                    .get_feerate_for_target(confirmation_target)
+                    .await
+            }
+        }
+    }
+
     async fn submit_transaction(&self, transaction: Transaction) -> Result<()> {
         match self
             .bitcoind_client
@@ -100,6 +100,14 @@ async fn get_feerate(&self) -> Result<Option<Feerate>> {
         tracked_call!(self, "get_feerate", self.inner.get_feerate().await)
     }
 
+    async fn get_feerate_for_target(&self, confirmation_target: u16) -> Result<Option<Feerate>> {
+        tracked_call!(
+            self,
+            "get_feerate_for_target",
+            self.inner.get_feerate_for_target(confirmation_target).await
+        )
+    }
+
     async fn submit_transaction(&self, transaction: Transaction) -> Result<()> {
         tracked_call!(
             self,
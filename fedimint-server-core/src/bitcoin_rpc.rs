@@ -1,3 +1,4 @@
+use std::collections::BTreeMap;
 use std::fmt::Debug;
 use std::sync::{Arc, OnceLock};
 use std::time::Duration;
@@ -28,6 +29,10 @@
 const MUTINYNET_CHAIN_ID_STR: &str =
     "000002855893a0a9b24eaffc5efc770558a326fee4fc10c9da22fc19cd2954f9";
 
+/// Confirmation targets in blocks for which the monitor estimates a fee rate
+/// in addition to the default one of [`IServerBitcoinRpc::get_feerate`].
+pub const FEERATE_CONFIRMATION_TARGETS: [u16; 3] = [1, 6, 144];
+
 /// Derives the Bitcoin network from a chain ID (block height 1 block hash).
 ///
 /// Returns the corresponding `Network` for well-known genesis hashes,
@@ -108,10 +113,27 @@ async fn fetch_status(rpc: &DynServerBitcoinRpc) -> Result<ServerBitcoinRpcStatu
             rpc.get_feerate().await?.context("Feerate not available")?
         };
 
+        let mut fee_rate_by_target = BTreeMap::new();
+
+        for target in FEERATE_CONFIRMATION_TARGETS {
+            let fee_rate = if network == Network::Regtest {
+                Some(Feerate { sats_per_kvb: 1000 })
+            } else {
+                // A missing estimate for a single target should not mark the
+                // whole backend as unavailable
+                rpc.get_feerate_for_target(target).await.ok().flatten()
+            };
+
+            if let Some(fee_rate) = fee_rate {
+                fee_rate_by_target.insert(target, fee_rate);
+            }
+        }
+
         Ok(ServerBitcoinRpcStatus {
             network,
             block_count,
             fee_rate,
+            fee_rate_by_target,
             sync_progress,
         })
     }
@@ -230,6 +252,15 @@ pub trait IServerBitcoinRpc: Debug + Send + Sync + 'static {
     /// estimation this function returns `None`.
     async fn get_feerate(&self) -> Result<Option<Feerate>>;
 
+    /// Estimates the fee rate for a transaction to confirm within the given
+    /// number of blocks. Backends that cannot estimate for a specific target
+    /// return the default estimate of [`Self::get_feerate`].
+    async fn get_feerate_for_target(&self, confirmation_target: u16) -> Result<Option<Feerate>> {
+        let _ = confirmation_target;
+
+        self.get_feerate().await
+    }
+
     /// Submits a transaction to the Bitcoin network
     ///
     /// It is never OK to consider the success of this call as final, as the
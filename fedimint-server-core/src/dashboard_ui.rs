@@ -123,5 +123,7 @@ pub struct ServerBitcoinRpcStatus {
     pub network: Network,
     pub block_count: u64,
     pub fee_rate: Feerate,
+    /// Fee rate estimates keyed by their confirmation target in blocks
+    pub fee_rate_by_target: BTreeMap<u16, Feerate>,
     pub sync_progress: Option<f64>,
 }
@@ -5,6 +5,7 @@
 pub enum LightningNodeType {
     Lnd,
     Ldk,
+    Cln,
 }
 
 impl fmt::Display for LightningNodeType {
@@ -12,6 +13,7 @@ fn fmt(&self, f: &mut fmt::Formatter<'_>) -> std::result::Result<(), std::fmt::E
         match self {
             LightningNodeType::Lnd => write!(f, "lnd"),
             LightningNodeType::Ldk => write!(f, "ldk"),
+            LightningNodeType::Cln => write!(f, "cln"),
         }
     }
 }
@@ -23,6 +25,7 @@ fn from_str(s: &str) -> Result<Self, Self::Err> {
         match s.to_lowercase().as_str() {
             "lnd" => Ok(LightningNodeType::Lnd),
             "ldk" => Ok(LightningNodeType::Ldk),
+            "cln" => Ok(LightningNodeType::Cln),
             _ => Err(format!("Invalid value for LightningNodeType: {s}")),
         }
     }
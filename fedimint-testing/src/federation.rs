@@ -243,6 +243,11 @@ pub fn online_peer_ids(&self) -> impl Iterator<Item = PeerId> + use<> {
     pub fn is_degraded(&self) -> bool {
         self.num_offline > 0
     }
+
+    /// Returns the config of every guardian, including its private keys
+    pub fn server_configs(&self) -> &BTreeMap<PeerId, ServerConfig> {
+        &self.configs
+    }
 }
 
 /// Builder struct for creating a `FederationTest`.
@@ -15,6 +15,7 @@
 use fedimint_core::db::Database;
 use fedimint_core::db::mem_impl::MemDatabase;
 use fedimint_core::envs::BitcoinRpcConfig;
+use fedimint_core::module::registry::ModuleDecoderRegistry;
 use fedimint_core::task::{MaybeSend, MaybeSync};
 use fedimint_core::util::SafeUrl;
 use fedimint_gateway_common::{
@@ -28,6 +29,7 @@
 use fedimint_logging::TracingSetup;
 use fedimint_server::core::{DynServerModuleInit, IServerModuleInit, ServerModuleInitRegistry};
 use fedimint_server_bitcoin_rpc::bitcoind::BitcoindClient;
+use fedimint_server_bitcoin_rpc::cbf::CompactFilterClient;
 use fedimint_server_bitcoin_rpc::esplora::EsploraClient;
 use fedimint_server_core::bitcoin_rpc::{DynServerBitcoinRpc, IServerBitcoinRpc};
 use fedimint_testing_core::test_dir;
@@ -106,6 +108,20 @@ pub fn new_primary(
                     .into_dyn()
                 }
                 "esplora" => EsploraClient::new(&rpc_config.url).unwrap().into_dyn(),
+                "cbf" => {
+                    let peer = SocketAddr::from_str(&format!(
+                        "{}:{}",
+                        rpc_config
+                            .url
+                            .host_str()
+                            .expect("bitcoin p2p url has a host"),
+                        rpc_config.url.port().expect("bitcoin p2p url has a port"),
+                    ))
+                    .expect("bitcoin p2p url must be a socket address");
+                    CompactFilterClient::new(vec![peer], bitcoin::Network::Regtest, None)
+                        .unwrap()
+                        .into_dyn()
+                }
                 kind => panic!("Unknown bitcoin rpc kind {kind}"),
             };
 
@@ -197,25 +213,22 @@ pub fn new_fed_builder(&self, num_offline: u16) -> FederationTestBuilder {
 
     /// Creates a new Gateway that can be used for module tests.
     pub async fn new_gateway(&self) -> Gateway {
-        // Use server_gens.iter() to match the alphabetical order used by the server
-        // when assigning module instance IDs (BTreeMap iteration order)
-        let module_kinds: Vec<_> = self
-            .servers
-            .iter()
-            .enumerate()
-            .map(|(id, (kind, _))| (id as ModuleInstanceId, kind.clone()))
-            .collect();
-        let decoders = self
-            .servers
-            .available_decoders(module_kinds.iter().map(|(id, kind)| (*id, kind)))
-            .unwrap();
-        let gateway_db = Database::new(MemDatabase::new(), decoders.clone());
+        let gateway_db = Database::new(MemDatabase::new(), self.gateway_decoders());
 
         let mnemonic = Bip39RootSecretStrategy::<12>::random(&mut OsRng);
         Client::store_encodable_client_secret(&gateway_db, mnemonic.to_entropy())
             .await
             .expect("Could not generate root secret for gateway");
 
+        self.new_gateway_with_db(gateway_db).await
+    }
+
+    /// Creates a Gateway on an existing database, such as one restored from a
+    /// disaster recovery bundle. The database has to hold the gateway's root
+    /// secret.
+    pub async fn new_gateway_with_db(&self, gateway_db: Database) -> Gateway {
+        let gateway_db = gateway_db.with_decoders(self.gateway_decoders());
+
         let registry = self
             .clients
             .iter()
@@ -302,6 +315,21 @@ pub async fn new_gateway(&self) -> Gateway {
         .expect("Failed to create gateway")
     }
 
+    fn gateway_decoders(&self) -> ModuleDecoderRegistry {
+        // Use server_gens.iter() to match the alphabetical order used by the server
+        // when assigning module instance IDs (BTreeMap iteration order)
+        let module_kinds: Vec<_> = self
+            .servers
+            .iter()
+            .enumerate()
+            .map(|(id, (kind, _))| (id as ModuleInstanceId, kind.clone()))
+            .collect();
+
+        self.servers
+            .available_decoders(module_kinds.iter().map(|(id, kind)| (*id, kind)))
+            .unwrap()
+    }
+
     /// Get a server bitcoin RPC config
     pub fn bitcoin_server(&self) -> BitcoinRpcConfig {
         self.bitcoin_rpc.clone()
@@ -1,5 +1,6 @@
-use std::sync::Arc;
+use std::collections::BTreeMap;
 use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
 use async_stream::stream;
@@ -9,17 +10,19 @@
 use bitcoin::secp256k1::{self, PublicKey, SecretKey};
 use fedimint_core::Amount;
 use fedimint_core::task::TaskGroup;
+use fedimint_core::time::duration_since_epoch;
 use fedimint_core::util::BoxStream;
 use fedimint_gateway_common::{
     CloseChannelsWithPeerRequest, CloseChannelsWithPeerResponse, ConnectPeerRequest,
     GetInvoiceRequest, GetInvoiceResponse, ListTransactionsResponse, OpenChannelRequest,
-    SendOnchainRequest, SetChannelFeesRequest,
+    PaymentDetails, PaymentDirection, PaymentKind, PaymentStatus, SendOnchainRequest,
+    SetChannelFeesRequest,
 };
 use fedimint_lightning::{
-    CreateInvoiceRequest, CreateInvoiceResponse, GetBalancesResponse, GetLnOnchainAddressResponse,
-    GetNodeInfoResponse, GetRouteHintsResponse, ILnRpcClient, InterceptPaymentRequest,
-    InterceptPaymentResponse, LightningRpcError, ListChannelsResponse, OpenChannelResponse,
-    PayInvoiceResponse, RouteHtlcStream, SendOnchainResponse,
+    CreateInvoiceRequest, CreateInvoiceResponse, FetchOfferInvoiceResponse, GetBalancesResponse,
+    GetLnOnchainAddressResponse, GetNodeInfoResponse, GetRouteHintsResponse, ILnRpcClient,
+    InterceptPaymentRequest, InterceptPaymentResponse, LightningRpcError, ListChannelsResponse,
+    OpenChannelResponse, PayInvoiceResponse, ProbeResponse, RouteHtlcStream, SendOnchainResponse,
 };
 use fedimint_ln_common::PrunedInvoice;
 use fedimint_ln_common::contracts::Preimage;
@@ -36,11 +39,16 @@
 
 pub const MOCK_INVOICE_PREIMAGE: [u8; 32] = [1; 32];
 
+/// The lightning balance reported by `FakeLightningTest`.
+pub const MOCK_LIGHTNING_BALANCE_MSATS: u64 = 100_000_000_000;
+
 #[derive(Debug)]
 pub struct FakeLightningTest {
     pub gateway_node_pub_key: secp256k1::PublicKey,
     gateway_node_sec_key: secp256k1::SecretKey,
     amount_sent: AtomicU64,
+    /// The outgoing payments, which are reported as the node's transactions.
+    payments: Mutex<Vec<PaymentDetails>>,
 }
 
 impl FakeLightningTest {
@@ -54,6 +62,7 @@ pub fn new() -> Self {
             gateway_node_sec_key: SecretKey::from_keypair(&kp),
             gateway_node_pub_key: PublicKey::from_keypair(&kp),
             amount_sent,
+            payments: Mutex::new(Vec::new()),
         }
     }
 }
@@ -153,7 +162,26 @@ async fn pay(
             Ordering::Relaxed,
         );
 
-        if *invoice.payment_secret() == PaymentSecret(INVALID_INVOICE_PAYMENT_SECRET) {
+        let failed = *invoice.payment_secret() == PaymentSecret(INVALID_INVOICE_PAYMENT_SECRET);
+
+        self.payments
+            .lock()
+            .expect("poisoned")
+            .push(PaymentDetails {
+                payment_hash: Some(*invoice.payment_hash()),
+                preimage: None,
+                payment_kind: PaymentKind::Bolt11,
+                amount: Amount::from_msats(invoice.amount_milli_satoshis().unwrap_or_default()),
+                direction: PaymentDirection::Outbound,
+                status: if failed {
+                    PaymentStatus::Failed
+                } else {
+                    PaymentStatus::Succeeded
+                },
+                timestamp_secs: duration_since_epoch().as_secs(),
+            });
+
+        if failed {
             return Err(LightningRpcError::FailedPayment {
                 failure_reason: "Invoice was invalid".to_string(),
             });
@@ -161,6 +189,30 @@ async fn pay(
 
         Ok(PayInvoiceResponse {
             preimage: Preimage(MOCK_INVOICE_PREIMAGE),
+            routing_fee: Some(Amount::ZERO),
+        })
+    }
+
+    fn supports_probe(&self) -> bool {
+        true
+    }
+
+    async fn probe(
+        &self,
+        invoice: Bolt11Invoice,
+        _max_delay: u64,
+        _max_fee: Amount,
+    ) -> Result<ProbeResponse, LightningRpcError> {
+        if *invoice.payment_secret() == PaymentSecret(INVALID_INVOICE_PAYMENT_SECRET) {
+            return Err(LightningRpcError::FailedToProbe {
+                failure_reason: "No route found for the payment".to_string(),
+            });
+        }
+
+        Ok(ProbeResponse {
+            routing_fee: Amount::ZERO,
+            success_probability: Some(1.0),
+            cltv_delta: invoice.min_final_cltv_expiry_delta(),
         })
     }
 
@@ -168,6 +220,27 @@ fn supports_private_payments(&self) -> bool {
         true
     }
 
+    async fn pay_keysend(
+        &self,
+        _payee: PublicKey,
+        amount: Amount,
+        preimage: Preimage,
+        _custom_records: BTreeMap<u64, Vec<u8>>,
+        _max_delay: u64,
+        _max_fee: Amount,
+    ) -> Result<PayInvoiceResponse, LightningRpcError> {
+        self.amount_sent.fetch_add(amount.msats, Ordering::Relaxed);
+
+        Ok(PayInvoiceResponse {
+            preimage,
+            routing_fee: Some(Amount::ZERO),
+        })
+    }
+
+    fn supports_keysend(&self) -> bool {
+        true
+    }
+
     async fn pay_private(
         &self,
         invoice: PrunedInvoice,
@@ -185,6 +258,7 @@ async fn pay_private(
 
         Ok(PayInvoiceResponse {
             preimage: Preimage(MOCK_INVOICE_PREIMAGE),
+            routing_fee: Some(Amount::ZERO),
         })
     }
 
@@ -310,7 +384,7 @@ async fn set_channel_fees(
     async fn get_balances(&self) -> Result<GetBalancesResponse, LightningRpcError> {
         Ok(GetBalancesResponse {
             onchain_balance_sats: 0,
-            lightning_balance_msats: 0,
+            lightning_balance_msats: MOCK_LIGHTNING_BALANCE_MSATS,
             inbound_lightning_liquidity_msats: 0,
         })
     }
@@ -326,12 +400,19 @@ async fn get_invoice(
 
     async fn list_transactions(
         &self,
-        _start_secs: u64,
-        _end_secs: u64,
+        start_secs: u64,
+        end_secs: u64,
     ) -> Result<ListTransactionsResponse, LightningRpcError> {
-        Err(LightningRpcError::FailedToListTransactions {
-            failure_reason: "FakeLightningTest does not support listing transactions".to_string(),
-        })
+        let transactions = self
+            .payments
+            .lock()
+            .expect("poisoned")
+            .iter()
+            .filter(|payment| (start_secs..end_secs).contains(&payment.timestamp_secs))
+            .cloned()
+            .collect();
+
+        Ok(ListTransactionsResponse { transactions })
     }
 
     fn create_offer(
@@ -358,6 +439,28 @@ async fn pay_offer(
         })
     }
 
+    async fn fetch_offer_invoice(
+        &self,
+        _offer: String,
+        _amount: Option<Amount>,
+        _payer_note: Option<String>,
+    ) -> Result<FetchOfferInvoiceResponse, LightningRpcError> {
+        Err(LightningRpcError::Bolt12Error {
+            failure_reason: "FakeLightningTest does not support Bolt12".to_string(),
+        })
+    }
+
+    async fn pay_offer_invoice(
+        &self,
+        _invoice: String,
+        _max_delay: u64,
+        _max_fee: Amount,
+    ) -> Result<PayInvoiceResponse, LightningRpcError> {
+        Err(LightningRpcError::Bolt12Error {
+            failure_reason: "FakeLightningTest does not support Bolt12".to_string(),
+        })
+    }
+
     fn sync_wallet(&self) -> Result<(), LightningRpcError> {
         Ok(())
     }
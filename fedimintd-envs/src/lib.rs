@@ -32,6 +32,8 @@
 
 pub const FM_ESPLORA_URL_ENV: &str = "FM_ESPLORA_URL";
 
+pub const FM_BITCOIN_P2P_PEERS_ENV: &str = "FM_BITCOIN_P2P_PEERS";
+
 pub const FM_ENABLE_IROH_ENV: &str = "FM_ENABLE_IROH";
 
 pub const FM_IROH_P2P_RELAY_ENV: &str = "FM_IROH_P2P_RELAY";
@@ -42,6 +42,7 @@
 use fedimint_server::net::api::ApiSecrets;
 use fedimint_server_bitcoin_rpc::BitcoindClientWithFallback;
 use fedimint_server_bitcoin_rpc::bitcoind::BitcoindClient;
+use fedimint_server_bitcoin_rpc::cbf::CompactFilterClient;
 use fedimint_server_bitcoin_rpc::esplora::EsploraClient;
 use fedimint_server_bitcoin_rpc::tracked::ServerBitcoinRpcTracked;
 use fedimint_server_core::ServerModuleInitRegistryExt;
@@ -50,10 +51,10 @@
 use fedimint_wallet_server::WalletInit;
 use fedimintd_envs::{
     FM_API_URL_ENV, FM_BIND_API_ENV, FM_BIND_API_NEXT_ENV, FM_BIND_METRICS_ENV, FM_BIND_P2P_ENV,
-    FM_BIND_TOKIO_CONSOLE_ENV, FM_BIND_UI_ENV, FM_BITCOIN_NETWORK_ENV, FM_BITCOIND_PASSWORD_ENV,
-    FM_BITCOIND_URL_ENV, FM_BITCOIND_URL_PASSWORD_FILE_ENV, FM_BITCOIND_USERNAME_ENV,
-    FM_DATA_DIR_ENV, FM_DB_CHECKPOINT_RETENTION_ENV, FM_DISABLE_META_MODULE_ENV,
-    FM_ENABLE_IROH_ENV, FM_ESPLORA_URL_ENV, FM_FORCE_API_SECRETS_ENV,
+    FM_BIND_TOKIO_CONSOLE_ENV, FM_BIND_UI_ENV, FM_BITCOIN_NETWORK_ENV, FM_BITCOIN_P2P_PEERS_ENV,
+    FM_BITCOIND_PASSWORD_ENV, FM_BITCOIND_URL_ENV, FM_BITCOIND_URL_PASSWORD_FILE_ENV,
+    FM_BITCOIND_USERNAME_ENV, FM_DATA_DIR_ENV, FM_DB_CHECKPOINT_RETENTION_ENV,
+    FM_DISABLE_META_MODULE_ENV, FM_ENABLE_IROH_ENV, FM_ESPLORA_URL_ENV, FM_FORCE_API_SECRETS_ENV,
     FM_IROH_API_MAX_CONNECTIONS_ENV, FM_IROH_API_MAX_REQUESTS_PER_CONNECTION_ENV,
     FM_IROH_NEXT_ENABLE_ENV, FM_IROH_P2P_RELAY_ENV, FM_P2P_MAX_CONNECTION_AGE_SECS_ENV,
     FM_P2P_URL_ENV, FM_PASSWORD_API_ENV, FM_PASSWORD_UI_ENV, FM_SESSION_TIMEOUT_SECS_ENV,
@@ -71,6 +72,10 @@
 /// Time we will wait before forcefully shutting down tasks
 const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(10);
 
+/// Directory below the data directory the compact block filter backend
+/// persists the bitcoin chain state to
+const BITCOIN_P2P_DIR: &str = "bitcoin-p2p";
+
 #[derive(Parser)]
 #[command(version)]
 #[command(
@@ -89,7 +94,7 @@
         ArgGroup::new("bitcoin_rpc")
             .required(true)
             .multiple(true)
-            .args(["bitcoind_url", "esplora_url"])
+            .args(["bitcoind_url", "esplora_url", "bitcoin_p2p_peers"])
     )
 )]
 struct ServerOpts {
@@ -143,6 +148,17 @@ struct ServerOpts {
     #[arg(long, env = FM_ESPLORA_URL_ENV)]
     esplora_url: Option<SafeUrl>,
 
+    /// Comma separated bitcoin P2P peers, e.g. `127.0.0.1:8333`, to follow the
+    /// chain via compact block filters (BIP157/158) instead of a bitcoind or
+    /// esplora backend. The peers need to serve compact block filters.
+    #[arg(
+        long,
+        env = FM_BITCOIN_P2P_PEERS_ENV,
+        value_delimiter = ',',
+        conflicts_with_all = ["bitcoind_url", "esplora_url"]
+    )]
+    bitcoin_p2p_peers: Vec<SocketAddr>,
+
     /// Address we bind to for p2p consensus communication
     ///
     /// Should be `0.0.0.0:8173` most of the time, as p2p connectivity is public
@@ -460,7 +476,13 @@ pub async fn run(
             .unwrap()
             .into_dyn()
         }
-        _ => unreachable!("ArgGroup already enforced XOR relation"),
+        (None, None) => CompactFilterClient::new(
+            server_opts.bitcoin_p2p_peers.clone(),
+            server_opts.bitcoin_network,
+            Some(server_opts.data_dir.join(BITCOIN_P2P_DIR)),
+        )
+        .unwrap()
+        .into_dyn(),
     };
     let dyn_server_bitcoin_rpc =
         ServerBitcoinRpcTracked::new(dyn_server_bitcoin_rpc, "server").into_dyn();
@@ -518,6 +518,7 @@
             gatewayd
             fedimint-dbtool
             gateway-cli
+            fedimint-cln-plugin
             fedimint-cli
             fedimintd
             fedimint-load-test-tool
@@ -2,8 +2,13 @@
 use fedimint_core::Amount;
 use fedimint_core::config::FederationId;
 use fedimint_core::util::SafeUrl;
-use fedimint_gateway_client::{get_config, get_info, set_fees, set_mnemonic};
-use fedimint_gateway_common::{ConfigPayload, SetFeesPayload, SetMnemonicPayload};
+use fedimint_gateway_client::{
+    fee_history, get_config, get_fee_schedule, get_info, set_fee_schedule, set_fees, set_mnemonic,
+};
+use fedimint_gateway_common::{
+    ConfigPayload, FeeHistoryPayload, FeeSchedule, FeeSchedulePayload, SetFeeSchedulePayload,
+    SetFeesPayload, SetMnemonicPayload,
+};
 use fedimint_ln_common::client::GatewayApi;
 
 use crate::{CliOutput, CliOutputResult};
@@ -39,6 +44,35 @@ pub enum ConfigCommands {
         #[clap(long)]
         tx_ppm: Option<u64>,
     },
+    /// Display the fee schedule of a federation
+    FeeSchedule {
+        #[clap(long)]
+        federation_id: FederationId,
+    },
+    /// Set the fee schedule that adjusts a federation's LNv2 fees by payment
+    /// amount, time of day and liquidity utilization
+    SetFeeSchedule {
+        #[clap(long)]
+        federation_id: FederationId,
+
+        /// The schedule as JSON
+        #[clap(long, value_parser = parse_fee_schedule)]
+        schedule: FeeSchedule,
+    },
+    /// Remove the fee schedule of a federation, such that it only charges its
+    /// flat fees
+    ClearFeeSchedule {
+        #[clap(long)]
+        federation_id: FederationId,
+    },
+    /// List the most recent changes of a federation's published LNv2 fees
+    FeeHistory {
+        #[clap(long)]
+        federation_id: FederationId,
+
+        #[clap(long, default_value_t = 25)]
+        limit: usize,
+    },
     /// Instructs the gateway to create a new mnemonic or set it to the provided
     /// mnemonic
     SetMnemonic {
@@ -47,6 +81,10 @@ pub enum ConfigCommands {
     },
 }
 
+fn parse_fee_schedule(s: &str) -> Result<FeeSchedule, serde_json::Error> {
+    serde_json::from_str(s)
+}
+
 impl ConfigCommands {
     pub async fn handle(self, client: &GatewayApi, base_url: &SafeUrl) -> CliOutputResult {
         match self {
@@ -90,6 +128,54 @@ pub async fn handle(self, client: &GatewayApi, base_url: &SafeUrl) -> CliOutputR
                 .await?;
                 Ok(CliOutput::Empty)
             }
+            Self::FeeSchedule { federation_id } => {
+                let schedule =
+                    get_fee_schedule(client, base_url, FeeSchedulePayload { federation_id })
+                        .await?;
+                Ok(CliOutput::FeeSchedule(schedule))
+            }
+            Self::SetFeeSchedule {
+                federation_id,
+                schedule,
+            } => {
+                set_fee_schedule(
+                    client,
+                    base_url,
+                    SetFeeSchedulePayload {
+                        federation_id,
+                        schedule: Some(schedule),
+                    },
+                )
+                .await?;
+                Ok(CliOutput::Empty)
+            }
+            Self::ClearFeeSchedule { federation_id } => {
+                set_fee_schedule(
+                    client,
+                    base_url,
+                    SetFeeSchedulePayload {
+                        federation_id,
+                        schedule: None,
+                    },
+                )
+                .await?;
+                Ok(CliOutput::Empty)
+            }
+            Self::FeeHistory {
+                federation_id,
+                limit,
+            } => {
+                let history = fee_history(
+                    client,
+                    base_url,
+                    FeeHistoryPayload {
+                        federation_id,
+                        limit,
+                    },
+                )
+                .await?;
+                Ok(CliOutput::FeeHistory(history))
+            }
             Self::SetMnemonic { words } => {
                 set_mnemonic(client, base_url, SetMnemonicPayload { words }).await?;
                 Ok(CliOutput::Empty)
@@ -0,0 +1,160 @@
+use std::path::PathBuf;
+use std::time::{Duration, UNIX_EPOCH};
+
+use clap::Subcommand;
+use fedimint_connectors::error::ServerError;
+use fedimint_core::config::FederationId;
+use fedimint_core::time::now;
+use fedimint_core::util::SafeUrl;
+use fedimint_gateway_client::{export_ledger, ledger, revenue};
+use fedimint_gateway_common::{
+    ExportLedgerPayload, LedgerFormat, LedgerPayload, RevenueInterval, RevenuePayload,
+};
+use fedimint_ln_common::client::GatewayApi;
+
+use crate::{CliOutput, CliOutputResult};
+
+/// Accounting commands for the double-entry ledger the gateway derives from
+/// its payments, peg-ins and peg-outs. All time ranges default to the last
+/// day and are given in milliseconds since the unix epoch.
+#[derive(Subcommand)]
+pub enum LedgerCommands {
+    /// List the ledger entries within the time range
+    Entries {
+        #[clap(long)]
+        start: Option<u64>,
+
+        #[clap(long)]
+        end: Option<u64>,
+
+        /// Only list the entries of this federation
+        #[clap(long)]
+        federation_id: Option<FederationId>,
+    },
+    /// Export the ledger entries within the time range for accounting tools
+    Export {
+        #[clap(long)]
+        start: Option<u64>,
+
+        #[clap(long)]
+        end: Option<u64>,
+
+        /// Only export the entries of this federation
+        #[clap(long)]
+        federation_id: Option<FederationId>,
+
+        #[clap(long, value_enum, default_value_t = LedgerFormat::Csv)]
+        format: LedgerFormat,
+
+        /// Write the export to this file instead of stdout
+        #[clap(long)]
+        output: Option<PathBuf>,
+    },
+    /// List the fees earned and the routing cost within the time range
+    Revenue {
+        #[clap(long)]
+        start: Option<u64>,
+
+        #[clap(long)]
+        end: Option<u64>,
+
+        /// Only include the revenue of this federation
+        #[clap(long)]
+        federation_id: Option<FederationId>,
+
+        #[clap(long, value_enum, default_value_t = RevenueInterval::Hour)]
+        interval: RevenueInterval,
+    },
+}
+
+/// Resolves the time range of a command, which defaults to the last day.
+fn time_range(start: Option<u64>, end: Option<u64>) -> Result<(u64, u64), ServerError> {
+    let to_millis = |duration: Duration| -> Result<u64, ServerError> {
+        duration
+            .as_millis()
+            .try_into()
+            .map_err(|e| ServerError::InternalClientError(anyhow::anyhow!("{e}")))
+    };
+
+    let now_millis = to_millis(now().duration_since(UNIX_EPOCH).expect("Before unix epoch"))?;
+    let end_millis = end.unwrap_or(now_millis);
+    let start_millis = match start {
+        Some(start_millis) => start_millis,
+        None => end_millis.saturating_sub(to_millis(Duration::from_hours(24))?),
+    };
+
+    Ok((start_millis, end_millis))
+}
+
+impl LedgerCommands {
+    pub async fn handle(self, client: &GatewayApi, base_url: &SafeUrl) -> CliOutputResult {
+        match self {
+            Self::Entries {
+                start,
+                end,
+                federation_id,
+            } => {
+                let (start_millis, end_millis) = time_range(start, end)?;
+                let entries = ledger(
+                    client,
+                    base_url,
+                    LedgerPayload {
+                        start_millis,
+                        end_millis,
+                        federation_id,
+                    },
+                )
+                .await?;
+                Ok(CliOutput::Ledger(entries))
+            }
+            Self::Export {
+                start,
+                end,
+                federation_id,
+                format,
+                output,
+            } => {
+                let (start_millis, end_millis) = time_range(start, end)?;
+                let export = export_ledger(
+                    client,
+                    base_url,
+                    ExportLedgerPayload {
+                        start_millis,
+                        end_millis,
+                        federation_id,
+                        format,
+                    },
+                )
+                .await?;
+
+                match output {
+                    Some(path) => std::fs::write(path, export.contents)
+                        .map_err(|e| ServerError::InternalClientError(e.into()))?,
+                    None => print!("{}", export.contents),
+                }
+
+                Ok(CliOutput::Empty)
+            }
+            Self::Revenue {
+                start,
+                end,
+                federation_id,
+                interval,
+            } => {
+                let (start_millis, end_millis) = time_range(start, end)?;
+                let revenue = revenue(
+                    client,
+                    base_url,
+                    RevenuePayload {
+                        start_millis,
+                        end_millis,
+                        interval,
+                        federation_id,
+                    },
+                )
+                .await?;
+                Ok(CliOutput::Revenue(revenue))
+            }
+        }
+    }
+}
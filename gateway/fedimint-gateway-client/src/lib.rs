@@ -9,28 +9,41 @@
 use fedimint_core::invite_code::InviteCode;
 use fedimint_core::util::SafeUrl;
 use fedimint_gateway_common::{
-    ADDRESS_ENDPOINT, ADDRESS_RECHECK_ENDPOINT, BACKUP_ENDPOINT, BackupPayload,
-    CLOSE_CHANNELS_WITH_PEER_ENDPOINT, CONFIGURATION_ENDPOINT, CONNECT_FED_ENDPOINT,
-    CONNECT_PEER_ENDPOINT, CREATE_BOLT11_INVOICE_FOR_OPERATOR_ENDPOINT,
-    CREATE_BOLT12_OFFER_FOR_OPERATOR_ENDPOINT, ChannelInfo, CloseChannelsWithPeerRequest,
-    CloseChannelsWithPeerResponse, ConfigPayload, ConnectFedPayload, ConnectPeerRequest,
-    CreateInvoiceForOperatorPayload, CreateOfferPayload, CreateOfferResponse,
-    DepositAddressPayload, DepositAddressRecheckPayload, FEDERATION_STATUS_ENDPOINT,
-    FederationInfo, FederationStatusRequest, FederationStatusResponse, GATEWAY_INFO_ENDPOINT,
-    GET_BALANCES_ENDPOINT, GET_INVOICE_ENDPOINT, GET_LN_ONCHAIN_ADDRESS_ENDPOINT, GatewayBalances,
-    GatewayFedConfig, GatewayInfo, GetInvoiceRequest, GetInvoiceResponse, INVITE_CODES_ENDPOINT,
-    LEAVE_FED_ENDPOINT, LIST_CHANNELS_ENDPOINT, LIST_TRANSACTIONS_ENDPOINT, LeaveFedPayload,
-    ListTransactionsPayload, ListTransactionsResponse, MNEMONIC_ENDPOINT, MnemonicResponse,
-    OPEN_CHANNEL_ENDPOINT, OPEN_CHANNEL_WITH_PUSH_ENDPOINT, OpenChannelRequest,
+    ADDRESS_ENDPOINT, ADDRESS_RECHECK_ENDPOINT, API_TOKEN_AUDIT_LOG_ENDPOINT, API_TOKENS_ENDPOINT,
+    ApiTokenAuditLogPayload, ApiTokenAuditLogResponse, ApiTokenInfo, BACKUP_ENDPOINT,
+    BackupPayload, CHANNEL_ORDER_STATUS_ENDPOINT, CLOSE_CHANNELS_WITH_PEER_ENDPOINT,
+    CONFIGURATION_ENDPOINT, CONNECT_FED_ENDPOINT, CONNECT_PEER_ENDPOINT, CREATE_API_TOKEN_ENDPOINT,
+    CREATE_BOLT11_INVOICE_FOR_OPERATOR_ENDPOINT, CREATE_BOLT12_OFFER_FOR_OPERATOR_ENDPOINT,
+    CREATE_LNURL_WITHDRAW_ENDPOINT, ChannelInfo, ChannelOrderPayload, ChannelOrderResponse,
+    ChannelOrderStatusPayload, CloseChannelsWithPeerRequest, CloseChannelsWithPeerResponse,
+    ConfigPayload, ConnectFedPayload, ConnectPeerRequest, CreateApiTokenPayload,
+    CreateApiTokenResponse, CreateInvoiceForOperatorPayload, CreateLnurlWithdrawPayload,
+    CreateLnurlWithdrawResponse, CreateOfferPayload, CreateOfferResponse, DepositAddressPayload,
+    DepositAddressRecheckPayload, EXPORT_LEDGER_ENDPOINT, ExportLedgerPayload,
+    ExportLedgerResponse, FEDERATION_STATUS_ENDPOINT, FEE_HISTORY_ENDPOINT, FEE_SCHEDULE_ENDPOINT,
+    FederationInfo, FederationStatusRequest, FederationStatusResponse, FeeHistoryPayload,
+    FeeHistoryResponse, FeeSchedule, FeeSchedulePayload, GATEWAY_INFO_ENDPOINT,
+    GET_BALANCES_ENDPOINT, GET_INVOICE_ENDPOINT, GET_LIQUIDITY_POLICY_ENDPOINT,
+    GET_LN_ONCHAIN_ADDRESS_ENDPOINT, GatewayBalances, GatewayFedConfig, GatewayInfo,
+    GetInvoiceRequest, GetInvoiceResponse, INVITE_CODES_ENDPOINT, LEAVE_FED_ENDPOINT,
+    LEDGER_ENDPOINT, LIQUIDITY_LOG_ENDPOINT, LIST_CHANNELS_ENDPOINT, LIST_TRANSACTIONS_ENDPOINT,
+    LSP_INFO_ENDPOINT, LeaveFedPayload, LedgerPayload, LedgerResponse, LiquidityLogPayload,
+    LiquidityLogResponse, LiquidityPolicy, ListTransactionsPayload, ListTransactionsResponse,
+    LspInfo, MNEMONIC_ENDPOINT, MnemonicResponse, OPEN_CHANNEL_ENDPOINT,
+    OPEN_CHANNEL_WITH_PUSH_ENDPOINT, ORDER_CHANNEL_ENDPOINT, OpenChannelRequest,
     PAY_INVOICE_FOR_OPERATOR_ENDPOINT, PAY_OFFER_FOR_OPERATOR_ENDPOINT, PAYMENT_LOG_ENDPOINT,
     PAYMENT_SUMMARY_ENDPOINT, PEGIN_FROM_ONCHAIN_ENDPOINT, PayInvoiceForOperatorPayload,
     PayOfferPayload, PayOfferResponse, PaymentLogPayload, PaymentLogResponse,
     PaymentSummaryPayload, PaymentSummaryResponse, PeginFromOnchainPayload, RECEIVE_ECASH_ENDPOINT,
-    ReceiveEcashPayload, ReceiveEcashResponse, SEND_ONCHAIN_ENDPOINT, SET_CHANNEL_FEES_ENDPOINT,
-    SET_FEES_ENDPOINT, SPEND_ECASH_ENDPOINT, STOP_ENDPOINT, SendOnchainRequest,
-    SetChannelFeesRequest, SetFeesPayload, SetMnemonicPayload, SpendEcashPayload,
-    SpendEcashResponse, WITHDRAW_ENDPOINT, WITHDRAW_TO_ONCHAIN_ENDPOINT, WithdrawPayload,
-    WithdrawResponse, WithdrawToOnchainPayload,
+    REVENUE_ENDPOINT, REVOKE_API_TOKEN_ENDPOINT, RISK_STATUS_ENDPOINT, ReceiveEcashPayload,
+    ReceiveEcashResponse, RevenuePayload, RevenueResponse, RevokeApiTokenPayload,
+    RiskStatusResponse, SEND_ONCHAIN_ENDPOINT, SET_CHANNEL_FEES_ENDPOINT,
+    SET_FEE_SCHEDULE_ENDPOINT, SET_FEES_ENDPOINT, SET_LIQUIDITY_POLICY_ENDPOINT,
+    SET_RISK_LIMITS_ENDPOINT, SPEND_ECASH_ENDPOINT, STOP_ENDPOINT, SendOnchainRequest,
+    SetChannelFeesRequest, SetFeeSchedulePayload, SetFeesPayload, SetLiquidityPolicyPayload,
+    SetMnemonicPayload, SetRiskLimitsPayload, SpendEcashPayload, SpendEcashResponse,
+    WITHDRAW_ENDPOINT, WITHDRAW_TO_ONCHAIN_ENDPOINT, WithdrawPayload, WithdrawResponse,
+    WithdrawToOnchainPayload,
 };
 use fedimint_ln_common::Method;
 use fedimint_ln_common::client::GatewayApi;
@@ -181,6 +194,163 @@ pub async fn set_fees(
         .await
 }
 
+pub async fn get_fee_schedule(
+    client: &GatewayApi,
+    base_url: &SafeUrl,
+    payload: FeeSchedulePayload,
+) -> ServerResult<Option<FeeSchedule>> {
+    client
+        .request(base_url, Method::POST, FEE_SCHEDULE_ENDPOINT, Some(payload))
+        .await
+}
+
+pub async fn set_fee_schedule(
+    client: &GatewayApi,
+    base_url: &SafeUrl,
+    payload: SetFeeSchedulePayload,
+) -> ServerResult<()> {
+    client
+        .request(
+            base_url,
+            Method::POST,
+            SET_FEE_SCHEDULE_ENDPOINT,
+            Some(payload),
+        )
+        .await
+}
+
+pub async fn fee_history(
+    client: &GatewayApi,
+    base_url: &SafeUrl,
+    payload: FeeHistoryPayload,
+) -> ServerResult<FeeHistoryResponse> {
+    client
+        .request(base_url, Method::POST, FEE_HISTORY_ENDPOINT, Some(payload))
+        .await
+}
+
+pub async fn risk_status(
+    client: &GatewayApi,
+    base_url: &SafeUrl,
+) -> ServerResult<RiskStatusResponse> {
+    client
+        .request::<(), RiskStatusResponse>(base_url, Method::GET, RISK_STATUS_ENDPOINT, None)
+        .await
+}
+
+pub async fn set_risk_limits(
+    client: &GatewayApi,
+    base_url: &SafeUrl,
+    payload: SetRiskLimitsPayload,
+) -> ServerResult<()> {
+    client
+        .request(
+            base_url,
+            Method::POST,
+            SET_RISK_LIMITS_ENDPOINT,
+            Some(payload),
+        )
+        .await
+}
+
+pub async fn list_api_tokens(
+    client: &GatewayApi,
+    base_url: &SafeUrl,
+) -> ServerResult<Vec<ApiTokenInfo>> {
+    client
+        .request::<(), Vec<ApiTokenInfo>>(base_url, Method::GET, API_TOKENS_ENDPOINT, None)
+        .await
+}
+
+pub async fn create_api_token(
+    client: &GatewayApi,
+    base_url: &SafeUrl,
+    payload: CreateApiTokenPayload,
+) -> ServerResult<CreateApiTokenResponse> {
+    client
+        .request(
+            base_url,
+            Method::POST,
+            CREATE_API_TOKEN_ENDPOINT,
+            Some(payload),
+        )
+        .await
+}
+
+pub async fn revoke_api_token(
+    client: &GatewayApi,
+    base_url: &SafeUrl,
+    payload: RevokeApiTokenPayload,
+) -> ServerResult<()> {
+    client
+        .request(
+            base_url,
+            Method::POST,
+            REVOKE_API_TOKEN_ENDPOINT,
+            Some(payload),
+        )
+        .await
+}
+
+pub async fn api_token_audit_log(
+    client: &GatewayApi,
+    base_url: &SafeUrl,
+    payload: ApiTokenAuditLogPayload,
+) -> ServerResult<ApiTokenAuditLogResponse> {
+    client
+        .request(
+            base_url,
+            Method::POST,
+            API_TOKEN_AUDIT_LOG_ENDPOINT,
+            Some(payload),
+        )
+        .await
+}
+
+pub async fn get_liquidity_policy(
+    client: &GatewayApi,
+    base_url: &SafeUrl,
+) -> ServerResult<Option<LiquidityPolicy>> {
+    client
+        .request::<(), Option<LiquidityPolicy>>(
+            base_url,
+            Method::GET,
+            GET_LIQUIDITY_POLICY_ENDPOINT,
+            None,
+        )
+        .await
+}
+
+pub async fn set_liquidity_policy(
+    client: &GatewayApi,
+    base_url: &SafeUrl,
+    payload: SetLiquidityPolicyPayload,
+) -> ServerResult<()> {
+    client
+        .request(
+            base_url,
+            Method::POST,
+            SET_LIQUIDITY_POLICY_ENDPOINT,
+            Some(payload),
+        )
+        .await
+}
+
+pub async fn liquidity_log(
+    client: &GatewayApi,
+    base_url: &SafeUrl,
+    payload: LiquidityLogPayload,
+) -> ServerResult<LiquidityLogResponse> {
+    client
+        .request(
+            base_url,
+            Method::POST,
+            LIQUIDITY_LOG_ENDPOINT,
+            Some(payload),
+        )
+        .await
+}
+
 pub async fn create_invoice_for_self(
     client: &GatewayApi,
     base_url: &SafeUrl,
@@ -398,6 +568,77 @@ pub async fn payment_summary(
         .await
 }
 
+pub async fn lsp_info(client: &GatewayApi, base_url: &SafeUrl) -> ServerResult<LspInfo> {
+    client
+        .request::<(), LspInfo>(base_url, Method::GET, LSP_INFO_ENDPOINT, None)
+        .await
+}
+
+pub async fn order_channel(
+    client: &GatewayApi,
+    base_url: &SafeUrl,
+    payload: ChannelOrderPayload,
+) -> ServerResult<ChannelOrderResponse> {
+    client
+        .request(
+            base_url,
+            Method::POST,
+            ORDER_CHANNEL_ENDPOINT,
+            Some(payload),
+        )
+        .await
+}
+
+pub async fn channel_order_status(
+    client: &GatewayApi,
+    base_url: &SafeUrl,
+    payload: ChannelOrderStatusPayload,
+) -> ServerResult<ChannelOrderResponse> {
+    client
+        .request(
+            base_url,
+            Method::POST,
+            CHANNEL_ORDER_STATUS_ENDPOINT,
+            Some(payload),
+        )
+        .await
+}
+
+pub async fn ledger(
+    client: &GatewayApi,
+    base_url: &SafeUrl,
+    payload: LedgerPayload,
+) -> ServerResult<LedgerResponse> {
+    client
+        .request(base_url, Method::POST, LEDGER_ENDPOINT, Some(payload))
+        .await
+}
+
+pub async fn export_ledger(
+    client: &GatewayApi,
+    base_url: &SafeUrl,
+    payload: ExportLedgerPayload,
+) -> ServerResult<ExportLedgerResponse> {
+    client
+        .request(
+            base_url,
+            Method::POST,
+            EXPORT_LEDGER_ENDPOINT,
+            Some(payload),
+        )
+        .await
+}
+
+pub async fn revenue(
+    client: &GatewayApi,
+    base_url: &SafeUrl,
+    payload: RevenuePayload,
+) -> ServerResult<RevenueResponse> {
+    client
+        .request(base_url, Method::POST, REVENUE_ENDPOINT, Some(payload))
+        .await
+}
+
 pub async fn get_invoice(
     client: &GatewayApi,
     base_url: &SafeUrl,
@@ -438,6 +679,21 @@ pub async fn create_offer(
         .await
 }
 
+pub async fn create_lnurl_withdraw(
+    client: &GatewayApi,
+    base_url: &SafeUrl,
+    payload: CreateLnurlWithdrawPayload,
+) -> ServerResult<CreateLnurlWithdrawResponse> {
+    client
+        .request(
+            base_url,
+            Method::POST,
+            CREATE_LNURL_WITHDRAW_ENDPOINT,
+            Some(payload),
+        )
+        .await
+}
+
 pub async fn pay_offer(
     client: &GatewayApi,
     base_url: &SafeUrl,
@@ -3,15 +3,17 @@
 use clap::Subcommand;
 use fedimint_connectors::error::ServerError;
 use fedimint_core::Amount;
+use fedimint_core::config::FederationId;
 use fedimint_gateway_client::{
-    close_channels_with_peer, connect_peer, create_invoice_for_self, create_offer, get_invoice,
-    list_channels, list_transactions, open_channel, open_channel_with_push, pay_invoice, pay_offer,
-    set_channel_fees,
+    close_channels_with_peer, connect_peer, create_invoice_for_self, create_lnurl_withdraw,
+    create_offer, get_invoice, list_channels, list_transactions, open_channel,
+    open_channel_with_push, pay_invoice, pay_offer, set_channel_fees,
 };
 use fedimint_gateway_common::{
     CloseChannelsWithPeerRequest, ConnectPeerRequest, CreateInvoiceForOperatorPayload,
-    CreateOfferPayload, GetInvoiceRequest, ListTransactionsPayload, NodeAddress,
-    OpenChannelRequest, PayInvoiceForOperatorPayload, PayOfferPayload, SetChannelFeesRequest,
+    CreateLnurlWithdrawPayload, CreateOfferPayload, GetInvoiceRequest, ListTransactionsPayload,
+    NodeAddress, OpenChannelRequest, PayInvoiceForOperatorPayload, PayOfferPayload,
+    SetChannelFeesRequest,
 };
 use fedimint_ln_common::client::GatewayApi;
 use lightning_invoice::Bolt11Invoice;
@@ -149,6 +151,25 @@ pub enum LightningCommands {
         #[clap(long)]
         payer_note: Option<String>,
     },
+    /// Create an LNURL-withdraw link that can be redeemed `uses` times, paid
+    /// from the lightning node's balance or, if a federation is given, from
+    /// the gateway's ecash in that federation.
+    CreateLnurlWithdraw {
+        #[clap(long)]
+        min_withdrawable_msat: u64,
+
+        #[clap(long)]
+        max_withdrawable_msat: u64,
+
+        #[clap(long, default_value_t = 1)]
+        uses: u64,
+
+        #[clap(long, default_value = "Withdraw from gateway")]
+        description: String,
+
+        #[clap(long)]
+        federation_id: Option<FederationId>,
+    },
 }
 
 fn parse_datetime(s: &str) -> Result<DateTime<Utc>, chrono::ParseError> {
@@ -204,6 +225,7 @@ pub async fn handle(self, client: &GatewayApi, base_url: &SafeUrl) -> CliOutputR
                     fee_rate_sats_per_vbyte,
                     base_fee_msat,
                     parts_per_million,
+                    unannounced: false,
                 };
                 let funding_txid = if payload.push_amount_sats > 0 {
                     open_channel_with_push(client, base_url, payload).await?
@@ -316,6 +338,27 @@ pub async fn handle(self, client: &GatewayApi, base_url: &SafeUrl) -> CliOutputR
                 .await?;
                 Ok(CliOutput::OfferPayment(response))
             }
+            Self::CreateLnurlWithdraw {
+                min_withdrawable_msat,
+                max_withdrawable_msat,
+                uses,
+                description,
+                federation_id,
+            } => {
+                let response = create_lnurl_withdraw(
+                    client,
+                    base_url,
+                    CreateLnurlWithdrawPayload {
+                        min_withdrawable: Amount::from_msats(min_withdrawable_msat),
+                        max_withdrawable: Amount::from_msats(max_withdrawable_msat),
+                        uses,
+                        description,
+                        federation_id,
+                    },
+                )
+                .await?;
+                Ok(CliOutput::LnurlWithdraw(response))
+            }
         }
     }
 }
@@ -0,0 +1,67 @@
+use clap::Subcommand;
+use fedimint_core::util::SafeUrl;
+use fedimint_gateway_client::{get_liquidity_policy, liquidity_log, set_liquidity_policy};
+use fedimint_gateway_common::{LiquidityLogPayload, LiquidityPolicy, SetLiquidityPolicyPayload};
+use fedimint_ln_common::client::GatewayApi;
+
+use crate::{CliOutput, CliOutputResult};
+
+/// Liquidity management commands for configuring how the gateway rebalances
+/// its funds between the federations, the lightning node's channels and its
+/// onchain wallet.
+#[derive(Subcommand)]
+pub enum LiquidityCommands {
+    /// Display the gateway's liquidity policy.
+    Policy,
+    /// Set the gateway's liquidity policy, which enables the liquidity
+    /// manager.
+    SetPolicy {
+        /// The policy as JSON
+        #[clap(long, value_parser = parse_policy)]
+        policy: LiquidityPolicy,
+    },
+    /// Remove the gateway's liquidity policy, which disables the liquidity
+    /// manager.
+    ClearPolicy,
+    /// List the most recent actions of the liquidity manager.
+    Log {
+        #[clap(long, default_value_t = 25)]
+        limit: usize,
+    },
+}
+
+fn parse_policy(s: &str) -> Result<LiquidityPolicy, serde_json::Error> {
+    serde_json::from_str(s)
+}
+
+impl LiquidityCommands {
+    pub async fn handle(self, client: &GatewayApi, base_url: &SafeUrl) -> CliOutputResult {
+        match self {
+            Self::Policy => {
+                let policy = get_liquidity_policy(client, base_url).await?;
+                Ok(CliOutput::LiquidityPolicy(policy))
+            }
+            Self::SetPolicy { policy } => {
+                set_liquidity_policy(
+                    client,
+                    base_url,
+                    SetLiquidityPolicyPayload {
+                        policy: Some(policy),
+                    },
+                )
+                .await?;
+                Ok(CliOutput::Empty)
+            }
+            Self::ClearPolicy => {
+                set_liquidity_policy(client, base_url, SetLiquidityPolicyPayload { policy: None })
+                    .await?;
+                Ok(CliOutput::Empty)
+            }
+            Self::Log { limit } => {
+                let response =
+                    liquidity_log(client, base_url, LiquidityLogPayload { limit }).await?;
+                Ok(CliOutput::LiquidityLog(response))
+            }
+        }
+    }
+}
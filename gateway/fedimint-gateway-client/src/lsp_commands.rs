@@ -0,0 +1,85 @@
+use bitcoin::hashes::sha256;
+use bitcoin::secp256k1::PublicKey;
+use clap::Subcommand;
+use fedimint_core::config::FederationId;
+use fedimint_core::util::SafeUrl;
+use fedimint_gateway_client::{channel_order_status, lsp_info, order_channel};
+use fedimint_gateway_common::{ChannelOrderPayload, ChannelOrderStatusPayload};
+use fedimint_ln_common::client::GatewayApi;
+
+use crate::{CliOutput, CliOutputResult};
+
+/// LSP commands for external wallets that buy channels from a gateway running
+/// the LDK backend. These commands do not require the gateway's password.
+#[derive(Subcommand)]
+pub enum LspCommands {
+    /// Display the services and prices of the gateway's LSP.
+    Info,
+    /// Order a channel to a node, paid for with ecash. The ecash beyond the
+    /// fee is pushed to the node's side of the channel.
+    OrderChannel {
+        /// The federation that issued the ecash
+        #[clap(long)]
+        federation_id: FederationId,
+
+        /// The ecash that pays for the channel
+        #[clap(long)]
+        notes: String,
+
+        /// The public key of the node the channel is opened to
+        #[clap(long)]
+        pubkey: PublicKey,
+
+        /// The address of the node the channel is opened to
+        #[clap(long)]
+        host: String,
+
+        /// The capacity the LSP provides on its side of the channel
+        #[clap(long, default_value_t = 0)]
+        inbound_sats: u64,
+    },
+    /// Display the state of a channel order, including the ecash of a refund.
+    OrderStatus {
+        /// The id of the order, as returned when the channel was ordered
+        #[clap(long)]
+        order_id: sha256::Hash,
+    },
+}
+
+impl LspCommands {
+    pub async fn handle(self, client: &GatewayApi, base_url: &SafeUrl) -> CliOutputResult {
+        match self {
+            Self::Info => {
+                let info = lsp_info(client, base_url).await?;
+                Ok(CliOutput::LspInfo(info))
+            }
+            Self::OrderChannel {
+                federation_id,
+                notes,
+                pubkey,
+                host,
+                inbound_sats,
+            } => {
+                let response = order_channel(
+                    client,
+                    base_url,
+                    ChannelOrderPayload {
+                        federation_id,
+                        notes,
+                        pubkey,
+                        host,
+                        inbound_sats,
+                    },
+                )
+                .await?;
+                Ok(CliOutput::ChannelOrder(response))
+            }
+            Self::OrderStatus { order_id } => {
+                let response =
+                    channel_order_status(client, base_url, ChannelOrderStatusPayload { order_id })
+                        .await?;
+                Ok(CliOutput::ChannelOrder(response))
+            }
+        }
+    }
+}
@@ -3,8 +3,13 @@
 mod config_commands;
 mod ecash_commands;
 mod general_commands;
+mod ledger_commands;
 mod lightning_commands;
+mod liquidity_commands;
+mod lsp_commands;
 mod onchain_commands;
+mod risk_commands;
+mod token_commands;
 
 use std::collections::BTreeMap;
 
@@ -20,17 +25,25 @@
 use fedimint_core::invite_code::InviteCode;
 use fedimint_core::util::SafeUrl;
 use fedimint_gateway_common::{
-    ChannelInfo, CloseChannelsWithPeerResponse, CreateOfferResponse, FederationConfig,
-    FederationInfo, GatewayBalances, GatewayFedConfig, GatewayInfo, GetInvoiceResponse,
-    ListTransactionsResponse, MnemonicResponse, PayOfferResponse, PaymentLogResponse,
-    PaymentSummaryResponse, ReceiveEcashResponse, SpendEcashResponse, WithdrawResponse,
+    ApiTokenAuditLogResponse, ApiTokenInfo, ChannelInfo, ChannelOrderResponse,
+    CloseChannelsWithPeerResponse, CreateApiTokenResponse, CreateLnurlWithdrawResponse,
+    CreateOfferResponse, FederationConfig, FederationInfo, FeeHistoryResponse, FeeSchedule,
+    GatewayBalances, GatewayFedConfig, GatewayInfo, GetInvoiceResponse, LedgerResponse,
+    LiquidityLogResponse, LiquidityPolicy, ListTransactionsResponse, LspInfo, MnemonicResponse,
+    PayOfferResponse, PaymentLogResponse, PaymentSummaryResponse, ReceiveEcashResponse,
+    RevenueResponse, RiskStatusResponse, SpendEcashResponse, WithdrawResponse,
 };
 use fedimint_ln_common::client::GatewayApi;
 use fedimint_logging::TracingSetup;
 use general_commands::GeneralCommands;
+use ledger_commands::LedgerCommands;
 use lightning_commands::LightningCommands;
+use liquidity_commands::LiquidityCommands;
+use lsp_commands::LspCommands;
 use onchain_commands::OnchainCommands;
+use risk_commands::RiskCommands;
 use serde::Serialize;
+use token_commands::TokenCommands;
 
 /// Unified output type for all gateway-cli commands.
 ///
@@ -66,6 +79,7 @@ pub enum CliOutput {
     Transactions(ListTransactionsResponse),
     Offer(CreateOfferResponse),
     OfferPayment(PayOfferResponse),
+    LnurlWithdraw(CreateLnurlWithdrawResponse),
 
     // Ecash commands
     DepositAddress {
@@ -90,6 +104,28 @@ pub enum CliOutput {
     // Config commands
     Config(GatewayFedConfig),
     FederationConfigs(Vec<FederationConfig>),
+    FeeSchedule(Option<FeeSchedule>),
+    FeeHistory(FeeHistoryResponse),
+
+    // Liquidity commands
+    LiquidityPolicy(Option<LiquidityPolicy>),
+    LiquidityLog(LiquidityLogResponse),
+
+    // Risk commands
+    RiskStatus(RiskStatusResponse),
+
+    // LSP commands
+    LspInfo(LspInfo),
+    ChannelOrder(ChannelOrderResponse),
+
+    // Ledger commands
+    Ledger(LedgerResponse),
+    Revenue(RevenueResponse),
+
+    // Token commands
+    ApiToken(CreateApiTokenResponse),
+    ApiTokens(Vec<ApiTokenInfo>),
+    ApiTokenAuditLog(ApiTokenAuditLogResponse),
 
     // No output (for commands that succeed silently)
     #[serde(skip)]
@@ -242,6 +278,16 @@ enum Commands {
     Onchain(OnchainCommands),
     #[command(subcommand)]
     Cfg(ConfigCommands),
+    #[command(subcommand)]
+    Liquidity(LiquidityCommands),
+    #[command(subcommand)]
+    Ledger(LedgerCommands),
+    #[command(subcommand)]
+    Lsp(LspCommands),
+    #[command(subcommand)]
+    Risk(RiskCommands),
+    #[command(subcommand)]
+    Token(TokenCommands),
     Completion {
         shell: clap_complete::Shell,
     },
@@ -285,6 +331,13 @@ async fn run() -> CliOutputResult {
         Commands::Ecash(ecash_command) => ecash_command.handle(&client, &cli.address).await?,
         Commands::Onchain(onchain_command) => onchain_command.handle(&client, &cli.address).await?,
         Commands::Cfg(config_commands) => config_commands.handle(&client, &cli.address).await?,
+        Commands::Liquidity(liquidity_commands) => {
+            liquidity_commands.handle(&client, &cli.address).await?
+        }
+        Commands::Ledger(ledger_commands) => ledger_commands.handle(&client, &cli.address).await?,
+        Commands::Lsp(lsp_commands) => lsp_commands.handle(&client, &cli.address).await?,
+        Commands::Risk(risk_commands) => risk_commands.handle(&client, &cli.address).await?,
+        Commands::Token(token_commands) => token_commands.handle(&client, &cli.address).await?,
         Commands::Completion { shell } => {
             clap_complete::generate(
                 shell,
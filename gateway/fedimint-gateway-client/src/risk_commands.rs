@@ -0,0 +1,86 @@
+use clap::Subcommand;
+use fedimint_core::Amount;
+use fedimint_core::config::FederationId;
+use fedimint_core::util::SafeUrl;
+use fedimint_gateway_client::{risk_status, set_risk_limits};
+use fedimint_gateway_common::{RiskLimits, SetRiskLimitsPayload};
+use fedimint_ln_common::client::GatewayApi;
+
+use crate::{CliOutput, CliOutputResult};
+
+/// Risk management commands for limiting the gateway's exposure to the
+/// federations it serves.
+#[derive(Subcommand)]
+pub enum RiskCommands {
+    /// Display the risk limits of all federations and the gateway's current
+    /// exposure to them.
+    Status,
+    /// Set the risk limits of a federation, which also pauses it while fewer
+    /// guardians than its threshold are reachable.
+    SetLimits {
+        #[clap(long)]
+        federation_id: FederationId,
+
+        /// The number of outgoing payments that are paid at the same time
+        #[clap(long)]
+        max_outstanding_outgoing: Option<u64>,
+
+        /// The largest outgoing payment
+        #[clap(long)]
+        max_payment: Option<Amount>,
+
+        /// The sum of the outgoing payments within the last 24 hours
+        #[clap(long)]
+        max_daily_volume: Option<Amount>,
+    },
+    /// Remove the risk limits of a federation, which resumes it if it is
+    /// paused.
+    ClearLimits {
+        #[clap(long)]
+        federation_id: FederationId,
+    },
+}
+
+impl RiskCommands {
+    pub async fn handle(self, client: &GatewayApi, base_url: &SafeUrl) -> CliOutputResult {
+        match self {
+            Self::Status => {
+                let status = risk_status(client, base_url).await?;
+                Ok(CliOutput::RiskStatus(status))
+            }
+            Self::SetLimits {
+                federation_id,
+                max_outstanding_outgoing,
+                max_payment,
+                max_daily_volume,
+            } => {
+                set_risk_limits(
+                    client,
+                    base_url,
+                    SetRiskLimitsPayload {
+                        federation_id,
+                        limits: Some(RiskLimits {
+                            max_outstanding_outgoing,
+                            max_payment,
+                            max_daily_volume,
+                        }),
+                    },
+                )
+                .await?;
+                Ok(CliOutput::Empty)
+            }
+            Self::ClearLimits { federation_id } => {
+                set_risk_limits(
+                    client,
+                    base_url,
+                    SetRiskLimitsPayload {
+                        federation_id,
+                        limits: None,
+                    },
+                )
+                .await?;
+                Ok(CliOutput::Empty)
+            }
+        }
+    }
+}
@@ -0,0 +1,85 @@
+use bitcoin::hashes::sha256;
+use clap::Subcommand;
+use fedimint_core::util::SafeUrl;
+use fedimint_gateway_client::{
+    api_token_audit_log, create_api_token, list_api_tokens, revoke_api_token,
+};
+use fedimint_gateway_common::{
+    ApiTokenAuditLogPayload, ApiTokenRole, CreateApiTokenPayload, RevokeApiTokenPayload,
+};
+use fedimint_ln_common::client::GatewayApi;
+
+use crate::{CliOutput, CliOutputResult};
+
+/// API token commands for granting scoped access to the gateway without
+/// sharing its password. These commands require the gateway's password.
+#[derive(Subcommand)]
+pub enum TokenCommands {
+    /// Create an API token, which is only displayed once
+    Create {
+        /// A name that identifies the token's holder in the audit log
+        #[clap(long)]
+        name: String,
+
+        #[clap(long, value_enum)]
+        role: ApiTokenRole,
+
+        /// The lifetime of the token, it does not expire if omitted
+        #[clap(long)]
+        expires_in_secs: Option<u64>,
+    },
+    /// List all API tokens
+    List,
+    /// Revoke an API token
+    Revoke {
+        #[clap(long)]
+        id: sha256::Hash,
+    },
+    /// List the most recent creations, revocations and uses of API tokens
+    AuditLog {
+        /// Only list the events of this token
+        #[clap(long)]
+        id: Option<sha256::Hash>,
+
+        #[clap(long, default_value_t = 25)]
+        limit: usize,
+    },
+}
+
+impl TokenCommands {
+    pub async fn handle(self, client: &GatewayApi, base_url: &SafeUrl) -> CliOutputResult {
+        match self {
+            Self::Create {
+                name,
+                role,
+                expires_in_secs,
+            } => {
+                let response = create_api_token(
+                    client,
+                    base_url,
+                    CreateApiTokenPayload {
+                        name,
+                        role,
+                        expires_in_secs,
+                    },
+                )
+                .await?;
+                Ok(CliOutput::ApiToken(response))
+            }
+            Self::List => {
+                let tokens = list_api_tokens(client, base_url).await?;
+                Ok(CliOutput::ApiTokens(tokens))
+            }
+            Self::Revoke { id } => {
+                revoke_api_token(client, base_url, RevokeApiTokenPayload { id }).await?;
+                Ok(CliOutput::Empty)
+            }
+            Self::AuditLog { id, limit } => {
+                let log =
+                    api_token_audit_log(client, base_url, ApiTokenAuditLogPayload { id, limit })
+                        .await?;
+                Ok(CliOutput::ApiTokenAuditLog(log))
+            }
+        }
+    }
+}
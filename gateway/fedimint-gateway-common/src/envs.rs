@@ -10,6 +10,14 @@
 /// Necessary for LND configuration.
 pub const FM_LND_MACAROON_ENV: &str = "FM_LND_MACAROON";
 
+/// Environment variable that specifies the location of Core Lightning's
+/// JSON-RPC socket. Necessary for CLN configuration.
+pub const FM_CLN_RPC_PATH_ENV: &str = "FM_CLN_RPC_PATH";
+
+/// Environment variable that specifies the location of the JSON file describing
+/// the network simulated by the simulated lightning backend.
+pub const FM_SIMULATED_NETWORK_ENV: &str = "FM_SIMULATED_NETWORK";
+
 /// Environment variable the specifies the port that the LDK Node should use.
 /// Necessary for LDK configuration.
 pub const FM_PORT_LDK: &str = "FM_PORT_LDK";
@@ -17,6 +25,30 @@
 /// The alias for the LDK Node
 pub const FM_LDK_ALIAS_ENV: &str = "FM_LDK_ALIAS";
 
+/// Environment variable that enables the LSP service of the LDK Node, which
+/// sells JIT channels (LSPS2) and channels paid for with ecash
+pub const FM_LDK_LSP_ENV: &str = "FM_LDK_LSP";
+
+/// Environment variable that specifies the fee the LSP charges for a channel,
+/// in parts per million of the capacity it provides
+pub const FM_LDK_LSP_OPENING_FEE_PPM_ENV: &str = "FM_LDK_LSP_OPENING_FEE_PPM";
+
+/// Environment variable that specifies the minimum fee the LSP charges for a
+/// channel, in sats
+pub const FM_LDK_LSP_MIN_OPENING_FEE_SATS_ENV: &str = "FM_LDK_LSP_MIN_OPENING_FEE_SATS";
+
+/// Environment variable that specifies the smallest channel the LSP opens, in
+/// sats
+pub const FM_LDK_LSP_MIN_CHANNEL_SATS_ENV: &str = "FM_LDK_LSP_MIN_CHANNEL_SATS";
+
+/// Environment variable that specifies the largest channel the LSP opens, in
+/// sats
+pub const FM_LDK_LSP_MAX_CHANNEL_SATS_ENV: &str = "FM_LDK_LSP_MAX_CHANNEL_SATS";
+
+/// Environment variable that specifies for how many blocks the LSP keeps a
+/// channel it sold open
+pub const FM_LDK_LSP_MIN_CHANNEL_LIFETIME_ENV: &str = "FM_LDK_LSP_MIN_CHANNEL_LIFETIME";
+
 /// Environment variable for overriding the iroh secret key
 pub const FM_GATEWAY_IROH_SECRET_KEY_OVERRIDE_ENV: &str = "FM_GATEWAY_IROH_SECRET_KEY_OVERRIDE";
 
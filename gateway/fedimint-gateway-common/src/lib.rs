@@ -9,16 +9,22 @@
 use bitcoin::{Address, Network, OutPoint};
 use clap::Subcommand;
 use envs::{
-    FM_LDK_ALIAS_ENV, FM_LND_MACAROON_ENV, FM_LND_PAYMENT_TIMEOUT_SECS_ENV, FM_LND_RPC_ADDR_ENV,
-    FM_LND_TIME_PREF_ENV, FM_LND_TLS_CERT_ENV, FM_PORT_LDK,
+    FM_CLN_RPC_PATH_ENV, FM_LDK_ALIAS_ENV, FM_LDK_LSP_ENV, FM_LDK_LSP_MAX_CHANNEL_SATS_ENV,
+    FM_LDK_LSP_MIN_CHANNEL_LIFETIME_ENV, FM_LDK_LSP_MIN_CHANNEL_SATS_ENV,
+    FM_LDK_LSP_MIN_OPENING_FEE_SATS_ENV, FM_LDK_LSP_OPENING_FEE_PPM_ENV, FM_LND_MACAROON_ENV,
+    FM_LND_PAYMENT_TIMEOUT_SECS_ENV, FM_LND_RPC_ADDR_ENV, FM_LND_TIME_PREF_ENV,
+    FM_LND_TLS_CERT_ENV, FM_PORT_LDK, FM_SIMULATED_NETWORK_ENV,
 };
 use fedimint_core::config::{FederationId, JsonClientConfig};
+use fedimint_core::core::ModuleKind;
 use fedimint_core::encoding::{Decodable, Encodable};
 use fedimint_core::invite_code::InviteCode;
 use fedimint_core::util::{SafeUrl, get_average, get_median};
 use fedimint_core::{Amount, BitcoinAmountOrAll, secp256k1};
-use fedimint_eventlog::{EventKind, EventLogId, PersistedLogEntry, StructuredPaymentEvents};
-use fedimint_lnv2_common::gateway_api::PaymentFee;
+use fedimint_eventlog::{
+    Event, EventKind, EventLogId, EventPersistence, PersistedLogEntry, StructuredPaymentEvents,
+};
+use fedimint_lnv2_common::gateway_api::{PaymentFee, SendFeeTier};
 use fedimint_wallet_client::PegOutFees;
 use lightning::ln::msgs::SocketAddress;
 use lightning_invoice::Bolt11Invoice;
@@ -34,24 +40,37 @@
 
 pub const ADDRESS_ENDPOINT: &str = "/address";
 pub const ADDRESS_RECHECK_ENDPOINT: &str = "/address_recheck";
+pub const API_TOKEN_AUDIT_LOG_ENDPOINT: &str = "/api_token_audit_log";
+pub const API_TOKENS_ENDPOINT: &str = "/api_tokens";
 pub const BACKUP_ENDPOINT: &str = "/backup";
 pub const CONFIGURATION_ENDPOINT: &str = "/config";
 pub const CONNECT_FED_ENDPOINT: &str = "/connect_fed";
+pub const CREATE_API_TOKEN_ENDPOINT: &str = "/create_api_token";
 pub const CREATE_BOLT11_INVOICE_FOR_OPERATOR_ENDPOINT: &str = "/create_bolt11_invoice_for_operator";
 pub const CREATE_BOLT12_OFFER_FOR_OPERATOR_ENDPOINT: &str = "/create_bolt12_offer_for_operator";
+pub const CREATE_LNURL_WITHDRAW_ENDPOINT: &str = "/create_lnurl_withdraw";
+pub const EXPORT_LEDGER_ENDPOINT: &str = "/export_ledger";
 pub const FEDERATION_STATUS_ENDPOINT: &str = "/federation_status";
+pub const FEE_HISTORY_ENDPOINT: &str = "/fee_history";
+pub const FEE_SCHEDULE_ENDPOINT: &str = "/fee_schedule";
 pub const GATEWAY_INFO_ENDPOINT: &str = "/info";
 pub const INVITE_CODES_ENDPOINT: &str = "/invite_codes";
 pub const GET_BALANCES_ENDPOINT: &str = "/balances";
 pub const GET_INVOICE_ENDPOINT: &str = "/get_invoice";
 pub const GET_LN_ONCHAIN_ADDRESS_ENDPOINT: &str = "/get_ln_onchain_address";
+pub const GET_LIQUIDITY_POLICY_ENDPOINT: &str = "/liquidity_policy";
 pub const LEAVE_FED_ENDPOINT: &str = "/leave_fed";
+pub const LEDGER_ENDPOINT: &str = "/ledger";
+pub const LIQUIDITY_LOG_ENDPOINT: &str = "/liquidity_log";
 pub const LIST_CHANNELS_ENDPOINT: &str = "/list_channels";
 pub const LIST_TRANSACTIONS_ENDPOINT: &str = "/list_transactions";
+pub const LSP_INFO_ENDPOINT: &str = "/lsp_info";
 pub const MNEMONIC_ENDPOINT: &str = "/mnemonic";
 pub const CONNECT_PEER_ENDPOINT: &str = "/connect_peer";
 pub const OPEN_CHANNEL_ENDPOINT: &str = "/open_channel";
 pub const OPEN_CHANNEL_WITH_PUSH_ENDPOINT: &str = "/open_channel_with_push";
+pub const ORDER_CHANNEL_ENDPOINT: &str = "/order_channel";
+pub const CHANNEL_ORDER_STATUS_ENDPOINT: &str = "/channel_order_status";
 pub const CLOSE_CHANNELS_WITH_PEER_ENDPOINT: &str = "/close_channels_with_peer";
 pub const PAY_INVOICE_FOR_OPERATOR_ENDPOINT: &str = "/pay_invoice_for_operator";
 pub const PAY_OFFER_FOR_OPERATOR_ENDPOINT: &str = "/pay_offer_for_operator";
@@ -59,8 +78,14 @@
 pub const PAYMENT_SUMMARY_ENDPOINT: &str = "/payment_summary";
 pub const PEGIN_FROM_ONCHAIN_ENDPOINT: &str = "/pegin_from_onchain";
 pub const RECEIVE_ECASH_ENDPOINT: &str = "/receive_ecash";
+pub const REVENUE_ENDPOINT: &str = "/revenue";
+pub const REVOKE_API_TOKEN_ENDPOINT: &str = "/revoke_api_token";
+pub const RISK_STATUS_ENDPOINT: &str = "/risk_status";
 pub const SET_CHANNEL_FEES_ENDPOINT: &str = "/set_channel_fees";
 pub const SET_FEES_ENDPOINT: &str = "/set_fees";
+pub const SET_FEE_SCHEDULE_ENDPOINT: &str = "/set_fee_schedule";
+pub const SET_LIQUIDITY_POLICY_ENDPOINT: &str = "/set_liquidity_policy";
+pub const SET_RISK_LIMITS_ENDPOINT: &str = "/set_risk_limits";
 pub const STOP_ENDPOINT: &str = "/stop";
 pub const SEND_ONCHAIN_ENDPOINT: &str = "/send_onchain";
 pub const SPEND_ECASH_ENDPOINT: &str = "/spend_ecash";
@@ -198,6 +223,18 @@ pub struct GatewayInfo {
     pub lightning_info: LightningInfo,
     pub lightning_mode: LightningMode,
     pub registrations: BTreeMap<RegisteredProtocol, (SafeUrl, secp256k1::PublicKey)>,
+    /// Leadership of this instance if it runs as one of an active/standby pair.
+    #[serde(default)]
+    pub high_availability: Option<HighAvailabilityInfo>,
+}
+
+/// Leadership of a gateway instance running in high availability mode. Only
+/// the leader serves requests, so the reporting instance always is the leader.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct HighAvailabilityInfo {
+    pub instance_id: String,
+    /// Expiry of the leader lease, `None` once it was lost.
+    pub lease_expires_at_secs: Option<u64>,
 }
 
 #[derive(Debug, Serialize, Deserialize, PartialEq)]
@@ -214,6 +251,145 @@ pub struct SetFeesPayload {
     pub transaction_parts_per_million: Option<u64>,
 }
 
+/// A lightning fee that replaces the federation's lightning fee for outgoing
+/// payments of at least `min_amount`.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Encodable, Decodable, Serialize, Deserialize)]
+pub struct LightningFeeTier {
+    pub min_amount: Amount,
+    pub lightning_fee: PaymentFee,
+}
+
+/// Hours of the day in UTC, from `start_hour` up to but excluding `end_hour`,
+/// during which the gateway charges a surcharge. The hours wrap around
+/// midnight if `end_hour` is smaller than `start_hour`.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Encodable, Decodable, Serialize, Deserialize)]
+pub struct PeakHours {
+    pub start_hour: u8,
+    pub end_hour: u8,
+    pub surcharge_ppm: u64,
+}
+
+/// A surcharge the gateway charges once the utilization of a balance reaches
+/// `min_utilization_percent`.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Encodable, Decodable, Serialize, Deserialize)]
+pub struct UtilizationSurcharge {
+    pub min_utilization_percent: u8,
+    pub surcharge_ppm: u64,
+}
+
+/// A schedule that adjusts the LNv2 fees of a federation on top of its flat
+/// fees, which remain the fees of LNv1 payments. Surcharges are added to the
+/// parts per million of the fees, up to the limits clients accept.
+#[derive(Debug, Clone, Default, Eq, PartialEq, Encodable, Decodable, Serialize, Deserialize)]
+pub struct FeeSchedule {
+    /// Discounted lightning fees for larger outgoing payments.
+    pub lightning_fee_tiers: Vec<LightningFeeTier>,
+    /// Surcharges on both outgoing and incoming payments during busy hours.
+    pub peak_hours: Vec<PeakHours>,
+    /// Surcharges on outgoing payments by the share of the node's channel
+    /// capacity that is not available as outbound capacity.
+    pub outbound_utilization: Vec<UtilizationSurcharge>,
+    /// The ecash balance in the federation at which its utilization is zero.
+    pub ecash_capacity: Option<Amount>,
+    /// Surcharges on incoming payments by the share of `ecash_capacity` that
+    /// is no longer covered by the gateway's ecash balance.
+    pub ecash_utilization: Vec<UtilizationSurcharge>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SetFeeSchedulePayload {
+    pub federation_id: FederationId,
+    /// The new schedule, or `None` to only charge the federation's flat fees.
+    pub schedule: Option<FeeSchedule>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct FeeSchedulePayload {
+    pub federation_id: FederationId,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct FeeHistoryPayload {
+    pub federation_id: FederationId,
+    /// The number of most recent entries to return
+    pub limit: usize,
+}
+
+/// The LNv2 fees a federation's routing info published from `timestamp_secs`
+/// on, until the next entry.
+#[derive(Debug, Clone, Eq, PartialEq, Encodable, Decodable, Serialize, Deserialize)]
+pub struct FeeHistoryEntry {
+    pub timestamp_secs: u64,
+    pub send_fee_default: PaymentFee,
+    pub send_fee_tiers: Vec<SendFeeTier>,
+    pub receive_fee: PaymentFee,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct FeeHistoryResponse(pub Vec<FeeHistoryEntry>);
+
+/// Limits on the risk the gateway takes on for a federation. The gateway pays
+/// outgoing payments on the Lightning network before it claims their contracts
+/// in the federation, so these limits bound what a federation that stops
+/// processing transactions can cost the gateway. A federation with limits is
+/// also paused while fewer guardians than its threshold are reachable.
+#[derive(Debug, Clone, Default, Eq, PartialEq, Encodable, Decodable, Serialize, Deserialize)]
+pub struct RiskLimits {
+    /// The number of outgoing payments that are paid at the same time
+    pub max_outstanding_outgoing: Option<u64>,
+    /// The largest outgoing payment
+    pub max_payment: Option<Amount>,
+    /// The sum of the outgoing payments begun within the last 24 hours
+    pub max_daily_volume: Option<Amount>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SetRiskLimitsPayload {
+    pub federation_id: FederationId,
+    /// The new limits, or `None` to remove the limits and the automatic pause
+    pub limits: Option<RiskLimits>,
+}
+
+/// The limits of a federation together with the current exposure of the
+/// gateway to it.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct FederationRiskStatus {
+    pub federation_id: FederationId,
+    pub limits: RiskLimits,
+    /// The connectivity that caused the federation to be paused, or `None` if
+    /// it is routing payments
+    pub paused: Option<FederationConnectivity>,
+    pub outstanding_outgoing: u64,
+    pub daily_volume: Amount,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RiskStatusResponse(pub Vec<FederationRiskStatus>);
+
+/// The risk limit that caused the gateway to reject an outgoing payment.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RiskLimit {
+    /// The federation is paused
+    Paused,
+    MaxOutstandingOutgoing,
+    MaxPayment,
+    MaxDailyVolume,
+}
+
+impl fmt::Display for RiskLimit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RiskLimit::Paused => write!(f, "the federation is paused"),
+            RiskLimit::MaxOutstandingOutgoing => {
+                write!(f, "too many outgoing payments are outstanding")
+            }
+            RiskLimit::MaxPayment => write!(f, "the payment exceeds the maximum payment"),
+            RiskLimit::MaxDailyVolume => write!(f, "the payment exceeds the daily volume"),
+        }
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct CreateInvoiceForOperatorPayload {
     pub amount_msats: u64,
@@ -226,6 +402,25 @@ pub struct PayInvoiceForOperatorPayload {
     pub invoice: Bolt11Invoice,
 }
 
+/// Creates an LNURL-withdraw link (LUD-03) that can be redeemed `uses` times
+/// for up to `max_withdrawable` each.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CreateLnurlWithdrawPayload {
+    pub min_withdrawable: Amount,
+    pub max_withdrawable: Amount,
+    pub uses: u64,
+    pub description: String,
+    /// Fund the withdrawals from the gateway's ecash in this federation
+    /// instead of the lightning node's balance. Such a link can only be
+    /// redeemed into this federation via this gateway.
+    pub federation_id: Option<FederationId>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CreateLnurlWithdrawResponse {
+    pub lnurl: String,
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct SpendEcashPayload {
     /// Federation id of the e-cash to spend
@@ -265,6 +460,239 @@ pub struct FederationBalanceInfo {
     pub ecash_balance_msats: Amount,
 }
 
+/// A range the liquidity manager keeps a balance of the gateway within. Once
+/// the balance leaves the range it is moved back to the middle of the range.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Encodable, Decodable, Serialize, Deserialize)]
+pub struct LiquidityRange {
+    pub min: Amount,
+    pub max: Amount,
+}
+
+impl LiquidityRange {
+    /// The balance the liquidity manager rebalances towards.
+    pub fn target(&self) -> Amount {
+        Amount::from_msats(self.min.msats.midpoint(self.max.msats))
+    }
+}
+
+/// The policy the gateway's liquidity manager rebalances the gateway's funds
+/// by. Peg-ins and peg-outs between the federations and the lightning node's
+/// onchain wallet are executed automatically, while changes to the node's
+/// channels are only proposed to the operator.
+#[derive(Debug, Clone, Eq, PartialEq, Encodable, Decodable, Serialize, Deserialize)]
+pub struct LiquidityPolicy {
+    /// Only log the actions the liquidity manager would take instead of
+    /// executing them.
+    pub dry_run: bool,
+    /// The target range of the gateway's ecash balance in each federation.
+    pub federations: BTreeMap<FederationId, LiquidityRange>,
+    /// The target range of the outbound capacity of the node's channels.
+    pub outbound: Option<LiquidityRange>,
+    /// Propose opening a channel once the inbound capacity of the node's
+    /// channels falls below this amount.
+    pub min_inbound: Option<Amount>,
+    /// Onchain funds that are never pegged into a federation, e.g. to pay for
+    /// channel opens.
+    pub onchain_reserve_sats: u64,
+    /// The fee rate of the transactions pegging in onchain funds.
+    pub pegin_fee_rate_sats_per_vbyte: u64,
+    /// The minimum time between two actions for the same federation or for
+    /// the node's channels. Peg-ins only show up in the ecash balance once
+    /// confirmed, so this should span a couple of blocks.
+    pub cooldown_secs: u64,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Encodable, Decodable, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChannelOpenReason {
+    /// The outbound capacity of the node's channels is below the target range,
+    /// so the gateway cannot pay for outgoing payments.
+    LowOutbound,
+    /// The inbound capacity of the node's channels is below the minimum, so
+    /// the gateway cannot receive incoming payments.
+    LowInbound,
+}
+
+/// An action the liquidity manager took or would have taken to move the
+/// gateway's funds back into the ranges of its [`LiquidityPolicy`].
+#[derive(Debug, Clone, Eq, PartialEq, Encodable, Decodable, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LiquidityAction {
+    /// Peg-in funds from the node's onchain wallet into a federation.
+    PegIn {
+        federation_id: FederationId,
+        amount_sats: u64,
+    },
+    /// Peg-out ecash from a federation into the node's onchain wallet.
+    PegOut {
+        federation_id: FederationId,
+        amount_sats: u64,
+    },
+    /// Open a channel of the given size, or buy inbound capacity of that size
+    /// from an LSP.
+    ChannelOpenProposal {
+        reason: ChannelOpenReason,
+        amount_sats: u64,
+    },
+    /// Swap the excess outbound capacity of the node's channels to onchain
+    /// funds, e.g. with a submarine swap service.
+    SwapOutProposal { amount: Amount },
+}
+
+impl LiquidityAction {
+    /// The federation the action moves funds in or out of, or `None` if it
+    /// concerns the node's channels.
+    pub fn federation_id(&self) -> Option<FederationId> {
+        match self {
+            Self::PegIn { federation_id, .. } | Self::PegOut { federation_id, .. } => {
+                Some(*federation_id)
+            }
+            Self::ChannelOpenProposal { .. } | Self::SwapOutProposal { .. } => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Encodable, Decodable, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LiquidityOutcome {
+    /// The action was not executed since the policy is in dry-run mode.
+    DryRun,
+    /// The action was proposed to the operator.
+    Proposed,
+    /// The action was executed by the given onchain transaction.
+    Executed {
+        txid: bitcoin::Txid,
+    },
+    Failed {
+        error: String,
+    },
+}
+
+/// An entry of the liquidity manager's log.
+#[derive(Debug, Clone, Eq, PartialEq, Encodable, Decodable, Serialize, Deserialize)]
+pub struct LiquidityEvent {
+    pub timestamp_secs: u64,
+    pub action: LiquidityAction,
+    pub outcome: LiquidityOutcome,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SetLiquidityPolicyPayload {
+    /// The new policy, or `None` to disable the liquidity manager.
+    pub policy: Option<LiquidityPolicy>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct LiquidityLogPayload {
+    /// The number of most recent events to return
+    pub limit: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct LiquidityLogResponse(pub Vec<LiquidityEvent>);
+
+/// The scope of an API token, which determines the authenticated routes it may
+/// access. No API token may manage API tokens, this requires the gateway's
+/// password.
+#[derive(
+    Debug, Clone, Copy, Eq, PartialEq, Encodable, Decodable, Serialize, Deserialize, clap::ValueEnum,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum ApiTokenRole {
+    /// Balances, payments and configuration, without changing anything
+    ReadOnly,
+    /// Paying and creating invoices and offers for the operator
+    Payments,
+    /// Every authenticated route
+    Admin,
+}
+
+/// An API token, identified by the hash of its secret since the gateway does
+/// not store the secret itself.
+#[derive(Debug, Clone, Eq, PartialEq, Encodable, Decodable, Serialize, Deserialize)]
+pub struct ApiTokenInfo {
+    pub id: sha256::Hash,
+    pub name: String,
+    pub role: ApiTokenRole,
+    pub created_at_secs: u64,
+    pub expires_at_secs: Option<u64>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CreateApiTokenPayload {
+    pub name: String,
+    pub role: ApiTokenRole,
+    /// The lifetime of the token, or `None` if it does not expire
+    pub expires_in_secs: Option<u64>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CreateApiTokenResponse {
+    pub id: sha256::Hash,
+    /// The bearer token, which is only returned once
+    pub token: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RevokeApiTokenPayload {
+    pub id: sha256::Hash,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ApiTokenAuditLogPayload {
+    /// Only return the events of this token
+    pub id: Option<sha256::Hash>,
+    /// The number of most recent events to return
+    pub limit: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ApiTokenAuditLogResponse(pub Vec<PersistedLogEntry>);
+
+/// Event that is emitted when an API token is created.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ApiTokenCreated {
+    pub id: sha256::Hash,
+    pub name: String,
+    pub role: ApiTokenRole,
+    pub expires_at_secs: Option<u64>,
+}
+
+impl Event for ApiTokenCreated {
+    const MODULE: Option<ModuleKind> = None;
+    const KIND: EventKind = EventKind::from_static("api-token-created");
+    const PERSISTENCE: EventPersistence = EventPersistence::Persistent;
+}
+
+/// Event that is emitted when an API token is revoked.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ApiTokenRevoked {
+    pub id: sha256::Hash,
+    pub name: String,
+}
+
+impl Event for ApiTokenRevoked {
+    const MODULE: Option<ModuleKind> = None;
+    const KIND: EventKind = EventKind::from_static("api-token-revoked");
+    const PERSISTENCE: EventPersistence = EventPersistence::Persistent;
+}
+
+/// Event that is emitted when a request authenticated with an API token is
+/// denied, or for the first permitted request of a token within an hour.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ApiTokenUsed {
+    pub id: sha256::Hash,
+    pub name: String,
+    pub route: String,
+    pub authorized: bool,
+}
+
+impl Event for ApiTokenUsed {
+    const MODULE: Option<ModuleKind> = None;
+    const KIND: EventKind = EventKind::from_static("api-token-used");
+    const PERSISTENCE: EventPersistence = EventPersistence::Trimable;
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct MnemonicResponse {
     pub mnemonic: Vec<String>,
@@ -333,6 +761,233 @@ pub struct PaymentSummaryPayload {
     pub end_millis: u64,
 }
 
+/// Event that is emitted when the gateway's lightning node reports the fee it
+/// paid to the nodes along the route of an outgoing payment.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct LightningRoutingFeePaid {
+    pub payment_hash: sha256::Hash,
+    pub routing_fee: Amount,
+}
+
+impl Event for LightningRoutingFeePaid {
+    const MODULE: Option<ModuleKind> = None;
+    const KIND: EventKind = EventKind::from_static("lightning-routing-fee-paid");
+    const PERSISTENCE: EventPersistence = EventPersistence::Persistent;
+}
+
+/// Event that is emitted when the gateway pegged out ecash of a federation,
+/// since the wallet module does not record the amount of a peg-out.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct EcashPeggedOut {
+    pub federation_id: FederationId,
+    pub txid: bitcoin::Txid,
+    pub amount_sats: u64,
+    pub fee_sats: u64,
+}
+
+impl Event for EcashPeggedOut {
+    const MODULE: Option<ModuleKind> = None;
+    const KIND: EventKind = EventKind::from_static("ecash-pegged-out");
+    const PERSISTENCE: EventPersistence = EventPersistence::Persistent;
+}
+
+/// Event that is emitted when the liquidity manager acts on a balance of the
+/// gateway that left the range of its policy.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct LiquidityActionTaken {
+    pub action: LiquidityAction,
+    pub outcome: LiquidityOutcome,
+}
+
+impl Event for LiquidityActionTaken {
+    const MODULE: Option<ModuleKind> = None;
+    const KIND: EventKind = EventKind::from_static("liquidity-action-taken");
+    const PERSISTENCE: EventPersistence = EventPersistence::Persistent;
+}
+
+/// Event that is emitted when the gateway's LSP opens a channel that was paid
+/// for with ecash.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct LspChannelOrdered {
+    pub federation_id: FederationId,
+    pub counterparty: PublicKey,
+    pub funding_txid: bitcoin::Txid,
+    pub channel_size_sats: u64,
+    pub push_amount_sats: u64,
+    /// The ecash that paid for the channel, which covers the pushed amount and
+    /// the fee
+    pub ecash: Amount,
+}
+
+impl Event for LspChannelOrdered {
+    const MODULE: Option<ModuleKind> = None;
+    const KIND: EventKind = EventKind::from_static("lsp-channel-ordered");
+    const PERSISTENCE: EventPersistence = EventPersistence::Persistent;
+}
+
+/// Event that is emitted when the gateway pauses a federation with risk limits
+/// because fewer guardians than its threshold are reachable. The gateway stops
+/// announcing itself to the federation and rejects its outgoing payments.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct FederationPaused {
+    pub federation_id: FederationId,
+    pub connectivity: FederationConnectivity,
+}
+
+impl Event for FederationPaused {
+    const MODULE: Option<ModuleKind> = None;
+    const KIND: EventKind = EventKind::from_static("federation-paused");
+    const PERSISTENCE: EventPersistence = EventPersistence::Persistent;
+}
+
+/// Event that is emitted when a paused federation is reachable again, or its
+/// risk limits were removed.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct FederationResumed {
+    pub federation_id: FederationId,
+}
+
+impl Event for FederationResumed {
+    const MODULE: Option<ModuleKind> = None;
+    const KIND: EventKind = EventKind::from_static("federation-resumed");
+    const PERSISTENCE: EventPersistence = EventPersistence::Persistent;
+}
+
+/// Event that is emitted when the gateway rejects an outgoing payment because
+/// of a federation's risk limits. Since anyone can request a payment, at most
+/// one rejection per federation and minute is recorded.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct OutgoingPaymentRejected {
+    pub federation_id: FederationId,
+    pub amount: Amount,
+    pub limit: RiskLimit,
+    /// The rejections of the federation's payments since the previous event
+    /// that were not recorded.
+    #[serde(default)]
+    pub unrecorded_rejections: u64,
+}
+
+impl Event for OutgoingPaymentRejected {
+    const MODULE: Option<ModuleKind> = None;
+    const KIND: EventKind = EventKind::from_static("outgoing-payment-rejected");
+    const PERSISTENCE: EventPersistence = EventPersistence::Trimable;
+}
+
+/// An account of the gateway's ledger, which is kept separately for every
+/// federation. Postings to assets and expenses are positive, postings to
+/// income and equity are negative.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LedgerAccount {
+    /// The gateway's ecash in the federation.
+    Ecash,
+    /// The lightning funds the gateway sent and received on behalf of the
+    /// federation. Swaps between federations pass through this account.
+    Lightning,
+    /// The onchain funds pegged in and out of the federation.
+    Onchain,
+    /// Ecash the operator spent or reissued out of band.
+    Operator,
+    /// The fees charged to the federation's users.
+    FeeIncome,
+    /// The fees paid to the lightning network for outgoing payments.
+    RoutingCost,
+    /// The onchain fees paid for peg-outs.
+    OnchainFees,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct LedgerPosting {
+    pub account: LedgerAccount,
+    pub amount_msats: i64,
+}
+
+/// A balanced transaction of the ledger of one federation, its postings sum up
+/// to zero.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct LedgerEntry {
+    pub federation_id: FederationId,
+    pub timestamp_usecs: u64,
+    pub description: String,
+    pub postings: Vec<LedgerPosting>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct LedgerPayload {
+    pub start_millis: u64,
+    pub end_millis: u64,
+    /// Restricts the ledger to one federation instead of all connected ones
+    pub federation_id: Option<FederationId>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct LedgerResponse(pub Vec<LedgerEntry>);
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum LedgerFormat {
+    Csv,
+    Beancount,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ExportLedgerPayload {
+    pub start_millis: u64,
+    pub end_millis: u64,
+    pub federation_id: Option<FederationId>,
+    pub format: LedgerFormat,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ExportLedgerResponse {
+    pub contents: String,
+}
+
+/// The width of the buckets the revenue is grouped into.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum RevenueInterval {
+    Hour,
+    Day,
+    Week,
+}
+
+impl RevenueInterval {
+    pub fn duration(&self) -> Duration {
+        match self {
+            Self::Hour => Duration::from_hours(1),
+            Self::Day => Duration::from_hours(24),
+            Self::Week => Duration::from_hours(24 * 7),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RevenuePayload {
+    pub start_millis: u64,
+    pub end_millis: u64,
+    pub interval: RevenueInterval,
+    pub federation_id: Option<FederationId>,
+}
+
+/// The revenue of the gateway in the interval starting at `start_millis`.
+#[derive(Debug, Serialize, Deserialize, Clone, Eq, PartialEq)]
+pub struct RevenueBucket {
+    pub start_millis: u64,
+    pub fees_earned: Amount,
+    /// Fees the gateway lost, e.g. on an LSP channel that pushed more to the
+    /// buyer than the buyer paid for it
+    #[serde(default)]
+    pub fee_losses: Amount,
+    pub routing_cost: Amount,
+    /// The fees earned minus the fee losses and the routing cost, which is
+    /// negative if the losses and routing cost exceeded the fees
+    pub net_revenue_msats: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RevenueResponse(pub Vec<RevenueBucket>);
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct ChannelInfo {
     pub remote_pubkey: secp256k1::PublicKey,
@@ -374,6 +1029,9 @@ pub struct OpenChannelRequest {
     /// channel. If `None`, the backend's default policy is used.
     #[serde(default, deserialize_with = "empty_string_as_none")]
     pub parts_per_million: Option<u64>,
+    /// Keep the channel out of the public network graph.
+    #[serde(default)]
+    pub unannounced: bool,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -714,7 +1372,180 @@ pub enum LightningMode {
         /// LDK's Alias
         #[arg(long = "ldk-alias", env = FM_LDK_ALIAS_ENV)]
         alias: String,
+
+        #[command(flatten)]
+        #[serde(default)]
+        lsp: LspConfig,
+    },
+    #[clap(name = "cln")]
+    Cln {
+        /// Path to Core Lightning's JSON-RPC socket. `lightningd` must have
+        /// `fedimint-cln-plugin` loaded for the gateway to intercept HTLCs.
+        #[arg(long = "cln-rpc-path", env = FM_CLN_RPC_PATH_ENV)]
+        cln_rpc_path: String,
     },
+    #[clap(name = "simulated")]
+    Simulated {
+        /// Path to a JSON file describing the simulated network of virtual
+        /// nodes. A small default network is simulated if omitted.
+        #[arg(long = "simulated-network", env = FM_SIMULATED_NETWORK_ENV)]
+        simulated_network: Option<String>,
+    },
+}
+
+impl LightningMode {
+    /// Returns true if the lightning node intercepts HTLCs forwarded to the
+    /// gateway's virtual short channel ids, which LNv1 payments rely on.
+    pub fn supports_lnv1(&self) -> bool {
+        matches!(
+            self,
+            LightningMode::Lnd { .. } | LightningMode::Cln { .. } | LightningMode::Simulated { .. }
+        )
+    }
+}
+
+/// Configuration of the LSP service of the LDK Node. A single fee policy
+/// applies to JIT channels and to channels ordered with ecash.
+#[derive(Debug, Clone, clap::Args, Serialize, Deserialize, PartialEq, Eq)]
+pub struct LspConfig {
+    /// Sell JIT channels (LSPS2) and channels paid for with ecash
+    #[arg(long = "ldk-lsp", env = FM_LDK_LSP_ENV)]
+    pub enabled: bool,
+
+    /// Fee for a channel in parts per million of the capacity the LSP provides
+    #[arg(
+        long = "ldk-lsp-opening-fee-ppm",
+        env = FM_LDK_LSP_OPENING_FEE_PPM_ENV,
+        default_value_t = LspConfig::DEFAULT_OPENING_FEE_PPM,
+    )]
+    pub opening_fee_ppm: u32,
+
+    /// Minimum fee for a channel in sats, which covers its on-chain cost
+    #[arg(
+        long = "ldk-lsp-min-opening-fee-sats",
+        env = FM_LDK_LSP_MIN_OPENING_FEE_SATS_ENV,
+        default_value_t = LspConfig::DEFAULT_MIN_OPENING_FEE_SATS,
+    )]
+    pub min_opening_fee_sats: u64,
+
+    /// Smallest channel the LSP opens in sats
+    #[arg(
+        long = "ldk-lsp-min-channel-sats",
+        env = FM_LDK_LSP_MIN_CHANNEL_SATS_ENV,
+        default_value_t = LspConfig::DEFAULT_MIN_CHANNEL_SATS,
+    )]
+    pub min_channel_sats: u64,
+
+    /// Largest channel the LSP opens in sats
+    #[arg(
+        long = "ldk-lsp-max-channel-sats",
+        env = FM_LDK_LSP_MAX_CHANNEL_SATS_ENV,
+        default_value_t = LspConfig::DEFAULT_MAX_CHANNEL_SATS,
+    )]
+    pub max_channel_sats: u64,
+
+    /// Number of blocks the LSP keeps a channel it sold open
+    #[arg(
+        long = "ldk-lsp-min-channel-lifetime",
+        env = FM_LDK_LSP_MIN_CHANNEL_LIFETIME_ENV,
+        default_value_t = LspConfig::DEFAULT_MIN_CHANNEL_LIFETIME,
+    )]
+    pub min_channel_lifetime_blocks: u32,
+}
+
+impl LspConfig {
+    pub const DEFAULT_OPENING_FEE_PPM: u32 = 10_000;
+    pub const DEFAULT_MIN_OPENING_FEE_SATS: u64 = 2_000;
+    pub const DEFAULT_MIN_CHANNEL_SATS: u64 = 20_000;
+    pub const DEFAULT_MAX_CHANNEL_SATS: u64 = 10_000_000;
+    /// Roughly a month of blocks
+    pub const DEFAULT_MIN_CHANNEL_LIFETIME: u32 = 4_320;
+
+    /// Returns the fee for a channel in which the LSP provides
+    /// `lsp_capacity_sats`.
+    pub fn opening_fee_sats(&self, lsp_capacity_sats: u64) -> u64 {
+        let proportional =
+            u128::from(lsp_capacity_sats) * u128::from(self.opening_fee_ppm) / 1_000_000;
+
+        u64::try_from(proportional)
+            .unwrap_or(u64::MAX)
+            .max(self.min_opening_fee_sats)
+    }
+}
+
+impl Default for LspConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            opening_fee_ppm: Self::DEFAULT_OPENING_FEE_PPM,
+            min_opening_fee_sats: Self::DEFAULT_MIN_OPENING_FEE_SATS,
+            min_channel_sats: Self::DEFAULT_MIN_CHANNEL_SATS,
+            max_channel_sats: Self::DEFAULT_MAX_CHANNEL_SATS,
+            min_channel_lifetime_blocks: Self::DEFAULT_MIN_CHANNEL_LIFETIME,
+        }
+    }
+}
+
+/// The services and prices of the gateway's LSP, which external wallets use
+/// to get JIT channels over LSPS2 from `node_pubkey` or to order channels with
+/// ecash through the gateway's API. Ordering a channel through the API takes
+/// the place of LSPS1: the LDK node only implements the service side of LSPS2,
+/// so channel requests over LSPS1 are not supported.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct LspInfo {
+    pub node_pubkey: PublicKey,
+    pub opening_fee_ppm: u32,
+    pub min_opening_fee_sats: u64,
+    pub min_channel_sats: u64,
+    pub max_channel_sats: u64,
+    pub min_channel_lifetime_blocks: u32,
+    /// The federations whose ecash pays for ordered channels
+    pub federations: Vec<FederationId>,
+}
+
+/// Orders a channel from the gateway's LSP to the node `pubkey` at `host`,
+/// paid for with `notes`. The LSP provides `inbound_sats` of capacity on its
+/// side and pushes the value of the notes minus the fee to the other side, so
+/// the ecash moves into the channel.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ChannelOrderPayload {
+    pub federation_id: FederationId,
+    /// Can be OOBNotes (v1) or ECash (v2)
+    pub notes: String,
+    pub pubkey: PublicKey,
+    pub host: String,
+    pub inbound_sats: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ChannelOrderOutcome {
+    /// The channel is being opened in `funding_txid`
+    Opened { funding_txid: bitcoin::Txid },
+    /// The channel could not be opened, the ecash is returned in `notes`
+    Refunded {
+        notes: String,
+        failure_reason: String,
+    },
+    /// The order is still being processed, its outcome is returned by the
+    /// channel order status endpoint
+    Pending,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct ChannelOrderResponse {
+    /// The hash of the ecash that paid for the order, by which its status can
+    /// be queried
+    pub order_id: sha256::Hash,
+    pub channel_size_sats: u64,
+    pub push_amount_sats: u64,
+    pub fee_sats: u64,
+    pub outcome: ChannelOrderOutcome,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ChannelOrderStatusPayload {
+    pub order_id: sha256::Hash,
 }
 
 #[derive(Clone)]
@@ -1,4 +1,4 @@
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 use std::str::FromStr;
 use std::time::SystemTime;
 
@@ -19,8 +19,12 @@
 use fedimint_core::time::duration_since_epoch;
 use fedimint_core::{Amount, impl_db_lookup, impl_db_record, push_db_pair_items, secp256k1};
 use fedimint_gateway_common::envs::FM_GATEWAY_IROH_SECRET_KEY_OVERRIDE_ENV;
-use fedimint_gateway_common::{ConnectorType, FederationConfig, RegisteredProtocol};
+use fedimint_gateway_common::{
+    ApiTokenInfo, ConnectorType, FederationConfig, FeeHistoryEntry, FeeSchedule, LiquidityPolicy,
+    RegisteredProtocol, RiskLimits,
+};
 use fedimint_ln_common::serde_routing_fees;
+use fedimint_lnv2_common::ContractId;
 use fedimint_lnv2_common::contracts::{IncomingContract, PaymentImage};
 use fedimint_lnv2_common::gateway_api::{MAX_INVOICE_EXPIRY_SECS, PaymentFee};
 use futures::{FutureExt, StreamExt};
@@ -44,6 +48,12 @@ fn get_client_database(&self, federation_id: &FederationId) -> Database {
     }
 }
 
+/// Returns whether the raw `key` belongs to the database of a federation
+/// client.
+pub fn is_client_database_key(key: &[u8]) -> bool {
+    key.first() == Some(&(DbKeyPrefix::ClientDatabase as u8))
+}
+
 #[allow(async_fn_in_trait)]
 pub trait GatewayDbtxNcExt {
     async fn save_federation_config(&mut self, config: &FederationConfig);
@@ -108,6 +118,186 @@ async fn claim_outgoing_payment_image(
         operation_id: OperationId,
     ) -> OperationId;
 
+    /// Saves an invoice the gateway fetched for a BOLT12 offer, such that it
+    /// can be paid once a client has funded an outgoing contract for it.
+    async fn save_fetched_offer_invoice(
+        &mut self,
+        payment_hash: sha256::Hash,
+        invoice: FetchedOfferInvoice,
+    );
+
+    async fn load_fetched_offer_invoice(
+        &mut self,
+        payment_hash: sha256::Hash,
+    ) -> Option<FetchedOfferInvoice>;
+
+    /// Deletes all fetched offer invoices that expired before
+    /// `expired_before_secs`, returning the number of deleted records.
+    async fn prune_fetched_offer_invoices(&mut self, expired_before_secs: u64) -> usize;
+
+    /// Saves a BOLT12 offer the gateway created on behalf of a recipient,
+    /// keyed by the offer id.
+    async fn save_registered_offer(&mut self, offer_id: String, offer: RegisteredOffer);
+
+    async fn load_registered_offer(&mut self, offer_id: String) -> Option<RegisteredOffer>;
+
+    /// Saves the incoming contract funded for a payment to a registered offer,
+    /// such that crediting the payment is idempotent.
+    async fn save_offer_payment_contract(
+        &mut self,
+        payment_hash: sha256::Hash,
+        contract: OfferPaymentContract,
+    );
+
+    async fn load_offer_payment_contract(
+        &mut self,
+        payment_hash: sha256::Hash,
+    ) -> Option<OfferPaymentContract>;
+
+    /// Returns the unix time in seconds up to which offer payments have been
+    /// credited.
+    async fn load_offer_payment_cursor(&mut self) -> Option<u64>;
+
+    async fn save_offer_payment_cursor(&mut self, cursor_secs: u64);
+
+    /// Saves the state of a hold invoice, keyed by the id of its incoming
+    /// contract.
+    async fn save_hold_invoice(&mut self, contract_id: ContractId, hold_invoice: HoldInvoice);
+
+    async fn load_hold_invoice(&mut self, contract_id: ContractId) -> Option<HoldInvoice>;
+
+    async fn delete_hold_invoice(&mut self, contract_id: ContractId);
+
+    /// Returns all hold invoices whose payment is currently held by the
+    /// gateway.
+    async fn load_held_invoices(&mut self) -> Vec<(ContractId, HoldInvoice)>;
+
+    /// Deletes all hold invoices that do not hold a payment and whose contract
+    /// expired before `expired_before_secs`, returning the number of deleted
+    /// records.
+    async fn prune_hold_invoices(&mut self, expired_before_secs: u64) -> usize;
+
+    async fn save_lnurl_withdraw_link(&mut self, link_id: [u8; 32], link: LnurlWithdrawLink);
+
+    async fn load_lnurl_withdraw_link(&mut self, link_id: [u8; 32]) -> Option<LnurlWithdrawLink>;
+
+    async fn save_lnurl_withdrawal(&mut self, withdrawal: LnurlWithdrawal);
+
+    async fn load_lnurl_withdrawal(
+        &mut self,
+        payment_hash: sha256::Hash,
+    ) -> Option<LnurlWithdrawal>;
+
+    /// Returns all withdrawals whose invoice has not been paid yet.
+    async fn load_lnurl_withdrawals(&mut self) -> Vec<LnurlWithdrawal>;
+
+    async fn remove_lnurl_withdrawal(&mut self, payment_hash: sha256::Hash);
+
+    async fn save_lsp_channel_order(&mut self, order_id: sha256::Hash, order: &LspChannelOrder);
+
+    async fn load_lsp_channel_order(&mut self, order_id: sha256::Hash) -> Option<LspChannelOrder>;
+
+    async fn remove_lsp_channel_order(&mut self, order_id: sha256::Hash);
+
+    /// Returns the channel orders that are not opened or refunded yet.
+    async fn load_pending_lsp_channel_orders(&mut self) -> Vec<(sha256::Hash, LspChannelOrder)>;
+
+    /// Marks the client of a federation to be recovered from the mnemonic
+    /// before it is opened.
+    async fn save_pending_client_recovery(&mut self, federation_id: FederationId);
+
+    async fn load_pending_client_recoveries(&mut self) -> BTreeSet<FederationId>;
+
+    async fn remove_pending_client_recovery(&mut self, federation_id: FederationId);
+
+    /// Saves the address of a peer the lightning node had channels with
+    /// before it was restored.
+    async fn save_restored_channel_peer(&mut self, pubkey: secp256k1::PublicKey, address: &str);
+
+    async fn load_restored_channel_peers(&mut self) -> BTreeMap<secp256k1::PublicKey, String>;
+
+    async fn remove_restored_channel_peer(&mut self, pubkey: secp256k1::PublicKey);
+
+    /// Saves an incoming contract a recipient created for a payment to its
+    /// static LNURL.
+    async fn save_static_lnurl_contract(
+        &mut self,
+        recipient_pk: secp256k1::PublicKey,
+        contract: StaticLnurlContract,
+    );
+
+    /// Returns the next unused static LNURL contract of a recipient.
+    async fn load_next_static_lnurl_contract(
+        &mut self,
+        recipient_pk: secp256k1::PublicKey,
+    ) -> Option<StaticLnurlContract>;
+
+    /// Removes a static LNURL contract, returning whether it was still
+    /// unused.
+    async fn remove_static_lnurl_contract(
+        &mut self,
+        recipient_pk: secp256k1::PublicKey,
+        contract_id: ContractId,
+    ) -> bool;
+
+    async fn count_static_lnurl_contracts(&mut self, recipient_pk: secp256k1::PublicKey) -> usize;
+
+    async fn load_liquidity_policy(&mut self) -> Option<LiquidityPolicy>;
+
+    /// Saves the policy of the liquidity manager, or disables it if `policy`
+    /// is `None`.
+    async fn save_liquidity_policy(&mut self, policy: Option<LiquidityPolicy>);
+
+    async fn load_fee_schedule(&mut self, federation_id: FederationId) -> Option<FeeSchedule>;
+
+    async fn load_fee_schedules(&mut self) -> BTreeMap<FederationId, FeeSchedule>;
+
+    /// Saves the fee schedule of a federation, or removes it if `schedule` is
+    /// `None`.
+    async fn save_fee_schedule(
+        &mut self,
+        federation_id: FederationId,
+        schedule: Option<FeeSchedule>,
+    );
+
+    async fn load_fee_surcharges(&mut self, federation_id: FederationId) -> Option<FeeSurcharges>;
+
+    async fn save_fee_surcharges(&mut self, federation_id: FederationId, surcharges: FeeSurcharges);
+
+    /// Appends an entry to the fee history of a federation and deletes its
+    /// entries from before `expired_before_secs`.
+    async fn save_fee_history_entry(
+        &mut self,
+        federation_id: FederationId,
+        entry: FeeHistoryEntry,
+        expired_before_secs: u64,
+    );
+
+    /// Returns up to `limit` of the most recent fee history entries of a
+    /// federation, newest first.
+    async fn load_fee_history(
+        &mut self,
+        federation_id: FederationId,
+        limit: usize,
+    ) -> Vec<FeeHistoryEntry>;
+
+    async fn load_risk_limits(&mut self, federation_id: FederationId) -> Option<RiskLimits>;
+
+    async fn load_all_risk_limits(&mut self) -> BTreeMap<FederationId, RiskLimits>;
+
+    /// Saves the risk limits of a federation, or removes them if `limits` is
+    /// `None`.
+    async fn save_risk_limits(&mut self, federation_id: FederationId, limits: Option<RiskLimits>);
+
+    async fn save_api_token(&mut self, token: ApiTokenInfo);
+
+    async fn load_api_token(&mut self, id: sha256::Hash) -> Option<ApiTokenInfo>;
+
+    async fn load_api_tokens(&mut self) -> Vec<ApiTokenInfo>;
+
+    /// Removes an API token and returns it, if it existed.
+    async fn remove_api_token(&mut self, id: sha256::Hash) -> Option<ApiTokenInfo>;
+
     /// Reads and serializes structures from the gateway's database for the
     /// purpose for serializing to JSON for inspection.
     async fn dump_database(
@@ -284,6 +474,412 @@ async fn claim_outgoing_payment_image(
         }
     }
 
+    async fn save_fetched_offer_invoice(
+        &mut self,
+        payment_hash: sha256::Hash,
+        invoice: FetchedOfferInvoice,
+    ) {
+        self.insert_entry(&FetchedOfferInvoiceKey(payment_hash), &invoice)
+            .await;
+    }
+
+    async fn load_fetched_offer_invoice(
+        &mut self,
+        payment_hash: sha256::Hash,
+    ) -> Option<FetchedOfferInvoice> {
+        self.get_value(&FetchedOfferInvoiceKey(payment_hash)).await
+    }
+
+    async fn prune_fetched_offer_invoices(&mut self, expired_before_secs: u64) -> usize {
+        let expired_payment_hashes = self
+            .find_by_prefix(&FetchedOfferInvoiceKeyPrefix)
+            .await
+            .filter_map(
+                |(key, invoice): (FetchedOfferInvoiceKey, FetchedOfferInvoice)| async move {
+                    (invoice.expires_at_secs < expired_before_secs).then_some(key.0)
+                },
+            )
+            .collect::<Vec<_>>()
+            .await;
+
+        let num_expired = expired_payment_hashes.len();
+
+        for payment_hash in expired_payment_hashes {
+            self.remove_entry(&FetchedOfferInvoiceKey(payment_hash))
+                .await;
+        }
+
+        num_expired
+    }
+
+    async fn save_registered_offer(&mut self, offer_id: String, offer: RegisteredOffer) {
+        self.insert_entry(&RegisteredOfferKey(offer_id), &offer)
+            .await;
+    }
+
+    async fn load_registered_offer(&mut self, offer_id: String) -> Option<RegisteredOffer> {
+        self.get_value(&RegisteredOfferKey(offer_id)).await
+    }
+
+    async fn save_offer_payment_contract(
+        &mut self,
+        payment_hash: sha256::Hash,
+        contract: OfferPaymentContract,
+    ) {
+        self.insert_entry(&OfferPaymentContractKey(payment_hash), &contract)
+            .await;
+    }
+
+    async fn load_offer_payment_contract(
+        &mut self,
+        payment_hash: sha256::Hash,
+    ) -> Option<OfferPaymentContract> {
+        self.get_value(&OfferPaymentContractKey(payment_hash)).await
+    }
+
+    async fn load_offer_payment_cursor(&mut self) -> Option<u64> {
+        self.get_value(&OfferPaymentCursorKey).await
+    }
+
+    async fn save_offer_payment_cursor(&mut self, cursor_secs: u64) {
+        self.insert_entry(&OfferPaymentCursorKey, &cursor_secs)
+            .await;
+    }
+
+    async fn save_hold_invoice(&mut self, contract_id: ContractId, hold_invoice: HoldInvoice) {
+        self.insert_entry(&HoldInvoiceKey(contract_id), &hold_invoice)
+            .await;
+    }
+
+    async fn load_hold_invoice(&mut self, contract_id: ContractId) -> Option<HoldInvoice> {
+        self.get_value(&HoldInvoiceKey(contract_id)).await
+    }
+
+    async fn delete_hold_invoice(&mut self, contract_id: ContractId) {
+        self.remove_entry(&HoldInvoiceKey(contract_id)).await;
+    }
+
+    async fn load_held_invoices(&mut self) -> Vec<(ContractId, HoldInvoice)> {
+        self.find_by_prefix(&HoldInvoiceKeyPrefix)
+            .await
+            .filter_map(
+                |(key, hold_invoice): (HoldInvoiceKey, HoldInvoice)| async move {
+                    matches!(hold_invoice.state, HoldInvoiceState::Held(..))
+                        .then_some((key.0, hold_invoice))
+                },
+            )
+            .collect::<Vec<_>>()
+            .await
+    }
+
+    async fn prune_hold_invoices(&mut self, expired_before_secs: u64) -> usize {
+        let expired_contract_ids = self
+            .find_by_prefix(&HoldInvoiceKeyPrefix)
+            .await
+            .filter_map(
+                |(key, hold_invoice): (HoldInvoiceKey, HoldInvoice)| async move {
+                    (!matches!(hold_invoice.state, HoldInvoiceState::Held(..))
+                        && hold_invoice.contract.commitment.expiration_or_fee < expired_before_secs)
+                        .then_some(key.0)
+                },
+            )
+            .collect::<Vec<_>>()
+            .await;
+
+        let num_expired = expired_contract_ids.len();
+
+        for contract_id in expired_contract_ids {
+            self.remove_entry(&HoldInvoiceKey(contract_id)).await;
+        }
+
+        num_expired
+    }
+
+    async fn save_lnurl_withdraw_link(&mut self, link_id: [u8; 32], link: LnurlWithdrawLink) {
+        self.insert_entry(&LnurlWithdrawLinkKey(link_id), &link)
+            .await;
+    }
+
+    async fn load_lnurl_withdraw_link(&mut self, link_id: [u8; 32]) -> Option<LnurlWithdrawLink> {
+        self.get_value(&LnurlWithdrawLinkKey(link_id)).await
+    }
+
+    async fn save_lnurl_withdrawal(&mut self, withdrawal: LnurlWithdrawal) {
+        self.insert_entry(
+            &LnurlWithdrawalKey(*withdrawal.invoice.payment_hash()),
+            &withdrawal,
+        )
+        .await;
+    }
+
+    async fn load_lnurl_withdrawal(
+        &mut self,
+        payment_hash: sha256::Hash,
+    ) -> Option<LnurlWithdrawal> {
+        self.get_value(&LnurlWithdrawalKey(payment_hash)).await
+    }
+
+    async fn load_lnurl_withdrawals(&mut self) -> Vec<LnurlWithdrawal> {
+        self.find_by_prefix(&LnurlWithdrawalKeyPrefix)
+            .await
+            .map(|(_, withdrawal)| withdrawal)
+            .collect::<Vec<_>>()
+            .await
+    }
+
+    async fn remove_lnurl_withdrawal(&mut self, payment_hash: sha256::Hash) {
+        self.remove_entry(&LnurlWithdrawalKey(payment_hash)).await;
+    }
+
+    async fn save_lsp_channel_order(&mut self, order_id: sha256::Hash, order: &LspChannelOrder) {
+        self.insert_entry(&LspChannelOrderKey(order_id), order)
+            .await;
+    }
+
+    async fn load_lsp_channel_order(&mut self, order_id: sha256::Hash) -> Option<LspChannelOrder> {
+        self.get_value(&LspChannelOrderKey(order_id)).await
+    }
+
+    async fn remove_lsp_channel_order(&mut self, order_id: sha256::Hash) {
+        self.remove_entry(&LspChannelOrderKey(order_id)).await;
+    }
+
+    async fn load_pending_lsp_channel_orders(&mut self) -> Vec<(sha256::Hash, LspChannelOrder)> {
+        self.find_by_prefix(&LspChannelOrderKeyPrefix)
+            .await
+            .filter_map(
+                |(key, order)| async move { order.state.is_pending().then_some((key.0, order)) },
+            )
+            .collect::<Vec<_>>()
+            .await
+    }
+
+    async fn save_pending_client_recovery(&mut self, federation_id: FederationId) {
+        self.insert_entry(&PendingClientRecoveryKey(federation_id), &())
+            .await;
+    }
+
+    async fn load_pending_client_recoveries(&mut self) -> BTreeSet<FederationId> {
+        self.find_by_prefix(&PendingClientRecoveryKeyPrefix)
+            .await
+            .map(|(key, ())| key.0)
+            .collect::<BTreeSet<_>>()
+            .await
+    }
+
+    async fn remove_pending_client_recovery(&mut self, federation_id: FederationId) {
+        self.remove_entry(&PendingClientRecoveryKey(federation_id))
+            .await;
+    }
+
+    async fn save_restored_channel_peer(&mut self, pubkey: secp256k1::PublicKey, address: &str) {
+        self.insert_entry(&RestoredChannelPeerKey(pubkey), &address.to_string())
+            .await;
+    }
+
+    async fn load_restored_channel_peers(&mut self) -> BTreeMap<secp256k1::PublicKey, String> {
+        self.find_by_prefix(&RestoredChannelPeerKeyPrefix)
+            .await
+            .map(|(key, address)| (key.0, address))
+            .collect::<BTreeMap<_, _>>()
+            .await
+    }
+
+    async fn remove_restored_channel_peer(&mut self, pubkey: secp256k1::PublicKey) {
+        self.remove_entry(&RestoredChannelPeerKey(pubkey)).await;
+    }
+
+    async fn save_static_lnurl_contract(
+        &mut self,
+        recipient_pk: secp256k1::PublicKey,
+        contract: StaticLnurlContract,
+    ) {
+        self.insert_entry(
+            &StaticLnurlContractKey {
+                recipient_pk,
+                contract_id: contract.contract.contract_id(),
+            },
+            &contract,
+        )
+        .await;
+    }
+
+    async fn load_next_static_lnurl_contract(
+        &mut self,
+        recipient_pk: secp256k1::PublicKey,
+    ) -> Option<StaticLnurlContract> {
+        self.find_by_prefix(&StaticLnurlContractRecipientPrefix(recipient_pk))
+            .await
+            .map(|(_, contract)| contract)
+            .next()
+            .await
+    }
+
+    async fn remove_static_lnurl_contract(
+        &mut self,
+        recipient_pk: secp256k1::PublicKey,
+        contract_id: ContractId,
+    ) -> bool {
+        self.remove_entry(&StaticLnurlContractKey {
+            recipient_pk,
+            contract_id,
+        })
+        .await
+        .is_some()
+    }
+
+    async fn count_static_lnurl_contracts(&mut self, recipient_pk: secp256k1::PublicKey) -> usize {
+        self.find_by_prefix(&StaticLnurlContractRecipientPrefix(recipient_pk))
+            .await
+            .count()
+            .await
+    }
+
+    async fn load_liquidity_policy(&mut self) -> Option<LiquidityPolicy> {
+        self.get_value(&LiquidityPolicyKey).await
+    }
+
+    async fn save_liquidity_policy(&mut self, policy: Option<LiquidityPolicy>) {
+        match policy {
+            Some(policy) => {
+                self.insert_entry(&LiquidityPolicyKey, &policy).await;
+            }
+            None => {
+                self.remove_entry(&LiquidityPolicyKey).await;
+            }
+        }
+    }
+
+    async fn load_fee_schedule(&mut self, federation_id: FederationId) -> Option<FeeSchedule> {
+        self.get_value(&FeeScheduleKey(federation_id)).await
+    }
+
+    async fn load_fee_schedules(&mut self) -> BTreeMap<FederationId, FeeSchedule> {
+        self.find_by_prefix(&FeeScheduleKeyPrefix)
+            .await
+            .map(|(key, schedule)| (key.0, schedule))
+            .collect::<BTreeMap<_, _>>()
+            .await
+    }
+
+    async fn save_fee_schedule(
+        &mut self,
+        federation_id: FederationId,
+        schedule: Option<FeeSchedule>,
+    ) {
+        match schedule {
+            Some(schedule) => {
+                self.insert_entry(&FeeScheduleKey(federation_id), &schedule)
+                    .await;
+            }
+            None => {
+                self.remove_entry(&FeeScheduleKey(federation_id)).await;
+                self.remove_entry(&FeeSurchargesKey(federation_id)).await;
+            }
+        }
+    }
+
+    async fn load_fee_surcharges(&mut self, federation_id: FederationId) -> Option<FeeSurcharges> {
+        self.get_value(&FeeSurchargesKey(federation_id)).await
+    }
+
+    async fn save_fee_surcharges(
+        &mut self,
+        federation_id: FederationId,
+        surcharges: FeeSurcharges,
+    ) {
+        self.insert_entry(&FeeSurchargesKey(federation_id), &surcharges)
+            .await;
+    }
+
+    async fn save_fee_history_entry(
+        &mut self,
+        federation_id: FederationId,
+        entry: FeeHistoryEntry,
+        expired_before_secs: u64,
+    ) {
+        let expired_keys = self
+            .find_by_prefix(&FeeHistoryFederationPrefix(federation_id))
+            .await
+            .map(|(key, _)| key)
+            .take_while(|key| {
+                let expired = key.timestamp_secs < expired_before_secs;
+                async move { expired }
+            })
+            .collect::<Vec<_>>()
+            .await;
+
+        for key in expired_keys {
+            self.remove_entry(&key).await;
+        }
+
+        self.insert_entry(
+            &FeeHistoryKey {
+                federation_id,
+                timestamp_secs: entry.timestamp_secs,
+            },
+            &entry,
+        )
+        .await;
+    }
+
+    async fn load_fee_history(
+        &mut self,
+        federation_id: FederationId,
+        limit: usize,
+    ) -> Vec<FeeHistoryEntry> {
+        self.find_by_prefix_sorted_descending(&FeeHistoryFederationPrefix(federation_id))
+            .await
+            .take(limit)
+            .map(|(_, entry)| entry)
+            .collect::<Vec<_>>()
+            .await
+    }
+
+    async fn load_risk_limits(&mut self, federation_id: FederationId) -> Option<RiskLimits> {
+        self.get_value(&RiskLimitsKey(federation_id)).await
+    }
+
+    async fn load_all_risk_limits(&mut self) -> BTreeMap<FederationId, RiskLimits> {
+        self.find_by_prefix(&RiskLimitsKeyPrefix)
+            .await
+            .map(|(key, limits)| (key.0, limits))
+            .collect::<BTreeMap<_, _>>()
+            .await
+    }
+
+    async fn save_risk_limits(&mut self, federation_id: FederationId, limits: Option<RiskLimits>) {
+        match limits {
+            Some(limits) => {
+                self.insert_entry(&RiskLimitsKey(federation_id), &limits)
+                    .await;
+            }
+            None => {
+                self.remove_entry(&RiskLimitsKey(federation_id)).await;
+            }
+        }
+    }
+
+    async fn save_api_token(&mut self, token: ApiTokenInfo) {
+        self.insert_entry(&ApiTokenKey(token.id), &token).await;
+    }
+
+    async fn load_api_token(&mut self, id: sha256::Hash) -> Option<ApiTokenInfo> {
+        self.get_value(&ApiTokenKey(id)).await
+    }
+
+    async fn load_api_tokens(&mut self) -> Vec<ApiTokenInfo> {
+        self.find_by_prefix(&ApiTokenKeyPrefix)
+            .await
+            .map(|(_, token)| token)
+            .collect::<Vec<_>>()
+            .await
+    }
+
+    async fn remove_api_token(&mut self, id: sha256::Hash) -> Option<ApiTokenInfo> {
+        self.remove_entry(&ApiTokenKey(id)).await
+    }
+
     async fn dump_database(
         &mut self,
         prefix_names: Vec<String>,
@@ -382,6 +978,23 @@ enum DbKeyPrefix {
     Iroh = 0x11,
     FederationBackup = 0x12,
     ClaimedOutgoingPaymentImage = 0x13,
+    FetchedOfferInvoice = 0x14,
+    RegisteredOffer = 0x15,
+    OfferPaymentContract = 0x16,
+    OfferPaymentCursor = 0x17,
+    HoldInvoice = 0x18,
+    LnurlWithdrawLink = 0x19,
+    LiquidityPolicy = 0x1A,
+    FeeSchedule = 0x1C,
+    FeeSurcharges = 0x1D,
+    FeeHistory = 0x1E,
+    ApiToken = 0x1F,
+    RiskLimits = 0x20,
+    LnurlWithdrawal = 0x21,
+    LspChannelOrder = 0x22,
+    StaticLnurlContract = 0x23,
+    PendingClientRecovery = 0x24,
+    RestoredChannelPeer = 0x25,
 }
 
 impl std::fmt::Display for DbKeyPrefix {
@@ -972,5 +1585,423 @@ pub struct RegisteredIncomingContractV0 {
     db_prefix = DbKeyPrefix::ClaimedOutgoingPaymentImage,
 );
 
+#[derive(Debug, Encodable, Decodable)]
+struct FetchedOfferInvoiceKey(pub sha256::Hash);
+
+#[derive(Debug, Encodable, Decodable)]
+struct FetchedOfferInvoiceKeyPrefix;
+
+#[derive(Debug, Clone, Encodable, Decodable)]
+pub struct FetchedOfferInvoice {
+    /// The invoice in the encoding of the Lightning backend.
+    pub invoice: String,
+    pub amount: Amount,
+    pub expires_at_secs: u64,
+}
+
+impl_db_record!(
+    key = FetchedOfferInvoiceKey,
+    value = FetchedOfferInvoice,
+    db_prefix = DbKeyPrefix::FetchedOfferInvoice,
+);
+
+impl_db_lookup!(
+    key = FetchedOfferInvoiceKey,
+    query_prefix = FetchedOfferInvoiceKeyPrefix
+);
+
+/// A BOLT12 offer the gateway created on behalf of a recipient in a
+/// federation, keyed by the hex encoded offer id.
+#[derive(Debug, Encodable, Decodable)]
+struct RegisteredOfferKey(pub String);
+
+#[derive(Debug, Clone, Encodable, Decodable)]
+pub struct RegisteredOffer {
+    pub federation_id: FederationId,
+    pub recipient_pk: secp256k1::PublicKey,
+}
+
+impl_db_record!(
+    key = RegisteredOfferKey,
+    value = RegisteredOffer,
+    db_prefix = DbKeyPrefix::RegisteredOffer,
+);
+
+#[derive(Debug, Encodable, Decodable)]
+struct OfferPaymentContractKey(pub sha256::Hash);
+
+#[derive(Debug, Clone, Encodable, Decodable)]
+pub struct OfferPaymentContract {
+    pub federation_id: FederationId,
+    pub contract: IncomingContract,
+}
+
+impl_db_record!(
+    key = OfferPaymentContractKey,
+    value = OfferPaymentContract,
+    db_prefix = DbKeyPrefix::OfferPaymentContract,
+);
+
+#[derive(Debug, Encodable, Decodable)]
+struct OfferPaymentCursorKey;
+
+impl_db_record!(
+    key = OfferPaymentCursorKey,
+    value = u64,
+    db_prefix = DbKeyPrefix::OfferPaymentCursor,
+);
+
+/// An invoice whose payment the gateway holds until the recipient decides to
+/// settle or cancel it. The contract is only funded once the recipient
+/// settles, hence a cancelled payment is simply failed back to the payer.
+#[derive(Debug, Encodable, Decodable)]
+struct HoldInvoiceKey(pub ContractId);
+
+#[derive(Debug, Encodable, Decodable)]
+struct HoldInvoiceKeyPrefix;
+
+#[derive(Debug, Clone, Encodable, Decodable)]
+pub struct HoldInvoice {
+    pub federation_id: FederationId,
+    pub contract: IncomingContract,
+    /// How long the gateway holds the payment once it has arrived.
+    pub hold_secs: u32,
+    pub state: HoldInvoiceState,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Encodable, Decodable)]
+pub enum HoldInvoiceState {
+    /// The payment has not arrived yet.
+    Pending,
+    /// The payment is held until the recipient decides or the deadline passes.
+    Held(HeldHtlc),
+    Settled,
+    Cancelled,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Encodable, Decodable)]
+pub struct HeldHtlc {
+    pub payment_hash: sha256::Hash,
+    pub incoming_chan_id: u64,
+    pub htlc_id: u64,
+    pub amount_msat: u64,
+    pub deadline_secs: u64,
+}
+
+impl_db_record!(
+    key = HoldInvoiceKey,
+    value = HoldInvoice,
+    db_prefix = DbKeyPrefix::HoldInvoice,
+);
+
+impl_db_lookup!(key = HoldInvoiceKey, query_prefix = HoldInvoiceKeyPrefix);
+
+/// An LNURL-withdraw link published by the operator, keyed by its random id
+/// which doubles as the `k1` of the link.
+#[derive(Debug, Encodable, Decodable)]
+struct LnurlWithdrawLinkKey(pub [u8; 32]);
+
+#[derive(Debug, Clone, Encodable, Decodable)]
+pub struct LnurlWithdrawLink {
+    pub min_withdrawable: Amount,
+    pub max_withdrawable: Amount,
+    pub remaining_uses: u64,
+    pub description: String,
+    /// The federation whose ecash funds the withdrawals, or `None` if they are
+    /// paid from the lightning node's balance.
+    pub federation_id: Option<FederationId>,
+}
+
+impl_db_record!(
+    key = LnurlWithdrawLinkKey,
+    value = LnurlWithdrawLink,
+    db_prefix = DbKeyPrefix::LnurlWithdrawLink,
+);
+
+#[derive(Debug, Encodable, Decodable)]
+struct LiquidityPolicyKey;
+
+impl_db_record!(
+    key = LiquidityPolicyKey,
+    value = LiquidityPolicy,
+    db_prefix = DbKeyPrefix::LiquidityPolicy,
+);
+
+#[derive(Debug, Encodable, Decodable)]
+struct FeeScheduleKey(pub FederationId);
+
+#[derive(Debug, Encodable, Decodable)]
+struct FeeScheduleKeyPrefix;
+
+impl_db_record!(
+    key = FeeScheduleKey,
+    value = FeeSchedule,
+    db_prefix = DbKeyPrefix::FeeSchedule,
+);
+
+impl_db_lookup!(key = FeeScheduleKey, query_prefix = FeeScheduleKeyPrefix);
+
+#[derive(Debug, Encodable, Decodable)]
+struct FeeSurchargesKey(pub FederationId);
+
+/// The surcharges of a federation's fee schedule that depend on the time of
+/// day and the gateway's liquidity, as of the last time they were updated.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Encodable, Decodable)]
+pub struct FeeSurcharges {
+    pub send_ppm: u64,
+    pub receive_ppm: u64,
+}
+
+impl_db_record!(
+    key = FeeSurchargesKey,
+    value = FeeSurcharges,
+    db_prefix = DbKeyPrefix::FeeSurcharges,
+);
+
+/// An entry of a federation's fee history, keyed by the time from which on
+/// the fees applied.
+#[derive(Debug, Encodable, Decodable)]
+struct FeeHistoryKey {
+    federation_id: FederationId,
+    timestamp_secs: u64,
+}
+
+#[derive(Debug, Encodable, Decodable)]
+struct FeeHistoryFederationPrefix(FederationId);
+
+impl_db_record!(
+    key = FeeHistoryKey,
+    value = FeeHistoryEntry,
+    db_prefix = DbKeyPrefix::FeeHistory,
+);
+
+impl_db_lookup!(
+    key = FeeHistoryKey,
+    query_prefix = FeeHistoryFederationPrefix
+);
+
+/// An API token, keyed by the hash of its secret.
+#[derive(Debug, Encodable, Decodable)]
+struct ApiTokenKey(sha256::Hash);
+
+#[derive(Debug, Encodable, Decodable)]
+struct ApiTokenKeyPrefix;
+
+impl_db_record!(
+    key = ApiTokenKey,
+    value = ApiTokenInfo,
+    db_prefix = DbKeyPrefix::ApiToken,
+);
+
+impl_db_lookup!(key = ApiTokenKey, query_prefix = ApiTokenKeyPrefix);
+
+#[derive(Debug, Encodable, Decodable)]
+struct RiskLimitsKey(pub FederationId);
+
+#[derive(Debug, Encodable, Decodable)]
+struct RiskLimitsKeyPrefix;
+
+impl_db_record!(
+    key = RiskLimitsKey,
+    value = RiskLimits,
+    db_prefix = DbKeyPrefix::RiskLimits,
+);
+
+impl_db_lookup!(key = RiskLimitsKey, query_prefix = RiskLimitsKeyPrefix);
+
+/// A withdrawal whose invoice is being paid, keyed by the payment hash of the
+/// invoice. The use of the link is consumed while the record exists and only
+/// restored once the payment has definitively failed.
+#[derive(Debug, Encodable, Decodable)]
+struct LnurlWithdrawalKey(pub sha256::Hash);
+
+#[derive(Debug, Encodable, Decodable)]
+struct LnurlWithdrawalKeyPrefix;
+
+#[derive(Debug, Clone, Encodable, Decodable)]
+pub struct LnurlWithdrawal {
+    pub link_id: [u8; 32],
+    pub invoice: lightning_invoice::Bolt11Invoice,
+    /// The federation whose ecash funds the withdrawal, or `None` if it is
+    /// paid from the lightning node's balance.
+    pub federation_id: Option<FederationId>,
+    /// When the withdrawal was accepted, which bounds the search for the
+    /// payment in the lightning node's history.
+    pub created_at_secs: u64,
+}
+
+impl_db_record!(
+    key = LnurlWithdrawalKey,
+    value = LnurlWithdrawal,
+    db_prefix = DbKeyPrefix::LnurlWithdrawal,
+);
+
+impl_db_lookup!(
+    key = LnurlWithdrawalKey,
+    query_prefix = LnurlWithdrawalKeyPrefix
+);
+
+/// The channel an order of the gateway's LSP buys, after the fee was taken
+/// from the ecash.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Encodable, Decodable)]
+pub struct ChannelOrderQuote {
+    pub channel_size_sats: u64,
+    pub push_amount_sats: u64,
+    pub fee_sats: u64,
+}
+
+/// A channel order of the gateway's LSP, keyed by the hash of the ecash that
+/// pays for it. The order is saved before the ecash is reissued and every
+/// step after, such that it can be resumed after a restart and its refund
+/// can be retrieved by its id.
+#[derive(Debug, Encodable, Decodable)]
+struct LspChannelOrderKey(pub sha256::Hash);
+
+#[derive(Debug, Encodable, Decodable)]
+struct LspChannelOrderKeyPrefix;
+
+#[derive(Debug, Clone, PartialEq, Eq, Encodable, Decodable)]
+pub struct LspChannelOrder {
+    pub federation_id: FederationId,
+    pub pubkey: secp256k1::PublicKey,
+    pub host: String,
+    pub inbound_sats: u64,
+    pub state: LspChannelOrderState,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Encodable, Decodable)]
+pub enum LspChannelOrderState {
+    /// The ecash is being reissued. An order whose ecash cannot be reissued
+    /// is removed.
+    Received { notes: String },
+    /// The ecash was reissued and the channel is being opened. The funding
+    /// transactions of the channels to the node from before the order tell
+    /// them apart from the ordered one.
+    Opening {
+        ecash: Amount,
+        quote: ChannelOrderQuote,
+        known_funding_txids: Vec<bitcoin::Txid>,
+    },
+    Opened {
+        ecash: Amount,
+        quote: ChannelOrderQuote,
+        funding_txid: bitcoin::Txid,
+    },
+    /// The channel cannot be opened and the ecash is being returned.
+    Refunding {
+        ecash: Amount,
+        quote: Option<ChannelOrderQuote>,
+        failure_reason: String,
+    },
+    /// The ecash of the refund is being spent since `since_secs`. A spend for
+    /// the order created since then is picked up instead of spending again.
+    RefundSpending {
+        ecash: Amount,
+        quote: Option<ChannelOrderQuote>,
+        failure_reason: String,
+        since_secs: u64,
+    },
+    Refunded {
+        quote: Option<ChannelOrderQuote>,
+        notes: String,
+        failure_reason: String,
+    },
+}
+
+impl LspChannelOrderState {
+    /// Returns whether the order still has to be resumed.
+    pub fn is_pending(&self) -> bool {
+        matches!(
+            self,
+            Self::Received { .. }
+                | Self::Opening { .. }
+                | Self::Refunding { .. }
+                | Self::RefundSpending { .. }
+        )
+    }
+}
+
+impl_db_record!(
+    key = LspChannelOrderKey,
+    value = LspChannelOrder,
+    db_prefix = DbKeyPrefix::LspChannelOrder,
+);
+
+impl_db_lookup!(
+    key = LspChannelOrderKey,
+    query_prefix = LspChannelOrderKeyPrefix
+);
+
+/// An incoming contract a recipient created and registered for a payment to
+/// its static LNURL, keyed by the recipient's public key and the contract id.
+/// The gateway hands out only these contracts, such that it never learns the
+/// preimage of a payment to the recipient. Every contract is used once.
+#[derive(Debug, Encodable, Decodable)]
+struct StaticLnurlContractKey {
+    recipient_pk: secp256k1::PublicKey,
+    contract_id: ContractId,
+}
+
+#[derive(Debug, Encodable, Decodable)]
+struct StaticLnurlContractRecipientPrefix(secp256k1::PublicKey);
+
+#[derive(Debug, Clone, Encodable, Decodable)]
+pub struct StaticLnurlContract {
+    pub federation_id: FederationId,
+    /// The amount of the invoices paying the contract.
+    pub amount: Amount,
+    pub contract: IncomingContract,
+}
+
+impl_db_record!(
+    key = StaticLnurlContractKey,
+    value = StaticLnurlContract,
+    db_prefix = DbKeyPrefix::StaticLnurlContract,
+);
+
+impl_db_lookup!(
+    key = StaticLnurlContractKey,
+    query_prefix = StaticLnurlContractRecipientPrefix
+);
+
+/// A federation whose client is recovered from the mnemonic the next time the
+/// gateway starts, since the gateway was restored without its client.
+#[derive(Debug, Encodable, Decodable)]
+struct PendingClientRecoveryKey(FederationId);
+
+#[derive(Debug, Encodable, Decodable)]
+struct PendingClientRecoveryKeyPrefix;
+
+impl_db_record!(
+    key = PendingClientRecoveryKey,
+    value = (),
+    db_prefix = DbKeyPrefix::PendingClientRecovery,
+);
+
+impl_db_lookup!(
+    key = PendingClientRecoveryKey,
+    query_prefix = PendingClientRecoveryKeyPrefix
+);
+
+/// A peer the lightning node had channels with before the gateway was
+/// restored, mapped to its address. Once the restored node connects to the
+/// peer, the peer force closes the channels the node no longer knows.
+#[derive(Debug, Encodable, Decodable)]
+struct RestoredChannelPeerKey(secp256k1::PublicKey);
+
+#[derive(Debug, Encodable, Decodable)]
+struct RestoredChannelPeerKeyPrefix;
+
+impl_db_record!(
+    key = RestoredChannelPeerKey,
+    value = String,
+    db_prefix = DbKeyPrefix::RestoredChannelPeer,
+);
+
+impl_db_lookup!(
+    key = RestoredChannelPeerKey,
+    query_prefix = RestoredChannelPeerKeyPrefix
+);
+
 #[cfg(test)]
 mod migration_tests;
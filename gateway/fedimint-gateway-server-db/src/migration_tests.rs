@@ -20,10 +20,11 @@
     Amount, BTreeMap, DbKeyPrefix, Encodable, FederationConfig, FederationConfigKey,
     FederationConfigKeyPrefix, FederationConfigKeyV0, FederationConfigV0, FederationId,
     GatewayConfigurationKeyV0, GatewayConfigurationV0, GatewayDbExt, GatewayDbtxNcExt,
-    GatewayPublicKey, IDatabaseTransactionOpsCoreTyped, IncomingContract, InviteCode, Keypair,
-    NetworkLegacyEncodingWrapper, OsRng, PaymentImage, PreimageAuthentication,
-    PreimageAuthenticationPrefix, RegisteredIncomingContractKeyV0, RegisteredIncomingContractV0,
-    StreamExt, duration_since_epoch, get_gatewayd_database_migrations, migrate_federation_configs,
+    GatewayPublicKey, HeldHtlc, HoldInvoice, HoldInvoiceState, IDatabaseTransactionOpsCoreTyped,
+    IncomingContract, InviteCode, Keypair, NetworkLegacyEncodingWrapper, OsRng, PaymentImage,
+    PreimageAuthentication, PreimageAuthenticationPrefix, RegisteredIncomingContractKeyV0,
+    RegisteredIncomingContractV0, StreamExt, duration_since_epoch,
+    get_gatewayd_database_migrations, migrate_federation_configs,
     migrate_registered_incoming_contracts, secp256k1, sha256,
 };
 use crate::GatewayPublicKeyV0;
@@ -223,6 +224,80 @@ async fn test_registered_incoming_contract_migration() -> anyhow::Result<()> {
     Ok(())
 }
 
+#[tokio::test(flavor = "multi_thread")]
+async fn test_prune_hold_invoices_keeps_held_payments() -> anyhow::Result<()> {
+    let db = Database::new(MemDatabase::new(), ModuleDecoderRegistry::default());
+
+    let expired_contract = |preimage: [u8; 32]| {
+        IncomingContract::new(
+            tpe::AggregatePublicKey(tpe::G1Affine::generator()),
+            [42; 32],
+            preimage,
+            PaymentImage::Hash(preimage.consensus_hash()),
+            Amount::from_sats(1000),
+            1000,
+            Keypair::new(secp256k1::SECP256K1, &mut rand::thread_rng()).public_key(),
+            Keypair::new(secp256k1::SECP256K1, &mut rand::thread_rng()).public_key(),
+            Keypair::new(secp256k1::SECP256K1, &mut rand::thread_rng()).public_key(),
+        )
+    };
+
+    let pending = expired_contract([0; 32]);
+    let held = expired_contract([1; 32]);
+
+    let mut dbtx = db.begin_transaction().await;
+
+    dbtx.save_hold_invoice(
+        pending.contract_id(),
+        HoldInvoice {
+            federation_id: FederationId::dummy(),
+            contract: pending.clone(),
+            hold_secs: 60,
+            state: HoldInvoiceState::Pending,
+        },
+    )
+    .await;
+
+    dbtx.save_hold_invoice(
+        held.contract_id(),
+        HoldInvoice {
+            federation_id: FederationId::dummy(),
+            contract: held.clone(),
+            hold_secs: 60,
+            state: HoldInvoiceState::Held(HeldHtlc {
+                payment_hash: [1_u8; 32].consensus_hash(),
+                incoming_chan_id: 0,
+                htlc_id: 0,
+                amount_msat: 1_000_000,
+                deadline_secs: 1000,
+            }),
+        },
+    )
+    .await;
+
+    // A held payment has to be failed back before its record may be deleted.
+    assert_eq!(
+        dbtx.prune_hold_invoices(duration_since_epoch().as_secs())
+            .await,
+        1
+    );
+
+    assert!(
+        dbtx.load_hold_invoice(pending.contract_id())
+            .await
+            .is_none()
+    );
+
+    let held_invoices = dbtx.load_held_invoices().await;
+
+    assert_eq!(held_invoices.len(), 1);
+    assert_eq!(held_invoices[0].0, held.contract_id());
+
+    dbtx.commit_tx().await;
+
+    Ok(())
+}
+
 #[tokio::test(flavor = "multi_thread")]
 async fn test_isolated_db_migration() -> anyhow::Result<()> {
     async fn create_isolated_record(prefix: Vec<u8>, db: &Database) {
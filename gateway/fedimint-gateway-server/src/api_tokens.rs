@@ -0,0 +1,303 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+use bitcoin::hashes::{Hash as _, sha256};
+use fedimint_core::time::{duration_since_epoch, now};
+use fedimint_eventlog::{DBTransactionEventLogExt as _, Event, EventKind, PersistedLogEntry};
+use fedimint_gateway_common::{
+    API_TOKEN_AUDIT_LOG_ENDPOINT, API_TOKENS_ENDPOINT, ApiTokenAuditLogPayload,
+    ApiTokenAuditLogResponse, ApiTokenCreated, ApiTokenInfo, ApiTokenRevoked, ApiTokenRole,
+    ApiTokenUsed, CONFIGURATION_ENDPOINT, CREATE_API_TOKEN_ENDPOINT,
+    CREATE_BOLT11_INVOICE_FOR_OPERATOR_ENDPOINT, CREATE_BOLT12_OFFER_FOR_OPERATOR_ENDPOINT,
+    CreateApiTokenPayload, CreateApiTokenResponse, EXPORT_LEDGER_ENDPOINT,
+    FEDERATION_STATUS_ENDPOINT, FEE_HISTORY_ENDPOINT, FEE_SCHEDULE_ENDPOINT, GATEWAY_INFO_ENDPOINT,
+    GET_BALANCES_ENDPOINT, GET_INVOICE_ENDPOINT, GET_LIQUIDITY_POLICY_ENDPOINT, LEDGER_ENDPOINT,
+    LIQUIDITY_LOG_ENDPOINT, LIST_CHANNELS_ENDPOINT, LIST_TRANSACTIONS_ENDPOINT,
+    PAY_INVOICE_FOR_OPERATOR_ENDPOINT, PAY_OFFER_FOR_OPERATOR_ENDPOINT, PAYMENT_LOG_ENDPOINT,
+    PAYMENT_SUMMARY_ENDPOINT, REVENUE_ENDPOINT, REVOKE_API_TOKEN_ENDPOINT, RISK_STATUS_ENDPOINT,
+    RevokeApiTokenPayload,
+};
+use fedimint_gateway_server_db::GatewayDbtxNcExt as _;
+use serde::Deserialize;
+
+use crate::error::AdminGatewayError;
+use crate::{AdminResult, Gateway};
+
+/// Routes that a read-only API token is allowed to access.
+const READ_ONLY_ROUTES: [&str; 17] = [
+    CONFIGURATION_ENDPOINT,
+    EXPORT_LEDGER_ENDPOINT,
+    FEDERATION_STATUS_ENDPOINT,
+    FEE_HISTORY_ENDPOINT,
+    FEE_SCHEDULE_ENDPOINT,
+    GATEWAY_INFO_ENDPOINT,
+    GET_BALANCES_ENDPOINT,
+    GET_INVOICE_ENDPOINT,
+    GET_LIQUIDITY_POLICY_ENDPOINT,
+    LEDGER_ENDPOINT,
+    LIQUIDITY_LOG_ENDPOINT,
+    LIST_CHANNELS_ENDPOINT,
+    LIST_TRANSACTIONS_ENDPOINT,
+    PAYMENT_LOG_ENDPOINT,
+    PAYMENT_SUMMARY_ENDPOINT,
+    REVENUE_ENDPOINT,
+    RISK_STATUS_ENDPOINT,
+];
+
+/// Routes that a payments API token is allowed to access.
+const PAYMENTS_ROUTES: [&str; 5] = [
+    CREATE_BOLT11_INVOICE_FOR_OPERATOR_ENDPOINT,
+    CREATE_BOLT12_OFFER_FOR_OPERATOR_ENDPOINT,
+    GET_INVOICE_ENDPOINT,
+    PAY_INVOICE_FOR_OPERATOR_ENDPOINT,
+    PAY_OFFER_FOR_OPERATOR_ENDPOINT,
+];
+
+/// Routes that no API token is allowed to access, such that a leaked token
+/// cannot be used to mint or revoke tokens.
+const TOKEN_MANAGEMENT_ROUTES: [&str; 4] = [
+    API_TOKEN_AUDIT_LOG_ENDPOINT,
+    API_TOKENS_ENDPOINT,
+    CREATE_API_TOKEN_ENDPOINT,
+    REVOKE_API_TOKEN_ENDPOINT,
+];
+
+/// The event kinds of the API token audit log.
+const API_TOKEN_EVENTS: [EventKind; 3] = [
+    ApiTokenCreated::KIND,
+    ApiTokenRevoked::KIND,
+    ApiTokenUsed::KIND,
+];
+
+/// The interval within which only the first authorized use of an API token is
+/// recorded in the audit log, such that authenticated requests do not write
+/// to the database. Denied requests are always recorded.
+const API_TOKEN_USE_LOG_INTERVAL: Duration = Duration::from_hours(1);
+
+/// The number of events read from the event log at once while searching it
+/// backwards for API token events.
+const AUDIT_LOG_BATCH_SIZE: u64 = 1000;
+
+fn role_allows_route(role: ApiTokenRole, route: &str) -> bool {
+    if TOKEN_MANAGEMENT_ROUTES.contains(&route) {
+        return false;
+    }
+
+    match role {
+        ApiTokenRole::ReadOnly => READ_ONLY_ROUTES.contains(&route),
+        ApiTokenRole::Payments => PAYMENTS_ROUTES.contains(&route),
+        ApiTokenRole::Admin => true,
+    }
+}
+
+fn is_expired(token: &ApiTokenInfo, now_secs: u64) -> bool {
+    token
+        .expires_at_secs
+        .is_some_and(|expires_at_secs| expires_at_secs <= now_secs)
+}
+
+/// Returns whether an event of the audit log belongs to the API token `id`.
+fn is_event_of_token(entry: &PersistedLogEntry, id: sha256::Hash) -> bool {
+    #[derive(Deserialize)]
+    struct TokenEvent {
+        id: sha256::Hash,
+    }
+
+    serde_json::from_slice::<TokenEvent>(&entry.as_raw().payload).is_ok_and(|event| event.id == id)
+}
+
+/// Remembers when the last authorized use of each API token was recorded in
+/// the audit log.
+#[derive(Debug, Default)]
+pub struct ApiTokenUseLog {
+    last_logged: Mutex<HashMap<sha256::Hash, SystemTime>>,
+}
+
+impl ApiTokenUseLog {
+    /// Returns whether an authorized use of the API token `id` should be
+    /// recorded, which is the case for the first use within
+    /// [`API_TOKEN_USE_LOG_INTERVAL`].
+    fn should_log_use(&self, id: sha256::Hash) -> bool {
+        self.should_log_use_at(id, now())
+    }
+
+    fn should_log_use_at(&self, id: sha256::Hash, now: SystemTime) -> bool {
+        let mut last_logged = self
+            .last_logged
+            .lock()
+            .expect("No code holding the lock can panic");
+
+        // `SystemTime` is not monotonic; if the clock moved backwards, the use is
+        // recorded and the interval restarts from the earlier time.
+        let logged_recently = last_logged.get(&id).is_some_and(|last_logged| {
+            now.duration_since(*last_logged)
+                .is_ok_and(|elapsed| elapsed < API_TOKEN_USE_LOG_INTERVAL)
+        });
+
+        if !logged_recently {
+            last_logged.insert(id, now);
+        }
+
+        !logged_recently
+    }
+}
+
+impl Gateway {
+    /// Checks whether `token` is an API token that is allowed to access
+    /// `route`. Denied requests and the first authorized use of a token per
+    /// [`API_TOKEN_USE_LOG_INTERVAL`] are recorded in the gateway's event log.
+    /// Returns `None` if `token` is not an API token.
+    pub(crate) async fn authorize_api_token(&self, token: &str, route: &str) -> Option<bool> {
+        let id = sha256::Hash::hash(token.as_bytes());
+
+        let api_token = self
+            .gateway_db
+            .begin_transaction_nc()
+            .await
+            .load_api_token(id)
+            .await?;
+
+        let authorized = !is_expired(&api_token, duration_since_epoch().as_secs())
+            && role_allows_route(api_token.role, route);
+
+        if authorized && !self.api_token_use_log.should_log_use(id) {
+            return Some(true);
+        }
+
+        let mut dbtx = self.gateway_db.begin_transaction().await;
+        dbtx.log_event(
+            self.event_log_wakeup_tx.clone(),
+            None,
+            ApiTokenUsed {
+                id,
+                name: api_token.name,
+                route: route.to_string(),
+                authorized,
+            },
+        )
+        .await;
+        dbtx.commit_tx().await;
+
+        Some(authorized)
+    }
+
+    /// Creates an API token and returns its secret, which is not stored by
+    /// the gateway.
+    pub async fn handle_create_api_token_msg(
+        &self,
+        payload: CreateApiTokenPayload,
+    ) -> AdminResult<CreateApiTokenResponse> {
+        if payload.name.trim().is_empty() {
+            return Err(AdminGatewayError::GatewayConfigurationError(
+                "An API token requires a name".to_string(),
+            ));
+        }
+
+        let token = hex::encode(rand::random::<[u8; 32]>());
+        let id = sha256::Hash::hash(token.as_bytes());
+        let created_at_secs = duration_since_epoch().as_secs();
+        let expires_at_secs = payload
+            .expires_in_secs
+            .map(|expires_in_secs| created_at_secs.saturating_add(expires_in_secs));
+
+        let mut dbtx = self.gateway_db.begin_transaction().await;
+        dbtx.save_api_token(ApiTokenInfo {
+            id,
+            name: payload.name.clone(),
+            role: payload.role,
+            created_at_secs,
+            expires_at_secs,
+        })
+        .await;
+        dbtx.log_event(
+            self.event_log_wakeup_tx.clone(),
+            None,
+            ApiTokenCreated {
+                id,
+                name: payload.name,
+                role: payload.role,
+                expires_at_secs,
+            },
+        )
+        .await;
+        dbtx.commit_tx().await;
+
+        Ok(CreateApiTokenResponse { id, token })
+    }
+
+    /// Returns all API tokens, including expired ones that were not revoked.
+    pub async fn handle_list_api_tokens_msg(&self) -> AdminResult<Vec<ApiTokenInfo>> {
+        Ok(self
+            .gateway_db
+            .begin_transaction_nc()
+            .await
+            .load_api_tokens()
+            .await)
+    }
+
+    /// Revokes an API token, which takes effect with the next request.
+    pub async fn handle_revoke_api_token_msg(
+        &self,
+        payload: RevokeApiTokenPayload,
+    ) -> AdminResult<()> {
+        let mut dbtx = self.gateway_db.begin_transaction().await;
+
+        let api_token = dbtx.remove_api_token(payload.id).await.ok_or_else(|| {
+            AdminGatewayError::GatewayConfigurationError(format!(
+                "There is no API token with id {}",
+                payload.id
+            ))
+        })?;
+
+        dbtx.log_event(
+            self.event_log_wakeup_tx.clone(),
+            None,
+            ApiTokenRevoked {
+                id: api_token.id,
+                name: api_token.name,
+            },
+        )
+        .await;
+        dbtx.commit_tx().await;
+
+        Ok(())
+    }
+
+    /// Returns the most recent API token events from the gateway's event log,
+    /// newest first.
+    pub async fn handle_api_token_audit_log_msg(
+        &self,
+        payload: ApiTokenAuditLogPayload,
+    ) -> AdminResult<ApiTokenAuditLogResponse> {
+        let mut dbtx = self.gateway_db.begin_transaction_nc().await;
+        let mut batch_end = u64::from(dbtx.get_next_event_log_trimable_id().await);
+        let mut events = Vec::new();
+
+        while batch_end > 0 && events.len() < payload.limit {
+            let batch_start = batch_end.saturating_sub(AUDIT_LOG_BATCH_SIZE);
+            let batch = dbtx
+                .get_event_log_trimable(Some(batch_start.into()), batch_end - batch_start)
+                .await;
+
+            // The log is trimmed from its start, so there are no older events
+            if batch.is_empty() {
+                break;
+            }
+
+            events.extend(batch.into_iter().rev().filter(|entry| {
+                API_TOKEN_EVENTS.contains(&entry.as_raw().kind)
+                    && payload.id.is_none_or(|id| is_event_of_token(entry, id))
+            }));
+
+            batch_end = batch_start;
+        }
+
+        events.truncate(payload.limit);
+
+        Ok(ApiTokenAuditLogResponse(events))
+    }
+}
+
+#[cfg(test)]
+mod tests;
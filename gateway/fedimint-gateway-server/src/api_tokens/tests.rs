@@ -0,0 +1,84 @@
+use std::time::Duration;
+
+use bitcoin::hashes::{Hash as _, sha256};
+use fedimint_core::time::now;
+use fedimint_gateway_common::{
+    API_TOKENS_ENDPOINT, ApiTokenInfo, ApiTokenRole, CREATE_API_TOKEN_ENDPOINT,
+    GET_BALANCES_ENDPOINT, MNEMONIC_ENDPOINT, PAY_INVOICE_FOR_OPERATOR_ENDPOINT,
+    REVOKE_API_TOKEN_ENDPOINT,
+};
+
+use super::{API_TOKEN_USE_LOG_INTERVAL, ApiTokenUseLog, is_expired, role_allows_route};
+
+#[test]
+fn roles_are_scoped_to_their_routes() {
+    assert!(role_allows_route(
+        ApiTokenRole::ReadOnly,
+        GET_BALANCES_ENDPOINT
+    ));
+    assert!(!role_allows_route(
+        ApiTokenRole::ReadOnly,
+        PAY_INVOICE_FOR_OPERATOR_ENDPOINT
+    ));
+
+    assert!(role_allows_route(
+        ApiTokenRole::Payments,
+        PAY_INVOICE_FOR_OPERATOR_ENDPOINT
+    ));
+    assert!(!role_allows_route(
+        ApiTokenRole::Payments,
+        GET_BALANCES_ENDPOINT
+    ));
+
+    assert!(role_allows_route(ApiTokenRole::Admin, MNEMONIC_ENDPOINT));
+}
+
+#[test]
+fn no_role_can_manage_api_tokens() {
+    for role in [
+        ApiTokenRole::ReadOnly,
+        ApiTokenRole::Payments,
+        ApiTokenRole::Admin,
+    ] {
+        for route in [
+            API_TOKENS_ENDPOINT,
+            CREATE_API_TOKEN_ENDPOINT,
+            REVOKE_API_TOKEN_ENDPOINT,
+        ] {
+            assert!(!role_allows_route(role, route));
+        }
+    }
+}
+
+#[test]
+fn tokens_expire_at_their_expiry() {
+    let token = |expires_at_secs| ApiTokenInfo {
+        id: sha256::Hash::hash(b"token"),
+        name: "billing".to_string(),
+        role: ApiTokenRole::Payments,
+        created_at_secs: 1000,
+        expires_at_secs,
+    };
+
+    assert!(!is_expired(&token(None), u64::MAX));
+    assert!(!is_expired(&token(Some(2000)), 1999));
+    assert!(is_expired(&token(Some(2000)), 2000));
+}
+
+#[test]
+fn only_the_first_use_per_interval_is_logged() {
+    let use_log = ApiTokenUseLog::default();
+    let start = now();
+    let token = sha256::Hash::hash(b"token");
+    let other_token = sha256::Hash::hash(b"other token");
+
+    assert!(use_log.should_log_use_at(token, start));
+    assert!(!use_log.should_log_use_at(token, start + Duration::from_secs(1)));
+    assert!(use_log.should_log_use_at(other_token, start + Duration::from_secs(1)));
+
+    assert!(!use_log.should_log_use_at(
+        token,
+        start + API_TOKEN_USE_LOG_INTERVAL - Duration::from_secs(1)
+    ));
+    assert!(use_log.should_log_use_at(token, start + API_TOKEN_USE_LOG_INTERVAL));
+}
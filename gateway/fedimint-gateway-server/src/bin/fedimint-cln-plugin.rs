@@ -0,0 +1,517 @@
+#![warn(missing_docs)]
+//! This crate provides `fedimint-cln-plugin`, the Core Lightning plugin that
+//! lets `gatewayd` intercept HTLCs on a `lightningd` node.
+//!
+//! The plugin holds every HTLC that `lightningd` passes to the `htlc_accepted`
+//! hook until the gateway decides whether to settle, cancel or forward it. The
+//! gateway fetches the held HTLCs and resolves them through the RPC methods
+//! the plugin registers with `lightningd`, see
+//! [`fedimint_lightning::cln::GatewayClnClient`].
+//!
+//! HTLCs are handed to `lightningd` unchanged while no gateway is polling, and
+//! all held HTLCs are released if the gateway stops polling, just like LND
+//! resumes intercepted HTLCs once the interceptor disconnects. A gateway that
+//! reconnects in time is handed the HTLCs it has not resolved yet again, and
+//! HTLCs close to their CLTV expiry are failed such that the channel they
+//! arrived on is not force-closed.
+
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::Context as _;
+use fedimint_lightning::cln::{
+    CLN_PLUGIN_COMPLETE_HTLC_METHOD, CLN_PLUGIN_NEXT_HTLCS_METHOD, parse_short_channel_id,
+};
+use fedimint_lightning::{InterceptPaymentRequest, InterceptPaymentResponse, PaymentAction};
+use serde_json::{Value, json};
+use tokio::io::{AsyncReadExt as _, AsyncWriteExt as _};
+use tokio::sync::{Mutex, Notify, mpsc};
+
+/// The longest a single poll for HTLCs may block, such that a gateway that
+/// sends unreasonable timeouts cannot stall the plugin.
+const MAX_POLL_TIMEOUT_SECS: u64 = 60;
+
+/// If the gateway did not poll for this long it is considered disconnected,
+/// and the held HTLCs are handed back to `lightningd`.
+const GATEWAY_TIMEOUT: Duration = Duration::from_secs(2 * MAX_POLL_TIMEOUT_SECS);
+
+/// The BOLT4 `temporary_node_failure` failure code, which lets the sender
+/// retry the payment along another route.
+const TEMPORARY_NODE_FAILURE: &str = "2002";
+
+/// Held HTLCs are failed once the chain is within this many blocks of their
+/// CLTV expiry, since the peer they arrived from force-closes the channel if
+/// they are still unresolved when they expire.
+const CLTV_EXPIRY_SAFETY_MARGIN: u32 = 18;
+
+/// An HTLC that is held in the `htlc_accepted` hook.
+struct HeldHtlc {
+    /// The id of the hook call, which `lightningd` expects the result under.
+    hook_request_id: Value,
+    htlc: InterceptPaymentRequest,
+    /// Whether the HTLC has been passed to the gateway already.
+    handed_out: bool,
+}
+
+#[derive(Default)]
+struct PluginState {
+    queue: VecDeque<InterceptPaymentRequest>,
+    held: BTreeMap<(u64, u64), HeldHtlc>,
+    last_poll: Option<Instant>,
+    polling: usize,
+    /// The height of the chain as last reported by `lightningd`.
+    block_height: Option<u32>,
+}
+
+impl PluginState {
+    fn is_gateway_connected(&self) -> bool {
+        self.polling > 0
+            || self
+                .last_poll
+                .is_some_and(|last_poll| last_poll.elapsed() < GATEWAY_TIMEOUT)
+    }
+
+    /// Returns whether an HTLC expiring at `cltv_expiry` has to be resolved
+    /// now to protect the channel it arrived on.
+    fn is_close_to_expiry(&self, cltv_expiry: u32) -> bool {
+        self.block_height.is_some_and(|block_height| {
+            cltv_expiry <= block_height.saturating_add(CLTV_EXPIRY_SAFETY_MARGIN)
+        })
+    }
+
+    /// Puts every HTLC that has been handed out but not resolved back into the
+    /// queue, such that a gateway that lost HTLCs it was handed is given them
+    /// again.
+    fn requeue_handed_out(&mut self) {
+        for held in self.held.values_mut() {
+            if held.handed_out {
+                held.handed_out = false;
+                self.queue.push_back(held.htlc.clone());
+            }
+        }
+    }
+}
+
+#[derive(Clone)]
+struct Plugin {
+    state: Arc<Mutex<PluginState>>,
+    htlc_notify: Arc<Notify>,
+    output: mpsc::UnboundedSender<Value>,
+}
+
+impl Plugin {
+    fn respond(&self, id: Value, result: Value) {
+        // The writer task only exits together with the plugin
+        let _ = self.output.send(json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "result": result,
+        }));
+    }
+
+    fn respond_error(&self, id: Value, message: String) {
+        let _ = self.output.send(json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "error": { "code": -32600, "message": message },
+        }));
+    }
+
+    fn log(&self, level: &str, message: String) {
+        let _ = self.output.send(json!({
+            "jsonrpc": "2.0",
+            "method": "log",
+            "params": { "level": level, "message": message },
+        }));
+    }
+
+    async fn handle_message(&self, message: Value) {
+        let id = message.get("id").cloned();
+        let params = message.get("params").cloned().unwrap_or(Value::Null);
+
+        let Some(method) = message.get("method").and_then(Value::as_str) else {
+            return;
+        };
+
+        // Notifications like `shutdown` carry no id and expect no response
+        let Some(id) = id else {
+            match method {
+                "shutdown" => std::process::exit(0),
+                "block_added" => self.handle_block_added(&params).await,
+                _ => {}
+            }
+
+            return;
+        };
+
+        match method {
+            "getmanifest" => self.respond(id, manifest()),
+            "init" => self.respond(id, json!({})),
+            "htlc_accepted" => self.handle_htlc_accepted(id, &params).await,
+            CLN_PLUGIN_NEXT_HTLCS_METHOD => {
+                let plugin = self.clone();
+
+                // Polls block, so they must not hold up the hook calls
+                tokio::spawn(async move { plugin.handle_next_htlcs(id, &params).await });
+            }
+            CLN_PLUGIN_COMPLETE_HTLC_METHOD => self.handle_complete_htlc(id, params).await,
+            method => self.respond_error(id, format!("Unknown method {method}")),
+        }
+    }
+
+    async fn handle_htlc_accepted(&self, id: Value, params: &Value) {
+        let Some(htlc) = intercept_payment_request(params) else {
+            self.log(
+                "unusual",
+                format!("Could not parse intercepted HTLC, continuing: {params}"),
+            );
+            self.respond(id, json!({ "result": "continue" }));
+            return;
+        };
+
+        let mut state = self.state.lock().await;
+
+        // The hook reports the expiry relative to the current height as well,
+        // which tells us the height before the first block notification.
+        if let Some(block_height) = params
+            .get("htlc")
+            .and_then(|htlc| htlc.get("cltv_expiry_relative"))
+            .and_then(Value::as_u64)
+            .and_then(|relative| u64::from(htlc.expiry).checked_sub(relative))
+            .and_then(|block_height| u32::try_from(block_height).ok())
+        {
+            state.block_height = state.block_height.max(Some(block_height));
+        }
+
+        if !state.is_gateway_connected() || state.is_close_to_expiry(htlc.expiry) {
+            drop(state);
+            self.respond(id, json!({ "result": "continue" }));
+            return;
+        }
+
+        state.held.insert(
+            (htlc.incoming_chan_id, htlc.htlc_id),
+            HeldHtlc {
+                hook_request_id: id,
+                htlc: htlc.clone(),
+                handed_out: false,
+            },
+        );
+        state.queue.push_back(htlc);
+
+        drop(state);
+        self.htlc_notify.notify_waiters();
+    }
+
+    async fn handle_next_htlcs(&self, id: Value, params: &Value) {
+        let timeout = Duration::from_secs(
+            params
+                .get("timeout_secs")
+                .and_then(Value::as_u64)
+                .unwrap_or_default()
+                .min(MAX_POLL_TIMEOUT_SECS),
+        );
+        let deadline = Instant::now() + timeout;
+
+        let mut state = self.state.lock().await;
+
+        // The gateway starts a new subscription after it reconnected or lost
+        // HTLCs, so it is handed every HTLC it has not resolved yet.
+        if params
+            .get("resubscribe")
+            .and_then(Value::as_bool)
+            .unwrap_or_default()
+        {
+            state.requeue_handed_out();
+        }
+
+        state.polling += 1;
+
+        drop(state);
+
+        let htlcs = loop {
+            // Register for notifications before checking the queue, such that
+            // an HTLC arriving in between wakes us up
+            let notified = self.htlc_notify.notified();
+
+            let mut state = self.state.lock().await;
+
+            if !state.queue.is_empty() || Instant::now() >= deadline {
+                let htlcs = state.queue.drain(..).collect::<Vec<_>>();
+
+                for htlc in &htlcs {
+                    if let Some(held) = state.held.get_mut(&(htlc.incoming_chan_id, htlc.htlc_id)) {
+                        held.handed_out = true;
+                    }
+                }
+
+                state.polling -= 1;
+                state.last_poll = Some(Instant::now());
+
+                break htlcs;
+            }
+
+            drop(state);
+
+            let _ = tokio::time::timeout_at(deadline.into(), notified).await;
+        };
+
+        self.respond(id, json!({ "htlcs": htlcs }));
+    }
+
+    async fn handle_complete_htlc(&self, id: Value, params: Value) {
+        let response = match serde_json::from_value::<InterceptPaymentResponse>(params) {
+            Ok(response) => response,
+            Err(e) => {
+                self.respond_error(id, format!("Invalid HTLC completion: {e}"));
+                return;
+            }
+        };
+
+        let held = self
+            .state
+            .lock()
+            .await
+            .held
+            .remove(&(response.incoming_chan_id, response.htlc_id));
+
+        let Some(held) = held else {
+            self.respond_error(
+                id,
+                format!(
+                    "No HTLC {} held on channel {}",
+                    response.htlc_id, response.incoming_chan_id
+                ),
+            );
+            return;
+        };
+
+        let hook_result = match response.action {
+            PaymentAction::Settle(preimage) => json!({
+                "result": "resolve",
+                "payment_key": hex::encode(preimage.0),
+            }),
+            PaymentAction::Cancel => json!({
+                "result": "fail",
+                "failure_message": TEMPORARY_NODE_FAILURE,
+            }),
+            PaymentAction::Forward => json!({ "result": "continue" }),
+        };
+
+        self.respond(held.hook_request_id, hook_result);
+        self.respond(id, json!({}));
+    }
+
+    async fn handle_block_added(&self, params: &Value) {
+        // Older versions of `lightningd` name the block `block`
+        let Some(block_height) = params
+            .get("block_added")
+            .or_else(|| params.get("block"))
+            .and_then(|block| block.get("height"))
+            .and_then(Value::as_u64)
+            .and_then(|height| u32::try_from(height).ok())
+        else {
+            return;
+        };
+
+        let mut state = self.state.lock().await;
+
+        state.block_height = state.block_height.max(Some(block_height));
+    }
+
+    /// Fails all held HTLCs that are close to their CLTV expiry, whether or
+    /// not they have been handed to the gateway, since the channel they
+    /// arrived on is force-closed if they expire unresolved.
+    async fn fail_expiring_htlcs(&self) {
+        let mut state = self.state.lock().await;
+
+        let expiring = state
+            .held
+            .iter()
+            .filter(|(_, held)| state.is_close_to_expiry(held.htlc.expiry))
+            .map(|(key, _)| *key)
+            .collect::<Vec<_>>();
+
+        if expiring.is_empty() {
+            return;
+        }
+
+        let expiring = expiring
+            .into_iter()
+            .filter_map(|key| state.held.remove(&key))
+            .collect::<Vec<_>>();
+
+        let PluginState { queue, held, .. } = &mut *state;
+
+        queue.retain(|htlc| held.contains_key(&(htlc.incoming_chan_id, htlc.htlc_id)));
+
+        drop(state);
+
+        self.log(
+            "unusual",
+            format!(
+                "Failing {} held HTLCs close to their CLTV expiry",
+                expiring.len()
+            ),
+        );
+
+        for held in expiring {
+            self.respond(
+                held.hook_request_id,
+                json!({
+                    "result": "fail",
+                    "failure_message": TEMPORARY_NODE_FAILURE,
+                }),
+            );
+        }
+    }
+
+    /// Hands all held HTLCs back to `lightningd` once the gateway stopped
+    /// polling, since it will not resolve them anymore.
+    async fn release_abandoned_htlcs(&self) {
+        let mut state = self.state.lock().await;
+
+        if state.held.is_empty() || state.is_gateway_connected() {
+            return;
+        }
+
+        let held = std::mem::take(&mut state.held);
+        state.queue.clear();
+        drop(state);
+
+        self.log(
+            "unusual",
+            format!(
+                "Gateway stopped polling, continuing {} held HTLCs ({} handed out)",
+                held.len(),
+                held.values().filter(|held| held.handed_out).count()
+            ),
+        );
+
+        for held in held.into_values() {
+            self.respond(held.hook_request_id, json!({ "result": "continue" }));
+        }
+    }
+}
+
+fn manifest() -> Value {
+    json!({
+        "options": [],
+        "rpcmethods": [
+            {
+                "name": CLN_PLUGIN_NEXT_HTLCS_METHOD,
+                "usage": "[timeout_secs] [resubscribe]",
+                "description": "Returns the HTLCs held for the Fedimint gateway, waiting up to timeout_secs for one to arrive. With resubscribe, HTLCs returned before but not resolved yet are returned again",
+            },
+            {
+                "name": CLN_PLUGIN_COMPLETE_HTLC_METHOD,
+                "usage": "incoming_chan_id htlc_id payment_hash action",
+                "description": "Settles, cancels or forwards an HTLC held for the Fedimint gateway",
+            },
+        ],
+        "hooks": [{ "name": "htlc_accepted" }],
+        "subscriptions": ["block_added"],
+        "dynamic": false,
+    })
+}
+
+/// Converts the parameters of the `htlc_accepted` hook into the request the
+/// gateway expects from every lightning backend.
+fn intercept_payment_request(params: &Value) -> Option<InterceptPaymentRequest> {
+    let htlc = params.get("htlc")?;
+    let onion = params.get("onion")?;
+
+    let amount_msat = onion
+        .get("forward_msat")
+        .or_else(|| htlc.get("amount_msat"))?
+        .as_u64()?;
+
+    Some(InterceptPaymentRequest {
+        payment_hash: htlc.get("payment_hash")?.as_str()?.parse().ok()?,
+        amount_msat,
+        expiry: u32::try_from(htlc.get("cltv_expiry")?.as_u64()?).ok()?,
+        incoming_chan_id: parse_short_channel_id(htlc.get("short_channel_id")?.as_str()?)?,
+        // Only present if we are not the final hop of the payment
+        short_channel_id: onion
+            .get("short_channel_id")
+            .and_then(Value::as_str)
+            .and_then(parse_short_channel_id),
+        htlc_id: htlc.get("id")?.as_u64()?,
+    })
+}
+
+async fn write_output(mut output: mpsc::UnboundedReceiver<Value>) -> anyhow::Result<()> {
+    let mut stdout = tokio::io::stdout();
+
+    while let Some(message) = output.recv().await {
+        let mut bytes = serde_json::to_vec(&message)?;
+        bytes.extend_from_slice(b"\n\n");
+
+        stdout.write_all(&bytes).await?;
+        stdout.flush().await?;
+    }
+
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let (output_sender, output_receiver) = mpsc::unbounded_channel();
+
+    let plugin = Plugin {
+        state: Arc::new(Mutex::new(PluginState::default())),
+        htlc_notify: Arc::new(Notify::new()),
+        output: output_sender,
+    };
+
+    tokio::spawn(async move {
+        if let Err(e) = write_output(output_receiver).await {
+            eprintln!("fedimint-cln-plugin failed to write to lightningd: {e:?}");
+            std::process::exit(1);
+        }
+    });
+
+    let sweeper = plugin.clone();
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(Duration::from_secs(10)).await;
+            sweeper.fail_expiring_htlcs().await;
+            sweeper.release_abandoned_htlcs().await;
+        }
+    });
+
+    let mut stdin = tokio::io::stdin();
+    let mut buffer = Vec::new();
+
+    loop {
+        if stdin
+            .read_buf(&mut buffer)
+            .await
+            .context("Failed to read from lightningd")?
+            == 0
+        {
+            // lightningd exited
+            return Ok(());
+        }
+
+        let mut messages = serde_json::Deserializer::from_slice(&buffer).into_iter::<Value>();
+        let mut parsed = Vec::new();
+
+        loop {
+            match messages.next() {
+                Some(Ok(message)) => parsed.push(message),
+                Some(Err(e)) if e.is_eof() => break,
+                Some(Err(e)) => return Err(e).context("Received invalid JSON from lightningd"),
+                None => break,
+            }
+        }
+
+        let consumed = messages.byte_offset();
+        buffer.drain(..consumed);
+
+        for message in parsed {
+            plugin.handle_message(message).await;
+        }
+    }
+}
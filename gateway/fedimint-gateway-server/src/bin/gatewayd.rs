@@ -10,9 +10,11 @@
 
 use std::sync::Arc;
 
+use clap::Parser as _;
 use fedimint_core::fedimint_build_code_version_env;
 use fedimint_core::util::handle_version_hash_command;
 use fedimint_gateway_server::Gateway;
+use fedimint_gateway_server::config::{RESTORE_COMMAND, RestoreOpts};
 use fedimint_logging::{LOG_GATEWAY, TracingSetup};
 #[cfg(not(any(target_env = "msvc", target_os = "ios", target_os = "android")))]
 use tikv_jemallocator::Jemalloc;
@@ -28,6 +30,15 @@ fn main() -> Result<(), anyhow::Error> {
     runtime.block_on(async {
         handle_version_hash_command(fedimint_build_code_version_env!());
         TracingSetup::default().init()?;
+
+        // `gatewayd restore` rebuilds the data directory from a disaster recovery
+        // bundle instead of starting the gateway
+        if std::env::args().nth(1).as_deref() == Some(RESTORE_COMMAND) {
+            return RestoreOpts::parse_from(std::env::args().skip(1))
+                .run()
+                .await;
+        }
+
         let (mnemonic_sender, mnemonic_receiver) = tokio::sync::broadcast::channel::<()>(4);
         let gatewayd = Gateway::new_with_default_modules(mnemonic_sender).await?;
         let shutdown_receiver = gatewayd
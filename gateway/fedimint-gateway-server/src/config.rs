@@ -1,6 +1,7 @@
 use std::net::SocketAddr;
 use std::path::PathBuf;
 use std::str::FromStr;
+use std::time::Duration;
 
 use anyhow::ensure;
 use bitcoin::Network;
@@ -12,10 +13,12 @@
 use fedimint_lnv2_common::gateway_api::PaymentFee;
 
 use super::envs;
+use crate::dr_backup::{BundleDestination, DisasterRecoveryParameters};
 use crate::envs::{
     FM_BITCOIND_PASSWORD_ENV, FM_BITCOIND_URL_ENV, FM_BITCOIND_USERNAME_ENV, FM_ESPLORA_URL_ENV,
     FM_GATEWAY_METRICS_LISTEN_ADDR_ENV, FM_GATEWAY_SKIP_SETUP_ENV,
 };
+use crate::high_availability::{HighAvailabilityParameters, LEADER_LEASE_FILE};
 
 #[derive(Debug, Clone, Copy, clap::ValueEnum)]
 pub enum DatabaseBackend {
@@ -142,6 +145,17 @@ pub struct GatewayOpts {
     )]
     skip_setup: bool,
 
+    /// Allow LNv2 clients to send keysend payments through the gateway. Since
+    /// the client hands the preimage to the gateway, only clients that trust
+    /// the gateway with the amount of their payments will use this.
+    #[arg(
+        long = "enable-keysend",
+        env = envs::FM_GATEWAY_ENABLE_KEYSEND_ENV,
+        default_value_t = false,
+        value_parser = BoolishValueParser::new()
+    )]
+    enable_keysend: bool,
+
     /// Maximum burst of requests to the public invoice creation endpoint
     /// accepted before rate limiting kicks in
     #[arg(
@@ -161,6 +175,63 @@ pub struct GatewayOpts {
         value_parser = clap::value_parser!(u32).range(1..)
     )]
     invoice_rate_limit_per_second: u32,
+
+    /// Identifies this instance of an active/standby pair that shares the
+    /// gateway's database. Setting it enables high availability mode, in
+    /// which the gateway waits for the leader lease before it starts.
+    #[arg(long = "ha-instance-id", env = envs::FM_GATEWAY_HA_INSTANCE_ID_ENV)]
+    ha_instance_id: Option<String>,
+
+    /// Path of the leader lease on storage shared by both instances. Defaults
+    /// to a file in the data directory.
+    #[arg(long = "ha-lease-path", env = envs::FM_GATEWAY_HA_LEASE_PATH_ENV)]
+    ha_lease_path: Option<PathBuf>,
+
+    /// Seconds the leader lease lasts without being renewed, which bounds the
+    /// time until the standby takes over
+    #[arg(
+        long = "ha-lease-secs",
+        env = envs::FM_GATEWAY_HA_LEASE_SECS_ENV,
+        default_value_t = super::DEFAULT_HA_LEASE_SECS,
+        value_parser = clap::value_parser!(u64).range(3..)
+    )]
+    ha_lease_secs: u64,
+
+    /// Directory that encrypted disaster recovery bundles are exported to,
+    /// typically on a mounted off-site volume
+    #[arg(
+        long = "dr-backup-dir",
+        env = envs::FM_GATEWAY_DR_BACKUP_DIR_ENV,
+        conflicts_with = "dr_backup_url"
+    )]
+    dr_backup_dir: Option<PathBuf>,
+
+    /// Base URL that encrypted disaster recovery bundles are uploaded to with
+    /// HTTP PUT, such as a bucket of an S3-compatible object store
+    #[arg(long = "dr-backup-url", env = envs::FM_GATEWAY_DR_BACKUP_URL_ENV)]
+    dr_backup_url: Option<SafeUrl>,
+
+    /// Password the disaster recovery bundles are encrypted with
+    #[arg(long = "dr-backup-password", env = envs::FM_GATEWAY_DR_BACKUP_PASSWORD_ENV)]
+    dr_backup_password: Option<String>,
+
+    /// Seconds between two exports of a disaster recovery bundle
+    #[arg(
+        long = "dr-backup-interval-secs",
+        env = envs::FM_GATEWAY_DR_BACKUP_INTERVAL_SECS_ENV,
+        default_value_t = super::DEFAULT_DR_BACKUP_INTERVAL_SECS,
+        value_parser = clap::value_parser!(u64).range(60..)
+    )]
+    dr_backup_interval_secs: u64,
+
+    /// Number of disaster recovery bundles kept in the backup directory
+    #[arg(
+        long = "dr-backup-retention",
+        env = envs::FM_GATEWAY_DR_BACKUP_RETENTION_ENV,
+        default_value_t = super::DEFAULT_DR_BACKUP_RETENTION,
+        value_parser = clap::value_parser!(u32).range(1..)
+    )]
+    dr_backup_retention: u32,
 }
 
 impl GatewayOpts {
@@ -205,6 +276,48 @@ pub fn to_gateway_parameters(&self) -> anyhow::Result<GatewayParameters> {
             PaymentFee::RECEIVE_FEE_LIMIT
         );
 
+        // The standby cannot take over an embedded node, which only ever runs in
+        // the process of the leader
+        ensure!(
+            self.ha_instance_id.is_none() || !matches!(self.mode, LightningMode::Ldk { .. }),
+            "High availability mode requires an external lightning node"
+        );
+        let high_availability =
+            self.ha_instance_id
+                .clone()
+                .map(|instance_id| HighAvailabilityParameters {
+                    instance_id,
+                    lease_path: self
+                        .ha_lease_path
+                        .clone()
+                        .unwrap_or_else(|| self.data_dir.join(LEADER_LEASE_FILE)),
+                    lease_duration: Duration::from_secs(self.ha_lease_secs),
+                });
+
+        let destination = match (&self.dr_backup_dir, &self.dr_backup_url) {
+            (Some(dir), _) => Some(BundleDestination::Directory(dir.clone())),
+            (None, Some(url)) => Some(BundleDestination::Upload(url.clone())),
+            (None, None) => None,
+        };
+        let disaster_recovery = match (destination, &self.dr_backup_password) {
+            (Some(destination), Some(password)) => {
+                ensure!(
+                    !password.is_empty(),
+                    "Disaster recovery backup password must not be empty"
+                );
+                Some(DisasterRecoveryParameters {
+                    destination,
+                    password: password.clone(),
+                    interval: Duration::from_secs(self.dr_backup_interval_secs),
+                    retention: self.dr_backup_retention as usize,
+                })
+            }
+            (Some(_), None) => {
+                anyhow::bail!("Disaster recovery backups require a password to encrypt them with")
+            }
+            (None, _) => None,
+        };
+
         // Default metrics listen to localhost on UI port + 1
         let metrics_listen = self.metrics_listen.unwrap_or_else(|| {
             SocketAddr::new(
@@ -226,9 +339,12 @@ pub fn to_gateway_parameters(&self) -> anyhow::Result<GatewayParameters> {
             iroh_dns: self.iroh_dns.clone(),
             iroh_relays: self.iroh_relays.clone(),
             skip_setup: self.skip_setup,
+            enable_keysend: self.enable_keysend,
             metrics_listen,
             invoice_rate_limit_burst: self.invoice_rate_limit_burst,
             invoice_rate_limit_per_second: self.invoice_rate_limit_per_second,
+            high_availability,
+            disaster_recovery,
         })
     }
 }
@@ -253,7 +369,45 @@ pub struct GatewayParameters {
     pub iroh_dns: Option<SafeUrl>,
     pub iroh_relays: Vec<SafeUrl>,
     pub skip_setup: bool,
+    pub enable_keysend: bool,
     pub metrics_listen: SocketAddr,
     pub invoice_rate_limit_burst: u32,
     pub invoice_rate_limit_per_second: u32,
+    pub high_availability: Option<HighAvailabilityParameters>,
+    pub disaster_recovery: Option<DisasterRecoveryParameters>,
+}
+
+/// Name of the `gatewayd` subcommand that restores a disaster recovery bundle.
+/// It is handled before [`GatewayOpts`] are parsed, since those require a
+/// lightning mode.
+pub const RESTORE_COMMAND: &str = "restore";
+
+/// Command line parameters of `gatewayd restore`, which rebuilds the data
+/// directory of a gateway from an encrypted disaster recovery bundle.
+#[derive(Parser)]
+#[command(name = "gatewayd restore")]
+pub struct RestoreOpts {
+    /// Path of the bundle to restore
+    #[arg(long = "bundle")]
+    pub bundle: PathBuf,
+
+    /// Path to the empty folder the gateway's config and data files are
+    /// restored to
+    #[arg(long = "data-dir", env = envs::FM_GATEWAY_DATA_DIR_ENV)]
+    pub data_dir: PathBuf,
+
+    /// Password the bundle was encrypted with
+    #[arg(long = "password", env = envs::FM_GATEWAY_DR_BACKUP_PASSWORD_ENV)]
+    pub password: String,
+
+    /// Database backend to restore into.
+    #[arg(long, env = envs::FM_DB_BACKEND_ENV, value_enum, default_value = "rocksdb")]
+    pub db_backend: DatabaseBackend,
+}
+
+impl RestoreOpts {
+    /// Restores the bundle into the data directory.
+    pub async fn run(&self) -> anyhow::Result<()> {
+        crate::dr_backup::run_restore(self).await
+    }
 }
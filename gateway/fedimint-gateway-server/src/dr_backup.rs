@@ -0,0 +1,554 @@
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use std::str::FromStr as _;
+use std::time::Duration;
+
+use anyhow::{Context as _, bail, ensure};
+use bitcoin::secp256k1::PublicKey;
+use bitcoin::{Network, OutPoint};
+use fedimint_core::config::FederationId;
+use fedimint_core::db::{Database, IDatabaseTransactionOpsCore as _};
+use fedimint_core::time::duration_since_epoch;
+use fedimint_core::util::{FmtCompactAnyhow as _, SafeUrl};
+use fedimint_gateway_common::{ChannelInfo, ConnectPeerRequest, LightningMode, NodeAddress};
+use fedimint_gateway_server_db::{GatewayDbtxNcExt as _, is_client_database_key};
+use fedimint_logging::LOG_GATEWAY;
+use futures::StreamExt as _;
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::config::RestoreOpts;
+use crate::{DB_FILE, Gateway, LDK_NODE_DB_FOLDER, open_gateway_db};
+
+/// Version of the bundle format. Bundles of a different version are rejected
+/// instead of being restored partially. Version 1 bundles included the files
+/// of the LDK node, version 2 bundles the databases of the federation clients.
+pub const DR_BUNDLE_VERSION: u16 = 3;
+
+/// Interval in which a restored gateway connects to the peers of its former
+/// channels until it reached all of them.
+const RESTORED_PEER_RETRY_INTERVAL: Duration = Duration::from_mins(1);
+
+/// Prefix of the file names of exported bundles, followed by the creation time
+/// in seconds since the epoch.
+const DR_BUNDLE_FILE_PREFIX: &str = "gatewayd-dr-";
+
+const DR_BUNDLE_FILE_EXTENSION: &str = "bundle";
+
+/// Where the gateway exports its disaster recovery bundles to.
+#[derive(Debug, Clone)]
+pub enum BundleDestination {
+    /// A directory, typically on a mounted off-site volume, in which only the
+    /// most recent bundles are kept.
+    Directory(PathBuf),
+    /// A base URL that bundles are uploaded to with HTTP `PUT`, such as a
+    /// bucket of an S3-compatible object store. Retention is left to the
+    /// store's lifecycle rules.
+    Upload(SafeUrl),
+}
+
+/// Parameters of the scheduled export of disaster recovery bundles.
+#[derive(Clone)]
+pub struct DisasterRecoveryParameters {
+    pub destination: BundleDestination,
+    /// Password the bundles are encrypted with.
+    pub password: String,
+    /// Time between two exports.
+    pub interval: Duration,
+    /// Number of bundles kept in a directory destination.
+    pub retention: usize,
+}
+
+impl std::fmt::Debug for DisasterRecoveryParameters {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DisasterRecoveryParameters")
+            .field("destination", &self.destination)
+            .field("interval", &self.interval)
+            .field("retention", &self.retention)
+            .finish_non_exhaustive()
+    }
+}
+
+/// A record of the gateway's database with its key and value hex encoded.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct DatabaseEntry {
+    key: String,
+    value: String,
+}
+
+/// A channel of the embedded LDK node as of the creation of a bundle. Like a
+/// static channel backup it cannot resume the channel, but it names the peer
+/// that has to force close it and the funding output to watch.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChannelBackup {
+    pub remote_pubkey: PublicKey,
+    pub remote_address: Option<String>,
+    pub funding_outpoint: Option<OutPoint>,
+    pub channel_size_sats: u64,
+}
+
+impl From<ChannelInfo> for ChannelBackup {
+    fn from(channel: ChannelInfo) -> Self {
+        Self {
+            remote_pubkey: channel.remote_pubkey,
+            remote_address: channel.remote_address,
+            funding_outpoint: channel.funding_outpoint,
+            channel_size_sats: channel.channel_size_sats,
+        }
+    }
+}
+
+/// Everything needed to rebuild the gateway on a new machine: its database,
+/// which holds the mnemonic, the federation configs, fees and registrations,
+/// and a static backup of the channels of an embedded LDK node. Only ever
+/// stored encrypted.
+///
+/// The state that is outdated as soon as the gateway makes its next payment
+/// is left out on purpose. The databases of the federation clients are
+/// recovered from the mnemonic instead, since a client restored from an
+/// outdated database reuses the nonces of notes it issued since. The channel
+/// monitors of the LDK node are left out, since a node that is started with
+/// outdated channel monitors broadcasts revoked commitment transactions, for
+/// which its peers claim all funds of the channel. The restored node connects
+/// to the peers of its former channels instead, which force close them, and
+/// recovers its onchain wallet from the mnemonic.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct DisasterRecoveryBundle {
+    network: Network,
+    created_at_secs: u64,
+    federations: BTreeSet<FederationId>,
+    database: Vec<DatabaseEntry>,
+    channels: Vec<ChannelBackup>,
+}
+
+/// A bundle as it is written to the destination. The version and creation
+/// time are kept in the clear, such that bundles can be told apart without
+/// the password.
+#[derive(Debug, Serialize, Deserialize)]
+struct EncryptedBundle {
+    version: u16,
+    created_at_secs: u64,
+    salt: String,
+    ciphertext: String,
+}
+
+/// Encrypts `bundle` with a key derived from `password` and serializes it.
+fn seal_bundle(bundle: &DisasterRecoveryBundle, password: &str) -> anyhow::Result<Vec<u8>> {
+    let salt = fedimint_aead::random_salt();
+    let key = fedimint_aead::get_encryption_key(password, &salt)?;
+    let ciphertext = fedimint_aead::encrypt(serde_json::to_vec(bundle)?, &key)?;
+
+    Ok(serde_json::to_vec(&EncryptedBundle {
+        version: DR_BUNDLE_VERSION,
+        created_at_secs: bundle.created_at_secs,
+        salt,
+        ciphertext: hex::encode(ciphertext),
+    })?)
+}
+
+/// Decrypts a bundle serialized by [`seal_bundle`].
+fn open_bundle(bytes: &[u8], password: &str) -> anyhow::Result<DisasterRecoveryBundle> {
+    let encrypted: EncryptedBundle =
+        serde_json::from_slice(bytes).context("File is not a disaster recovery bundle")?;
+
+    ensure!(
+        encrypted.version == DR_BUNDLE_VERSION,
+        "Bundle has version {} but this gatewayd only restores version {DR_BUNDLE_VERSION}",
+        encrypted.version
+    );
+
+    let key = fedimint_aead::get_encryption_key(password, &encrypted.salt)?;
+    let mut ciphertext = hex::decode(&encrypted.ciphertext).context("Bundle is corrupted")?;
+    let plaintext = fedimint_aead::decrypt(&mut ciphertext, &key)
+        .context("Failed to decrypt the bundle, the password is wrong or it was tampered with")?;
+
+    serde_json::from_slice(plaintext).context("Bundle is corrupted")
+}
+
+fn bundle_file_name(created_at_secs: u64) -> String {
+    format!("{DR_BUNDLE_FILE_PREFIX}{created_at_secs}.{DR_BUNDLE_FILE_EXTENSION}")
+}
+
+/// Returns the creation time of a bundle from its file name, or `None` if the
+/// file is not a bundle.
+fn bundle_created_at_secs(file_name: &str) -> Option<u64> {
+    file_name
+        .strip_prefix(DR_BUNDLE_FILE_PREFIX)?
+        .strip_suffix(DR_BUNDLE_FILE_EXTENSION)?
+        .strip_suffix('.')?
+        .parse()
+        .ok()
+}
+
+/// Reads all records of `db` but the databases of the federation clients.
+async fn read_database(db: &Database) -> anyhow::Result<Vec<DatabaseEntry>> {
+    let mut dbtx = db.begin_transaction_nc().await;
+
+    let entries = dbtx
+        .raw_find_by_prefix(&[])
+        .await?
+        .filter(|(key, _)| std::future::ready(!is_client_database_key(key)))
+        .map(|(key, value)| DatabaseEntry {
+            key: hex::encode(key),
+            value: hex::encode(value),
+        })
+        .collect::<Vec<_>>()
+        .await;
+
+    Ok(entries)
+}
+
+/// Writes the records of `bundle` into `db`, which has to be empty such that a
+/// restore never mixes the state of two gateways. The clients of the bundle's
+/// federations are marked to be recovered from the mnemonic, and the peers of
+/// its channels to be connected to.
+async fn write_database(db: &Database, bundle: &DisasterRecoveryBundle) -> anyhow::Result<()> {
+    let mut dbtx = db.begin_transaction().await;
+
+    ensure!(
+        dbtx.raw_find_by_prefix(&[]).await?.next().await.is_none(),
+        "Refusing to restore into a database that is not empty"
+    );
+
+    for entry in &bundle.database {
+        let key = hex::decode(&entry.key).context("Bundle is corrupted")?;
+        let value = hex::decode(&entry.value).context("Bundle is corrupted")?;
+
+        ensure!(!is_client_database_key(&key), "Bundle is corrupted");
+
+        dbtx.raw_insert_bytes(&key, &value).await?;
+    }
+
+    for federation_id in &bundle.federations {
+        dbtx.save_pending_client_recovery(*federation_id).await;
+    }
+
+    for channel in &bundle.channels {
+        if let Some(address) = &channel.remote_address {
+            dbtx.save_restored_channel_peer(channel.remote_pubkey, address)
+                .await;
+        }
+    }
+
+    dbtx.commit_tx_result().await?;
+
+    Ok(())
+}
+
+/// Returns whether `dir` is missing or empty.
+fn is_empty_dir(dir: &Path) -> anyhow::Result<bool> {
+    if !dir.exists() {
+        return Ok(true);
+    }
+
+    Ok(std::fs::read_dir(dir)?.next().is_none())
+}
+
+/// Deletes all but the `retention` most recent bundles in `dir`.
+fn prune_bundles(dir: &Path, retention: usize) -> anyhow::Result<()> {
+    let mut bundles = std::fs::read_dir(dir)?
+        .filter_map(|entry| {
+            let entry = entry.ok()?;
+            let created_at_secs = bundle_created_at_secs(entry.file_name().to_str()?)?;
+            Some((created_at_secs, entry.path()))
+        })
+        .collect::<Vec<_>>();
+
+    bundles.sort_unstable_by(|a, b| b.cmp(a));
+
+    for (_, path) in bundles.into_iter().skip(retention) {
+        std::fs::remove_file(path)?;
+    }
+
+    Ok(())
+}
+
+/// Writes a sealed bundle to `destination`.
+async fn export_bundle(
+    destination: &BundleDestination,
+    file_name: &str,
+    sealed: Vec<u8>,
+) -> anyhow::Result<()> {
+    match destination {
+        BundleDestination::Directory(dir) => {
+            std::fs::create_dir_all(dir)?;
+
+            // A bundle only appears under its final name once it is complete
+            let tmp_path = dir.join(format!("{file_name}.tmp"));
+            std::fs::write(&tmp_path, sealed)?;
+            std::fs::rename(&tmp_path, dir.join(file_name))?;
+        }
+        BundleDestination::Upload(base_url) => {
+            reqwest::Client::new()
+                .put(base_url.join_path(file_name).to_unsafe())
+                .body(sealed)
+                .send()
+                .await?
+                .error_for_status()?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Summary of a restored bundle.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RestoreSummary {
+    pub network: Network,
+    pub created_at_secs: u64,
+    pub federations: BTreeSet<FederationId>,
+    pub database_entries: usize,
+    pub channels: Vec<ChannelBackup>,
+}
+
+/// Rebuilds the gateway's data directory from a bundle. The directory must
+/// not contain a gateway database or LDK node yet, such that the restored
+/// mnemonic never meets the channel state of another node.
+pub async fn restore_bundle(opts: &RestoreOpts) -> anyhow::Result<RestoreSummary> {
+    let bundle = open_bundle(
+        &std::fs::read(&opts.bundle).context("Failed to read the bundle")?,
+        &opts.password,
+    )?;
+
+    let db_path = opts.data_dir.join(DB_FILE);
+    if db_path.exists() {
+        bail!(
+            "Refusing to restore over the existing gateway database at {}",
+            db_path.display()
+        );
+    }
+
+    // Checked before the database is created, such that a failed restore can
+    // be retried
+    let node_dir = opts.data_dir.join(LDK_NODE_DB_FOLDER);
+    ensure!(
+        is_empty_dir(&node_dir)?,
+        "Refusing to restore into {} since it is not empty",
+        node_dir.display()
+    );
+
+    restore_bundle_into(bundle, &open_gateway_db(db_path, opts.db_backend).await?).await
+}
+
+async fn restore_bundle_into(
+    bundle: DisasterRecoveryBundle,
+    db: &Database,
+) -> anyhow::Result<RestoreSummary> {
+    write_database(db, &bundle).await?;
+
+    Ok(RestoreSummary {
+        network: bundle.network,
+        created_at_secs: bundle.created_at_secs,
+        federations: bundle.federations,
+        database_entries: bundle.database.len(),
+        channels: bundle.channels,
+    })
+}
+
+impl Gateway {
+    /// Collects the gateway's state into a bundle.
+    async fn create_dr_bundle(&self) -> anyhow::Result<DisasterRecoveryBundle> {
+        let federations = self
+            .gateway_db
+            .begin_transaction_nc()
+            .await
+            .load_federation_configs()
+            .await
+            .into_keys()
+            .collect::<BTreeSet<_>>();
+
+        // Federations joined before v0.5.0 did not derive their client secret
+        // from the mnemonic, so their clients cannot be recovered
+        let legacy_federations = self.client_builder.legacy_federations(federations.clone());
+        if !legacy_federations.is_empty() {
+            warn!(
+                target: LOG_GATEWAY,
+                ?legacy_federations,
+                "The ecash of legacy federations cannot be recovered from a disaster recovery bundle"
+            );
+        }
+
+        Ok(DisasterRecoveryBundle {
+            network: self.network,
+            created_at_secs: duration_since_epoch().as_secs(),
+            federations,
+            database: read_database(&self.gateway_db).await?,
+            channels: self
+                .channel_backups()
+                .await
+                .context("Failed to list the channels of the lightning node")?,
+        })
+    }
+
+    /// Returns the static backups of the channels of an embedded LDK node.
+    /// The channels of an external node are backed up by the node itself.
+    async fn channel_backups(&self) -> anyhow::Result<Vec<ChannelBackup>> {
+        if !matches!(self.lightning_mode, LightningMode::Ldk { .. }) {
+            return Ok(Vec::new());
+        }
+
+        let channels = self
+            .get_lightning_context()
+            .await?
+            .lnrpc
+            .list_channels()
+            .await?
+            .channels;
+
+        Ok(channels.into_iter().map(ChannelBackup::from).collect())
+    }
+
+    /// Spawns a background task that connects a restored lightning node to
+    /// the peers of the channels it had before, such that the peers force
+    /// close the channels the node no longer knows.
+    pub(crate) fn spawn_restored_channel_peers_task(&self) {
+        let self_copy = self.clone();
+        self.task_group
+            .spawn_cancellable_silent("connect to restored channel peers", async move {
+                let mut interval = tokio::time::interval(RESTORED_PEER_RETRY_INTERVAL);
+                loop {
+                    interval.tick().await;
+
+                    let peers = self_copy
+                        .gateway_db
+                        .begin_transaction_nc()
+                        .await
+                        .load_restored_channel_peers()
+                        .await;
+
+                    if peers.is_empty() {
+                        return;
+                    }
+
+                    let Ok(context) = self_copy.get_lightning_context().await else {
+                        continue;
+                    };
+
+                    for (pubkey, address) in peers {
+                        let node_address = match NodeAddress::from_str(&format!("{pubkey}@{address}")) {
+                            Ok(node_address) => node_address,
+                            Err(err) => {
+                                warn!(
+                                    target: LOG_GATEWAY,
+                                    %pubkey,
+                                    %address,
+                                    %err,
+                                    "Cannot connect to the peer of a restored channel, it has to force close the channel"
+                                );
+                                self_copy.remove_restored_channel_peer(pubkey).await;
+                                continue;
+                            }
+                        };
+
+                        match context
+                            .lnrpc
+                            .connect_peer(ConnectPeerRequest { node_address })
+                            .await
+                        {
+                            Ok(()) => {
+                                info!(
+                                    target: LOG_GATEWAY,
+                                    %pubkey,
+                                    "Connected to the peer of a restored channel"
+                                );
+                                self_copy.remove_restored_channel_peer(pubkey).await;
+                            }
+                            Err(err) => {
+                                warn!(
+                                    target: LOG_GATEWAY,
+                                    %pubkey,
+                                    %address,
+                                    %err,
+                                    "Failed to connect to the peer of a restored channel, retrying later"
+                                );
+                            }
+                        }
+                    }
+                }
+            });
+    }
+
+    async fn remove_restored_channel_peer(&self, pubkey: PublicKey) {
+        let mut dbtx = self.gateway_db.begin_transaction().await;
+        dbtx.remove_restored_channel_peer(pubkey).await;
+        dbtx.commit_tx().await;
+    }
+
+    /// Creates, encrypts and exports a bundle, as the scheduled export does.
+    pub async fn export_dr_bundle(
+        &self,
+        params: &DisasterRecoveryParameters,
+    ) -> anyhow::Result<()> {
+        let bundle = self.create_dr_bundle().await?;
+        let file_name = bundle_file_name(bundle.created_at_secs);
+        let sealed = seal_bundle(&bundle, &params.password)?;
+
+        export_bundle(&params.destination, &file_name, sealed).await?;
+
+        if let BundleDestination::Directory(dir) = &params.destination {
+            prune_bundles(dir, params.retention)?;
+        }
+
+        info!(
+            target: LOG_GATEWAY,
+            %file_name,
+            federations = bundle.federations.len(),
+            "Exported disaster recovery bundle"
+        );
+
+        Ok(())
+    }
+
+    /// Spawns a background task that exports a disaster recovery bundle on
+    /// the configured schedule.
+    pub(crate) fn spawn_dr_backup_task(&self) {
+        let Some(params) = self.disaster_recovery.clone() else {
+            return;
+        };
+
+        let self_copy = self.clone();
+        self.task_group
+            .spawn_cancellable_silent("export disaster recovery bundle", async move {
+                let mut interval = tokio::time::interval(params.interval);
+                loop {
+                    interval.tick().await;
+
+                    if let Err(err) = self_copy.export_dr_bundle(&params).await {
+                        warn!(
+                            target: LOG_GATEWAY,
+                            err = %err.fmt_compact_anyhow(),
+                            "Failed to export disaster recovery bundle"
+                        );
+                    }
+                }
+            });
+    }
+}
+
+/// Restores a bundle for `gatewayd restore`.
+pub(crate) async fn run_restore(opts: &RestoreOpts) -> anyhow::Result<()> {
+    let summary = restore_bundle(opts).await?;
+
+    info!(
+        target: LOG_GATEWAY,
+        network = %summary.network,
+        created_at_secs = summary.created_at_secs,
+        federations = ?summary.federations,
+        database_entries = summary.database_entries,
+        data_dir = %opts.data_dir.display(),
+        "Restored gateway from disaster recovery bundle, start gatewayd on the same network to resume. It recovers the ecash of all federations from the mnemonic on its first start."
+    );
+
+    if !summary.channels.is_empty() {
+        warn!(
+            target: LOG_GATEWAY,
+            channels = ?summary.channels,
+            "The channels of the embedded LDK node are not restored. The restored node connects to their peers, which force close them, peers without an address have to be asked to force close."
+        );
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,272 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use bitcoin::Network;
+use bitcoin::secp256k1::{PublicKey, Secp256k1, SecretKey};
+use fedimint_core::config::FederationId;
+use fedimint_core::db::mem_impl::MemDatabase;
+use fedimint_core::db::{Database, IDatabaseTransactionOpsCore as _};
+use fedimint_core::module::registry::ModuleDecoderRegistry;
+use fedimint_gateway_common::RegisteredProtocol;
+use fedimint_gateway_server_db::GatewayDbtxNcExt as _;
+
+use super::{
+    ChannelBackup, DR_BUNDLE_VERSION, DatabaseEntry, DisasterRecoveryBundle, EncryptedBundle,
+    bundle_file_name, is_empty_dir, open_bundle, prune_bundles, read_database, restore_bundle_into,
+    seal_bundle,
+};
+
+const PASSWORD: &str = "correct horse battery staple";
+
+const PEER_ADDRESS: &str = "127.0.0.1:9735";
+
+fn peer() -> PublicKey {
+    PublicKey::from_secret_key(
+        &Secp256k1::new(),
+        &SecretKey::from_slice(&[1; 32]).expect("valid secret key"),
+    )
+}
+
+fn bundle() -> DisasterRecoveryBundle {
+    DisasterRecoveryBundle {
+        network: Network::Regtest,
+        created_at_secs: 1_700_000_000,
+        federations: BTreeSet::from([FederationId::dummy()]),
+        database: vec![DatabaseEntry {
+            key: "04".to_string(),
+            value: "deadbeef".to_string(),
+        }],
+        channels: vec![ChannelBackup {
+            remote_pubkey: peer(),
+            remote_address: Some(PEER_ADDRESS.to_string()),
+            funding_outpoint: None,
+            channel_size_sats: 1_000_000,
+        }],
+    }
+}
+
+fn database() -> Database {
+    Database::new(MemDatabase::new(), ModuleDecoderRegistry::default())
+}
+
+#[test]
+fn sealed_bundles_open_with_the_password_only() {
+    let sealed = seal_bundle(&bundle(), PASSWORD).expect("bundle can be sealed");
+
+    assert_eq!(
+        open_bundle(&sealed, PASSWORD).expect("bundle opens with the password"),
+        bundle()
+    );
+    assert!(open_bundle(&sealed, "wrong password").is_err());
+    assert!(
+        !String::from_utf8_lossy(&sealed).contains("deadbeef"),
+        "the bundle contents are encrypted"
+    );
+}
+
+#[test]
+fn tampered_bundles_are_rejected() {
+    let sealed = seal_bundle(&bundle(), PASSWORD).expect("bundle can be sealed");
+    let mut encrypted: EncryptedBundle = serde_json::from_slice(&sealed).expect("valid envelope");
+
+    let flipped = if encrypted.ciphertext.ends_with('0') {
+        '1'
+    } else {
+        '0'
+    };
+    encrypted.ciphertext.pop();
+    encrypted.ciphertext.push(flipped);
+
+    let tampered = serde_json::to_vec(&encrypted).expect("envelope serializes");
+    assert!(open_bundle(&tampered, PASSWORD).is_err());
+}
+
+#[test]
+fn bundles_of_another_version_are_rejected() {
+    let sealed = seal_bundle(&bundle(), PASSWORD).expect("bundle can be sealed");
+    let mut encrypted: EncryptedBundle = serde_json::from_slice(&sealed).expect("valid envelope");
+    encrypted.version = DR_BUNDLE_VERSION + 1;
+
+    let future = serde_json::to_vec(&encrypted).expect("envelope serializes");
+    let err = open_bundle(&future, PASSWORD).expect_err("newer bundles are rejected");
+    assert!(err.to_string().contains("version"));
+}
+
+#[tokio::test]
+async fn restored_database_holds_the_same_identity() {
+    let source = database();
+    let mut dbtx = source.begin_transaction().await;
+    let keypair = dbtx
+        .load_or_create_gateway_keypair(RegisteredProtocol::Http)
+        .await;
+    dbtx.commit_tx().await;
+
+    let original = DisasterRecoveryBundle {
+        database: read_database(&source).await.expect("database can be read"),
+        ..bundle()
+    };
+    let sealed = seal_bundle(&original, PASSWORD).expect("bundle can be sealed");
+
+    let target = database();
+    let summary = restore_bundle_into(
+        open_bundle(&sealed, PASSWORD).expect("bundle opens"),
+        &target,
+    )
+    .await
+    .expect("bundle restores into an empty database");
+
+    assert_eq!(summary.database_entries, original.database.len());
+    assert_eq!(summary.federations, original.federations);
+    assert_eq!(summary.channels, original.channels);
+
+    let restored = read_database(&target).await.expect("database can be read");
+    assert!(
+        original
+            .database
+            .iter()
+            .all(|entry| restored.contains(entry)),
+        "all records of the bundle are restored"
+    );
+
+    let mut dbtx = target.begin_transaction().await;
+    assert_eq!(
+        dbtx.load_or_create_gateway_keypair(RegisteredProtocol::Http)
+            .await,
+        keypair,
+        "the restored gateway keeps its identity"
+    );
+    assert_eq!(
+        dbtx.load_pending_client_recoveries().await,
+        original.federations,
+        "the clients are recovered from the mnemonic"
+    );
+    assert_eq!(
+        dbtx.load_restored_channel_peers().await,
+        BTreeMap::from([(peer(), PEER_ADDRESS.to_string())]),
+        "the peers of the channels are connected to"
+    );
+    dbtx.commit_tx().await;
+
+    assert!(
+        restore_bundle_into(original, &target).await.is_err(),
+        "a database that is not empty is never restored into"
+    );
+}
+
+#[test]
+fn bundles_do_not_contain_channel_state() {
+    let sealed = seal_bundle(&bundle(), PASSWORD).expect("bundle can be sealed");
+    let plaintext = serde_json::to_value(open_bundle(&sealed, PASSWORD).expect("bundle opens"))
+        .expect("bundle serializes");
+
+    assert_eq!(
+        plaintext
+            .as_object()
+            .expect("bundle is an object")
+            .keys()
+            .map(String::as_str)
+            .collect::<BTreeSet<_>>(),
+        BTreeSet::from([
+            "channels",
+            "created_at_secs",
+            "database",
+            "federations",
+            "network"
+        ]),
+        "restoring stale channel monitors would broadcast revoked states"
+    );
+    assert_eq!(
+        plaintext["channels"][0]
+            .as_object()
+            .expect("channel is an object")
+            .keys()
+            .map(String::as_str)
+            .collect::<BTreeSet<_>>(),
+        BTreeSet::from([
+            "channel_size_sats",
+            "funding_outpoint",
+            "remote_address",
+            "remote_pubkey"
+        ]),
+        "channels are only backed up statically"
+    );
+}
+
+#[tokio::test]
+async fn bundles_do_not_contain_client_databases() {
+    // Records of the federation clients are stored under the prefix 0x10
+    let client_entry = DatabaseEntry {
+        key: "10aa".to_string(),
+        value: "deadbeef".to_string(),
+    };
+
+    let source = database();
+    let mut dbtx = source.begin_transaction().await;
+    dbtx.raw_insert_bytes(&[0x10, 0xaa], &[0xde, 0xad, 0xbe, 0xef])
+        .await
+        .expect("record can be written");
+    dbtx.commit_tx().await;
+
+    assert!(
+        !read_database(&source)
+            .await
+            .expect("database can be read")
+            .contains(&client_entry),
+        "stale clients would reuse the nonces of notes issued since"
+    );
+
+    let corrupted = DisasterRecoveryBundle {
+        database: vec![client_entry],
+        ..bundle()
+    };
+    assert!(
+        restore_bundle_into(corrupted, &database()).await.is_err(),
+        "client databases are never restored"
+    );
+}
+
+#[test]
+fn restores_require_an_empty_node_folder() {
+    let dir = tempfile::tempdir().expect("temporary directory");
+    let node_dir = dir.path().join("ldk_node");
+
+    assert!(is_empty_dir(&node_dir).expect("a missing folder is empty"));
+
+    std::fs::create_dir_all(&node_dir).expect("directory can be created");
+    assert!(is_empty_dir(&node_dir).expect("folder can be read"));
+
+    std::fs::write(node_dir.join("ldk_node_data.sqlite"), b"node").expect("file is written");
+    assert!(!is_empty_dir(&node_dir).expect("folder can be read"));
+}
+
+#[test]
+fn only_the_most_recent_bundles_are_kept() {
+    let dir = tempfile::tempdir().expect("temporary directory");
+    for created_at_secs in [9, 10, 100, 11] {
+        std::fs::write(dir.path().join(bundle_file_name(created_at_secs)), b"")
+            .expect("file is written");
+    }
+    std::fs::write(dir.path().join("unrelated.txt"), b"").expect("file is written");
+
+    prune_bundles(dir.path(), 2).expect("bundles can be pruned");
+
+    let mut remaining = std::fs::read_dir(dir.path())
+        .expect("directory can be read")
+        .map(|entry| {
+            entry
+                .expect("entry can be read")
+                .file_name()
+                .into_string()
+                .expect("file name is UTF-8")
+        })
+        .collect::<Vec<_>>();
+    remaining.sort();
+
+    assert_eq!(
+        remaining,
+        vec![
+            bundle_file_name(100),
+            bundle_file_name(11),
+            "unrelated.txt".to_string()
+        ]
+    );
+}
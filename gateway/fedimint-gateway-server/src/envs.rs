@@ -76,6 +76,10 @@
 /// one has not already been set.
 pub const FM_GATEWAY_SKIP_SETUP_ENV: &str = "FM_GATEWAY_SKIP_SETUP";
 
+/// Environment variable that allows LNv2 clients to send keysend payments
+/// through the gateway.
+pub const FM_GATEWAY_ENABLE_KEYSEND_ENV: &str = "FM_GATEWAY_ENABLE_KEYSEND";
+
 /// Environment variable that specifies the maximum burst of requests to the
 /// public invoice creation endpoint accepted before rate limiting kicks in.
 pub const FM_GATEWAY_INVOICE_RATE_LIMIT_BURST_ENV: &str = "FM_GATEWAY_INVOICE_RATE_LIMIT_BURST";
@@ -85,3 +89,35 @@
 /// limiting kicks in.
 pub const FM_GATEWAY_INVOICE_RATE_LIMIT_PER_SECOND_ENV: &str =
     "FM_GATEWAY_INVOICE_RATE_LIMIT_PER_SECOND";
+
+/// Environment variable that identifies this instance of an active/standby
+/// pair. Setting it enables high availability mode.
+pub const FM_GATEWAY_HA_INSTANCE_ID_ENV: &str = "FM_GATEWAY_HA_INSTANCE_ID";
+
+/// Environment variable that specifies the path of the leader lease, which has
+/// to be on storage shared by both instances of an active/standby pair.
+pub const FM_GATEWAY_HA_LEASE_PATH_ENV: &str = "FM_GATEWAY_HA_LEASE_PATH";
+
+/// Environment variable that specifies for how many seconds the leader lease
+/// lasts without being renewed.
+pub const FM_GATEWAY_HA_LEASE_SECS_ENV: &str = "FM_GATEWAY_HA_LEASE_SECS";
+
+/// Environment variable that specifies the directory encrypted disaster
+/// recovery bundles are exported to.
+pub const FM_GATEWAY_DR_BACKUP_DIR_ENV: &str = "FM_GATEWAY_DR_BACKUP_DIR";
+
+/// Environment variable that specifies the base URL encrypted disaster
+/// recovery bundles are uploaded to with HTTP PUT.
+pub const FM_GATEWAY_DR_BACKUP_URL_ENV: &str = "FM_GATEWAY_DR_BACKUP_URL";
+
+/// Environment variable that specifies the password disaster recovery bundles
+/// are encrypted with, which `gatewayd restore` also reads.
+pub const FM_GATEWAY_DR_BACKUP_PASSWORD_ENV: &str = "FM_GATEWAY_DR_BACKUP_PASSWORD";
+
+/// Environment variable that specifies the seconds between two exports of a
+/// disaster recovery bundle.
+pub const FM_GATEWAY_DR_BACKUP_INTERVAL_SECS_ENV: &str = "FM_GATEWAY_DR_BACKUP_INTERVAL_SECS";
+
+/// Environment variable that specifies how many disaster recovery bundles are
+/// kept in the backup directory.
+pub const FM_GATEWAY_DR_BACKUP_RETENTION_ENV: &str = "FM_GATEWAY_DR_BACKUP_RETENTION";
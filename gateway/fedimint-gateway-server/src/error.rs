@@ -8,6 +8,7 @@
 use fedimint_core::envs::is_env_var_set;
 use fedimint_core::fmt_utils::OptStacktrace;
 use fedimint_core::util::FmtCompactAnyhow;
+use fedimint_gateway_common::RiskLimit;
 use fedimint_gw_client::pay::OutgoingPaymentError;
 use fedimint_lightning::LightningRpcError;
 use fedimint_logging::LOG_GATEWAY;
@@ -55,6 +56,10 @@ pub enum PublicGatewayError {
     FederationNotConnected(#[from] FederationNotConnected),
     #[error("Failed to receive ecash: {failure_reason}")]
     ReceiveEcashError { failure_reason: String },
+    #[error("LSP request failed: {failure_reason}")]
+    LspError { failure_reason: String },
+    #[error("Outgoing payment rejected, {0}")]
+    RiskLimitExceeded(RiskLimit),
     #[error("Too many requests")]
     RateLimited,
     #[error("Unexpected Error: {}", OptStacktrace(.0))]
@@ -87,6 +92,9 @@ fn into_response(self) -> Response {
                 "Failed to receive ecash".to_string(),
                 StatusCode::INTERNAL_SERVER_ERROR,
             ),
+            // LSP requests are rejected before the gateway takes any ecash, and the
+            // reasons tell external wallets how to fix their order.
+            PublicGatewayError::LspError { .. } => (self.to_string(), StatusCode::BAD_REQUEST),
             PublicGatewayError::Lightning(_) => (
                 "Lightning Network operation failed".to_string(),
                 StatusCode::INTERNAL_SERVER_ERROR,
@@ -99,6 +107,10 @@ fn into_response(self) -> Response {
                 "LNv2 operation failed, please contact gateway operator".to_string(),
                 StatusCode::INTERNAL_SERVER_ERROR,
             ),
+            PublicGatewayError::RiskLimitExceeded(_) => (
+                "The gateway cannot route payments of this federation right now".to_string(),
+                StatusCode::SERVICE_UNAVAILABLE,
+            ),
             PublicGatewayError::RateLimited => (
                 "Too many requests, please try again later".to_string(),
                 StatusCode::TOO_MANY_REQUESTS,
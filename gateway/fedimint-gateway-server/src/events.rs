@@ -1,14 +1,13 @@
-use std::sync::Arc;
 use std::time::{SystemTime, UNIX_EPOCH};
 
-use fedimint_client::ClientHandle;
+use fedimint_core::db::Database;
 use fedimint_eventlog::{
     DBTransactionEventLogExt, Event, EventKind, EventLogId, PersistedLogEntry,
 };
 use fedimint_gwv2_client::events::{
-    CompleteLightningPaymentSucceeded, IncomingPaymentFailed, IncomingPaymentStarted,
-    IncomingPaymentSucceeded, OutgoingPaymentFailed, OutgoingPaymentStarted,
-    OutgoingPaymentSucceeded,
+    CompleteLightningPaymentSucceeded, IncomingPaymentFailed, IncomingPaymentHeld,
+    IncomingPaymentStarted, IncomingPaymentSucceeded, OutgoingPaymentFailed,
+    OutgoingPaymentStarted, OutgoingPaymentSucceeded,
 };
 use fedimint_mint_client::events::{OOBNotesReissued, OOBNotesSpent};
 use fedimint_wallet_client::events::{DepositConfirmed, WithdrawRequest};
@@ -19,13 +18,14 @@
 /// This does not include all events in the log (e.g. `tx-created`,
 /// `tx-accepted`, `NoteCreated`, `NoteSpent` are excluded), so paginated
 /// results filtered to these kinds will have non-contiguous event IDs.
-pub const ALL_GATEWAY_EVENTS: [EventKind; 11] = [
+pub const ALL_GATEWAY_EVENTS: [EventKind; 12] = [
     OutgoingPaymentStarted::KIND,
     OutgoingPaymentSucceeded::KIND,
     OutgoingPaymentFailed::KIND,
     IncomingPaymentStarted::KIND,
     IncomingPaymentSucceeded::KIND,
     IncomingPaymentFailed::KIND,
+    IncomingPaymentHeld::KIND,
     CompleteLightningPaymentSucceeded::KIND,
     OOBNotesSpent::KIND,
     OOBNotesReissued::KIND,
@@ -33,15 +33,16 @@
     DepositConfirmed::KIND,
 ];
 
-/// Searches through the event log for all events that occurred within the
-/// specified time bounds.
+/// Searches through the event log of `db`, which is either the database of a
+/// federation client or the gateway's own, for all events that occurred within
+/// the specified time bounds.
 ///
 /// Because it is inefficient to search the log backwards, instead this function
 /// traverses the log forwards, but in batches.
 /// All events are appended to an array until the cutoff event where the
 /// timestamp is greater than the start timestamp or the end of the log is hit.
 pub async fn get_events_for_duration(
-    client: &Arc<ClientHandle>,
+    db: &Database,
     start: SystemTime,
     end: SystemTime,
 ) -> Vec<PersistedLogEntry> {
@@ -58,7 +59,7 @@ pub async fn get_events_for_duration(
         .as_micros() as u64;
 
     let batch_end = {
-        let mut dbtx = client.db().begin_transaction_nc().await;
+        let mut dbtx = db.begin_transaction_nc().await;
         dbtx.get_next_event_log_id().await
     };
 
@@ -68,7 +69,11 @@ pub async fn get_events_for_duration(
     // Once an event with a timestamp before our start time is found, then we start
     // traversing forward to find events that fall within our time bound.
     while batch_start != EventLogId::LOG_START {
-        let batch = client.get_event_log(Some(batch_start), BATCH_SIZE).await;
+        let batch = db
+            .begin_transaction_nc()
+            .await
+            .get_event_log(Some(batch_start), BATCH_SIZE)
+            .await;
 
         match batch.first() {
             Some(first_event) => {
@@ -87,7 +92,11 @@ pub async fn get_events_for_duration(
 
     let mut all_events = Vec::new();
     loop {
-        let batch = client.get_event_log(Some(batch_start), BATCH_SIZE).await;
+        let batch = db
+            .begin_transaction_nc()
+            .await
+            .get_event_log(Some(batch_start), BATCH_SIZE)
+            .await;
 
         if batch.is_empty() {
             return all_events;
@@ -140,7 +140,7 @@ pub async fn wait_for_incoming_payments(&self) -> AdminResult<()> {
         Ok(())
     }
 
-    async fn unannounce_from_federation(
+    pub async fn unannounce_from_federation(
         &self,
         federation_id: FederationId,
         gateway_keypair: Keypair,
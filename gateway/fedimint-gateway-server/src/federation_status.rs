@@ -8,7 +8,7 @@
 use fedimint_core::util::Spanned;
 use fedimint_core::{NumPeers, PeerId};
 use fedimint_gateway_common::{
-    FederationConnectivity, FederationStatusResponse, LightningMode, LightningModuleStatus,
+    FederationConnectivity, FederationStatusResponse, LightningModuleStatus,
     Lnv1RegistrationStatus, Lnv2RegistrationStatus,
 };
 use fedimint_gateway_server_db::GatewayDbtxNcExt as _;
@@ -21,7 +21,7 @@
 
 use crate::{GW_ANNOUNCEMENT_TTL, Gateway};
 
-fn federation_connectivity(
+pub(crate) fn federation_connectivity(
     connection_status: &BTreeMap<PeerId, PeerStatus>,
 ) -> FederationConnectivity {
     let connected = connection_status
@@ -158,7 +158,7 @@ pub async fn handle_federation_status(
             .copied()
             .collect::<BTreeSet<_>>();
 
-        let lnv1_registration_configured = matches!(self.lightning_mode, LightningMode::Lnd { .. })
+        let lnv1_registration_configured = self.lightning_mode.supports_lnv1()
             && !self.registrations.is_empty()
             && self
                 .gateway_db
@@ -0,0 +1,387 @@
+use std::time::Duration;
+
+use anyhow::ensure;
+use fedimint_core::Amount;
+use fedimint_core::config::FederationId;
+use fedimint_core::time::duration_since_epoch;
+use fedimint_core::util::FmtCompactAnyhow as _;
+use fedimint_gateway_common::{
+    FeeHistoryEntry, FeeHistoryPayload, FeeHistoryResponse, FeeSchedule, FeeSchedulePayload,
+    GatewayBalances, PeakHours, SetFeeSchedulePayload, UtilizationSurcharge,
+};
+use fedimint_gateway_server_db::{FeeSurcharges, GatewayDbtxNcExt as _};
+use fedimint_lnv2_common::gateway_api::{PaymentFee, SendFeeTier};
+use fedimint_logging::LOG_GATEWAY;
+use tracing::{debug, info};
+
+use crate::error::{AdminGatewayError, FederationNotConnected};
+use crate::{AdminResult, Gateway, IAdminGateway as _};
+
+/// How often the surcharges of the fee schedules are recomputed and changes of
+/// the published fees are recorded in the fee history.
+const FEE_SCHEDULE_UPDATE_INTERVAL: Duration = Duration::from_mins(1);
+
+/// How long entries of the fee history are retained.
+const FEE_HISTORY_RETENTION: Duration = Duration::from_hours(24 * 365);
+
+/// The LNv2 fees a federation's routing info publishes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct EffectiveFees {
+    pub send_fee_default: PaymentFee,
+    pub send_fee_tiers: Vec<SendFeeTier>,
+    pub receive_fee: PaymentFee,
+}
+
+impl EffectiveFees {
+    fn matches(&self, entry: &FeeHistoryEntry) -> bool {
+        self.send_fee_default == entry.send_fee_default
+            && self.send_fee_tiers == entry.send_fee_tiers
+            && self.receive_fee == entry.receive_fee
+    }
+
+    fn into_history_entry(self, timestamp_secs: u64) -> FeeHistoryEntry {
+        FeeHistoryEntry {
+            timestamp_secs,
+            send_fee_default: self.send_fee_default,
+            send_fee_tiers: self.send_fee_tiers,
+            receive_fee: self.receive_fee,
+        }
+    }
+}
+
+/// Adds a surcharge to the parts per million of a fee without exceeding the
+/// limit clients accept, unless the fee itself already does.
+fn with_surcharge(fee: PaymentFee, surcharge_ppm: u64, limit: PaymentFee) -> PaymentFee {
+    PaymentFee {
+        base: fee.base,
+        parts_per_million: fee
+            .parts_per_million
+            .saturating_add(surcharge_ppm)
+            .min(limit.parts_per_million)
+            .max(fee.parts_per_million),
+    }
+}
+
+/// Composes the LNv2 fees of a federation from its flat fees, its fee schedule
+/// and the current surcharges. Returns `None` if the flat fees cannot be
+/// added, which is possible for fees stored before the fee limits applied.
+pub(crate) fn effective_fees(
+    lightning_fee: PaymentFee,
+    transaction_fee: PaymentFee,
+    schedule: Option<&FeeSchedule>,
+    surcharges: FeeSurcharges,
+) -> Option<EffectiveFees> {
+    let send_fee_default = with_surcharge(
+        lightning_fee.checked_add(transaction_fee)?,
+        surcharges.send_ppm,
+        PaymentFee::SEND_FEE_LIMIT,
+    );
+
+    let mut send_fee_tiers = Vec::new();
+
+    for tier in schedule.map_or(&[][..], |schedule| &schedule.lightning_fee_tiers) {
+        let fee = with_surcharge(
+            tier.lightning_fee.checked_add(transaction_fee)?,
+            surcharges.send_ppm,
+            PaymentFee::SEND_FEE_LIMIT,
+        );
+
+        // The flat lightning fee might have been lowered below the tier since the
+        // schedule was set, and clients rely on no tier exceeding the default.
+        if fee.is_within(&send_fee_default) {
+            send_fee_tiers.push(SendFeeTier {
+                min_amount: tier.min_amount,
+                send_fee_default: fee,
+            });
+        }
+    }
+
+    send_fee_tiers.sort_by_key(|tier| tier.min_amount);
+
+    Some(EffectiveFees {
+        send_fee_default,
+        send_fee_tiers,
+        receive_fee: with_surcharge(
+            transaction_fee,
+            surcharges.receive_ppm,
+            PaymentFee::RECEIVE_FEE_LIMIT,
+        ),
+    })
+}
+
+fn is_peak_hour(peak_hours: &PeakHours, hour: u8) -> bool {
+    if peak_hours.start_hour <= peak_hours.end_hour {
+        peak_hours.start_hour <= hour && hour < peak_hours.end_hour
+    } else {
+        peak_hours.start_hour <= hour || hour < peak_hours.end_hour
+    }
+}
+
+/// Returns the surcharge of the highest utilization step that has been
+/// reached.
+fn utilization_surcharge(steps: &[UtilizationSurcharge], utilization_percent: u64) -> u64 {
+    steps
+        .iter()
+        .filter(|step| u64::from(step.min_utilization_percent) <= utilization_percent)
+        .max_by_key(|step| step.min_utilization_percent)
+        .map_or(0, |step| step.surcharge_ppm)
+}
+
+fn utilization_percent(used: u64, capacity: u64) -> u64 {
+    if capacity == 0 {
+        return 0;
+    }
+
+    (u128::from(used) * 100 / u128::from(capacity)).min(100) as u64
+}
+
+/// Computes the surcharges of a federation's fee schedule for the given hour
+/// of the day in UTC and the gateway's balances.
+fn fee_surcharges(
+    schedule: &FeeSchedule,
+    federation_id: FederationId,
+    hour: u8,
+    balances: Option<&GatewayBalances>,
+) -> FeeSurcharges {
+    let peak_ppm = schedule
+        .peak_hours
+        .iter()
+        .filter(|peak_hours| is_peak_hour(peak_hours, hour))
+        .map(|peak_hours| peak_hours.surcharge_ppm)
+        .max()
+        .unwrap_or(0);
+
+    let Some(balances) = balances else {
+        return FeeSurcharges {
+            send_ppm: peak_ppm,
+            receive_ppm: peak_ppm,
+        };
+    };
+
+    let outbound_ppm = utilization_surcharge(
+        &schedule.outbound_utilization,
+        utilization_percent(
+            balances.inbound_lightning_liquidity_msats,
+            balances
+                .inbound_lightning_liquidity_msats
+                .saturating_add(balances.lightning_balance_msats),
+        ),
+    );
+
+    let ecash_ppm = schedule.ecash_capacity.map_or(0, |capacity| {
+        let ecash_balance = balances
+            .ecash_balances
+            .iter()
+            .find(|balance| balance.federation_id == federation_id)
+            .map_or(Amount::ZERO, |balance| balance.ecash_balance_msats);
+
+        utilization_surcharge(
+            &schedule.ecash_utilization,
+            utilization_percent(capacity.saturating_sub(ecash_balance).msats, capacity.msats),
+        )
+    });
+
+    FeeSurcharges {
+        send_ppm: peak_ppm.saturating_add(outbound_ppm),
+        receive_ppm: peak_ppm.saturating_add(ecash_ppm),
+    }
+}
+
+fn verify_fee_schedule(schedule: &FeeSchedule, lightning_fee: PaymentFee) -> anyhow::Result<()> {
+    for (i, tier) in schedule.lightning_fee_tiers.iter().enumerate() {
+        ensure!(
+            tier.lightning_fee.is_within(&lightning_fee),
+            "The lightning fee of the tier from {} exceeds the federation's lightning fee of {}",
+            tier.min_amount,
+            lightning_fee
+        );
+
+        ensure!(
+            schedule.lightning_fee_tiers[..i]
+                .iter()
+                .all(|other| other.min_amount != tier.min_amount),
+            "There are multiple tiers from {}",
+            tier.min_amount
+        );
+    }
+
+    for peak_hours in &schedule.peak_hours {
+        ensure!(
+            peak_hours.start_hour < 24 && peak_hours.end_hour <= 24,
+            "Peak hours have to be within the 24 hours of a day"
+        );
+    }
+
+    for step in schedule
+        .outbound_utilization
+        .iter()
+        .chain(&schedule.ecash_utilization)
+    {
+        ensure!(
+            step.min_utilization_percent <= 100,
+            "A utilization cannot exceed 100 percent"
+        );
+    }
+
+    ensure!(
+        schedule.ecash_utilization.is_empty() || schedule.ecash_capacity.is_some(),
+        "Ecash utilization surcharges require an ecash capacity"
+    );
+
+    Ok(())
+}
+
+impl Gateway {
+    /// Spawns a background task that periodically updates the surcharges of
+    /// the fee schedules and records changes of the published LNv2 fees.
+    pub(crate) fn spawn_fee_schedule_task(&self) {
+        let self_copy = self.clone();
+        self.task_group
+            .spawn_cancellable_silent("fee schedule", async move {
+                let mut interval = tokio::time::interval(FEE_SCHEDULE_UPDATE_INTERVAL);
+                loop {
+                    interval.tick().await;
+
+                    if let Err(err) = self_copy.update_fee_schedules().await {
+                        debug!(
+                            target: LOG_GATEWAY,
+                            err = %err.fmt_compact_anyhow(),
+                            "Failed to update the fee schedules"
+                        );
+                    }
+                }
+            });
+    }
+
+    async fn update_fee_schedules(&self) -> anyhow::Result<()> {
+        let (configs, schedules) = {
+            let mut dbtx = self.gateway_db.begin_transaction_nc().await;
+            (
+                dbtx.load_federation_configs().await,
+                dbtx.load_fee_schedules().await,
+            )
+        };
+
+        // The balances are only queried if a schedule depends on them, since this
+        // requires the lightning node to be reachable.
+        let balances = if schedules.values().any(|schedule| {
+            !schedule.outbound_utilization.is_empty() || !schedule.ecash_utilization.is_empty()
+        }) {
+            Some(self.handle_get_balances_msg().await?)
+        } else {
+            None
+        };
+
+        let now_secs = duration_since_epoch().as_secs();
+        let hour = u8::try_from(now_secs / 3600 % 24).expect("Smaller than 24");
+
+        let mut dbtx = self.gateway_db.begin_transaction().await;
+
+        for (federation_id, config) in configs {
+            let schedule = schedules.get(&federation_id);
+
+            let surcharges = schedule.map_or_else(FeeSurcharges::default, |schedule| {
+                fee_surcharges(schedule, federation_id, hour, balances.as_ref())
+            });
+
+            if schedule.is_some() {
+                dbtx.save_fee_surcharges(federation_id, surcharges).await;
+            }
+
+            let Some(fees) = effective_fees(
+                config.lightning_fee,
+                config.transaction_fee,
+                schedule,
+                surcharges,
+            ) else {
+                continue;
+            };
+
+            let last_entry = dbtx.load_fee_history(federation_id, 1).await.pop();
+
+            if last_entry.is_none_or(|entry| !fees.matches(&entry)) {
+                info!(target: LOG_GATEWAY, %federation_id, ?fees, "Published LNv2 fees changed");
+
+                dbtx.save_fee_history_entry(
+                    federation_id,
+                    fees.into_history_entry(now_secs),
+                    now_secs.saturating_sub(FEE_HISTORY_RETENTION.as_secs()),
+                )
+                .await;
+            }
+        }
+
+        dbtx.commit_tx_result().await?;
+
+        Ok(())
+    }
+
+    /// Returns the fee schedule of a federation, or `None` if it only charges
+    /// its flat fees.
+    pub async fn handle_get_fee_schedule_msg(
+        &self,
+        payload: FeeSchedulePayload,
+    ) -> AdminResult<Option<FeeSchedule>> {
+        Ok(self
+            .gateway_db
+            .begin_transaction_nc()
+            .await
+            .load_fee_schedule(payload.federation_id)
+            .await)
+    }
+
+    /// Replaces the fee schedule of a federation. Its tiers take effect
+    /// immediately, its surcharges with the next update.
+    pub async fn handle_set_fee_schedule_msg(
+        &self,
+        SetFeeSchedulePayload {
+            federation_id,
+            schedule,
+        }: SetFeeSchedulePayload,
+    ) -> AdminResult<()> {
+        let mut dbtx = self.gateway_db.begin_transaction().await;
+
+        let config =
+            dbtx.load_federation_config(federation_id)
+                .await
+                .ok_or(FederationNotConnected {
+                    federation_id_prefix: federation_id.to_prefix(),
+                })?;
+
+        let schedule = schedule
+            .map(|mut schedule| {
+                verify_fee_schedule(&schedule, config.lightning_fee)?;
+                schedule
+                    .lightning_fee_tiers
+                    .sort_by_key(|tier| tier.min_amount);
+                Ok(schedule)
+            })
+            .transpose()
+            .map_err(|err: anyhow::Error| {
+                AdminGatewayError::GatewayConfigurationError(err.to_string())
+            })?;
+
+        dbtx.save_fee_schedule(federation_id, schedule).await;
+        dbtx.commit_tx().await;
+
+        Ok(())
+    }
+
+    /// Returns the most recent changes of a federation's published LNv2 fees,
+    /// newest first.
+    pub async fn handle_fee_history_msg(
+        &self,
+        payload: FeeHistoryPayload,
+    ) -> AdminResult<FeeHistoryResponse> {
+        Ok(FeeHistoryResponse(
+            self.gateway_db
+                .begin_transaction_nc()
+                .await
+                .load_fee_history(payload.federation_id, payload.limit)
+                .await,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests;
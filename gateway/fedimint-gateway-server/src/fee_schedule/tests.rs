@@ -0,0 +1,207 @@
+use fedimint_core::Amount;
+use fedimint_gateway_common::{FeeSchedule, LightningFeeTier, PeakHours, UtilizationSurcharge};
+use fedimint_gateway_server_db::FeeSurcharges;
+use fedimint_lnv2_common::gateway_api::{PaymentFee, SendFeeTier};
+
+use super::{effective_fees, fee_surcharges, verify_fee_schedule};
+use crate::test_helpers::{balances, federation};
+
+fn fee(base_msats: u64, parts_per_million: u64) -> PaymentFee {
+    PaymentFee {
+        base: Amount::from_msats(base_msats),
+        parts_per_million,
+    }
+}
+
+fn step(min_utilization_percent: u8, surcharge_ppm: u64) -> UtilizationSurcharge {
+    UtilizationSurcharge {
+        min_utilization_percent,
+        surcharge_ppm,
+    }
+}
+
+#[test]
+fn effective_fees_without_schedule_are_the_flat_fees() {
+    let fees = effective_fees(
+        fee(1000, 1000),
+        fee(2000, 500),
+        None,
+        FeeSurcharges::default(),
+    )
+    .expect("fees can be added");
+
+    assert_eq!(fees.send_fee_default, fee(3000, 1500));
+    assert!(fees.send_fee_tiers.is_empty());
+    assert_eq!(fees.receive_fee, fee(2000, 500));
+}
+
+#[test]
+fn effective_fees_add_surcharges_up_to_the_limits() {
+    let schedule = FeeSchedule {
+        lightning_fee_tiers: vec![LightningFeeTier {
+            min_amount: Amount::from_sats(1_000_000),
+            lightning_fee: fee(0, 100),
+        }],
+        ..FeeSchedule::default()
+    };
+
+    let fees = effective_fees(
+        fee(1000, 1000),
+        fee(2000, 500),
+        Some(&schedule),
+        FeeSurcharges {
+            send_ppm: 100_000,
+            receive_ppm: 1000,
+        },
+    )
+    .expect("fees can be added");
+
+    assert_eq!(
+        fees.send_fee_default,
+        fee(3000, PaymentFee::SEND_FEE_LIMIT.parts_per_million)
+    );
+    assert_eq!(
+        fees.send_fee_tiers,
+        vec![SendFeeTier {
+            min_amount: Amount::from_sats(1_000_000),
+            send_fee_default: fee(2000, PaymentFee::SEND_FEE_LIMIT.parts_per_million),
+        }]
+    );
+    assert_eq!(fees.receive_fee, fee(2000, 1500));
+}
+
+#[test]
+fn effective_fees_drop_tiers_above_the_lightning_fee() {
+    let schedule = FeeSchedule {
+        lightning_fee_tiers: vec![LightningFeeTier {
+            min_amount: Amount::from_sats(1_000_000),
+            lightning_fee: fee(0, 2000),
+        }],
+        ..FeeSchedule::default()
+    };
+
+    let fees = effective_fees(
+        fee(1000, 1000),
+        fee(2000, 500),
+        Some(&schedule),
+        FeeSurcharges::default(),
+    )
+    .expect("fees can be added");
+
+    assert!(fees.send_fee_tiers.is_empty());
+}
+
+#[test]
+fn peak_hours_wrap_around_midnight() {
+    let schedule = FeeSchedule {
+        peak_hours: vec![PeakHours {
+            start_hour: 22,
+            end_hour: 2,
+            surcharge_ppm: 300,
+        }],
+        ..FeeSchedule::default()
+    };
+
+    for (hour, expected_ppm) in [(21, 0), (22, 300), (1, 300), (2, 0)] {
+        assert_eq!(
+            fee_surcharges(&schedule, federation(1), hour, None),
+            FeeSurcharges {
+                send_ppm: expected_ppm,
+                receive_ppm: expected_ppm,
+            }
+        );
+    }
+}
+
+#[test]
+fn utilization_surcharges_apply_the_highest_step_reached() {
+    let schedule = FeeSchedule {
+        outbound_utilization: vec![step(50, 100), step(80, 400)],
+        ecash_capacity: Some(Amount::from_msats(1000)),
+        ecash_utilization: vec![step(50, 200)],
+        ..FeeSchedule::default()
+    };
+
+    // 60 percent of the channel capacity is inbound, 30 percent of the ecash
+    // capacity is no longer covered
+    let mut node_balances = balances(0, &[(federation(1), Amount::from_msats(700))]);
+    node_balances.lightning_balance_msats = 400;
+    node_balances.inbound_lightning_liquidity_msats = 600;
+    assert_eq!(
+        fee_surcharges(&schedule, federation(1), 12, Some(&node_balances)),
+        FeeSurcharges {
+            send_ppm: 100,
+            receive_ppm: 0,
+        }
+    );
+
+    // 90 percent of the channel capacity is inbound, the ecash is depleted
+    let mut node_balances = balances(0, &[(federation(1), Amount::ZERO)]);
+    node_balances.lightning_balance_msats = 100;
+    node_balances.inbound_lightning_liquidity_msats = 900;
+    assert_eq!(
+        fee_surcharges(&schedule, federation(1), 12, Some(&node_balances)),
+        FeeSurcharges {
+            send_ppm: 400,
+            receive_ppm: 200,
+        }
+    );
+}
+
+#[test]
+fn verify_fee_schedule_rejects_invalid_schedules() {
+    let lightning_fee = fee(1000, 1000);
+
+    let tier = LightningFeeTier {
+        min_amount: Amount::from_sats(1_000_000),
+        lightning_fee: fee(0, 500),
+    };
+
+    let valid = FeeSchedule {
+        lightning_fee_tiers: vec![tier],
+        peak_hours: vec![PeakHours {
+            start_hour: 18,
+            end_hour: 24,
+            surcharge_ppm: 100,
+        }],
+        outbound_utilization: vec![step(100, 100)],
+        ecash_capacity: Some(Amount::from_sats(100_000)),
+        ecash_utilization: vec![step(50, 100)],
+    };
+
+    assert!(verify_fee_schedule(&valid, lightning_fee).is_ok());
+
+    let invalid_schedules = [
+        FeeSchedule {
+            lightning_fee_tiers: vec![LightningFeeTier {
+                lightning_fee: fee(0, 2000),
+                ..tier
+            }],
+            ..valid.clone()
+        },
+        FeeSchedule {
+            lightning_fee_tiers: vec![tier, tier],
+            ..valid.clone()
+        },
+        FeeSchedule {
+            peak_hours: vec![PeakHours {
+                start_hour: 24,
+                end_hour: 2,
+                surcharge_ppm: 100,
+            }],
+            ..valid.clone()
+        },
+        FeeSchedule {
+            outbound_utilization: vec![step(101, 100)],
+            ..valid.clone()
+        },
+        FeeSchedule {
+            ecash_capacity: None,
+            ..valid.clone()
+        },
+    ];
+
+    for schedule in invalid_schedules {
+        assert!(verify_fee_schedule(&schedule, lightning_fee).is_err());
+    }
+}
@@ -0,0 +1,328 @@
+use std::io::ErrorKind;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use anyhow::Context as _;
+use fedimint_core::time::duration_since_epoch;
+use fedimint_core::util::FmtCompactAnyhow as _;
+use fedimint_gateway_common::HighAvailabilityInfo;
+use fedimint_logging::LOG_GATEWAY;
+use serde::{Deserialize, Serialize};
+use tracing::{error, info, warn};
+
+use crate::Gateway;
+
+/// Name of the leader lease file in the gateway's data directory, unless a
+/// different path is configured.
+pub const LEADER_LEASE_FILE: &str = "leader_lease.json";
+
+/// Time a freshly written lease is left to settle before it is read back, such
+/// that an instance that wrote the lease concurrently has overwritten it by
+/// then.
+const LEASE_SETTLE_DELAY: Duration = Duration::from_secs(1);
+
+/// Time the lease file is given to respond before an access counts as failed,
+/// such that a hanging shared volume cannot stall the renewal past the expiry.
+const LEASE_IO_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Parameters of an instance that runs as one of an active/standby pair
+/// sharing the gateway's database and identity.
+#[derive(Debug, Clone)]
+pub struct HighAvailabilityParameters {
+    /// Identifies this instance in the lease, has to differ between the pair.
+    pub instance_id: String,
+    /// Location of the lease on storage that both instances share.
+    pub lease_path: PathBuf,
+    /// Time a lease lasts without being renewed.
+    pub lease_duration: Duration,
+}
+
+/// The leader lease as it is stored in the lease file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct LeaderLeaseRecord {
+    instance_id: String,
+    expires_at_secs: u64,
+}
+
+/// Returns whether `instance_id` may write the lease, which is the case if
+/// nobody holds it, it expired or it is held by `instance_id` already.
+fn can_acquire(current: Option<&LeaderLeaseRecord>, instance_id: &str, now_secs: u64) -> bool {
+    current
+        .is_none_or(|lease| lease.instance_id == instance_id || lease.expires_at_secs <= now_secs)
+}
+
+/// Returns whether `instance_id` holds an unexpired lease, in which case the
+/// other instance cannot write it and a renewal does not need to settle.
+fn is_holder(current: Option<&LeaderLeaseRecord>, instance_id: &str, now_secs: u64) -> bool {
+    current
+        .is_some_and(|lease| lease.instance_id == instance_id && now_secs < lease.expires_at_secs)
+}
+
+/// Leader lease kept in a file on storage that both instances share, standing
+/// in for a lease row in a shared database. The file is replaced atomically
+/// and a newly taken lease only counts once it reads back unchanged after
+/// [`LEASE_SETTLE_DELAY`], such that two instances racing for an expired lease
+/// cannot both become the leader.
+///
+/// The expiry in the file is compared against the wall clock of the other
+/// instance, hence the clocks of both hosts have to be in sync. Locally the
+/// lease is tracked against a monotonic deadline, which does not move with
+/// the wall clock.
+#[derive(Debug)]
+pub struct LeaderLease {
+    params: HighAvailabilityParameters,
+    /// The point in time until which this instance holds the lease, if it
+    /// acquired it.
+    deadline: Mutex<Option<Instant>>,
+}
+
+impl LeaderLease {
+    pub fn new(params: HighAvailabilityParameters) -> Self {
+        Self {
+            params,
+            deadline: Mutex::new(None),
+        }
+    }
+
+    pub fn instance_id(&self) -> &str {
+        &self.params.instance_id
+    }
+
+    /// Interval in which the leader renews its lease, leaving it two attempts
+    /// before the lease expires.
+    pub fn renewal_interval(&self) -> Duration {
+        self.params.lease_duration / 3
+    }
+
+    /// Returns the point in time until which this instance holds the lease,
+    /// or `None` if it never acquired it.
+    fn deadline(&self) -> Option<Instant> {
+        *self
+            .deadline
+            .lock()
+            .expect("No code holding the lock can panic")
+    }
+
+    /// Returns whether this instance holds the lease according to its
+    /// monotonic deadline.
+    pub fn holds_lease(&self) -> bool {
+        self.deadline()
+            .is_some_and(|deadline| Instant::now() < deadline)
+    }
+
+    /// Gives up the lease locally, such that [`Self::lapsed`] resolves right
+    /// away.
+    fn release(&self) {
+        *self
+            .deadline
+            .lock()
+            .expect("No code holding the lock can panic") = None;
+    }
+
+    /// Resolves once this instance no longer holds the lease.
+    pub async fn lapsed(&self) {
+        while let Some(deadline) = self.deadline()
+            && Instant::now() < deadline
+        {
+            tokio::time::sleep_until(deadline.into()).await;
+        }
+    }
+
+    /// Runs blocking I/O on the lease file on the blocking thread pool and
+    /// fails if it does not complete within [`LEASE_IO_TIMEOUT`].
+    async fn run_io<T: Send + 'static>(
+        io: impl FnOnce() -> anyhow::Result<T> + Send + 'static,
+    ) -> anyhow::Result<T> {
+        tokio::time::timeout(LEASE_IO_TIMEOUT, tokio::task::spawn_blocking(io))
+            .await
+            .context("Leader lease file did not respond in time")?
+            .context("Leader lease file access panicked")?
+    }
+
+    async fn read(&self) -> anyhow::Result<Option<LeaderLeaseRecord>> {
+        let lease_path = self.params.lease_path.clone();
+
+        Self::run_io(move || match std::fs::read(&lease_path) {
+            Ok(bytes) => Ok(Some(
+                serde_json::from_slice(&bytes).context("Leader lease file is corrupted")?,
+            )),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err).context("Failed to read the leader lease file"),
+        })
+        .await
+    }
+
+    async fn write(&self, record: &LeaderLeaseRecord) -> anyhow::Result<()> {
+        let lease_path = self.params.lease_path.clone();
+        let tmp_path = lease_path.with_extension(format!("{}.tmp", self.params.instance_id));
+        let bytes = serde_json::to_vec(record)?;
+
+        Self::run_io(move || {
+            std::fs::write(&tmp_path, bytes).context("Failed to write the leader lease file")?;
+            std::fs::rename(&tmp_path, &lease_path)
+                .context("Failed to replace the leader lease file")
+        })
+        .await
+    }
+
+    /// Takes or renews the lease and returns its expiry, or `None` if the
+    /// other instance holds it.
+    pub async fn try_acquire(&self) -> anyhow::Result<Option<u64>> {
+        // The expiry is truncated to whole seconds, so the deadline ends a
+        // second early to never outlast the expiry seen by the other instance
+        let deadline = Instant::now()
+            + self
+                .params
+                .lease_duration
+                .saturating_sub(Duration::from_secs(1));
+        let now_secs = duration_since_epoch().as_secs();
+        let current = self.read().await?;
+
+        if !can_acquire(current.as_ref(), self.instance_id(), now_secs) {
+            return Ok(None);
+        }
+
+        let record = LeaderLeaseRecord {
+            instance_id: self.params.instance_id.clone(),
+            expires_at_secs: now_secs + self.params.lease_duration.as_secs(),
+        };
+
+        self.write(&record).await?;
+
+        if !is_holder(current.as_ref(), self.instance_id(), now_secs) {
+            tokio::time::sleep(LEASE_SETTLE_DELAY).await;
+
+            if self.read().await?.as_ref() != Some(&record) {
+                return Ok(None);
+            }
+        }
+
+        *self
+            .deadline
+            .lock()
+            .expect("No code holding the lock can panic") = Some(deadline);
+
+        Ok(Some(record.expires_at_secs))
+    }
+
+    /// Waits as the standby until this instance holds the lease and returns
+    /// its expiry.
+    pub async fn wait_for_leadership(&self) -> u64 {
+        loop {
+            match self.try_acquire().await {
+                Ok(Some(expires_at_secs)) => {
+                    info!(
+                        target: LOG_GATEWAY,
+                        instance_id = %self.instance_id(),
+                        "Acquired the leader lease"
+                    );
+                    return expires_at_secs;
+                }
+                Ok(None) => {
+                    info!(
+                        target: LOG_GATEWAY,
+                        instance_id = %self.instance_id(),
+                        "Waiting as standby, the leader lease is held by another instance"
+                    );
+                }
+                Err(err) => {
+                    warn!(
+                        target: LOG_GATEWAY,
+                        err = %err.fmt_compact_anyhow(),
+                        "Failed to acquire the leader lease"
+                    );
+                }
+            }
+
+            tokio::time::sleep(self.renewal_interval()).await;
+        }
+    }
+}
+
+impl Gateway {
+    /// Renews the leader lease while the gateway runs, starting right away
+    /// since the startup may have taken a good part of the lease. Once the
+    /// lease is lost or cannot be renewed before it expires the gateway shuts
+    /// down, since the standby might already be intercepting HTLCs with the
+    /// same identity.
+    pub(crate) fn spawn_leader_lease_task(&self) {
+        let Some(leader_lease) = self.leader_lease.clone() else {
+            return;
+        };
+
+        let registration_health = self.registration_health.clone();
+        let task_group = self.task_group.clone();
+        self.task_group
+            .spawn_cancellable_silent("leader lease", async move {
+                let mut interval = tokio::time::interval(leader_lease.renewal_interval());
+
+                loop {
+                    interval.tick().await;
+
+                    match leader_lease.try_acquire().await {
+                        Ok(Some(expires_at_secs)) => {
+                            registration_health.record_leader_lease(expires_at_secs);
+                            continue;
+                        }
+                        Ok(None) => {
+                            error!(
+                                target: LOG_GATEWAY,
+                                instance_id = %leader_lease.instance_id(),
+                                "Lost the leader lease to another instance, shutting down"
+                            );
+                        }
+                        Err(err) => {
+                            let next_attempt = Instant::now() + leader_lease.renewal_interval();
+
+                            if leader_lease
+                                .deadline()
+                                .is_some_and(|deadline| next_attempt < deadline)
+                            {
+                                warn!(
+                                    target: LOG_GATEWAY,
+                                    err = %err.fmt_compact_anyhow(),
+                                    "Failed to renew the leader lease"
+                                );
+                                continue;
+                            }
+
+                            error!(
+                                target: LOG_GATEWAY,
+                                err = %err.fmt_compact_anyhow(),
+                                "Failed to renew the leader lease before it expires, shutting down"
+                            );
+                        }
+                    }
+
+                    leader_lease.release();
+                    registration_health.clear_leader_lease();
+                    task_group.shutdown();
+                    return;
+                }
+            });
+    }
+
+    /// Resolves once this instance no longer holds the leader lease, which
+    /// never happens if it does not run as one of an active/standby pair.
+    pub(crate) async fn leader_lease_lapsed(&self) {
+        match &self.leader_lease {
+            Some(leader_lease) => leader_lease.lapsed().await,
+            None => std::future::pending().await,
+        }
+    }
+
+    /// Returns the leadership of this instance if it runs in high availability
+    /// mode.
+    pub(crate) fn high_availability_info(&self) -> Option<HighAvailabilityInfo> {
+        self.leader_lease
+            .as_ref()
+            .map(|leader_lease| HighAvailabilityInfo {
+                instance_id: leader_lease.instance_id().to_string(),
+                lease_expires_at_secs: self.registration_health.leader_lease_expires_at_secs(),
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests;
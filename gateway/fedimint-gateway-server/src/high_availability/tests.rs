@@ -0,0 +1,112 @@
+use std::time::Duration;
+
+use super::{HighAvailabilityParameters, LeaderLease, LeaderLeaseRecord, can_acquire, is_holder};
+
+fn lease(instance_id: &str, expires_at_secs: u64) -> LeaderLeaseRecord {
+    LeaderLeaseRecord {
+        instance_id: instance_id.to_string(),
+        expires_at_secs,
+    }
+}
+
+fn leader_lease(dir: &std::path::Path, instance_id: &str, lease_secs: u64) -> LeaderLease {
+    LeaderLease::new(HighAvailabilityParameters {
+        instance_id: instance_id.to_string(),
+        lease_path: dir.join(super::LEADER_LEASE_FILE),
+        lease_duration: Duration::from_secs(lease_secs),
+    })
+}
+
+#[test]
+fn lease_can_be_acquired_if_free_expired_or_own() {
+    assert!(can_acquire(None, "a", 100));
+    assert!(can_acquire(Some(&lease("a", 130)), "a", 100));
+    assert!(can_acquire(Some(&lease("b", 100)), "a", 100));
+    assert!(!can_acquire(Some(&lease("b", 101)), "a", 100));
+}
+
+#[test]
+fn only_an_unexpired_own_lease_is_held() {
+    assert!(is_holder(Some(&lease("a", 101)), "a", 100));
+    assert!(!is_holder(Some(&lease("a", 100)), "a", 100));
+    assert!(!is_holder(Some(&lease("b", 101)), "a", 100));
+    assert!(!is_holder(None, "a", 100));
+}
+
+#[tokio::test]
+async fn standby_cannot_take_the_lease_of_the_leader() {
+    let dir = tempfile::tempdir().expect("temporary directory");
+    let leader = leader_lease(dir.path(), "a", 30);
+    let standby = leader_lease(dir.path(), "b", 30);
+
+    let expires_at_secs = leader
+        .try_acquire()
+        .await
+        .expect("lease is readable")
+        .expect("free lease is acquired");
+    assert!(leader.holds_lease());
+
+    assert_eq!(
+        standby.try_acquire().await.expect("lease is readable"),
+        None
+    );
+
+    // Renewing does not have to wait for the lease to settle
+    assert!(
+        leader
+            .try_acquire()
+            .await
+            .expect("lease is readable")
+            .is_some_and(|renewed_expiry| renewed_expiry >= expires_at_secs)
+    );
+}
+
+#[tokio::test]
+async fn standby_takes_over_once_the_lease_expired() {
+    let dir = tempfile::tempdir().expect("temporary directory");
+    let leader = leader_lease(dir.path(), "a", 2);
+    let standby = leader_lease(dir.path(), "b", 2);
+
+    leader
+        .try_acquire()
+        .await
+        .expect("lease is readable")
+        .expect("free lease is acquired");
+
+    // The leader stops renewing its lease, e.g. since its host hangs
+    tokio::time::sleep(Duration::from_secs(3)).await;
+    assert!(!leader.holds_lease());
+
+    assert!(
+        standby
+            .try_acquire()
+            .await
+            .expect("lease is readable")
+            .is_some()
+    );
+    assert!(standby.holds_lease());
+
+    // The former leader cannot take the lease back
+    assert_eq!(leader.try_acquire().await.expect("lease is readable"), None);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn only_one_of_two_racing_instances_acquires_a_free_lease() {
+    let dir = tempfile::tempdir().expect("temporary directory");
+    let first = leader_lease(dir.path(), "a", 30);
+    let second = leader_lease(dir.path(), "b", 30);
+
+    let (first_expiry, second_expiry) = tokio::join!(first.try_acquire(), second.try_acquire());
+
+    assert_eq!(
+        [
+            first_expiry.expect("lease is readable"),
+            second_expiry.expect("lease is readable"),
+        ]
+        .iter()
+        .filter(|expiry| expiry.is_some())
+        .count(),
+        1
+    );
+    assert_ne!(first.holds_lease(), second.holds_lease());
+}
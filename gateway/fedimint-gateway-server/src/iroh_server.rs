@@ -3,7 +3,7 @@
 use std::pin::Pin;
 use std::sync::Arc;
 
-use anyhow::anyhow;
+use anyhow::{anyhow, ensure};
 use axum::extract::{Path, Query};
 use axum::{Extension, Json};
 use bitcoin::hashes::sha256;
@@ -289,7 +289,8 @@ pub(crate) async fn handle_request(
         return Ok(unknown_route(&request.route));
     }
 
-    if handlers.is_authenticated(&request.route) && iroh_verify_password(&gateway, request).is_err()
+    if handlers.is_authenticated(&request.route)
+        && iroh_verify_password(&gateway, request).await.is_err()
     {
         return Ok((StatusCode::UNAUTHORIZED, Json(json!(()))));
     }
@@ -400,14 +401,21 @@ fn parse_verify_route(
 }
 
 /// Verifies if the supplied password in the Iroh request matches the gateway's
-/// password
-fn iroh_verify_password(
+/// password or is an API token that is allowed to access the route
+async fn iroh_verify_password(
     gateway: &Arc<Gateway>,
     request: &IrohGatewayRequest,
 ) -> anyhow::Result<()> {
-    if let Some(password) = request.password.as_ref()
-        && bcrypt::verify(password, &gateway.bcrypt_password_hash)?
-    {
+    let Some(password) = request.password.as_ref() else {
+        return Err(anyhow!("Invalid password"));
+    };
+
+    if let Some(authorized) = gateway.authorize_api_token(password, &request.route).await {
+        ensure!(authorized, "The API token may not access {}", request.route);
+        return Ok(());
+    }
+
+    if bcrypt::verify(password, &gateway.bcrypt_password_hash)? {
         return Ok(());
     }
 
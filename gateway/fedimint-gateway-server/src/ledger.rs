@@ -0,0 +1,703 @@
+use std::collections::{BTreeSet, HashMap};
+use std::fmt::Write as _;
+use std::time::{Duration, UNIX_EPOCH};
+
+use bitcoin::hashes::sha256;
+use chrono::DateTime;
+use fedimint_core::Amount;
+use fedimint_core::config::FederationId;
+use fedimint_eventlog::{DBTransactionEventLogExt as _, Event, PersistedLogEntry};
+use fedimint_gateway_common::{
+    EcashPeggedOut, ExportLedgerPayload, ExportLedgerResponse, LedgerAccount, LedgerEntry,
+    LedgerFormat, LedgerPayload, LedgerPosting, LedgerResponse, LightningRoutingFeePaid,
+    LspChannelOrdered, RevenueBucket, RevenuePayload, RevenueResponse, WithdrawResponse,
+};
+use fedimint_lightning::PayInvoiceResponse;
+use fedimint_lnv2_common::contracts::PaymentImage;
+use fedimint_mint_client::events::{OOBNotesReissued, OOBNotesSpent};
+use fedimint_wallet_client::events::DepositConfirmed;
+
+use crate::error::{AdminGatewayError, FederationNotConnected};
+use crate::events::get_events_for_duration;
+use crate::{AdminResult, Gateway};
+
+/// How far before the start of a queried range the event logs are searched
+/// for the start events of payments that succeeded within the range. A
+/// lightning payment cannot stay in flight for longer than the maximum CLTV
+/// expiry of 2016 blocks, about two weeks. A payment that succeeded more than
+/// that after it started is left out of the ledger.
+const PAYMENT_LOOKBACK: Duration = Duration::from_hours(24 * 14);
+
+/// The maximum number of buckets a revenue query may return.
+const MAX_REVENUE_BUCKETS: u64 = 1000;
+
+fn msats(amount: Amount) -> i64 {
+    i64::try_from(amount.msats).expect("Amounts of bitcoin fit into an i64")
+}
+
+/// Parses `entry` if it is an event of type `E`.
+pub(crate) fn to_event<E: Event>(entry: &PersistedLogEntry) -> Option<E> {
+    let raw = entry.as_raw();
+
+    if raw.module_kind() == E::MODULE.as_ref() && raw.kind == E::KIND {
+        raw.to_event()
+    } else {
+        None
+    }
+}
+
+fn ledger_entry(
+    federation_id: FederationId,
+    timestamp_usecs: u64,
+    description: String,
+    postings: impl IntoIterator<Item = (LedgerAccount, i64)>,
+) -> LedgerEntry {
+    LedgerEntry {
+        federation_id,
+        timestamp_usecs,
+        description,
+        postings: postings
+            .into_iter()
+            .filter(|(_, amount_msats)| *amount_msats != 0)
+            .map(|(account, amount_msats)| LedgerPosting {
+                account,
+                amount_msats,
+            })
+            .collect(),
+    }
+}
+
+/// Books an outgoing payment, for which the gateway received the contract
+/// amount in ecash and paid the invoice amount plus the routing fee over
+/// lightning.
+fn outgoing_payment(
+    federation_id: FederationId,
+    timestamp_usecs: u64,
+    description: String,
+    contract_amount: Amount,
+    invoice_amount: Amount,
+    routing_fee: Option<Amount>,
+) -> Option<LedgerEntry> {
+    let fee = contract_amount.checked_sub(invoice_amount)?;
+    let routing_fee = routing_fee.unwrap_or(Amount::ZERO);
+
+    Some(ledger_entry(
+        federation_id,
+        timestamp_usecs,
+        description,
+        [
+            (LedgerAccount::Ecash, msats(contract_amount)),
+            (
+                LedgerAccount::Lightning,
+                -msats(invoice_amount) - msats(routing_fee),
+            ),
+            (LedgerAccount::FeeIncome, -msats(fee)),
+            (LedgerAccount::RoutingCost, msats(routing_fee)),
+        ],
+    ))
+}
+
+/// Books an incoming payment, for which the gateway received the invoice
+/// amount over lightning and funded the contract amount with ecash.
+fn incoming_payment(
+    federation_id: FederationId,
+    timestamp_usecs: u64,
+    description: String,
+    invoice_amount: Amount,
+    contract_amount: Amount,
+) -> Option<LedgerEntry> {
+    let fee = invoice_amount.checked_sub(contract_amount)?;
+
+    Some(ledger_entry(
+        federation_id,
+        timestamp_usecs,
+        description,
+        [
+            (LedgerAccount::Lightning, msats(invoice_amount)),
+            (LedgerAccount::Ecash, -msats(contract_amount)),
+            (LedgerAccount::FeeIncome, -msats(fee)),
+        ],
+    ))
+}
+
+fn payment_hash(image: &PaymentImage) -> Option<sha256::Hash> {
+    match image {
+        PaymentImage::Hash(hash) => Some(*hash),
+        PaymentImage::Point(_) => None,
+    }
+}
+
+/// Sums up the routing fees the gateway's lightning node reported by payment
+/// hash, a payment sent in parts reports a fee for every part.
+pub(crate) fn routing_fees(gateway_events: &[PersistedLogEntry]) -> HashMap<sha256::Hash, Amount> {
+    let mut routing_fees = HashMap::new();
+
+    for event in gateway_events
+        .iter()
+        .filter_map(to_event::<LightningRoutingFeePaid>)
+    {
+        *routing_fees
+            .entry(event.payment_hash)
+            .or_insert(Amount::ZERO) += event.routing_fee;
+    }
+
+    routing_fees
+}
+
+/// Turns the events of a federation's client and the gateway's own events into
+/// the ledger of the federation. Payments are booked when they succeed, with
+/// the amounts of their start events, such that only entries from
+/// `start_usecs` on are returned even though `client_events` reach further
+/// back.
+pub(crate) fn ledger_entries(
+    federation_id: FederationId,
+    client_events: &[PersistedLogEntry],
+    gateway_events: &[PersistedLogEntry],
+    routing_fees: &HashMap<sha256::Hash, Amount>,
+    start_usecs: u64,
+) -> Vec<LedgerEntry> {
+    let mut lnv1_outgoing = HashMap::new();
+    let mut lnv1_incoming = HashMap::new();
+    let mut lnv2_outgoing = HashMap::new();
+    let mut lnv2_incoming = HashMap::new();
+    let mut entries = Vec::new();
+
+    for entry in client_events {
+        let ts = entry.as_raw().ts_usecs;
+
+        if let Some(start) = to_event::<fedimint_gw_client::events::OutgoingPaymentStarted>(entry) {
+            lnv1_outgoing.insert(start.contract_id, start.invoice_amount);
+        } else if let Some(start) =
+            to_event::<fedimint_gw_client::events::IncomingPaymentStarted>(entry)
+        {
+            lnv1_incoming.insert(
+                start.payment_hash,
+                (start.invoice_amount, start.contract_amount),
+            );
+        } else if let Some(start) =
+            to_event::<fedimint_gwv2_client::events::OutgoingPaymentStarted>(entry)
+        {
+            lnv2_outgoing.insert(
+                start.outgoing_contract.payment_image.clone(),
+                (start.outgoing_contract.amount, start.invoice_amount),
+            );
+        } else if let Some(start) =
+            to_event::<fedimint_gwv2_client::events::IncomingPaymentStarted>(entry)
+        {
+            lnv2_incoming.insert(
+                start.incoming_contract_commitment.payment_image.clone(),
+                (
+                    start.invoice_amount,
+                    start.incoming_contract_commitment.amount,
+                ),
+            );
+        }
+
+        if ts < start_usecs {
+            continue;
+        }
+
+        if let Some(success) =
+            to_event::<fedimint_gw_client::events::OutgoingPaymentSucceeded>(entry)
+        {
+            let payment_hash = success.outgoing_contract.contract.hash;
+            entries.extend(
+                lnv1_outgoing
+                    .get(&success.contract_id)
+                    .and_then(|invoice_amount| {
+                        outgoing_payment(
+                            federation_id,
+                            ts,
+                            format!("LNv1 outgoing payment {payment_hash}"),
+                            success.outgoing_contract.amount,
+                            *invoice_amount,
+                            routing_fees.get(&payment_hash).copied(),
+                        )
+                    }),
+            );
+        } else if let Some(success) =
+            to_event::<fedimint_gw_client::events::IncomingPaymentSucceeded>(entry)
+        {
+            entries.extend(lnv1_incoming.get(&success.payment_hash).and_then(
+                |(invoice_amount, contract_amount)| {
+                    incoming_payment(
+                        federation_id,
+                        ts,
+                        format!("LNv1 incoming payment {}", success.payment_hash),
+                        *invoice_amount,
+                        *contract_amount,
+                    )
+                },
+            ));
+        } else if let Some(success) =
+            to_event::<fedimint_gwv2_client::events::OutgoingPaymentSucceeded>(entry)
+        {
+            let payment_hash = payment_hash(&success.payment_image);
+            entries.extend(lnv2_outgoing.get(&success.payment_image).and_then(
+                |(contract_amount, invoice_amount)| {
+                    outgoing_payment(
+                        federation_id,
+                        ts,
+                        match (success.target_federation, payment_hash) {
+                            (Some(target_federation), _) => {
+                                format!("LNv2 swap to federation {}", target_federation.to_prefix())
+                            }
+                            (None, Some(payment_hash)) => {
+                                format!("LNv2 outgoing payment {payment_hash}")
+                            }
+                            (None, None) => "LNv2 outgoing payment".to_string(),
+                        },
+                        *contract_amount,
+                        *invoice_amount,
+                        payment_hash
+                            .and_then(|payment_hash| routing_fees.get(&payment_hash).copied()),
+                    )
+                },
+            ));
+        } else if let Some(success) =
+            to_event::<fedimint_gwv2_client::events::IncomingPaymentSucceeded>(entry)
+        {
+            entries.extend(lnv2_incoming.get(&success.payment_image).and_then(
+                |(invoice_amount, contract_amount)| {
+                    incoming_payment(
+                        federation_id,
+                        ts,
+                        payment_hash(&success.payment_image).map_or_else(
+                            || "LNv2 incoming payment".to_string(),
+                            |payment_hash| format!("LNv2 incoming payment {payment_hash}"),
+                        ),
+                        *invoice_amount,
+                        *contract_amount,
+                    )
+                },
+            ));
+        } else if let Some(spent) = to_event::<OOBNotesSpent>(entry) {
+            entries.push(ledger_entry(
+                federation_id,
+                ts,
+                "Ecash spent out of band".to_string(),
+                [
+                    (LedgerAccount::Ecash, -msats(spent.spent_amount)),
+                    (LedgerAccount::Operator, msats(spent.spent_amount)),
+                ],
+            ));
+        } else if let Some(reissued) = to_event::<OOBNotesReissued>(entry) {
+            entries.push(ledger_entry(
+                federation_id,
+                ts,
+                "Ecash reissued".to_string(),
+                [
+                    (LedgerAccount::Ecash, msats(reissued.amount)),
+                    (LedgerAccount::Operator, -msats(reissued.amount)),
+                ],
+            ));
+        } else if let Some(deposit) = to_event::<DepositConfirmed>(entry) {
+            entries.push(ledger_entry(
+                federation_id,
+                ts,
+                format!("Peg-in {}:{}", deposit.txid, deposit.out_idx),
+                [
+                    (LedgerAccount::Ecash, msats(deposit.amount)),
+                    (LedgerAccount::Onchain, -msats(deposit.amount)),
+                ],
+            ));
+        }
+    }
+
+    for entry in gateway_events {
+        let ts = entry.as_raw().ts_usecs;
+
+        if let Some(peg_out) = to_event::<EcashPeggedOut>(entry)
+            && peg_out.federation_id == federation_id
+            && start_usecs <= ts
+        {
+            let amount = Amount::from_sats(peg_out.amount_sats);
+            let fee = Amount::from_sats(peg_out.fee_sats);
+
+            entries.push(ledger_entry(
+                federation_id,
+                ts,
+                format!("Peg-out {}", peg_out.txid),
+                [
+                    (LedgerAccount::Ecash, -msats(amount) - msats(fee)),
+                    (LedgerAccount::Onchain, msats(amount)),
+                    (LedgerAccount::OnchainFees, msats(fee)),
+                ],
+            ));
+        } else if let Some(order) = to_event::<LspChannelOrdered>(entry)
+            && order.federation_id == federation_id
+            && start_usecs <= ts
+        {
+            // The ecash that paid for the channel is booked against the operator
+            // when it is reissued, which this entry offsets.
+            let channel_size = Amount::from_sats(order.channel_size_sats);
+            let push_amount = Amount::from_sats(order.push_amount_sats);
+
+            entries.push(ledger_entry(
+                federation_id,
+                ts,
+                format!(
+                    "LSP channel to {} in {}",
+                    order.counterparty, order.funding_txid
+                ),
+                [
+                    (LedgerAccount::Operator, msats(order.ecash)),
+                    (LedgerAccount::Onchain, -msats(channel_size)),
+                    (
+                        LedgerAccount::Lightning,
+                        msats(channel_size) - msats(push_amount),
+                    ),
+                    (
+                        LedgerAccount::FeeIncome,
+                        msats(push_amount) - msats(order.ecash),
+                    ),
+                ],
+            ));
+        }
+    }
+
+    entries.sort_by_key(|entry| entry.timestamp_usecs);
+
+    entries
+}
+
+/// Groups the fees earned, the fee losses and the routing cost of `entries`
+/// into buckets of `interval` from `start_millis` until `end_millis`.
+pub(crate) fn revenue_buckets(
+    entries: &[LedgerEntry],
+    start_millis: u64,
+    end_millis: u64,
+    interval: Duration,
+) -> Vec<RevenueBucket> {
+    let interval_millis = interval.as_millis() as u64;
+
+    let mut buckets = (start_millis..end_millis)
+        .step_by(interval_millis as usize)
+        .map(|start_millis| RevenueBucket {
+            start_millis,
+            fees_earned: Amount::ZERO,
+            fee_losses: Amount::ZERO,
+            routing_cost: Amount::ZERO,
+            net_revenue_msats: 0,
+        })
+        .collect::<Vec<_>>();
+
+    for entry in entries {
+        let Some(bucket) = (entry.timestamp_usecs / 1000)
+            .checked_sub(start_millis)
+            .and_then(|offset_millis| buckets.get_mut((offset_millis / interval_millis) as usize))
+        else {
+            continue;
+        };
+
+        for posting in &entry.postings {
+            let amount = Amount::from_msats(posting.amount_msats.unsigned_abs());
+
+            match posting.account {
+                // Income is credited, so a fee the gateway earned is a negative
+                // posting and a positive one is a loss, e.g. of an LSP channel
+                // that pushed more than was paid for it
+                LedgerAccount::FeeIncome => {
+                    if posting.amount_msats < 0 {
+                        bucket.fees_earned += amount;
+                    } else {
+                        bucket.fee_losses += amount;
+                    }
+                    bucket.net_revenue_msats -= posting.amount_msats;
+                }
+                LedgerAccount::RoutingCost => {
+                    bucket.routing_cost += amount;
+                    bucket.net_revenue_msats -= posting.amount_msats;
+                }
+                _ => {}
+            }
+        }
+    }
+
+    buckets
+}
+
+fn account_name(account: LedgerAccount) -> &'static str {
+    match account {
+        LedgerAccount::Ecash => "Assets:Ecash",
+        LedgerAccount::Lightning => "Assets:Lightning",
+        LedgerAccount::Onchain => "Assets:Onchain",
+        LedgerAccount::Operator => "Equity:Operator",
+        LedgerAccount::FeeIncome => "Income:Fees",
+        LedgerAccount::RoutingCost => "Expenses:Routing",
+        LedgerAccount::OnchainFees => "Expenses:OnchainFees",
+    }
+}
+
+/// The Beancount account of `account` in the ledger of `federation_id`, whose
+/// components have to start with a capital letter or a digit.
+fn beancount_account(account: LedgerAccount, federation_id: FederationId) -> String {
+    format!(
+        "{}:F{}",
+        account_name(account),
+        federation_id.to_prefix().to_string().to_uppercase()
+    )
+}
+
+fn rfc3339(timestamp_usecs: u64) -> String {
+    DateTime::from_timestamp_micros(timestamp_usecs as i64)
+        .unwrap_or_default()
+        .to_rfc3339()
+}
+
+/// Exports `entries` as CSV with one row per posting.
+pub(crate) fn to_csv(entries: &[LedgerEntry]) -> String {
+    let mut csv = "timestamp,federation_id,description,account,amount_msats\n".to_string();
+
+    for entry in entries {
+        for posting in &entry.postings {
+            writeln!(
+                csv,
+                "{},{},\"{}\",{},{}",
+                rfc3339(entry.timestamp_usecs),
+                entry.federation_id,
+                entry.description.replace('"', "\"\""),
+                account_name(posting.account),
+                posting.amount_msats
+            )
+            .expect("Writing to a string cannot fail");
+        }
+    }
+
+    csv
+}
+
+/// Exports `entries` as a Beancount ledger denominated in millisatoshis, with
+/// separate accounts for every federation.
+pub(crate) fn to_beancount(entries: &[LedgerEntry]) -> String {
+    let mut beancount = "option \"operating_currency\" \"MSAT\"\n\n".to_string();
+
+    let accounts = entries
+        .iter()
+        .flat_map(|entry| {
+            entry
+                .postings
+                .iter()
+                .map(|posting| beancount_account(posting.account, entry.federation_id))
+        })
+        .collect::<BTreeSet<_>>();
+
+    for account in accounts {
+        writeln!(beancount, "1970-01-01 open {account} MSAT")
+            .expect("Writing to a string cannot fail");
+    }
+
+    for entry in entries {
+        let date = DateTime::from_timestamp_micros(entry.timestamp_usecs as i64)
+            .unwrap_or_default()
+            .format("%Y-%m-%d");
+
+        writeln!(
+            beancount,
+            "\n{date} * \"{}\"\n  federation_id: \"{}\"",
+            entry.description.replace('"', "'"),
+            entry.federation_id
+        )
+        .expect("Writing to a string cannot fail");
+
+        for posting in &entry.postings {
+            writeln!(
+                beancount,
+                "  {} {} MSAT",
+                beancount_account(posting.account, entry.federation_id),
+                posting.amount_msats
+            )
+            .expect("Writing to a string cannot fail");
+        }
+    }
+
+    beancount
+}
+
+impl Gateway {
+    /// Records the routing fee the lightning node reported for an outgoing
+    /// payment in the gateway's event log.
+    pub(crate) async fn record_routing_fee(
+        &self,
+        payment_hash: sha256::Hash,
+        response: &PayInvoiceResponse,
+    ) {
+        let Some(routing_fee) = response.routing_fee else {
+            return;
+        };
+
+        let mut dbtx = self.gateway_db.begin_transaction().await;
+        dbtx.log_event(
+            self.event_log_wakeup_tx.clone(),
+            None,
+            LightningRoutingFeePaid {
+                payment_hash,
+                routing_fee,
+            },
+        )
+        .await;
+        dbtx.commit_tx().await;
+    }
+
+    /// Records a peg-out in the gateway's event log.
+    pub(crate) async fn record_peg_out(
+        &self,
+        federation_id: FederationId,
+        response: &WithdrawResponse,
+        amount: bitcoin::Amount,
+    ) {
+        let mut dbtx = self.gateway_db.begin_transaction().await;
+        dbtx.log_event(
+            self.event_log_wakeup_tx.clone(),
+            None,
+            EcashPeggedOut {
+                federation_id,
+                txid: response.txid,
+                amount_sats: amount.to_sat(),
+                fee_sats: response.fees.amount().to_sat(),
+            },
+        )
+        .await;
+        dbtx.commit_tx().await;
+    }
+
+    /// Builds the ledger of all connected federations, or of `federation_id`,
+    /// from `start_millis` until `end_millis`.
+    async fn ledger(
+        &self,
+        start_millis: u64,
+        end_millis: u64,
+        federation_id: Option<FederationId>,
+    ) -> AdminResult<Vec<LedgerEntry>> {
+        if start_millis > end_millis {
+            return Err(AdminGatewayError::GatewayConfigurationError(
+                "Invalid time range".to_string(),
+            ));
+        }
+
+        // The event log is indexed in microseconds since the epoch, so both
+        // bounds have to be representable as such
+        let (Some(start_usecs), Some(_), Some(start), Some(end)) = (
+            start_millis.checked_mul(1000),
+            end_millis.checked_mul(1000),
+            UNIX_EPOCH.checked_add(Duration::from_millis(start_millis)),
+            UNIX_EPOCH.checked_add(Duration::from_millis(end_millis)),
+        ) else {
+            return Err(AdminGatewayError::GatewayConfigurationError(
+                "Invalid time range".to_string(),
+            ));
+        };
+
+        let lookback_start = start.checked_sub(PAYMENT_LOOKBACK).unwrap_or(UNIX_EPOCH);
+
+        let gateway_events = get_events_for_duration(&self.gateway_db, lookback_start, end).await;
+        let routing_fees = routing_fees(&gateway_events);
+
+        let federation_manager = self.federation_manager.read().await;
+        let federation_ids = match federation_id {
+            Some(federation_id) => {
+                if federation_manager.client(&federation_id).is_none() {
+                    return Err(FederationNotConnected {
+                        federation_id_prefix: federation_id.to_prefix(),
+                    }
+                    .into());
+                }
+
+                vec![federation_id]
+            }
+            None => federation_manager
+                .get_all_federation_configs()
+                .await
+                .into_keys()
+                .collect(),
+        };
+
+        let mut entries = Vec::new();
+
+        for federation_id in federation_ids {
+            let Some(client) = federation_manager.client(&federation_id) else {
+                continue;
+            };
+
+            let client_events =
+                get_events_for_duration(client.value().db(), lookback_start, end).await;
+
+            entries.extend(ledger_entries(
+                federation_id,
+                &client_events,
+                &gateway_events,
+                &routing_fees,
+                start_usecs,
+            ));
+        }
+
+        entries.sort_by_key(|entry| entry.timestamp_usecs);
+
+        Ok(entries)
+    }
+
+    /// Returns the double-entry ledger of the gateway's federations.
+    pub async fn handle_ledger_msg(&self, payload: LedgerPayload) -> AdminResult<LedgerResponse> {
+        let entries = self
+            .ledger(
+                payload.start_millis,
+                payload.end_millis,
+                payload.federation_id,
+            )
+            .await?;
+
+        Ok(LedgerResponse(entries))
+    }
+
+    /// Exports the ledger of the gateway's federations as CSV or Beancount.
+    pub async fn handle_export_ledger_msg(
+        &self,
+        payload: ExportLedgerPayload,
+    ) -> AdminResult<ExportLedgerResponse> {
+        let entries = self
+            .ledger(
+                payload.start_millis,
+                payload.end_millis,
+                payload.federation_id,
+            )
+            .await?;
+
+        let contents = match payload.format {
+            LedgerFormat::Csv => to_csv(&entries),
+            LedgerFormat::Beancount => to_beancount(&entries),
+        };
+
+        Ok(ExportLedgerResponse { contents })
+    }
+
+    /// Returns the fees earned and the routing cost over time.
+    pub(crate) async fn revenue(&self, payload: RevenuePayload) -> AdminResult<RevenueResponse> {
+        let interval = payload.interval.duration();
+
+        if payload.end_millis.saturating_sub(payload.start_millis)
+            > MAX_REVENUE_BUCKETS * interval.as_millis() as u64
+        {
+            return Err(AdminGatewayError::GatewayConfigurationError(format!(
+                "The time range may span at most {MAX_REVENUE_BUCKETS} intervals"
+            )));
+        }
+
+        let entries = self
+            .ledger(
+                payload.start_millis,
+                payload.end_millis,
+                payload.federation_id,
+            )
+            .await?;
+
+        Ok(RevenueResponse(revenue_buckets(
+            &entries,
+            payload.start_millis,
+            payload.end_millis,
+            interval,
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests;
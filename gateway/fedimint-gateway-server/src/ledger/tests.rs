@@ -0,0 +1,311 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use bitcoin::hashes::{Hash as _, sha256};
+use fedimint_core::Amount;
+use fedimint_core::core::OperationId;
+use fedimint_eventlog::PersistedLogEntry;
+use fedimint_gateway_common::{
+    EcashPeggedOut, LedgerAccount, LedgerEntry, LedgerPosting, LightningRoutingFeePaid,
+    LspChannelOrdered,
+};
+use fedimint_gw_client::events::{IncomingPaymentStarted, IncomingPaymentSucceeded};
+use fedimint_ln_common::contracts::ContractId;
+use fedimint_mint_client::events::{OOBNotesReissued, OOBNotesSpent};
+
+use super::{
+    ledger_entries, outgoing_payment, revenue_buckets, routing_fees, to_beancount, to_csv,
+};
+use crate::test_helpers::{federation, log_entry};
+
+fn posting(account: LedgerAccount, amount_msats: i64) -> LedgerPosting {
+    LedgerPosting {
+        account,
+        amount_msats,
+    }
+}
+
+fn incoming_payment_events(
+    payment_hash: sha256::Hash,
+    started_usecs: u64,
+) -> Vec<PersistedLogEntry> {
+    vec![
+        log_entry(
+            0,
+            started_usecs,
+            &IncomingPaymentStarted {
+                contract_id: ContractId::from_raw_hash(payment_hash),
+                payment_hash,
+                invoice_amount: Amount::from_msats(1_000_000),
+                contract_amount: Amount::from_msats(990_000),
+                operation_id: OperationId::new_random(),
+            },
+        ),
+        log_entry(
+            1,
+            started_usecs + 1_000_000,
+            &IncomingPaymentSucceeded {
+                payment_hash,
+                preimage: "00".repeat(32),
+            },
+        ),
+    ]
+}
+
+fn is_balanced(entry: &LedgerEntry) -> bool {
+    entry
+        .postings
+        .iter()
+        .map(|posting| posting.amount_msats)
+        .sum::<i64>()
+        == 0
+}
+
+#[test]
+fn incoming_payment_is_booked_with_its_fee() {
+    let federation_id = federation(1);
+    let events = incoming_payment_events(sha256::Hash::hash(b"preimage"), 10_000_000);
+
+    let entries = ledger_entries(federation_id, &events, &[], &HashMap::new(), 0);
+
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].timestamp_usecs, 11_000_000);
+    assert_eq!(
+        entries[0].postings,
+        vec![
+            posting(LedgerAccount::Lightning, 1_000_000),
+            posting(LedgerAccount::Ecash, -990_000),
+            posting(LedgerAccount::FeeIncome, -10_000),
+        ]
+    );
+    assert!(is_balanced(&entries[0]));
+}
+
+#[test]
+fn payments_are_booked_when_they_succeed() {
+    let federation_id = federation(1);
+    let events = incoming_payment_events(sha256::Hash::hash(b"preimage"), 10_000_000);
+
+    // The start event lies before the range but the payment succeeded within it
+    let entries = ledger_entries(federation_id, &events, &[], &HashMap::new(), 10_500_000);
+    assert_eq!(entries.len(), 1);
+
+    // The payment succeeded before the range
+    let entries = ledger_entries(federation_id, &events, &[], &HashMap::new(), 11_500_000);
+    assert!(entries.is_empty());
+}
+
+#[test]
+fn outgoing_payment_books_routing_cost() {
+    let entry = outgoing_payment(
+        federation(1),
+        0,
+        "LNv1 outgoing payment".to_string(),
+        Amount::from_msats(101_000),
+        Amount::from_msats(100_000),
+        Some(Amount::from_msats(300)),
+    )
+    .expect("contract covers the invoice");
+
+    assert_eq!(
+        entry.postings,
+        vec![
+            posting(LedgerAccount::Ecash, 101_000),
+            posting(LedgerAccount::Lightning, -100_300),
+            posting(LedgerAccount::FeeIncome, -1_000),
+            posting(LedgerAccount::RoutingCost, 300),
+        ]
+    );
+    assert!(is_balanced(&entry));
+}
+
+#[test]
+fn routing_fees_of_parts_are_summed() {
+    let payment_hash = sha256::Hash::hash(b"preimage");
+    let events = [100, 200]
+        .into_iter()
+        .enumerate()
+        .map(|(id, msats)| {
+            log_entry(
+                id as u64,
+                0,
+                &LightningRoutingFeePaid {
+                    payment_hash,
+                    routing_fee: Amount::from_msats(msats),
+                },
+            )
+        })
+        .collect::<Vec<_>>();
+
+    assert_eq!(
+        routing_fees(&events).get(&payment_hash),
+        Some(&Amount::from_msats(300))
+    );
+}
+
+#[test]
+fn oob_notes_and_peg_outs_are_booked() {
+    let federation_id = federation(1);
+    let client_events = vec![
+        log_entry(
+            0,
+            1,
+            &OOBNotesSpent {
+                requested_amount: Amount::from_msats(5_000),
+                spent_amount: Amount::from_msats(5_000),
+                timeout: None,
+                include_invite: false,
+            },
+        ),
+        log_entry(
+            1,
+            2,
+            &OOBNotesReissued {
+                amount: Amount::from_msats(2_000),
+            },
+        ),
+    ];
+    let peg_out = |federation_id| EcashPeggedOut {
+        federation_id,
+        txid: "02".repeat(32).parse().expect("valid txid"),
+        amount_sats: 10_000,
+        fee_sats: 500,
+    };
+    let gateway_events = vec![
+        log_entry(0, 3, &peg_out(federation_id)),
+        log_entry(1, 4, &peg_out(federation(2))),
+    ];
+
+    let entries = ledger_entries(
+        federation_id,
+        &client_events,
+        &gateway_events,
+        &HashMap::new(),
+        0,
+    );
+
+    assert_eq!(entries.len(), 3);
+    assert!(entries.iter().all(is_balanced));
+    assert_eq!(
+        entries[0].postings,
+        vec![
+            posting(LedgerAccount::Ecash, -5_000),
+            posting(LedgerAccount::Operator, 5_000),
+        ]
+    );
+    assert_eq!(
+        entries[1].postings,
+        vec![
+            posting(LedgerAccount::Ecash, 2_000),
+            posting(LedgerAccount::Operator, -2_000),
+        ]
+    );
+    assert_eq!(
+        entries[2].postings,
+        vec![
+            posting(LedgerAccount::Ecash, -10_500_000),
+            posting(LedgerAccount::Onchain, 10_000_000),
+            posting(LedgerAccount::OnchainFees, 500_000),
+        ]
+    );
+}
+
+#[test]
+fn lsp_channel_orders_are_booked() {
+    let federation_id = federation(1);
+    let gateway_events = vec![log_entry(
+        0,
+        1,
+        &LspChannelOrdered {
+            federation_id,
+            counterparty: "0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798"
+                .parse()
+                .expect("valid public key"),
+            funding_txid: "03".repeat(32).parse().expect("valid txid"),
+            channel_size_sats: 148_000,
+            push_amount_sats: 48_000,
+            ecash: Amount::from_msats(50_000_500),
+        },
+    )];
+
+    let entries = ledger_entries(federation_id, &[], &gateway_events, &HashMap::new(), 0);
+
+    assert_eq!(entries.len(), 1);
+    assert!(is_balanced(&entries[0]));
+    assert_eq!(
+        entries[0].postings,
+        vec![
+            posting(LedgerAccount::Operator, 50_000_500),
+            posting(LedgerAccount::Onchain, -148_000_000),
+            posting(LedgerAccount::Lightning, 100_000_000),
+            posting(LedgerAccount::FeeIncome, -2_000_500),
+        ]
+    );
+}
+
+#[test]
+fn revenue_is_grouped_into_buckets() {
+    let entry = |timestamp_usecs, fee_msats, routing_cost_msats| LedgerEntry {
+        federation_id: federation(1),
+        timestamp_usecs,
+        description: String::new(),
+        postings: vec![
+            posting(LedgerAccount::FeeIncome, -fee_msats),
+            posting(LedgerAccount::RoutingCost, routing_cost_msats),
+        ],
+    };
+    let entries = [
+        entry(1_000_000, 1_000, 300),
+        entry(2_000_000, 500, 0),
+        entry(2_500_000, -200, 0),
+        entry(7_500_000, 100, 400),
+        entry(20_000_000, 10_000, 0),
+    ];
+
+    let buckets = revenue_buckets(&entries, 1_000, 10_000, Duration::from_secs(3));
+
+    assert_eq!(
+        buckets
+            .iter()
+            .map(|bucket| bucket.start_millis)
+            .collect::<Vec<_>>(),
+        vec![1_000, 4_000, 7_000]
+    );
+    assert_eq!(buckets[0].fees_earned, Amount::from_msats(1_500));
+    assert_eq!(buckets[0].fee_losses, Amount::from_msats(200));
+    assert_eq!(buckets[0].routing_cost, Amount::from_msats(300));
+    assert_eq!(buckets[0].net_revenue_msats, 1_000);
+    assert_eq!(buckets[1].net_revenue_msats, 0);
+    assert_eq!(buckets[2].net_revenue_msats, -300);
+}
+
+#[test]
+fn ledger_is_exported_as_csv_and_beancount() {
+    let federation_id = federation(1);
+    let entries = ledger_entries(
+        federation_id,
+        &incoming_payment_events(sha256::Hash::hash(b"preimage"), 0),
+        &[],
+        &HashMap::new(),
+        0,
+    );
+
+    let csv = to_csv(&entries);
+    let mut lines = csv.lines();
+    assert_eq!(
+        lines.next(),
+        Some("timestamp,federation_id,description,account,amount_msats")
+    );
+    assert_eq!(lines.clone().count(), 3);
+    assert!(
+        lines
+            .next()
+            .expect("row for every posting")
+            .ends_with(",Assets:Lightning,1000000")
+    );
+
+    let beancount = to_beancount(&entries);
+    assert!(beancount.contains("1970-01-01 open Assets:Ecash:F01010101 MSAT"));
+    assert!(beancount.contains("1970-01-01 * \"LNv1 incoming payment"));
+    assert!(beancount.contains("  Income:Fees:F01010101 -10000 MSAT"));
+}
@@ -14,36 +14,49 @@
 #![allow(clippy::large_futures)]
 #![allow(clippy::struct_field_names)]
 
+mod api_tokens;
 pub mod client;
 pub mod config;
+mod dr_backup;
 pub mod envs;
 mod error;
 mod events;
 mod federation_manager;
 mod federation_status;
+mod fee_schedule;
+mod high_availability;
 mod iroh_server;
+mod ledger;
+mod liquidity;
+mod lsp;
 mod metrics;
 mod rate_limit;
 mod registration_health;
+mod risk;
 pub mod rpc_server;
+#[cfg(test)]
+mod test_helpers;
 mod types;
 
 use std::collections::{BTreeMap, BTreeSet};
 use std::env;
 use std::fmt::Display;
 use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
 use std::sync::Arc;
 use std::time::{Duration, UNIX_EPOCH};
 
 use anyhow::{Context, anyhow, ensure};
 use async_trait::async_trait;
-use bitcoin::hashes::sha256;
+use bitcoin::hashes::{Hash as _, sha256};
+use bitcoin::secp256k1::Scalar;
 use bitcoin::{Address, Network, Txid, secp256k1};
 use clap::Parser;
 use client::GatewayClientBuilder;
 pub use config::GatewayParameters;
 use config::{DatabaseBackend, GatewayOpts};
+pub use dr_backup::{BundleDestination, DisasterRecoveryParameters};
 use envs::FM_GATEWAY_SKIP_WAIT_FOR_SYNC_ENV;
 use error::FederationNotConnected;
 use events::ALL_GATEWAY_EVENTS;
@@ -55,9 +68,12 @@
 use fedimint_client::secret::RootSecretStrategy;
 use fedimint_client::{Client, ClientHandleArc};
 use fedimint_core::base32::{self, FEDIMINT_PREFIX};
-use fedimint_core::config::FederationId;
+use fedimint_core::config::{FederationId, FederationIdPrefix};
 use fedimint_core::core::OperationId;
-use fedimint_core::db::{Committable, Database, DatabaseTransaction, apply_migrations};
+use fedimint_core::db::{
+    AutocommitError, Committable, Database, DatabaseTransaction, apply_migrations,
+};
+use fedimint_core::encoding::Encodable as _;
 use fedimint_core::envs::is_env_var_set;
 use fedimint_core::invite_code::InviteCode;
 use fedimint_core::module::CommonModuleInit;
@@ -73,22 +89,30 @@
     Amount, BitcoinAmountOrAll, PeerId, TieredCounts, crit, fedimint_build_code_version_env,
     get_network_for_address,
 };
-use fedimint_eventlog::{DBTransactionEventLogExt, EventLogId, StructuredPaymentEvents};
+use fedimint_eventlog::{
+    DBTransactionEventLogExt, EventLogId, StructuredPaymentEvents, run_event_log_ordering_task,
+};
 use fedimint_gateway_common::{
     BackupPayload, ChainSource, CloseChannelsWithPeerRequest, CloseChannelsWithPeerResponse,
     ConnectFedPayload, ConnectPeerRequest, ConnectorType, CreateInvoiceForOperatorPayload,
-    CreateOfferPayload, CreateOfferResponse, DepositAddressPayload, DepositAddressRecheckPayload,
+    CreateLnurlWithdrawPayload, CreateLnurlWithdrawResponse, CreateOfferPayload,
+    CreateOfferResponse, DepositAddressPayload, DepositAddressRecheckPayload,
     FederationBalanceInfo, FederationConfig, FederationInfo, GatewayBalances, GatewayFedConfig,
     GatewayInfo, GetInvoiceRequest, GetInvoiceResponse, LeaveFedPayload, LightningInfo,
     LightningMode, ListTransactionsPayload, ListTransactionsResponse, MnemonicResponse,
     OpenChannelRequest, PayInvoiceForOperatorPayload, PayOfferPayload, PayOfferResponse,
-    PaymentLogPayload, PaymentLogResponse, PaymentStats, PaymentSummaryPayload,
-    PaymentSummaryResponse, PeginFromOnchainPayload, ReceiveEcashPayload, ReceiveEcashResponse,
-    RegisteredProtocol, SendOnchainRequest, SetChannelFeesRequest, SetFeesPayload,
-    SetMnemonicPayload, SpendEcashPayload, SpendEcashResponse, V1_API_ENDPOINT, WithdrawPayload,
-    WithdrawPreviewPayload, WithdrawPreviewResponse, WithdrawResponse, WithdrawToOnchainPayload,
+    PaymentDirection, PaymentLogPayload, PaymentLogResponse, PaymentStats, PaymentStatus,
+    PaymentSummaryPayload, PaymentSummaryResponse, PeginFromOnchainPayload, ReceiveEcashPayload,
+    ReceiveEcashResponse, RegisteredProtocol, RevenuePayload, RevenueResponse, SendOnchainRequest,
+    SetChannelFeesRequest, SetFeesPayload, SetMnemonicPayload, SpendEcashPayload,
+    SpendEcashResponse, V1_API_ENDPOINT, WithdrawPayload, WithdrawPreviewPayload,
+    WithdrawPreviewResponse, WithdrawResponse, WithdrawToOnchainPayload,
+};
+use fedimint_gateway_server_db::{
+    FetchedOfferInvoice, GatewayDbtxNcExt as _, HeldHtlc, HoldInvoice, HoldInvoiceState,
+    LnurlWithdrawLink, LnurlWithdrawal, OfferPaymentContract, RegisteredOffer, StaticLnurlContract,
+    get_gatewayd_database_migrations,
 };
-use fedimint_gateway_server_db::{GatewayDbtxNcExt as _, get_gatewayd_database_migrations};
 pub use fedimint_gateway_ui::IAdminGateway;
 use fedimint_gw_client::events::compute_lnv1_stats;
 use fedimint_gw_client::pay::{OutgoingPaymentError, OutgoingPaymentErrorType};
@@ -104,19 +128,30 @@
 use fedimint_lightning::{
     CreateInvoiceRequest, ILnRpcClient, InterceptPaymentRequest, InterceptPaymentResponse,
     InvoiceDescription, LightningContext, LightningRpcError, LnRpcTracked, Lnv2HoldInvoiceFilter,
-    PayInvoiceResponse, PaymentAction, RouteHtlcStream, ldk,
+    OfferPayment, PayInvoiceResponse, PaymentAction, RouteHtlcStream, bolt12_offer_id, cln, ldk,
+    simulated,
 };
 use fedimint_ln_client::pay::PaymentData;
 use fedimint_ln_common::LightningCommonInit;
 use fedimint_ln_common::config::LightningClientConfig;
 use fedimint_ln_common::contracts::outgoing::OutgoingContractAccount;
 use fedimint_ln_common::contracts::{IdentifiableContract, Preimage};
-use fedimint_lnurl::VerifyResponse;
-use fedimint_lnv2_common::Bolt11InvoiceDescription;
-use fedimint_lnv2_common::contracts::{IncomingContract, PaymentImage};
+use fedimint_lnurl::{
+    InvoiceResponse, PayMetadata, PayResponse, VerifyResponse, pay_request_tag,
+    withdraw_request_tag,
+};
+use fedimint_lnv2_common::contracts::{IncomingContract, PaymentImage, fee_encoded_expiration};
 use fedimint_lnv2_common::gateway_api::{
-    CreateBolt11InvoicePayload, MAX_INVOICE_EXPIRY_SECS, PaymentFee, RoutingInfo,
-    SendPaymentPayload,
+    CreateBolt11InvoicePayload, CreateBolt12OfferPayload, CreateHoldBolt11InvoicePayload,
+    FetchBolt12InvoicePayload, HoldInvoiceStatus, HoldInvoiceStatusPayload, MAX_HOLD_INVOICE_SECS,
+    MAX_INVOICE_EXPIRY_SECS, MAX_STATIC_LNURL_CONTRACTS, PaymentFee, ProbePaymentPayload,
+    RegisterStaticLnurlContractsPayload, ResolveHoldInvoicePayload, RoutePreview, RoutingInfo,
+    SendPaymentPayload, register_static_lnurl_contracts_message, resolve_hold_invoice_message,
+};
+use fedimint_lnv2_common::lnurl::LnurlRequest;
+use fedimint_lnv2_common::{
+    Bolt11InvoiceDescription, Bolt12OfferInvoice, ContractId, KeysendPayment, LightningInvoice,
+    MINIMUM_INCOMING_CONTRACT_AMOUNT, tweak,
 };
 use fedimint_logging::LOG_GATEWAY;
 use fedimint_mint_client::{MintClientInit, MintClientModule, OOBNotes, ReissueExternalNotesState};
@@ -125,16 +160,20 @@
 };
 use fedimint_wallet_client::{PegOutFees, WalletClientInit, WalletClientModule, WithdrawState};
 use futures::stream::StreamExt;
+use hex::FromHex as _;
 use lightning_invoice::{Bolt11Invoice, RoutingFees};
 use rand::rngs::OsRng;
-use tokio::sync::RwLock;
+use tokio::sync::{Mutex, RwLock, watch};
 use tracing::{debug, info, info_span, warn};
 
+use crate::api_tokens::ApiTokenUseLog;
 use crate::envs::FM_GATEWAY_MNEMONIC_ENV;
 use crate::error::{AdminGatewayError, LNv1Error, LNv2Error, PublicGatewayError};
 use crate::events::get_events_for_duration;
+use crate::high_availability::LeaderLease;
 use crate::rate_limit::TokenBucketRateLimiter;
 use crate::registration_health::RegistrationHealthTracker;
+use crate::risk::{OutgoingPaymentId, RiskTracker};
 use crate::rpc_server::run_webserver;
 use crate::types::PrettyInterceptPaymentRequest;
 
@@ -152,6 +191,16 @@
 /// creation endpoint.
 const DEFAULT_INVOICE_RATE_LIMIT_PER_SECOND: u32 = 5;
 
+/// Default number of seconds the leader lease lasts in high availability mode.
+const DEFAULT_HA_LEASE_SECS: u64 = 30;
+
+/// Default number of seconds between two exports of a disaster recovery
+/// bundle.
+const DEFAULT_DR_BACKUP_INTERVAL_SECS: u64 = 60 * 60;
+
+/// Default number of disaster recovery bundles kept in the backup directory.
+const DEFAULT_DR_BACKUP_RETENTION: u32 = 48;
+
 /// Default Bitcoin network for testing purposes.
 pub const DEFAULT_NETWORK: Network = Network::Regtest;
 
@@ -168,17 +217,68 @@
 /// simply poll again.
 const VERIFY_WAIT_TIMEOUT: Duration = Duration::from_secs(30);
 
+/// Smallest amount of the payments a recipient may register for its static
+/// LNURL served by the gateway.
+const LNURL_MIN_SENDABLE_MSAT: u64 = 100_000;
+
+/// Largest amount of the payments a recipient may register for its static
+/// LNURL served by the gateway.
+const LNURL_MAX_SENDABLE_MSAT: u64 = 100_000_000_000;
+
+/// Expiry of the invoices created for payments to a static LNURL.
+const LNURL_INVOICE_EXPIRY_SECS: u32 = 3600;
+
+/// Base fee the lightning node may pay to route an LNURL-withdraw payment, on
+/// top of one percent of the amount.
+const LNURL_WITHDRAW_BASE_FEE_MSAT: u64 = 50;
+
+/// Maximum CLTV delay of the route of an LNURL-withdraw payment.
+const LNURL_WITHDRAW_MAX_DELAY: u64 = 1008;
+
+/// Interval between attempts to pay the invoice of an LNURL-withdrawal whose
+/// payment has not reached a final state.
+const LNURL_WITHDRAW_RETRY_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Number of attempts to pay the invoice of an LNURL-withdrawal that leave no
+/// trace of the payment, after which the payment is considered failed.
+const LNURL_WITHDRAW_MAX_UNSTARTED_ATTEMPTS: u32 = 5;
+
 pub type Result<T> = std::result::Result<T, PublicGatewayError>;
 pub type AdminResult<T> = std::result::Result<T, AdminGatewayError>;
 
 /// Name of the gateway's database that is used for metadata and configuration
 /// storage.
-const DB_FILE: &str = "gatewayd.db";
+pub const DB_FILE: &str = "gatewayd.db";
 
 /// Name of the folder that the gateway uses to store its node database when
 /// running in LDK mode.
 const LDK_NODE_DB_FOLDER: &str = "ldk_node";
 
+/// Opens the gateway's database at `db_path` with the configured backend.
+async fn open_gateway_db(
+    db_path: PathBuf,
+    db_backend: DatabaseBackend,
+) -> anyhow::Result<Database> {
+    let decoders = ModuleDecoderRegistry::default();
+
+    Ok(match db_backend {
+        DatabaseBackend::RocksDb => {
+            debug!(target: LOG_GATEWAY, "Using RocksDB database backend");
+            Database::new(
+                fedimint_rocksdb::RocksDb::build(db_path).open().await?,
+                decoders,
+            )
+        }
+        DatabaseBackend::CursedRedb => {
+            debug!(target: LOG_GATEWAY, "Using CursedRedb database backend");
+            Database::new(
+                fedimint_cursed_redb::MemAndRedb::new(db_path).await?,
+                decoders,
+            )
+        }
+    })
+}
+
 #[cfg_attr(doc, aquamarine::aquamarine)]
 /// ```mermaid
 /// graph LR
@@ -280,6 +380,7 @@ pub async fn new_with_builder(
         iroh_dns: Option<SafeUrl>,
         #[builder(default)] iroh_relays: Vec<SafeUrl>,
         metrics_listen: Option<SocketAddr>,
+        #[builder(default)] enable_keysend: bool,
     ) -> anyhow::Result<Gateway> {
         let versioned_api = api_addr.map(|addr| {
             addr.join(V1_API_ENDPOINT)
@@ -308,9 +409,12 @@ pub async fn new_with_builder(
                 iroh_dns,
                 iroh_relays,
                 skip_setup: true,
+                enable_keysend,
                 metrics_listen,
                 invoice_rate_limit_burst: DEFAULT_INVOICE_RATE_LIMIT_BURST,
                 invoice_rate_limit_per_second: DEFAULT_INVOICE_RATE_LIMIT_PER_SECOND,
+                high_availability: None,
+                disaster_recovery: None,
             },
             gateway_db,
             client_builder,
@@ -376,6 +480,9 @@ pub struct Gateway {
     /// The default transaction fees for new federations
     default_transaction_fees: PaymentFee,
 
+    /// Whether LNv2 clients may send keysend payments through the gateway.
+    enable_keysend: bool,
+
     /// The secret key for the Iroh `Endpoint`
     iroh_sk: iroh::SecretKey,
 
@@ -398,6 +505,37 @@ pub struct Gateway {
 
     /// Rate limiter for the public invoice creation endpoint.
     invoice_rate_limiter: Arc<TokenBucketRateLimiter>,
+
+    /// The gateway's exposure to its federations and their circuit breakers.
+    risk_tracker: RiskTracker,
+
+    /// Wakes up the task that orders new events into the gateway's own event
+    /// log, which is kept separately from the event logs of the federation
+    /// clients.
+    event_log_wakeup_tx: watch::Sender<()>,
+
+    /// Serializes settling and cancelling hold invoices, whose state is only
+    /// recorded once their HTLC has been resolved.
+    hold_invoice_lock: Arc<Mutex<()>>,
+
+    /// Serializes the callbacks of LNURL-withdraw links, such that every
+    /// callback checks the balance against all withdrawals accepted before.
+    lnurl_withdraw_lock: Arc<Mutex<()>>,
+
+    /// The LSP channel orders that are being advanced, such that an order is
+    /// never resumed while it is being processed. The lock is only held to
+    /// claim or release an order.
+    lsp_orders_in_progress: Arc<std::sync::Mutex<BTreeSet<sha256::Hash>>>,
+
+    /// When the authorized uses of the API tokens were last recorded in the
+    /// audit log.
+    api_token_use_log: Arc<ApiTokenUseLog>,
+
+    /// Leader lease of an instance running as one of an active/standby pair.
+    leader_lease: Option<Arc<LeaderLease>>,
+
+    /// Schedule and destination of the encrypted disaster recovery bundles.
+    disaster_recovery: Option<DisasterRecoveryParameters>,
 }
 
 impl std::fmt::Debug for Gateway {
@@ -426,7 +564,7 @@ async fn withdraw_v2(
     wallet_module: &fedimint_walletv2_client::WalletClientModule,
     address: &Address,
     amount: BitcoinAmountOrAll,
-) -> AdminResult<WithdrawResponse> {
+) -> AdminResult<(WithdrawResponse, bitcoin::Amount)> {
     let fee = wallet_module
         .send_fee()
         .await
@@ -484,7 +622,7 @@ async fn withdraw_v2(
     match result {
         fedimint_walletv2_client::FinalSendOperationState::Success(txid) => {
             info!(target: LOG_GATEWAY, amount = %withdraw_amount, address = %address, "Sent funds via walletv2");
-            Ok(WithdrawResponse { txid, fees })
+            Ok((WithdrawResponse { txid, fees }, withdraw_amount))
         }
         fedimint_walletv2_client::FinalSendOperationState::Aborted => {
             Err(AdminGatewayError::WithdrawError {
@@ -606,26 +744,20 @@ pub async fn new_with_default_modules(
     ) -> anyhow::Result<Gateway> {
         let opts = GatewayOpts::parse();
         let gateway_parameters = opts.to_gateway_parameters()?;
-        let decoders = ModuleDecoderRegistry::default();
-
-        let db_path = opts.data_dir.join(DB_FILE);
-        let gateway_db = match opts.db_backend {
-            DatabaseBackend::RocksDb => {
-                debug!(target: LOG_GATEWAY, "Using RocksDB database backend");
-                Database::new(
-                    fedimint_rocksdb::RocksDb::build(db_path).open().await?,
-                    decoders,
-                )
-            }
-            DatabaseBackend::CursedRedb => {
-                debug!(target: LOG_GATEWAY, "Using CursedRedb database backend");
-                Database::new(
-                    fedimint_cursed_redb::MemAndRedb::new(db_path).await?,
-                    decoders,
-                )
+
+        // A standby must not open the database or intercept HTLCs, so it waits
+        // for the leader lease before doing anything else
+        let leader_lease = match &gateway_parameters.high_availability {
+            Some(params) => {
+                let leader_lease = Arc::new(LeaderLease::new(params.clone()));
+                let expires_at_secs = leader_lease.wait_for_leadership().await;
+                Some((leader_lease, expires_at_secs))
             }
+            None => None,
         };
 
+        let gateway_db = open_gateway_db(opts.data_dir.join(DB_FILE), opts.db_backend).await?;
+
         // Apply database migrations before using the database to ensure old database
         // structures are readable.
         apply_migrations(
@@ -714,7 +846,7 @@ pub async fn new_with_default_modules(
             "Starting gatewayd",
         );
 
-        Gateway::new(
+        let mut gateway = Gateway::new(
             opts.mode,
             gateway_parameters,
             gateway_db,
@@ -722,7 +854,18 @@ pub async fn new_with_default_modules(
             gateway_state,
             chain_source,
         )
-        .await
+        .await?;
+
+        // Hand over the lease that was acquired before opening the database,
+        // which tracks how long this instance holds it
+        if let Some((leader_lease, expires_at_secs)) = leader_lease {
+            gateway
+                .registration_health
+                .record_leader_lease(expires_at_secs);
+            gateway.leader_lease = Some(leader_lease);
+        }
+
+        Ok(gateway)
     }
 
     /// Helper function for creating a gateway from either
@@ -738,6 +881,13 @@ async fn new(
         let num_route_hints = gateway_parameters.num_route_hints;
         let network = gateway_parameters.network;
 
+        if let LightningMode::Simulated { .. } = lightning_mode {
+            ensure!(
+                network == Network::Regtest,
+                "The simulated lightning network only runs on regtest, not {network}"
+            );
+        }
+
         let task_group = TaskGroup::new();
         task_group.install_kill_handler();
 
@@ -758,7 +908,7 @@ async fn new(
             );
         }
 
-        Ok(Self {
+        let gateway = Self {
             federation_manager: Arc::new(RwLock::new(FederationManager::new())),
             lightning_mode,
             state: Arc::new(RwLock::new(gateway_state)),
@@ -776,6 +926,7 @@ async fn new(
             chain_source,
             default_routing_fees: gateway_parameters.default_routing_fees,
             default_transaction_fees: gateway_parameters.default_transaction_fees,
+            enable_keysend: gateway_parameters.enable_keysend,
             iroh_sk,
             iroh_dns: gateway_parameters.iroh_dns,
             iroh_relays: gateway_parameters.iroh_relays,
@@ -786,7 +937,23 @@ async fn new(
                 gateway_parameters.invoice_rate_limit_burst,
                 gateway_parameters.invoice_rate_limit_per_second,
             )),
-        })
+            risk_tracker: RiskTracker::default(),
+            event_log_wakeup_tx: watch::channel(()).0,
+            hold_invoice_lock: Arc::new(Mutex::new(())),
+            lnurl_withdraw_lock: Arc::new(Mutex::new(())),
+            lsp_orders_in_progress: Arc::new(std::sync::Mutex::new(BTreeSet::new())),
+            api_token_use_log: Arc::new(ApiTokenUseLog::default()),
+            leader_lease: gateway_parameters
+                .high_availability
+                .map(|params| Arc::new(LeaderLease::new(params))),
+            disaster_recovery: gateway_parameters.disaster_recovery,
+        };
+
+        // Events are ordered from the start, such that events logged before the
+        // gateway runs, e.g. in tests, are readable as well.
+        gateway.spawn_event_log_ordering_task();
+
+        Ok(gateway)
     }
 
     async fn load_or_create_gateway_keypair(
@@ -837,11 +1004,21 @@ pub async fn run(
         mnemonic_receiver: tokio::sync::broadcast::Receiver<()>,
     ) -> anyhow::Result<TaskShutdownToken> {
         install_crypto_provider().await;
+        self.spawn_leader_lease_task();
         self.register_clients_timer();
         self.load_clients().await?;
         self.start_gateway(runtime, mnemonic_receiver.resubscribe());
         self.spawn_backup_task();
         self.spawn_prune_registered_contracts_task();
+        self.spawn_credit_offer_payments_task();
+        self.spawn_cancel_expired_hold_invoices_task();
+        self.resume_lnurl_withdrawals().await;
+        self.spawn_channel_order_task();
+        self.spawn_liquidity_manager_task();
+        self.spawn_fee_schedule_task();
+        self.spawn_risk_monitor_task();
+        self.spawn_dr_backup_task();
+        self.spawn_restored_channel_peers_task();
         // start metrics server
         fedimint_metrics::spawn_api_server(self.metrics_listen, self.task_group.clone()).await?;
         // start webserver last to avoid handling requests before fully initialized
@@ -851,6 +1028,23 @@ pub async fn run(
         Ok(shutdown_receiver)
     }
 
+    /// Spawns the task that orders the events logged to the gateway's own
+    /// event log, such as the use of API tokens.
+    fn spawn_event_log_ordering_task(&self) {
+        let gateway_db = self.gateway_db.clone();
+        let log_ordering_wakeup_rx = self.event_log_wakeup_tx.subscribe();
+        self.task_group
+            .spawn_cancellable_silent("gateway event log ordering", async move {
+                run_event_log_ordering_task(
+                    gateway_db,
+                    log_ordering_wakeup_rx,
+                    watch::channel(()).0,
+                    tokio::sync::broadcast::channel(1).0,
+                )
+                .await;
+            });
+    }
+
     /// Spawns a background task that checks every `BACKUP_UPDATE_INTERVAL` to
     /// see if any federations need to be backed up.
     fn spawn_backup_task(&self) {
@@ -894,7 +1088,9 @@ fn spawn_prune_registered_contracts_task(&self) {
                         .as_secs();
 
                     let mut dbtx = self_copy.gateway_db.begin_transaction().await;
-                    let num_pruned = dbtx.prune_registered_incoming_contracts(cutoff_secs).await;
+                    let num_pruned = dbtx.prune_registered_incoming_contracts(cutoff_secs).await
+                        + dbtx.prune_fetched_offer_invoices(cutoff_secs).await
+                        + dbtx.prune_hold_invoices(cutoff_secs).await;
                     match dbtx.commit_tx_result().await {
                         Ok(()) => {
                             if num_pruned > 0 {
@@ -918,6 +1114,71 @@ fn spawn_prune_registered_contracts_task(&self) {
         );
     }
 
+    /// Spawns a background task that fails back the HTLCs of hold invoices the
+    /// recipient has neither settled nor cancelled before the deadline.
+    fn spawn_cancel_expired_hold_invoices_task(&self) {
+        let self_copy = self.clone();
+        self.task_group
+            .spawn_cancellable_silent("cancel expired hold invoices", async move {
+                const CANCEL_INTERVAL: Duration = Duration::from_secs(5);
+
+                let mut interval = tokio::time::interval(CANCEL_INTERVAL);
+                loop {
+                    interval.tick().await;
+
+                    let now_secs = duration_since_epoch().as_secs();
+
+                    let held_invoices = self_copy
+                        .gateway_db
+                        .begin_transaction_nc()
+                        .await
+                        .load_held_invoices()
+                        .await;
+
+                    for (contract_id, hold_invoice) in held_invoices {
+                        if let HoldInvoiceState::Held(held) = &hold_invoice.state
+                            && held.deadline_secs <= now_secs
+                            && let Err(err) = self_copy
+                                .cancel_hold_invoice(
+                                    contract_id,
+                                    "The hold invoice has not been settled in time",
+                                )
+                                .await
+                        {
+                            warn!(
+                                target: LOG_GATEWAY,
+                                err = %err.fmt_compact(),
+                                "Failed to cancel expired hold invoice"
+                            );
+                        }
+                    }
+                }
+            });
+    }
+
+    /// Spawns a background task that periodically funds incoming contracts for
+    /// payments to BOLT12 offers the gateway created on behalf of clients.
+    fn spawn_credit_offer_payments_task(&self) {
+        let self_copy = self.clone();
+        self.task_group
+            .spawn_cancellable_silent("credit offer payments", async move {
+                const CREDIT_INTERVAL: Duration = Duration::from_secs(10);
+
+                let mut interval = tokio::time::interval(CREDIT_INTERVAL);
+                loop {
+                    interval.tick().await;
+
+                    if let Err(err) = self_copy.credit_offer_payments().await {
+                        debug!(
+                            target: LOG_GATEWAY,
+                            err = %err.fmt_compact_anyhow(),
+                            "Failed to credit offer payments"
+                        );
+                    }
+                }
+            });
+    }
+
     /// Loops through all federations and checks their last save backup time. If
     /// the last saved backup time is past the threshold time, backup the
     /// federation.
@@ -983,8 +1244,16 @@ fn start_gateway(
                         );
                     }
 
+                    let lnrpc_route = match self_copy.create_lightning_client(runtime.clone()).await {
+                        Ok(lnrpc_route) => lnrpc_route,
+                        Err(err) => {
+                            crit!(target: LOG_GATEWAY, err = %err.fmt_compact_anyhow(), "Failed to create lightning client");
+                            sleep(Duration::from_secs(PAYMENT_STREAM_RETRY_SECONDS)).await;
+                            continue
+                        }
+                    };
+
                     let payment_stream_task_group = tg.make_subgroup();
-                    let lnrpc_route = self_copy.create_lightning_client(runtime.clone()).await;
 
                     debug!(target: LOG_GATEWAY, "Establishing lightning payment stream...");
                     let (stream, ln_client) = match lnrpc_route.route_htlcs(&payment_stream_task_group).await
@@ -1089,7 +1358,7 @@ async fn route_lightning_payments<'a>(
             info!(target: LOG_GATEWAY, "Gateway is running");
         }
 
-        if matches!(self.lightning_mode, LightningMode::Lnd { .. }) {
+        if self.lightning_mode.supports_lnv1() {
             // Re-register the gateway with all federations after connecting to the
             // lightning node
             let mut dbtx = self.gateway_db.begin_transaction_nc().await;
@@ -1102,7 +1371,7 @@ async fn route_lightning_payments<'a>(
         // Runs until the connection to the lightning node breaks or we receive the
         // shutdown signal.
         let htlc_task_group = self.task_group.make_subgroup();
-        if handle
+        match handle
             .cancel_on_shutdown(async move {
                 loop {
                     let payment_request_or = tokio::select! {
@@ -1110,7 +1379,12 @@ async fn route_lightning_payments<'a>(
                             payment_request_or
                         }
                         () = self.is_shutting_down_safely() => {
-                            break;
+                            break false;
+                        }
+                        // The standby may take over once the lease lapsed, so this
+                        // instance stops intercepting HTLCs even if its renewal hangs
+                        () = self.leader_lease_lapsed() => {
+                            break true;
                         }
                     };
 
@@ -1119,9 +1393,17 @@ async fn route_lightning_payments<'a>(
                             target: LOG_GATEWAY,
                             "Unexpected response from incoming lightning payment stream. Shutting down payment processor"
                         );
-                        break;
+                        break false;
                     };
 
+                    if self
+                        .leader_lease
+                        .as_ref()
+                        .is_some_and(|leader_lease| !leader_lease.holds_lease())
+                    {
+                        break true;
+                    }
+
                     let state_guard = self.state.read().await;
                     if let GatewayState::Running { ref lightning_context } = *state_guard {
                         // Spawn a subtask to handle each payment in parallel
@@ -1150,18 +1432,24 @@ async fn route_lightning_payments<'a>(
                             state = %state_guard,
                             "Gateway isn't in a running state, cannot handle incoming payments."
                         );
-                        break;
+                        break false;
                     }
                 }
             })
             .await
-            .is_ok()
         {
-            warn!(target: LOG_GATEWAY, "Lightning payment stream connection broken. Gateway is disconnected");
-            ReceivePaymentStreamAction::RetryAfterDelay
-        } else {
-            info!(target: LOG_GATEWAY, "Received shutdown signal");
-            ReceivePaymentStreamAction::NoRetry
+            Ok(false) => {
+                warn!(target: LOG_GATEWAY, "Lightning payment stream connection broken. Gateway is disconnected");
+                ReceivePaymentStreamAction::RetryAfterDelay
+            }
+            Ok(true) => {
+                crit!(target: LOG_GATEWAY, "Leader lease lapsed, no longer intercepting HTLCs");
+                ReceivePaymentStreamAction::NoRetry
+            }
+            Err(_) => {
+                info!(target: LOG_GATEWAY, "Received shutdown signal");
+                ReceivePaymentStreamAction::NoRetry
+            }
         }
     }
 
@@ -1302,6 +1590,21 @@ async fn try_handle_lightning_payment_lnv2(
             )
             .await?;
 
+        let contract_id = contract.contract_id();
+
+        if let Some(hold_invoice) = self
+            .gateway_db
+            .begin_transaction_nc()
+            .await
+            .load_hold_invoice(contract_id)
+            .await
+            && hold_invoice.state != HoldInvoiceState::Settled
+        {
+            return self
+                .hold_incoming_htlc_v2(htlc_request, contract_id, &client)
+                .await;
+        }
+
         if let Err(err) = client
             .get_first_module::<GatewayClientModuleV2>()
             .expect("Must have client module")
@@ -1331,6 +1634,91 @@ async fn try_handle_lightning_payment_lnv2(
         Ok(())
     }
 
+    /// Holds an HTLC paying a hold invoice until the recipient settles or
+    /// cancels it. HTLCs replayed by the Lightning node after a restart are
+    /// matched by their circuit, any other HTLC for the same invoice is failed.
+    async fn hold_incoming_htlc_v2(
+        &self,
+        htlc_request: &InterceptPaymentRequest,
+        contract_id: ContractId,
+        client: &ClientHandleArc,
+    ) -> Result<()> {
+        let module = client
+            .get_first_module::<GatewayClientModuleV2>()
+            .expect("Must have client module");
+
+        let _guard = self.hold_invoice_lock.lock().await;
+
+        let mut dbtx = self.gateway_db.begin_transaction().await;
+
+        let Some(mut hold_invoice) = dbtx.load_hold_invoice(contract_id).await else {
+            return Err(PublicGatewayError::LNv2(LNv2Error::IncomingPayment(
+                "No corresponding hold invoice available".to_string(),
+            )));
+        };
+
+        let cancel_reason = match &hold_invoice.state {
+            HoldInvoiceState::Pending => {
+                let deadline_secs = duration_since_epoch()
+                    .as_secs()
+                    .saturating_add(u64::from(hold_invoice.hold_secs))
+                    .min(hold_invoice.contract.commitment.expiration_or_fee);
+
+                hold_invoice.state = HoldInvoiceState::Held(HeldHtlc {
+                    payment_hash: htlc_request.payment_hash,
+                    incoming_chan_id: htlc_request.incoming_chan_id,
+                    htlc_id: htlc_request.htlc_id,
+                    amount_msat: htlc_request.amount_msat,
+                    deadline_secs,
+                });
+
+                dbtx.save_hold_invoice(contract_id, hold_invoice.clone())
+                    .await;
+
+                dbtx.commit_tx_result().await.map_err(|_| {
+                    PublicGatewayError::LNv2(LNv2Error::IncomingPayment(
+                        "Hold invoice has been modified concurrently".to_string(),
+                    ))
+                })?;
+
+                module
+                    .hold_incoming_htlc(
+                        &hold_invoice.contract,
+                        htlc_request.amount_msat,
+                        deadline_secs,
+                    )
+                    .await;
+
+                return Ok(());
+            }
+            HoldInvoiceState::Held(held)
+                if held.incoming_chan_id == htlc_request.incoming_chan_id
+                    && held.htlc_id == htlc_request.htlc_id =>
+            {
+                return Ok(());
+            }
+            HoldInvoiceState::Held(..) => "The hold invoice has already been paid",
+            HoldInvoiceState::Cancelled => "The hold invoice has been cancelled",
+            // The invoice has been settled concurrently, which relays the held HTLC
+            HoldInvoiceState::Settled => return Ok(()),
+        };
+
+        if let Err(err) = module
+            .cancel_held_htlc(
+                htlc_request.payment_hash,
+                htlc_request.incoming_chan_id,
+                htlc_request.htlc_id,
+                &hold_invoice.contract,
+                cancel_reason.to_string(),
+            )
+            .await
+        {
+            warn!(target: LOG_GATEWAY, err = %err.fmt_compact_anyhow(), "Error cancelling HTLC for hold invoice");
+        }
+
+        Ok(())
+    }
+
     /// Tries to handle a lightning payment using the legacy lightning protocol.
     /// Returns `Ok` if the payment was handled, `Err` otherwise.
     async fn try_handle_lightning_payment_ln_legacy(
@@ -1557,6 +1945,15 @@ async fn handle_pay_invoice_msg(
 
         debug!(target: LOG_GATEWAY, "Handling pay invoice message");
         let client = self.select_client(payload.federation_id).await?;
+        // The gateway module rejects invoices without an amount
+        let _outgoing_payment = self
+            .begin_outgoing_payment(
+                client.value(),
+                payload.federation_id,
+                OutgoingPaymentId::Lnv1(payload.contract_id),
+                payload.payment_data.amount().unwrap_or(Amount::ZERO),
+            )
+            .await?;
         let contract_id = payload.contract_id;
         let gateway_module = &client
             .value()
@@ -1667,22 +2064,11 @@ pub async fn handle_receive_ecash_msg(
         &self,
         payload: ReceiveEcashPayload,
     ) -> Result<ReceiveEcashResponse> {
-        // Extract federation_id_prefix from either format
-        let federation_id_prefix = base32::decode_prefixed::<fedimint_mintv2_client::ECash>(
-            FEDIMINT_PREFIX,
-            &payload.notes,
-        )
-        .ok()
-        .and_then(|e| e.mint())
-        .map(|id| id.to_prefix())
-        .or_else(|| {
-            OOBNotes::from_str(&payload.notes)
-                .ok()
-                .map(|n| n.federation_id_prefix())
-        })
-        .ok_or_else(|| PublicGatewayError::ReceiveEcashError {
-            failure_reason: "Invalid ecash format: could not parse as ECash or OOBNotes"
-                .to_string(),
+        let federation_id_prefix = ecash_federation_id_prefix(&payload.notes).ok_or_else(|| {
+            PublicGatewayError::ReceiveEcashError {
+                failure_reason: "Invalid ecash format: could not parse as ECash or OOBNotes"
+                    .to_string(),
+            }
         })?;
 
         let client = self
@@ -1856,6 +2242,11 @@ async fn register_federations(
             }
 
             for (federation_id, federation_config) in federations {
+                // Paused federations are announced again once they are resumed
+                if self.risk_tracker.is_paused(*federation_id) {
+                    continue;
+                }
+
                 // A fee that predates the fee limits may be too large to announce. Skip
                 // that federation rather than failing the whole registration pass: this
                 // runs on the root task group at startup, so an error here would keep
@@ -1954,7 +2345,7 @@ async fn load_mnemonic(gateway_db: &Database) -> Option<Mnemonic> {
     /// Reads the connected federation client configs from the Gateway's
     /// database and reconstructs the clients necessary for interacting with
     /// connection federations.
-    async fn load_clients(&self) -> AdminResult<()> {
+    pub async fn load_clients(&self) -> AdminResult<()> {
         if let GatewayState::NotConfigured { .. } = self.get_state().await {
             return Ok(());
         }
@@ -1974,8 +2365,38 @@ async fn load_clients(&self) -> AdminResult<()> {
             .await
             .expect("mnemonic should be set");
 
+        // Federations of a restored disaster recovery bundle, whose clients are
+        // recovered from the mnemonic instead of being restored from the bundle
+        let pending_recoveries = {
+            let mut dbtx = self.gateway_db.begin_transaction_nc().await;
+            dbtx.load_pending_client_recoveries().await
+        };
+
         for (federation_id, config) in configs {
             let federation_index = config.federation_index;
+            let pending_recovery = pending_recoveries.contains(&federation_id);
+
+            if pending_recovery
+                && !Client::is_initialized(&self.gateway_db.get_client_database(&federation_id))
+                    .await
+            {
+                info!(target: LOG_GATEWAY, %federation_id, "Recovering client from mnemonic");
+
+                if let Err(err) = self
+                    .client_builder
+                    .recover(config.clone(), Arc::new(self.clone()), &mnemonic)
+                    .await
+                {
+                    warn!(
+                        target: LOG_GATEWAY,
+                        %federation_id,
+                        err = %err.fmt_compact(),
+                        "Failed to recover client, retrying on the next start"
+                    );
+                    continue;
+                }
+            }
+
             match Box::pin(Spanned::try_new(
                 info_span!(target: LOG_GATEWAY, "client", federation_id  = %federation_id.clone()),
                 self.client_builder
@@ -1985,6 +2406,12 @@ async fn load_clients(&self) -> AdminResult<()> {
             {
                 Ok(client) => {
                     federation_manager.add_client(federation_index, client);
+
+                    if pending_recovery {
+                        let mut dbtx = self.gateway_db.begin_transaction().await;
+                        dbtx.remove_pending_client_recovery(federation_id).await;
+                        dbtx.commit_tx().await;
+                    }
                 }
                 _ => {
                     warn!(target: LOG_GATEWAY, federation_id = %federation_id, "Failed to load client");
@@ -2001,8 +2428,8 @@ async fn load_clients(&self) -> AdminResult<()> {
     /// has successfully connected to the Lightning node, so that it can
     /// include route hints in the registration.
     fn register_clients_timer(&self) {
-        // Only spawn background registration thread if gateway is LND
-        if matches!(self.lightning_mode, LightningMode::Lnd { .. }) {
+        // Only spawn background registration thread if the gateway supports LNv1
+        if self.lightning_mode.supports_lnv1() {
             info!(target: LOG_GATEWAY, "Spawning register task...");
             let gateway = self.clone();
             let register_task_group = self.task_group.make_subgroup();
@@ -2157,7 +2584,7 @@ async fn await_lightning_context(&self) -> LightningContext {
     /// Iterates through all of the federations the gateway is registered with
     /// and requests to remove the registration record.
     pub async fn unannounce_from_all_federations(&self) {
-        if matches!(self.lightning_mode, LightningMode::Lnd { .. }) {
+        if self.lightning_mode.supports_lnv1() {
             for registration in self.registrations.values() {
                 self.federation_manager
                     .read()
@@ -2171,8 +2598,8 @@ pub async fn unannounce_from_all_federations(&self) {
     async fn create_lightning_client(
         &self,
         runtime: Arc<tokio::runtime::Runtime>,
-    ) -> Box<dyn ILnRpcClient> {
-        match self.lightning_mode.clone() {
+    ) -> anyhow::Result<Box<dyn ILnRpcClient>> {
+        Ok(match self.lightning_mode.clone() {
             LightningMode::Lnd {
                 lnd_rpc_addr,
                 lnd_tls_cert,
@@ -2210,6 +2637,7 @@ async fn create_lightning_client(
             LightningMode::Ldk {
                 lightning_port,
                 alias,
+                lsp,
             } => {
                 let mnemonic = Self::load_mnemonic(&self.gateway_db)
                     .await
@@ -2224,6 +2652,7 @@ async fn create_lightning_client(
                         self.network,
                         lightning_port,
                         alias.clone(),
+                        &lsp,
                         mnemonic.clone(),
                         runtime.clone(),
                     )
@@ -2232,7 +2661,21 @@ async fn create_lightning_client(
                 .await
                 .expect("Could not create LDK Node")
             }
-        }
+            LightningMode::Cln { cln_rpc_path } => {
+                Box::new(cln::GatewayClnClient::new(cln_rpc_path))
+            }
+            LightningMode::Simulated { simulated_network } => {
+                let config = match simulated_network {
+                    Some(path) => simulated::SimulatedNetworkConfig::load(Path::new(&path))
+                        .context("Could not load simulated network")?,
+                    None => simulated::SimulatedNetworkConfig::default(),
+                };
+
+                Box::new(simulated::GatewaySimulatedClient::new(
+                    simulated::SimulatedNetwork::new(self.network, config)?,
+                ))
+            }
+        })
     }
 }
 
@@ -2256,6 +2699,7 @@ async fn handle_get_info(&self) -> AdminResult<GatewayInfo> {
                     .iter()
                     .map(|(k, v)| (k.clone(), (v.endpoint_url.clone(), v.keypair.public_key())))
                     .collect(),
+                high_availability: self.high_availability_info(),
             });
         };
 
@@ -2291,6 +2735,7 @@ async fn handle_get_info(&self) -> AdminResult<GatewayInfo> {
                 .iter()
                 .map(|(k, v)| (k.clone(), (v.endpoint_url.clone(), v.keypair.public_key())))
                 .collect(),
+            high_availability: self.high_availability_info(),
         })
     }
 
@@ -2330,7 +2775,7 @@ async fn handle_payment_summary_msg(
                 .client(fed_id)
                 .expect("No client available")
                 .value();
-            let all_events = &get_events_for_duration(client, start, end).await;
+            let all_events = &get_events_for_duration(client.db(), start, end).await;
 
             let (mut lnv1_outgoing, mut lnv1_incoming) = compute_lnv1_stats(all_events);
             let (mut lnv2_outgoing, mut lnv2_incoming) = compute_lnv2_stats(all_events);
@@ -2463,7 +2908,7 @@ async fn handle_connect_federation(
         };
 
         Self::check_federation_network(&client, self.network).await?;
-        if matches!(self.lightning_mode, LightningMode::Lnd { .. })
+        if self.lightning_mode.supports_lnv1()
             && let Ok(lnv1) = client.get_first_module::<GatewayClientModule>()
         {
             for (protocol, registration) in &self.registrations {
@@ -2604,7 +3049,7 @@ async fn handle_set_fees_msg(
 
         dbtx.commit_tx().await;
 
-        if matches!(self.lightning_mode, LightningMode::Lnd { .. }) {
+        if self.lightning_mode.supports_lnv1() {
             let register_task_group = TaskGroup::new();
 
             self.register_federations(&fed_configs, &register_task_group)
@@ -2959,7 +3404,11 @@ async fn handle_withdraw_msg(&self, payload: WithdrawPayload) -> AdminResult<Wit
             .value()
             .get_first_module::<fedimint_walletv2_client::WalletClientModule>()
         {
-            return withdraw_v2(client.value(), &wallet_module, &address, amount).await;
+            let (response, withdraw_amount) =
+                withdraw_v2(client.value(), &wallet_module, &address, amount).await?;
+            self.record_peg_out(federation_id, &response, withdraw_amount)
+                .await;
+            return Ok(response);
         }
 
         let wallet_module = client.value().get_first_module::<WalletClientModule>()?;
@@ -3025,7 +3474,10 @@ async fn handle_withdraw_msg(&self, payload: WithdrawPayload) -> AdminResult<Wit
             match update {
                 WithdrawState::Succeeded(txid) => {
                     info!(target: LOG_GATEWAY, amount = %withdraw_amount, address = %address, "Sent funds");
-                    return Ok(WithdrawResponse { txid, fees });
+                    let response = WithdrawResponse { txid, fees };
+                    self.record_peg_out(federation_id, &response, withdraw_amount)
+                        .await;
+                    return Ok(response);
                 }
                 WithdrawState::Failed(e) => {
                     return Err(AdminGatewayError::WithdrawError { failure_reason: e });
@@ -3276,6 +3728,10 @@ async fn handle_get_note_summary_msg(
         fed_manager.get_note_summary(federation_id).await
     }
 
+    async fn handle_revenue_msg(&self, payload: RevenuePayload) -> AdminResult<RevenueResponse> {
+        self.revenue(payload).await
+    }
+
     fn get_password_hash(&self) -> String {
         self.bcrypt_password_hash.clone()
     }
@@ -3319,12 +3775,53 @@ async fn public_key_v2(&self, federation_id: &FederationId) -> Option<PublicKey>
             })
     }
 
+    /// Returns whether LNv2 clients of the federation can instruct this Gateway
+    /// to send keysend payments, which the operator has to enable explicitly.
+    pub async fn supports_keysend_v2(&self, federation_id: &FederationId) -> Result<bool> {
+        if !self.enable_keysend {
+            return Ok(false);
+        }
+
+        let context = self.get_lightning_context().await?;
+
+        let connected = self
+            .gateway_db
+            .begin_transaction_nc()
+            .await
+            .load_federation_config(*federation_id)
+            .await
+            .is_some();
+
+        Ok(connected && context.lnrpc.supports_keysend())
+    }
+
+    /// Returns whether LNv2 clients of the federation can instruct this Gateway
+    /// to pay a part of a multi-part payment.
+    pub async fn supports_multi_part_v2(&self, federation_id: &FederationId) -> Result<bool> {
+        let context = self.get_lightning_context().await?;
+
+        let connected = self
+            .gateway_db
+            .begin_transaction_nc()
+            .await
+            .load_federation_config(*federation_id)
+            .await
+            .is_some();
+
+        Ok(connected && context.lnrpc.supports_pay_part())
+    }
+
     /// Returns payment information that LNv2 clients can use to instruct this
     /// Gateway to pay an invoice or receive a payment.
     pub async fn routing_info_v2(
         &self,
         federation_id: &FederationId,
     ) -> Result<Option<RoutingInfo>> {
+        // Clients do not select a gateway without routing info for their federation
+        if self.risk_tracker.is_paused(*federation_id) {
+            return Ok(None);
+        }
+
         let context = self.get_lightning_context().await?;
 
         let mut dbtx = self.gateway_db.begin_transaction_nc().await;
@@ -3334,12 +3831,21 @@ pub async fn routing_info_v2(
             }),
         )?;
 
-        let lightning_fee = fed_config.lightning_fee;
-        let transaction_fee = fed_config.transaction_fee;
+        let schedule = dbtx.load_fee_schedule(*federation_id).await;
+        let surcharges = dbtx
+            .load_fee_surcharges(*federation_id)
+            .await
+            .unwrap_or_default();
 
         // This route is public and unauthenticated, so the sum of two fees stored
         // before the fee limits applied must not be able to panic here.
-        let send_fee_default = lightning_fee.checked_add(transaction_fee).ok_or_else(|| {
+        let fees = fee_schedule::effective_fees(
+            fed_config.lightning_fee,
+            fed_config.transaction_fee,
+            schedule.as_ref(),
+            surcharges,
+        )
+        .ok_or_else(|| {
             PublicGatewayError::Unexpected(anyhow!(
                 "The configured fees of federation {federation_id} cannot be added"
             ))
@@ -3352,16 +3858,17 @@ pub async fn routing_info_v2(
                 lightning_public_key: context.lightning_public_key,
                 lightning_alias: Some(context.lightning_alias.clone()),
                 module_public_key,
-                send_fee_default,
+                send_fee_default: fees.send_fee_default,
+                send_fee_tiers: fees.send_fee_tiers,
                 // The base fee ensures that the gateway does not loose sats sending the payment due
                 // to fees paid on the transaction claiming the outgoing contract or
                 // subsequent transactions spending the newly issued ecash
-                send_fee_minimum: transaction_fee,
+                send_fee_minimum: fed_config.transaction_fee,
                 expiration_delta_default: 1440,
                 expiration_delta_minimum: EXPIRATION_DELTA_MINIMUM_V2,
                 // The base fee ensures that the gateway does not loose sats receiving the payment
                 // due to fees paid on the transaction funding the incoming contract
-                receive_fee: transaction_fee,
+                receive_fee: fees.receive_fee,
             }))
     }
 
@@ -3371,7 +3878,21 @@ pub async fn send_payment_v2(
         &self,
         payload: SendPaymentPayload,
     ) -> Result<std::result::Result<[u8; 32], Signature>> {
+        if matches!(payload.invoice, LightningInvoice::Keysend(..)) && !self.enable_keysend {
+            return Err(PublicGatewayError::LNv2(LNv2Error::OutgoingPayment(
+                anyhow!("Keysend payments are disabled on this gateway"),
+            )));
+        }
+
         let client = self.select_client(payload.federation_id).await?;
+        let _outgoing_payment = self
+            .begin_outgoing_payment(
+                client.value(),
+                payload.federation_id,
+                OutgoingPaymentId::Lnv2(payload.contract.payment_image.clone()),
+                payload.contract.amount,
+            )
+            .await?;
         // A federation only has to offer one of the two lightning modules, so a
         // client we serve over LNv1 may well have no LNv2 module at all.
         let module = client
@@ -3393,6 +3914,45 @@ pub async fn send_payment_v2(
     async fn create_bolt11_invoice_v2(
         &self,
         payload: CreateBolt11InvoicePayload,
+    ) -> Result<Bolt11Invoice> {
+        self.create_incoming_contract_invoice_v2(payload, None)
+            .await
+    }
+
+    /// Creates an invoice like `create_bolt11_invoice_v2`, but holds the HTLC
+    /// paying it for up to `hold_secs` until the recipient settles or cancels
+    /// the payment. The incoming contract is only funded once the recipient
+    /// settles, such that a cancelled payment can be failed back to the payer.
+    async fn create_hold_bolt11_invoice_v2(
+        &self,
+        payload: CreateHoldBolt11InvoicePayload,
+    ) -> Result<Bolt11Invoice> {
+        if payload.hold_secs > MAX_HOLD_INVOICE_SECS {
+            return Err(PublicGatewayError::LNv2(LNv2Error::IncomingPayment(
+                "The hold time exceeds the maximum of ten minutes".to_string(),
+            )));
+        }
+
+        let held_until_secs = duration_since_epoch()
+            .as_secs()
+            .saturating_add(u64::from(payload.invoice.expiry_secs))
+            .saturating_add(u64::from(payload.hold_secs));
+
+        // Allow for some clock skew between the recipient and the gateway.
+        if payload.invoice.contract.commitment.expiration_or_fee + 60 < held_until_secs {
+            return Err(PublicGatewayError::LNv2(LNv2Error::IncomingPayment(
+                "The contract expires before the payment could be settled".to_string(),
+            )));
+        }
+
+        self.create_incoming_contract_invoice_v2(payload.invoice, Some(payload.hold_secs))
+            .await
+    }
+
+    async fn create_incoming_contract_invoice_v2(
+        &self,
+        payload: CreateBolt11InvoicePayload,
+        hold_secs: Option<u32>,
     ) -> Result<Bolt11Invoice> {
         // Unauthenticated invoice creation consumes resources on the Lightning
         // node and burns CPU on contract verification, so the request rate is
@@ -3428,7 +3988,9 @@ async fn create_bolt11_invoice_v2(
             )));
         }
 
-        if contract_amount != payload.contract.commitment.amount {
+        // The receive fee follows the federation's fee schedule, so a client that
+        // fetched the routing info before a surcharge ended may pay a higher fee.
+        if payload.contract.commitment.amount > contract_amount {
             return Err(PublicGatewayError::LNv2(LNv2Error::IncomingPayment(
                 "The contract amount does not pay the correct amount of fees".to_string(),
             )));
@@ -3446,15 +4008,38 @@ async fn create_bolt11_invoice_v2(
             )));
         }
 
-        let payment_hash = match payload.contract.commitment.payment_image {
-            PaymentImage::Hash(payment_hash) => payment_hash,
-            PaymentImage::Point(..) => {
-                return Err(PublicGatewayError::LNv2(LNv2Error::IncomingPayment(
-                    "PaymentImage is not a payment hash".to_string(),
-                )));
-            }
-        };
-
+        self.register_contract_and_create_invoice(
+            payload.federation_id,
+            payload.contract,
+            payload.amount,
+            payload.description,
+            payload.expiry_secs,
+            hold_secs,
+        )
+        .await
+    }
+
+    /// Registers the incoming contract to be funded once the invoice created
+    /// for its payment hash is paid, then creates that invoice. If `hold_secs`
+    /// is set the payment is held until the recipient settles it.
+    async fn register_contract_and_create_invoice(
+        &self,
+        federation_id: FederationId,
+        contract: IncomingContract,
+        amount: Amount,
+        description: Bolt11InvoiceDescription,
+        expiry_secs: u32,
+        hold_secs: Option<u32>,
+    ) -> Result<Bolt11Invoice> {
+        let payment_hash = match contract.commitment.payment_image {
+            PaymentImage::Hash(payment_hash) => payment_hash,
+            PaymentImage::Point(..) => {
+                return Err(PublicGatewayError::LNv2(LNv2Error::IncomingPayment(
+                    "PaymentImage is not a payment hash".to_string(),
+                )));
+            }
+        };
+
         // Reserve the payment hash in the database before requesting the
         // invoice so a replayed payment hash is rejected without creating any
         // state on the Lightning node, and so the contract is guaranteed to be
@@ -3463,14 +4048,29 @@ async fn create_bolt11_invoice_v2(
 
         let invoice_expires_at_secs = duration_since_epoch()
             .as_secs()
-            .saturating_add(u64::from(payload.expiry_secs));
+            .saturating_add(u64::from(expiry_secs));
+
+        let contract_id = contract.contract_id();
+
+        if let Some(hold_secs) = hold_secs {
+            dbtx.save_hold_invoice(
+                contract_id,
+                HoldInvoice {
+                    federation_id,
+                    contract: contract.clone(),
+                    hold_secs,
+                    state: HoldInvoiceState::Pending,
+                },
+            )
+            .await;
+        }
 
         if dbtx
             .save_registered_incoming_contract(
-                payload.federation_id,
-                payload.amount,
+                federation_id,
+                amount,
                 invoice_expires_at_secs,
-                payload.contract,
+                contract,
             )
             .await
             .is_some()
@@ -3487,12 +4087,7 @@ async fn create_bolt11_invoice_v2(
         })?;
 
         match self
-            .create_invoice_via_lnrpc_v2(
-                payment_hash,
-                payload.amount,
-                payload.description.clone(),
-                payload.expiry_secs,
-            )
+            .create_invoice_via_lnrpc_v2(payment_hash, amount, description, expiry_secs)
             .await
         {
             Ok(invoice) => Ok(invoice),
@@ -3502,6 +4097,7 @@ async fn create_bolt11_invoice_v2(
                 let mut dbtx = self.gateway_db.begin_transaction().await;
                 dbtx.delete_registered_incoming_contract(PaymentImage::Hash(payment_hash))
                     .await;
+                dbtx.delete_hold_invoice(contract_id).await;
                 if let Err(db_err) = dbtx.commit_tx_result().await {
                     warn!(
                         target: LOG_GATEWAY,
@@ -3516,45 +4112,1115 @@ async fn create_bolt11_invoice_v2(
         }
     }
 
-    /// Retrieves a BOLT11 invoice from the connected Lightning node with a
-    /// specific `payment_hash`.
-    pub async fn create_invoice_via_lnrpc_v2(
+    /// Retrieves a BOLT11 invoice from the connected Lightning node with a
+    /// specific `payment_hash`.
+    pub async fn create_invoice_via_lnrpc_v2(
+        &self,
+        payment_hash: sha256::Hash,
+        amount: Amount,
+        description: Bolt11InvoiceDescription,
+        expiry_time: u32,
+    ) -> std::result::Result<Bolt11Invoice, LightningRpcError> {
+        let lnrpc = self.get_lightning_context().await?.lnrpc;
+
+        let response = match description {
+            Bolt11InvoiceDescription::Direct(description) => {
+                lnrpc
+                    .create_invoice(CreateInvoiceRequest {
+                        payment_hash: Some(payment_hash),
+                        amount_msat: amount.msats,
+                        expiry_secs: expiry_time,
+                        description: Some(InvoiceDescription::Direct(description)),
+                    })
+                    .await?
+            }
+            Bolt11InvoiceDescription::Hash(hash) => {
+                lnrpc
+                    .create_invoice(CreateInvoiceRequest {
+                        payment_hash: Some(payment_hash),
+                        amount_msat: amount.msats,
+                        expiry_secs: expiry_time,
+                        description: Some(InvoiceDescription::Hash(hash)),
+                    })
+                    .await?
+            }
+        };
+
+        Bolt11Invoice::from_str(&response.invoice).map_err(|e| {
+            LightningRpcError::FailedToGetInvoice {
+                failure_reason: e.to_string(),
+            }
+        })
+    }
+
+    /// Fetches an invoice for a BOLT12 offer from the payee via the connected
+    /// Lightning node and remembers it, such that it can be paid once the
+    /// client has funded an outgoing contract for its payment hash.
+    async fn fetch_bolt12_invoice_v2(
+        &self,
+        payload: FetchBolt12InvoicePayload,
+    ) -> Result<Bolt12OfferInvoice> {
+        // Fetching an invoice makes the Lightning node send onion messages to
+        // the payee, so the request rate is limited like invoice creation.
+        if !self.invoice_rate_limiter.try_acquire() {
+            return Err(PublicGatewayError::RateLimited);
+        }
+
+        self.routing_info_v2(&payload.federation_id)
+            .await?
+            .ok_or(LNv2Error::OutgoingPayment(anyhow!(
+                "Federation {} does not exist",
+                payload.federation_id
+            )))?;
+
+        let response = self
+            .get_lightning_context()
+            .await?
+            .lnrpc
+            .fetch_offer_invoice(payload.offer.clone(), payload.amount, payload.payer_note)
+            .await?;
+
+        let mut dbtx = self.gateway_db.begin_transaction().await;
+
+        dbtx.save_fetched_offer_invoice(
+            response.payment_hash,
+            FetchedOfferInvoice {
+                invoice: response.invoice,
+                amount: response.amount,
+                expires_at_secs: response.expires_at_secs,
+            },
+        )
+        .await;
+
+        dbtx.commit_tx_result()
+            .await
+            .map_err(|e| PublicGatewayError::Unexpected(e.into()))?;
+
+        Ok(Bolt12OfferInvoice {
+            offer: payload.offer,
+            invoice: response.signed_invoice,
+            payment_hash: response.payment_hash,
+            amount: response.amount,
+        })
+    }
+
+    /// Estimates the routing fee and the likelihood of success of paying the
+    /// invoice over Lightning, without paying it. The route is constrained by
+    /// the same fee budget and expiration delta the gateway would have for a
+    /// payment with its default send fee. Returns `None` if the Lightning node
+    /// cannot probe payments.
+    async fn probe_payment_v2(&self, payload: ProbePaymentPayload) -> Result<Option<RoutePreview>> {
+        // Probing makes the Lightning node search its graph for a route, so the
+        // request rate is limited like invoice creation.
+        if !self.invoice_rate_limiter.try_acquire() {
+            return Err(PublicGatewayError::RateLimited);
+        }
+
+        let routing_info = self.routing_info_v2(&payload.federation_id).await?.ok_or(
+            LNv2Error::OutgoingPayment(anyhow!(
+                "Federation {} does not exist",
+                payload.federation_id
+            )),
+        )?;
+
+        let amount_msats =
+            payload
+                .invoice
+                .amount_milli_satoshis()
+                .ok_or(LNv2Error::OutgoingPayment(anyhow!(
+                    "Amountless invoices are not supported"
+                )))?;
+
+        let lightning_context = self.get_lightning_context().await?;
+
+        // A payment to our own node is a direct swap, which does not touch the
+        // Lightning network and only succeeds if the invoice is registered.
+        if payload.invoice.get_payee_pub_key() == lightning_context.lightning_public_key {
+            let registered = self
+                .get_registered_incoming_contract_and_client_v2(
+                    PaymentImage::Hash(*payload.invoice.payment_hash()),
+                    amount_msats,
+                )
+                .await
+                .is_ok();
+
+            return Ok(Some(RoutePreview {
+                routing_fee: Amount::ZERO,
+                success_probability: Some(if registered { 1.0 } else { 0.0 }),
+                cltv_delta: 0,
+            }));
+        }
+
+        if !lightning_context.lnrpc.supports_probe() {
+            return Ok(None);
+        }
+
+        let max_fee = routing_info
+            .send_fee_default
+            .add_to(amount_msats)
+            .checked_sub(routing_info.send_fee_minimum.add_to(amount_msats))
+            .unwrap_or(Amount::ZERO);
+
+        let response = lightning_context
+            .lnrpc
+            .probe(
+                payload.invoice,
+                routing_info.expiration_delta_default,
+                max_fee,
+            )
+            .await?;
+
+        Ok(Some(RoutePreview {
+            routing_fee: response.routing_fee,
+            success_probability: response.success_probability,
+            cltv_delta: response.cltv_delta,
+        }))
+    }
+
+    /// Creates a reusable BOLT12 offer on the connected Lightning node on
+    /// behalf of a recipient in the given federation. Payments to the offer
+    /// are credited to the recipient by [`Gateway::credit_offer_payments`].
+    async fn create_bolt12_offer_v2(&self, payload: CreateBolt12OfferPayload) -> Result<String> {
+        if !self.invoice_rate_limiter.try_acquire() {
+            return Err(PublicGatewayError::RateLimited);
+        }
+
+        self.routing_info_v2(&payload.federation_id)
+            .await?
+            .ok_or(LNv2Error::IncomingPayment(format!(
+                "Federation {} does not exist",
+                payload.federation_id
+            )))?;
+
+        let offer = self.get_lightning_context().await?.lnrpc.create_offer(
+            None,
+            payload.description,
+            None,
+            None,
+        )?;
+
+        let mut dbtx = self.gateway_db.begin_transaction().await;
+
+        dbtx.save_registered_offer(
+            bolt12_offer_id(&offer)?,
+            RegisteredOffer {
+                federation_id: payload.federation_id,
+                recipient_pk: payload.recipient_pk,
+            },
+        )
+        .await;
+
+        dbtx.commit_tx_result()
+            .await
+            .map_err(|e| PublicGatewayError::Unexpected(e.into()))?;
+
+        Ok(offer)
+    }
+
+    /// Funds an incoming contract for every settled payment to an offer
+    /// created via [`Gateway::create_bolt12_offer_v2`] since the last call.
+    async fn credit_offer_payments(&self) -> anyhow::Result<()> {
+        /// Payments are listed by their latest update, which the Lightning
+        /// node may record slightly before we poll, so we look back further
+        /// than the cursor. Crediting a payment twice is prevented by the
+        /// persisted contracts.
+        const CURSOR_OVERLAP_SECS: u64 = 600;
+
+        let cursor_secs = self
+            .gateway_db
+            .begin_transaction_nc()
+            .await
+            .load_offer_payment_cursor()
+            .await
+            .unwrap_or(0);
+
+        let now_secs = duration_since_epoch().as_secs();
+
+        let payments = self
+            .get_lightning_context()
+            .await?
+            .lnrpc
+            .list_offer_payments(cursor_secs.saturating_sub(CURSOR_OVERLAP_SECS), now_secs)
+            .await?;
+
+        // A payment that fails to be credited must not hold up the others, but
+        // the cursor only advances once all of them are credited such that the
+        // failed ones are retried.
+        let mut all_credited = true;
+
+        for payment in payments {
+            if let Err(err) = self.credit_offer_payment(&payment).await {
+                warn!(
+                    target: LOG_GATEWAY,
+                    offer_id = %payment.offer_id,
+                    payment_hash = %payment.payment_hash,
+                    err = %err.fmt_compact_anyhow(),
+                    "Failed to credit offer payment"
+                );
+
+                all_credited = false;
+            }
+        }
+
+        if all_credited {
+            let mut dbtx = self.gateway_db.begin_transaction().await;
+
+            dbtx.save_offer_payment_cursor(now_secs).await;
+
+            dbtx.commit_tx_result().await?;
+        }
+
+        Ok(())
+    }
+
+    /// Funds the incoming contract for a payment to an offer created on
+    /// behalf of a client, ignoring payments to offers of the operator.
+    async fn credit_offer_payment(&self, payment: &OfferPayment) -> anyhow::Result<()> {
+        let Some(offer) = self
+            .gateway_db
+            .begin_transaction_nc()
+            .await
+            .load_registered_offer(payment.offer_id.clone())
+            .await
+        else {
+            // The offer was created by the operator, not on behalf of a client
+            return Ok(());
+        };
+
+        let Some(contract) = self.offer_payment_contract(payment, &offer).await? else {
+            return Ok(());
+        };
+
+        let client = self.select_client(contract.federation_id).await?;
+
+        let state = client
+            .value()
+            .get_first_module::<GatewayClientModuleV2>()?
+            .relay_direct_swap(contract.contract, payment.amount.msats)
+            .await?;
+
+        info!(
+            target: LOG_GATEWAY,
+            offer_id = %payment.offer_id,
+            payment_hash = %payment.payment_hash,
+            ?state,
+            "Credited offer payment"
+        );
+
+        Ok(())
+    }
+
+    /// Returns the incoming contract crediting an offer payment to the
+    /// recipient, creating and persisting it on first use such that retries
+    /// fund the very same contract.
+    async fn offer_payment_contract(
+        &self,
+        payment: &OfferPayment,
+        offer: &RegisteredOffer,
+    ) -> anyhow::Result<Option<OfferPaymentContract>> {
+        let mut dbtx = self.gateway_db.begin_transaction().await;
+
+        if let Some(contract) = dbtx.load_offer_payment_contract(payment.payment_hash).await {
+            return Ok(Some(contract));
+        }
+
+        let routing_info = self
+            .routing_info_v2(&offer.federation_id)
+            .await?
+            .ok_or(anyhow!("Federation {} does not exist", offer.federation_id))?;
+
+        let client = self.select_client(offer.federation_id).await?;
+
+        let module = client.value().get_first_module::<GatewayClientModuleV2>()?;
+
+        let contract_amount = routing_info.receive_fee.subtract_from(payment.amount.msats);
+
+        if contract_amount < MINIMUM_INCOMING_CONTRACT_AMOUNT {
+            warn!(
+                target: LOG_GATEWAY,
+                offer_id = %payment.offer_id,
+                amount = %payment.amount,
+                "Offer payment is too small to be credited"
+            );
+
+            return Ok(None);
+        }
+
+        let contract = OfferPaymentContract {
+            federation_id: offer.federation_id,
+            contract: recipient_incoming_contract(
+                &module,
+                offer.recipient_pk,
+                contract_amount,
+                payment.amount.msats - contract_amount.msats,
+            ),
+        };
+
+        dbtx.save_offer_payment_contract(payment.payment_hash, contract.clone())
+            .await;
+
+        dbtx.commit_tx_result().await?;
+
+        Ok(Some(contract))
+    }
+
+    /// Registers incoming contracts the recipient created for payments to its
+    /// static LNURL. The gateway hands out every contract for one payment
+    /// only, and never creates contracts for a static LNURL itself such that
+    /// it never learns the preimage of a payment to the recipient.
+    async fn register_static_lnurl_contracts_v2(
+        &self,
+        payload: RegisterStaticLnurlContractsPayload,
+    ) -> Result<()> {
+        if !self.invoice_rate_limiter.try_acquire() {
+            return Err(PublicGatewayError::RateLimited);
+        }
+
+        if payload.contracts.len() > MAX_STATIC_LNURL_CONTRACTS {
+            return Err(PublicGatewayError::LNv2(LNv2Error::IncomingPayment(
+                format!(
+                    "A recipient can register at most {MAX_STATIC_LNURL_CONTRACTS} unused contracts"
+                ),
+            )));
+        }
+
+        secp256k1::SECP256K1
+            .verify_schnorr(
+                &payload.signature,
+                &register_static_lnurl_contracts_message(
+                    &payload.federation_id,
+                    payload.amount,
+                    &payload.contracts,
+                ),
+                &payload.recipient_pk.x_only_public_key().0,
+            )
+            .map_err(|_| {
+                PublicGatewayError::LNv2(LNv2Error::IncomingPayment(
+                    "Invalid signature for static lnurl contracts".to_string(),
+                ))
+            })?;
+
+        if !(LNURL_MIN_SENDABLE_MSAT..=LNURL_MAX_SENDABLE_MSAT).contains(&payload.amount.msats) {
+            return Err(PublicGatewayError::LNv2(LNv2Error::IncomingPayment(
+                format!(
+                    "Amount must be between {LNURL_MIN_SENDABLE_MSAT} and {LNURL_MAX_SENDABLE_MSAT}"
+                ),
+            )));
+        }
+
+        let routing_info = self.routing_info_v2(&payload.federation_id).await?.ok_or(
+            LNv2Error::IncomingPayment(format!(
+                "Federation {} does not exist",
+                payload.federation_id
+            )),
+        )?;
+
+        let contract_amount = routing_info.receive_fee.subtract_from(payload.amount.msats);
+
+        for contract in &payload.contracts {
+            if !contract.verify() {
+                return Err(PublicGatewayError::LNv2(LNv2Error::IncomingPayment(
+                    "The contract is invalid".to_string(),
+                )));
+            }
+
+            if contract.commitment.refund_pk != routing_info.module_public_key {
+                return Err(PublicGatewayError::LNv2(LNv2Error::IncomingPayment(
+                    "The incoming contract is keyed to another gateway".to_string(),
+                )));
+            }
+
+            if !matches!(contract.commitment.payment_image, PaymentImage::Hash(..)) {
+                return Err(PublicGatewayError::LNv2(LNv2Error::IncomingPayment(
+                    "PaymentImage is not a payment hash".to_string(),
+                )));
+            }
+
+            if contract.commitment.amount < MINIMUM_INCOMING_CONTRACT_AMOUNT {
+                return Err(PublicGatewayError::LNv2(LNv2Error::IncomingPayment(
+                    "Amount too small".to_string(),
+                )));
+            }
+
+            if contract.commitment.amount > contract_amount {
+                return Err(PublicGatewayError::LNv2(LNv2Error::IncomingPayment(
+                    "The contract amount does not pay the correct amount of fees".to_string(),
+                )));
+            }
+        }
+
+        let mut dbtx = self.gateway_db.begin_transaction().await;
+
+        for contract in payload.contracts {
+            dbtx.save_static_lnurl_contract(
+                payload.recipient_pk,
+                StaticLnurlContract {
+                    federation_id: payload.federation_id,
+                    amount: payload.amount,
+                    contract,
+                },
+            )
+            .await;
+        }
+
+        if dbtx
+            .count_static_lnurl_contracts(payload.recipient_pk)
+            .await
+            > MAX_STATIC_LNURL_CONTRACTS
+        {
+            return Err(PublicGatewayError::LNv2(LNv2Error::IncomingPayment(
+                format!(
+                    "A recipient can register at most {MAX_STATIC_LNURL_CONTRACTS} unused contracts"
+                ),
+            )));
+        }
+
+        dbtx.commit_tx_result()
+            .await
+            .map_err(|e| PublicGatewayError::Unexpected(e.into()))?;
+
+        Ok(())
+    }
+
+    /// Returns the next contract the recipient of a static LNURL registered,
+    /// which determines the amount of the next payment.
+    async fn next_static_lnurl_contract(
+        &self,
+        request: &LnurlRequest,
+    ) -> Result<StaticLnurlContract> {
+        self.gateway_db
+            .begin_transaction_nc()
+            .await
+            .load_next_static_lnurl_contract(request.recipient_pk)
+            .await
+            .filter(|contract| contract.federation_id == request.federation_id)
+            .ok_or(PublicGatewayError::LNv2(LNv2Error::IncomingPayment(
+                "The recipient has no payments left, it has to come online to receive more"
+                    .to_string(),
+            )))
+    }
+
+    /// Answers the first request of the LNURL-pay protocol for a static
+    /// LNURL created by the lnv2 client's `generate_static_lnurl`. The
+    /// recipient registers the contracts for its payments in advance, each
+    /// of which fixes the amount of one payment.
+    async fn lnurl_pay_v2(&self, payload: &str) -> Result<PayResponse> {
+        let request = self.decode_lnurl_request(payload).await?;
+
+        let contract = self.next_static_lnurl_contract(&request).await?;
+
+        let callback = self
+            .registrations
+            .get(&RegisteredProtocol::Http)
+            .ok_or(PublicGatewayError::Unexpected(anyhow!(
+                "The gateway has no http api address"
+            )))?
+            .endpoint_url
+            .join_path(&format!("lnurl/invoice/{payload}"));
+
+        Ok(PayResponse {
+            callback: callback.to_string(),
+            max_sendable: contract.amount.msats,
+            min_sendable: contract.amount.msats,
+            tag: pay_request_tag(),
+            metadata: lnurl_metadata(&request),
+            comment_allowed: None,
+            allows_nostr: None,
+            nostr_pubkey: None,
+        })
+    }
+
+    /// Creates an invoice for a payment to a static LNURL from the next
+    /// contract the recipient registered. Once the invoice is paid the gateway
+    /// funds the contract, which the recipient claims via the federation's
+    /// contract stream the next time it is online.
+    async fn lnurl_invoice_v2(&self, payload: &str, amount_msats: u64) -> Result<InvoiceResponse> {
+        if !self.invoice_rate_limiter.try_acquire() {
+            return Err(PublicGatewayError::RateLimited);
+        }
+
+        let request = self.decode_lnurl_request(payload).await?;
+
+        let contract = self.next_static_lnurl_contract(&request).await?;
+
+        if amount_msats != contract.amount.msats {
+            return Err(PublicGatewayError::LNv2(LNv2Error::IncomingPayment(
+                format!("Amount must be {}", contract.amount.msats),
+            )));
+        }
+
+        let routing_info = self.routing_info_v2(&request.federation_id).await?.ok_or(
+            LNv2Error::IncomingPayment(format!(
+                "Federation {} does not exist",
+                request.federation_id
+            )),
+        )?;
+
+        // The receive fee follows the federation's fee schedule and may have
+        // risen since the contract was registered.
+        if contract.contract.commitment.amount
+            > routing_info.receive_fee.subtract_from(amount_msats)
+        {
+            return Err(PublicGatewayError::LNv2(LNv2Error::IncomingPayment(
+                "The contract amount does not pay the correct amount of fees".to_string(),
+            )));
+        }
+
+        let contract_id = contract.contract.contract_id();
+
+        // The contract is removed before the invoice is created, such that
+        // concurrent requests never hand out the same contract twice.
+        let mut dbtx = self.gateway_db.begin_transaction().await;
+
+        if !dbtx
+            .remove_static_lnurl_contract(request.recipient_pk, contract_id)
+            .await
+        {
+            return Err(PublicGatewayError::LNv2(LNv2Error::IncomingPayment(
+                "The contract has already been used".to_string(),
+            )));
+        }
+
+        dbtx.commit_tx_result().await.map_err(|_| {
+            PublicGatewayError::LNv2(LNv2Error::IncomingPayment(
+                "The contract has already been used".to_string(),
+            ))
+        })?;
+
+        let invoice = match self
+            .register_contract_and_create_invoice(
+                request.federation_id,
+                contract.contract.clone(),
+                Amount::from_msats(amount_msats),
+                // The invoice commits to the metadata of the pay response as
+                // required by LUD-06
+                Bolt11InvoiceDescription::Hash(sha256::Hash::hash(
+                    lnurl_metadata(&request).as_bytes(),
+                )),
+                LNURL_INVOICE_EXPIRY_SECS,
+                None,
+            )
+            .await
+        {
+            Ok(invoice) => invoice,
+            Err(err) => {
+                // Return the contract such that a failure of the Lightning
+                // node does not use up one of the recipient's payments.
+                let mut dbtx = self.gateway_db.begin_transaction().await;
+                dbtx.save_static_lnurl_contract(request.recipient_pk, contract)
+                    .await;
+                dbtx.commit_tx().await;
+
+                return Err(err);
+            }
+        };
+
+        let verify = self
+            .registrations
+            .get(&RegisteredProtocol::Http)
+            .map(|registration| {
+                registration
+                    .endpoint_url
+                    .join_path(&format!("verify/{}", invoice.payment_hash()))
+                    .to_string()
+            });
+
+        Ok(InvoiceResponse {
+            pr: invoice,
+            verify,
+        })
+    }
+
+    /// Decodes the payload of a static LNURL and checks that it names this
+    /// gateway and is addressed to a federation this gateway is connected to.
+    async fn decode_lnurl_request(&self, payload: &str) -> Result<LnurlRequest> {
+        let request = base32::decode_prefixed::<LnurlRequest>(FEDIMINT_PREFIX, payload)
+            .map_err(|_| LNv2Error::IncomingPayment("Failed to decode payload".to_string()))?;
+
+        // The api address may or may not have been given with a trailing slash
+        let names_this_gateway = self.registrations.values().any(|registration| {
+            request.gateways.iter().any(|gateway| {
+                gateway.as_str().trim_end_matches('/')
+                    == registration.endpoint_url.as_str().trim_end_matches('/')
+            })
+        });
+
+        if !names_this_gateway {
+            return Err(PublicGatewayError::LNv2(LNv2Error::IncomingPayment(
+                "The payload was created for another gateway".to_string(),
+            )));
+        }
+
+        let client = self.select_client(request.federation_id).await?;
+
+        let module = client
+            .value()
+            .get_first_module::<GatewayClientModuleV2>()
+            .map_err(|err| LNv2Error::IncomingPayment(err.to_string()))?;
+
+        if module.cfg.tpe_agg_pk != request.aggregate_pk {
+            return Err(PublicGatewayError::LNv2(LNv2Error::IncomingPayment(
+                "The payload was created for another federation".to_string(),
+            )));
+        }
+
+        Ok(request)
+    }
+
+    /// Creates an LNURL-withdraw link funded from the lightning node's balance
+    /// or the gateway's ecash in a federation.
+    pub async fn handle_create_lnurl_withdraw_msg(
+        &self,
+        payload: CreateLnurlWithdrawPayload,
+    ) -> AdminResult<CreateLnurlWithdrawResponse> {
+        if payload.min_withdrawable > payload.max_withdrawable || payload.uses == 0 {
+            return Err(AdminGatewayError::Unexpected(anyhow!(
+                "The link must allow at least one withdrawal of a valid amount range"
+            )));
+        }
+
+        if let Some(federation_id) = payload.federation_id {
+            self.select_client(federation_id).await?;
+        }
+
+        let endpoint_url = self
+            .registrations
+            .get(&RegisteredProtocol::Http)
+            .ok_or(AdminGatewayError::Unexpected(anyhow!(
+                "The gateway has no http api address"
+            )))?
+            .endpoint_url
+            .clone();
+
+        let link_id = rand::random::<[u8; 32]>();
+
+        let mut dbtx = self.gateway_db.begin_transaction().await;
+
+        dbtx.save_lnurl_withdraw_link(
+            link_id,
+            LnurlWithdrawLink {
+                min_withdrawable: payload.min_withdrawable,
+                max_withdrawable: payload.max_withdrawable,
+                remaining_uses: payload.uses,
+                description: payload.description,
+                federation_id: payload.federation_id,
+            },
+        )
+        .await;
+
+        dbtx.commit_tx_result()
+            .await
+            .map_err(|e| AdminGatewayError::Unexpected(e.into()))?;
+
+        let url = endpoint_url.join_path(&format!("lnurl/withdraw/{}", hex::encode(link_id)));
+
+        Ok(CreateLnurlWithdrawResponse {
+            lnurl: fedimint_lnurl::encode_lnurl(url.as_str()),
+        })
+    }
+
+    /// Returns the LNURL-withdraw response (LUD-03) of a withdraw link as long
+    /// as it has uses left.
+    pub async fn lnurl_withdraw(
+        &self,
+        link_id: &str,
+    ) -> std::result::Result<fedimint_lnurl::WithdrawResponse, String> {
+        let (link_id, link) = self.load_lnurl_withdraw_link(link_id).await?;
+
+        let callback = self
+            .registrations
+            .get(&RegisteredProtocol::Http)
+            .ok_or("The gateway has no http api address".to_string())?
+            .endpoint_url
+            .join_path(&format!("lnurl/withdraw/{}/callback", hex::encode(link_id)));
+
+        Ok(fedimint_lnurl::WithdrawResponse {
+            tag: withdraw_request_tag(),
+            callback: callback.to_string(),
+            k1: hex::encode(link_id),
+            default_description: link.description,
+            min_withdrawable: link.min_withdrawable.msats,
+            max_withdrawable: link.max_withdrawable.msats,
+        })
+    }
+
+    /// Accepts an invoice submitted to the callback of a withdraw link. The
+    /// invoice is checked against the link and the balance funding it, then a
+    /// use of the link is consumed and the withdrawal is recorded, such that
+    /// the payment of the invoice in the background is resumed if the gateway
+    /// restarts before it completes.
+    pub async fn lnurl_withdraw_callback(
+        &self,
+        link_id: &str,
+        k1: &str,
+        invoice: &str,
+    ) -> std::result::Result<(), String> {
+        if link_id != k1 {
+            return Err("Invalid k1".to_string());
+        }
+
+        let invoice =
+            Bolt11Invoice::from_str(invoice).map_err(|_| "Failed to parse invoice".to_string())?;
+
+        let amount = Amount::from_msats(
+            invoice
+                .amount_milli_satoshis()
+                .ok_or("Invoice is missing an amount".to_string())?,
+        );
+
+        // Callbacks are serialized such that the balance checks account for all
+        // withdrawals accepted before.
+        let _guard = self.lnurl_withdraw_lock.lock().await;
+
+        let (link_id, link) = self.load_lnurl_withdraw_link(link_id).await?;
+
+        if amount < link.min_withdrawable || link.max_withdrawable < amount {
+            return Err(format!(
+                "Amount must be between {} and {}",
+                link.min_withdrawable, link.max_withdrawable
+            ));
+        }
+
+        let pending_withdrawals = self
+            .gateway_db
+            .begin_transaction_nc()
+            .await
+            .load_lnurl_withdrawals()
+            .await;
+
+        match link.federation_id {
+            Some(federation_id) => {
+                self.check_federation_withdraw_balance(
+                    federation_id,
+                    &invoice,
+                    &pending_withdrawals,
+                )
+                .await?;
+            }
+            None => {
+                self.check_lightning_withdraw_balance(amount, &pending_withdrawals)
+                    .await?;
+            }
+        }
+
+        let withdrawal = LnurlWithdrawal {
+            link_id,
+            invoice,
+            federation_id: link.federation_id,
+            created_at_secs: duration_since_epoch().as_secs(),
+        };
+
+        self.start_lnurl_withdrawal(withdrawal.clone()).await?;
+
+        self.spawn_lnurl_withdrawal(withdrawal);
+
+        Ok(())
+    }
+
+    async fn load_lnurl_withdraw_link(
+        &self,
+        link_id: &str,
+    ) -> std::result::Result<([u8; 32], LnurlWithdrawLink), String> {
+        let link_id = <[u8; 32]>::from_hex(link_id).map_err(|_| "Invalid link id".to_string())?;
+
+        let link = self
+            .gateway_db
+            .begin_transaction_nc()
+            .await
+            .load_lnurl_withdraw_link(link_id)
+            .await
+            .ok_or("Unknown withdraw link".to_string())?;
+
+        if link.remaining_uses == 0 {
+            return Err("The withdraw link has been used up".to_string());
+        }
+
+        Ok((link_id, link))
+    }
+
+    /// Consumes a use of the withdraw link and records the withdrawal in the
+    /// same transaction. Fails if another withdrawal took the last use
+    /// concurrently or the invoice is already being paid.
+    async fn start_lnurl_withdrawal(
+        &self,
+        withdrawal: LnurlWithdrawal,
+    ) -> std::result::Result<(), String> {
+        self.gateway_db
+            .autocommit(
+                |dbtx, _| {
+                    let withdrawal = withdrawal.clone();
+
+                    Box::pin(async move {
+                        if dbtx
+                            .load_lnurl_withdrawal(*withdrawal.invoice.payment_hash())
+                            .await
+                            .is_some()
+                        {
+                            return Err("The invoice is already being paid".to_string());
+                        }
+
+                        let mut link = dbtx
+                            .load_lnurl_withdraw_link(withdrawal.link_id)
+                            .await
+                            .ok_or("Unknown withdraw link".to_string())?;
+
+                        link.remaining_uses = link
+                            .remaining_uses
+                            .checked_sub(1)
+                            .ok_or("The withdraw link has been used up".to_string())?;
+
+                        dbtx.save_lnurl_withdraw_link(withdrawal.link_id, link)
+                            .await;
+
+                        dbtx.save_lnurl_withdrawal(withdrawal).await;
+
+                        Ok::<(), String>(())
+                    })
+                },
+                None,
+            )
+            .await
+            .map_err(|e| match e {
+                AutocommitError::ClosureError { error, .. } => error,
+                AutocommitError::CommitFailed { last_error, .. } => last_error.to_string(),
+            })
+    }
+
+    /// Removes the record of a withdrawal once its payment is final and
+    /// restores the use of the withdraw link unless the invoice has been paid.
+    async fn finish_lnurl_withdrawal(
+        &self,
+        withdrawal: &LnurlWithdrawal,
+        paid: bool,
+    ) -> anyhow::Result<()> {
+        let mut dbtx = self.gateway_db.begin_transaction().await;
+
+        dbtx.remove_lnurl_withdrawal(*withdrawal.invoice.payment_hash())
+            .await;
+
+        if !paid && let Some(mut link) = dbtx.load_lnurl_withdraw_link(withdrawal.link_id).await {
+            link.remaining_uses += 1;
+
+            dbtx.save_lnurl_withdraw_link(withdrawal.link_id, link)
+                .await;
+        }
+
+        dbtx.commit_tx_result().await?;
+
+        Ok(())
+    }
+
+    /// Resumes the payments of all withdrawals that were accepted before the
+    /// gateway restarted.
+    async fn resume_lnurl_withdrawals(&self) {
+        let withdrawals = self
+            .gateway_db
+            .begin_transaction_nc()
+            .await
+            .load_lnurl_withdrawals()
+            .await;
+
+        for withdrawal in withdrawals {
+            self.spawn_lnurl_withdrawal(withdrawal);
+        }
+    }
+
+    /// Spawns a task that pays the invoice of a withdrawal until its payment
+    /// is final. The use of the withdraw link is only restored if the payment
+    /// definitively failed, since an error of the lightning node does not
+    /// imply that the invoice has not been paid.
+    fn spawn_lnurl_withdrawal(&self, withdrawal: LnurlWithdrawal) {
+        let gateway = self.clone();
+
+        self.task_group
+            .spawn_cancellable("lnurl withdraw payment", async move {
+                let payment_hash = *withdrawal.invoice.payment_hash();
+
+                let mut unstarted_attempts = 0;
+
+                let paid = loop {
+                    match gateway.pay_lnurl_withdrawal(&withdrawal).await {
+                        Ok(Some(paid)) => break paid,
+                        Ok(None) => {
+                            unstarted_attempts += 1;
+
+                            if unstarted_attempts == LNURL_WITHDRAW_MAX_UNSTARTED_ATTEMPTS {
+                                break false;
+                            }
+                        }
+                        Err(e) => {
+                            warn!(
+                                target: LOG_GATEWAY,
+                                err = %e.fmt_compact_anyhow(),
+                                %payment_hash,
+                                "Outcome of lnurl withdraw payment is unknown, retrying"
+                            );
+                        }
+                    }
+
+                    sleep(LNURL_WITHDRAW_RETRY_INTERVAL).await;
+                };
+
+                if !paid {
+                    warn!(
+                        target: LOG_GATEWAY,
+                        %payment_hash,
+                        "Failed to pay lnurl withdraw invoice, restoring the use of the link"
+                    );
+                }
+
+                if let Err(e) = gateway.finish_lnurl_withdrawal(&withdrawal, paid).await {
+                    warn!(
+                        target: LOG_GATEWAY,
+                        err = %e.fmt_compact_anyhow(),
+                        %payment_hash,
+                        "Failed to record the outcome of lnurl withdraw payment"
+                    );
+                }
+            });
+    }
+
+    /// Checks that the lightning node can afford to pay a withdrawal including
+    /// the maximum routing fee, on top of the withdrawals it is still paying.
+    async fn check_lightning_withdraw_balance(
+        &self,
+        amount: Amount,
+        pending_withdrawals: &[LnurlWithdrawal],
+    ) -> std::result::Result<(), String> {
+        let balances = self
+            .get_lightning_context()
+            .await
+            .map_err(|e| e.to_string())?
+            .lnrpc
+            .get_balances()
+            .await
+            .map_err(|e| e.to_string())?;
+
+        let pending_msats = pending_withdrawals
+            .iter()
+            .filter(|withdrawal| withdrawal.federation_id.is_none())
+            .map(|withdrawal| {
+                let amount = lnurl_withdrawal_amount(withdrawal);
+
+                (amount + lnurl_withdraw_max_fee(amount)).msats
+            })
+            .sum::<u64>();
+
+        if balances.lightning_balance_msats
+            < pending_msats + (amount + lnurl_withdraw_max_fee(amount)).msats
+        {
+            return Err("The service has insufficient funds".to_string());
+        }
+
+        Ok(())
+    }
+
+    /// A withdrawal funded by a federation is paid by funding the incoming
+    /// contract of the invoice directly with the gateway's ecash, hence the
+    /// invoice has to be created by this gateway for a recipient in the
+    /// federation and the gateway's ecash balance has to cover the contract
+    /// as well as the withdrawals from the federation it is still paying.
+    async fn check_federation_withdraw_balance(
+        &self,
+        federation_id: FederationId,
+        invoice: &Bolt11Invoice,
+        pending_withdrawals: &[LnurlWithdrawal],
+    ) -> std::result::Result<(), String> {
+        let (contract, client) = self
+            .is_direct_swap(invoice)
+            .await
+            .map_err(|e| e.to_string())?
+            .ok_or("The invoice has to be created via this gateway".to_string())?;
+
+        if client.federation_id() != federation_id {
+            return Err(format!(
+                "The withdraw link can only be redeemed into federation {federation_id}"
+            ));
+        }
+
+        let balance = client
+            .get_balance_for_btc()
+            .await
+            .map_err(|e| e.to_string())?;
+
+        let pending = pending_withdrawals
+            .iter()
+            .filter(|withdrawal| withdrawal.federation_id == Some(federation_id))
+            .map(lnurl_withdrawal_amount)
+            .sum::<Amount>();
+
+        if balance < pending + contract.commitment.amount {
+            return Err("The service has insufficient funds".to_string());
+        }
+
+        Ok(())
+    }
+
+    /// Pays the invoice of a withdrawal. Returns whether the invoice has been
+    /// paid once the payment is final and `None` if there is no trace of the
+    /// payment, while an error means that its outcome is not known yet.
+    async fn pay_lnurl_withdrawal(
         &self,
-        payment_hash: sha256::Hash,
-        amount: Amount,
-        description: Bolt11InvoiceDescription,
-        expiry_time: u32,
-    ) -> std::result::Result<Bolt11Invoice, LightningRpcError> {
-        let lnrpc = self.get_lightning_context().await?.lnrpc;
+        withdrawal: &LnurlWithdrawal,
+    ) -> anyhow::Result<Option<bool>> {
+        let amount = lnurl_withdrawal_amount(withdrawal);
+
+        if withdrawal.federation_id.is_some() {
+            let Ok(Some((contract, client))) = self.is_direct_swap(&withdrawal.invoice).await
+            else {
+                return Ok(None);
+            };
 
-        let response = match description {
-            Bolt11InvoiceDescription::Direct(description) => {
-                lnrpc
-                    .create_invoice(CreateInvoiceRequest {
-                        payment_hash: Some(payment_hash),
-                        amount_msat: amount.msats,
-                        expiry_secs: expiry_time,
-                        description: Some(InvoiceDescription::Direct(description)),
-                    })
-                    .await?
-            }
-            Bolt11InvoiceDescription::Hash(hash) => {
-                lnrpc
-                    .create_invoice(CreateInvoiceRequest {
-                        payment_hash: Some(payment_hash),
-                        amount_msat: amount.msats,
-                        expiry_secs: expiry_time,
-                        description: Some(InvoiceDescription::Hash(hash)),
-                    })
-                    .await?
-            }
+            let module = client.get_first_module::<GatewayClientModuleV2>()?;
+
+            return match module
+                .relay_direct_swap(contract.clone(), amount.msats)
+                .await
+            {
+                Ok(FinalReceiveState::Success(..)) => Ok(Some(true)),
+                Ok(_) => Ok(Some(false)),
+                Err(..) if !module.incoming_contract_funded(&contract).await => Ok(None),
+                Err(e) => Err(e),
+            };
+        }
+
+        let context = self.get_lightning_context().await?;
+
+        let Err(error) = context
+            .lnrpc
+            .pay(
+                withdrawal.invoice.clone(),
+                LNURL_WITHDRAW_MAX_DELAY,
+                lnurl_withdraw_max_fee(amount),
+            )
+            .await
+        else {
+            return Ok(Some(true));
         };
 
-        Bolt11Invoice::from_str(&response.invoice).map_err(|e| {
-            LightningRpcError::FailedToGetInvoice {
-                failure_reason: e.to_string(),
-            }
-        })
+        // The lightning node reports any error as a failed payment, hence only
+        // its payment history tells whether the payment has actually failed.
+        let status = context
+            .lnrpc
+            .list_transactions(
+                withdrawal.created_at_secs,
+                duration_since_epoch().as_secs() + 1,
+            )
+            .await?
+            .transactions
+            .into_iter()
+            .find(|payment| {
+                payment.direction == PaymentDirection::Outbound
+                    && payment.payment_hash == Some(*withdrawal.invoice.payment_hash())
+            })
+            .map(|payment| payment.status);
+
+        match status {
+            Some(PaymentStatus::Succeeded) => Ok(Some(true)),
+            Some(PaymentStatus::Failed) => Ok(Some(false)),
+            Some(PaymentStatus::Pending) => Err(anyhow!("The payment is pending: {error}")),
+            None => Ok(None),
+        }
     }
 
     pub async fn verify_bolt11_preimage_v2(
@@ -3642,6 +5308,346 @@ pub async fn get_registered_incoming_contract_and_client_v2(
 
         Ok((registered_incoming_contract.contract, client))
     }
+
+    async fn hold_invoice_status_v2(
+        &self,
+        payload: HoldInvoiceStatusPayload,
+    ) -> Result<HoldInvoiceStatus> {
+        let hold_invoice = self
+            .gateway_db
+            .begin_transaction_nc()
+            .await
+            .load_hold_invoice(payload.contract_id)
+            .await
+            .filter(|hold_invoice| hold_invoice.federation_id == payload.federation_id)
+            .ok_or(PublicGatewayError::LNv2(LNv2Error::IncomingPayment(
+                "No corresponding hold invoice available".to_string(),
+            )))?;
+
+        Ok(match hold_invoice.state {
+            HoldInvoiceState::Pending => HoldInvoiceStatus::Pending,
+            HoldInvoiceState::Held(held) => HoldInvoiceStatus::Held {
+                deadline_secs: held.deadline_secs,
+            },
+            HoldInvoiceState::Settled => HoldInvoiceStatus::Settled,
+            HoldInvoiceState::Cancelled => HoldInvoiceStatus::Cancelled,
+        })
+    }
+
+    /// Settles or cancels a hold invoice on behalf of the recipient, who proves
+    /// ownership of the incoming contract with a signature of its claim key.
+    /// Repeating a decision is idempotent, reverting it is an error.
+    async fn resolve_hold_invoice_v2(&self, payload: ResolveHoldInvoicePayload) -> Result<()> {
+        let hold_invoice = self
+            .gateway_db
+            .begin_transaction_nc()
+            .await
+            .load_hold_invoice(payload.contract_id)
+            .await
+            .filter(|hold_invoice| hold_invoice.federation_id == payload.federation_id)
+            .ok_or(PublicGatewayError::LNv2(LNv2Error::IncomingPayment(
+                "No corresponding hold invoice available".to_string(),
+            )))?;
+
+        secp256k1::SECP256K1
+            .verify_schnorr(
+                &payload.signature,
+                &resolve_hold_invoice_message(&payload.contract_id, payload.settle),
+                &hold_invoice
+                    .contract
+                    .commitment
+                    .claim_pk
+                    .x_only_public_key()
+                    .0,
+            )
+            .map_err(|_| {
+                PublicGatewayError::LNv2(LNv2Error::IncomingPayment(
+                    "Invalid signature for hold invoice".to_string(),
+                ))
+            })?;
+
+        if payload.settle {
+            self.settle_hold_invoice(payload.contract_id).await
+        } else {
+            self.cancel_hold_invoice(payload.contract_id, "The recipient cancelled the payment")
+                .await
+        }
+    }
+
+    /// Funds the incoming contract of a hold invoice whose payment is held,
+    /// such that the HTLC is settled once the federation has decrypted the
+    /// preimage.
+    async fn settle_hold_invoice(&self, contract_id: ContractId) -> Result<()> {
+        let _guard = self.hold_invoice_lock.lock().await;
+
+        let Some(mut hold_invoice) = self
+            .gateway_db
+            .begin_transaction_nc()
+            .await
+            .load_hold_invoice(contract_id)
+            .await
+        else {
+            return Err(PublicGatewayError::LNv2(LNv2Error::IncomingPayment(
+                "No corresponding hold invoice available".to_string(),
+            )));
+        };
+
+        let held = match hold_invoice.state {
+            HoldInvoiceState::Held(held) => held,
+            HoldInvoiceState::Settled => return Ok(()),
+            HoldInvoiceState::Pending => {
+                return Err(PublicGatewayError::LNv2(LNv2Error::IncomingPayment(
+                    "The hold invoice has not been paid yet".to_string(),
+                )));
+            }
+            HoldInvoiceState::Cancelled => {
+                return Err(PublicGatewayError::LNv2(LNv2Error::IncomingPayment(
+                    "The hold invoice has already been cancelled".to_string(),
+                )));
+            }
+        };
+
+        if held.deadline_secs <= duration_since_epoch().as_secs() {
+            return Err(PublicGatewayError::LNv2(LNv2Error::IncomingPayment(
+                "The deadline to settle the hold invoice has passed".to_string(),
+            )));
+        }
+
+        let client = self
+            .select_client(hold_invoice.federation_id)
+            .await?
+            .into_value();
+
+        let module = client
+            .get_first_module::<GatewayClientModuleV2>()
+            .expect("Must have client module");
+
+        let Err(err) = module
+            .relay_incoming_htlc(
+                held.payment_hash,
+                held.incoming_chan_id,
+                held.htlc_id,
+                hold_invoice.contract.clone(),
+                held.amount_msat,
+            )
+            .await
+        else {
+            hold_invoice.state = HoldInvoiceState::Settled;
+
+            return self.save_hold_invoice(contract_id, hold_invoice).await;
+        };
+
+        warn!(target: LOG_GATEWAY, err = %err.fmt_compact_anyhow(), "Error relaying settled hold invoice");
+
+        // Once the funding transaction has been submitted the circuit
+        // completion resolves the HTLC, so we leave the invoice held and let
+        // the recipient or the expiry task retry.
+        if module
+            .incoming_contract_funded(&hold_invoice.contract)
+            .await
+        {
+            return Err(PublicGatewayError::LNv2(LNv2Error::IncomingPayment(
+                err.to_string(),
+            )));
+        }
+
+        module
+            .cancel_held_htlc(
+                held.payment_hash,
+                held.incoming_chan_id,
+                held.htlc_id,
+                &hold_invoice.contract,
+                err.to_string(),
+            )
+            .await
+            .map_err(|err| LNv2Error::IncomingPayment(err.to_string()))?;
+
+        hold_invoice.state = HoldInvoiceState::Cancelled;
+
+        self.save_hold_invoice(contract_id, hold_invoice).await?;
+
+        Err(PublicGatewayError::LNv2(LNv2Error::IncomingPayment(
+            format!("Failed to settle the hold invoice: {err}"),
+        )))
+    }
+
+    /// Cancels a hold invoice and fails back its HTLC if the payment is held.
+    /// The cancellation is only recorded once the HTLC has been failed back,
+    /// such that the expiry task retries if the lightning node is unavailable.
+    async fn cancel_hold_invoice(&self, contract_id: ContractId, reason: &str) -> Result<()> {
+        let _guard = self.hold_invoice_lock.lock().await;
+
+        let Some(mut hold_invoice) = self
+            .gateway_db
+            .begin_transaction_nc()
+            .await
+            .load_hold_invoice(contract_id)
+            .await
+        else {
+            return Err(PublicGatewayError::LNv2(LNv2Error::IncomingPayment(
+                "No corresponding hold invoice available".to_string(),
+            )));
+        };
+
+        let held = match hold_invoice.state {
+            HoldInvoiceState::Held(held) => Some(held),
+            HoldInvoiceState::Pending => None,
+            HoldInvoiceState::Cancelled => return Ok(()),
+            HoldInvoiceState::Settled => {
+                return Err(PublicGatewayError::LNv2(LNv2Error::IncomingPayment(
+                    "The hold invoice has already been settled".to_string(),
+                )));
+            }
+        };
+
+        if let Some(held) = held {
+            let client = self
+                .select_client(hold_invoice.federation_id)
+                .await?
+                .into_value();
+
+            let module = client
+                .get_first_module::<GatewayClientModuleV2>()
+                .expect("Must have client module");
+
+            // A settlement that funded the contract but was interrupted before
+            // recording its state has to be completed instead.
+            if module
+                .incoming_contract_funded(&hold_invoice.contract)
+                .await
+            {
+                module
+                    .relay_incoming_htlc(
+                        held.payment_hash,
+                        held.incoming_chan_id,
+                        held.htlc_id,
+                        hold_invoice.contract.clone(),
+                        held.amount_msat,
+                    )
+                    .await
+                    .map_err(|err| LNv2Error::IncomingPayment(err.to_string()))?;
+
+                hold_invoice.state = HoldInvoiceState::Settled;
+
+                self.save_hold_invoice(contract_id, hold_invoice).await?;
+
+                return Err(PublicGatewayError::LNv2(LNv2Error::IncomingPayment(
+                    "The hold invoice has already been settled".to_string(),
+                )));
+            }
+
+            module
+                .cancel_held_htlc(
+                    held.payment_hash,
+                    held.incoming_chan_id,
+                    held.htlc_id,
+                    &hold_invoice.contract,
+                    reason.to_string(),
+                )
+                .await
+                .map_err(|err| LNv2Error::IncomingPayment(err.to_string()))?;
+        }
+
+        hold_invoice.state = HoldInvoiceState::Cancelled;
+
+        self.save_hold_invoice(contract_id, hold_invoice).await
+    }
+
+    /// Records the resolution of a hold invoice.
+    async fn save_hold_invoice(
+        &self,
+        contract_id: ContractId,
+        hold_invoice: HoldInvoice,
+    ) -> Result<()> {
+        let mut dbtx = self.gateway_db.begin_transaction().await;
+
+        dbtx.save_hold_invoice(contract_id, hold_invoice).await;
+
+        dbtx.commit_tx_result().await.map_err(|_| {
+            PublicGatewayError::LNv2(LNv2Error::IncomingPayment(
+                "Hold invoice has been modified concurrently".to_string(),
+            ))
+        })
+    }
+}
+
+/// Extracts the prefix of the federation that issued `notes`, which can be
+/// either `ECash` or `OOBNotes`.
+fn ecash_federation_id_prefix(notes: &str) -> Option<FederationIdPrefix> {
+    base32::decode_prefixed::<fedimint_mintv2_client::ECash>(FEDIMINT_PREFIX, notes)
+        .ok()
+        .and_then(|e| e.mint())
+        .map(|id| id.to_prefix())
+        .or_else(|| {
+            OOBNotes::from_str(notes)
+                .ok()
+                .map(|n| n.federation_id_prefix())
+        })
+}
+
+/// The maximum routing fee the lightning node may pay for an LNURL-withdraw
+/// payment of the given amount.
+fn lnurl_withdraw_max_fee(amount: Amount) -> Amount {
+    Amount::from_msats(LNURL_WITHDRAW_BASE_FEE_MSAT + amount.msats / 100)
+}
+
+fn lnurl_withdrawal_amount(withdrawal: &LnurlWithdrawal) -> Amount {
+    Amount::from_msats(
+        withdrawal
+            .invoice
+            .amount_milli_satoshis()
+            .expect("The amount of the invoice has been checked by the callback"),
+    )
+}
+
+/// The LNURL-pay metadata of a static LNURL served by the gateway.
+fn lnurl_metadata(request: &LnurlRequest) -> String {
+    PayMetadata::text(format!("Pay to federation {}", request.federation_id)).encode()
+}
+
+/// Creates an incoming contract locked to the recipient's static public key,
+/// encoding the gateway fee in place of an expiration such that the contract
+/// can be claimed whenever the recipient comes online. The recipient recovers
+/// the contract keys from the ephemeral public key in the same way as for
+/// contracts created via LNURL.
+///
+/// The gateway knows the preimage of the contract, so this is only used to
+/// credit payments to BOLT12 offers, whose invoices the gateway's Lightning
+/// node creates and therefore knows the preimage of regardless.
+fn recipient_incoming_contract(
+    module: &GatewayClientModuleV2,
+    recipient_pk: PublicKey,
+    contract_amount: Amount,
+    fee_msats: u64,
+) -> IncomingContract {
+    let (ephemeral_tweak, ephemeral_pk) = tweak::generate(recipient_pk);
+
+    let encryption_seed = ephemeral_tweak
+        .consensus_hash::<sha256::Hash>()
+        .to_byte_array();
+
+    let preimage = encryption_seed
+        .consensus_hash::<sha256::Hash>()
+        .to_byte_array();
+
+    let claim_pk = recipient_pk
+        .mul_tweak(
+            secp256k1::SECP256K1,
+            &Scalar::from_be_bytes(ephemeral_tweak).expect("Within curve order"),
+        )
+        .expect("Tweak is valid");
+
+    IncomingContract::new(
+        module.cfg.tpe_agg_pk,
+        encryption_seed,
+        preimage,
+        PaymentImage::Hash(preimage.consensus_hash()),
+        contract_amount,
+        fee_encoded_expiration(fee_msats),
+        claim_pk,
+        module.keypair.public_key(),
+        ephemeral_pk,
+    )
 }
 
 #[async_trait]
@@ -3709,11 +5715,85 @@ async fn pay(
         // The send state machine forfeits the outgoing contract on any error from
         // here, so only the lightning node gets to say this payment failed.
         let lightning_context = self.await_lightning_context().await;
-        lightning_context
+        let payment_hash = *invoice.payment_hash();
+        let response = lightning_context
             .lnrpc
             .pay(invoice, max_delay, max_fee)
+            .await?;
+        self.record_routing_fee(payment_hash, &response).await;
+        Ok(response.preimage.0)
+    }
+
+    async fn pay_part(
+        &self,
+        invoice: Bolt11Invoice,
+        amount: Amount,
+        max_delay: u64,
+        max_fee: Amount,
+    ) -> std::result::Result<[u8; 32], LightningRpcError> {
+        let lightning_context = self.await_lightning_context().await;
+        let payment_hash = *invoice.payment_hash();
+        let response = lightning_context
+            .lnrpc
+            .pay_part(invoice, amount, max_delay, max_fee)
+            .await?;
+        self.record_routing_fee(payment_hash, &response).await;
+        Ok(response.preimage.0)
+    }
+
+    async fn pay_offer_invoice(
+        &self,
+        invoice: Bolt12OfferInvoice,
+        max_delay: u64,
+        max_fee: Amount,
+    ) -> std::result::Result<[u8; 32], LightningRpcError> {
+        let fetched_invoice = self
+            .gateway_db
+            .begin_transaction_nc()
+            .await
+            .load_fetched_offer_invoice(invoice.payment_hash)
             .await
-            .map(|response| response.preimage.0)
+            .ok_or(LightningRpcError::Bolt12Error {
+                failure_reason: "The offer invoice was not fetched by this gateway".to_string(),
+            })?;
+
+        if fetched_invoice.amount != invoice.amount {
+            return Err(LightningRpcError::Bolt12Error {
+                failure_reason: "The amount does not match the fetched offer invoice".to_string(),
+            });
+        }
+
+        let lightning_context = self.await_lightning_context().await;
+        let response = lightning_context
+            .lnrpc
+            .pay_offer_invoice(fetched_invoice.invoice, max_delay, max_fee)
+            .await?;
+        self.record_routing_fee(invoice.payment_hash, &response)
+            .await;
+        Ok(response.preimage.0)
+    }
+
+    async fn pay_keysend(
+        &self,
+        payment: KeysendPayment,
+        max_delay: u64,
+        max_fee: Amount,
+    ) -> std::result::Result<[u8; 32], LightningRpcError> {
+        let lightning_context = self.await_lightning_context().await;
+        let payment_hash = sha256::Hash::hash(&payment.preimage);
+        let response = lightning_context
+            .lnrpc
+            .pay_keysend(
+                payment.payee,
+                payment.amount,
+                Preimage(payment.preimage),
+                payment.custom_records,
+                max_delay,
+                max_fee,
+            )
+            .await?;
+        self.record_routing_fee(payment_hash, &response).await;
+        Ok(response.preimage.0)
     }
 
     async fn min_contract_amount(
@@ -3928,21 +6008,26 @@ async fn pay(
         // `GatewayPayInvoice` cancels the outgoing contract on any error from
         // here, so only the lightning node gets to say this payment failed.
         let lightning_context = self.await_lightning_context().await;
+        let payment_hash = payment_data.payment_hash();
 
-        match payment_data {
+        let response = match payment_data {
             PaymentData::Invoice(invoice) => {
                 lightning_context
                     .lnrpc
                     .pay(invoice, max_delay, max_fee)
-                    .await
+                    .await?
             }
             PaymentData::PrunedInvoice(invoice) => {
                 lightning_context
                     .lnrpc
                     .pay_private(invoice, max_delay, max_fee)
-                    .await
+                    .await?
             }
-        }
+        };
+
+        self.record_routing_fee(payment_hash, &response).await;
+
+        Ok(response)
     }
 
     async fn complete_htlc(
@@ -0,0 +1,445 @@
+use std::collections::BTreeMap;
+use std::time::{Duration, UNIX_EPOCH};
+
+use anyhow::ensure;
+use fedimint_core::config::FederationId;
+use fedimint_core::time::{duration_since_epoch, now};
+use fedimint_core::util::FmtCompactAnyhow as _;
+use fedimint_core::{Amount, BitcoinAmountOrAll};
+use fedimint_eventlog::{DBTransactionEventLogExt as _, EventLogId, PersistedLogEntry};
+use fedimint_gateway_common::{
+    ChannelOpenReason, GatewayBalances, LiquidityAction, LiquidityActionTaken, LiquidityEvent,
+    LiquidityLogPayload, LiquidityLogResponse, LiquidityOutcome, LiquidityPolicy,
+    PeginFromOnchainPayload, SetLiquidityPolicyPayload, WithdrawToOnchainPayload,
+};
+use fedimint_gateway_server_db::GatewayDbtxNcExt as _;
+use fedimint_logging::LOG_GATEWAY;
+use fedimint_wallet_client::events::DepositConfirmed;
+use fedimint_walletv2_client::events::ReceivePaymentEvent;
+use tracing::{debug, info};
+
+use crate::error::AdminGatewayError;
+use crate::events::get_events_for_duration;
+use crate::ledger::to_event;
+use crate::{AdminResult, Gateway, IAdminGateway as _};
+
+/// How often the liquidity manager compares the gateway's balances against
+/// its policy.
+const LIQUIDITY_CHECK_INTERVAL: Duration = Duration::from_mins(5);
+
+/// The longest cooldown a policy may set.
+const MAX_COOLDOWN_SECS: u64 = 60 * 60 * 24 * 30;
+
+/// How long after a peg-in its deposit is expected to be credited. A peg-in
+/// whose deposit has not been credited by then, e.g. since its transaction was
+/// dropped, no longer counts as in flight.
+const PEGIN_IN_FLIGHT_WINDOW: Duration = Duration::from_hours(24);
+
+/// Returns the actions that move the gateway's balances back into the ranges
+/// of the policy. Peg-ins are funded from the onchain balance above the
+/// reserve, in the order of the federations' ids. Peg-ins that have been sent
+/// but not credited yet count towards the ecash balance when deciding whether
+/// to peg in.
+fn plan_liquidity_actions(
+    policy: &LiquidityPolicy,
+    balances: &GatewayBalances,
+    in_flight_pegins: &BTreeMap<FederationId, Amount>,
+) -> Vec<LiquidityAction> {
+    let mut actions = Vec::new();
+    let mut spendable_onchain_sats = balances
+        .onchain_balance_sats
+        .saturating_sub(policy.onchain_reserve_sats);
+
+    for (federation_id, range) in &policy.federations {
+        // The gateway might have left the federation since the policy was set
+        let Some(ecash_balance) = balances
+            .ecash_balances
+            .iter()
+            .find(|balance| balance.federation_id == *federation_id)
+            .map(|balance| balance.ecash_balance_msats)
+        else {
+            continue;
+        };
+
+        // Only ecash that has been credited can be pegged out
+        let expected_ecash_balance = ecash_balance
+            + in_flight_pegins
+                .get(federation_id)
+                .copied()
+                .unwrap_or(Amount::ZERO);
+
+        if expected_ecash_balance < range.min {
+            let amount_sats = range
+                .target()
+                .saturating_sub(expected_ecash_balance)
+                .sats_round_down()
+                .min(spendable_onchain_sats);
+
+            if amount_sats > 0 {
+                spendable_onchain_sats -= amount_sats;
+                actions.push(LiquidityAction::PegIn {
+                    federation_id: *federation_id,
+                    amount_sats,
+                });
+            }
+        } else if ecash_balance > range.max {
+            let amount_sats = ecash_balance
+                .saturating_sub(range.target())
+                .sats_round_down();
+
+            if amount_sats > 0 {
+                actions.push(LiquidityAction::PegOut {
+                    federation_id: *federation_id,
+                    amount_sats,
+                });
+            }
+        }
+    }
+
+    if let Some(range) = policy.outbound {
+        let outbound = Amount::from_msats(balances.lightning_balance_msats);
+
+        if outbound < range.min {
+            actions.push(LiquidityAction::ChannelOpenProposal {
+                reason: ChannelOpenReason::LowOutbound,
+                amount_sats: range.target().saturating_sub(outbound).sats_round_down(),
+            });
+        } else if outbound > range.max {
+            actions.push(LiquidityAction::SwapOutProposal {
+                amount: outbound.saturating_sub(range.target()),
+            });
+        }
+    }
+
+    if let Some(min_inbound) = policy.min_inbound {
+        let inbound = Amount::from_msats(balances.inbound_lightning_liquidity_msats);
+
+        if inbound < min_inbound {
+            actions.push(LiquidityAction::ChannelOpenProposal {
+                reason: ChannelOpenReason::LowInbound,
+                amount_sats: min_inbound.saturating_sub(inbound).sats_round_down(),
+            });
+        }
+    }
+
+    actions
+}
+
+/// Drops the actions for federations, or the node's channels, that already saw
+/// an action within the cooldown. This also applies to dry-run and failed
+/// actions, such that the log is not flooded with the same action.
+fn without_cooling_down(
+    actions: Vec<LiquidityAction>,
+    recent_events: &[LiquidityEvent],
+    now_secs: u64,
+    cooldown_secs: u64,
+) -> Vec<LiquidityAction> {
+    actions
+        .into_iter()
+        .filter(|action| {
+            !recent_events.iter().any(|event| {
+                event.action.federation_id() == action.federation_id()
+                    && now_secs.saturating_sub(event.timestamp_secs) < cooldown_secs
+            })
+        })
+        .collect()
+}
+
+/// Converts an entry of the gateway's event log into an entry of the liquidity
+/// manager's log, if it records an action of the liquidity manager.
+fn liquidity_event(entry: &PersistedLogEntry) -> Option<LiquidityEvent> {
+    let LiquidityActionTaken { action, outcome } = to_event(entry)?;
+
+    Some(LiquidityEvent {
+        timestamp_secs: entry.as_raw().ts_usecs / 1_000_000,
+        action,
+        outcome,
+    })
+}
+
+fn verify_liquidity_policy(policy: &LiquidityPolicy) -> anyhow::Result<()> {
+    for (federation_id, range) in &policy.federations {
+        ensure!(
+            range.min <= range.max,
+            "The ecash range of federation {federation_id} is empty"
+        );
+    }
+
+    if let Some(range) = policy.outbound {
+        ensure!(range.min <= range.max, "The outbound range is empty");
+    }
+
+    ensure!(
+        policy.cooldown_secs <= MAX_COOLDOWN_SECS,
+        "The cooldown must not exceed {MAX_COOLDOWN_SECS} seconds"
+    );
+
+    Ok(())
+}
+
+impl Gateway {
+    /// Spawns a background task that periodically rebalances the gateway's
+    /// funds according to its liquidity policy, if one is set.
+    pub(crate) fn spawn_liquidity_manager_task(&self) {
+        let self_copy = self.clone();
+        self.task_group
+            .spawn_cancellable_silent("liquidity manager", async move {
+                let mut interval = tokio::time::interval(LIQUIDITY_CHECK_INTERVAL);
+                loop {
+                    interval.tick().await;
+
+                    if let Err(err) = self_copy.manage_liquidity().await {
+                        debug!(
+                            target: LOG_GATEWAY,
+                            err = %err.fmt_compact_anyhow(),
+                            "Failed to manage the gateway's liquidity"
+                        );
+                    }
+                }
+            });
+    }
+
+    /// Compares the gateway's balances against the liquidity policy once and
+    /// acts on the balances outside of its ranges, recording every action in
+    /// the gateway's event log.
+    pub async fn manage_liquidity(&self) -> anyhow::Result<()> {
+        let Some(policy) = self
+            .gateway_db
+            .begin_transaction_nc()
+            .await
+            .load_liquidity_policy()
+            .await
+        else {
+            return Ok(());
+        };
+
+        let now = now();
+
+        // The events cover both the cooldown and the peg-ins that may still be in
+        // flight
+        let lookback = Duration::from_secs(policy.cooldown_secs).max(PEGIN_IN_FLIGHT_WINDOW);
+
+        let recent_events = get_events_for_duration(
+            &self.gateway_db,
+            now.checked_sub(lookback).unwrap_or(UNIX_EPOCH),
+            now + Duration::from_secs(1),
+        )
+        .await
+        .iter()
+        .filter_map(liquidity_event)
+        .collect::<Vec<_>>();
+
+        let balances = self.handle_get_balances_msg().await?;
+        let now_secs = duration_since_epoch().as_secs();
+
+        let in_flight_pegins = self.in_flight_pegins(&recent_events, now_secs).await;
+
+        let actions = without_cooling_down(
+            plan_liquidity_actions(&policy, &balances, &in_flight_pegins),
+            &recent_events,
+            now_secs,
+            policy.cooldown_secs,
+        );
+
+        for action in actions {
+            let outcome = if policy.dry_run {
+                LiquidityOutcome::DryRun
+            } else {
+                self.execute_liquidity_action(&policy, &action).await
+            };
+
+            info!(target: LOG_GATEWAY, ?action, ?outcome, "Liquidity manager acted");
+
+            let mut dbtx = self.gateway_db.begin_transaction().await;
+            dbtx.log_event(
+                self.event_log_wakeup_tx.clone(),
+                None,
+                LiquidityActionTaken { action, outcome },
+            )
+            .await;
+            dbtx.commit_tx_result().await?;
+        }
+
+        Ok(())
+    }
+
+    /// Returns the amount of the peg-ins of the liquidity manager per
+    /// federation whose deposit the federation's client has not credited yet.
+    async fn in_flight_pegins(
+        &self,
+        recent_events: &[LiquidityEvent],
+        now_secs: u64,
+    ) -> BTreeMap<FederationId, Amount> {
+        let mut pegins = BTreeMap::<FederationId, Vec<(u64, bitcoin::Txid, u64)>>::new();
+
+        for event in recent_events {
+            if let (
+                LiquidityAction::PegIn {
+                    federation_id,
+                    amount_sats,
+                },
+                LiquidityOutcome::Executed { txid },
+            ) = (&event.action, &event.outcome)
+                && now_secs.saturating_sub(event.timestamp_secs) < PEGIN_IN_FLIGHT_WINDOW.as_secs()
+            {
+                pegins.entry(*federation_id).or_default().push((
+                    event.timestamp_secs,
+                    *txid,
+                    *amount_sats,
+                ));
+            }
+        }
+
+        let mut in_flight = BTreeMap::new();
+
+        for (federation_id, pegins) in pegins {
+            let Ok(client) = self.select_client(federation_id).await else {
+                continue;
+            };
+
+            let since_secs = pegins
+                .iter()
+                .map(|(timestamp_secs, ..)| *timestamp_secs)
+                .min()
+                .unwrap_or(now_secs);
+
+            let credited_txids = get_events_for_duration(
+                client.value().db(),
+                UNIX_EPOCH + Duration::from_secs(since_secs),
+                now() + Duration::from_secs(1),
+            )
+            .await
+            .iter()
+            .filter_map(|entry| {
+                to_event::<DepositConfirmed>(entry)
+                    .map(|deposit| deposit.txid)
+                    .or_else(|| {
+                        to_event::<ReceivePaymentEvent>(entry)
+                            .and_then(|receive| receive.outpoint)
+                            .map(|outpoint| outpoint.txid)
+                    })
+            })
+            .collect::<Vec<_>>();
+
+            let amount_sats = pegins
+                .iter()
+                .filter(|(_, txid, _)| !credited_txids.contains(txid))
+                .map(|(.., amount_sats)| *amount_sats)
+                .sum::<u64>();
+
+            if amount_sats > 0 {
+                in_flight.insert(federation_id, Amount::from_sats(amount_sats));
+            }
+        }
+
+        in_flight
+    }
+
+    async fn execute_liquidity_action(
+        &self,
+        policy: &LiquidityPolicy,
+        action: &LiquidityAction,
+    ) -> LiquidityOutcome {
+        let result = match action {
+            LiquidityAction::PegIn {
+                federation_id,
+                amount_sats,
+            } => {
+                self.handle_pegin_from_onchain_msg(PeginFromOnchainPayload {
+                    federation_id: *federation_id,
+                    amount: BitcoinAmountOrAll::Amount(bitcoin::Amount::from_sat(*amount_sats)),
+                    fee_rate_sats_per_vbyte: policy.pegin_fee_rate_sats_per_vbyte,
+                })
+                .await
+            }
+            LiquidityAction::PegOut {
+                federation_id,
+                amount_sats,
+            } => self
+                .handle_withdraw_to_onchain_msg(WithdrawToOnchainPayload {
+                    federation_id: *federation_id,
+                    amount: BitcoinAmountOrAll::Amount(bitcoin::Amount::from_sat(*amount_sats)),
+                })
+                .await
+                .map(|response| response.txid),
+            LiquidityAction::ChannelOpenProposal { .. }
+            | LiquidityAction::SwapOutProposal { .. } => {
+                return LiquidityOutcome::Proposed;
+            }
+        };
+
+        match result {
+            Ok(txid) => LiquidityOutcome::Executed { txid },
+            Err(err) => LiquidityOutcome::Failed {
+                error: err.to_string(),
+            },
+        }
+    }
+
+    /// Returns the policy of the liquidity manager, or `None` if it is
+    /// disabled.
+    pub async fn handle_get_liquidity_policy_msg(&self) -> AdminResult<Option<LiquidityPolicy>> {
+        Ok(self
+            .gateway_db
+            .begin_transaction_nc()
+            .await
+            .load_liquidity_policy()
+            .await)
+    }
+
+    /// Replaces the policy of the liquidity manager, which takes effect with
+    /// its next check.
+    pub async fn handle_set_liquidity_policy_msg(
+        &self,
+        payload: SetLiquidityPolicyPayload,
+    ) -> AdminResult<()> {
+        if let Some(policy) = &payload.policy {
+            verify_liquidity_policy(policy)
+                .map_err(|err| AdminGatewayError::GatewayConfigurationError(err.to_string()))?;
+        }
+
+        let mut dbtx = self.gateway_db.begin_transaction().await;
+        dbtx.save_liquidity_policy(payload.policy).await;
+        dbtx.commit_tx().await;
+
+        Ok(())
+    }
+
+    /// Returns the most recent actions of the liquidity manager, newest first,
+    /// by reading the gateway's event log backwards in batches.
+    pub async fn handle_liquidity_log_msg(
+        &self,
+        payload: LiquidityLogPayload,
+    ) -> AdminResult<LiquidityLogResponse> {
+        const BATCH_SIZE: u64 = 1_000;
+
+        let mut dbtx = self.gateway_db.begin_transaction_nc().await;
+
+        let mut batch_end = dbtx.get_next_event_log_id().await;
+
+        let mut events = Vec::new();
+
+        while events.len() < payload.limit && batch_end != EventLogId::LOG_START {
+            let batch_start = batch_end.saturating_sub(BATCH_SIZE);
+
+            let batch = dbtx.get_event_log(Some(batch_start), BATCH_SIZE).await;
+
+            events.extend(
+                batch
+                    .iter()
+                    .rev()
+                    .filter(|entry| entry.id() < batch_end)
+                    .filter_map(liquidity_event),
+            );
+
+            batch_end = batch_start;
+        }
+
+        events.truncate(payload.limit);
+
+        Ok(LiquidityLogResponse(events))
+    }
+}
+
+#[cfg(test)]
+mod tests;
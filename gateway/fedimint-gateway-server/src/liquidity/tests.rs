@@ -0,0 +1,245 @@
+use std::collections::BTreeMap;
+
+use fedimint_core::config::FederationId;
+use fedimint_core::{Amount, sats};
+use fedimint_gateway_common::{
+    ChannelOpenReason, LiquidityAction, LiquidityEvent, LiquidityOutcome, LiquidityPolicy,
+    LiquidityRange,
+};
+
+use super::{
+    MAX_COOLDOWN_SECS, plan_liquidity_actions, verify_liquidity_policy, without_cooling_down,
+};
+use crate::test_helpers::{balances, federation};
+
+fn range(min_sats: u64, max_sats: u64) -> LiquidityRange {
+    LiquidityRange {
+        min: Amount::from_sats(min_sats),
+        max: Amount::from_sats(max_sats),
+    }
+}
+
+fn policy(federations: BTreeMap<FederationId, LiquidityRange>) -> LiquidityPolicy {
+    LiquidityPolicy {
+        dry_run: false,
+        federations,
+        outbound: None,
+        min_inbound: None,
+        onchain_reserve_sats: 10_000,
+        pegin_fee_rate_sats_per_vbyte: 5,
+        cooldown_secs: 3600,
+    }
+}
+
+#[test]
+fn ecash_outside_of_range_is_rebalanced_to_the_middle() {
+    let low = federation(1);
+    let high = federation(2);
+    let within = federation(3);
+
+    let policy = policy(BTreeMap::from([
+        (low, range(100_000, 200_000)),
+        (high, range(100_000, 200_000)),
+        (within, range(100_000, 200_000)),
+    ]));
+
+    let actions = plan_liquidity_actions(
+        &policy,
+        &balances(
+            1_000_000,
+            &[
+                (low, sats(50_000)),
+                (high, sats(400_000)),
+                (within, sats(120_000)),
+            ],
+        ),
+        &BTreeMap::new(),
+    );
+
+    assert_eq!(
+        actions,
+        vec![
+            LiquidityAction::PegIn {
+                federation_id: low,
+                amount_sats: 100_000,
+            },
+            LiquidityAction::PegOut {
+                federation_id: high,
+                amount_sats: 250_000,
+            },
+        ]
+    );
+}
+
+#[test]
+fn peg_ins_do_not_spend_the_onchain_reserve() {
+    let first = federation(1);
+    let second = federation(2);
+    let unconnected = federation(3);
+
+    let policy = policy(BTreeMap::from([
+        (first, range(100_000, 200_000)),
+        (second, range(100_000, 200_000)),
+        (unconnected, range(100_000, 200_000)),
+    ]));
+
+    let actions = plan_liquidity_actions(
+        &policy,
+        &balances(110_000, &[(first, Amount::ZERO), (second, Amount::ZERO)]),
+        &BTreeMap::new(),
+    );
+
+    assert_eq!(
+        actions,
+        vec![LiquidityAction::PegIn {
+            federation_id: first,
+            amount_sats: 100_000,
+        }]
+    );
+}
+
+#[test]
+fn in_flight_peg_ins_count_towards_the_ecash_balance() {
+    let pending = federation(1);
+    let partially_pending = federation(2);
+
+    let policy = policy(BTreeMap::from([
+        (pending, range(100_000, 200_000)),
+        (partially_pending, range(100_000, 200_000)),
+    ]));
+
+    let actions = plan_liquidity_actions(
+        &policy,
+        &balances(
+            1_000_000,
+            &[(pending, sats(50_000)), (partially_pending, sats(50_000))],
+        ),
+        &BTreeMap::from([(pending, sats(100_000)), (partially_pending, sats(40_000))]),
+    );
+
+    assert_eq!(
+        actions,
+        vec![LiquidityAction::PegIn {
+            federation_id: partially_pending,
+            amount_sats: 60_000,
+        }]
+    );
+}
+
+#[test]
+fn channel_changes_are_proposed() {
+    let mut policy = policy(BTreeMap::new());
+    policy.outbound = Some(range(1_000_000, 5_000_000));
+    policy.min_inbound = Some(Amount::from_sats(2_000_000));
+
+    let mut low = balances(0, &[]);
+    low.lightning_balance_msats = Amount::from_sats(500_000).msats;
+    low.inbound_lightning_liquidity_msats = Amount::from_sats(1_500_000).msats;
+
+    assert_eq!(
+        plan_liquidity_actions(&policy, &low, &BTreeMap::new()),
+        vec![
+            LiquidityAction::ChannelOpenProposal {
+                reason: ChannelOpenReason::LowOutbound,
+                amount_sats: 2_500_000,
+            },
+            LiquidityAction::ChannelOpenProposal {
+                reason: ChannelOpenReason::LowInbound,
+                amount_sats: 500_000,
+            },
+        ]
+    );
+
+    let mut high = balances(0, &[]);
+    high.lightning_balance_msats = Amount::from_sats(6_000_000).msats;
+    high.inbound_lightning_liquidity_msats = Amount::from_sats(2_000_000).msats;
+
+    assert_eq!(
+        plan_liquidity_actions(&policy, &high, &BTreeMap::new()),
+        vec![LiquidityAction::SwapOutProposal {
+            amount: Amount::from_sats(3_000_000),
+        }]
+    );
+}
+
+#[test]
+fn recent_actions_hold_back_actions_for_the_same_target() {
+    let cooling_down = federation(1);
+    let cooled_down = federation(2);
+
+    let event = |timestamp_secs, action| LiquidityEvent {
+        timestamp_secs,
+        action,
+        outcome: LiquidityOutcome::DryRun,
+    };
+
+    let recent_events = [
+        event(
+            9_000,
+            LiquidityAction::PegIn {
+                federation_id: cooling_down,
+                amount_sats: 1,
+            },
+        ),
+        event(
+            5_000,
+            LiquidityAction::PegOut {
+                federation_id: cooled_down,
+                amount_sats: 1,
+            },
+        ),
+        event(
+            9_500,
+            LiquidityAction::SwapOutProposal {
+                amount: Amount::from_sats(1),
+            },
+        ),
+    ];
+
+    let actions = vec![
+        LiquidityAction::PegOut {
+            federation_id: cooling_down,
+            amount_sats: 1,
+        },
+        LiquidityAction::PegIn {
+            federation_id: cooled_down,
+            amount_sats: 1,
+        },
+        LiquidityAction::ChannelOpenProposal {
+            reason: ChannelOpenReason::LowInbound,
+            amount_sats: 1,
+        },
+    ];
+
+    assert_eq!(
+        without_cooling_down(actions, &recent_events, 10_000, 3600),
+        vec![LiquidityAction::PegIn {
+            federation_id: cooled_down,
+            amount_sats: 1,
+        }]
+    );
+}
+
+#[test]
+fn policies_with_empty_ranges_are_rejected() {
+    assert!(
+        verify_liquidity_policy(&policy(BTreeMap::from([(federation(1), range(1, 1))]))).is_ok()
+    );
+    assert!(
+        verify_liquidity_policy(&policy(BTreeMap::from([(federation(1), range(2, 1))]))).is_err()
+    );
+
+    let mut policy = policy(BTreeMap::new());
+    policy.outbound = Some(range(2, 1));
+    assert!(verify_liquidity_policy(&policy).is_err());
+}
+
+#[test]
+fn policies_with_excessive_cooldowns_are_rejected() {
+    let mut policy = policy(BTreeMap::new());
+    policy.cooldown_secs = MAX_COOLDOWN_SECS;
+    assert!(verify_liquidity_policy(&policy).is_ok());
+
+    policy.cooldown_secs = u64::MAX;
+    assert!(verify_liquidity_policy(&policy).is_err());
+}
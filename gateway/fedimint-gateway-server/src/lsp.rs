@@ -0,0 +1,984 @@
+use std::collections::BTreeSet;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, UNIX_EPOCH};
+
+use bitcoin::Txid;
+use bitcoin::hashes::{Hash as _, sha256};
+use bitcoin::secp256k1::PublicKey;
+use fedimint_client::ClientHandleArc;
+use fedimint_client_module::oplog::OperationLogEntry;
+use fedimint_core::base32::{self, FEDIMINT_PREFIX};
+use fedimint_core::config::FederationId;
+use fedimint_core::core::OperationId;
+use fedimint_core::time::duration_since_epoch;
+use fedimint_core::{Amount, runtime};
+use fedimint_eventlog::DBTransactionEventLogExt as _;
+use fedimint_gateway_common::{
+    ChannelInfo, ChannelOrderOutcome, ChannelOrderPayload, ChannelOrderResponse,
+    ChannelOrderStatusPayload, LightningMode, LspChannelOrdered, LspConfig, LspInfo,
+    OpenChannelRequest,
+};
+use fedimint_gateway_server_db::{
+    ChannelOrderQuote, GatewayDbtxNcExt as _, LspChannelOrder, LspChannelOrderState,
+};
+use fedimint_logging::LOG_GATEWAY;
+use fedimint_mint_client::{
+    MintClientModule, MintOperationMeta, MintOperationMetaVariant, OOBNotes,
+    ReissueExternalNotesState,
+};
+use fedimint_mintv2_client::{
+    ECash, FinalReceiveOperationState, MintClientModule as MintV2ClientModule,
+};
+use futures::StreamExt as _;
+use tracing::{info, warn};
+
+use crate::error::PublicGatewayError;
+use crate::{Gateway, Result, ecash_federation_id_prefix};
+
+/// Time the lightning node is given to open an ordered channel. Once it runs
+/// out the order stays pending and is resolved from the node's channels.
+const LSP_OPEN_CHANNEL_TIMEOUT: Duration = Duration::from_mins(1);
+
+/// Interval in which channel orders that were interrupted are resumed.
+const LSP_CHANNEL_ORDER_RETRY_INTERVAL: Duration = Duration::from_mins(1);
+
+/// Number of operations read at once when looking for the spend of a refund.
+const REFUND_OPERATION_PAGE_SIZE: usize = 100;
+
+/// Prices an order for `inbound_sats` of capacity provided by the LSP, paid
+/// for with `ecash`. Whatever the ecash holds beyond the fee is pushed to the
+/// other side of the channel.
+pub(crate) fn channel_order_quote(
+    lsp: &LspConfig,
+    ecash: Amount,
+    inbound_sats: u64,
+) -> std::result::Result<ChannelOrderQuote, String> {
+    let fee_sats = lsp.opening_fee_sats(inbound_sats);
+
+    let push_amount_sats = (ecash.msats / 1000)
+        .checked_sub(fee_sats)
+        .ok_or_else(|| format!("The ecash does not cover the fee of {fee_sats} sats"))?;
+
+    let channel_size_sats = inbound_sats
+        .checked_add(push_amount_sats)
+        .ok_or_else(|| "Invalid channel size".to_string())?;
+
+    if !(lsp.min_channel_sats..=lsp.max_channel_sats).contains(&channel_size_sats) {
+        return Err(format!(
+            "The channel size of {channel_size_sats} sats is outside of {} to {} sats",
+            lsp.min_channel_sats, lsp.max_channel_sats
+        ));
+    }
+
+    Ok(ChannelOrderQuote {
+        channel_size_sats,
+        push_amount_sats,
+        fee_sats,
+    })
+}
+
+/// Returns the response of the order `order_id` in `state`.
+pub(crate) fn channel_order_response(
+    order_id: sha256::Hash,
+    state: &LspChannelOrderState,
+) -> ChannelOrderResponse {
+    let (quote, outcome) = match state {
+        LspChannelOrderState::Received { .. } => (None, ChannelOrderOutcome::Pending),
+        LspChannelOrderState::Opening { quote, .. } => (Some(*quote), ChannelOrderOutcome::Pending),
+        LspChannelOrderState::Opened {
+            quote,
+            funding_txid,
+            ..
+        } => (
+            Some(*quote),
+            ChannelOrderOutcome::Opened {
+                funding_txid: *funding_txid,
+            },
+        ),
+        LspChannelOrderState::Refunding { quote, .. }
+        | LspChannelOrderState::RefundSpending { quote, .. } => {
+            (*quote, ChannelOrderOutcome::Pending)
+        }
+        LspChannelOrderState::Refunded {
+            quote,
+            notes,
+            failure_reason,
+        } => (
+            *quote,
+            ChannelOrderOutcome::Refunded {
+                notes: notes.clone(),
+                failure_reason: failure_reason.clone(),
+            },
+        ),
+    };
+
+    // The fee is only charged for a channel that was opened
+    let fee_sats = match outcome {
+        ChannelOrderOutcome::Opened { .. } => quote.map_or(0, |quote| quote.fee_sats),
+        _ => 0,
+    };
+
+    ChannelOrderResponse {
+        order_id,
+        channel_size_sats: quote.map_or(0, |quote| quote.channel_size_sats),
+        push_amount_sats: quote.map_or(0, |quote| quote.push_amount_sats),
+        fee_sats,
+        outcome,
+    }
+}
+
+/// The ordered channel among the channels of the lightning node.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum OrderedChannel {
+    /// The channel is funded in the given transaction.
+    Funded(Txid),
+    /// The channel is being negotiated with the node.
+    Unfunded,
+    /// There is no channel of the ordered size to the node that existed
+    /// before the order.
+    Missing,
+}
+
+/// Looks for the channel of an order of `quote` to `pubkey` among `channels`,
+/// ignoring the channels funded in `known_funding_txids` that existed before.
+pub(crate) fn find_ordered_channel(
+    channels: &[ChannelInfo],
+    pubkey: PublicKey,
+    quote: &ChannelOrderQuote,
+    known_funding_txids: &[Txid],
+) -> OrderedChannel {
+    channels
+        .iter()
+        .filter(|channel| {
+            channel.remote_pubkey == pubkey && channel.channel_size_sats == quote.channel_size_sats
+        })
+        .map(|channel| match channel.funding_outpoint {
+            Some(outpoint) if known_funding_txids.contains(&outpoint.txid) => {
+                OrderedChannel::Missing
+            }
+            Some(outpoint) => OrderedChannel::Funded(outpoint.txid),
+            None => OrderedChannel::Unfunded,
+        })
+        .min_by_key(|channel| match channel {
+            OrderedChannel::Funded(_) => 0,
+            OrderedChannel::Unfunded => 1,
+            OrderedChannel::Missing => 2,
+        })
+        .unwrap_or(OrderedChannel::Missing)
+}
+
+/// Returns the metadata of the operation that spends the refund of the order
+/// `order_id`, by which an interrupted refund is found again.
+pub(crate) fn refund_meta(order_id: sha256::Hash) -> serde_json::Value {
+    serde_json::json!({ "lsp_channel_order_refund": order_id })
+}
+
+/// Returns the ecash spent by the operation `entry` if its metadata is `meta`.
+pub(crate) fn refund_notes(entry: &OperationLogEntry, meta: &serde_json::Value) -> Option<String> {
+    if let Ok(MintOperationMeta {
+        variant: MintOperationMetaVariant::SpendOOB { oob_notes, .. },
+        extra_meta,
+        ..
+    }) = entry.try_meta::<MintOperationMeta>()
+        && extra_meta == *meta
+    {
+        return Some(oob_notes.to_string());
+    }
+
+    match entry.try_meta::<fedimint_mintv2_client::MintOperationMeta>() {
+        Ok(fedimint_mintv2_client::MintOperationMeta::Send { ecash, custom_meta })
+            if custom_meta == *meta =>
+        {
+            Some(ecash)
+        }
+        _ => None,
+    }
+}
+
+/// Marks an order as being advanced until it is dropped, such that no other
+/// task advances the order at the same time.
+pub(crate) struct ChannelOrderClaim {
+    orders_in_progress: Arc<Mutex<BTreeSet<sha256::Hash>>>,
+    order_id: sha256::Hash,
+}
+
+impl ChannelOrderClaim {
+    /// Claims the order `order_id`, unless another task already claimed it.
+    pub(crate) fn new(
+        orders_in_progress: &Arc<Mutex<BTreeSet<sha256::Hash>>>,
+        order_id: sha256::Hash,
+    ) -> Option<Self> {
+        orders_in_progress
+            .lock()
+            .expect("No code holding the lock can panic")
+            .insert(order_id)
+            .then(|| Self {
+                orders_in_progress: orders_in_progress.clone(),
+                order_id,
+            })
+    }
+}
+
+impl Drop for ChannelOrderClaim {
+    fn drop(&mut self) {
+        self.orders_in_progress
+            .lock()
+            .expect("No code holding the lock can panic")
+            .remove(&self.order_id);
+    }
+}
+
+/// Starts the reissue `operation_id` with `reissue` unless an earlier
+/// attempt started it already. If the reissue fails to start without
+/// creating the operation, the ecash was not reissued.
+async fn start_channel_order_reissue<T, E: std::fmt::Display>(
+    client: &ClientHandleArc,
+    operation_id: OperationId,
+    reissue: impl Future<Output = std::result::Result<T, E>>,
+) -> std::result::Result<(), String> {
+    if client.operation_exists(operation_id).await {
+        return Ok(());
+    }
+
+    match reissue.await {
+        Ok(_) => Ok(()),
+        Err(_) if client.operation_exists(operation_id).await => Ok(()),
+        Err(err) => Err(err.to_string()),
+    }
+}
+
+/// Looks for the ecash spent with `meta` among the operations of `client`
+/// since `since_secs`.
+async fn find_refund(
+    client: &ClientHandleArc,
+    meta: &serde_json::Value,
+    since_secs: u64,
+) -> Option<String> {
+    let since = UNIX_EPOCH + Duration::from_secs(since_secs);
+    let mut last_seen = None;
+
+    loop {
+        let operations = client
+            .operation_log()
+            .paginate_operations_rev(REFUND_OPERATION_PAGE_SIZE, last_seen)
+            .await;
+
+        for (key, entry) in &operations {
+            if key.creation_time < since {
+                return None;
+            }
+
+            if let Some(notes) = refund_notes(entry, meta) {
+                return Some(notes);
+            }
+        }
+
+        if operations.len() < REFUND_OPERATION_PAGE_SIZE {
+            return None;
+        }
+
+        last_seen = operations.last().map(|(key, _)| *key);
+    }
+}
+
+fn lsp_error(failure_reason: impl Into<String>) -> PublicGatewayError {
+    PublicGatewayError::LspError {
+        failure_reason: failure_reason.into(),
+    }
+}
+
+impl Gateway {
+    /// Returns the configuration of the LSP if the gateway runs one, which
+    /// requires the LDK backend.
+    fn lsp_config(&self) -> Option<&LspConfig> {
+        match &self.lightning_mode {
+            LightningMode::Ldk { lsp, .. } if lsp.enabled => Some(lsp),
+            _ => None,
+        }
+    }
+
+    /// Returns the services and prices of the gateway's LSP.
+    pub async fn handle_lsp_info_msg(&self) -> Result<LspInfo> {
+        let lsp = self
+            .lsp_config()
+            .ok_or_else(|| lsp_error("The gateway does not run an LSP"))?;
+        let context = self.get_lightning_context().await?;
+        let federations = self
+            .federation_manager
+            .read()
+            .await
+            .get_all_federation_configs()
+            .await
+            .into_keys()
+            .collect();
+
+        Ok(LspInfo {
+            node_pubkey: context.lightning_public_key,
+            opening_fee_ppm: lsp.opening_fee_ppm,
+            min_opening_fee_sats: lsp.min_opening_fee_sats,
+            min_channel_sats: lsp.min_channel_sats,
+            max_channel_sats: lsp.max_channel_sats,
+            min_channel_lifetime_blocks: lsp.min_channel_lifetime_blocks,
+            federations,
+        })
+    }
+
+    /// Opens an unannounced channel to an external node that is paid for with
+    /// ecash. The ecash is checked and the channel priced before the order is
+    /// saved, such that invalid orders leave no trace. The order is saved
+    /// before the ecash is reissued, once it is reissued the order can no
+    /// longer be rejected with an error: if the channel cannot be opened the
+    /// ecash is returned instead. An order that is still pending can be
+    /// queried by the id in the response, and ordering with the same ecash
+    /// again returns the existing order.
+    pub async fn handle_order_channel_msg(
+        &self,
+        payload: ChannelOrderPayload,
+    ) -> Result<ChannelOrderResponse> {
+        let lsp = self
+            .lsp_config()
+            .ok_or_else(|| lsp_error("The gateway does not run an LSP"))?
+            .clone();
+
+        let order_id = sha256::Hash::hash(payload.notes.as_bytes());
+
+        if let Some(order) = self.load_channel_order(order_id).await {
+            return Ok(channel_order_response(order_id, &order.state));
+        }
+
+        if ecash_federation_id_prefix(&payload.notes) != Some(payload.federation_id.to_prefix()) {
+            return Err(lsp_error(
+                "The ecash was not issued by the given federation",
+            ));
+        }
+
+        if payload.inbound_sats > lsp.max_channel_sats {
+            return Err(lsp_error(format!(
+                "The LSP provides at most {} sats of capacity",
+                lsp.max_channel_sats
+            )));
+        }
+
+        let ecash = self
+            .validate_channel_order_ecash(payload.federation_id, &payload.notes)
+            .await
+            .map_err(lsp_error)?;
+
+        let quote = channel_order_quote(&lsp, ecash, payload.inbound_sats).map_err(lsp_error)?;
+
+        let context = self.get_lightning_context().await?;
+        let onchain_balance_sats = context.lnrpc.get_balances().await?.onchain_balance_sats;
+
+        if onchain_balance_sats < quote.channel_size_sats {
+            return Err(lsp_error(
+                "The LSP cannot fund a channel of this size right now",
+            ));
+        }
+
+        // A concurrent request with the same ecash is processing the order
+        let Some(_claim) = ChannelOrderClaim::new(&self.lsp_orders_in_progress, order_id) else {
+            return Ok(channel_order_response(
+                order_id,
+                &LspChannelOrderState::Received {
+                    notes: payload.notes,
+                },
+            ));
+        };
+
+        // The order might have been saved since it was looked up
+        if let Some(order) = self.load_channel_order(order_id).await {
+            return Ok(channel_order_response(order_id, &order.state));
+        }
+
+        let mut order = LspChannelOrder {
+            federation_id: payload.federation_id,
+            pubkey: payload.pubkey,
+            host: payload.host,
+            inbound_sats: payload.inbound_sats,
+            state: LspChannelOrderState::Received {
+                notes: payload.notes,
+            },
+        };
+
+        let mut dbtx = self.gateway_db.begin_transaction().await;
+        dbtx.save_lsp_channel_order(order_id, &order).await;
+        dbtx.commit_tx().await;
+
+        self.advance_channel_order(&lsp, order_id, &mut order, false)
+            .await
+            .map_err(lsp_error)?;
+
+        Ok(channel_order_response(order_id, &order.state))
+    }
+
+    async fn load_channel_order(&self, order_id: sha256::Hash) -> Option<LspChannelOrder> {
+        self.gateway_db
+            .begin_transaction_nc()
+            .await
+            .load_lsp_channel_order(order_id)
+            .await
+    }
+
+    /// Returns the state of a channel order, including the ecash of a refund.
+    pub async fn handle_channel_order_status_msg(
+        &self,
+        payload: ChannelOrderStatusPayload,
+    ) -> Result<ChannelOrderResponse> {
+        let order = self
+            .load_channel_order(payload.order_id)
+            .await
+            .ok_or_else(|| lsp_error("There is no channel order with this id"))?;
+
+        Ok(channel_order_response(payload.order_id, &order.state))
+    }
+
+    /// Spawns a background task that resumes the channel orders that were
+    /// interrupted by a restart, a lightning node that did not open the
+    /// channel in time or a refund that failed.
+    pub(crate) fn spawn_channel_order_task(&self) {
+        let Some(lsp) = self.lsp_config().cloned() else {
+            return;
+        };
+
+        let self_copy = self.clone();
+        self.task_group
+            .spawn_cancellable_silent("resume channel orders", async move {
+                let mut interval = tokio::time::interval(LSP_CHANNEL_ORDER_RETRY_INTERVAL);
+                loop {
+                    interval.tick().await;
+
+                    let order_ids = self_copy
+                        .gateway_db
+                        .begin_transaction_nc()
+                        .await
+                        .load_pending_lsp_channel_orders()
+                        .await
+                        .into_iter()
+                        .map(|(order_id, _)| order_id)
+                        .collect::<Vec<_>>();
+
+                    for order_id in order_ids {
+                        // The order is being processed by a request
+                        let Some(_claim) =
+                            ChannelOrderClaim::new(&self_copy.lsp_orders_in_progress, order_id)
+                        else {
+                            continue;
+                        };
+
+                        // The request might have completed the order in the meantime
+                        let Some(mut order) = self_copy
+                            .load_channel_order(order_id)
+                            .await
+                            .filter(|order| order.state.is_pending())
+                        else {
+                            continue;
+                        };
+
+                        info!(target: LOG_GATEWAY, %order_id, "Resuming channel order");
+
+                        // An order whose ecash cannot be reissued is removed and
+                        // logged while it is advanced
+                        let _ = self_copy
+                            .advance_channel_order(&lsp, order_id, &mut order, true)
+                            .await;
+                    }
+                }
+            });
+    }
+
+    /// Moves an order forward until it is opened or refunded, or a step has to
+    /// be retried later. The first step of a `resumed` order checks whether
+    /// the channel was opened instead of opening it again. If the ecash of the
+    /// order cannot be reissued the order is removed and the reason returned.
+    /// Requires the caller to hold the [`ChannelOrderClaim`] of the order.
+    async fn advance_channel_order(
+        &self,
+        lsp: &LspConfig,
+        order_id: sha256::Hash,
+        order: &mut LspChannelOrder,
+        mut resumed: bool,
+    ) -> std::result::Result<(), String> {
+        loop {
+            let state = match order.state.clone() {
+                LspChannelOrderState::Received { notes } => {
+                    match self.receive_channel_order_ecash(lsp, order, &notes).await {
+                        Ok(state) => state,
+                        Err(failure_reason) => {
+                            warn!(
+                                target: LOG_GATEWAY,
+                                %order_id,
+                                %failure_reason,
+                                "Removing channel order whose ecash cannot be reissued"
+                            );
+
+                            let mut dbtx = self.gateway_db.begin_transaction().await;
+                            dbtx.remove_lsp_channel_order(order_id).await;
+                            dbtx.commit_tx().await;
+
+                            return Err(failure_reason);
+                        }
+                    }
+                }
+                LspChannelOrderState::Opening {
+                    ecash,
+                    quote,
+                    known_funding_txids,
+                } => {
+                    if resumed {
+                        self.resolve_ordered_channel(order, ecash, quote, &known_funding_txids)
+                            .await
+                    } else {
+                        self.open_ordered_channel(order, ecash, quote).await
+                    }
+                }
+                // The intent to spend the refund is saved before spending, such
+                // that a spend that was interrupted is picked up again
+                LspChannelOrderState::Refunding {
+                    ecash,
+                    quote,
+                    failure_reason,
+                } => Some(LspChannelOrderState::RefundSpending {
+                    ecash,
+                    quote,
+                    failure_reason,
+                    since_secs: duration_since_epoch().as_secs(),
+                }),
+                LspChannelOrderState::RefundSpending {
+                    ecash,
+                    quote,
+                    failure_reason,
+                    since_secs,
+                } => {
+                    self.refund_channel_order(
+                        order_id,
+                        order,
+                        ecash,
+                        quote,
+                        failure_reason,
+                        since_secs,
+                    )
+                    .await
+                }
+                LspChannelOrderState::Opened { .. } | LspChannelOrderState::Refunded { .. } => {
+                    return Ok(());
+                }
+            };
+
+            let Some(state) = state else {
+                return Ok(());
+            };
+
+            self.update_channel_order(order_id, order, state).await;
+
+            resumed = false;
+        }
+    }
+
+    /// Saves the next state of an order, recording the channel in the event
+    /// log once it was opened.
+    async fn update_channel_order(
+        &self,
+        order_id: sha256::Hash,
+        order: &mut LspChannelOrder,
+        state: LspChannelOrderState,
+    ) {
+        order.state = state;
+
+        let mut dbtx = self.gateway_db.begin_transaction().await;
+        dbtx.save_lsp_channel_order(order_id, order).await;
+
+        if let LspChannelOrderState::Opened {
+            ecash,
+            quote,
+            funding_txid,
+        } = &order.state
+        {
+            dbtx.log_event(
+                self.event_log_wakeup_tx.clone(),
+                None,
+                LspChannelOrdered {
+                    federation_id: order.federation_id,
+                    counterparty: order.pubkey,
+                    funding_txid: *funding_txid,
+                    channel_size_sats: quote.channel_size_sats,
+                    push_amount_sats: quote.push_amount_sats,
+                    ecash: *ecash,
+                },
+            )
+            .await;
+
+            info!(target: LOG_GATEWAY, %order_id, %funding_txid, "Opened ordered channel");
+        }
+
+        dbtx.commit_tx().await;
+    }
+
+    /// Checks the ecash of an order without reissuing it, returning its amount.
+    async fn validate_channel_order_ecash(
+        &self,
+        federation_id: FederationId,
+        notes: &str,
+    ) -> std::result::Result<Amount, String> {
+        let client = self
+            .select_client(federation_id)
+            .await
+            .map_err(|err| err.to_string())?
+            .into_value();
+
+        if let Ok(mint) = client.get_first_module::<MintClientModule>() {
+            let notes = OOBNotes::from_str(notes).map_err(|err| format!("Invalid ecash: {err}"))?;
+
+            mint.validate_notes(&notes)
+                .map_err(|err| format!("Invalid ecash: {err}"))
+        } else if client.get_first_module::<MintV2ClientModule>().is_ok() {
+            let ecash: ECash = base32::decode_prefixed(FEDIMINT_PREFIX, notes)
+                .map_err(|err| format!("Invalid ecash: {err}"))?;
+
+            if ecash.mint() != Some(federation_id) {
+                return Err("The ecash was not issued by the given federation".to_string());
+            }
+
+            Ok(ecash.amount())
+        } else {
+            Err("No mint module found".to_string())
+        }
+    }
+
+    /// Reissues the ecash of an order and prices the channel. Returns `None`
+    /// if the reissue has to be retried later and an error if the ecash
+    /// cannot be reissued, e.g. since it was spent already.
+    async fn receive_channel_order_ecash(
+        &self,
+        lsp: &LspConfig,
+        order: &LspChannelOrder,
+        notes: &str,
+    ) -> std::result::Result<Option<LspChannelOrderState>, String> {
+        let Some(ecash) = self
+            .reissue_channel_order_ecash(order.federation_id, notes)
+            .await?
+        else {
+            return Ok(None);
+        };
+
+        let quote = match channel_order_quote(lsp, ecash, order.inbound_sats) {
+            Ok(quote) => quote,
+            Err(failure_reason) => {
+                return Ok(Some(LspChannelOrderState::Refunding {
+                    ecash,
+                    quote: None,
+                    failure_reason,
+                }));
+            }
+        };
+
+        let known_funding_txids = match self.channels_to(order.pubkey).await {
+            Ok(channels) => channels
+                .iter()
+                .filter_map(|channel| channel.funding_outpoint)
+                .map(|outpoint| outpoint.txid)
+                .collect(),
+            Err(failure_reason) => {
+                return Ok(Some(LspChannelOrderState::Refunding {
+                    ecash,
+                    quote: Some(quote),
+                    failure_reason,
+                }));
+            }
+        };
+
+        Ok(Some(LspChannelOrderState::Opening {
+            ecash,
+            quote,
+            known_funding_txids,
+        }))
+    }
+
+    /// Reissues the ecash of an order, or awaits the reissue an earlier
+    /// attempt started. The ids of the reissue operations are derived from the
+    /// ecash, so a started reissue is found again after a restart. Returns
+    /// `None` if the outcome is unknown yet, and an error if the ecash was not
+    /// and cannot be reissued.
+    async fn reissue_channel_order_ecash(
+        &self,
+        federation_id: FederationId,
+        notes: &str,
+    ) -> std::result::Result<Option<Amount>, String> {
+        let client = match self.select_client(federation_id).await {
+            Ok(client) => client.into_value(),
+            Err(err) => {
+                warn!(
+                    target: LOG_GATEWAY,
+                    %federation_id,
+                    %err,
+                    "Failed to reissue the ecash of a channel order, retrying later"
+                );
+                return Ok(None);
+            }
+        };
+
+        if let Ok(mint) = client.get_first_module::<MintClientModule>() {
+            let notes = OOBNotes::from_str(notes).map_err(|err| format!("Invalid ecash: {err}"))?;
+            let amount = notes.total_amount();
+            let operation_id = MintClientModule::reissue_external_notes_operation_id(&notes);
+
+            start_channel_order_reissue(
+                &client,
+                operation_id,
+                mint.reissue_external_notes(notes, ()),
+            )
+            .await?;
+
+            let Ok(updates) = mint.subscribe_reissue_external_notes(operation_id).await else {
+                return Ok(None);
+            };
+
+            let mut updates = updates.into_stream();
+
+            while let Some(update) = updates.next().await {
+                match update {
+                    ReissueExternalNotesState::Done => return Ok(Some(amount)),
+                    ReissueExternalNotesState::Failed(failure_reason) => {
+                        return Err(failure_reason);
+                    }
+                    ReissueExternalNotesState::Created | ReissueExternalNotesState::Issuing => {}
+                }
+            }
+
+            Ok(None)
+        } else if let Ok(mint) = client.get_first_module::<MintV2ClientModule>() {
+            let ecash: ECash = base32::decode_prefixed(FEDIMINT_PREFIX, notes)
+                .map_err(|err| format!("Invalid ecash: {err}"))?;
+            let amount = ecash.amount();
+            let operation_id = OperationId::from_encodable(&ecash);
+
+            start_channel_order_reissue(
+                &client,
+                operation_id,
+                mint.receive(ecash, serde_json::Value::Null),
+            )
+            .await?;
+
+            match mint.await_final_receive_operation_state(operation_id).await {
+                Ok(FinalReceiveOperationState::Success) => Ok(Some(amount)),
+                Ok(FinalReceiveOperationState::Rejected) => {
+                    Err("The ecash was rejected by the federation".to_string())
+                }
+                Err(_) => Ok(None),
+            }
+        } else {
+            Err("No mint module found".to_string())
+        }
+    }
+
+    /// Returns the channels of the lightning node to `pubkey`.
+    async fn channels_to(
+        &self,
+        pubkey: PublicKey,
+    ) -> std::result::Result<Vec<ChannelInfo>, String> {
+        let context = self
+            .get_lightning_context()
+            .await
+            .map_err(|err| err.to_string())?;
+
+        Ok(context
+            .lnrpc
+            .list_channels()
+            .await
+            .map_err(|err| err.to_string())?
+            .channels
+            .into_iter()
+            .filter(|channel| channel.remote_pubkey == pubkey)
+            .collect())
+    }
+
+    /// Opens the ordered channel. If the lightning node does not respond in
+    /// time the order stays pending, since the channel might be opened after
+    /// all.
+    async fn open_ordered_channel(
+        &self,
+        order: &LspChannelOrder,
+        ecash: Amount,
+        quote: ChannelOrderQuote,
+    ) -> Option<LspChannelOrderState> {
+        info!(
+            target: LOG_GATEWAY,
+            pubkey = %order.pubkey,
+            channel_size_sats = quote.channel_size_sats,
+            push_amount_sats = quote.push_amount_sats,
+            "Opening ordered channel..."
+        );
+
+        let context = match self.get_lightning_context().await {
+            Ok(context) => context,
+            Err(err) => {
+                return Some(LspChannelOrderState::Refunding {
+                    ecash,
+                    quote: Some(quote),
+                    failure_reason: err.to_string(),
+                });
+            }
+        };
+
+        let open_channel = context.lnrpc.open_channel(OpenChannelRequest {
+            pubkey: order.pubkey,
+            host: order.host.clone(),
+            channel_size_sats: quote.channel_size_sats,
+            push_amount_sats: quote.push_amount_sats,
+            fee_rate_sats_per_vbyte: None,
+            base_fee_msat: None,
+            parts_per_million: None,
+            unannounced: true,
+        });
+
+        match runtime::timeout(LSP_OPEN_CHANNEL_TIMEOUT, open_channel).await {
+            Ok(Ok(response)) => match Txid::from_str(&response.funding_txid) {
+                Ok(funding_txid) => Some(LspChannelOrderState::Opened {
+                    ecash,
+                    quote,
+                    funding_txid,
+                }),
+                Err(err) => {
+                    warn!(
+                        target: LOG_GATEWAY,
+                        funding_txid = %response.funding_txid,
+                        %err,
+                        "Lightning node returned an invalid funding txid for an ordered channel"
+                    );
+                    None
+                }
+            },
+            Ok(Err(err)) => Some(LspChannelOrderState::Refunding {
+                ecash,
+                quote: Some(quote),
+                failure_reason: err.to_string(),
+            }),
+            Err(_) => {
+                warn!(
+                    target: LOG_GATEWAY,
+                    pubkey = %order.pubkey,
+                    "Lightning node did not open the ordered channel in time"
+                );
+                None
+            }
+        }
+    }
+
+    /// Checks whether the channel of an interrupted order was opened, and
+    /// refunds the order if there is no such channel.
+    async fn resolve_ordered_channel(
+        &self,
+        order: &LspChannelOrder,
+        ecash: Amount,
+        quote: ChannelOrderQuote,
+        known_funding_txids: &[Txid],
+    ) -> Option<LspChannelOrderState> {
+        let channels = match self.channels_to(order.pubkey).await {
+            Ok(channels) => channels,
+            Err(failure_reason) => {
+                warn!(
+                    target: LOG_GATEWAY,
+                    %failure_reason,
+                    "Failed to list the channels of an interrupted channel order"
+                );
+                return None;
+            }
+        };
+
+        match find_ordered_channel(&channels, order.pubkey, &quote, known_funding_txids) {
+            OrderedChannel::Funded(funding_txid) => Some(LspChannelOrderState::Opened {
+                ecash,
+                quote,
+                funding_txid,
+            }),
+            OrderedChannel::Unfunded => None,
+            OrderedChannel::Missing => Some(LspChannelOrderState::Refunding {
+                ecash,
+                quote: Some(quote),
+                failure_reason: "The channel was not opened".to_string(),
+            }),
+        }
+    }
+
+    /// Returns the ecash of an order that could not be fulfilled. A spend for
+    /// the order since `since_secs` is picked up instead of spending again. If
+    /// spending the ecash fails the order stays pending and the refund is
+    /// retried.
+    async fn refund_channel_order(
+        &self,
+        order_id: sha256::Hash,
+        order: &LspChannelOrder,
+        ecash: Amount,
+        quote: Option<ChannelOrderQuote>,
+        failure_reason: String,
+        since_secs: u64,
+    ) -> Option<LspChannelOrderState> {
+        let client = match self.select_client(order.federation_id).await {
+            Ok(client) => client.into_value(),
+            Err(err) => {
+                warn!(
+                    target: LOG_GATEWAY,
+                    %order_id,
+                    %err,
+                    "Failed to refund channel order, retrying later"
+                );
+                return None;
+            }
+        };
+
+        let meta = refund_meta(order_id);
+
+        if let Some(notes) = find_refund(&client, &meta, since_secs).await {
+            info!(target: LOG_GATEWAY, %order_id, "Picked up interrupted refund of channel order");
+
+            return Some(LspChannelOrderState::Refunded {
+                quote,
+                notes,
+                failure_reason,
+            });
+        }
+
+        warn!(
+            target: LOG_GATEWAY,
+            federation_id = %order.federation_id,
+            %ecash,
+            %failure_reason,
+            "Refunding channel order"
+        );
+
+        let notes = if let Ok(mint) = client.get_first_module::<MintClientModule>() {
+            mint.send_oob_notes(ecash, meta)
+                .await
+                .map(|notes| notes.to_string())
+                .map_err(|err| err.to_string())
+        } else if let Ok(mint) = client.get_first_module::<MintV2ClientModule>() {
+            mint.send(ecash, meta, true)
+                .await
+                .map(|(_, ecash)| base32::encode_prefixed(FEDIMINT_PREFIX, &ecash))
+                .map_err(|err| err.to_string())
+        } else {
+            Err("No mint module found".to_string())
+        };
+
+        match notes {
+            Ok(notes) => Some(LspChannelOrderState::Refunded {
+                quote,
+                notes,
+                failure_reason,
+            }),
+            Err(err) => {
+                warn!(
+                    target: LOG_GATEWAY,
+                    federation_id = %order.federation_id,
+                    %ecash,
+                    %err,
+                    "Failed to refund channel order, retrying later"
+                );
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests;
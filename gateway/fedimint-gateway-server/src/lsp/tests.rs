@@ -0,0 +1,263 @@
+use std::collections::BTreeSet;
+use std::sync::{Arc, Mutex};
+
+use bitcoin::hashes::{Hash as _, sha256};
+use bitcoin::secp256k1::{PublicKey, Secp256k1, SecretKey};
+use bitcoin::{OutPoint, Txid};
+use fedimint_client_module::oplog::{JsonStringed, OperationLogEntry};
+use fedimint_core::Amount;
+use fedimint_gateway_common::{ChannelInfo, ChannelOrderOutcome, LspConfig};
+use fedimint_gateway_server_db::LspChannelOrderState;
+
+use super::{
+    ChannelOrderClaim, ChannelOrderQuote, OrderedChannel, channel_order_quote,
+    channel_order_response, find_ordered_channel, refund_meta, refund_notes,
+};
+
+const QUOTE: ChannelOrderQuote = ChannelOrderQuote {
+    channel_size_sats: 148_000,
+    push_amount_sats: 48_000,
+    fee_sats: 2_000,
+};
+
+fn pubkey(byte: u8) -> PublicKey {
+    SecretKey::from_slice(&[byte; 32])
+        .expect("Valid secret key")
+        .public_key(&Secp256k1::new())
+}
+
+fn txid(byte: u8) -> Txid {
+    Txid::from_byte_array([byte; 32])
+}
+
+fn channel(remote_pubkey: PublicKey, channel_size_sats: u64, funding: Option<Txid>) -> ChannelInfo {
+    ChannelInfo {
+        remote_pubkey,
+        channel_size_sats,
+        outbound_liquidity_sats: 0,
+        inbound_liquidity_sats: 0,
+        is_active: false,
+        funding_outpoint: funding.map(|txid| OutPoint { txid, vout: 0 }),
+        remote_node_alias: None,
+        remote_address: None,
+        base_fee_msat: None,
+        parts_per_million: None,
+    }
+}
+
+fn lsp() -> LspConfig {
+    LspConfig {
+        enabled: true,
+        ..LspConfig::default()
+    }
+}
+
+#[test]
+fn quote_charges_the_minimum_fee() {
+    assert_eq!(
+        channel_order_quote(&lsp(), Amount::from_sats(50_000), 100_000),
+        Ok(ChannelOrderQuote {
+            channel_size_sats: 148_000,
+            push_amount_sats: 48_000,
+            fee_sats: 2_000,
+        })
+    );
+}
+
+#[test]
+fn quote_charges_the_proportional_fee_for_large_channels() {
+    assert_eq!(
+        channel_order_quote(&lsp(), Amount::from_sats(10_000), 1_000_000),
+        Err("The ecash does not cover the fee of 10000 sats".to_string())
+    );
+
+    assert_eq!(
+        channel_order_quote(&lsp(), Amount::from_msats(10_000_999), 1_000_000),
+        Ok(ChannelOrderQuote {
+            channel_size_sats: 1_000_000,
+            push_amount_sats: 0,
+            fee_sats: 10_000,
+        })
+    );
+}
+
+#[test]
+fn quote_moves_ecash_into_a_channel_without_inbound_capacity() {
+    assert_eq!(
+        channel_order_quote(&lsp(), Amount::from_sats(102_000), 0),
+        Ok(ChannelOrderQuote {
+            channel_size_sats: 100_000,
+            push_amount_sats: 100_000,
+            fee_sats: 2_000,
+        })
+    );
+}
+
+#[test]
+fn quote_rejects_channels_outside_of_the_size_limits() {
+    assert!(channel_order_quote(&lsp(), Amount::from_sats(10_000), 0).is_err());
+    assert!(
+        channel_order_quote(
+            &lsp(),
+            Amount::from_sats(200_000),
+            LspConfig::DEFAULT_MAX_CHANNEL_SATS
+        )
+        .is_err()
+    );
+}
+
+#[test]
+fn response_reports_pending_orders_without_charging_a_fee() {
+    let order_id = sha256::Hash::hash(b"notes");
+
+    let response = channel_order_response(
+        order_id,
+        &LspChannelOrderState::Opening {
+            ecash: Amount::from_sats(50_000),
+            quote: QUOTE,
+            known_funding_txids: vec![],
+        },
+    );
+
+    assert_eq!(response.order_id, order_id);
+    assert_eq!(response.channel_size_sats, 148_000);
+    assert_eq!(response.fee_sats, 0);
+    assert_eq!(response.outcome, ChannelOrderOutcome::Pending);
+
+    let response = channel_order_response(
+        order_id,
+        &LspChannelOrderState::Received {
+            notes: "notes".to_string(),
+        },
+    );
+
+    assert_eq!(response.channel_size_sats, 0);
+    assert_eq!(response.outcome, ChannelOrderOutcome::Pending);
+}
+
+#[test]
+fn response_charges_the_fee_only_for_opened_channels() {
+    let order_id = sha256::Hash::hash(b"notes");
+
+    let response = channel_order_response(
+        order_id,
+        &LspChannelOrderState::Opened {
+            ecash: Amount::from_sats(50_000),
+            quote: QUOTE,
+            funding_txid: txid(1),
+        },
+    );
+
+    assert_eq!(response.fee_sats, 2_000);
+    assert_eq!(
+        response.outcome,
+        ChannelOrderOutcome::Opened {
+            funding_txid: txid(1)
+        }
+    );
+
+    let response = channel_order_response(
+        order_id,
+        &LspChannelOrderState::Refunded {
+            quote: Some(QUOTE),
+            notes: "refund".to_string(),
+            failure_reason: "The channel was not opened".to_string(),
+        },
+    );
+
+    assert_eq!(response.push_amount_sats, 48_000);
+    assert_eq!(response.fee_sats, 0);
+    assert_eq!(
+        response.outcome,
+        ChannelOrderOutcome::Refunded {
+            notes: "refund".to_string(),
+            failure_reason: "The channel was not opened".to_string(),
+        }
+    );
+}
+
+#[test]
+fn ordered_channel_is_found_by_counterparty_and_size() {
+    let channels = vec![
+        channel(pubkey(1), QUOTE.channel_size_sats, Some(txid(1))),
+        channel(pubkey(2), 100_000, Some(txid(2))),
+        channel(pubkey(2), QUOTE.channel_size_sats, Some(txid(3))),
+    ];
+
+    assert_eq!(
+        find_ordered_channel(&channels, pubkey(2), &QUOTE, &[]),
+        OrderedChannel::Funded(txid(3))
+    );
+    assert_eq!(
+        find_ordered_channel(&channels, pubkey(3), &QUOTE, &[]),
+        OrderedChannel::Missing
+    );
+}
+
+#[test]
+fn ordered_channel_ignores_channels_that_existed_before() {
+    let channels = vec![channel(pubkey(1), QUOTE.channel_size_sats, Some(txid(1)))];
+
+    assert_eq!(
+        find_ordered_channel(&channels, pubkey(1), &QUOTE, &[txid(1)]),
+        OrderedChannel::Missing
+    );
+
+    let channels = vec![
+        channel(pubkey(1), QUOTE.channel_size_sats, Some(txid(1))),
+        channel(pubkey(1), QUOTE.channel_size_sats, None),
+    ];
+
+    assert_eq!(
+        find_ordered_channel(&channels, pubkey(1), &QUOTE, &[txid(1)]),
+        OrderedChannel::Unfunded
+    );
+}
+
+#[test]
+fn an_order_is_claimed_once_until_the_claim_is_dropped() {
+    let orders_in_progress = Arc::new(Mutex::new(BTreeSet::new()));
+    let order_id = sha256::Hash::hash(b"notes");
+
+    let claim = ChannelOrderClaim::new(&orders_in_progress, order_id);
+    assert!(claim.is_some());
+    assert!(ChannelOrderClaim::new(&orders_in_progress, order_id).is_none());
+    assert!(ChannelOrderClaim::new(&orders_in_progress, sha256::Hash::hash(b"other")).is_some());
+
+    drop(claim);
+    assert!(ChannelOrderClaim::new(&orders_in_progress, order_id).is_some());
+}
+
+#[test]
+fn refunds_are_found_by_their_order() {
+    let order_id = sha256::Hash::hash(b"notes");
+
+    let send = |custom_meta| {
+        OperationLogEntry::new(
+            "mintv2".to_string(),
+            JsonStringed(serde_json::json!({
+                "Send": {
+                    "ecash": "refund",
+                    "custom_meta": custom_meta,
+                }
+            })),
+            None,
+        )
+    };
+
+    assert_eq!(
+        refund_notes(&send(refund_meta(order_id)), &refund_meta(order_id)),
+        Some("refund".to_string())
+    );
+    assert_eq!(
+        refund_notes(
+            &send(refund_meta(sha256::Hash::hash(b"other"))),
+            &refund_meta(order_id)
+        ),
+        None
+    );
+    assert_eq!(
+        refund_notes(&send(serde_json::Value::Null), &refund_meta(order_id)),
+        None
+    );
+}
@@ -65,6 +65,9 @@ pub(crate) struct RegistrationHealthTracker {
     next_sequence: Arc<AtomicU64>,
     /// Retained observations and leave/rejoin invalidation watermarks.
     state: Arc<RwLock<RegistrationTrackerState>>,
+    /// Expiry of the leader lease held in high availability mode, zero while
+    /// no lease is held.
+    leader_lease_expires_at_secs: Arc<AtomicU64>,
 }
 
 impl RegistrationHealthTracker {
@@ -175,6 +178,25 @@ pub(crate) async fn clear_federation(&self, federation_id: FederationId) {
             .cleared_through
             .insert(federation_id, self.next_sequence.load(Ordering::Relaxed));
     }
+
+    /// Retains the expiry of the leader lease after it was acquired or
+    /// renewed.
+    pub(crate) fn record_leader_lease(&self, expires_at_secs: u64) {
+        self.leader_lease_expires_at_secs
+            .store(expires_at_secs, Ordering::Relaxed);
+    }
+
+    /// Forgets the leader lease after it was lost.
+    pub(crate) fn clear_leader_lease(&self) {
+        self.leader_lease_expires_at_secs
+            .store(0, Ordering::Relaxed);
+    }
+
+    /// Returns the expiry of the leader lease if this instance holds it.
+    pub(crate) fn leader_lease_expires_at_secs(&self) -> Option<u64> {
+        Some(self.leader_lease_expires_at_secs.load(Ordering::Relaxed))
+            .filter(|expires_at_secs| *expires_at_secs != 0)
+    }
 }
 
 #[cfg(test)]
@@ -304,3 +304,15 @@ async fn clear_rejects_stale_completion_but_accepts_rejoin_attempt() {
         })
     );
 }
+
+#[test]
+fn leader_lease_is_reported_until_cleared() {
+    let tracker = RegistrationHealthTracker::default();
+    assert_eq!(tracker.leader_lease_expires_at_secs(), None);
+
+    tracker.record_leader_lease(1_700_000_030);
+    assert_eq!(tracker.leader_lease_expires_at_secs(), Some(1_700_000_030));
+
+    tracker.clear_leader_lease();
+    assert_eq!(tracker.leader_lease_expires_at_secs(), None);
+}
@@ -0,0 +1,715 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, UNIX_EPOCH};
+
+use fedimint_client::ClientHandleArc;
+use fedimint_core::Amount;
+use fedimint_core::config::FederationId;
+use fedimint_core::db::Database;
+use fedimint_core::time::{duration_since_epoch, now};
+use fedimint_core::util::Spanned;
+use fedimint_eventlog::{DBTransactionEventLogExt as _, EventLogId, PersistedLogEntry};
+use fedimint_gateway_common::{
+    FederationConnectivity, FederationPaused, FederationResumed, FederationRiskStatus,
+    OutgoingPaymentRejected, RiskLimit, RiskLimits, RiskStatusResponse, SetRiskLimitsPayload,
+};
+use fedimint_gateway_server_db::GatewayDbtxNcExt as _;
+use fedimint_ln_common::contracts::ContractId;
+use fedimint_lnv2_common::contracts::PaymentImage;
+use fedimint_logging::LOG_GATEWAY;
+use futures::StreamExt as _;
+use tracing::{info, warn};
+
+use crate::error::{FederationNotConnected, PublicGatewayError};
+use crate::events::get_events_for_duration;
+use crate::federation_status::federation_connectivity;
+use crate::ledger::to_event;
+use crate::{AdminResult, Gateway, Result};
+
+/// How often the connectivity of federations with risk limits is checked.
+const RISK_CHECK_INTERVAL: Duration = Duration::from_secs(20);
+
+/// The number of consecutive checks a federation has to fail before it is
+/// paused, such that connections that are re-established after a restart or a
+/// brief outage do not pause it.
+const PAUSE_AFTER_FAILED_CHECKS: u32 = 3;
+
+/// The window of the daily volume limit.
+const DAILY_VOLUME_WINDOW: Duration = Duration::from_hours(24);
+
+/// How far back the event log of a federation's client is read once the
+/// gateway starts. Outgoing payments that started before and are still in
+/// flight are no longer counted as outstanding.
+const RISK_EVENT_LOOKBACK: Duration = Duration::from_hours(48);
+
+/// The number of entries read from a client's event log at once.
+const RISK_EVENT_BATCH_SIZE: u64 = 1_000;
+
+/// Rejected outgoing payments of a federation are recorded in the event log at
+/// most once per interval, since anyone can request a payment.
+const REJECTION_LOG_INTERVAL: Duration = Duration::from_mins(1);
+
+/// Identifies an outgoing payment across the events of its operation.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub(crate) enum OutgoingPaymentId {
+    Lnv1(ContractId),
+    Lnv2(PaymentImage),
+}
+
+/// An outgoing payment the federation's client started.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct StartedPayment {
+    started_at_secs: u64,
+    invoice_amount: Amount,
+    succeeded: bool,
+}
+
+/// The gateway's exposure to a federation and the state of its circuit
+/// breaker.
+#[derive(Debug, Default)]
+struct FederationRisk {
+    /// The next entry of the client's event log to apply, `None` until the
+    /// log was read.
+    next_event: Option<EventLogId>,
+    /// The outgoing payments started within the lookback that did not fail.
+    /// Payments only start once the client verified their contract, and
+    /// count as outstanding until they succeed or fail.
+    started: HashMap<OutgoingPaymentId, StartedPayment>,
+    /// The amounts of the outgoing payments that were admitted but have not
+    /// started yet, such that concurrent requests cannot exceed the limits.
+    admitted: HashMap<OutgoingPaymentId, Amount>,
+    /// Consecutive checks in which fewer guardians than the threshold were
+    /// reachable.
+    failed_checks: u32,
+    paused: Option<FederationConnectivity>,
+    last_rejection_logged_secs: Option<u64>,
+    /// Rejections since the last one that was recorded in the event log.
+    unrecorded_rejections: u64,
+}
+
+impl FederationRisk {
+    /// Applies the entries of the client's event log that were not applied
+    /// before, in the order of the log.
+    fn apply_events(&mut self, events: &[PersistedLogEntry]) {
+        for entry in events {
+            if self
+                .next_event
+                .is_some_and(|next_event| entry.id() < next_event)
+            {
+                continue;
+            }
+
+            self.next_event = Some(entry.id().next());
+
+            let started_at_secs = entry.as_raw().ts_usecs / 1_000_000;
+
+            if let Some(start) =
+                to_event::<fedimint_gw_client::events::OutgoingPaymentStarted>(entry)
+            {
+                self.start_payment(
+                    OutgoingPaymentId::Lnv1(start.contract_id),
+                    started_at_secs,
+                    start.invoice_amount,
+                );
+            } else if let Some(success) =
+                to_event::<fedimint_gw_client::events::OutgoingPaymentSucceeded>(entry)
+            {
+                self.end_payment(&OutgoingPaymentId::Lnv1(success.contract_id), true);
+            } else if let Some(failure) =
+                to_event::<fedimint_gw_client::events::OutgoingPaymentFailed>(entry)
+            {
+                self.end_payment(&OutgoingPaymentId::Lnv1(failure.contract_id), false);
+            } else if let Some(start) =
+                to_event::<fedimint_gwv2_client::events::OutgoingPaymentStarted>(entry)
+            {
+                self.start_payment(
+                    OutgoingPaymentId::Lnv2(start.outgoing_contract.payment_image),
+                    started_at_secs,
+                    start.invoice_amount,
+                );
+            } else if let Some(success) =
+                to_event::<fedimint_gwv2_client::events::OutgoingPaymentSucceeded>(entry)
+            {
+                self.end_payment(&OutgoingPaymentId::Lnv2(success.payment_image), true);
+            } else if let Some(failure) =
+                to_event::<fedimint_gwv2_client::events::OutgoingPaymentFailed>(entry)
+            {
+                self.end_payment(&OutgoingPaymentId::Lnv2(failure.payment_image), false);
+            }
+        }
+    }
+
+    fn start_payment(
+        &mut self,
+        payment: OutgoingPaymentId,
+        started_at_secs: u64,
+        invoice_amount: Amount,
+    ) {
+        self.admitted.remove(&payment);
+        self.started.insert(
+            payment,
+            StartedPayment {
+                started_at_secs,
+                invoice_amount,
+                succeeded: false,
+            },
+        );
+    }
+
+    /// Ends a payment. A failed payment did not spend the gateway's funds, so
+    /// it no longer counts towards the daily volume either.
+    fn end_payment(&mut self, payment: &OutgoingPaymentId, succeeded: bool) {
+        if succeeded {
+            if let Some(started) = self.started.get_mut(payment) {
+                started.succeeded = true;
+            }
+        } else {
+            self.started.remove(payment);
+        }
+    }
+
+    fn prune_started_payments(&mut self, now_secs: u64) {
+        let window_start = now_secs.saturating_sub(DAILY_VOLUME_WINDOW.as_secs());
+        let lookback_start = now_secs.saturating_sub(RISK_EVENT_LOOKBACK.as_secs());
+
+        self.started.retain(|_, started| {
+            lookback_start < started.started_at_secs
+                && !(started.succeeded && started.started_at_secs <= window_start)
+        });
+    }
+
+    fn outstanding_outgoing(&self) -> u64 {
+        let started = self
+            .started
+            .values()
+            .filter(|started| !started.succeeded)
+            .count();
+
+        (started + self.admitted.len()) as u64
+    }
+
+    fn daily_volume(&self, now_secs: u64) -> Amount {
+        let window_start = now_secs.saturating_sub(DAILY_VOLUME_WINDOW.as_secs());
+
+        Amount::from_msats(
+            self.started
+                .values()
+                .filter(|started| window_start < started.started_at_secs)
+                .map(|started| started.invoice_amount)
+                .chain(self.admitted.values().copied())
+                .fold(0, |volume, amount| volume.saturating_add(amount.msats)),
+        )
+    }
+
+    /// Returns the limit an outgoing payment of `amount` would exceed, if any.
+    fn check_outgoing_payment(
+        &self,
+        limits: &RiskLimits,
+        amount: Amount,
+        now_secs: u64,
+    ) -> std::result::Result<(), RiskLimit> {
+        if self.paused.is_some() {
+            return Err(RiskLimit::Paused);
+        }
+
+        if limits
+            .max_outstanding_outgoing
+            .is_some_and(|max| max <= self.outstanding_outgoing())
+        {
+            return Err(RiskLimit::MaxOutstandingOutgoing);
+        }
+
+        if limits.max_payment.is_some_and(|max| max < amount) {
+            return Err(RiskLimit::MaxPayment);
+        }
+
+        if limits.max_daily_volume.is_some_and(|max| {
+            max.msats
+                < self
+                    .daily_volume(now_secs)
+                    .msats
+                    .saturating_add(amount.msats)
+        }) {
+            return Err(RiskLimit::MaxDailyVolume);
+        }
+
+        Ok(())
+    }
+
+    /// Returns whether a rejection is recorded in the event log, together
+    /// with the number of rejections since the last recorded one.
+    fn record_rejection(&mut self, now_secs: u64) -> Option<u64> {
+        if self.last_rejection_logged_secs.is_some_and(|logged_secs| {
+            now_secs < logged_secs.saturating_add(REJECTION_LOG_INTERVAL.as_secs())
+        }) {
+            self.unrecorded_rejections = self.unrecorded_rejections.saturating_add(1);
+            return None;
+        }
+
+        self.last_rejection_logged_secs = Some(now_secs);
+
+        Some(std::mem::take(&mut self.unrecorded_rejections))
+    }
+
+    /// Records the connectivity of a check and returns whether this paused or
+    /// resumed the federation.
+    fn record_connectivity(&mut self, connectivity: FederationConnectivity) -> Option<PauseChange> {
+        if connectivity == FederationConnectivity::Connected {
+            self.failed_checks = 0;
+            return self.paused.take().map(|_| PauseChange::Resumed);
+        }
+
+        self.failed_checks = self.failed_checks.saturating_add(1);
+
+        if self.paused.is_none() && PAUSE_AFTER_FAILED_CHECKS <= self.failed_checks {
+            self.paused = Some(connectivity);
+            return Some(PauseChange::Paused(connectivity));
+        }
+
+        None
+    }
+}
+
+/// A change of a federation's circuit breaker.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PauseChange {
+    Paused(FederationConnectivity),
+    Resumed,
+}
+
+/// Reads the entries of a client's event log from `next_event` on, or from
+/// the start of the lookback if the log was not read before.
+async fn read_risk_events(db: &Database, next_event: Option<EventLogId>) -> Vec<PersistedLogEntry> {
+    let (mut events, mut pos) = match next_event {
+        Some(next_event) => (vec![], next_event),
+        None => {
+            let log_end = db
+                .begin_transaction_nc()
+                .await
+                .get_next_event_log_id()
+                .await;
+            let end = now();
+            let start = end.checked_sub(RISK_EVENT_LOOKBACK).unwrap_or(UNIX_EPOCH);
+            let events = get_events_for_duration(db, start, end).await;
+            let pos = events.last().map_or(log_end, |entry| entry.id().next());
+
+            (events, pos)
+        }
+    };
+
+    loop {
+        let batch = db
+            .begin_transaction_nc()
+            .await
+            .get_event_log(Some(pos), RISK_EVENT_BATCH_SIZE)
+            .await;
+
+        let Some(last) = batch.last() else {
+            return events;
+        };
+
+        pos = last.id().next();
+        events.extend(batch);
+    }
+}
+
+/// Tracks the gateway's exposure to every federation. The exposure is derived
+/// from the outgoing payment events of the federations' clients, which are
+/// persisted and follow a payment through its operation. Outgoing payments
+/// are tracked whether or not the federation has risk limits, such that
+/// limits set later on account for the payments in flight.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct RiskTracker {
+    state: Arc<Mutex<BTreeMap<FederationId, FederationRisk>>>,
+}
+
+impl RiskTracker {
+    fn with_risk<T>(
+        &self,
+        federation_id: FederationId,
+        f: impl FnOnce(&mut FederationRisk) -> T,
+    ) -> T {
+        let mut state = self
+            .state
+            .lock()
+            .expect("No code holding the lock can panic");
+
+        f(state.entry(federation_id).or_default())
+    }
+
+    /// Applies the events of the federation's client that were logged since
+    /// the last update.
+    async fn update(&self, federation_id: FederationId, client: &ClientHandleArc) {
+        let next_event = self.with_risk(federation_id, |risk| risk.next_event);
+        let events = read_risk_events(client.db(), next_event).await;
+
+        self.with_risk(federation_id, |risk| risk.apply_events(&events));
+    }
+
+    /// Admits an outgoing payment unless it exceeds the federation's limits.
+    /// Until the payment starts it counts with the amount of the request, the
+    /// returned guard releases it if the payment never starts. A payment that
+    /// is already tracked is admitted again, since it does not add to the
+    /// exposure.
+    fn begin_outgoing_payment(
+        &self,
+        federation_id: FederationId,
+        limits: Option<&RiskLimits>,
+        payment: OutgoingPaymentId,
+        amount: Amount,
+        now_secs: u64,
+    ) -> std::result::Result<OutgoingPaymentGuard, RiskLimit> {
+        let admitted = self.with_risk(federation_id, |risk| {
+            risk.prune_started_payments(now_secs);
+
+            if risk.started.contains_key(&payment) || risk.admitted.contains_key(&payment) {
+                return Ok(None);
+            }
+
+            if let Some(limits) = limits {
+                risk.check_outgoing_payment(limits, amount, now_secs)?;
+            }
+
+            risk.admitted.insert(payment.clone(), amount);
+
+            Ok(Some(payment))
+        })?;
+
+        Ok(OutgoingPaymentGuard {
+            tracker: self.clone(),
+            federation_id,
+            admitted,
+        })
+    }
+
+    fn record_rejection(&self, federation_id: FederationId, now_secs: u64) -> Option<u64> {
+        self.with_risk(federation_id, |risk| risk.record_rejection(now_secs))
+    }
+
+    fn record_connectivity(
+        &self,
+        federation_id: FederationId,
+        connectivity: FederationConnectivity,
+    ) -> Option<PauseChange> {
+        self.with_risk(federation_id, |risk| risk.record_connectivity(connectivity))
+    }
+
+    /// Resets the circuit breaker of a federation and returns whether it was
+    /// paused.
+    fn reset_pause(&self, federation_id: FederationId) -> bool {
+        self.with_risk(federation_id, |risk| {
+            risk.failed_checks = 0;
+            risk.paused.take().is_some()
+        })
+    }
+
+    /// Returns whether a federation is paused. This does not track the
+    /// federation, since public requests can name any federation.
+    pub(crate) fn is_paused(&self, federation_id: FederationId) -> bool {
+        self.state
+            .lock()
+            .expect("No code holding the lock can panic")
+            .get(&federation_id)
+            .is_some_and(|risk| risk.paused.is_some())
+    }
+
+    fn status(
+        &self,
+        federation_id: FederationId,
+        limits: RiskLimits,
+        now_secs: u64,
+    ) -> FederationRiskStatus {
+        self.with_risk(federation_id, |risk| {
+            risk.prune_started_payments(now_secs);
+
+            FederationRiskStatus {
+                federation_id,
+                limits,
+                paused: risk.paused,
+                outstanding_outgoing: risk.outstanding_outgoing(),
+                daily_volume: risk.daily_volume(now_secs),
+            }
+        })
+    }
+}
+
+/// Counts an admitted outgoing payment until it starts or the request ends.
+#[derive(Debug)]
+pub(crate) struct OutgoingPaymentGuard {
+    tracker: RiskTracker,
+    federation_id: FederationId,
+    admitted: Option<OutgoingPaymentId>,
+}
+
+impl Drop for OutgoingPaymentGuard {
+    fn drop(&mut self) {
+        if let Some(payment) = self.admitted.take() {
+            self.tracker.with_risk(self.federation_id, |risk| {
+                risk.admitted.remove(&payment);
+            });
+        }
+    }
+}
+
+impl Gateway {
+    /// Admits an outgoing payment of a connected federation, or rejects it if
+    /// it exceeds the federation's risk limits. The payment is only counted
+    /// with the amount of its invoice once the federation's client verified
+    /// and started it, until then the returned guard holds its place.
+    pub(crate) async fn begin_outgoing_payment(
+        &self,
+        client: &ClientHandleArc,
+        federation_id: FederationId,
+        payment: OutgoingPaymentId,
+        amount: Amount,
+    ) -> Result<OutgoingPaymentGuard> {
+        let limits = self
+            .gateway_db
+            .begin_transaction_nc()
+            .await
+            .load_risk_limits(federation_id)
+            .await;
+
+        self.risk_tracker.update(federation_id, client).await;
+
+        let now_secs = duration_since_epoch().as_secs();
+
+        match self.risk_tracker.begin_outgoing_payment(
+            federation_id,
+            limits.as_ref(),
+            payment,
+            amount,
+            now_secs,
+        ) {
+            Ok(guard) => Ok(guard),
+            Err(limit) => {
+                if let Some(unrecorded_rejections) =
+                    self.risk_tracker.record_rejection(federation_id, now_secs)
+                {
+                    warn!(
+                        target: LOG_GATEWAY,
+                        %federation_id,
+                        %amount,
+                        ?limit,
+                        unrecorded_rejections,
+                        "Rejecting outgoing payment"
+                    );
+
+                    let mut dbtx = self.gateway_db.begin_transaction().await;
+                    dbtx.log_event(
+                        self.event_log_wakeup_tx.clone(),
+                        None,
+                        OutgoingPaymentRejected {
+                            federation_id,
+                            amount,
+                            limit,
+                            unrecorded_rejections,
+                        },
+                    )
+                    .await;
+                    dbtx.commit_tx().await;
+                }
+
+                Err(PublicGatewayError::RiskLimitExceeded(limit))
+            }
+        }
+    }
+
+    /// Spawns a background task that pauses federations with risk limits
+    /// while fewer guardians than their threshold are reachable, and resumes
+    /// them once they are reachable again.
+    pub(crate) fn spawn_risk_monitor_task(&self) {
+        let self_copy = self.clone();
+        self.task_group
+            .spawn_cancellable_silent("risk monitor", async move {
+                let mut interval = tokio::time::interval(RISK_CHECK_INTERVAL);
+                loop {
+                    interval.tick().await;
+                    self_copy.check_federation_risks().await;
+                }
+            });
+    }
+
+    async fn check_federation_risks(&self) {
+        let limits = self
+            .gateway_db
+            .begin_transaction_nc()
+            .await
+            .load_all_risk_limits()
+            .await;
+
+        for federation_id in limits.into_keys() {
+            // The gateway might have left the federation since its limits were set
+            let Some(connectivity) = self.federation_connectivity(federation_id).await else {
+                continue;
+            };
+
+            match self
+                .risk_tracker
+                .record_connectivity(federation_id, connectivity)
+            {
+                Some(PauseChange::Paused(connectivity)) => {
+                    self.pause_federation(federation_id, connectivity).await;
+                }
+                Some(PauseChange::Resumed) => self.resume_federation(federation_id).await,
+                None => {}
+            }
+        }
+    }
+
+    async fn federation_connectivity(
+        &self,
+        federation_id: FederationId,
+    ) -> Option<FederationConnectivity> {
+        let federation_manager = self.federation_manager.read().await;
+        let client = federation_manager
+            .client(&federation_id)
+            .map(Spanned::value)?;
+
+        let connection_status = client
+            .connection_status_stream()
+            .next()
+            .await
+            .unwrap_or_default();
+
+        Some(federation_connectivity(&connection_status))
+    }
+
+    /// Stops announcing the gateway to a paused federation. Removing the LNv1
+    /// registration is best effort since the federation is hardly reachable,
+    /// but the registration is no longer renewed either way and LNv2 clients
+    /// stop receiving routing info right away.
+    async fn pause_federation(
+        &self,
+        federation_id: FederationId,
+        connectivity: FederationConnectivity,
+    ) {
+        warn!(
+            target: LOG_GATEWAY,
+            %federation_id,
+            ?connectivity,
+            "Pausing federation, too few guardians are reachable"
+        );
+
+        let mut dbtx = self.gateway_db.begin_transaction().await;
+        dbtx.log_event(
+            self.event_log_wakeup_tx.clone(),
+            None,
+            FederationPaused {
+                federation_id,
+                connectivity,
+            },
+        )
+        .await;
+        dbtx.commit_tx().await;
+
+        if self.lightning_mode.supports_lnv1() {
+            let self_copy = self.clone();
+            self.task_group
+                .spawn_cancellable_silent("unannounce paused federation", async move {
+                    for registration in self_copy.registrations.values() {
+                        self_copy
+                            .federation_manager
+                            .read()
+                            .await
+                            .unannounce_from_federation(federation_id, registration.keypair)
+                            .await;
+                    }
+                });
+        }
+    }
+
+    async fn resume_federation(&self, federation_id: FederationId) {
+        info!(target: LOG_GATEWAY, %federation_id, "Resuming federation");
+
+        let mut dbtx = self.gateway_db.begin_transaction().await;
+        dbtx.log_event(
+            self.event_log_wakeup_tx.clone(),
+            None,
+            FederationResumed { federation_id },
+        )
+        .await;
+        dbtx.commit_tx().await;
+
+        if self.lightning_mode.supports_lnv1()
+            && let Some(config) = self
+                .gateway_db
+                .begin_transaction_nc()
+                .await
+                .load_federation_config(federation_id)
+                .await
+        {
+            self.register_federations(&BTreeMap::from([(federation_id, config)]), &self.task_group)
+                .await;
+        }
+    }
+
+    /// Returns the risk limits of all federations that have them together
+    /// with the gateway's current exposure to them.
+    pub async fn handle_risk_status_msg(&self) -> AdminResult<RiskStatusResponse> {
+        let limits = self
+            .gateway_db
+            .begin_transaction_nc()
+            .await
+            .load_all_risk_limits()
+            .await;
+
+        for federation_id in limits.keys() {
+            let client = self
+                .federation_manager
+                .read()
+                .await
+                .client(federation_id)
+                .map(Spanned::value)
+                .cloned();
+
+            // The gateway might have left the federation since its limits were set
+            if let Some(client) = client {
+                self.risk_tracker.update(*federation_id, &client).await;
+            }
+        }
+
+        let now_secs = duration_since_epoch().as_secs();
+
+        Ok(RiskStatusResponse(
+            limits
+                .into_iter()
+                .map(|(federation_id, limits)| {
+                    self.risk_tracker.status(federation_id, limits, now_secs)
+                })
+                .collect(),
+        ))
+    }
+
+    /// Replaces the risk limits of a federation, which apply to the next
+    /// outgoing payment. Removing the limits resumes a paused federation.
+    pub async fn handle_set_risk_limits_msg(
+        &self,
+        SetRiskLimitsPayload {
+            federation_id,
+            limits,
+        }: SetRiskLimitsPayload,
+    ) -> AdminResult<()> {
+        let mut dbtx = self.gateway_db.begin_transaction().await;
+
+        if dbtx.load_federation_config(federation_id).await.is_none() {
+            return Err(FederationNotConnected {
+                federation_id_prefix: federation_id.to_prefix(),
+            }
+            .into());
+        }
+
+        let remove_limits = limits.is_none();
+        dbtx.save_risk_limits(federation_id, limits).await;
+        dbtx.commit_tx().await;
+
+        if remove_limits && self.risk_tracker.reset_pause(federation_id) {
+            self.resume_federation(federation_id).await;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests;
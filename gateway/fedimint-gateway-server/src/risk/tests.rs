@@ -0,0 +1,430 @@
+use bitcoin::hashes::{Hash as _, sha256};
+use fedimint_core::Amount;
+use fedimint_core::config::FederationId;
+use fedimint_core::core::OperationId;
+use fedimint_eventlog::PersistedLogEntry;
+use fedimint_gateway_common::{FederationConnectivity, RiskLimit, RiskLimits};
+use fedimint_gw_client::events::OutgoingPaymentStarted;
+use fedimint_gwv2_client::events::{OutgoingPaymentFailed, OutgoingPaymentSucceeded};
+use fedimint_ln_common::contracts::ContractId;
+use fedimint_lnv2_common::contracts::PaymentImage;
+use serde_json::json;
+
+use super::{
+    FederationRisk, OutgoingPaymentId, PAUSE_AFTER_FAILED_CHECKS, PauseChange,
+    REJECTION_LOG_INTERVAL, RiskTracker,
+};
+use crate::test_helpers::log_entry;
+
+const NOW_SECS: u64 = 1_700_000_000;
+const DAY_SECS: u64 = 24 * 60 * 60;
+
+fn msats(msats: u64) -> Amount {
+    Amount::from_msats(msats)
+}
+
+fn lnv1_payment(byte: u8) -> OutgoingPaymentId {
+    OutgoingPaymentId::Lnv1(ContractId::from_raw_hash(sha256::Hash::hash(&[byte])))
+}
+
+fn lnv2_image(byte: u8) -> PaymentImage {
+    PaymentImage::Hash(sha256::Hash::hash(&[byte]))
+}
+
+/// Returns the start event of the LNv1 payment `byte`.
+fn lnv1_start(id: u64, started_at_secs: u64, byte: u8, amount: Amount) -> PersistedLogEntry {
+    log_entry(
+        id,
+        started_at_secs * 1_000_000,
+        &OutgoingPaymentStarted {
+            contract_id: ContractId::from_raw_hash(sha256::Hash::hash(&[byte])),
+            invoice_amount: amount,
+            operation_id: OperationId::new_random(),
+        },
+    )
+}
+
+#[test]
+fn payments_within_limits_are_accepted() {
+    let limits = RiskLimits {
+        max_outstanding_outgoing: Some(1),
+        max_payment: Some(msats(1_000)),
+        max_daily_volume: Some(msats(1_000)),
+    };
+
+    assert_eq!(
+        FederationRisk::default().check_outgoing_payment(&limits, msats(1_000), NOW_SECS),
+        Ok(())
+    );
+    assert_eq!(
+        FederationRisk::default().check_outgoing_payment(
+            &RiskLimits::default(),
+            msats(u64::MAX),
+            NOW_SECS
+        ),
+        Ok(())
+    );
+}
+
+#[test]
+fn payments_exceeding_a_limit_are_rejected() {
+    let mut risk = FederationRisk::default();
+    risk.start_payment(lnv1_payment(1), NOW_SECS, msats(600));
+    risk.admitted.insert(lnv1_payment(2), msats(0));
+
+    let max_outstanding = RiskLimits {
+        max_outstanding_outgoing: Some(2),
+        ..RiskLimits::default()
+    };
+    assert_eq!(
+        risk.check_outgoing_payment(&max_outstanding, msats(1), NOW_SECS),
+        Err(RiskLimit::MaxOutstandingOutgoing)
+    );
+
+    let max_payment = RiskLimits {
+        max_payment: Some(msats(500)),
+        ..RiskLimits::default()
+    };
+    assert_eq!(
+        risk.check_outgoing_payment(&max_payment, msats(501), NOW_SECS),
+        Err(RiskLimit::MaxPayment)
+    );
+
+    let max_daily_volume = RiskLimits {
+        max_daily_volume: Some(msats(1_000)),
+        ..RiskLimits::default()
+    };
+    assert_eq!(
+        risk.check_outgoing_payment(&max_daily_volume, msats(400), NOW_SECS),
+        Ok(())
+    );
+    assert_eq!(
+        risk.check_outgoing_payment(&max_daily_volume, msats(401), NOW_SECS),
+        Err(RiskLimit::MaxDailyVolume)
+    );
+    assert_eq!(
+        risk.check_outgoing_payment(&max_daily_volume, msats(u64::MAX), NOW_SECS),
+        Err(RiskLimit::MaxDailyVolume)
+    );
+}
+
+#[test]
+fn paused_federations_reject_all_payments() {
+    let risk = FederationRisk {
+        paused: Some(FederationConnectivity::Degraded),
+        ..FederationRisk::default()
+    };
+
+    assert_eq!(
+        risk.check_outgoing_payment(&RiskLimits::default(), msats(1), NOW_SECS),
+        Err(RiskLimit::Paused)
+    );
+}
+
+#[test]
+fn admitted_payments_are_released_if_they_never_start() {
+    let tracker = RiskTracker::default();
+    let federation_id = FederationId::dummy();
+    let limits = RiskLimits {
+        max_outstanding_outgoing: Some(1),
+        ..RiskLimits::default()
+    };
+
+    let payment = tracker
+        .begin_outgoing_payment(
+            federation_id,
+            Some(&limits),
+            lnv1_payment(1),
+            msats(1),
+            NOW_SECS,
+        )
+        .expect("first payment is within the limits");
+
+    assert_eq!(
+        tracker
+            .begin_outgoing_payment(
+                federation_id,
+                Some(&limits),
+                lnv1_payment(2),
+                msats(1),
+                NOW_SECS
+            )
+            .err(),
+        Some(RiskLimit::MaxOutstandingOutgoing)
+    );
+
+    drop(payment);
+
+    let status = tracker.status(federation_id, limits.clone(), NOW_SECS);
+    assert_eq!(status.outstanding_outgoing, 0);
+    assert_eq!(
+        status.daily_volume,
+        msats(0),
+        "a payment that never started has no volume"
+    );
+
+    assert!(
+        tracker
+            .begin_outgoing_payment(
+                federation_id,
+                Some(&limits),
+                lnv1_payment(2),
+                msats(1),
+                NOW_SECS
+            )
+            .is_ok()
+    );
+}
+
+#[test]
+fn started_payments_stay_outstanding_after_the_request_ended() {
+    let tracker = RiskTracker::default();
+    let federation_id = FederationId::dummy();
+    let limits = RiskLimits {
+        max_outstanding_outgoing: Some(1),
+        ..RiskLimits::default()
+    };
+
+    let payment = tracker
+        .begin_outgoing_payment(
+            federation_id,
+            Some(&limits),
+            lnv1_payment(1),
+            msats(5_000),
+            NOW_SECS,
+        )
+        .expect("payment is within the limits");
+
+    tracker.with_risk(federation_id, |risk| {
+        risk.apply_events(&[lnv1_start(0, NOW_SECS, 1, msats(1_000))]);
+    });
+
+    drop(payment);
+
+    let status = tracker.status(federation_id, limits.clone(), NOW_SECS);
+    assert_eq!(status.outstanding_outgoing, 1);
+    assert_eq!(
+        status.daily_volume,
+        msats(1_000),
+        "a started payment counts with the amount of its invoice"
+    );
+
+    assert_eq!(
+        tracker
+            .begin_outgoing_payment(
+                federation_id,
+                Some(&limits),
+                lnv1_payment(2),
+                msats(1),
+                NOW_SECS
+            )
+            .err(),
+        Some(RiskLimit::MaxOutstandingOutgoing)
+    );
+    assert!(
+        tracker
+            .begin_outgoing_payment(
+                federation_id,
+                Some(&limits),
+                lnv1_payment(1),
+                msats(1),
+                NOW_SECS
+            )
+            .is_ok(),
+        "a payment that is already tracked does not add to the exposure"
+    );
+}
+
+#[test]
+fn ended_payments_are_no_longer_outstanding() {
+    let mut risk = FederationRisk::default();
+    risk.start_payment(
+        OutgoingPaymentId::Lnv2(lnv2_image(1)),
+        NOW_SECS,
+        msats(1_000),
+    );
+    risk.start_payment(
+        OutgoingPaymentId::Lnv2(lnv2_image(2)),
+        NOW_SECS,
+        msats(2_000),
+    );
+
+    risk.apply_events(&[
+        log_entry(
+            0,
+            NOW_SECS * 1_000_000,
+            &OutgoingPaymentSucceeded {
+                payment_image: lnv2_image(1),
+                target_federation: None,
+            },
+        ),
+        log_entry(
+            1,
+            NOW_SECS * 1_000_000,
+            // The reason of the failure is private to the client module
+            &serde_json::from_value::<OutgoingPaymentFailed>(json!({
+                "payment_image": lnv2_image(2),
+                "error": "Rejected",
+            }))
+            .expect("valid event"),
+        ),
+    ]);
+
+    assert_eq!(risk.outstanding_outgoing(), 0);
+    assert_eq!(
+        risk.daily_volume(NOW_SECS),
+        msats(1_000),
+        "only the payment that succeeded spent the gateway's funds"
+    );
+}
+
+#[test]
+fn events_are_applied_once() {
+    let mut risk = FederationRisk::default();
+    let events = [
+        lnv1_start(0, NOW_SECS, 1, msats(1_000)),
+        lnv1_start(1, NOW_SECS, 2, msats(2_000)),
+    ];
+
+    risk.apply_events(&events);
+    risk.apply_events(&events[1..]);
+
+    assert_eq!(risk.outstanding_outgoing(), 2);
+    assert_eq!(risk.daily_volume(NOW_SECS), msats(3_000));
+}
+
+#[test]
+fn daily_volume_only_counts_the_last_day() {
+    let tracker = RiskTracker::default();
+    let federation_id = FederationId::dummy();
+    let limits = RiskLimits {
+        max_daily_volume: Some(msats(1_000)),
+        ..RiskLimits::default()
+    };
+
+    tracker.with_risk(federation_id, |risk| {
+        risk.apply_events(&[lnv1_start(0, NOW_SECS, 1, msats(1_000))]);
+    });
+
+    assert_eq!(
+        tracker
+            .begin_outgoing_payment(
+                federation_id,
+                Some(&limits),
+                lnv1_payment(2),
+                msats(1),
+                NOW_SECS + DAY_SECS - 1
+            )
+            .err(),
+        Some(RiskLimit::MaxDailyVolume)
+    );
+
+    assert!(
+        tracker
+            .begin_outgoing_payment(
+                federation_id,
+                Some(&limits),
+                lnv1_payment(2),
+                msats(1_000),
+                NOW_SECS + DAY_SECS
+            )
+            .is_ok()
+    );
+}
+
+#[test]
+fn payments_without_limits_are_tracked() {
+    let tracker = RiskTracker::default();
+    let federation_id = FederationId::dummy();
+
+    let _payment = tracker
+        .begin_outgoing_payment(federation_id, None, lnv1_payment(1), msats(5_000), NOW_SECS)
+        .expect("payments without limits are accepted");
+
+    let status = tracker.status(federation_id, RiskLimits::default(), NOW_SECS);
+    assert_eq!(status.outstanding_outgoing, 1);
+    assert_eq!(status.daily_volume, msats(5_000));
+}
+
+#[test]
+fn rejections_are_recorded_once_per_interval() {
+    let mut risk = FederationRisk::default();
+    let interval_secs = REJECTION_LOG_INTERVAL.as_secs();
+
+    assert_eq!(risk.record_rejection(NOW_SECS), Some(0));
+    assert_eq!(risk.record_rejection(NOW_SECS + 1), None);
+    assert_eq!(risk.record_rejection(NOW_SECS + interval_secs - 1), None);
+    assert_eq!(risk.record_rejection(NOW_SECS + interval_secs), Some(2));
+    assert_eq!(risk.record_rejection(NOW_SECS + interval_secs), None);
+}
+
+#[test]
+fn federations_are_paused_after_consecutive_failed_checks() {
+    let mut risk = FederationRisk::default();
+
+    for _ in 1..PAUSE_AFTER_FAILED_CHECKS {
+        assert_eq!(
+            risk.record_connectivity(FederationConnectivity::Disconnected),
+            None
+        );
+    }
+
+    assert_eq!(
+        risk.record_connectivity(FederationConnectivity::Degraded),
+        Some(PauseChange::Paused(FederationConnectivity::Degraded))
+    );
+    assert_eq!(
+        risk.record_connectivity(FederationConnectivity::Disconnected),
+        None,
+        "a paused federation is not paused again"
+    );
+    assert_eq!(risk.paused, Some(FederationConnectivity::Degraded));
+
+    assert_eq!(
+        risk.record_connectivity(FederationConnectivity::Connected),
+        Some(PauseChange::Resumed)
+    );
+    assert_eq!(risk.paused, None);
+    assert_eq!(
+        risk.record_connectivity(FederationConnectivity::Connected),
+        None
+    );
+}
+
+#[test]
+fn brief_outages_do_not_pause_federations() {
+    let mut risk = FederationRisk::default();
+
+    for _ in 0..3 {
+        for _ in 1..PAUSE_AFTER_FAILED_CHECKS {
+            assert_eq!(
+                risk.record_connectivity(FederationConnectivity::Disconnected),
+                None
+            );
+        }
+
+        assert_eq!(
+            risk.record_connectivity(FederationConnectivity::Connected),
+            None
+        );
+    }
+
+    assert_eq!(risk.paused, None);
+}
+
+#[test]
+fn resetting_the_pause_reports_whether_it_was_paused() {
+    let tracker = RiskTracker::default();
+    let federation_id = FederationId::dummy();
+
+    assert!(!tracker.is_paused(federation_id));
+
+    for _ in 0..PAUSE_AFTER_FAILED_CHECKS {
+        tracker.record_connectivity(federation_id, FederationConnectivity::Disconnected);
+    }
+
+    assert!(tracker.is_paused(federation_id));
+    assert!(tracker.reset_pause(federation_id));
+    assert!(!tracker.is_paused(federation_id));
+    assert!(!tracker.reset_pause(federation_id));
+}
@@ -13,35 +13,55 @@
 use fedimint_core::task::TaskGroup;
 use fedimint_core::util::FmtCompact;
 use fedimint_gateway_common::{
-    ADDRESS_ENDPOINT, ADDRESS_RECHECK_ENDPOINT, BACKUP_ENDPOINT, BackupPayload,
+    ADDRESS_ENDPOINT, ADDRESS_RECHECK_ENDPOINT, API_TOKEN_AUDIT_LOG_ENDPOINT, API_TOKENS_ENDPOINT,
+    ApiTokenAuditLogPayload, BACKUP_ENDPOINT, BackupPayload, CHANNEL_ORDER_STATUS_ENDPOINT,
     CLOSE_CHANNELS_WITH_PEER_ENDPOINT, CONFIGURATION_ENDPOINT, CONNECT_FED_ENDPOINT,
-    CONNECT_PEER_ENDPOINT, CREATE_BOLT11_INVOICE_FOR_OPERATOR_ENDPOINT,
-    CREATE_BOLT12_OFFER_FOR_OPERATOR_ENDPOINT, CloseChannelsWithPeerRequest, ConfigPayload,
-    ConnectFedPayload, ConnectPeerRequest, CreateInvoiceForOperatorPayload, CreateOfferPayload,
-    DepositAddressPayload, DepositAddressRecheckPayload, FEDERATION_STATUS_ENDPOINT,
-    FederationStatusRequest, GATEWAY_INFO_ENDPOINT, GET_BALANCES_ENDPOINT, GET_INVOICE_ENDPOINT,
+    CONNECT_PEER_ENDPOINT, CREATE_API_TOKEN_ENDPOINT, CREATE_BOLT11_INVOICE_FOR_OPERATOR_ENDPOINT,
+    CREATE_BOLT12_OFFER_FOR_OPERATOR_ENDPOINT, CREATE_LNURL_WITHDRAW_ENDPOINT, ChannelOrderPayload,
+    ChannelOrderStatusPayload, CloseChannelsWithPeerRequest, ConfigPayload, ConnectFedPayload,
+    ConnectPeerRequest, CreateApiTokenPayload, CreateInvoiceForOperatorPayload,
+    CreateLnurlWithdrawPayload, CreateOfferPayload, DepositAddressPayload,
+    DepositAddressRecheckPayload, EXPORT_LEDGER_ENDPOINT, ExportLedgerPayload,
+    FEDERATION_STATUS_ENDPOINT, FEE_HISTORY_ENDPOINT, FEE_SCHEDULE_ENDPOINT,
+    FederationStatusRequest, FeeHistoryPayload, FeeSchedulePayload, GATEWAY_INFO_ENDPOINT,
+    GET_BALANCES_ENDPOINT, GET_INVOICE_ENDPOINT, GET_LIQUIDITY_POLICY_ENDPOINT,
     GET_LN_ONCHAIN_ADDRESS_ENDPOINT, GetInvoiceRequest, INVITE_CODES_ENDPOINT, LEAVE_FED_ENDPOINT,
-    LIST_CHANNELS_ENDPOINT, LIST_TRANSACTIONS_ENDPOINT, LeaveFedPayload, ListTransactionsPayload,
-    MNEMONIC_ENDPOINT, OPEN_CHANNEL_ENDPOINT, OPEN_CHANNEL_WITH_PUSH_ENDPOINT, OpenChannelRequest,
+    LEDGER_ENDPOINT, LIQUIDITY_LOG_ENDPOINT, LIST_CHANNELS_ENDPOINT, LIST_TRANSACTIONS_ENDPOINT,
+    LSP_INFO_ENDPOINT, LeaveFedPayload, LedgerPayload, LiquidityLogPayload,
+    ListTransactionsPayload, MNEMONIC_ENDPOINT, OPEN_CHANNEL_ENDPOINT,
+    OPEN_CHANNEL_WITH_PUSH_ENDPOINT, ORDER_CHANNEL_ENDPOINT, OpenChannelRequest,
     PAY_INVOICE_FOR_OPERATOR_ENDPOINT, PAY_OFFER_FOR_OPERATOR_ENDPOINT, PAYMENT_LOG_ENDPOINT,
     PAYMENT_SUMMARY_ENDPOINT, PEGIN_FROM_ONCHAIN_ENDPOINT, PayInvoiceForOperatorPayload,
     PayOfferPayload, PaymentLogPayload, PaymentSummaryPayload, PeginFromOnchainPayload,
-    RECEIVE_ECASH_ENDPOINT, ReceiveEcashPayload, SEND_ONCHAIN_ENDPOINT, SET_CHANNEL_FEES_ENDPOINT,
-    SET_FEES_ENDPOINT, SPEND_ECASH_ENDPOINT, STOP_ENDPOINT, SendOnchainRequest,
-    SetChannelFeesRequest, SetFeesPayload, SetMnemonicPayload, SpendEcashPayload, V1_API_ENDPOINT,
-    WITHDRAW_ENDPOINT, WITHDRAW_TO_ONCHAIN_ENDPOINT, WithdrawPayload, WithdrawToOnchainPayload,
+    RECEIVE_ECASH_ENDPOINT, REVENUE_ENDPOINT, REVOKE_API_TOKEN_ENDPOINT, RISK_STATUS_ENDPOINT,
+    ReceiveEcashPayload, RevenuePayload, RevokeApiTokenPayload, SEND_ONCHAIN_ENDPOINT,
+    SET_CHANNEL_FEES_ENDPOINT, SET_FEE_SCHEDULE_ENDPOINT, SET_FEES_ENDPOINT,
+    SET_LIQUIDITY_POLICY_ENDPOINT, SET_RISK_LIMITS_ENDPOINT, SPEND_ECASH_ENDPOINT, STOP_ENDPOINT,
+    SendOnchainRequest, SetChannelFeesRequest, SetFeeSchedulePayload, SetFeesPayload,
+    SetLiquidityPolicyPayload, SetMnemonicPayload, SetRiskLimitsPayload, SpendEcashPayload,
+    V1_API_ENDPOINT, WITHDRAW_ENDPOINT, WITHDRAW_TO_ONCHAIN_ENDPOINT, WithdrawPayload,
+    WithdrawToOnchainPayload,
 };
 use fedimint_gateway_ui::IAdminGateway;
 use fedimint_ln_common::gateway_endpoint_constants::{
     GET_GATEWAY_ID_ENDPOINT, PAY_INVOICE_ENDPOINT,
 };
-use fedimint_lnurl::LnurlResponse;
+use fedimint_lnurl::{InvoiceResponse, LnurlResponse, PayResponse, StatusResponse};
 use fedimint_lnv2_common::endpoint_constants::{
-    CREATE_BOLT11_INVOICE_ENDPOINT, ROUTING_INFO_ENDPOINT, SEND_PAYMENT_ENDPOINT,
+    CREATE_BOLT11_INVOICE_ENDPOINT, CREATE_BOLT12_OFFER_ENDPOINT,
+    CREATE_HOLD_BOLT11_INVOICE_ENDPOINT, FETCH_BOLT12_INVOICE_ENDPOINT,
+    HOLD_INVOICE_STATUS_ENDPOINT, PROBE_PAYMENT_ENDPOINT, REGISTER_STATIC_LNURL_CONTRACTS_ENDPOINT,
+    RESOLVE_HOLD_INVOICE_ENDPOINT, ROUTING_INFO_ENDPOINT, SEND_PAYMENT_ENDPOINT,
+    SUPPORTS_KEYSEND_ENDPOINT, SUPPORTS_MULTI_PART_ENDPOINT,
+};
+use fedimint_lnv2_common::gateway_api::{
+    CreateBolt11InvoicePayload, CreateBolt12OfferPayload, CreateHoldBolt11InvoicePayload,
+    FetchBolt12InvoicePayload, HoldInvoiceStatusPayload, ProbePaymentPayload,
+    RegisterStaticLnurlContractsPayload, ResolveHoldInvoicePayload, SendPaymentPayload,
 };
-use fedimint_lnv2_common::gateway_api::{CreateBolt11InvoicePayload, SendPaymentPayload};
 use fedimint_logging::LOG_GATEWAY;
 use hex::ToHex;
+use serde::Deserialize;
 use serde::de::DeserializeOwned;
 use serde_json::json;
 use tokio::net::TcpListener;
@@ -54,7 +74,7 @@
 
 // Routes that the liquidity manager is allowed to access. Any authenticated
 // route NOT in this list requires the admin password.
-const LIQUIDITY_MANAGER_ROUTES: [&str; 21] = [
+const LIQUIDITY_MANAGER_ROUTES: [&str; 31] = [
     ADDRESS_ENDPOINT,
     ADDRESS_RECHECK_ENDPOINT,
     CLOSE_CHANNELS_WITH_PEER_ENDPOINT,
@@ -62,19 +82,29 @@
     CONNECT_PEER_ENDPOINT,
     CREATE_BOLT11_INVOICE_FOR_OPERATOR_ENDPOINT,
     CREATE_BOLT12_OFFER_FOR_OPERATOR_ENDPOINT,
+    EXPORT_LEDGER_ENDPOINT,
+    FEE_HISTORY_ENDPOINT,
+    FEE_SCHEDULE_ENDPOINT,
     GATEWAY_INFO_ENDPOINT,
     GET_BALANCES_ENDPOINT,
     GET_INVOICE_ENDPOINT,
+    GET_LIQUIDITY_POLICY_ENDPOINT,
     GET_LN_ONCHAIN_ADDRESS_ENDPOINT,
     INVITE_CODES_ENDPOINT,
+    LEDGER_ENDPOINT,
+    LIQUIDITY_LOG_ENDPOINT,
     LIST_CHANNELS_ENDPOINT,
     LIST_TRANSACTIONS_ENDPOINT,
     OPEN_CHANNEL_ENDPOINT,
     PAYMENT_LOG_ENDPOINT,
     PAYMENT_SUMMARY_ENDPOINT,
     PEGIN_FROM_ONCHAIN_ENDPOINT,
+    REVENUE_ENDPOINT,
+    RISK_STATUS_ENDPOINT,
     SET_CHANNEL_FEES_ENDPOINT,
     SET_FEES_ENDPOINT,
+    SET_FEE_SCHEDULE_ENDPOINT,
+    SET_LIQUIDITY_POLICY_ENDPOINT,
     WITHDRAW_TO_ONCHAIN_ENDPOINT,
 ];
 
@@ -193,6 +223,21 @@ async fn auth_middleware(
     next: Next,
 ) -> Result<impl IntoResponse, StatusCode> {
     let token = extract_bearer_token(&request)?;
+
+    // API tokens are looked up by their hash, so they are checked before the
+    // passwords that have to be verified with bcrypt.
+    let api_token = gateway
+        .authorize_api_token(&token, strip_v1_prefix(request.uri().path()))
+        .await;
+
+    if let Some(authorized) = api_token {
+        if !authorized {
+            return Err(StatusCode::UNAUTHORIZED);
+        }
+
+        return Ok(next.run(request).await);
+    }
+
     if bcrypt::verify(token.clone(), &gateway.bcrypt_password_hash)
         .expect("Bcrypt hash is valid since we just stringified it")
     {
@@ -274,6 +319,20 @@ fn lnv2_routes(handlers: &mut Handlers) -> Router {
         false,
         router,
     );
+    let router = register_post_handler(
+        handlers,
+        SUPPORTS_KEYSEND_ENDPOINT,
+        supports_keysend_v2,
+        false,
+        router,
+    );
+    let router = register_post_handler(
+        handlers,
+        SUPPORTS_MULTI_PART_ENDPOINT,
+        supports_multi_part_v2,
+        false,
+        router,
+    );
     let router = register_post_handler(
         handlers,
         SEND_PAYMENT_ENDPOINT,
@@ -281,6 +340,13 @@ fn lnv2_routes(handlers: &mut Handlers) -> Router {
         false,
         router,
     );
+    let router = register_post_handler(
+        handlers,
+        PROBE_PAYMENT_ENDPOINT,
+        probe_payment_v2,
+        false,
+        router,
+    );
     let router = register_post_handler(
         handlers,
         CREATE_BOLT11_INVOICE_ENDPOINT,
@@ -288,8 +354,54 @@ fn lnv2_routes(handlers: &mut Handlers) -> Router {
         false,
         router,
     );
-    // Verify endpoint does not have the same signature, it is handled separately
-    router.route("/verify/{payment_hash}", get(verify_bolt11_preimage_v2_get))
+    let router = register_post_handler(
+        handlers,
+        CREATE_HOLD_BOLT11_INVOICE_ENDPOINT,
+        create_hold_bolt11_invoice_v2,
+        false,
+        router,
+    );
+    let router = register_post_handler(
+        handlers,
+        HOLD_INVOICE_STATUS_ENDPOINT,
+        hold_invoice_status_v2,
+        false,
+        router,
+    );
+    let router = register_post_handler(
+        handlers,
+        RESOLVE_HOLD_INVOICE_ENDPOINT,
+        resolve_hold_invoice_v2,
+        false,
+        router,
+    );
+    let router = register_post_handler(
+        handlers,
+        REGISTER_STATIC_LNURL_CONTRACTS_ENDPOINT,
+        register_static_lnurl_contracts_v2,
+        false,
+        router,
+    );
+    let router = register_post_handler(
+        handlers,
+        FETCH_BOLT12_INVOICE_ENDPOINT,
+        fetch_bolt12_invoice_v2,
+        false,
+        router,
+    );
+    let router = register_post_handler(
+        handlers,
+        CREATE_BOLT12_OFFER_ENDPOINT,
+        create_bolt12_offer_v2,
+        false,
+        router,
+    );
+    // The LNURL endpoints do not have the same signature, they are handled
+    // separately
+    router
+        .route("/verify/{payment_hash}", get(verify_bolt11_preimage_v2_get))
+        .route("/lnurl/pay/{payload}", get(lnurl_pay_v2_get))
+        .route("/lnurl/invoice/{payload}", get(lnurl_invoice_v2_get))
 }
 
 fn public_routes(handlers: &mut Handlers) -> Router {
@@ -300,8 +412,31 @@ fn public_routes(handlers: &mut Handlers) -> Router {
         false,
         Router::new(),
     );
+    routes = register_get_handler(handlers, LSP_INFO_ENDPOINT, lsp_info, false, routes);
+    routes = register_post_handler(
+        handlers,
+        ORDER_CHANNEL_ENDPOINT,
+        order_channel,
+        false,
+        routes,
+    );
+    routes = register_post_handler(
+        handlers,
+        CHANNEL_ORDER_STATUS_ENDPOINT,
+        channel_order_status,
+        false,
+        routes,
+    );
     routes = routes.merge(lnv1_routes(handlers));
     routes = routes.merge(lnv2_routes(handlers));
+    // The LNURL-withdraw endpoints do not have the same signature, they are
+    // handled separately
+    routes = routes
+        .route("/lnurl/withdraw/{link_id}", get(lnurl_withdraw_get))
+        .route(
+            "/lnurl/withdraw/{link_id}/callback",
+            get(lnurl_withdraw_callback_get),
+        );
     register_post_handler(
         handlers,
         FEDERATION_STATUS_ENDPOINT,
@@ -386,6 +521,13 @@ fn routes(gateway: Arc<Gateway>, task_group: TaskGroup, handlers: &mut Handlers)
         is_authenticated,
         authenticated_routes,
     );
+    let authenticated_routes = register_post_handler(
+        handlers,
+        CREATE_LNURL_WITHDRAW_ENDPOINT,
+        create_lnurl_withdraw,
+        is_authenticated,
+        authenticated_routes,
+    );
     let authenticated_routes = register_post_handler(
         handlers,
         PAY_INVOICE_FOR_OPERATOR_ENDPOINT,
@@ -514,6 +656,27 @@ fn routes(gateway: Arc<Gateway>, task_group: TaskGroup, handlers: &mut Handlers)
         is_authenticated,
         authenticated_routes,
     );
+    let authenticated_routes = register_post_handler(
+        handlers,
+        LEDGER_ENDPOINT,
+        ledger,
+        is_authenticated,
+        authenticated_routes,
+    );
+    let authenticated_routes = register_post_handler(
+        handlers,
+        EXPORT_LEDGER_ENDPOINT,
+        export_ledger,
+        is_authenticated,
+        authenticated_routes,
+    );
+    let authenticated_routes = register_post_handler(
+        handlers,
+        REVENUE_ENDPOINT,
+        revenue,
+        is_authenticated,
+        authenticated_routes,
+    );
     let authenticated_routes = register_post_handler(
         handlers,
         SET_FEES_ENDPOINT,
@@ -521,6 +684,90 @@ fn routes(gateway: Arc<Gateway>, task_group: TaskGroup, handlers: &mut Handlers)
         is_authenticated,
         authenticated_routes,
     );
+    let authenticated_routes = register_post_handler(
+        handlers,
+        FEE_SCHEDULE_ENDPOINT,
+        fee_schedule,
+        is_authenticated,
+        authenticated_routes,
+    );
+    let authenticated_routes = register_post_handler(
+        handlers,
+        SET_FEE_SCHEDULE_ENDPOINT,
+        set_fee_schedule,
+        is_authenticated,
+        authenticated_routes,
+    );
+    let authenticated_routes = register_post_handler(
+        handlers,
+        FEE_HISTORY_ENDPOINT,
+        fee_history,
+        is_authenticated,
+        authenticated_routes,
+    );
+    let authenticated_routes = register_get_handler(
+        handlers,
+        RISK_STATUS_ENDPOINT,
+        risk_status,
+        is_authenticated,
+        authenticated_routes,
+    );
+    let authenticated_routes = register_post_handler(
+        handlers,
+        SET_RISK_LIMITS_ENDPOINT,
+        set_risk_limits,
+        is_authenticated,
+        authenticated_routes,
+    );
+    let authenticated_routes = register_get_handler(
+        handlers,
+        API_TOKENS_ENDPOINT,
+        api_tokens,
+        is_authenticated,
+        authenticated_routes,
+    );
+    let authenticated_routes = register_post_handler(
+        handlers,
+        CREATE_API_TOKEN_ENDPOINT,
+        create_api_token,
+        is_authenticated,
+        authenticated_routes,
+    );
+    let authenticated_routes = register_post_handler(
+        handlers,
+        REVOKE_API_TOKEN_ENDPOINT,
+        revoke_api_token,
+        is_authenticated,
+        authenticated_routes,
+    );
+    let authenticated_routes = register_post_handler(
+        handlers,
+        API_TOKEN_AUDIT_LOG_ENDPOINT,
+        api_token_audit_log,
+        is_authenticated,
+        authenticated_routes,
+    );
+    let authenticated_routes = register_get_handler(
+        handlers,
+        GET_LIQUIDITY_POLICY_ENDPOINT,
+        get_liquidity_policy,
+        is_authenticated,
+        authenticated_routes,
+    );
+    let authenticated_routes = register_post_handler(
+        handlers,
+        SET_LIQUIDITY_POLICY_ENDPOINT,
+        set_liquidity_policy,
+        is_authenticated,
+        authenticated_routes,
+    );
+    let authenticated_routes = register_post_handler(
+        handlers,
+        LIQUIDITY_LOG_ENDPOINT,
+        liquidity_log,
+        is_authenticated,
+        authenticated_routes,
+    );
     let authenticated_routes = register_post_handler(
         handlers,
         CONFIGURATION_ENDPOINT,
@@ -703,6 +950,138 @@ async fn set_fees(
     Ok(Json(json!(())))
 }
 
+#[instrument(target = LOG_GATEWAY, skip_all, err, fields(?payload))]
+async fn fee_schedule(
+    Extension(gateway): Extension<Arc<Gateway>>,
+    Json(payload): Json<FeeSchedulePayload>,
+) -> Result<Json<serde_json::Value>, GatewayError> {
+    let schedule = gateway.handle_get_fee_schedule_msg(payload).await?;
+    Ok(Json(json!(schedule)))
+}
+
+#[instrument(target = LOG_GATEWAY, skip_all, err, fields(?payload))]
+async fn set_fee_schedule(
+    Extension(gateway): Extension<Arc<Gateway>>,
+    Json(payload): Json<SetFeeSchedulePayload>,
+) -> Result<Json<serde_json::Value>, GatewayError> {
+    gateway.handle_set_fee_schedule_msg(payload).await?;
+    Ok(Json(json!(())))
+}
+
+#[instrument(target = LOG_GATEWAY, skip_all, err, fields(?payload))]
+async fn fee_history(
+    Extension(gateway): Extension<Arc<Gateway>>,
+    Json(payload): Json<FeeHistoryPayload>,
+) -> Result<Json<serde_json::Value>, GatewayError> {
+    let history = gateway.handle_fee_history_msg(payload).await?;
+    Ok(Json(json!(history)))
+}
+
+#[instrument(target = LOG_GATEWAY, skip_all, err)]
+async fn risk_status(
+    Extension(gateway): Extension<Arc<Gateway>>,
+) -> Result<Json<serde_json::Value>, GatewayError> {
+    let status = gateway.handle_risk_status_msg().await?;
+    Ok(Json(json!(status)))
+}
+
+#[instrument(target = LOG_GATEWAY, skip_all, err, fields(?payload))]
+async fn set_risk_limits(
+    Extension(gateway): Extension<Arc<Gateway>>,
+    Json(payload): Json<SetRiskLimitsPayload>,
+) -> Result<Json<serde_json::Value>, GatewayError> {
+    gateway.handle_set_risk_limits_msg(payload).await?;
+    Ok(Json(json!(())))
+}
+
+#[instrument(target = LOG_GATEWAY, skip_all, err, fields(?payload))]
+async fn ledger(
+    Extension(gateway): Extension<Arc<Gateway>>,
+    Json(payload): Json<LedgerPayload>,
+) -> Result<Json<serde_json::Value>, GatewayError> {
+    let ledger = gateway.handle_ledger_msg(payload).await?;
+    Ok(Json(json!(ledger)))
+}
+
+#[instrument(target = LOG_GATEWAY, skip_all, err, fields(?payload))]
+async fn export_ledger(
+    Extension(gateway): Extension<Arc<Gateway>>,
+    Json(payload): Json<ExportLedgerPayload>,
+) -> Result<Json<serde_json::Value>, GatewayError> {
+    let export = gateway.handle_export_ledger_msg(payload).await?;
+    Ok(Json(json!(export)))
+}
+
+#[instrument(target = LOG_GATEWAY, skip_all, err, fields(?payload))]
+async fn revenue(
+    Extension(gateway): Extension<Arc<Gateway>>,
+    Json(payload): Json<RevenuePayload>,
+) -> Result<Json<serde_json::Value>, GatewayError> {
+    let revenue = gateway.handle_revenue_msg(payload).await?;
+    Ok(Json(json!(revenue)))
+}
+
+#[instrument(target = LOG_GATEWAY, skip_all, err)]
+async fn api_tokens(
+    Extension(gateway): Extension<Arc<Gateway>>,
+) -> Result<Json<serde_json::Value>, GatewayError> {
+    let tokens = gateway.handle_list_api_tokens_msg().await?;
+    Ok(Json(json!(tokens)))
+}
+
+#[instrument(target = LOG_GATEWAY, skip_all, err, fields(?payload))]
+async fn create_api_token(
+    Extension(gateway): Extension<Arc<Gateway>>,
+    Json(payload): Json<CreateApiTokenPayload>,
+) -> Result<Json<serde_json::Value>, GatewayError> {
+    let response = gateway.handle_create_api_token_msg(payload).await?;
+    Ok(Json(json!(response)))
+}
+
+#[instrument(target = LOG_GATEWAY, skip_all, err, fields(?payload))]
+async fn revoke_api_token(
+    Extension(gateway): Extension<Arc<Gateway>>,
+    Json(payload): Json<RevokeApiTokenPayload>,
+) -> Result<Json<serde_json::Value>, GatewayError> {
+    gateway.handle_revoke_api_token_msg(payload).await?;
+    Ok(Json(json!(())))
+}
+
+#[instrument(target = LOG_GATEWAY, skip_all, err, fields(?payload))]
+async fn api_token_audit_log(
+    Extension(gateway): Extension<Arc<Gateway>>,
+    Json(payload): Json<ApiTokenAuditLogPayload>,
+) -> Result<Json<serde_json::Value>, GatewayError> {
+    let log = gateway.handle_api_token_audit_log_msg(payload).await?;
+    Ok(Json(json!(log)))
+}
+
+#[instrument(target = LOG_GATEWAY, skip_all, err)]
+async fn get_liquidity_policy(
+    Extension(gateway): Extension<Arc<Gateway>>,
+) -> Result<Json<serde_json::Value>, GatewayError> {
+    let policy = gateway.handle_get_liquidity_policy_msg().await?;
+    Ok(Json(json!(policy)))
+}
+
+#[instrument(target = LOG_GATEWAY, skip_all, err, fields(?payload))]
+async fn set_liquidity_policy(
+    Extension(gateway): Extension<Arc<Gateway>>,
+    Json(payload): Json<SetLiquidityPolicyPayload>,
+) -> Result<Json<serde_json::Value>, GatewayError> {
+    gateway.handle_set_liquidity_policy_msg(payload).await?;
+    Ok(Json(json!(())))
+}
+
+#[instrument(target = LOG_GATEWAY, skip_all, err, fields(?payload))]
+async fn liquidity_log(
+    Extension(gateway): Extension<Arc<Gateway>>,
+    Json(payload): Json<LiquidityLogPayload>,
+) -> Result<Json<serde_json::Value>, GatewayError> {
+    let log = gateway.handle_liquidity_log_msg(payload).await?;
+    Ok(Json(json!(log)))
+}
+
 #[instrument(target = LOG_GATEWAY, skip_all, err)]
 async fn get_ln_onchain_address(
     Extension(gateway): Extension<Arc<Gateway>>,
@@ -807,6 +1186,22 @@ async fn routing_info_v2(
     Ok(Json(json!(routing_info)))
 }
 
+async fn supports_keysend_v2(
+    Extension(gateway): Extension<Arc<Gateway>>,
+    Json(federation_id): Json<FederationId>,
+) -> Result<Json<serde_json::Value>, GatewayError> {
+    let supported = gateway.supports_keysend_v2(&federation_id).await?;
+    Ok(Json(json!(supported)))
+}
+
+async fn supports_multi_part_v2(
+    Extension(gateway): Extension<Arc<Gateway>>,
+    Json(federation_id): Json<FederationId>,
+) -> Result<Json<serde_json::Value>, GatewayError> {
+    let supported = gateway.supports_multi_part_v2(&federation_id).await?;
+    Ok(Json(json!(supported)))
+}
+
 #[instrument(target = LOG_GATEWAY, skip_all, err)]
 async fn pay_bolt11_invoice_v2(
     Extension(gateway): Extension<Arc<Gateway>>,
@@ -816,6 +1211,14 @@ async fn pay_bolt11_invoice_v2(
     Ok(Json(json!(payment_result)))
 }
 
+async fn probe_payment_v2(
+    Extension(gateway): Extension<Arc<Gateway>>,
+    Json(payload): Json<ProbePaymentPayload>,
+) -> Result<Json<serde_json::Value>, GatewayError> {
+    let preview = gateway.probe_payment_v2(payload).await?;
+    Ok(Json(json!(preview)))
+}
+
 #[instrument(target = LOG_GATEWAY, skip_all, err)]
 async fn create_bolt11_invoice_v2(
     Extension(gateway): Extension<Arc<Gateway>>,
@@ -825,6 +1228,116 @@ async fn create_bolt11_invoice_v2(
     Ok(Json(json!(invoice)))
 }
 
+#[instrument(target = LOG_GATEWAY, skip_all, err)]
+async fn create_hold_bolt11_invoice_v2(
+    Extension(gateway): Extension<Arc<Gateway>>,
+    Json(payload): Json<CreateHoldBolt11InvoicePayload>,
+) -> Result<Json<serde_json::Value>, GatewayError> {
+    let invoice = gateway.create_hold_bolt11_invoice_v2(payload).await?;
+    Ok(Json(json!(invoice)))
+}
+
+async fn hold_invoice_status_v2(
+    Extension(gateway): Extension<Arc<Gateway>>,
+    Json(payload): Json<HoldInvoiceStatusPayload>,
+) -> Result<Json<serde_json::Value>, GatewayError> {
+    let status = gateway.hold_invoice_status_v2(payload).await?;
+    Ok(Json(json!(status)))
+}
+
+#[instrument(target = LOG_GATEWAY, skip_all, err)]
+async fn resolve_hold_invoice_v2(
+    Extension(gateway): Extension<Arc<Gateway>>,
+    Json(payload): Json<ResolveHoldInvoicePayload>,
+) -> Result<Json<serde_json::Value>, GatewayError> {
+    gateway.resolve_hold_invoice_v2(payload).await?;
+    Ok(Json(json!(())))
+}
+
+async fn register_static_lnurl_contracts_v2(
+    Extension(gateway): Extension<Arc<Gateway>>,
+    Json(payload): Json<RegisterStaticLnurlContractsPayload>,
+) -> Result<Json<serde_json::Value>, GatewayError> {
+    gateway.register_static_lnurl_contracts_v2(payload).await?;
+    Ok(Json(json!(())))
+}
+
+async fn fetch_bolt12_invoice_v2(
+    Extension(gateway): Extension<Arc<Gateway>>,
+    Json(payload): Json<FetchBolt12InvoicePayload>,
+) -> Result<Json<serde_json::Value>, GatewayError> {
+    let invoice = gateway.fetch_bolt12_invoice_v2(payload).await?;
+    Ok(Json(json!(invoice)))
+}
+
+async fn create_bolt12_offer_v2(
+    Extension(gateway): Extension<Arc<Gateway>>,
+    Json(payload): Json<CreateBolt12OfferPayload>,
+) -> Result<Json<serde_json::Value>, GatewayError> {
+    let offer = gateway.create_bolt12_offer_v2(payload).await?;
+    Ok(Json(json!(offer)))
+}
+
+async fn lnurl_pay_v2_get(
+    Extension(gateway): Extension<Arc<Gateway>>,
+    Path(payload): Path<String>,
+) -> Json<LnurlResponse<PayResponse>> {
+    Json(match gateway.lnurl_pay_v2(&payload).await {
+        Ok(response) => LnurlResponse::Ok(response),
+        Err(e) => LnurlResponse::error(e.to_string()),
+    })
+}
+
+#[derive(Debug, Deserialize)]
+struct LnurlInvoiceQuery {
+    amount: u64,
+}
+
+async fn lnurl_invoice_v2_get(
+    Extension(gateway): Extension<Arc<Gateway>>,
+    Path(payload): Path<String>,
+    Query(query): Query<LnurlInvoiceQuery>,
+) -> Json<LnurlResponse<InvoiceResponse>> {
+    Json(
+        match gateway.lnurl_invoice_v2(&payload, query.amount).await {
+            Ok(response) => LnurlResponse::Ok(response),
+            Err(e) => LnurlResponse::error(e.to_string()),
+        },
+    )
+}
+
+async fn lnurl_withdraw_get(
+    Extension(gateway): Extension<Arc<Gateway>>,
+    Path(link_id): Path<String>,
+) -> Json<LnurlResponse<fedimint_lnurl::WithdrawResponse>> {
+    Json(match gateway.lnurl_withdraw(&link_id).await {
+        Ok(response) => LnurlResponse::Ok(response),
+        Err(e) => LnurlResponse::error(e),
+    })
+}
+
+#[derive(Debug, Deserialize)]
+struct LnurlWithdrawCallbackQuery {
+    k1: String,
+    pr: String,
+}
+
+async fn lnurl_withdraw_callback_get(
+    Extension(gateway): Extension<Arc<Gateway>>,
+    Path(link_id): Path<String>,
+    Query(query): Query<LnurlWithdrawCallbackQuery>,
+) -> Json<StatusResponse> {
+    Json(
+        match gateway
+            .lnurl_withdraw_callback(&link_id, &query.k1, &query.pr)
+            .await
+        {
+            Ok(()) => StatusResponse::ok(),
+            Err(e) => StatusResponse::error(e),
+        },
+    )
+}
+
 pub(crate) async fn verify_bolt11_preimage_v2_get(
     Extension(gateway): Extension<Arc<Gateway>>,
     Path(payment_hash): Path<sha256::Hash>,
@@ -856,6 +1369,33 @@ async fn receive_ecash(
     )))
 }
 
+#[instrument(target = LOG_GATEWAY, skip_all, err)]
+async fn lsp_info(
+    Extension(gateway): Extension<Arc<Gateway>>,
+) -> Result<Json<serde_json::Value>, GatewayError> {
+    Ok(Json(json!(gateway.handle_lsp_info_msg().await?)))
+}
+
+#[instrument(target = LOG_GATEWAY, skip_all, err)]
+async fn order_channel(
+    Extension(gateway): Extension<Arc<Gateway>>,
+    Json(payload): Json<ChannelOrderPayload>,
+) -> Result<Json<serde_json::Value>, GatewayError> {
+    Ok(Json(json!(
+        gateway.handle_order_channel_msg(payload).await?
+    )))
+}
+
+#[instrument(target = LOG_GATEWAY, skip_all, err)]
+async fn channel_order_status(
+    Extension(gateway): Extension<Arc<Gateway>>,
+    Json(payload): Json<ChannelOrderStatusPayload>,
+) -> Result<Json<serde_json::Value>, GatewayError> {
+    Ok(Json(json!(
+        gateway.handle_channel_order_status_msg(payload).await?
+    )))
+}
+
 #[instrument(target = LOG_GATEWAY, skip_all, err)]
 async fn mnemonic(
     Extension(gateway): Extension<Arc<Gateway>>,
@@ -935,6 +1475,15 @@ async fn create_offer_for_operator(
     Ok(Json(json!(offer)))
 }
 
+#[instrument(target = LOG_GATEWAY, skip_all, err)]
+async fn create_lnurl_withdraw(
+    Extension(gateway): Extension<Arc<Gateway>>,
+    Json(payload): Json<CreateLnurlWithdrawPayload>,
+) -> Result<Json<serde_json::Value>, GatewayError> {
+    let response = gateway.handle_create_lnurl_withdraw_msg(payload).await?;
+    Ok(Json(json!(response)))
+}
+
 #[instrument(target = LOG_GATEWAY, skip_all, err)]
 async fn pay_offer_operator(
     Extension(gateway): Extension<Arc<Gateway>>,
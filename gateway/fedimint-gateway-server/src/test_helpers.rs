@@ -0,0 +1,48 @@
+//! Fixtures shared by the unit tests of the gateway's modules.
+
+use fedimint_core::Amount;
+use fedimint_core::config::FederationId;
+use fedimint_eventlog::{Event, PersistedLogEntry};
+use fedimint_gateway_common::{FederationBalanceInfo, GatewayBalances};
+use serde_json::json;
+
+/// Returns a federation id consisting of the repeated `byte`.
+pub fn federation(byte: u8) -> FederationId {
+    format!("{byte:02x}")
+        .repeat(32)
+        .parse()
+        .expect("valid federation id")
+}
+
+/// Returns the balances of a gateway whose node has no channels.
+pub fn balances(
+    onchain_balance_sats: u64,
+    ecash_balances: &[(FederationId, Amount)],
+) -> GatewayBalances {
+    GatewayBalances {
+        onchain_balance_sats,
+        lightning_balance_msats: 0,
+        ecash_balances: ecash_balances
+            .iter()
+            .map(
+                |(federation_id, ecash_balance_msats)| FederationBalanceInfo {
+                    federation_id: *federation_id,
+                    ecash_balance_msats: *ecash_balance_msats,
+                },
+            )
+            .collect(),
+        inbound_lightning_liquidity_msats: 0,
+    }
+}
+
+/// Returns the entry `id` of an event log, which holds `event`.
+pub fn log_entry<E: Event>(id: u64, ts_usecs: u64, event: &E) -> PersistedLogEntry {
+    serde_json::from_value(json!({
+        "id": id,
+        "kind": E::KIND,
+        "module": E::MODULE.map(|kind| json!({ "kind": kind, "id": 0 })),
+        "ts_usecs": ts_usecs,
+        "payload": event,
+    }))
+    .expect("valid log entry")
+}
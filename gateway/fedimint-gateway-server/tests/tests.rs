@@ -2,6 +2,7 @@
 //!
 //! This crate contains integration tests for the gateway API
 //! and business logic.
+use std::collections::BTreeMap;
 use std::sync::Arc;
 use std::time::Duration;
 
@@ -16,7 +17,9 @@
 use fedimint_client_module::module::OutPointRange;
 use fedimint_core::config::FederationId;
 use fedimint_core::core::{IntoDynInstance, OperationId};
+use fedimint_core::db::Database;
 use fedimint_core::encoding::Encodable;
+use fedimint_core::module::registry::ModuleDecoderRegistry;
 use fedimint_core::module::{AmountUnit, Amounts};
 use fedimint_core::task::{TaskGroup, sleep_in_test, timeout};
 use fedimint_core::time::now;
@@ -27,8 +30,14 @@
 use fedimint_dummy_client::{DummyClientInit, DummyClientModule};
 use fedimint_dummy_server::DummyInit;
 use fedimint_eventlog::Event;
-use fedimint_gateway_common::{PaymentLogPayload, SetFeesPayload};
-use fedimint_gateway_server::{Gateway, GatewayState};
+use fedimint_gateway_common::{
+    CreateLnurlWithdrawPayload, LiquidityAction, LiquidityLogPayload, LiquidityOutcome,
+    LiquidityPolicy, LiquidityRange, PaymentLogPayload, SetFeesPayload, SetLiquidityPolicyPayload,
+};
+use fedimint_gateway_server::config::{DatabaseBackend, RestoreOpts};
+use fedimint_gateway_server::{
+    BundleDestination, DB_FILE, DisasterRecoveryParameters, Gateway, GatewayState,
+};
 use fedimint_gateway_ui::IAdminGateway;
 use fedimint_gw_client::pay::{
     OutgoingContractError, OutgoingPaymentError, OutgoingPaymentErrorType,
@@ -59,11 +68,12 @@
 };
 use fedimint_ln_common::{LightningGateway, LightningInput, LightningOutput, PrunedInvoice};
 use fedimint_ln_server::LightningInit;
-use fedimint_lnv2_common::LightningInvoice;
 use fedimint_lnv2_common::contracts::{IncomingContract, OutgoingContract, PaymentImage};
 use fedimint_lnv2_common::gateway_api::{
-    GatewayConnection, PaymentFee, RoutingInfo, SendPaymentPayload,
+    GatewayConnection, HoldInvoiceStatus, PaymentFee, RegisterStaticLnurlContractsPayload,
+    RoutePreview, RoutingInfo, SendPaymentPayload,
 };
+use fedimint_lnv2_common::{Bolt12OfferInvoice, ContractId, LightningInvoice};
 use fedimint_logging::LOG_TEST;
 use fedimint_testing::btc::BitcoinTest;
 use fedimint_testing::db::BYTE_33;
@@ -1943,6 +1953,7 @@ fn publish(&self, module_public_key: PublicKey) {
             expiration_delta_default: 500,
             expiration_delta_minimum: 144,
             receive_fee: PaymentFee::TRANSACTION_FEE_DEFAULT,
+            send_fee_tiers: Vec::new(),
         });
     }
 
@@ -1985,6 +1996,39 @@ async fn bolt11_invoice(
         unimplemented!("This connection only sends")
     }
 
+    async fn hold_bolt11_invoice(
+        &self,
+        _gateway_api: SafeUrl,
+        _federation_id: FederationId,
+        _contract: IncomingContract,
+        _amount: Amount,
+        _description: fedimint_lnv2_common::Bolt11InvoiceDescription,
+        _expiry_secs: u32,
+        _hold_secs: u32,
+    ) -> Result<Bolt11Invoice, ServerError> {
+        unimplemented!("This connection only sends")
+    }
+
+    async fn hold_invoice_status(
+        &self,
+        _gateway_api: SafeUrl,
+        _federation_id: FederationId,
+        _contract_id: ContractId,
+    ) -> Result<HoldInvoiceStatus, ServerError> {
+        unimplemented!("This connection only sends")
+    }
+
+    async fn resolve_hold_invoice(
+        &self,
+        _gateway_api: SafeUrl,
+        _federation_id: FederationId,
+        _contract_id: ContractId,
+        _settle: bool,
+        _signature: Signature,
+    ) -> Result<(), ServerError> {
+        unimplemented!("This connection only sends")
+    }
+
     async fn send_payment(
         &self,
         _gateway_api: SafeUrl,
@@ -2006,6 +2050,60 @@ async fn send_payment(
         // refund the contract out from under the test.
         std::future::pending().await
     }
+
+    async fn bolt12_invoice(
+        &self,
+        _gateway_api: SafeUrl,
+        _federation_id: FederationId,
+        _offer: String,
+        _amount: Option<Amount>,
+        _payer_note: Option<String>,
+    ) -> Result<Bolt12OfferInvoice, ServerError> {
+        unimplemented!("This connection only sends")
+    }
+
+    async fn bolt12_offer(
+        &self,
+        _gateway_api: SafeUrl,
+        _federation_id: FederationId,
+        _recipient_pk: PublicKey,
+        _description: Option<String>,
+    ) -> Result<String, ServerError> {
+        unimplemented!("This connection only sends")
+    }
+
+    async fn register_static_lnurl_contracts(
+        &self,
+        _gateway_api: SafeUrl,
+        _payload: RegisterStaticLnurlContractsPayload,
+    ) -> Result<(), ServerError> {
+        unimplemented!("This connection only sends")
+    }
+
+    async fn probe_payment(
+        &self,
+        _gateway_api: SafeUrl,
+        _federation_id: FederationId,
+        _invoice: Bolt11Invoice,
+    ) -> Result<Option<RoutePreview>, ServerError> {
+        unimplemented!("This connection only sends")
+    }
+
+    async fn supports_keysend(
+        &self,
+        _gateway_api: SafeUrl,
+        _federation_id: &FederationId,
+    ) -> Result<bool, ServerError> {
+        unimplemented!("This connection only sends")
+    }
+
+    async fn supports_multi_part(
+        &self,
+        _gateway_api: SafeUrl,
+        _federation_id: &FederationId,
+    ) -> Result<bool, ServerError> {
+        unimplemented!("This connection only sends")
+    }
 }
 
 fn capturing_lnv2_fixtures(gateway_conn: Arc<CapturingGatewayConnection>) -> Fixtures {
@@ -2504,3 +2602,191 @@ async fn test_gateway_client_direct_swap_reentry_joins_the_funded_swap() -> anyh
     })
     .await
 }
+
+/// Returns the id of the withdraw link encoded in an LNURL.
+fn lnurl_withdraw_link_id(lnurl: &str) -> String {
+    fedimint_lnurl::parse_lnurl(lnurl)
+        .expect("Valid lnurl")
+        .rsplit('/')
+        .next()
+        .expect("Url has a path")
+        .to_string()
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn lnurl_withdraw_restores_the_use_only_if_the_payment_failed() -> anyhow::Result<()> {
+    let fixtures = fixtures();
+    let gateway = fixtures.new_gateway().await;
+    let other_ln = FakeLightningTest::new();
+
+    let lnurl = gateway
+        .handle_create_lnurl_withdraw_msg(CreateLnurlWithdrawPayload {
+            min_withdrawable: sats(1),
+            max_withdrawable: sats(1_000),
+            uses: 1,
+            description: "test withdraw".to_string(),
+            federation_id: None,
+        })
+        .await?
+        .lnurl;
+    let link_id = lnurl_withdraw_link_id(&lnurl);
+
+    // The payment of the first invoice fails, hence the use is restored once
+    // the lightning node reports the payment as failed.
+    let unpayable_invoice = other_ln.unpayable_invoice(sats(500), None);
+    gateway
+        .lnurl_withdraw_callback(&link_id, &link_id, &unpayable_invoice.to_string())
+        .await
+        .map_err(|e| anyhow::anyhow!(e))?;
+
+    retry(
+        "Waiting for the use of the link to be restored",
+        backoff_util::aggressive_backoff(),
+        || async {
+            gateway
+                .lnurl_withdraw(&link_id)
+                .await
+                .map_err(|e| anyhow::anyhow!(e))
+        },
+    )
+    .await?;
+
+    // Of two concurrent callbacks only one may consume the single use.
+    let invoice = other_ln.invoice(sats(500), None)?.to_string();
+    let (first, second) = tokio::join!(
+        gateway.lnurl_withdraw_callback(&link_id, &link_id, &invoice),
+        gateway.lnurl_withdraw_callback(&link_id, &link_id, &invoice),
+    );
+    assert_eq!(
+        [first.is_ok(), second.is_ok()]
+            .into_iter()
+            .filter(|ok| *ok)
+            .count(),
+        1,
+        "Only one callback may consume the last use of the link"
+    );
+
+    // The successful payment does not restore the use.
+    sleep_in_test(
+        "waiting for the payment to complete",
+        Duration::from_secs(1),
+    )
+    .await;
+    assert!(gateway.lnurl_withdraw(&link_id).await.is_err());
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn liquidity_manager_records_its_actions_and_respects_the_cooldown() -> anyhow::Result<()> {
+    let fixtures = fixtures();
+    let gateway = fixtures.new_gateway().await;
+
+    // The mock node's outbound capacity exceeds the range, hence the liquidity
+    // manager proposes a swap out.
+    gateway
+        .handle_set_liquidity_policy_msg(SetLiquidityPolicyPayload {
+            policy: Some(LiquidityPolicy {
+                dry_run: false,
+                federations: BTreeMap::new(),
+                outbound: Some(LiquidityRange {
+                    min: sats(100),
+                    max: sats(1_000),
+                }),
+                min_inbound: None,
+                onchain_reserve_sats: 0,
+                pegin_fee_rate_sats_per_vbyte: 1,
+                cooldown_secs: 3600,
+            }),
+        })
+        .await?;
+
+    gateway.manage_liquidity().await?;
+
+    let events = retry(
+        "Waiting for the liquidity action to be logged",
+        backoff_util::aggressive_backoff(),
+        || async {
+            let events = gateway
+                .handle_liquidity_log_msg(LiquidityLogPayload { limit: 10 })
+                .await?
+                .0;
+            anyhow::ensure!(!events.is_empty(), "No liquidity action logged yet");
+            Ok(events)
+        },
+    )
+    .await?;
+
+    assert_eq!(events.len(), 1);
+    assert_matches!(events[0].action, LiquidityAction::SwapOutProposal { .. });
+    assert_eq!(events[0].outcome, LiquidityOutcome::Proposed);
+
+    // The node's channels are cooling down, so the second check takes no action.
+    gateway.manage_liquidity().await?;
+
+    sleep_in_test("waiting for the event log", Duration::from_secs(1)).await;
+
+    assert_eq!(
+        gateway
+            .handle_liquidity_log_msg(LiquidityLogPayload { limit: 10 })
+            .await?
+            .0
+            .len(),
+        1
+    );
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn restored_gateway_starts_with_the_federations_of_its_bundle() -> anyhow::Result<()> {
+    const PASSWORD: &str = "correct horse battery staple";
+
+    let fixtures = fixtures();
+    let fed = fixtures.new_fed_degraded().await;
+    let gateway = fixtures.new_gateway().await;
+    fed.connect_gateway(&gateway).await;
+
+    let backup_dir = tempfile::tempdir()?;
+    gateway
+        .export_dr_bundle(&DisasterRecoveryParameters {
+            destination: BundleDestination::Directory(backup_dir.path().to_owned()),
+            password: PASSWORD.to_string(),
+            interval: Duration::from_secs(3600),
+            retention: 1,
+        })
+        .await?;
+
+    let bundles = std::fs::read_dir(backup_dir.path())?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<Vec<_>, _>>()?;
+    assert_eq!(bundles.len(), 1);
+
+    let data_dir = tempfile::tempdir()?;
+    RestoreOpts {
+        bundle: bundles[0].clone(),
+        data_dir: data_dir.path().to_owned(),
+        password: PASSWORD.to_string(),
+        db_backend: DatabaseBackend::RocksDb,
+    }
+    .run()
+    .await?;
+
+    let restored_db = Database::new(
+        fedimint_rocksdb::RocksDb::build(data_dir.path().join(DB_FILE))
+            .open()
+            .await?,
+        ModuleDecoderRegistry::default(),
+    );
+    let restored = fixtures.new_gateway_with_db(restored_db).await;
+    restored.load_clients().await?;
+
+    assert_eq!(
+        restored.http_gateway_id().await,
+        gateway.http_gateway_id().await
+    );
+    let client = restored.select_client(fed.id()).await?.into_value();
+    assert_eq!(client.federation_id(), fed.id());
+
+    Ok(())
+}
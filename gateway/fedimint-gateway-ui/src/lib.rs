@@ -34,8 +34,8 @@
     LightningMode, ListTransactionsPayload, ListTransactionsResponse, MnemonicResponse,
     OpenChannelRequest, PayInvoiceForOperatorPayload, PayOfferPayload, PayOfferResponse,
     PaymentLogPayload, PaymentLogResponse, PaymentSummaryPayload, PaymentSummaryResponse,
-    ReceiveEcashPayload, ReceiveEcashResponse, SendOnchainRequest, SetFeesPayload,
-    SetMnemonicPayload, SpendEcashPayload, SpendEcashResponse, WithdrawPayload,
+    ReceiveEcashPayload, ReceiveEcashResponse, RevenuePayload, RevenueResponse, SendOnchainRequest,
+    SetFeesPayload, SetMnemonicPayload, SpendEcashPayload, SpendEcashResponse, WithdrawPayload,
     WithdrawPreviewPayload, WithdrawPreviewResponse, WithdrawResponse,
 };
 use fedimint_ln_common::contracts::Preimage;
@@ -266,6 +266,10 @@ async fn handle_get_note_summary_msg(
         &self,
         federation_id: &FederationId,
     ) -> Result<TieredCounts, Self::Error>;
+    async fn handle_revenue_msg(
+        &self,
+        payload: RevenuePayload,
+    ) -> Result<RevenueResponse, Self::Error>;
 }
 
 async fn login_form_handler<E>(
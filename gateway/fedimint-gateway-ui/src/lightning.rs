@@ -230,6 +230,43 @@ pub async fn render<E>(gateway_info: &GatewayInfo, api: &DynGatewayApi<E>) -> Ma
                                     }
                                 }
                             }
+                            LightningMode::Cln { cln_rpc_path } => {
+                                div id="node-type" class="alert alert-info" {
+                                    "Node Type: " strong { "External Core Lightning" }
+                                }
+                                table class="table table-sm mb-0" {
+                                    tbody {
+                                        tr {
+                                            th { "RPC Path" }
+                                            td { (cln_rpc_path) }
+                                        }
+                                        tr {
+                                            th { "Network" }
+                                            td { (network) }
+                                        }
+                                        tr {
+                                            th { "Block Height" }
+                                            td { (block_height) }
+                                        }
+                                        tr {
+                                            th { "Status" }
+                                            td { (status_badge) }
+                                        }
+                                        @if let Some(a) = alias {
+                                            tr {
+                                                th { "Alias" }
+                                                td { (a) }
+                                            }
+                                        }
+                                        @if let Some(pk) = pubkey {
+                                            tr {
+                                                th { "Public Key" }
+                                                td { (pk) }
+                                            }
+                                        }
+                                    }
+                                }
+                            }
                             LightningMode::Ldk { lightning_port, .. } => {
                                 div id="node-type" class="alert alert-info" {
                                     "Node Type: " strong { "Internal LDK" }
@@ -267,6 +304,43 @@ pub async fn render<E>(gateway_info: &GatewayInfo, api: &DynGatewayApi<E>) -> Ma
                                     }
                                 }
                             }
+                            LightningMode::Simulated { simulated_network } => {
+                                div id="node-type" class="alert alert-warning" {
+                                    "Node Type: " strong { "Simulated (testing only)" }
+                                }
+                                table class="table table-sm mb-0" {
+                                    tbody {
+                                        tr {
+                                            th { "Network Config" }
+                                            td { (simulated_network.as_deref().unwrap_or("default")) }
+                                        }
+                                        tr {
+                                            th { "Network" }
+                                            td { (network) }
+                                        }
+                                        tr {
+                                            th { "Block Height" }
+                                            td { (block_height) }
+                                        }
+                                        tr {
+                                            th { "Status" }
+                                            td { (status_badge) }
+                                        }
+                                        @if let Some(a) = alias {
+                                            tr {
+                                                th { "Alias" }
+                                                td { (a) }
+                                            }
+                                        }
+                                        @if let Some(pk) = pubkey {
+                                            tr {
+                                                th { "Public Key" }
+                                                td { (pk) }
+                                            }
+                                        }
+                                    }
+                                }
+                            }
                         }
 
                         div class="mt-3 pt-3 border-top" {
@@ -8,7 +8,7 @@
 use fedimint_eventlog::{Event, EventKind, EventLogId};
 use fedimint_gateway_common::{
     FederationInfo, PaymentLogPayload, PaymentLogResponse, PaymentStats, PaymentSummaryPayload,
-    PaymentSummaryResponse,
+    PaymentSummaryResponse, RevenueInterval, RevenuePayload, RevenueResponse,
 };
 use fedimint_gwv2_client::events::{
     CompleteLightningPaymentSucceeded, IncomingPaymentFailed, IncomingPaymentStarted,
@@ -50,6 +50,11 @@
     ("Notes Reissued", OOBNotesReissued::KIND),
 ];
 
+/// Number of days the revenue tab of the dashboard covers
+const REVENUE_DAYS: u64 = 7;
+
+const MILLIS_PER_DAY: u64 = 24 * 60 * 60 * 1000;
+
 /// Query parameters for the payment log handler
 /// Note: event_kinds is parsed separately from the raw query string
 /// because serde_urlencoded doesn't handle repeated params well
@@ -85,11 +90,21 @@ pub async fn render<E>(api: &DynGatewayApi<E>, federations: &[FederationInfo]) -
         })
         .await;
 
-    render_tabs(payment_summary, federations)
+    let revenue = api
+        .handle_revenue_msg(RevenuePayload {
+            start_millis: now_millis.saturating_sub(REVENUE_DAYS * MILLIS_PER_DAY),
+            end_millis: now_millis,
+            interval: RevenueInterval::Day,
+            federation_id: None,
+        })
+        .await;
+
+    render_tabs(payment_summary, revenue, federations)
 }
 
 fn render_tabs(
     summary: Result<PaymentSummaryResponse, impl std::fmt::Display>,
+    revenue: Result<RevenueResponse, impl std::fmt::Display>,
     federations: &[FederationInfo],
 ) -> Markup {
     html! {
@@ -106,6 +121,16 @@ fn render_tabs(
                             "Summary"
                         }
                     }
+                    li class="nav-item flex-fill text-center" {
+                        button
+                            class="nav-link w-100"
+                            data-bs-toggle="tab"
+                            data-bs-target="#revenue"
+                            type="button"
+                        {
+                            "Revenue"
+                        }
+                    }
                     li class="nav-item flex-fill text-center" {
                         button
                             class="nav-link w-100"
@@ -127,6 +152,13 @@ fn render_tabs(
                     (render_summary_tab(summary))
                 }
 
+                div
+                    class="tab-pane fade"
+                    id="revenue"
+                {
+                    (render_revenue_tab(revenue))
+                }
+
                 div
                     class="tab-pane fade"
                     id="payment-log"
@@ -168,6 +200,69 @@ fn render_summary_body(summary: &PaymentSummaryResponse) -> Markup {
     }
 }
 
+fn render_revenue_tab(revenue: Result<RevenueResponse, impl std::fmt::Display>) -> Markup {
+    match revenue {
+        Ok(revenue) => render_revenue_body(&revenue),
+        Err(e) => html! {
+            div class="alert alert-danger mb-0" {
+                strong { "Failed to load revenue: " }
+                (e.to_string())
+            }
+        },
+    }
+}
+
+fn render_revenue_body(revenue: &RevenueResponse) -> Markup {
+    let total_net_msats = revenue
+        .0
+        .iter()
+        .map(|bucket| bucket.net_revenue_msats)
+        .sum::<i64>();
+
+    html! {
+        div {
+            h5 class="mb-3" { (format!("Revenue (Last {REVENUE_DAYS} Days)")) }
+
+            table class="table table-sm mb-0" {
+                thead {
+                    tr {
+                        th { "Day" }
+                        th class="text-end" { "Fees Earned" }
+                        th class="text-end" { "Fee Losses" }
+                        th class="text-end" { "Routing Cost" }
+                        th class="text-end" { "Net" }
+                    }
+                }
+                tbody {
+                    @for bucket in revenue.0.iter().rev() {
+                        tr {
+                            td { (format_date(bucket.start_millis)) }
+                            td class="text-end text-success" {
+                                (format!("{} msats", bucket.fees_earned.msats))
+                            }
+                            td class="text-end text-danger" {
+                                (format!("{} msats", bucket.fee_losses.msats))
+                            }
+                            td class="text-end text-danger" {
+                                (format!("{} msats", bucket.routing_cost.msats))
+                            }
+                            td class="text-end" {
+                                (format!("{} msats", bucket.net_revenue_msats))
+                            }
+                        }
+                    }
+                }
+                tfoot {
+                    tr {
+                        th colspan="4" { "Total" }
+                        th class="text-end" { (format!("{total_net_msats} msats")) }
+                    }
+                }
+            }
+        }
+    }
+}
+
 fn render_stats_table(title: &str, stats: &PaymentStats, title_class: &str) -> Markup {
     html! {
         div {
@@ -515,6 +610,12 @@ fn render_payment_log_result<E>(
     }
 }
 
+fn format_date(ts_millis: u64) -> String {
+    let dt: chrono::DateTime<chrono::Utc> = (UNIX_EPOCH + Duration::from_millis(ts_millis)).into();
+
+    dt.format("%Y-%m-%d").to_string()
+}
+
 fn format_timestamp(ts_usecs: u64) -> String {
     let secs = ts_usecs / 1_000_000;
     let nanos = (ts_usecs % 1_000_000) * 1_000;
@@ -0,0 +1,1805 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::{Read as _, Write as _};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, UNIX_EPOCH};
+
+use anyhow::{Context as _, bail};
+use async_trait::async_trait;
+use bitcoin::hashes::{Hash, sha256};
+use bitcoin::{Network, OutPoint, Txid};
+use fedimint_core::secp256k1::{PublicKey, SECP256K1, SecretKey};
+use fedimint_core::task::{TaskGroup, sleep};
+use fedimint_core::time::duration_since_epoch;
+use fedimint_core::util::FmtCompact as _;
+use fedimint_core::{Amount, BitcoinAmountOrAll};
+use fedimint_gateway_common::{
+    ConnectPeerRequest, ListTransactionsResponse, PaymentDetails, PaymentDirection, PaymentKind,
+    PaymentStatus,
+};
+use fedimint_ln_common::contracts::Preimage;
+use fedimint_ln_common::route_hints::{RouteHint, RouteHintHop};
+use fedimint_lnv2_common::KEYSEND_RECORD_TYPE;
+use fedimint_logging::LOG_LIGHTNING;
+use lightning::util::ser::{BigSize, Writeable as _};
+use lightning_invoice::{Bolt11Invoice, Currency, InvoiceBuilder, PaymentSecret};
+use rand::rngs::OsRng;
+use serde::Deserialize;
+use serde::de::DeserializeOwned;
+use serde_json::{Value, json};
+use tokio::io::{AsyncReadExt as _, AsyncWriteExt as _};
+use tokio::net::UnixStream;
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use tracing::{debug, info, warn};
+
+use super::{ChannelInfo, ILnRpcClient, LightningRpcError, ListChannelsResponse, RouteHtlcStream};
+use crate::{
+    CloseChannelsWithPeerRequest, CloseChannelsWithPeerResponse, CreateInvoiceRequest,
+    CreateInvoiceResponse, FetchOfferInvoiceResponse, GetBalancesResponse, GetInvoiceRequest,
+    GetInvoiceResponse, GetLnOnchainAddressResponse, GetNodeInfoResponse, GetRouteHintsResponse,
+    InterceptPaymentRequest, InterceptPaymentResponse, InvoiceDescription, OfferPayment,
+    OpenChannelResponse, PayInvoiceResponse, ProbeResponse, SendOnchainRequest,
+    SendOnchainResponse, SetChannelFeesRequest,
+};
+
+/// RPC method registered by `fedimint-cln-plugin` that returns the HTLCs it
+/// intercepted since the last call as a list of [`InterceptPaymentRequest`],
+/// waiting up to `timeout_secs` for one to arrive if there are none yet. If
+/// `resubscribe` is set, the HTLCs returned before but not resolved yet are
+/// returned again.
+pub const CLN_PLUGIN_NEXT_HTLCS_METHOD: &str = "fedimint-next-htlcs";
+
+/// RPC method registered by `fedimint-cln-plugin` that resolves an intercepted
+/// HTLC as described by an [`InterceptPaymentResponse`].
+pub const CLN_PLUGIN_COMPLETE_HTLC_METHOD: &str = "fedimint-complete-htlc";
+
+/// How long a single call to [`CLN_PLUGIN_NEXT_HTLCS_METHOD`] waits for an HTLC
+/// before returning an empty list, such that the gateway notices when
+/// `lightningd` went away.
+const NEXT_HTLCS_TIMEOUT_SECS: u64 = 30;
+
+/// The minimum final CLTV delta of the invoices the gateway creates itself,
+/// matching the `cltv-final` default of `lightningd`.
+const MIN_FINAL_CLTV_EXPIRY_DELTA: u64 = 18;
+
+/// How long `lightningd` keeps retrying a payment before giving up.
+const PAYMENT_RETRY_FOR_SECS: u64 = 60;
+
+/// The channel states in which a channel is open from the perspective of the
+/// gateway, that is neither still being negotiated nor closing.
+const OPEN_CHANNEL_STATES: [&str; 2] = ["CHANNELD_AWAITING_LOCKIN", "CHANNELD_NORMAL"];
+
+static RPC_REQUEST_ID: AtomicU64 = AtomicU64::new(0);
+
+/// Client for Core Lightning's JSON-RPC interface, which `lightningd` serves
+/// over a unix socket. Every call opens a connection of its own, which lets
+/// long polling calls to the plugin run alongside regular calls.
+#[derive(Debug, Clone)]
+struct ClnRpc {
+    socket_path: PathBuf,
+}
+
+#[derive(Deserialize)]
+struct ClnRpcResponse {
+    result: Option<Value>,
+    error: Option<ClnRpcResponseError>,
+}
+
+#[derive(Deserialize)]
+struct ClnRpcResponseError {
+    code: i64,
+    message: String,
+}
+
+impl ClnRpc {
+    fn request(method: &str, params: Value) -> anyhow::Result<Vec<u8>> {
+        let id = RPC_REQUEST_ID.fetch_add(1, Ordering::Relaxed);
+
+        Ok(serde_json::to_vec(&json!({
+            "jsonrpc": "2.0",
+            "id": format!("fedimint-gateway:{method}#{id}"),
+            "method": method,
+            "params": params,
+        }))?)
+    }
+
+    /// Parses the response in `buffer`, returning `None` if it is incomplete.
+    fn parse_response(buffer: &[u8]) -> anyhow::Result<Option<Value>> {
+        let mut responses = serde_json::Deserializer::from_slice(buffer).into_iter();
+
+        match responses.next() {
+            Some(Ok(ClnRpcResponse {
+                error: Some(error), ..
+            })) => bail!("{} (code {})", error.message, error.code),
+            Some(Ok(ClnRpcResponse { result, .. })) => Ok(Some(result.unwrap_or(Value::Null))),
+            Some(Err(error)) if error.is_eof() => Ok(None),
+            Some(Err(error)) => Err(error.into()),
+            None => Ok(None),
+        }
+    }
+
+    async fn call<T: DeserializeOwned>(&self, method: &str, params: Value) -> anyhow::Result<T> {
+        let mut stream = UnixStream::connect(&self.socket_path)
+            .await
+            .with_context(|| format!("Failed to connect to {}", self.socket_path.display()))?;
+
+        stream.write_all(&Self::request(method, params)?).await?;
+
+        let mut buffer = Vec::new();
+
+        loop {
+            if stream.read_buf(&mut buffer).await? == 0 {
+                bail!("lightningd closed the connection before responding to {method}");
+            }
+
+            if let Some(result) = Self::parse_response(&buffer)? {
+                return Ok(serde_json::from_value(result)?);
+            }
+        }
+    }
+
+    /// Same as [`ClnRpc::call`] for the few synchronous trait methods.
+    fn call_blocking<T: DeserializeOwned>(&self, method: &str, params: Value) -> anyhow::Result<T> {
+        let mut stream = std::os::unix::net::UnixStream::connect(&self.socket_path)
+            .with_context(|| format!("Failed to connect to {}", self.socket_path.display()))?;
+
+        stream.write_all(&Self::request(method, params)?)?;
+
+        let mut buffer = Vec::new();
+        let mut chunk = [0; 4096];
+
+        loop {
+            let read = stream.read(&mut chunk)?;
+
+            if read == 0 {
+                bail!("lightningd closed the connection before responding to {method}");
+            }
+
+            buffer.extend_from_slice(&chunk[..read]);
+
+            if let Some(result) = Self::parse_response(&buffer)? {
+                return Ok(serde_json::from_value(result)?);
+            }
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct ClnGetInfo {
+    id: PublicKey,
+    alias: Option<String>,
+    network: String,
+    blockheight: u32,
+    warning_bitcoind_sync: Option<String>,
+    warning_lightningd_sync: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ClnPeerChannels {
+    channels: Vec<ClnPeerChannel>,
+}
+
+#[derive(Deserialize)]
+struct ClnPeerChannel {
+    peer_id: PublicKey,
+    peer_connected: bool,
+    state: String,
+    channel_id: Option<String>,
+    short_channel_id: Option<String>,
+    funding_txid: Option<Txid>,
+    funding_outnum: Option<u32>,
+    total_msat: Option<u64>,
+    spendable_msat: Option<u64>,
+    receivable_msat: Option<u64>,
+    fee_base_msat: Option<u64>,
+    fee_proportional_millionths: Option<u64>,
+    updates: Option<ClnChannelUpdates>,
+}
+
+impl ClnPeerChannel {
+    fn is_open(&self) -> bool {
+        OPEN_CHANNEL_STATES.contains(&self.state.as_str())
+    }
+
+    fn is_usable(&self) -> bool {
+        self.peer_connected && self.state == "CHANNELD_NORMAL"
+    }
+
+    fn funding_outpoint(&self) -> Option<OutPoint> {
+        Some(OutPoint {
+            txid: self.funding_txid?,
+            vout: self.funding_outnum?,
+        })
+    }
+
+    /// The id by which `lightningd` commands refer to the channel.
+    fn id(&self) -> Option<String> {
+        self.short_channel_id
+            .clone()
+            .or_else(|| self.channel_id.clone())
+    }
+}
+
+#[derive(Deserialize)]
+struct ClnChannelUpdates {
+    remote: Option<ClnChannelPolicy>,
+}
+
+#[derive(Deserialize)]
+struct ClnChannelPolicy {
+    htlc_minimum_msat: u64,
+    htlc_maximum_msat: u64,
+    cltv_expiry_delta: u16,
+    fee_base_msat: u32,
+    fee_proportional_millionths: u32,
+}
+
+#[derive(Deserialize)]
+struct ClnPays {
+    pays: Vec<ClnPay>,
+}
+
+#[derive(Deserialize)]
+struct ClnPay {
+    payment_hash: sha256::Hash,
+    status: String,
+    created_at: u64,
+    preimage: Option<String>,
+    amount_sent_msat: Option<u64>,
+    bolt12: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ClnPayResponse {
+    payment_preimage: String,
+    amount_msat: Option<u64>,
+    amount_sent_msat: Option<u64>,
+}
+
+#[derive(Deserialize)]
+struct ClnInvoices {
+    invoices: Vec<ClnInvoice>,
+}
+
+#[derive(Deserialize)]
+struct ClnInvoice {
+    payment_hash: sha256::Hash,
+    status: String,
+    amount_msat: Option<u64>,
+    amount_received_msat: Option<u64>,
+    payment_preimage: Option<String>,
+    paid_at: Option<u64>,
+    bolt11: Option<String>,
+    bolt12: Option<String>,
+    local_offer_id: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ClnFetchInvoice {
+    invoice: String,
+}
+
+#[derive(Deserialize)]
+struct ClnDecodedBolt12Invoice {
+    invoice_payment_hash: sha256::Hash,
+    invoice_amount_msat: u64,
+    invoice_created_at: u64,
+    invoice_relative_expiry: Option<u64>,
+}
+
+#[derive(Deserialize)]
+struct ClnRoute {
+    route: Vec<ClnRouteHop>,
+}
+
+#[derive(Deserialize)]
+struct ClnRouteHop {
+    id: PublicKey,
+    channel: String,
+    amount_msat: u64,
+    delay: u64,
+}
+
+#[derive(Deserialize)]
+struct ClnSendPays {
+    payments: Vec<ClnSendPay>,
+}
+
+#[derive(Deserialize)]
+struct ClnSendPay {
+    status: String,
+    amount_msat: Option<u64>,
+    amount_sent_msat: Option<u64>,
+}
+
+#[derive(Deserialize)]
+struct ClnOnion {
+    onion: String,
+    shared_secrets: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct ClnNextHtlcs {
+    htlcs: Vec<InterceptPaymentRequest>,
+}
+
+/// Parses a short channel id in the `BLOCKxTXxOUTPUT` notation of `lightningd`.
+pub fn parse_short_channel_id(short_channel_id: &str) -> Option<u64> {
+    let mut parts = short_channel_id.split('x').map(u64::from_str);
+
+    match (parts.next(), parts.next(), parts.next(), parts.next()) {
+        (Some(Ok(block)), Some(Ok(tx)), Some(Ok(output)), None)
+            if block < 1 << 24 && tx < 1 << 24 && output < 1 << 16 =>
+        {
+            Some(block << 40 | tx << 16 | output)
+        }
+        _ => None,
+    }
+}
+
+fn parse_preimage(preimage: &str) -> Option<Preimage> {
+    let bytes = hex::decode(preimage).ok()?;
+
+    Some(Preimage(bytes.try_into().ok()?))
+}
+
+/// Encodes the payload of a hop of an onion as a TLV stream prefixed by its
+/// length, as expected by the `createonion` command of `lightningd`.
+fn encode_hop_payload(records: &BTreeMap<u64, Vec<u8>>) -> String {
+    let mut stream = Vec::new();
+
+    for (record_type, value) in records {
+        stream.extend(BigSize(*record_type).encode());
+        stream.extend(BigSize(value.len() as u64).encode());
+        stream.extend(value);
+    }
+
+    let mut payload = BigSize(stream.len() as u64).encode();
+
+    payload.extend(stream);
+
+    hex::encode(payload)
+}
+
+/// Encodes an integer as a truncated unsigned integer of the Lightning
+/// protocol, that is big endian without leading zero bytes.
+fn encode_truncated(value: u64) -> Vec<u8> {
+    value
+        .to_be_bytes()
+        .into_iter()
+        .skip_while(|byte| *byte == 0)
+        .collect()
+}
+
+/// Decodes the bech32 encoding of a BOLT12 message, which unlike BOLT11 has no
+/// checksum and may be split into several parts joined by `+`.
+fn decode_bolt12(encoded: &str) -> Option<Vec<u8>> {
+    const CHARSET: &[u8; 32] = b"qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+
+    let encoded = encoded
+        .split('+')
+        .map(str::trim)
+        .collect::<String>()
+        .to_lowercase();
+
+    let (_, data) = encoded.rsplit_once('1')?;
+
+    let mut bytes = Vec::with_capacity(data.len() * 5 / 8);
+    let mut acc = 0u32;
+    let mut bits = 0;
+
+    for c in data.bytes() {
+        let value = CHARSET.iter().position(|&x| x == c)?;
+
+        acc = (acc << 5) | u32::try_from(value).expect("Below 32");
+        bits += 5;
+
+        if bits >= 8 {
+            bits -= 8;
+            bytes.push(u8::try_from((acc >> bits) & 0xff).expect("Masked to a byte"));
+        }
+    }
+
+    // The padding has to fit into a single character and be zero
+    if bits >= 5 || acc & ((1 << bits) - 1) != 0 {
+        return None;
+    }
+
+    Some(bytes)
+}
+
+/// Formats a feerate in sats per vbyte the way `lightningd` expects it.
+fn feerate(sats_per_vbyte: u64) -> String {
+    format!("{}perkb", sats_per_vbyte * 1000)
+}
+
+/// A gateway lightning backend for Core Lightning. Regular commands go to the
+/// JSON-RPC socket of `lightningd`, while intercepting HTLCs requires the
+/// `fedimint-cln-plugin` to be loaded: it holds every incoming HTLC in the
+/// `htlc_accepted` hook and hands it to the gateway, which in turn resolves it
+/// through the plugin.
+#[derive(Clone)]
+pub struct GatewayClnClient {
+    rpc: ClnRpc,
+}
+
+impl GatewayClnClient {
+    pub fn new(socket_path: String) -> Self {
+        info!(
+            target: LOG_LIGHTNING,
+            socket_path = %socket_path,
+            "Gateway configured to connect to CLN LnRpcClient",
+        );
+
+        GatewayClnClient {
+            rpc: ClnRpc {
+                socket_path: PathBuf::from(socket_path),
+            },
+        }
+    }
+
+    async fn peer_channels(&self, peer: Option<PublicKey>) -> anyhow::Result<Vec<ClnPeerChannel>> {
+        let params = match peer {
+            Some(peer) => json!({ "id": peer }),
+            None => json!({}),
+        };
+
+        Ok(self
+            .rpc
+            .call::<ClnPeerChannels>("listpeerchannels", params)
+            .await?
+            .channels)
+    }
+
+    async fn currency(&self) -> anyhow::Result<Currency> {
+        let info: ClnGetInfo = self.rpc.call("getinfo", json!({})).await?;
+
+        Ok(Currency::from(Network::from_str(&info.network)?))
+    }
+
+    async fn connect_peer_if_needed(
+        &self,
+        pubkey: PublicKey,
+        host: String,
+    ) -> Result<(), LightningRpcError> {
+        self.rpc
+            .call::<Value>("connect", json!({ "id": format!("{pubkey}@{host}") }))
+            .await
+            .map_err(|e| LightningRpcError::FailedToConnectToPeer {
+                failure_reason: format!("Failed to connect to peer {e:?}"),
+            })?;
+
+        Ok(())
+    }
+
+    /// Builds an invoice for a payment hash that `lightningd` does not know the
+    /// preimage of. The signature is a placeholder, since `lightningd` signs
+    /// the invoice with the node key when it is passed to `signinvoice` or
+    /// `createinvoice`.
+    async fn build_invoice(
+        &self,
+        payment_hash: sha256::Hash,
+        create_invoice_request: &CreateInvoiceRequest,
+    ) -> anyhow::Result<Bolt11Invoice> {
+        let builder = InvoiceBuilder::new(self.currency().await?);
+
+        let builder = match create_invoice_request.description.clone() {
+            Some(InvoiceDescription::Direct(description)) => builder.description(description),
+            Some(InvoiceDescription::Hash(hash)) => builder.description_hash(hash),
+            None => builder.description(String::new()),
+        };
+
+        let placeholder_key = SecretKey::new(&mut OsRng);
+
+        Ok(builder
+            .payment_hash(payment_hash)
+            .payment_secret(PaymentSecret(rand::random()))
+            .amount_milli_satoshis(create_invoice_request.amount_msat)
+            .current_timestamp()
+            .min_final_cltv_expiry_delta(MIN_FINAL_CLTV_EXPIRY_DELTA)
+            .expiry_time(Duration::from_secs(u64::from(
+                create_invoice_request.expiry_secs,
+            )))
+            .build_signed(|message| SECP256K1.sign_ecdsa_recoverable(message, &placeholder_key))?)
+    }
+
+    /// Pays a BOLT11 or BOLT12 invoice, or the part `partial_msat` of it. Since
+    /// `lightningd` keeps track of the payment by its hash, a payment that is
+    /// already pending or complete is awaited instead of being sent again.
+    async fn pay_invoice(
+        &self,
+        invoice: String,
+        payment_hash: sha256::Hash,
+        partial_msat: Option<u64>,
+        max_delay: u64,
+        max_fee: Amount,
+    ) -> Result<PayInvoiceResponse, LightningRpcError> {
+        loop {
+            let pays = self
+                .rpc
+                .call::<ClnPays>("listpays", json!({ "payment_hash": payment_hash }))
+                .await
+                .map_err(|e| LightningRpcError::FailedPayment {
+                    failure_reason: format!("Failed to look up payment {e:?}"),
+                })?
+                .pays;
+
+            if let Some(preimage) = pays
+                .iter()
+                .filter(|pay| pay.status == "complete")
+                .find_map(|pay| pay.preimage.as_deref().and_then(parse_preimage))
+            {
+                return Ok(PayInvoiceResponse {
+                    preimage,
+                    routing_fee: None,
+                });
+            }
+
+            if !pays.iter().any(|pay| pay.status == "pending") {
+                if !pays.is_empty() {
+                    return Err(LightningRpcError::FailedPayment {
+                        failure_reason: "Payment has already failed".to_string(),
+                    });
+                }
+
+                break;
+            }
+
+            debug!(target: LOG_LIGHTNING, %payment_hash, "Waiting for pending CLN payment");
+
+            sleep(Duration::from_secs(1)).await;
+        }
+
+        let mut params = json!({
+            "bolt11": invoice,
+            "maxfee": max_fee.msats,
+            "maxdelay": max_delay,
+            "retry_for": PAYMENT_RETRY_FOR_SECS,
+        });
+
+        if let Some(partial_msat) = partial_msat {
+            params["partial_msat"] = json!(partial_msat);
+        }
+
+        let response: ClnPayResponse =
+            self.rpc
+                .call("pay", params)
+                .await
+                .map_err(|e| LightningRpcError::FailedPayment {
+                    failure_reason: format!("Failed to pay invoice {e:?}"),
+                })?;
+
+        let preimage = parse_preimage(&response.payment_preimage).ok_or_else(|| {
+            LightningRpcError::FailedPayment {
+                failure_reason: "lightningd returned an invalid preimage".to_string(),
+            }
+        })?;
+
+        // The amount sent exceeds the amount delivered by the fees paid to the
+        // nodes along the route
+        let routing_fee = response.amount_sent_msat.zip(response.amount_msat).map(
+            |(sent_msat, delivered_msat)| {
+                Amount::from_msats(sent_msat.saturating_sub(delivered_msat))
+            },
+        );
+
+        Ok(PayInvoiceResponse {
+            preimage,
+            routing_fee,
+        })
+    }
+
+    /// Sends a keysend payment along the cheapest route `lightningd` finds for
+    /// it. The onion is built here rather than by the `keysend` command of
+    /// `lightningd`, since the latter picks the preimage itself.
+    async fn send_keysend_onion(
+        &self,
+        payee: PublicKey,
+        amount: Amount,
+        preimage: &Preimage,
+        custom_records: BTreeMap<u64, Vec<u8>>,
+        max_delay: u64,
+        max_fee: Amount,
+    ) -> anyhow::Result<()> {
+        let payment_hash = sha256::Hash::hash(&preimage.0);
+
+        let route = self
+            .rpc
+            .call::<ClnRoute>(
+                "getroute",
+                json!({
+                    "id": payee,
+                    "amount_msat": amount.msats,
+                    "riskfactor": 10,
+                    "cltv": MIN_FINAL_CLTV_EXPIRY_DELTA,
+                }),
+            )
+            .await
+            .context("Failed to find route")?
+            .route;
+
+        let first_hop = route
+            .first()
+            .context("lightningd returned an empty route")?;
+
+        let routing_fee = Amount::from_msats(first_hop.amount_msat.saturating_sub(amount.msats));
+
+        if routing_fee > max_fee || first_hop.delay > max_delay {
+            bail!(
+                "No route within the limits, cheapest route has fee {routing_fee} and delay {}",
+                first_hop.delay
+            );
+        }
+
+        // Every hop is told how much to forward with which delay, either over the
+        // channel to the next hop or, for the payee, as the final amount along
+        // with the preimage and the custom records.
+        let mut hops = Vec::with_capacity(route.len());
+
+        for (index, hop) in route.iter().enumerate() {
+            let mut records = match route.get(index + 1) {
+                Some(next_hop) => BTreeMap::from([(
+                    6,
+                    parse_short_channel_id(&next_hop.channel)
+                        .context("lightningd returned an invalid short channel id")?
+                        .to_be_bytes()
+                        .to_vec(),
+                )]),
+                None => {
+                    let mut records = custom_records.clone();
+
+                    records.insert(KEYSEND_RECORD_TYPE, preimage.0.to_vec());
+
+                    records
+                }
+            };
+
+            let forward = route.get(index + 1).unwrap_or(hop);
+
+            records.insert(2, encode_truncated(forward.amount_msat));
+            records.insert(4, encode_truncated(forward.delay));
+
+            hops.push(json!({
+                "pubkey": hop.id,
+                "payload": encode_hop_payload(&records),
+            }));
+        }
+
+        let onion: ClnOnion = self
+            .rpc
+            .call(
+                "createonion",
+                json!({
+                    "hops": hops,
+                    "assocdata": payment_hash,
+                }),
+            )
+            .await
+            .context("Failed to create onion")?;
+
+        self.rpc
+            .call::<Value>(
+                "sendonion",
+                json!({
+                    "onion": onion.onion,
+                    "first_hop": {
+                        "id": first_hop.id,
+                        "amount_msat": first_hop.amount_msat,
+                        "delay": first_hop.delay,
+                    },
+                    "payment_hash": payment_hash,
+                    "shared_secrets": onion.shared_secrets,
+                    "destination": payee,
+                    "amount_msat": amount.msats,
+                }),
+            )
+            .await
+            .context("Failed to send onion")?;
+
+        Ok(())
+    }
+
+    async fn fetch_bolt12_invoice(
+        &self,
+        offer: String,
+        quantity: Option<u64>,
+        amount: Option<Amount>,
+        payer_note: Option<String>,
+    ) -> Result<String, LightningRpcError> {
+        let mut params = json!({ "offer": offer });
+
+        if let Some(amount) = amount {
+            params["amount_msat"] = json!(amount.msats);
+        }
+
+        if let Some(quantity) = quantity {
+            params["quantity"] = json!(quantity);
+        }
+
+        if let Some(payer_note) = payer_note {
+            params["payer_note"] = json!(payer_note);
+        }
+
+        self.rpc
+            .call::<ClnFetchInvoice>("fetchinvoice", params)
+            .await
+            .map(|response| response.invoice)
+            .map_err(|e| LightningRpcError::Bolt12Error {
+                failure_reason: format!("Failed to fetch invoice for offer {e:?}"),
+            })
+    }
+
+    async fn decode_bolt12_invoice(
+        &self,
+        invoice: &str,
+    ) -> Result<ClnDecodedBolt12Invoice, LightningRpcError> {
+        self.rpc
+            .call("decode", json!({ "string": invoice }))
+            .await
+            .map_err(|e| LightningRpcError::Bolt12Error {
+                failure_reason: format!("Failed to decode offer invoice {e:?}"),
+            })
+    }
+
+    async fn spawn_htlc_subscription(
+        &self,
+        task_group: &TaskGroup,
+        gateway_sender: mpsc::Sender<InterceptPaymentRequest>,
+    ) -> Result<(), LightningRpcError> {
+        // Fails if the plugin is not loaded, in which case the gateway must not
+        // assume that it receives any HTLCs. The plugin hands out the HTLCs a
+        // previous subscription did not resolve again, since they may have been
+        // lost with it.
+        let htlcs = self
+            .rpc
+            .call::<ClnNextHtlcs>(
+                CLN_PLUGIN_NEXT_HTLCS_METHOD,
+                json!({ "timeout_secs": 0, "resubscribe": true }),
+            )
+            .await
+            .map_err(|e| LightningRpcError::FailedToRouteHtlcs {
+                failure_reason: format!("Failed to reach fedimint-cln-plugin {e:?}"),
+            })?
+            .htlcs;
+
+        let rpc = self.rpc.clone();
+
+        // Like for LND, we shut down the payment-stream subgroup if the
+        // subscription exits unexpectedly, which drops the sender, closes the
+        // gateway's HTLC stream and thereby drives the gateway to reconnect.
+        let subgroup = task_group.clone();
+        task_group.spawn("CLN HTLC Subscription", |handle| async move {
+            let mut htlcs = htlcs;
+
+            loop {
+                for htlc in htlcs {
+                    // The HTLC stays held by the plugin, which hands it to the
+                    // subscription of the reconnected gateway again.
+                    if let Err(err) = gateway_sender.send(htlc).await {
+                        warn!(target: LOG_LIGHTNING, err = %err.fmt_compact(), "Failed to send HTLC to gatewayd for processing, shutting down payment-stream subgroup to trigger gateway reconnect");
+                        subgroup.shutdown();
+                        return;
+                    }
+                }
+
+                let next_htlcs = tokio::select! {
+                    () = handle.make_shutdown_rx() => {
+                        info!(target: LOG_LIGHTNING, "CLN HTLC Subscription task received shutdown signal");
+                        return;
+                    }
+                    next_htlcs = rpc.call::<ClnNextHtlcs>(
+                        CLN_PLUGIN_NEXT_HTLCS_METHOD,
+                        json!({ "timeout_secs": NEXT_HTLCS_TIMEOUT_SECS }),
+                    ) => next_htlcs,
+                };
+
+                match next_htlcs {
+                    Ok(next_htlcs) => htlcs = next_htlcs.htlcs,
+                    Err(err) => {
+                        warn!(target: LOG_LIGHTNING, err = %err.fmt_compact(), "CLN HTLC Subscription exited unexpectedly, shutting down payment-stream subgroup to trigger gateway reconnect");
+                        subgroup.shutdown();
+                        return;
+                    }
+                }
+            }
+        });
+
+        Ok(())
+    }
+}
+
+impl fmt::Debug for GatewayClnClient {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "ClnClient")
+    }
+}
+
+#[async_trait]
+impl ILnRpcClient for GatewayClnClient {
+    async fn info(&self) -> Result<GetNodeInfoResponse, LightningRpcError> {
+        let info: ClnGetInfo = self.rpc.call("getinfo", json!({})).await.map_err(|e| {
+            LightningRpcError::FailedToGetNodeInfo {
+                failure_reason: format!("Failed to get node info {e:?}"),
+            }
+        })?;
+
+        Ok(GetNodeInfoResponse {
+            pub_key: info.id,
+            alias: info.alias.unwrap_or_default(),
+            network: info.network,
+            block_height: info.blockheight,
+            synced_to_chain: info.warning_bitcoind_sync.is_none()
+                && info.warning_lightningd_sync.is_none(),
+        })
+    }
+
+    async fn routehints(
+        &self,
+        num_route_hints: usize,
+    ) -> Result<GetRouteHintsResponse, LightningRpcError> {
+        let mut channels = self.peer_channels(None).await.map_err(|e| {
+            LightningRpcError::FailedToGetRouteHints {
+                failure_reason: format!("Failed to list channels {e:?}"),
+            }
+        })?;
+
+        channels.retain(ClnPeerChannel::is_usable);
+
+        // Take the channels with the largest incoming capacity
+        channels.sort_by_key(|channel| std::cmp::Reverse(channel.receivable_msat));
+
+        let route_hints = channels
+            .into_iter()
+            .filter_map(|channel| {
+                // The hop describes the peer forwarding a payment into the gateway,
+                // so it carries the policy the peer advertises for its direction.
+                let policy = channel.updates?.remote?;
+
+                Some(RouteHint(vec![RouteHintHop {
+                    src_node_id: channel.peer_id,
+                    short_channel_id: parse_short_channel_id(&channel.short_channel_id?)?,
+                    base_msat: policy.fee_base_msat,
+                    proportional_millionths: policy.fee_proportional_millionths,
+                    cltv_expiry_delta: policy.cltv_expiry_delta,
+                    htlc_minimum_msat: Some(policy.htlc_minimum_msat),
+                    htlc_maximum_msat: Some(policy.htlc_maximum_msat),
+                }]))
+            })
+            .take(num_route_hints)
+            .collect();
+
+        Ok(GetRouteHintsResponse { route_hints })
+    }
+
+    async fn pay(
+        &self,
+        invoice: Bolt11Invoice,
+        max_delay: u64,
+        max_fee: Amount,
+    ) -> Result<PayInvoiceResponse, LightningRpcError> {
+        let payment_hash = *invoice.payment_hash();
+
+        self.pay_invoice(invoice.to_string(), payment_hash, None, max_delay, max_fee)
+            .await
+    }
+
+    fn supports_pay_part(&self) -> bool {
+        true
+    }
+
+    async fn pay_part(
+        &self,
+        invoice: Bolt11Invoice,
+        amount: Amount,
+        max_delay: u64,
+        max_fee: Amount,
+    ) -> Result<PayInvoiceResponse, LightningRpcError> {
+        let payment_hash = *invoice.payment_hash();
+
+        self.pay_invoice(
+            invoice.to_string(),
+            payment_hash,
+            Some(amount.msats),
+            max_delay,
+            max_fee,
+        )
+        .await
+    }
+
+    /// Since `lightningd` keeps track of the payment by its hash, a payment
+    /// that is already pending or complete is awaited instead of being sent
+    /// again.
+    async fn pay_keysend(
+        &self,
+        payee: PublicKey,
+        amount: Amount,
+        preimage: Preimage,
+        custom_records: BTreeMap<u64, Vec<u8>>,
+        max_delay: u64,
+        max_fee: Amount,
+    ) -> Result<PayInvoiceResponse, LightningRpcError> {
+        let payment_hash = sha256::Hash::hash(&preimage.0);
+
+        info!(target: LOG_LIGHTNING, %payment_hash, %amount, "CLN Paying keysend");
+
+        let payments = self
+            .rpc
+            .call::<ClnSendPays>("listsendpays", json!({ "payment_hash": payment_hash }))
+            .await
+            .map_err(|e| LightningRpcError::FailedPayment {
+                failure_reason: format!("Failed to look up payment {e:?}"),
+            })?
+            .payments;
+
+        if payments.iter().any(|payment| payment.status == "complete") {
+            return Ok(PayInvoiceResponse {
+                preimage,
+                routing_fee: None,
+            });
+        }
+
+        if !payments.iter().any(|payment| payment.status == "pending") {
+            if !payments.is_empty() {
+                return Err(LightningRpcError::FailedPayment {
+                    failure_reason: "Payment has already failed".to_string(),
+                });
+            }
+
+            self.send_keysend_onion(payee, amount, &preimage, custom_records, max_delay, max_fee)
+                .await
+                .map_err(|e| LightningRpcError::FailedPayment {
+                    failure_reason: format!("Failed to send keysend payment {e:?}"),
+                })?;
+        }
+
+        let payment: ClnSendPay = self
+            .rpc
+            .call("waitsendpay", json!({ "payment_hash": payment_hash }))
+            .await
+            .map_err(|e| LightningRpcError::FailedPayment {
+                failure_reason: format!("Keysend payment failed {e:?}"),
+            })?;
+
+        let routing_fee =
+            payment
+                .amount_sent_msat
+                .zip(payment.amount_msat)
+                .map(|(sent_msat, delivered_msat)| {
+                    Amount::from_msats(sent_msat.saturating_sub(delivered_msat))
+                });
+
+        Ok(PayInvoiceResponse {
+            preimage,
+            routing_fee,
+        })
+    }
+
+    fn supports_keysend(&self) -> bool {
+        true
+    }
+
+    fn supports_probe(&self) -> bool {
+        true
+    }
+
+    async fn probe(
+        &self,
+        invoice: Bolt11Invoice,
+        max_delay: u64,
+        max_fee: Amount,
+    ) -> Result<ProbeResponse, LightningRpcError> {
+        let amount_msat =
+            invoice
+                .amount_milli_satoshis()
+                .ok_or_else(|| LightningRpcError::FailedToProbe {
+                    failure_reason: "Invoice has no amount".to_string(),
+                })?;
+
+        let route: ClnRoute = self
+            .rpc
+            .call(
+                "getroute",
+                json!({
+                    "id": invoice.recover_payee_pub_key(),
+                    "amount_msat": amount_msat,
+                    "riskfactor": 10,
+                    "cltv": invoice.min_final_cltv_expiry_delta(),
+                }),
+            )
+            .await
+            .map_err(|e| LightningRpcError::FailedToProbe {
+                failure_reason: format!("Failed to find route {e:?}"),
+            })?;
+
+        let first_hop = route
+            .route
+            .first()
+            .ok_or_else(|| LightningRpcError::FailedToProbe {
+                failure_reason: "lightningd returned an empty route".to_string(),
+            })?;
+
+        let routing_fee = Amount::from_msats(first_hop.amount_msat.saturating_sub(amount_msat));
+
+        if routing_fee > max_fee || first_hop.delay > max_delay {
+            return Err(LightningRpcError::FailedToProbe {
+                failure_reason: format!(
+                    "No route within the limits, cheapest route has fee {routing_fee} and delay {}",
+                    first_hop.delay
+                ),
+            });
+        }
+
+        Ok(ProbeResponse {
+            routing_fee,
+            success_probability: None,
+            cltv_delta: first_hop.delay,
+        })
+    }
+
+    async fn route_htlcs<'a>(
+        self: Box<Self>,
+        task_group: &TaskGroup,
+    ) -> Result<(RouteHtlcStream<'a>, Arc<dyn ILnRpcClient>), LightningRpcError> {
+        const CHANNEL_SIZE: usize = 100;
+
+        let (gateway_sender, gateway_receiver) =
+            mpsc::channel::<InterceptPaymentRequest>(CHANNEL_SIZE);
+
+        self.spawn_htlc_subscription(task_group, gateway_sender)
+            .await?;
+
+        Ok((
+            Box::pin(ReceiverStream::new(gateway_receiver)),
+            Arc::new(*self),
+        ))
+    }
+
+    async fn complete_htlc(&self, htlc: InterceptPaymentResponse) -> Result<(), LightningRpcError> {
+        // Every HTLC reaches the gateway through the `htlc_accepted` hook, so
+        // unlike for LND every payment has an incoming circuit, including the
+        // ones paying an invoice created by `create_invoice`.
+        let params =
+            serde_json::to_value(&htlc).map_err(|e| LightningRpcError::FailedToCompleteHtlc {
+                failure_reason: format!("Failed to encode HTLC completion {e:?}"),
+            })?;
+
+        self.rpc
+            .call::<Value>(CLN_PLUGIN_COMPLETE_HTLC_METHOD, params)
+            .await
+            .map_err(|e| LightningRpcError::FailedToCompleteHtlc {
+                failure_reason: format!("Failed to complete HTLC {e:?}"),
+            })?;
+
+        Ok(())
+    }
+
+    async fn create_invoice(
+        &self,
+        create_invoice_request: CreateInvoiceRequest,
+    ) -> Result<CreateInvoiceResponse, LightningRpcError> {
+        let label = format!(
+            "fedimint-gateway-{}",
+            hex::encode(rand::random::<[u8; 16]>())
+        );
+
+        let invoice = match (
+            create_invoice_request.payment_hash,
+            create_invoice_request.description.clone(),
+        ) {
+            // The preimage is held by the federation, so the payment is settled
+            // by the gateway via the plugin rather than by `lightningd`, which
+            // merely signs the invoice.
+            (Some(payment_hash), _) => {
+                let invoice = self
+                    .build_invoice(payment_hash, &create_invoice_request)
+                    .await
+                    .map_err(|e| LightningRpcError::FailedToGetInvoice {
+                        failure_reason: format!("Failed to build invoice {e:?}"),
+                    })?;
+
+                self.rpc
+                    .call::<Value>("signinvoice", json!({ "invstring": invoice.to_string() }))
+                    .await
+                    .map(|response| response["bolt11"].as_str().map(ToString::to_string))
+            }
+            // `lightningd` can only derive the description hash from the
+            // description itself, so the invoice is built here and stored with a
+            // fresh preimage via `createinvoice`.
+            (None, Some(InvoiceDescription::Hash(_))) => {
+                let preimage: [u8; 32] = rand::random();
+
+                let invoice = self
+                    .build_invoice(sha256::Hash::hash(&preimage), &create_invoice_request)
+                    .await
+                    .map_err(|e| LightningRpcError::FailedToGetInvoice {
+                        failure_reason: format!("Failed to build invoice {e:?}"),
+                    })?;
+
+                self.rpc
+                    .call::<Value>(
+                        "createinvoice",
+                        json!({
+                            "invstring": invoice.to_string(),
+                            "label": label,
+                            "preimage": hex::encode(preimage),
+                        }),
+                    )
+                    .await
+                    .map(|response| response["bolt11"].as_str().map(ToString::to_string))
+            }
+            (None, description) => {
+                let description = match description {
+                    Some(InvoiceDescription::Direct(description)) => description,
+                    _ => String::new(),
+                };
+
+                self.rpc
+                    .call::<Value>(
+                        "invoice",
+                        json!({
+                            "amount_msat": create_invoice_request.amount_msat,
+                            "label": label,
+                            "description": description,
+                            "expiry": create_invoice_request.expiry_secs,
+                        }),
+                    )
+                    .await
+                    .map(|response| response["bolt11"].as_str().map(ToString::to_string))
+            }
+        }
+        .map_err(|e| LightningRpcError::FailedToGetInvoice {
+            failure_reason: e.to_string(),
+        })?
+        .ok_or_else(|| LightningRpcError::FailedToGetInvoice {
+            failure_reason: "lightningd did not return an invoice".to_string(),
+        })?;
+
+        Ok(CreateInvoiceResponse { invoice })
+    }
+
+    async fn get_ln_onchain_address(
+        &self,
+    ) -> Result<GetLnOnchainAddressResponse, LightningRpcError> {
+        let response: Value = self
+            .rpc
+            .call("newaddr", json!({ "addresstype": "p2tr" }))
+            .await
+            .map_err(|e| LightningRpcError::FailedToGetLnOnchainAddress {
+                failure_reason: format!("Failed to get funding address {e:?}"),
+            })?;
+
+        let address = response["p2tr"].as_str().ok_or_else(|| {
+            LightningRpcError::FailedToGetLnOnchainAddress {
+                failure_reason: "lightningd did not return a taproot address".to_string(),
+            }
+        })?;
+
+        Ok(GetLnOnchainAddressResponse {
+            address: address.to_string(),
+        })
+    }
+
+    async fn send_onchain(
+        &self,
+        SendOnchainRequest {
+            address,
+            amount,
+            fee_rate_sats_per_vbyte,
+        }: SendOnchainRequest,
+    ) -> Result<SendOnchainResponse, LightningRpcError> {
+        let satoshi = match amount {
+            BitcoinAmountOrAll::All => json!("all"),
+            BitcoinAmountOrAll::Amount(amount) => json!(amount.to_sat()),
+        };
+
+        let response: Value = self
+            .rpc
+            .call(
+                "withdraw",
+                json!({
+                    "destination": address.assume_checked().to_string(),
+                    "satoshi": satoshi,
+                    "feerate": feerate(fee_rate_sats_per_vbyte),
+                }),
+            )
+            .await
+            .map_err(|e| LightningRpcError::FailedToWithdrawOnchain {
+                failure_reason: format!("Failed to withdraw funds on-chain {e:?}"),
+            })?;
+
+        Ok(SendOnchainResponse {
+            txid: response["txid"].as_str().unwrap_or_default().to_string(),
+        })
+    }
+
+    async fn open_channel(
+        &self,
+        crate::OpenChannelRequest {
+            pubkey,
+            host,
+            channel_size_sats,
+            push_amount_sats,
+            fee_rate_sats_per_vbyte,
+            base_fee_msat,
+            parts_per_million,
+            unannounced,
+        }: crate::OpenChannelRequest,
+    ) -> Result<OpenChannelResponse, LightningRpcError> {
+        self.connect_peer_if_needed(pubkey, host).await?;
+
+        let mut params = json!({
+            "id": pubkey,
+            "amount": channel_size_sats,
+            "push_msat": push_amount_sats * 1000,
+            "announce": !unannounced,
+        });
+
+        if let Some(rate) = fee_rate_sats_per_vbyte {
+            params["feerate"] = json!(feerate(rate));
+        }
+
+        let response: Value = self.rpc.call("fundchannel", params).await.map_err(|e| {
+            LightningRpcError::FailedToOpenChannel {
+                failure_reason: format!("Failed to open channel {e:?}"),
+            }
+        })?;
+
+        // Unlike LND, `lightningd` does not take the routing fees when opening
+        // the channel, so they are set once the channel has been created.
+        if base_fee_msat.is_some() || parts_per_million.is_some() {
+            let mut params = json!({ "id": response["channel_id"] });
+
+            if let Some(base_fee_msat) = base_fee_msat {
+                params["feebase"] = json!(base_fee_msat);
+            }
+
+            if let Some(parts_per_million) = parts_per_million {
+                params["feeppm"] = json!(parts_per_million);
+            }
+
+            if let Err(e) = self.rpc.call::<Value>("setchannel", params).await {
+                warn!(target: LOG_LIGHTNING, err = %e.fmt_compact(), "Failed to set routing fees of opened channel");
+            }
+        }
+
+        Ok(OpenChannelResponse {
+            funding_txid: response["txid"].as_str().unwrap_or_default().to_string(),
+        })
+    }
+
+    async fn connect_peer(&self, payload: ConnectPeerRequest) -> Result<(), LightningRpcError> {
+        self.connect_peer_if_needed(
+            payload.node_address.pubkey,
+            payload.node_address.host_with_port(),
+        )
+        .await
+    }
+
+    async fn close_channels_with_peer(
+        &self,
+        CloseChannelsWithPeerRequest {
+            pubkey,
+            force,
+            sats_per_vbyte,
+        }: CloseChannelsWithPeerRequest,
+    ) -> Result<CloseChannelsWithPeerResponse, LightningRpcError> {
+        let channels = self.peer_channels(Some(pubkey)).await.map_err(|e| {
+            LightningRpcError::FailedToCloseChannelsWithPeer {
+                failure_reason: format!("Failed to list channels {e:?}"),
+            }
+        })?;
+
+        let mut num_channels_closed = 0;
+
+        for channel in channels.iter().filter(|channel| channel.is_open()) {
+            let Some(id) = channel.id() else {
+                continue;
+            };
+
+            let mut params = json!({ "id": id });
+
+            // A unilateral timeout of one second makes `lightningd` force close
+            // right away if the peer does not cooperate.
+            if force {
+                params["unilateraltimeout"] = json!(1);
+            }
+
+            if let Some(sats_per_vbyte) = sats_per_vbyte {
+                params["feerange"] = json!([feerate(sats_per_vbyte), feerate(sats_per_vbyte)]);
+            }
+
+            self.rpc.call::<Value>("close", params).await.map_err(|e| {
+                LightningRpcError::FailedToCloseChannelsWithPeer {
+                    failure_reason: format!("Failed to close channel {e:?}"),
+                }
+            })?;
+
+            num_channels_closed += 1;
+        }
+
+        Ok(CloseChannelsWithPeerResponse {
+            num_channels_closed,
+        })
+    }
+
+    async fn list_channels(&self) -> Result<ListChannelsResponse, LightningRpcError> {
+        let channels = self.peer_channels(None).await.map_err(|e| {
+            LightningRpcError::FailedToListChannels {
+                failure_reason: format!("Failed to list active channels {e:?}"),
+            }
+        })?;
+
+        // Fetch peer addresses so we can populate remote_address on each channel
+        let peer_addresses: BTreeMap<PublicKey, String> = self
+            .rpc
+            .call::<Value>("listpeers", json!({}))
+            .await
+            .map(|response| {
+                response["peers"]
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|peer| {
+                        let id = PublicKey::from_str(peer["id"].as_str()?).ok()?;
+                        let address = peer["netaddr"].as_array()?.first()?.as_str()?;
+
+                        Some((id, address.to_string()))
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        let mut channel_infos = Vec::new();
+
+        for channel in channels.into_iter().filter(ClnPeerChannel::is_open) {
+            let remote_node_alias = self
+                .rpc
+                .call::<Value>("listnodes", json!({ "id": channel.peer_id }))
+                .await
+                .ok()
+                .and_then(|response| {
+                    response["nodes"][0]["alias"]
+                        .as_str()
+                        .filter(|alias| !alias.is_empty())
+                        .map(ToString::to_string)
+                });
+
+            channel_infos.push(ChannelInfo {
+                remote_pubkey: channel.peer_id,
+                channel_size_sats: channel.total_msat.unwrap_or_default() / 1000,
+                outbound_liquidity_sats: channel.spendable_msat.unwrap_or_default() / 1000,
+                inbound_liquidity_sats: channel.receivable_msat.unwrap_or_default() / 1000,
+                is_active: channel.is_usable(),
+                funding_outpoint: channel.funding_outpoint(),
+                remote_node_alias,
+                remote_address: peer_addresses.get(&channel.peer_id).cloned(),
+                base_fee_msat: channel.fee_base_msat,
+                parts_per_million: channel.fee_proportional_millionths,
+            });
+        }
+
+        Ok(ListChannelsResponse {
+            channels: channel_infos,
+        })
+    }
+
+    async fn set_channel_fees(
+        &self,
+        payload: SetChannelFeesRequest,
+    ) -> Result<(), LightningRpcError> {
+        let id = self
+            .peer_channels(None)
+            .await
+            .map_err(|e| LightningRpcError::FailedToSetChannelFees {
+                failure_reason: format!("Failed to list channels: {e:?}"),
+            })?
+            .into_iter()
+            .find(|channel| channel.funding_outpoint() == Some(payload.funding_outpoint))
+            .and_then(|channel| channel.id())
+            .ok_or_else(|| LightningRpcError::FailedToSetChannelFees {
+                failure_reason: format!(
+                    "No channel found with funding outpoint {}",
+                    payload.funding_outpoint
+                ),
+            })?;
+
+        self.rpc
+            .call::<Value>(
+                "setchannel",
+                json!({
+                    "id": id,
+                    "feebase": payload.base_fee_msat,
+                    "feeppm": payload.parts_per_million,
+                }),
+            )
+            .await
+            .map_err(|e| LightningRpcError::FailedToSetChannelFees {
+                failure_reason: format!("setchannel failed: {e:?}"),
+            })?;
+
+        Ok(())
+    }
+
+    async fn get_balances(&self) -> Result<GetBalancesResponse, LightningRpcError> {
+        let funds: Value = self.rpc.call("listfunds", json!({})).await.map_err(|e| {
+            LightningRpcError::FailedToGetBalances {
+                failure_reason: format!("Failed to get on-chain balance {e:?}"),
+            }
+        })?;
+
+        let onchain_balance_msats: u64 = funds["outputs"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter(|output| output["status"] == "confirmed" && output["reserved"] != true)
+            .filter_map(|output| output["amount_msat"].as_u64())
+            .sum();
+
+        let channels =
+            self.peer_channels(None)
+                .await
+                .map_err(|e| LightningRpcError::FailedToGetBalances {
+                    failure_reason: format!("Failed to get lightning balance {e:?}"),
+                })?;
+
+        let usable_channels = channels.iter().filter(|channel| channel.is_usable());
+
+        Ok(GetBalancesResponse {
+            onchain_balance_sats: onchain_balance_msats / 1000,
+            lightning_balance_msats: usable_channels
+                .clone()
+                .filter_map(|channel| channel.spendable_msat)
+                .sum(),
+            inbound_lightning_liquidity_msats: usable_channels
+                .filter_map(|channel| channel.receivable_msat)
+                .sum(),
+        })
+    }
+
+    async fn get_invoice(
+        &self,
+        get_invoice_request: GetInvoiceRequest,
+    ) -> Result<Option<GetInvoiceResponse>, LightningRpcError> {
+        let invoices: ClnInvoices = self
+            .rpc
+            .call(
+                "listinvoices",
+                json!({ "payment_hash": get_invoice_request.payment_hash }),
+            )
+            .await
+            .map_err(|e| LightningRpcError::FailedToGetInvoice {
+                failure_reason: format!("Failed to list invoices {e:?}"),
+            })?;
+
+        let Some(invoice) = invoices.invoices.into_iter().next() else {
+            return Ok(None);
+        };
+
+        let created_at = invoice
+            .bolt11
+            .as_deref()
+            .and_then(|bolt11| Bolt11Invoice::from_str(bolt11).ok())
+            .map_or(UNIX_EPOCH, |bolt11| bolt11.timestamp());
+
+        let status = match invoice.status.as_str() {
+            "paid" => PaymentStatus::Succeeded,
+            "expired" => PaymentStatus::Failed,
+            _ => PaymentStatus::Pending,
+        };
+
+        Ok(Some(GetInvoiceResponse {
+            preimage: invoice.payment_preimage,
+            payment_hash: Some(invoice.payment_hash),
+            amount: Amount::from_msats(invoice.amount_msat.unwrap_or_default()),
+            created_at,
+            status,
+        }))
+    }
+
+    async fn list_transactions(
+        &self,
+        start_secs: u64,
+        end_secs: u64,
+    ) -> Result<ListTransactionsResponse, LightningRpcError> {
+        let pays: ClnPays = self.rpc.call("listpays", json!({})).await.map_err(|e| {
+            LightningRpcError::FailedToListTransactions {
+                failure_reason: e.to_string(),
+            }
+        })?;
+
+        let mut payments = pays
+            .pays
+            .into_iter()
+            .filter(|pay| start_secs <= pay.created_at && pay.created_at < end_secs)
+            .map(|pay| PaymentDetails {
+                payment_hash: Some(pay.payment_hash),
+                preimage: pay.preimage,
+                payment_kind: if pay.bolt12.is_some() {
+                    PaymentKind::Bolt12Offer
+                } else {
+                    PaymentKind::Bolt11
+                },
+                amount: Amount::from_msats(pay.amount_sent_msat.unwrap_or_default()),
+                direction: PaymentDirection::Outbound,
+                status: match pay.status.as_str() {
+                    "complete" => PaymentStatus::Succeeded,
+                    "failed" => PaymentStatus::Failed,
+                    _ => PaymentStatus::Pending,
+                },
+                timestamp_secs: pay.created_at,
+            })
+            .collect::<Vec<_>>();
+
+        let invoices: ClnInvoices =
+            self.rpc
+                .call("listinvoices", json!({}))
+                .await
+                .map_err(|e| LightningRpcError::FailedToListTransactions {
+                    failure_reason: e.to_string(),
+                })?;
+
+        let mut incoming_payments = invoices
+            .invoices
+            .into_iter()
+            .filter_map(|invoice| {
+                let paid_at = invoice.paid_at?;
+
+                if paid_at < start_secs || paid_at >= end_secs {
+                    return None;
+                }
+
+                Some(PaymentDetails {
+                    payment_hash: Some(invoice.payment_hash),
+                    preimage: invoice.payment_preimage,
+                    payment_kind: if invoice.bolt12.is_some() {
+                        PaymentKind::Bolt12Offer
+                    } else {
+                        PaymentKind::Bolt11
+                    },
+                    amount: Amount::from_msats(invoice.amount_received_msat.unwrap_or_default()),
+                    direction: PaymentDirection::Inbound,
+                    status: PaymentStatus::Succeeded,
+                    timestamp_secs: paid_at,
+                })
+            })
+            .collect::<Vec<_>>();
+
+        payments.append(&mut incoming_payments);
+        payments.sort_by_key(|p| p.timestamp_secs);
+
+        Ok(ListTransactionsResponse {
+            transactions: payments,
+        })
+    }
+
+    fn create_offer(
+        &self,
+        amount: Option<Amount>,
+        description: Option<String>,
+        expiry_secs: Option<u32>,
+        quantity: Option<u64>,
+    ) -> Result<String, LightningRpcError> {
+        let mut params = json!({
+            "amount": amount.map_or_else(|| "any".to_string(), |amount| format!("{}msat", amount.msats)),
+            "description": description.unwrap_or_default(),
+        });
+
+        if let Some(expiry_secs) = expiry_secs {
+            params["absolute_expiry"] =
+                json!(duration_since_epoch().as_secs() + u64::from(expiry_secs));
+        }
+
+        if let Some(quantity) = quantity {
+            params["quantity_max"] = json!(quantity);
+        }
+
+        let response: Value = self.rpc.call_blocking("offer", params).map_err(|e| {
+            LightningRpcError::Bolt12Error {
+                failure_reason: format!("Failed to create offer {e:?}"),
+            }
+        })?;
+
+        response["bolt12"]
+            .as_str()
+            .map(ToString::to_string)
+            .ok_or_else(|| LightningRpcError::Bolt12Error {
+                failure_reason: "lightningd did not return an offer".to_string(),
+            })
+    }
+
+    async fn pay_offer(
+        &self,
+        offer: String,
+        quantity: Option<u64>,
+        amount: Option<Amount>,
+        payer_note: Option<String>,
+    ) -> Result<Preimage, LightningRpcError> {
+        let invoice = self
+            .fetch_bolt12_invoice(offer, quantity, amount, payer_note)
+            .await?;
+
+        let response: ClnPayResponse = self
+            .rpc
+            .call("pay", json!({ "bolt11": invoice }))
+            .await
+            .map_err(|e| LightningRpcError::FailedPayment {
+                failure_reason: format!("Failed to pay offer {e:?}"),
+            })?;
+
+        parse_preimage(&response.payment_preimage).ok_or_else(|| LightningRpcError::FailedPayment {
+            failure_reason: "lightningd returned an invalid preimage".to_string(),
+        })
+    }
+
+    async fn fetch_offer_invoice(
+        &self,
+        offer: String,
+        amount: Option<Amount>,
+        payer_note: Option<String>,
+    ) -> Result<FetchOfferInvoiceResponse, LightningRpcError> {
+        let invoice = self
+            .fetch_bolt12_invoice(offer, None, amount, payer_note)
+            .await?;
+
+        let decoded = self.decode_bolt12_invoice(&invoice).await?;
+
+        // BOLT12 invoices expire after two hours unless stated otherwise
+        let relative_expiry = decoded.invoice_relative_expiry.unwrap_or(7200);
+
+        let signed_invoice =
+            decode_bolt12(&invoice).ok_or_else(|| LightningRpcError::Bolt12Error {
+                failure_reason: "lightningd returned an invalid offer invoice".to_string(),
+            })?;
+
+        Ok(FetchOfferInvoiceResponse {
+            invoice,
+            signed_invoice,
+            payment_hash: decoded.invoice_payment_hash,
+            amount: Amount::from_msats(decoded.invoice_amount_msat),
+            expires_at_secs: decoded.invoice_created_at + relative_expiry,
+        })
+    }
+
+    async fn pay_offer_invoice(
+        &self,
+        invoice: String,
+        max_delay: u64,
+        max_fee: Amount,
+    ) -> Result<PayInvoiceResponse, LightningRpcError> {
+        let payment_hash = self
+            .decode_bolt12_invoice(&invoice)
+            .await?
+            .invoice_payment_hash;
+
+        self.pay_invoice(invoice, payment_hash, None, max_delay, max_fee)
+            .await
+    }
+
+    async fn list_offer_payments(
+        &self,
+        start_secs: u64,
+        end_secs: u64,
+    ) -> Result<Vec<OfferPayment>, LightningRpcError> {
+        let invoices: ClnInvoices =
+            self.rpc
+                .call("listinvoices", json!({}))
+                .await
+                .map_err(|e| LightningRpcError::Bolt12Error {
+                    failure_reason: format!("Failed to list invoices {e:?}"),
+                })?;
+
+        let payments = invoices
+            .invoices
+            .into_iter()
+            .filter(|invoice| invoice.status == "paid")
+            .filter(|invoice| {
+                invoice
+                    .paid_at
+                    .is_some_and(|paid_at| start_secs <= paid_at && paid_at < end_secs)
+            })
+            .filter_map(|invoice| {
+                Some(OfferPayment {
+                    // `lightningd` identifies offers by the same merkle root as
+                    // `bolt12_offer_id`
+                    offer_id: invoice.local_offer_id?,
+                    payment_hash: invoice.payment_hash,
+                    preimage: parse_preimage(invoice.payment_preimage.as_deref()?)?,
+                    amount: Amount::from_msats(invoice.amount_received_msat?),
+                })
+            })
+            .collect();
+
+        Ok(payments)
+    }
+
+    fn sync_wallet(&self) -> Result<(), LightningRpcError> {
+        // lightningd follows the chain on its own
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::str::FromStr;
+
+    use lightning::offers::offer::Offer;
+    use serde_json::json;
+
+    use super::{
+        ClnRpc, decode_bolt12, encode_hop_payload, encode_truncated, parse_short_channel_id,
+    };
+
+    #[test]
+    fn parses_short_channel_ids() {
+        assert_eq!(parse_short_channel_id("103x1x0"), Some(103 << 40 | 1 << 16));
+        assert_eq!(parse_short_channel_id("1x2x3x4"), None);
+        assert_eq!(parse_short_channel_id("103x1"), None);
+        assert_eq!(parse_short_channel_id("16777216x0x0"), None);
+    }
+
+    #[test]
+    fn encodes_hop_payloads() {
+        assert_eq!(encode_truncated(0), Vec::<u8>::new());
+        assert_eq!(encode_truncated(0x0100), vec![1, 0]);
+
+        let records = BTreeMap::from([(4, vec![0x12]), (2, vec![0x01])]);
+
+        assert_eq!(encode_hop_payload(&records), "06020101040112");
+
+        // Record types and lengths above 0xfc take more than a single byte
+        let records = BTreeMap::from([(5_482_373_484, vec![0; 32])]);
+
+        assert_eq!(
+            encode_hop_payload(&records),
+            format!("2aff{:016x}20{}", 5_482_373_484_u64, "00".repeat(32))
+        );
+    }
+
+    #[test]
+    fn decodes_bolt12_messages() {
+        // Test vector from the BOLT12 specification
+        let offer = "lno1pqps7sjqpgtyzm3qv4uxzmtsd3jjqer9wd3hy6tsw35k7msjzfpy7nz5yqcnygrfdej82um5wf5k2uckyypwa3eyt44h6txtxquqh7lz5djge4afgfjn7k4rgrkuag0jsd5xvxg";
+
+        let expected = Offer::from_str(offer).expect("Offer is valid");
+
+        assert_eq!(decode_bolt12(offer).as_deref(), Some(expected.as_ref()));
+
+        let (head, tail) = offer.split_at(40);
+
+        assert_eq!(
+            decode_bolt12(&format!("{head}+ {tail}")).as_deref(),
+            Some(expected.as_ref())
+        );
+
+        // Characters outside of the bech32 alphabet
+        assert_eq!(decode_bolt12(&offer.replace("xg", "xb")), None);
+
+        // Padding bits that are not zero
+        assert_eq!(decode_bolt12(&offer.replace("xg", "xx")), None);
+    }
+
+    #[test]
+    fn parses_partial_and_failed_responses() {
+        let response = br#"{"jsonrpc":"2.0","id":"a","result":{"id":"x"}}"#;
+
+        assert!(
+            ClnRpc::parse_response(&response[..response.len() - 5])
+                .expect("Incomplete response is not an error")
+                .is_none()
+        );
+        assert_eq!(
+            ClnRpc::parse_response(response).expect("Response is valid"),
+            Some(json!({ "id": "x" }))
+        );
+
+        let error =
+            br#"{"jsonrpc":"2.0","id":"a","error":{"code":-32601,"message":"Unknown command"}}"#;
+
+        assert!(ClnRpc::parse_response(error).is_err());
+    }
+}
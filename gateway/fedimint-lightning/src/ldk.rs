@@ -9,18 +9,21 @@
 use bitcoin::{FeeRate, Network, OutPoint};
 use fedimint_bip39::Mnemonic;
 use fedimint_core::envs::is_running_in_test_env;
+use fedimint_core::secp256k1::PublicKey;
 use fedimint_core::task::{TaskGroup, TaskHandle, block_in_place};
 use fedimint_core::util::{FmtCompact, SafeUrl};
 use fedimint_core::{Amount, BitcoinAmountOrAll, crit};
 use fedimint_gateway_common::{
     ChainSource, ConnectPeerRequest, GetInvoiceRequest, GetInvoiceResponse,
-    ListTransactionsResponse, NodeAddress, SetChannelFeesRequest,
+    ListTransactionsResponse, LspConfig, NodeAddress, SetChannelFeesRequest,
 };
 use fedimint_ln_common::contracts::Preimage;
 use fedimint_logging::{LOG_LIGHTNING, LOG_LIGHTNING_LDK};
+use ldk_node::CustomTlvRecord;
 use ldk_node::config::ChannelConfig;
 use ldk_node::lightning::ln::msgs::SocketAddress;
 use ldk_node::lightning::routing::gossip::{NodeAlias, NodeId};
+use ldk_node::liquidity::LSPS2ServiceConfig;
 use ldk_node::logger::{LogLevel, LogRecord, LogWriter};
 use ldk_node::payment::{PaymentDirection, PaymentKind, PaymentStatus, SendingParameters};
 use lightning::ln::channelmanager::PaymentId;
@@ -35,12 +38,38 @@
 use super::{ChannelInfo, ILnRpcClient, LightningRpcError, ListChannelsResponse, RouteHtlcStream};
 use crate::{
     CloseChannelsWithPeerRequest, CloseChannelsWithPeerResponse, CreateInvoiceRequest,
-    CreateInvoiceResponse, GetBalancesResponse, GetLnOnchainAddressResponse, GetNodeInfoResponse,
-    GetRouteHintsResponse, InterceptPaymentRequest, InterceptPaymentResponse, InvoiceDescription,
-    NO_INCOMING_CIRCUIT, OpenChannelRequest, OpenChannelResponse, PayInvoiceResponse,
-    PaymentAction, SendOnchainRequest, SendOnchainResponse,
+    CreateInvoiceResponse, FetchOfferInvoiceResponse, GetBalancesResponse,
+    GetLnOnchainAddressResponse, GetNodeInfoResponse, GetRouteHintsResponse,
+    InterceptPaymentRequest, InterceptPaymentResponse, InvoiceDescription, NO_INCOMING_CIRCUIT,
+    OfferPayment, OpenChannelRequest, OpenChannelResponse, PayInvoiceResponse, PaymentAction,
+    SendOnchainRequest, SendOnchainResponse,
 };
 
+/// Share of a JIT channel's capacity that the LSP adds on top of the payment
+/// that opens it, such that the client can receive further payments without
+/// another channel.
+const LSPS2_CHANNEL_OVER_PROVISIONING_PPM: u32 = 100_000;
+
+/// Maximum `to_self_delay` a JIT channel client may require of the LSP.
+const LSPS2_MAX_CLIENT_TO_SELF_DELAY: u32 = 2016;
+
+/// Maps the gateway's LSP configuration onto the LSPS2 service of `ldk-node`,
+/// which negotiates JIT channels with clients over peer messages and opens
+/// them when it intercepts the HTLCs for their intercept scids.
+fn lsps2_service_config(lsp: &LspConfig) -> LSPS2ServiceConfig {
+    LSPS2ServiceConfig {
+        require_token: None,
+        advertise_service: true,
+        channel_opening_fee_ppm: lsp.opening_fee_ppm,
+        channel_over_provisioning_ppm: LSPS2_CHANNEL_OVER_PROVISIONING_PPM,
+        min_channel_opening_fee_msat: lsp.min_opening_fee_sats.saturating_mul(1000),
+        min_channel_lifetime: lsp.min_channel_lifetime_blocks,
+        max_client_to_self_delay: LSPS2_MAX_CLIENT_TO_SELF_DELAY,
+        min_payment_size_msat: lsp.min_channel_sats.saturating_mul(1000),
+        max_payment_size_msat: lsp.max_channel_sats.saturating_mul(1000),
+    }
+}
+
 /// Forwards `ldk-node`'s log records into the gateway's `tracing` subscriber.
 ///
 /// By default `ldk-node` writes to its own append-only `ldk_node/ldk_node.log`
@@ -159,6 +188,7 @@ pub fn new(
         network: Network,
         lightning_port: u16,
         alias: String,
+        lsp: &LspConfig,
         mnemonic: Mnemonic,
         runtime: Arc<tokio::runtime::Runtime>,
     ) -> anyhow::Result<Self> {
@@ -219,7 +249,11 @@ pub fn new(
         };
         node_builder.set_storage_dir_path(data_dir_str.to_string());
 
-        info!(chain_source = %chain_source, data_dir = %data_dir_str, alias = %alias, "Starting LDK Node...");
+        if lsp.enabled {
+            node_builder.set_liquidity_provider_lsps2(lsps2_service_config(lsp));
+        }
+
+        info!(chain_source = %chain_source, data_dir = %data_dir_str, alias = %alias, lsp = lsp.enabled, "Starting LDK Node...");
         let node = Arc::new(node_builder.build()?);
         node.start_with_runtime(runtime).map_err(|err| {
             crit!(target: LOG_LIGHTNING, err = %err.fmt_compact(), "Failed to start LDK Node");
@@ -400,10 +434,15 @@ fn ldk_payment_result(
                 if let PaymentKind::Bolt11 {
                     preimage: Some(preimage),
                     ..
+                }
+                | PaymentKind::Spontaneous {
+                    preimage: Some(preimage),
+                    ..
                 } = payment_details.kind
                 {
                     Some(Ok(PayInvoiceResponse {
                         preimage: Preimage(preimage.0),
+                        routing_fee: payment_details.fee_paid_msat.map(Amount::from_msats),
                     }))
                 } else {
                     Some(Err(LightningRpcError::FailedPayment {
@@ -551,6 +590,84 @@ async fn pay(
         })
     }
 
+    async fn pay_keysend(
+        &self,
+        payee: PublicKey,
+        amount: Amount,
+        preimage: Preimage,
+        custom_records: BTreeMap<u64, Vec<u8>>,
+        max_delay: u64,
+        max_fee: Amount,
+    ) -> Result<PayInvoiceResponse, LightningRpcError> {
+        // `ldk-node` identifies spontaneous payments by their payment hash
+        let payment_id = PaymentId(*sha256::Hash::hash(&preimage.0).as_byte_array());
+
+        // Like for `pay()`, we lock by the payment hash and register the waiter
+        // before initiating the payment, since the spontaneous send of
+        // `ldk-node` is not idempotent either.
+        let _payment_lock_guard = self
+            .outbound_lightning_payment_lock_pool
+            .async_lock(payment_id)
+            .await;
+
+        let (payment_sender, payment_receiver) = oneshot::channel();
+        self.pending_payments
+            .write()
+            .await
+            .insert(payment_id, payment_sender);
+
+        if self.node.payment(&payment_id).is_none() {
+            let custom_tlvs = custom_records
+                .into_iter()
+                .map(|(type_num, value)| CustomTlvRecord { type_num, value })
+                .collect();
+
+            let sent_payment_id = match self
+                .node
+                .spontaneous_payment()
+                .send_with_preimage_and_custom_tlvs(
+                    amount.msats,
+                    payee,
+                    custom_tlvs,
+                    PaymentPreimage(preimage.0),
+                    Some(SendingParameters {
+                        max_total_routing_fee_msat: Some(Some(max_fee.msats)),
+                        max_total_cltv_expiry_delta: Some(max_delay as u32),
+                        max_path_count: None,
+                        max_channel_saturation_power_of_half: None,
+                    }),
+                ) {
+                Ok(sent_payment_id) => sent_payment_id,
+                Err(err) => {
+                    self.pending_payments.write().await.remove(&payment_id);
+                    return Err(LightningRpcError::FailedPayment {
+                        failure_reason: format!("LDK keysend failed to initialize: {err:?}"),
+                    });
+                }
+            };
+            assert_eq!(sent_payment_id, payment_id);
+        }
+
+        if let Some(result) = self.ldk_payment_result(payment_id) {
+            self.pending_payments.write().await.remove(&payment_id);
+            return result;
+        }
+
+        let _ = payment_receiver.await;
+
+        self.pending_payments.write().await.remove(&payment_id);
+        self.ldk_payment_result(payment_id).unwrap_or_else(|| {
+            Err(LightningRpcError::FailedPayment {
+                failure_reason: "LDK payment event fired without terminal payment status"
+                    .to_string(),
+            })
+        })
+    }
+
+    fn supports_keysend(&self) -> bool {
+        true
+    }
+
     async fn route_htlcs<'a>(
         mut self: Box<Self>,
         _task_group: &TaskGroup,
@@ -708,6 +825,7 @@ async fn open_channel(
             fee_rate_sats_per_vbyte,
             base_fee_msat,
             parts_per_million,
+            unannounced,
         }: OpenChannelRequest,
     ) -> Result<OpenChannelResponse, LightningRpcError> {
         let push_amount_msats_or = if push_amount_sats == 0 {
@@ -754,22 +872,31 @@ async fn open_channel(
 
         {
             let mut channels = self.pending_channels.write().await;
-            let user_channel_id = self
-                .node
-                .open_announced_channel(
+            let address = SocketAddress::from_str(&host).map_err(|e| {
+                LightningRpcError::FailedToConnectToPeer {
+                    failure_reason: e.to_string(),
+                }
+            })?;
+            let user_channel_id = if unannounced {
+                self.node.open_channel(
                     pubkey,
-                    SocketAddress::from_str(&host).map_err(|e| {
-                        LightningRpcError::FailedToConnectToPeer {
-                            failure_reason: e.to_string(),
-                        }
-                    })?,
+                    address,
                     channel_size_sats,
                     push_amount_msats_or,
                     channel_config,
                 )
-                .map_err(|e| LightningRpcError::FailedToOpenChannel {
-                    failure_reason: e.to_string(),
-                })?;
+            } else {
+                self.node.open_announced_channel(
+                    pubkey,
+                    address,
+                    channel_size_sats,
+                    push_amount_msats_or,
+                    channel_config,
+                )
+            }
+            .map_err(|e| LightningRpcError::FailedToOpenChannel {
+                failure_reason: e.to_string(),
+            })?;
 
             channels.insert(UserChannelId(user_channel_id), tx);
         }
@@ -1148,6 +1275,63 @@ async fn pay_offer(
         }
     }
 
+    // ldk-node only pays offers in one go, fetching the invoice internally, so
+    // clients cannot commit to the payment hash of the invoice beforehand.
+    async fn fetch_offer_invoice(
+        &self,
+        _offer: String,
+        _amount: Option<Amount>,
+        _payer_note: Option<String>,
+    ) -> Result<FetchOfferInvoiceResponse, LightningRpcError> {
+        Err(LightningRpcError::Bolt12Error {
+            failure_reason: "LDK cannot fetch an offer invoice without paying it".to_string(),
+        })
+    }
+
+    async fn pay_offer_invoice(
+        &self,
+        _invoice: String,
+        _max_delay: u64,
+        _max_fee: Amount,
+    ) -> Result<PayInvoiceResponse, LightningRpcError> {
+        Err(LightningRpcError::Bolt12Error {
+            failure_reason: "LDK cannot pay a previously fetched offer invoice".to_string(),
+        })
+    }
+
+    async fn list_offer_payments(
+        &self,
+        start_secs: u64,
+        end_secs: u64,
+    ) -> Result<Vec<OfferPayment>, LightningRpcError> {
+        let payments = self
+            .node
+            .list_payments_with_filter(|details| {
+                details.direction == PaymentDirection::Inbound
+                    && details.status == PaymentStatus::Succeeded
+                    && details.latest_update_timestamp >= start_secs
+                    && details.latest_update_timestamp < end_secs
+            })
+            .into_iter()
+            .filter_map(|details| match details.kind {
+                PaymentKind::Bolt12Offer {
+                    hash: Some(hash),
+                    preimage: Some(preimage),
+                    offer_id,
+                    ..
+                } => Some(OfferPayment {
+                    offer_id: hex::encode(offer_id.0),
+                    payment_hash: sha256::Hash::from_byte_array(hash.0),
+                    preimage: Preimage(preimage.0),
+                    amount: Amount::from_msats(details.amount_msat?),
+                }),
+                _ => None,
+            })
+            .collect();
+
+        Ok(payments)
+    }
+
     fn sync_wallet(&self) -> Result<(), LightningRpcError> {
         block_in_place(|| {
             let _ = self.node.sync_wallets();
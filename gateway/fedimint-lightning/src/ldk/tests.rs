@@ -2,10 +2,14 @@
 use std::sync::Arc;
 
 use fedimint_core::util::SafeUrl;
+use fedimint_gateway_common::LspConfig;
 use lightning::ln::channelmanager::PaymentId;
 use tokio::sync::{RwLock, oneshot};
 
-use super::{GatewayLdkClient, PendingPaymentWakeup, get_esplora_url};
+use super::{
+    GatewayLdkClient, LSPS2_CHANNEL_OVER_PROVISIONING_PPM, LSPS2_MAX_CLIENT_TO_SELF_DELAY,
+    PendingPaymentWakeup, get_esplora_url, lsps2_service_config,
+};
 
 #[test]
 fn verify_ldk_esplora_url() {
@@ -56,3 +60,33 @@ async fn wake_pending_payment_reports_waiter_state() {
     );
     assert!(pending_payments.read().await.is_empty());
 }
+
+#[test]
+fn lsps2_service_config_converts_sats_to_msats() {
+    let lsp = LspConfig {
+        enabled: true,
+        opening_fee_ppm: 5_000,
+        min_opening_fee_sats: 1_500,
+        min_channel_sats: 25_000,
+        max_channel_sats: 2_000_000,
+        min_channel_lifetime_blocks: 4_032,
+    };
+
+    let config = lsps2_service_config(&lsp);
+
+    assert_eq!(config.require_token, None);
+    assert!(config.advertise_service);
+    assert_eq!(config.channel_opening_fee_ppm, 5_000);
+    assert_eq!(
+        config.channel_over_provisioning_ppm,
+        LSPS2_CHANNEL_OVER_PROVISIONING_PPM
+    );
+    assert_eq!(config.min_channel_opening_fee_msat, 1_500_000);
+    assert_eq!(config.min_channel_lifetime, 4_032);
+    assert_eq!(
+        config.max_client_to_self_delay,
+        LSPS2_MAX_CLIENT_TO_SELF_DELAY
+    );
+    assert_eq!(config.min_payment_size_msat, 25_000_000);
+    assert_eq!(config.max_payment_size_msat, 2_000_000_000);
+}
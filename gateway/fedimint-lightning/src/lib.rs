@@ -1,7 +1,10 @@
+pub mod cln;
 pub mod ldk;
 pub mod lnd;
 pub mod metrics;
+pub mod simulated;
 
+use std::collections::BTreeMap;
 use std::fmt::Debug;
 use std::str::FromStr;
 use std::sync::Arc;
@@ -28,6 +31,7 @@
 use fedimint_metrics::HistogramExt as _;
 use futures::future::BoxFuture;
 use futures::stream::BoxStream;
+use lightning::offers::offer::Offer;
 use lightning_invoice::Bolt11Invoice;
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
@@ -91,6 +95,8 @@ pub enum LightningRpcError {
     // persisted in gateway client state machines: only append new variants.
     #[error("HTLC completion cannot reach the requested outcome: {failure_reason}")]
     HtlcCompletionRejected { failure_reason: String },
+    #[error("Failed to probe payment: {failure_reason}")]
+    FailedToProbe { failure_reason: String },
 }
 
 /// Represents an active connection to the lightning node.
@@ -168,6 +174,78 @@ async fn pay_private(
         })
     }
 
+    /// Pays `amount` of the invoice as one part of a multi-part payment whose
+    /// remaining parts are sent by other nodes, waiting for the payment to
+    /// complete and returning the preimage. The payee only releases the
+    /// preimage once all parts have arrived. This _must_ be idempotent for a
+    /// given invoice in the same way as [`ILnRpcClient::pay`]. If this is
+    /// implemented, [`ILnRpcClient::supports_pay_part`] must return true.
+    async fn pay_part(
+        &self,
+        _invoice: Bolt11Invoice,
+        _amount: Amount,
+        _max_delay: u64,
+        _max_fee: Amount,
+    ) -> Result<PayInvoiceResponse, LightningRpcError> {
+        Err(LightningRpcError::FailedPayment {
+            failure_reason: "Multi-part payments across nodes are not supported".to_string(),
+        })
+    }
+
+    /// Returns true if the lightning backend supports paying parts of a
+    /// multi-part payment via [`ILnRpcClient::pay_part`].
+    fn supports_pay_part(&self) -> bool {
+        false
+    }
+
+    /// Sends a spontaneous payment of `amount` to `payee` without an invoice,
+    /// waiting for the payment to complete. The preimage is chosen by the
+    /// sender and handed to the payee in the keysend TLV record of the onion,
+    /// alongside the given custom records. This _must_ be idempotent for a
+    /// given preimage in the same way as [`ILnRpcClient::pay`]. If this is
+    /// implemented, [`ILnRpcClient::supports_keysend`] must return true.
+    async fn pay_keysend(
+        &self,
+        _payee: PublicKey,
+        _amount: Amount,
+        _preimage: Preimage,
+        _custom_records: BTreeMap<u64, Vec<u8>>,
+        _max_delay: u64,
+        _max_fee: Amount,
+    ) -> Result<PayInvoiceResponse, LightningRpcError> {
+        Err(LightningRpcError::FailedPayment {
+            failure_reason: "Keysend payments are not supported".to_string(),
+        })
+    }
+
+    /// Returns true if the lightning backend supports spontaneous payments via
+    /// [`ILnRpcClient::pay_keysend`].
+    fn supports_keysend(&self) -> bool {
+        false
+    }
+
+    /// Finds a route for paying the invoice without paying it, returning the
+    /// estimated routing fee, the likelihood of the payment succeeding and
+    /// the CLTV delta the route requires. Only routes within `max_delay` and
+    /// `max_fee` are considered, just like for [`ILnRpcClient::pay`]. If this
+    /// is implemented, [`ILnRpcClient::supports_probe`] must return true.
+    async fn probe(
+        &self,
+        _invoice: Bolt11Invoice,
+        _max_delay: u64,
+        _max_fee: Amount,
+    ) -> Result<ProbeResponse, LightningRpcError> {
+        Err(LightningRpcError::FailedToProbe {
+            failure_reason: "Probing payments is not supported".to_string(),
+        })
+    }
+
+    /// Returns true if the lightning backend supports finding routes without
+    /// paying via [`ILnRpcClient::probe`].
+    fn supports_probe(&self) -> bool {
+        false
+    }
+
     /// Returns true if the lightning backend supports payments without full
     /// invoices. If this returns true, [`ILnRpcClient::pay_private`] must
     /// be implemented.
@@ -274,6 +352,39 @@ async fn pay_offer(
         payer_note: Option<String>,
     ) -> Result<Preimage, LightningRpcError>;
 
+    /// Requests an invoice for a BOLT12 offer from the payee without paying
+    /// it yet, such that the payment hash can be committed to before the
+    /// payment is made via [`ILnRpcClient::pay_offer_invoice`].
+    async fn fetch_offer_invoice(
+        &self,
+        offer: String,
+        amount: Option<Amount>,
+        payer_note: Option<String>,
+    ) -> Result<FetchOfferInvoiceResponse, LightningRpcError>;
+
+    /// Pays an invoice returned by [`ILnRpcClient::fetch_offer_invoice`],
+    /// waiting for the payment to complete and returning the preimage. This
+    /// _must_ be idempotent for a given invoice in the same way as
+    /// [`ILnRpcClient::pay`].
+    async fn pay_offer_invoice(
+        &self,
+        invoice: String,
+        max_delay: u64,
+        max_fee: Amount,
+    ) -> Result<PayInvoiceResponse, LightningRpcError>;
+
+    /// Lists the settled payments to BOLT12 offers created by this node whose
+    /// latest update falls between `start_secs` and `end_secs`.
+    async fn list_offer_payments(
+        &self,
+        _start_secs: u64,
+        _end_secs: u64,
+    ) -> Result<Vec<OfferPayment>, LightningRpcError> {
+        Err(LightningRpcError::Bolt12Error {
+            failure_reason: "Listing offer payments is not supported".to_string(),
+        })
+    }
+
     fn sync_wallet(&self) -> Result<(), LightningRpcError>;
 }
 
@@ -419,6 +530,54 @@ pub struct GetRouteHintsResponse {
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct PayInvoiceResponse {
     pub preimage: Preimage,
+    /// The fee paid to the nodes along the route, if the node reported it.
+    #[serde(default)]
+    pub routing_fee: Option<Amount>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ProbeResponse {
+    /// The routing fee of the cheapest route found.
+    pub routing_fee: Amount,
+    /// The probability between zero and one that a payment along the route
+    /// succeeds, if the backend estimates it.
+    pub success_probability: Option<f64>,
+    /// The number of blocks the route adds to the current block height for the
+    /// expiry of the payment, including the final CLTV delta of the invoice.
+    pub cltv_delta: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct FetchOfferInvoiceResponse {
+    /// The invoice in the encoding of the backend, to be passed back to
+    /// [`ILnRpcClient::pay_offer_invoice`] unchanged.
+    pub invoice: String,
+    /// The TLV encoding of the invoice including the payee's signature, which
+    /// the client verifies against the offer before funding the payment.
+    pub signed_invoice: Vec<u8>,
+    pub payment_hash: sha256::Hash,
+    pub amount: Amount,
+    pub expires_at_secs: u64,
+}
+
+/// A settled payment to a BOLT12 offer created by the lightning node.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct OfferPayment {
+    /// The id of the paid offer as returned by [`bolt12_offer_id`].
+    pub offer_id: String,
+    pub payment_hash: sha256::Hash,
+    pub preimage: Preimage,
+    pub amount: Amount,
+}
+
+/// Returns the hex encoded id of a BOLT12 offer, which is how offer payments
+/// refer to the offer they paid.
+pub fn bolt12_offer_id(offer: &str) -> Result<String, LightningRpcError> {
+    Offer::from_str(offer)
+        .map(|offer| hex::encode(offer.id().0))
+        .map_err(|_| LightningRpcError::Bolt12Error {
+            failure_reason: "Failed to parse Bolt12 Offer".to_string(),
+        })
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -585,6 +744,65 @@ async fn pay_private(
         )
     }
 
+    async fn pay_part(
+        &self,
+        invoice: Bolt11Invoice,
+        amount: Amount,
+        max_delay: u64,
+        max_fee: Amount,
+    ) -> Result<PayInvoiceResponse, LightningRpcError> {
+        tracked_call!(
+            self,
+            "pay_part",
+            self.inner
+                .pay_part(invoice, amount, max_delay, max_fee)
+                .await
+        )
+    }
+
+    async fn pay_keysend(
+        &self,
+        payee: PublicKey,
+        amount: Amount,
+        preimage: Preimage,
+        custom_records: BTreeMap<u64, Vec<u8>>,
+        max_delay: u64,
+        max_fee: Amount,
+    ) -> Result<PayInvoiceResponse, LightningRpcError> {
+        tracked_call!(
+            self,
+            "pay_keysend",
+            self.inner
+                .pay_keysend(payee, amount, preimage, custom_records, max_delay, max_fee)
+                .await
+        )
+    }
+
+    fn supports_keysend(&self) -> bool {
+        self.inner.supports_keysend()
+    }
+
+    fn supports_pay_part(&self) -> bool {
+        self.inner.supports_pay_part()
+    }
+
+    async fn probe(
+        &self,
+        invoice: Bolt11Invoice,
+        max_delay: u64,
+        max_fee: Amount,
+    ) -> Result<ProbeResponse, LightningRpcError> {
+        tracked_call!(
+            self,
+            "probe",
+            self.inner.probe(invoice, max_delay, max_fee).await
+        )
+    }
+
+    fn supports_probe(&self) -> bool {
+        self.inner.supports_probe()
+    }
+
     fn supports_private_payments(&self) -> bool {
         self.inner.supports_private_payments()
     }
@@ -729,6 +947,48 @@ async fn pay_offer(
         )
     }
 
+    async fn fetch_offer_invoice(
+        &self,
+        offer: String,
+        amount: Option<Amount>,
+        payer_note: Option<String>,
+    ) -> Result<FetchOfferInvoiceResponse, LightningRpcError> {
+        tracked_call!(
+            self,
+            "fetch_offer_invoice",
+            self.inner
+                .fetch_offer_invoice(offer, amount, payer_note)
+                .await
+        )
+    }
+
+    async fn pay_offer_invoice(
+        &self,
+        invoice: String,
+        max_delay: u64,
+        max_fee: Amount,
+    ) -> Result<PayInvoiceResponse, LightningRpcError> {
+        tracked_call!(
+            self,
+            "pay_offer_invoice",
+            self.inner
+                .pay_offer_invoice(invoice, max_delay, max_fee)
+                .await
+        )
+    }
+
+    async fn list_offer_payments(
+        &self,
+        start_secs: u64,
+        end_secs: u64,
+    ) -> Result<Vec<OfferPayment>, LightningRpcError> {
+        tracked_call!(
+            self,
+            "list_offer_payments",
+            self.inner.list_offer_payments(start_secs, end_secs).await
+        )
+    }
+
     fn sync_wallet(&self) -> Result<(), LightningRpcError> {
         tracked_call!(self, "sync_wallet", self.inner.sync_wallet())
     }
@@ -1,4 +1,4 @@
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, HashMap};
 use std::fmt::{self, Display};
 use std::str::FromStr;
 use std::sync::Arc;
@@ -18,8 +18,10 @@
 use fedimint_ln_common::PrunedInvoice;
 use fedimint_ln_common::contracts::Preimage;
 use fedimint_ln_common::route_hints::{RouteHint, RouteHintHop};
+use fedimint_lnv2_common::KEYSEND_RECORD_TYPE;
 use fedimint_logging::LOG_LIGHTNING;
 use hex::ToHex;
+use lightning_invoice::Bolt11Invoice;
 use secp256k1::PublicKey;
 use tokio::sync::mpsc;
 use tokio_stream::wrappers::ReceiverStream;
@@ -30,19 +32,20 @@
 };
 use tonic_lnd::lnrpc::channel_point::FundingTxid;
 use tonic_lnd::lnrpc::failure::FailureCode;
+use tonic_lnd::lnrpc::htlc_attempt::HtlcStatus;
 use tonic_lnd::lnrpc::invoice::InvoiceState;
 use tonic_lnd::lnrpc::payment::PaymentStatus;
 use tonic_lnd::lnrpc::policy_update_request::Scope as PolicyUpdateScope;
 use tonic_lnd::lnrpc::{
     ChanInfoRequest, ChannelBalanceRequest, ChannelPoint, CloseChannelRequest,
-    ConnectPeerRequest as LndConnectPeerRequest, FeeReportRequest, GetInfoRequest, Invoice,
-    InvoiceSubscription, LightningAddress, ListChannelsRequest, ListInvoiceRequest,
-    ListPaymentsRequest, ListPeersRequest, OpenChannelRequest, PolicyUpdateRequest,
-    SendCoinsRequest, UpdateFailure, WalletBalanceRequest,
+    ConnectPeerRequest as LndConnectPeerRequest, FeeLimit, FeeReportRequest, GetInfoRequest,
+    Invoice, InvoiceSubscription, LightningAddress, ListChannelsRequest, ListInvoiceRequest,
+    ListPaymentsRequest, ListPeersRequest, MppRecord, OpenChannelRequest, PolicyUpdateRequest,
+    QueryRoutesRequest, SendCoinsRequest, UpdateFailure, WalletBalanceRequest, fee_limit,
 };
 use tonic_lnd::routerrpc::{
     CircuitKey, ForwardHtlcInterceptResponse, ResolveHoldForwardAction, SendPaymentRequest,
-    TrackPaymentRequest,
+    SendToRouteRequest, TrackPaymentRequest,
 };
 use tonic_lnd::tonic::Code;
 use tonic_lnd::walletrpc::AddrRequest;
@@ -55,11 +58,11 @@
 };
 use crate::{
     CloseChannelsWithPeerRequest, CloseChannelsWithPeerResponse, CreateInvoiceRequest,
-    CreateInvoiceResponse, GetBalancesResponse, GetInvoiceRequest, GetInvoiceResponse,
-    GetLnOnchainAddressResponse, GetNodeInfoResponse, GetRouteHintsResponse,
+    CreateInvoiceResponse, FetchOfferInvoiceResponse, GetBalancesResponse, GetInvoiceRequest,
+    GetInvoiceResponse, GetLnOnchainAddressResponse, GetNodeInfoResponse, GetRouteHintsResponse,
     InterceptPaymentRequest, InterceptPaymentResponse, InvoiceDescription, NO_INCOMING_CIRCUIT,
-    OpenChannelResponse, PayInvoiceResponse, PaymentAction, SendOnchainRequest,
-    SendOnchainResponse, SetChannelFeesRequest,
+    OfferPayment, OpenChannelResponse, PayInvoiceResponse, PaymentAction, ProbeResponse,
+    SendOnchainRequest, SendOnchainResponse, SetChannelFeesRequest,
 };
 
 type HtlcSubscriptionSender = mpsc::Sender<InterceptPaymentRequest>;
@@ -1069,7 +1072,7 @@ async fn pay_private(
         );
 
         // If the payment exists, that means we've already tried to pay the invoice
-        let preimage: Vec<u8> = match self
+        let (preimage, routing_fee): (Vec<u8>, Option<Amount>) = match self
             .lookup_payment(invoice.payment_hash.to_byte_array().to_vec(), &mut client)
             .await?
         {
@@ -1079,11 +1082,12 @@ async fn pay_private(
                     payment_hash = %PrettyPaymentHash(&payment_hash),
                     "LND payment already exists for invoice",
                 );
-                hex::FromHex::from_hex(preimage.as_str()).map_err(|error| {
+                let preimage = hex::FromHex::from_hex(preimage.as_str()).map_err(|error| {
                     LightningRpcError::FailedPayment {
                         failure_reason: format!("Failed to convert preimage {error:?}"),
                     }
-                })?
+                })?;
+                (preimage, None)
             }
             _ => {
                 // LND API allows fee limits in the `i64` range, but we use `u64` for
@@ -1177,10 +1181,18 @@ async fn pay_private(
                                 payment_hash = %PrettyPaymentHash(&payment_hash),
                                 "LND payment succeeded for invoice",
                             );
-                            break hex::FromHex::from_hex(payment.payment_preimage.as_str())
-                                .map_err(|error| LightningRpcError::FailedPayment {
+                            let preimage = hex::FromHex::from_hex(
+                                payment.payment_preimage.as_str(),
+                            )
+                            .map_err(|error| {
+                                LightningRpcError::FailedPayment {
                                     failure_reason: format!("Failed to convert preimage {error:?}"),
-                                })?;
+                                }
+                            })?;
+                            break (
+                                preimage,
+                                u64::try_from(payment.fee_msat).ok().map(Amount::from_msats),
+                            );
                         }
                         Ok(Some(payment)) if payment.status() == PaymentStatus::InFlight => {
                             debug!(
@@ -1230,6 +1242,420 @@ async fn pay_private(
         };
         Ok(PayInvoiceResponse {
             preimage: Preimage(preimage.try_into().expect("Failed to create preimage")),
+            routing_fee,
+        })
+    }
+
+    /// Keysend payments are sent via `SendPaymentV2` like regular payments,
+    /// but without an invoice the preimage is attached to the onion of the
+    /// final hop as a custom record.
+    async fn pay_keysend(
+        &self,
+        payee: PublicKey,
+        amount: Amount,
+        preimage: Preimage,
+        custom_records: BTreeMap<u64, Vec<u8>>,
+        max_delay: u64,
+        max_fee: Amount,
+    ) -> Result<PayInvoiceResponse, LightningRpcError> {
+        let payment_hash = sha256::Hash::hash(&preimage.0).to_byte_array().to_vec();
+        info!(
+            target: LOG_LIGHTNING,
+            payment_hash = %PrettyPaymentHash(&payment_hash),
+            %amount,
+            "LND Paying keysend",
+        );
+        let mut client = self.connect().await?;
+
+        // If the payment exists, that means we've already tried to pay the payee
+        if self
+            .lookup_payment(payment_hash.clone(), &mut client)
+            .await?
+            .is_some()
+        {
+            info!(
+                target: LOG_LIGHTNING,
+                payment_hash = %PrettyPaymentHash(&payment_hash),
+                "LND payment already exists for keysend",
+            );
+            return Ok(PayInvoiceResponse {
+                preimage,
+                routing_fee: None,
+            });
+        }
+
+        let fee_limit_msat: i64 =
+            max_fee
+                .msats
+                .try_into()
+                .map_err(|error| LightningRpcError::FailedPayment {
+                    failure_reason: format!(
+                        "max_fee_msat exceeds valid LND fee limit ranges {error:?}"
+                    ),
+                })?;
+
+        let amt_msat =
+            amount
+                .msats
+                .try_into()
+                .map_err(|error| LightningRpcError::FailedPayment {
+                    failure_reason: format!("amount exceeds valid LND amount ranges {error:?}"),
+                })?;
+
+        let cltv_limit =
+            max_delay
+                .try_into()
+                .map_err(|error| LightningRpcError::FailedPayment {
+                    failure_reason: format!("max delay exceeds valid LND range {error:?}"),
+                })?;
+
+        let mut dest_custom_records = custom_records.into_iter().collect::<HashMap<_, _>>();
+        dest_custom_records.insert(KEYSEND_RECORD_TYPE, preimage.0.to_vec());
+
+        let payments = client
+            .router()
+            .send_payment_v2(SendPaymentRequest {
+                amt_msat,
+                dest: payee.serialize().to_vec(),
+                payment_hash: payment_hash.clone(),
+                dest_custom_records,
+                cltv_limit,
+                no_inflight_updates: true,
+                timeout_seconds: self.payment_timeout_secs,
+                fee_limit_msat,
+                time_pref: self.time_pref,
+                ..Default::default()
+            })
+            .await
+            .map_err(|status| LightningRpcError::FailedPayment {
+                failure_reason: format!("Failed to make keysend payment {status:?}"),
+            })?;
+
+        // Without inflight updates the only message is the final payment status
+        let payment = payments
+            .into_inner()
+            .message()
+            .await
+            .map_err(|error| LightningRpcError::FailedPayment {
+                failure_reason: format!("Failed to get payment status {error:?}"),
+            })?
+            .ok_or(LightningRpcError::FailedPayment {
+                failure_reason: "Failed to get payment status for keysend".to_string(),
+            })?;
+
+        if payment.status() != PaymentStatus::Succeeded {
+            warn!(
+                target: LOG_LIGHTNING,
+                payment_hash = %PrettyPaymentHash(&payment_hash),
+                status = %payment.status,
+                "LND keysend payment failed",
+            );
+            return Err(LightningRpcError::FailedPayment {
+                failure_reason: format!("{:?}", payment.failure_reason()),
+            });
+        }
+
+        info!(
+            target: LOG_LIGHTNING,
+            payment_hash = %PrettyPaymentHash(&payment_hash),
+            "LND keysend payment succeeded",
+        );
+
+        Ok(PayInvoiceResponse {
+            preimage,
+            routing_fee: u64::try_from(payment.fee_msat).ok().map(Amount::from_msats),
+        })
+    }
+
+    fn supports_keysend(&self) -> bool {
+        true
+    }
+
+    fn supports_pay_part(&self) -> bool {
+        true
+    }
+
+    /// LND cannot send a single part of a multi-part payment via
+    /// `SendPaymentV2`, so we query a route for the part ourselves and attach
+    /// the MPP record announcing the total amount to its final hop. The part
+    /// is attempted along this one route only.
+    async fn pay_part(
+        &self,
+        invoice: Bolt11Invoice,
+        amount: Amount,
+        max_delay: u64,
+        max_fee: Amount,
+    ) -> Result<PayInvoiceResponse, LightningRpcError> {
+        let total_amt_msat = invoice
+            .amount_milli_satoshis()
+            .ok_or(LightningRpcError::FailedPayment {
+                failure_reason: "Invoice has no amount".to_string(),
+            })?
+            .try_into()
+            .map_err(|error| LightningRpcError::FailedPayment {
+                failure_reason: format!("amount exceeds valid LND amount ranges {error:?}"),
+            })?;
+
+        let invoice = PrunedInvoice::new(&invoice, amount);
+        let payment_hash = invoice.payment_hash.to_byte_array().to_vec();
+        info!(
+            target: LOG_LIGHTNING,
+            payment_hash = %PrettyPaymentHash(&payment_hash),
+            %amount,
+            "LND Paying part of invoice",
+        );
+        let mut client = self.connect().await?;
+
+        // If the payment exists, that means we've already tried to pay our part
+        if let Some(preimage) = self
+            .lookup_payment(payment_hash.clone(), &mut client)
+            .await?
+        {
+            info!(
+                target: LOG_LIGHTNING,
+                payment_hash = %PrettyPaymentHash(&payment_hash),
+                "LND payment already exists for invoice part",
+            );
+            let preimage: Vec<u8> = hex::FromHex::from_hex(preimage.as_str()).map_err(|error| {
+                LightningRpcError::FailedPayment {
+                    failure_reason: format!("Failed to convert preimage {error:?}"),
+                }
+            })?;
+            return Ok(PayInvoiceResponse {
+                preimage: Preimage(preimage.try_into().expect("Failed to create preimage")),
+                routing_fee: None,
+            });
+        }
+
+        let fee_limit_msat: i64 =
+            max_fee
+                .msats
+                .try_into()
+                .map_err(|error| LightningRpcError::FailedPayment {
+                    failure_reason: format!(
+                        "max_fee_msat exceeds valid LND fee limit ranges {error:?}"
+                    ),
+                })?;
+
+        let amt_msat =
+            amount
+                .msats
+                .try_into()
+                .map_err(|error| LightningRpcError::FailedPayment {
+                    failure_reason: format!("amount exceeds valid LND amount ranges {error:?}"),
+                })?;
+
+        let final_cltv_delta = invoice.min_final_cltv_delta.try_into().map_err(|error| {
+            LightningRpcError::FailedPayment {
+                failure_reason: format!("final cltv delta exceeds valid LND range {error:?}"),
+            }
+        })?;
+
+        let cltv_limit =
+            max_delay
+                .try_into()
+                .map_err(|error| LightningRpcError::FailedPayment {
+                    failure_reason: format!("max delay exceeds valid LND range {error:?}"),
+                })?;
+
+        let dest_features = wire_features_to_lnd_feature_vec(&invoice.destination_features)
+            .map_err(|e| LightningRpcError::FailedPayment {
+                failure_reason: e.to_string(),
+            })?;
+
+        let mut route = client
+            .lightning()
+            .query_routes(QueryRoutesRequest {
+                pub_key: invoice.destination.to_string(),
+                amt_msat,
+                final_cltv_delta,
+                fee_limit: Some(FeeLimit {
+                    limit: Some(fee_limit::Limit::FixedMsat(fee_limit_msat)),
+                }),
+                route_hints: route_hints_to_lnd(&invoice.route_hints),
+                dest_features,
+                cltv_limit,
+                ..Default::default()
+            })
+            .await
+            .map_err(|status| LightningRpcError::FailedPayment {
+                failure_reason: format!("Failed to query route {status:?}"),
+            })?
+            .into_inner()
+            .routes
+            .into_iter()
+            .next()
+            .ok_or(LightningRpcError::FailedPayment {
+                failure_reason: "No route found for the payment part".to_string(),
+            })?;
+
+        let final_hop = route
+            .hops
+            .last_mut()
+            .ok_or(LightningRpcError::FailedPayment {
+                failure_reason: "Route has no hops".to_string(),
+            })?;
+
+        // The payee only holds the part until the remaining parts arrive if the
+        // final hop carries the payment secret and the total amount
+        final_hop.mpp_record = Some(MppRecord {
+            payment_addr: invoice.payment_secret.to_vec(),
+            total_amt_msat,
+        });
+
+        let attempt = client
+            .router()
+            .send_to_route_v2(SendToRouteRequest {
+                payment_hash: payment_hash.clone(),
+                route: Some(route),
+                skip_temp_err: false,
+                ..Default::default()
+            })
+            .await
+            .map_err(|status| {
+                warn!(
+                    target: LOG_LIGHTNING,
+                    status = %status,
+                    payment_hash = %PrettyPaymentHash(&payment_hash),
+                    "LND payment part request failed",
+                );
+                LightningRpcError::FailedPayment {
+                    failure_reason: format!("Failed to send payment part {status:?}"),
+                }
+            })?
+            .into_inner();
+
+        if attempt.status() != HtlcStatus::Succeeded {
+            warn!(
+                target: LOG_LIGHTNING,
+                payment_hash = %PrettyPaymentHash(&payment_hash),
+                failure = ?attempt.failure,
+                "LND payment part failed",
+            );
+            return Err(LightningRpcError::FailedPayment {
+                failure_reason: format!("{:?}", attempt.failure),
+            });
+        }
+
+        info!(
+            target: LOG_LIGHTNING,
+            payment_hash = %PrettyPaymentHash(&payment_hash),
+            "LND payment part succeeded for invoice",
+        );
+
+        let routing_fee = attempt
+            .route
+            .as_ref()
+            .map(|route| Amount::from_msats(route.total_fees_msat.try_into().unwrap_or(0)));
+
+        Ok(PayInvoiceResponse {
+            preimage: Preimage(attempt.preimage.try_into().map_err(|_| {
+                LightningRpcError::FailedPayment {
+                    failure_reason: "Invalid preimage length".to_string(),
+                }
+            })?),
+            routing_fee,
+        })
+    }
+
+    fn supports_probe(&self) -> bool {
+        true
+    }
+
+    async fn probe(
+        &self,
+        invoice: Bolt11Invoice,
+        max_delay: u64,
+        max_fee: Amount,
+    ) -> Result<ProbeResponse, LightningRpcError> {
+        let invoice =
+            PrunedInvoice::try_from(invoice).map_err(|_| LightningRpcError::FailedToProbe {
+                failure_reason: "Invoice has no amount".to_string(),
+            })?;
+
+        let mut client = self.connect().await?;
+
+        let amt_msat =
+            invoice
+                .amount
+                .msats
+                .try_into()
+                .map_err(|error| LightningRpcError::FailedToProbe {
+                    failure_reason: format!("amount exceeds valid LND amount ranges {error:?}"),
+                })?;
+
+        let fee_limit_msat: i64 =
+            max_fee
+                .msats
+                .try_into()
+                .map_err(|error| LightningRpcError::FailedToProbe {
+                    failure_reason: format!(
+                        "max_fee_msat exceeds valid LND fee limit ranges {error:?}"
+                    ),
+                })?;
+
+        let final_cltv_delta = invoice.min_final_cltv_delta.try_into().map_err(|error| {
+            LightningRpcError::FailedToProbe {
+                failure_reason: format!("final cltv delta exceeds valid LND range {error:?}"),
+            }
+        })?;
+
+        let cltv_limit =
+            max_delay
+                .try_into()
+                .map_err(|error| LightningRpcError::FailedToProbe {
+                    failure_reason: format!("max delay exceeds valid LND range {error:?}"),
+                })?;
+
+        let dest_features = wire_features_to_lnd_feature_vec(&invoice.destination_features)
+            .map_err(|e| LightningRpcError::FailedToProbe {
+                failure_reason: e.to_string(),
+            })?;
+
+        let block_height = client
+            .lightning()
+            .get_info(GetInfoRequest {})
+            .await
+            .map_err(|status| LightningRpcError::FailedToProbe {
+                failure_reason: format!("Failed to get node info {status:?}"),
+            })?
+            .into_inner()
+            .block_height;
+
+        // Querying routes consults LND's mission control, so the success
+        // probability reflects the outcome of previous payments and probes.
+        let response = client
+            .lightning()
+            .query_routes(QueryRoutesRequest {
+                pub_key: invoice.destination.to_string(),
+                amt_msat,
+                final_cltv_delta,
+                fee_limit: Some(FeeLimit {
+                    limit: Some(fee_limit::Limit::FixedMsat(fee_limit_msat)),
+                }),
+                route_hints: route_hints_to_lnd(&invoice.route_hints),
+                dest_features,
+                cltv_limit,
+                use_mission_control: true,
+                ..Default::default()
+            })
+            .await
+            .map_err(|status| LightningRpcError::FailedToProbe {
+                failure_reason: format!("Failed to query route {status:?}"),
+            })?
+            .into_inner();
+
+        let route = response
+            .routes
+            .first()
+            .ok_or(LightningRpcError::FailedToProbe {
+                failure_reason: "No route found for the payment".to_string(),
+            })?;
+
+        Ok(ProbeResponse {
+            routing_fee: Amount::from_msats(route.total_fees_msat.try_into().unwrap_or(0)),
+            success_probability: Some(response.success_prob),
+            cltv_delta: u64::from(route.total_time_lock.saturating_sub(block_height)),
         })
     }
 
@@ -1474,6 +1900,7 @@ async fn open_channel(
             fee_rate_sats_per_vbyte,
             base_fee_msat,
             parts_per_million,
+            unannounced,
         }: crate::OpenChannelRequest,
     ) -> Result<OpenChannelResponse, LightningRpcError> {
         let mut client = self.connect().await?;
@@ -1487,6 +1914,7 @@ async fn open_channel(
             node_pubkey: pubkey.serialize().to_vec(),
             local_funding_amount: channel_size_sats.try_into().expect("u64 -> i64"),
             push_sat: push_amount_sats.try_into().expect("u64 -> i64"),
+            private: unannounced,
             ..Default::default()
         };
         if let Some(rate) = fee_rate_sats_per_vbyte {
@@ -2101,6 +2529,38 @@ async fn pay_offer(
         })
     }
 
+    async fn fetch_offer_invoice(
+        &self,
+        _offer: String,
+        _amount: Option<Amount>,
+        _payer_note: Option<String>,
+    ) -> Result<FetchOfferInvoiceResponse, LightningRpcError> {
+        Err(LightningRpcError::Bolt12Error {
+            failure_reason: "LND Does not support Bolt12".to_string(),
+        })
+    }
+
+    async fn pay_offer_invoice(
+        &self,
+        _invoice: String,
+        _max_delay: u64,
+        _max_fee: Amount,
+    ) -> Result<PayInvoiceResponse, LightningRpcError> {
+        Err(LightningRpcError::Bolt12Error {
+            failure_reason: "LND Does not support Bolt12".to_string(),
+        })
+    }
+
+    async fn list_offer_payments(
+        &self,
+        _start_secs: u64,
+        _end_secs: u64,
+    ) -> Result<Vec<OfferPayment>, LightningRpcError> {
+        Err(LightningRpcError::Bolt12Error {
+            failure_reason: "LND Does not support Bolt12".to_string(),
+        })
+    }
+
     fn sync_wallet(&self) -> Result<(), LightningRpcError> {
         // There is nothing explicit needed to do for syncing an LND node
         Ok(())
@@ -0,0 +1,1760 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::num::NonZeroU64;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, SystemTime};
+
+use anyhow::{Context as _, ensure};
+use async_trait::async_trait;
+use bitcoin::hashes::{Hash, sha256};
+use bitcoin::{Address, CompressedPublicKey, Network, OutPoint, Txid};
+use fedimint_core::secp256k1::{PublicKey, SECP256K1, SecretKey};
+use fedimint_core::task::{TaskGroup, sleep};
+use fedimint_core::time::{duration_since_epoch, now};
+use fedimint_core::{Amount, BitcoinAmountOrAll};
+use fedimint_gateway_common::{
+    ConnectPeerRequest, ListTransactionsResponse, PaymentDetails, PaymentDirection, PaymentKind,
+    PaymentStatus,
+};
+use fedimint_ln_common::contracts::Preimage;
+use fedimint_ln_common::route_hints::{RouteHint, RouteHintHop};
+use fedimint_logging::LOG_LIGHTNING;
+use lightning::offers::offer::{Amount as OfferAmount, Offer, OfferBuilder, Quantity};
+use lightning_invoice::{Bolt11Invoice, Currency, InvoiceBuilder, PaymentSecret};
+use rand::rngs::StdRng;
+use rand::{Rng as _, RngCore as _, SeedableRng as _};
+use serde::{Deserialize, Serialize};
+use tokio::sync::{mpsc, oneshot};
+use tokio_stream::wrappers::ReceiverStream;
+use tracing::{debug, info};
+
+use super::{ChannelInfo, ILnRpcClient, LightningRpcError, ListChannelsResponse, RouteHtlcStream};
+use crate::{
+    CloseChannelsWithPeerRequest, CloseChannelsWithPeerResponse, CreateInvoiceRequest,
+    CreateInvoiceResponse, FetchOfferInvoiceResponse, GetBalancesResponse, GetInvoiceRequest,
+    GetInvoiceResponse, GetLnOnchainAddressResponse, GetNodeInfoResponse, GetRouteHintsResponse,
+    InterceptPaymentRequest, InterceptPaymentResponse, InvoiceDescription, NO_INCOMING_CIRCUIT,
+    OfferPayment, OpenChannelRequest, OpenChannelResponse, PayInvoiceResponse, PaymentAction,
+    ProbeResponse, SendOnchainRequest, SendOnchainResponse, SetChannelFeesRequest, bolt12_offer_id,
+};
+
+/// The minimum final CLTV delta of the invoices created in the simulation.
+const MIN_FINAL_CLTV_EXPIRY_DELTA: u64 = 18;
+
+/// The CLTV delta the virtual nodes require for forwarding into the gateway.
+const CLTV_EXPIRY_DELTA: u16 = 40;
+
+/// The number of blocks until HTLCs that virtual nodes send to the gateway
+/// expire.
+const HTLC_EXPIRY_DELTA: u32 = 144;
+
+/// The routing fee policy of channels the gateway opens without specifying
+/// one, matching the defaults of LND.
+const DEFAULT_BASE_FEE_MSAT: u64 = 1000;
+const DEFAULT_FEE_PPM: u64 = 1;
+
+/// The virtual size of the transactions of the simulated on-chain wallet,
+/// which the on-chain fees are derived from.
+const TX_VBYTES: u64 = 141;
+
+/// Describes the network of virtual nodes a [`GatewaySimulatedClient`] is
+/// connected to. Every virtual node has a single channel with the gateway.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SimulatedNetworkConfig {
+    /// Seed that the keys of the gateway and the virtual nodes as well as
+    /// random payment failures are derived from, such that runs with the same
+    /// configuration are reproducible.
+    pub seed: u64,
+    pub alias: String,
+    pub block_height: u32,
+    pub onchain_balance_sats: u64,
+    pub nodes: Vec<SimulatedNodeConfig>,
+    /// Rules for the outcome of payments from the gateway to the virtual
+    /// nodes, the first matching rule applies.
+    pub script: Vec<SimulatedPaymentRule>,
+}
+
+impl Default for SimulatedNetworkConfig {
+    fn default() -> Self {
+        SimulatedNetworkConfig {
+            seed: 0,
+            alias: "simulated-gateway".to_string(),
+            block_height: 100,
+            onchain_balance_sats: 10_000_000,
+            nodes: vec![
+                SimulatedNodeConfig {
+                    alias: "alice".to_string(),
+                    ..SimulatedNodeConfig::default()
+                },
+                SimulatedNodeConfig {
+                    alias: "bob".to_string(),
+                    local_balance_sats: 500_000,
+                    remote_balance_sats: 2_000_000,
+                    latency_ms: 500,
+                    ..SimulatedNodeConfig::default()
+                },
+                SimulatedNodeConfig {
+                    alias: "carol".to_string(),
+                    local_balance_sats: 50_000,
+                    remote_balance_sats: 50_000,
+                    failure_rate: 0.5,
+                    ..SimulatedNodeConfig::default()
+                },
+            ],
+            script: vec![],
+        }
+    }
+}
+
+impl SimulatedNetworkConfig {
+    /// Reads the configuration from a JSON file, omitted fields take their
+    /// default values.
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let config = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read simulated network from {}", path.display()))?;
+
+        serde_json::from_str(&config)
+            .with_context(|| format!("Invalid simulated network in {}", path.display()))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SimulatedNodeConfig {
+    pub alias: String,
+    /// The gateway's side of the channel with the node, which the gateway can
+    /// send to the node.
+    pub local_balance_sats: u64,
+    /// The node's side of the channel with the gateway, which the node can
+    /// send to the gateway.
+    pub remote_balance_sats: u64,
+    /// The probability between zero and one that a payment to the node fails.
+    pub failure_rate: f64,
+    /// How long every payment to or from the node takes.
+    pub latency_ms: u64,
+    /// The routing fee the node charges for forwarding payments to the
+    /// gateway, as advertised in the gateway's route hints.
+    pub base_fee_msat: u32,
+    pub fee_ppm: u32,
+}
+
+impl Default for SimulatedNodeConfig {
+    fn default() -> Self {
+        SimulatedNodeConfig {
+            alias: "node".to_string(),
+            local_balance_sats: 1_000_000,
+            remote_balance_sats: 1_000_000,
+            failure_rate: 0.0,
+            latency_ms: 0,
+            base_fee_msat: 1000,
+            fee_ppm: 100,
+        }
+    }
+}
+
+/// Overrides the outcome of the payments from the gateway that match all of
+/// the given conditions.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SimulatedPaymentRule {
+    /// The alias of the paid virtual node, any node if `None`.
+    #[serde(default)]
+    pub payee: Option<String>,
+    #[serde(default)]
+    pub min_amount_msat: Option<u64>,
+    #[serde(default)]
+    pub max_amount_msat: Option<u64>,
+    pub outcome: SimulatedOutcome,
+    /// The number of payments the rule applies to before it is removed, all
+    /// payments if `None`.
+    #[serde(default)]
+    pub times: Option<u32>,
+}
+
+impl SimulatedPaymentRule {
+    fn matches(&self, payee: &str, amount_msat: u64) -> bool {
+        self.payee.as_deref().is_none_or(|alias| alias == payee)
+            && self.min_amount_msat.is_none_or(|min| min <= amount_msat)
+            && self.max_amount_msat.is_none_or(|max| amount_msat <= max)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SimulatedOutcome {
+    /// The payment succeeds regardless of the failure rate of the payee, as
+    /// long as there is enough liquidity.
+    Succeed,
+    /// The payment fails.
+    Fail,
+    /// The payment takes the given time on top of the latency of the payee,
+    /// and succeeds or fails as usual.
+    Delay { millis: u64 },
+}
+
+#[derive(Debug)]
+struct SimulatedNode {
+    alias: String,
+    secret_key: SecretKey,
+    public_key: PublicKey,
+    failure_rate: f64,
+    latency_ms: u64,
+    base_fee_msat: u32,
+    fee_ppm: u32,
+}
+
+impl SimulatedNode {
+    /// The preimage of the node's invoice with the given payment secret, which
+    /// lets the node settle payments without keeping track of its invoices.
+    fn invoice_preimage(&self, payment_secret: &PaymentSecret) -> Preimage {
+        let mut engine = sha256::Hash::engine();
+        bitcoin::hashes::HashEngine::input(&mut engine, &self.secret_key.secret_bytes());
+        bitcoin::hashes::HashEngine::input(&mut engine, &payment_secret.0);
+
+        Preimage(sha256::Hash::from_engine(engine).to_byte_array())
+    }
+}
+
+#[derive(Debug)]
+struct SimulatedChannel {
+    node_index: usize,
+    short_channel_id: u64,
+    funding_outpoint: OutPoint,
+    local_msat: u64,
+    remote_msat: u64,
+    base_fee_msat: u64,
+    fee_ppm: u64,
+}
+
+#[derive(Debug)]
+struct SimulatedInvoice {
+    /// `None` for invoices the gateway settles through HTLC interception.
+    preimage: Option<Preimage>,
+    amount_msat: u64,
+    created_at: SystemTime,
+    status: PaymentStatus,
+    paid_at_secs: Option<u64>,
+}
+
+#[derive(Debug)]
+struct SimulatedPayment {
+    preimage: Option<Preimage>,
+    amount_msat: u64,
+    kind: PaymentKind,
+    status: PaymentStatus,
+    timestamp_secs: u64,
+}
+
+struct SimulationState {
+    script: Vec<SimulatedPaymentRule>,
+    rng: StdRng,
+    block_height: u32,
+    onchain_balance_sats: u64,
+    channels: Vec<SimulatedChannel>,
+    next_channel_index: u64,
+    invoices: BTreeMap<sha256::Hash, SimulatedInvoice>,
+    payments: BTreeMap<sha256::Hash, SimulatedPayment>,
+    offers: BTreeSet<String>,
+    node_offers: BTreeMap<String, usize>,
+    offer_payments: Vec<(u64, OfferPayment)>,
+    htlc_sender: Option<mpsc::Sender<InterceptPaymentRequest>>,
+    held_forwards: BTreeMap<(u64, u64), oneshot::Sender<PaymentAction>>,
+    held_invoices: BTreeMap<sha256::Hash, oneshot::Sender<PaymentAction>>,
+    next_htlc_id: u64,
+}
+
+impl SimulationState {
+    /// Returns the outcome the script dictates for a payment, consuming one
+    /// use of the matching rule.
+    fn take_scripted_outcome(&mut self, payee: &str, amount_msat: u64) -> Option<SimulatedOutcome> {
+        let index = self
+            .script
+            .iter()
+            .position(|rule| rule.matches(payee, amount_msat))?;
+
+        let rule = &mut self.script[index];
+        let outcome = rule.outcome.clone();
+
+        match rule.times {
+            Some(1) => {
+                self.script.remove(index);
+            }
+            Some(times) => rule.times = Some(times.saturating_sub(1)),
+            None => {}
+        }
+
+        Some(outcome)
+    }
+
+    fn channel_mut(&mut self, short_channel_id: u64) -> Option<&mut SimulatedChannel> {
+        self.channels
+            .iter_mut()
+            .find(|channel| channel.short_channel_id == short_channel_id)
+    }
+
+    fn random_preimage(&mut self) -> Preimage {
+        let mut preimage = [0; 32];
+        self.rng.fill_bytes(&mut preimage);
+        Preimage(preimage)
+    }
+}
+
+/// Where a payment from the gateway ends up in the simulated network.
+enum Route {
+    /// The payment is for an invoice of the gateway itself, which simulates a
+    /// virtual node paying the gateway.
+    Gateway,
+    /// The payment is routed through the gateway over the given short channel
+    /// id, which simulates a virtual node paying an LNv1 invoice.
+    Forward(u64),
+    /// The payment is for the virtual node with the given index.
+    Node(usize),
+}
+
+struct SimulatedNetworkInner {
+    network: Network,
+    alias: String,
+    secret_key: SecretKey,
+    public_key: PublicKey,
+    nodes: Vec<SimulatedNode>,
+    state: Mutex<SimulationState>,
+}
+
+/// Handle to the state of a simulated network, which is shared with the
+/// [`GatewaySimulatedClient`] connected to it. Tests use it to create invoices
+/// of the virtual nodes for the gateway to pay, and to script the outcome of
+/// payments while the gateway is running.
+#[derive(Clone)]
+pub struct SimulatedNetwork {
+    inner: Arc<SimulatedNetworkInner>,
+}
+
+impl fmt::Debug for SimulatedNetwork {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "SimulatedNetwork({})", self.inner.alias)
+    }
+}
+
+fn derive_secret_key(seed: u64, name: &str) -> SecretKey {
+    let hash = sha256::Hash::hash(format!("fedimint-simulated-ln/{seed}/{name}").as_bytes());
+
+    SecretKey::from_slice(&hash.to_byte_array()).expect("Hash is a valid secret key")
+}
+
+fn derive_txid(seed: u64, name: &str) -> Txid {
+    let hash = sha256::Hash::hash(format!("fedimint-simulated-tx/{seed}/{name}").as_bytes());
+
+    Txid::from_byte_array(hash.to_byte_array())
+}
+
+impl SimulatedNetwork {
+    /// Creates the simulated network, which refuses to run on any network but
+    /// regtest such that it cannot be mistaken for a real lightning node.
+    pub fn new(network: Network, config: SimulatedNetworkConfig) -> anyhow::Result<Self> {
+        ensure!(
+            network == Network::Regtest,
+            "The simulated lightning network only runs on regtest, not {network}"
+        );
+
+        let secret_key = derive_secret_key(config.seed, &config.alias);
+
+        let nodes = config
+            .nodes
+            .iter()
+            .map(|node| {
+                let secret_key = derive_secret_key(config.seed, &node.alias);
+
+                SimulatedNode {
+                    alias: node.alias.clone(),
+                    secret_key,
+                    public_key: secret_key.public_key(SECP256K1),
+                    failure_rate: node.failure_rate.clamp(0.0, 1.0),
+                    latency_ms: node.latency_ms,
+                    base_fee_msat: node.base_fee_msat,
+                    fee_ppm: node.fee_ppm,
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let mut state = SimulationState {
+            script: config.script.clone(),
+            rng: StdRng::seed_from_u64(config.seed),
+            block_height: config.block_height,
+            onchain_balance_sats: config.onchain_balance_sats,
+            channels: vec![],
+            next_channel_index: 0,
+            invoices: BTreeMap::new(),
+            payments: BTreeMap::new(),
+            offers: BTreeSet::new(),
+            node_offers: BTreeMap::new(),
+            offer_payments: vec![],
+            htlc_sender: None,
+            held_forwards: BTreeMap::new(),
+            held_invoices: BTreeMap::new(),
+            next_htlc_id: 0,
+        };
+
+        for (node_index, node) in config.nodes.iter().enumerate() {
+            Self::add_channel(
+                &mut state,
+                config.seed,
+                node_index,
+                node.local_balance_sats * 1000,
+                node.remote_balance_sats * 1000,
+                DEFAULT_BASE_FEE_MSAT,
+                DEFAULT_FEE_PPM,
+            );
+        }
+
+        Ok(SimulatedNetwork {
+            inner: Arc::new(SimulatedNetworkInner {
+                network,
+                alias: config.alias,
+                secret_key,
+                public_key: secret_key.public_key(SECP256K1),
+                nodes,
+                state: Mutex::new(state),
+            }),
+        })
+    }
+
+    fn add_channel(
+        state: &mut SimulationState,
+        seed: u64,
+        node_index: usize,
+        local_msat: u64,
+        remote_msat: u64,
+        base_fee_msat: u64,
+        fee_ppm: u64,
+    ) -> OutPoint {
+        let channel_index = state.next_channel_index;
+        state.next_channel_index += 1;
+
+        let funding_outpoint = OutPoint {
+            txid: derive_txid(seed, &format!("channel/{channel_index}")),
+            vout: 0,
+        };
+
+        state.channels.push(SimulatedChannel {
+            node_index,
+            // Every channel is confirmed in a block of its own
+            short_channel_id: ((u64::from(state.block_height) + channel_index) << 40) | (1 << 16),
+            funding_outpoint,
+            local_msat,
+            remote_msat,
+            base_fee_msat,
+            fee_ppm,
+        });
+
+        funding_outpoint
+    }
+
+    fn lock(&self) -> MutexGuard<'_, SimulationState> {
+        self.inner.state.lock().expect("Not poisoned")
+    }
+
+    fn node_index(&self, alias: &str) -> anyhow::Result<usize> {
+        self.inner
+            .nodes
+            .iter()
+            .position(|node| node.alias == alias)
+            .with_context(|| format!("No virtual node with alias {alias}"))
+    }
+
+    fn node_index_by_key(&self, public_key: PublicKey) -> Option<usize> {
+        self.inner
+            .nodes
+            .iter()
+            .position(|node| node.public_key == public_key)
+    }
+
+    /// Returns the public key of the virtual node with the given alias.
+    pub fn node_public_key(&self, alias: &str) -> anyhow::Result<PublicKey> {
+        Ok(self.inner.nodes[self.node_index(alias)?].public_key)
+    }
+
+    /// Creates an invoice of a virtual node, which the gateway can pay.
+    pub fn create_node_invoice(
+        &self,
+        alias: &str,
+        amount: Amount,
+        description: String,
+    ) -> anyhow::Result<Bolt11Invoice> {
+        let node = &self.inner.nodes[self.node_index(alias)?];
+
+        let payment_secret = PaymentSecret(rand::random());
+        let preimage = node.invoice_preimage(&payment_secret);
+
+        Ok(InvoiceBuilder::new(Currency::from(self.inner.network))
+            .description(description)
+            .payment_hash(sha256::Hash::hash(&preimage.0))
+            .payment_secret(payment_secret)
+            .amount_milli_satoshis(amount.msats)
+            .current_timestamp()
+            .min_final_cltv_expiry_delta(MIN_FINAL_CLTV_EXPIRY_DELTA)
+            .build_signed(|message| SECP256K1.sign_ecdsa_recoverable(message, &node.secret_key))?)
+    }
+
+    /// Creates a BOLT12 offer of a virtual node, which the gateway can pay.
+    pub fn create_node_offer(&self, alias: &str, amount: Option<Amount>) -> anyhow::Result<String> {
+        let node_index = self.node_index(alias)?;
+        let node = &self.inner.nodes[node_index];
+
+        let offer = self
+            .build_offer(node.public_key, amount, Some(alias.to_string()), None, None)
+            .map_err(|e| anyhow::anyhow!("{e}"))?;
+
+        self.lock().node_offers.insert(
+            bolt12_offer_id(&offer).map_err(|e| anyhow::anyhow!("{e}"))?,
+            node_index,
+        );
+
+        Ok(offer)
+    }
+
+    /// Appends a rule to the script, which applies to payments that no earlier
+    /// rule matches.
+    pub fn add_rule(&self, rule: SimulatedPaymentRule) {
+        self.lock().script.push(rule);
+    }
+
+    /// Mines the given number of blocks.
+    pub fn mine_blocks(&self, blocks: u32) {
+        let mut state = self.lock();
+        state.block_height = state.block_height.saturating_add(blocks);
+    }
+
+    fn build_offer(
+        &self,
+        signing_pubkey: PublicKey,
+        amount: Option<Amount>,
+        description: Option<String>,
+        expiry_secs: Option<u32>,
+        quantity: Option<u64>,
+    ) -> Result<String, LightningRpcError> {
+        let mut builder = OfferBuilder::new(signing_pubkey)
+            .chain(self.inner.network)
+            .description(description.unwrap_or_default());
+
+        if let Some(amount) = amount {
+            builder = builder.amount_msats(amount.msats);
+        }
+
+        if let Some(expiry_secs) = expiry_secs {
+            builder = builder.absolute_expiry(
+                duration_since_epoch() + Duration::from_secs(u64::from(expiry_secs)),
+            );
+        }
+
+        if let Some(quantity) = quantity {
+            let quantity =
+                NonZeroU64::new(quantity).ok_or_else(|| LightningRpcError::Bolt12Error {
+                    failure_reason: "Quantity must be positive".to_string(),
+                })?;
+
+            builder = builder.supported_quantity(Quantity::Bounded(quantity));
+        }
+
+        builder
+            .build()
+            .map(|offer| offer.to_string())
+            .map_err(|e| LightningRpcError::Bolt12Error {
+                failure_reason: format!("Failed to build offer {e:?}"),
+            })
+    }
+
+    fn route(
+        &self,
+        payee: PublicKey,
+        invoice: Option<&Bolt11Invoice>,
+    ) -> Result<Route, LightningRpcError> {
+        if payee == self.inner.public_key {
+            return Ok(Route::Gateway);
+        }
+
+        if let Some(short_channel_id) = invoice
+            .into_iter()
+            .flat_map(Bolt11Invoice::route_hints)
+            .flat_map(|route_hint| route_hint.0)
+            .find(|hop| hop.src_node_id == self.inner.public_key)
+            .map(|hop| hop.short_channel_id)
+        {
+            return Ok(Route::Forward(short_channel_id));
+        }
+
+        self.node_index_by_key(payee)
+            .map(Route::Node)
+            .ok_or_else(|| LightningRpcError::FailedPayment {
+                failure_reason: format!(
+                    "No route to {payee}, the simulated network only reaches its virtual nodes"
+                ),
+            })
+    }
+
+    /// Runs `attempt` at most once for a payment hash, such that repeated calls
+    /// for the same payment wait for and return the result of the first one.
+    async fn pay_once(
+        &self,
+        payment_hash: sha256::Hash,
+        amount_msat: u64,
+        kind: PaymentKind,
+        attempt: impl Future<Output = Result<Preimage, String>>,
+    ) -> Result<Preimage, LightningRpcError> {
+        loop {
+            {
+                let mut state = self.lock();
+
+                match state.payments.get(&payment_hash) {
+                    Some(payment) if payment.status == PaymentStatus::Succeeded => {
+                        return Ok(payment
+                            .preimage
+                            .clone()
+                            .expect("Succeeded payments have a preimage"));
+                    }
+                    Some(payment) if payment.status == PaymentStatus::Failed => {
+                        return Err(LightningRpcError::FailedPayment {
+                            failure_reason: "Payment has already failed".to_string(),
+                        });
+                    }
+                    Some(_) => {}
+                    None => {
+                        state.payments.insert(
+                            payment_hash,
+                            SimulatedPayment {
+                                preimage: None,
+                                amount_msat,
+                                kind,
+                                status: PaymentStatus::Pending,
+                                timestamp_secs: duration_since_epoch().as_secs(),
+                            },
+                        );
+
+                        break;
+                    }
+                }
+            }
+
+            debug!(target: LOG_LIGHTNING, %payment_hash, "Waiting for pending simulated payment");
+
+            sleep(Duration::from_millis(100)).await;
+        }
+
+        let result = attempt.await;
+
+        let mut state = self.lock();
+        let payment = state
+            .payments
+            .get_mut(&payment_hash)
+            .expect("Pending payment was inserted above");
+
+        match &result {
+            Ok(preimage) => {
+                payment.preimage = Some(preimage.clone());
+                payment.status = PaymentStatus::Succeeded;
+            }
+            Err(failure_reason) => {
+                debug!(target: LOG_LIGHTNING, %payment_hash, %failure_reason, "Simulated payment failed");
+                payment.status = PaymentStatus::Failed;
+            }
+        }
+
+        result.map_err(|failure_reason| LightningRpcError::FailedPayment { failure_reason })
+    }
+
+    /// Sends `amount_msat` to a virtual node, subject to its latency, the
+    /// script, its failure rate and the liquidity of the gateway's channels
+    /// with it.
+    async fn send_to_node(&self, node_index: usize, amount_msat: u64) -> Result<(), String> {
+        let node = &self.inner.nodes[node_index];
+
+        sleep(Duration::from_millis(node.latency_ms)).await;
+
+        let (outcome, failed_randomly) = {
+            let mut state = self.lock();
+            let outcome = state.take_scripted_outcome(&node.alias, amount_msat);
+
+            (outcome, state.rng.gen_bool(node.failure_rate))
+        };
+
+        match outcome {
+            Some(SimulatedOutcome::Fail) => {
+                return Err(format!("Payment to {} failed as scripted", node.alias));
+            }
+            Some(SimulatedOutcome::Delay { millis }) => {
+                sleep(Duration::from_millis(millis)).await;
+            }
+            Some(SimulatedOutcome::Succeed) | None => {}
+        }
+
+        if outcome != Some(SimulatedOutcome::Succeed) && failed_randomly {
+            return Err(format!("Temporary channel failure towards {}", node.alias));
+        }
+
+        let mut state = self.lock();
+
+        let channel = state
+            .channels
+            .iter_mut()
+            .filter(|channel| channel.node_index == node_index && amount_msat <= channel.local_msat)
+            .max_by_key(|channel| channel.local_msat)
+            .ok_or_else(|| format!("Insufficient outbound liquidity towards {}", node.alias))?;
+
+        channel.local_msat -= amount_msat;
+        channel.remote_msat += amount_msat;
+
+        Ok(())
+    }
+
+    /// Reserves inbound liquidity for a payment from the virtual node with the
+    /// most liquidity towards the gateway, returning the channel it arrives
+    /// over.
+    fn reserve_inbound(&self, amount_msat: u64) -> Result<(u64, usize), String> {
+        let mut state = self.lock();
+
+        let channel = state
+            .channels
+            .iter_mut()
+            .filter(|channel| amount_msat <= channel.remote_msat)
+            .max_by_key(|channel| channel.remote_msat)
+            .ok_or_else(|| "Insufficient inbound liquidity".to_string())?;
+
+        channel.remote_msat -= amount_msat;
+
+        Ok((channel.short_channel_id, channel.node_index))
+    }
+
+    fn release_inbound(&self, short_channel_id: u64, amount_msat: u64, settled: bool) {
+        if let Some(channel) = self.lock().channel_mut(short_channel_id) {
+            if settled {
+                channel.local_msat += amount_msat;
+            } else {
+                channel.remote_msat += amount_msat;
+            }
+        }
+    }
+
+    /// Simulates a virtual node paying the gateway, either for an invoice of
+    /// the gateway itself or for an HTLC the gateway is asked to forward over
+    /// `short_channel_id`. Payments the gateway does not know the preimage of
+    /// are handed to it through the stream of intercepted HTLCs.
+    async fn receive(
+        &self,
+        payment_hash: sha256::Hash,
+        amount_msat: u64,
+        short_channel_id: Option<u64>,
+    ) -> Result<Preimage, String> {
+        if short_channel_id.is_none() {
+            let paid_preimage = self
+                .lock()
+                .invoices
+                .get(&payment_hash)
+                .filter(|invoice| invoice.status == PaymentStatus::Succeeded)
+                .map(|invoice| invoice.preimage.clone());
+
+            if let Some(preimage) = paid_preimage {
+                return preimage.ok_or_else(|| "Invoice has already been paid".to_string());
+            }
+        }
+
+        let (incoming_chan_id, node_index) = self.reserve_inbound(amount_msat)?;
+
+        sleep(Duration::from_millis(
+            self.inner.nodes[node_index].latency_ms,
+        ))
+        .await;
+
+        let result = self
+            .deliver(
+                payment_hash,
+                amount_msat,
+                incoming_chan_id,
+                short_channel_id,
+            )
+            .await;
+
+        self.release_inbound(incoming_chan_id, amount_msat, result.is_ok());
+
+        let preimage = result?;
+
+        if let Some(invoice) = self.lock().invoices.get_mut(&payment_hash) {
+            invoice.preimage = Some(preimage.clone());
+            invoice.status = PaymentStatus::Succeeded;
+            invoice.paid_at_secs = Some(duration_since_epoch().as_secs());
+        }
+
+        Ok(preimage)
+    }
+
+    async fn deliver(
+        &self,
+        payment_hash: sha256::Hash,
+        amount_msat: u64,
+        incoming_chan_id: u64,
+        short_channel_id: Option<u64>,
+    ) -> Result<Preimage, String> {
+        let (request, receiver, htlc_sender) = {
+            let mut state = self.lock();
+            let (sender, receiver) = oneshot::channel();
+
+            let (request, htlc_sender) = if short_channel_id.is_some() {
+                let htlc_sender = state
+                    .htlc_sender
+                    .clone()
+                    .ok_or_else(|| "Gateway is not intercepting HTLCs".to_string())?;
+
+                let htlc_id = state.next_htlc_id;
+                state.next_htlc_id += 1;
+
+                state
+                    .held_forwards
+                    .insert((incoming_chan_id, htlc_id), sender);
+
+                let request = InterceptPaymentRequest {
+                    payment_hash,
+                    amount_msat,
+                    expiry: state.block_height + HTLC_EXPIRY_DELTA,
+                    incoming_chan_id,
+                    short_channel_id,
+                    htlc_id,
+                };
+
+                (request, htlc_sender)
+            } else {
+                let invoice = state
+                    .invoices
+                    .get(&payment_hash)
+                    .ok_or_else(|| "No invoice found for the payment hash".to_string())?;
+
+                if amount_msat < invoice.amount_msat {
+                    return Err("Payment is below the invoice amount".to_string());
+                }
+
+                // The gateway settles regular invoices on its own
+                if let Some(preimage) = &invoice.preimage {
+                    return Ok(preimage.clone());
+                }
+
+                let htlc_sender = state
+                    .htlc_sender
+                    .clone()
+                    .ok_or_else(|| "Gateway is not intercepting HTLCs".to_string())?;
+
+                state.held_invoices.insert(payment_hash, sender);
+
+                let (incoming_chan_id, htlc_id) = NO_INCOMING_CIRCUIT;
+
+                let request = InterceptPaymentRequest {
+                    payment_hash,
+                    amount_msat,
+                    expiry: state.block_height + HTLC_EXPIRY_DELTA,
+                    incoming_chan_id,
+                    short_channel_id: None,
+                    htlc_id,
+                };
+
+                (request, htlc_sender)
+            };
+
+            (request, receiver, htlc_sender)
+        };
+
+        htlc_sender
+            .send(request)
+            .await
+            .map_err(|_| "Gateway stopped intercepting HTLCs".to_string())?;
+
+        match receiver
+            .await
+            .map_err(|_| "Gateway dropped the HTLC".to_string())?
+        {
+            PaymentAction::Settle(preimage) => {
+                if sha256::Hash::hash(&preimage.0) != payment_hash {
+                    return Err("Gateway settled the HTLC with an invalid preimage".to_string());
+                }
+
+                Ok(preimage)
+            }
+            PaymentAction::Cancel => Err("Gateway failed the HTLC".to_string()),
+            PaymentAction::Forward => {
+                Err("Gateway forwarded the HTLC to a channel that does not exist".to_string())
+            }
+        }
+    }
+}
+
+/// A lightning backend that runs entirely in-process and simulates a small
+/// network of virtual nodes, each with a channel to the gateway. It needs
+/// neither a lightning node nor bitcoind, which lets the gateway be tested end
+/// to end offline.
+///
+/// The gateway can pay invoices and offers of the virtual nodes, see
+/// [`SimulatedNetwork`]. Paying an invoice of the gateway itself, or an LNv1
+/// invoice with a route hint through the gateway, simulates a virtual node
+/// paying the gateway: the HTLC is handed to the gateway through the stream of
+/// intercepted HTLCs, just like a real lightning node would.
+#[derive(Clone)]
+pub struct GatewaySimulatedClient {
+    network: SimulatedNetwork,
+}
+
+impl GatewaySimulatedClient {
+    pub fn new(network: SimulatedNetwork) -> Self {
+        info!(
+            target: LOG_LIGHTNING,
+            alias = %network.inner.alias,
+            nodes = network.inner.nodes.len(),
+            "Gateway configured to use a simulated lightning network",
+        );
+
+        GatewaySimulatedClient { network }
+    }
+
+    /// Returns the handle to the simulated network, which stays usable after
+    /// the client is handed to the gateway.
+    pub fn simulated_network(&self) -> SimulatedNetwork {
+        self.network.clone()
+    }
+}
+
+impl fmt::Debug for GatewaySimulatedClient {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "SimulatedClient")
+    }
+}
+
+#[async_trait]
+impl ILnRpcClient for GatewaySimulatedClient {
+    async fn info(&self) -> Result<GetNodeInfoResponse, LightningRpcError> {
+        Ok(GetNodeInfoResponse {
+            pub_key: self.network.inner.public_key,
+            alias: self.network.inner.alias.clone(),
+            network: self.network.inner.network.to_string(),
+            block_height: self.network.lock().block_height,
+            synced_to_chain: true,
+        })
+    }
+
+    async fn routehints(
+        &self,
+        num_route_hints: usize,
+    ) -> Result<GetRouteHintsResponse, LightningRpcError> {
+        let state = self.network.lock();
+
+        let mut channels = state.channels.iter().collect::<Vec<_>>();
+
+        // Take the channels with the largest incoming capacity
+        channels.sort_by_key(|channel| std::cmp::Reverse(channel.remote_msat));
+
+        let route_hints = channels
+            .into_iter()
+            .take(num_route_hints)
+            .map(|channel| {
+                let node = &self.network.inner.nodes[channel.node_index];
+
+                RouteHint(vec![RouteHintHop {
+                    src_node_id: node.public_key,
+                    short_channel_id: channel.short_channel_id,
+                    base_msat: node.base_fee_msat,
+                    proportional_millionths: node.fee_ppm,
+                    cltv_expiry_delta: CLTV_EXPIRY_DELTA,
+                    htlc_minimum_msat: Some(1000),
+                    htlc_maximum_msat: Some(channel.remote_msat),
+                }])
+            })
+            .collect();
+
+        Ok(GetRouteHintsResponse { route_hints })
+    }
+
+    async fn pay(
+        &self,
+        invoice: Bolt11Invoice,
+        _max_delay: u64,
+        _max_fee: Amount,
+    ) -> Result<PayInvoiceResponse, LightningRpcError> {
+        let payment_hash = *invoice.payment_hash();
+
+        let amount_msat =
+            invoice
+                .amount_milli_satoshis()
+                .ok_or_else(|| LightningRpcError::FailedPayment {
+                    failure_reason: "Invoice has no amount".to_string(),
+                })?;
+
+        let preimage = match self
+            .network
+            .route(invoice.recover_payee_pub_key(), Some(&invoice))?
+        {
+            Route::Gateway => self.network.receive(payment_hash, amount_msat, None).await,
+            Route::Forward(short_channel_id) => {
+                self.network
+                    .receive(payment_hash, amount_msat, Some(short_channel_id))
+                    .await
+            }
+            Route::Node(node_index) => {
+                let node = &self.network.inner.nodes[node_index];
+                let preimage = node.invoice_preimage(invoice.payment_secret());
+
+                if sha256::Hash::hash(&preimage.0) != payment_hash {
+                    return Err(LightningRpcError::FailedPayment {
+                        failure_reason: format!(
+                            "Invoice was not created by the virtual node {}",
+                            node.alias
+                        ),
+                    });
+                }
+
+                return self
+                    .network
+                    .pay_once(payment_hash, amount_msat, PaymentKind::Bolt11, async {
+                        self.network.send_to_node(node_index, amount_msat).await?;
+
+                        Ok(preimage)
+                    })
+                    .await
+                    .map(|preimage| PayInvoiceResponse {
+                        preimage,
+                        routing_fee: Some(Amount::ZERO),
+                    });
+            }
+        }
+        .map_err(|failure_reason| LightningRpcError::FailedPayment { failure_reason })?;
+
+        Ok(PayInvoiceResponse {
+            preimage,
+            routing_fee: Some(Amount::ZERO),
+        })
+    }
+
+    async fn pay_keysend(
+        &self,
+        payee: PublicKey,
+        amount: Amount,
+        preimage: Preimage,
+        _custom_records: BTreeMap<u64, Vec<u8>>,
+        _max_delay: u64,
+        _max_fee: Amount,
+    ) -> Result<PayInvoiceResponse, LightningRpcError> {
+        let Route::Node(node_index) = self.network.route(payee, None)? else {
+            return Err(LightningRpcError::FailedPayment {
+                failure_reason: "The gateway cannot pay itself".to_string(),
+            });
+        };
+
+        self.network
+            .pay_once(
+                sha256::Hash::hash(&preimage.0),
+                amount.msats,
+                PaymentKind::Bolt11,
+                async {
+                    self.network.send_to_node(node_index, amount.msats).await?;
+
+                    Ok(preimage)
+                },
+            )
+            .await
+            .map(|preimage| PayInvoiceResponse {
+                preimage,
+                routing_fee: Some(Amount::ZERO),
+            })
+    }
+
+    fn supports_keysend(&self) -> bool {
+        true
+    }
+
+    fn supports_probe(&self) -> bool {
+        true
+    }
+
+    async fn probe(
+        &self,
+        invoice: Bolt11Invoice,
+        _max_delay: u64,
+        _max_fee: Amount,
+    ) -> Result<ProbeResponse, LightningRpcError> {
+        let amount_msat =
+            invoice
+                .amount_milli_satoshis()
+                .ok_or_else(|| LightningRpcError::FailedToProbe {
+                    failure_reason: "Invoice has no amount".to_string(),
+                })?;
+
+        let Route::Node(node_index) = self
+            .network
+            .route(invoice.recover_payee_pub_key(), Some(&invoice))
+            .map_err(|e| LightningRpcError::FailedToProbe {
+                failure_reason: e.to_string(),
+            })?
+        else {
+            return Err(LightningRpcError::FailedToProbe {
+                failure_reason: "Payments through the gateway cannot be probed".to_string(),
+            });
+        };
+
+        let has_liquidity =
+            self.network.lock().channels.iter().any(|channel| {
+                channel.node_index == node_index && amount_msat <= channel.local_msat
+            });
+
+        if !has_liquidity {
+            return Err(LightningRpcError::FailedToProbe {
+                failure_reason: "Insufficient outbound liquidity".to_string(),
+            });
+        }
+
+        // Virtual nodes are direct peers of the gateway, so there is no routing fee
+        Ok(ProbeResponse {
+            routing_fee: Amount::ZERO,
+            success_probability: Some(1.0 - self.network.inner.nodes[node_index].failure_rate),
+            cltv_delta: invoice.min_final_cltv_expiry_delta(),
+        })
+    }
+
+    async fn route_htlcs<'a>(
+        self: Box<Self>,
+        _task_group: &TaskGroup,
+    ) -> Result<(RouteHtlcStream<'a>, Arc<dyn ILnRpcClient>), LightningRpcError> {
+        const CHANNEL_SIZE: usize = 100;
+
+        let (gateway_sender, gateway_receiver) =
+            mpsc::channel::<InterceptPaymentRequest>(CHANNEL_SIZE);
+
+        self.network.lock().htlc_sender = Some(gateway_sender);
+
+        Ok((
+            Box::pin(ReceiverStream::new(gateway_receiver)),
+            Arc::new(*self),
+        ))
+    }
+
+    async fn complete_htlc(&self, htlc: InterceptPaymentResponse) -> Result<(), LightningRpcError> {
+        let mut state = self.network.lock();
+
+        let sender = match htlc.incoming_circuit() {
+            Some(circuit) => state.held_forwards.remove(&circuit),
+            None => state.held_invoices.remove(&htlc.payment_hash),
+        }
+        .ok_or_else(|| LightningRpcError::FailedToCompleteHtlc {
+            failure_reason: format!(
+                "No HTLC held for payment hash {} on channel {}",
+                htlc.payment_hash, htlc.incoming_chan_id
+            ),
+        })?;
+
+        // The paying virtual node only goes away if the payment was abandoned
+        let _ = sender.send(htlc.action);
+
+        Ok(())
+    }
+
+    async fn create_invoice(
+        &self,
+        create_invoice_request: CreateInvoiceRequest,
+    ) -> Result<CreateInvoiceResponse, LightningRpcError> {
+        // Without a payment hash the gateway is paid directly and knows the
+        // preimage, otherwise the preimage is held by the federation
+        let (payment_hash, preimage) = match create_invoice_request.payment_hash {
+            Some(payment_hash) => (payment_hash, None),
+            None => {
+                let preimage = self.network.lock().random_preimage();
+
+                (sha256::Hash::hash(&preimage.0), Some(preimage))
+            }
+        };
+
+        let builder = InvoiceBuilder::new(Currency::from(self.network.inner.network));
+
+        let builder = match create_invoice_request.description {
+            Some(InvoiceDescription::Direct(description)) => builder.description(description),
+            Some(InvoiceDescription::Hash(hash)) => builder.description_hash(hash),
+            None => builder.description(String::new()),
+        };
+
+        let invoice = builder
+            .payment_hash(payment_hash)
+            .payment_secret(PaymentSecret(rand::random()))
+            .amount_milli_satoshis(create_invoice_request.amount_msat)
+            .current_timestamp()
+            .min_final_cltv_expiry_delta(MIN_FINAL_CLTV_EXPIRY_DELTA)
+            .expiry_time(Duration::from_secs(u64::from(
+                create_invoice_request.expiry_secs,
+            )))
+            .build_signed(|message| {
+                SECP256K1.sign_ecdsa_recoverable(message, &self.network.inner.secret_key)
+            })
+            .map_err(|e| LightningRpcError::FailedToGetInvoice {
+                failure_reason: format!("Failed to build invoice {e:?}"),
+            })?;
+
+        self.network.lock().invoices.insert(
+            payment_hash,
+            SimulatedInvoice {
+                preimage,
+                amount_msat: create_invoice_request.amount_msat,
+                created_at: now(),
+                status: PaymentStatus::Pending,
+                paid_at_secs: None,
+            },
+        );
+
+        Ok(CreateInvoiceResponse {
+            invoice: invoice.to_string(),
+        })
+    }
+
+    async fn get_ln_onchain_address(
+        &self,
+    ) -> Result<GetLnOnchainAddressResponse, LightningRpcError> {
+        let address = Address::p2wpkh(
+            &CompressedPublicKey(self.network.inner.public_key),
+            self.network.inner.network,
+        );
+
+        Ok(GetLnOnchainAddressResponse {
+            address: address.to_string(),
+        })
+    }
+
+    async fn send_onchain(
+        &self,
+        payload: SendOnchainRequest,
+    ) -> Result<SendOnchainResponse, LightningRpcError> {
+        payload
+            .address
+            .require_network(self.network.inner.network)
+            .map_err(|e| LightningRpcError::FailedToWithdrawOnchain {
+                failure_reason: format!("Invalid address {e:?}"),
+            })?;
+
+        let fee_sats = payload.fee_rate_sats_per_vbyte * TX_VBYTES;
+
+        let mut state = self.network.lock();
+
+        let amount_sats = match payload.amount {
+            BitcoinAmountOrAll::All => state.onchain_balance_sats.saturating_sub(fee_sats),
+            BitcoinAmountOrAll::Amount(amount) => amount.to_sat(),
+        };
+
+        state.onchain_balance_sats = state
+            .onchain_balance_sats
+            .checked_sub(amount_sats + fee_sats)
+            .filter(|_| amount_sats > 0)
+            .ok_or_else(|| LightningRpcError::FailedToWithdrawOnchain {
+                failure_reason: "Insufficient on-chain funds".to_string(),
+            })?;
+
+        let txid = state.random_preimage();
+
+        Ok(SendOnchainResponse {
+            txid: Txid::from_byte_array(txid.0).to_string(),
+        })
+    }
+
+    async fn open_channel(
+        &self,
+        payload: OpenChannelRequest,
+    ) -> Result<OpenChannelResponse, LightningRpcError> {
+        let node_index = self
+            .network
+            .node_index_by_key(payload.pubkey)
+            .ok_or_else(|| LightningRpcError::FailedToOpenChannel {
+                failure_reason: format!("{} is not a virtual node", payload.pubkey),
+            })?;
+
+        if payload.push_amount_sats > payload.channel_size_sats {
+            return Err(LightningRpcError::FailedToOpenChannel {
+                failure_reason: "Push amount exceeds the channel size".to_string(),
+            });
+        }
+
+        let fee_sats = payload.fee_rate_sats_per_vbyte.unwrap_or(1) * TX_VBYTES;
+
+        let mut state = self.network.lock();
+
+        state.onchain_balance_sats = state
+            .onchain_balance_sats
+            .checked_sub(payload.channel_size_sats + fee_sats)
+            .ok_or_else(|| LightningRpcError::FailedToOpenChannel {
+                failure_reason: "Insufficient on-chain funds".to_string(),
+            })?;
+
+        let seed = state.rng.r#gen();
+
+        let funding_outpoint = SimulatedNetwork::add_channel(
+            &mut state,
+            seed,
+            node_index,
+            (payload.channel_size_sats - payload.push_amount_sats) * 1000,
+            payload.push_amount_sats * 1000,
+            payload.base_fee_msat.unwrap_or(DEFAULT_BASE_FEE_MSAT),
+            payload.parts_per_million.unwrap_or(DEFAULT_FEE_PPM),
+        );
+
+        Ok(OpenChannelResponse {
+            funding_txid: funding_outpoint.txid.to_string(),
+        })
+    }
+
+    async fn connect_peer(&self, payload: ConnectPeerRequest) -> Result<(), LightningRpcError> {
+        self.network
+            .node_index_by_key(payload.node_address.pubkey)
+            .map(|_| ())
+            .ok_or_else(|| LightningRpcError::FailedToConnectToPeer {
+                failure_reason: format!("{} is not a virtual node", payload.node_address.pubkey),
+            })
+    }
+
+    async fn close_channels_with_peer(
+        &self,
+        payload: CloseChannelsWithPeerRequest,
+    ) -> Result<CloseChannelsWithPeerResponse, LightningRpcError> {
+        let node_index = self.network.node_index_by_key(payload.pubkey);
+
+        let mut state = self.network.lock();
+
+        let (closed, open) = std::mem::take(&mut state.channels)
+            .into_iter()
+            .partition::<Vec<_>, _>(|channel| Some(channel.node_index) == node_index);
+
+        state.channels = open;
+
+        let closing_fee_sats = payload.sats_per_vbyte.unwrap_or(1) * TX_VBYTES;
+
+        for channel in &closed {
+            state.onchain_balance_sats +=
+                (channel.local_msat / 1000).saturating_sub(closing_fee_sats);
+        }
+
+        Ok(CloseChannelsWithPeerResponse {
+            num_channels_closed: closed.len() as u32,
+        })
+    }
+
+    async fn list_channels(&self) -> Result<ListChannelsResponse, LightningRpcError> {
+        let state = self.network.lock();
+
+        let channels = state
+            .channels
+            .iter()
+            .map(|channel| {
+                let node = &self.network.inner.nodes[channel.node_index];
+
+                ChannelInfo {
+                    remote_pubkey: node.public_key,
+                    channel_size_sats: (channel.local_msat + channel.remote_msat) / 1000,
+                    outbound_liquidity_sats: channel.local_msat / 1000,
+                    inbound_liquidity_sats: channel.remote_msat / 1000,
+                    is_active: true,
+                    funding_outpoint: Some(channel.funding_outpoint),
+                    remote_node_alias: Some(node.alias.clone()),
+                    remote_address: None,
+                    base_fee_msat: Some(channel.base_fee_msat),
+                    parts_per_million: Some(channel.fee_ppm),
+                }
+            })
+            .collect();
+
+        Ok(ListChannelsResponse { channels })
+    }
+
+    async fn set_channel_fees(
+        &self,
+        payload: SetChannelFeesRequest,
+    ) -> Result<(), LightningRpcError> {
+        let mut state = self.network.lock();
+
+        let channel = state
+            .channels
+            .iter_mut()
+            .find(|channel| channel.funding_outpoint == payload.funding_outpoint)
+            .ok_or_else(|| LightningRpcError::FailedToSetChannelFees {
+                failure_reason: format!(
+                    "No channel found with funding outpoint {}",
+                    payload.funding_outpoint
+                ),
+            })?;
+
+        channel.base_fee_msat = payload.base_fee_msat;
+        channel.fee_ppm = payload.parts_per_million;
+
+        Ok(())
+    }
+
+    async fn get_balances(&self) -> Result<GetBalancesResponse, LightningRpcError> {
+        let state = self.network.lock();
+
+        Ok(GetBalancesResponse {
+            onchain_balance_sats: state.onchain_balance_sats,
+            lightning_balance_msats: state
+                .channels
+                .iter()
+                .map(|channel| channel.local_msat)
+                .sum(),
+            inbound_lightning_liquidity_msats: state
+                .channels
+                .iter()
+                .map(|channel| channel.remote_msat)
+                .sum(),
+        })
+    }
+
+    async fn get_invoice(
+        &self,
+        get_invoice_request: GetInvoiceRequest,
+    ) -> Result<Option<GetInvoiceResponse>, LightningRpcError> {
+        Ok(self
+            .network
+            .lock()
+            .invoices
+            .get(&get_invoice_request.payment_hash)
+            .map(|invoice| GetInvoiceResponse {
+                preimage: invoice
+                    .preimage
+                    .as_ref()
+                    .filter(|_| invoice.status == PaymentStatus::Succeeded)
+                    .map(|preimage| hex::encode(preimage.0)),
+                payment_hash: Some(get_invoice_request.payment_hash),
+                amount: Amount::from_msats(invoice.amount_msat),
+                created_at: invoice.created_at,
+                status: invoice.status.clone(),
+            }))
+    }
+
+    async fn list_transactions(
+        &self,
+        start_secs: u64,
+        end_secs: u64,
+    ) -> Result<ListTransactionsResponse, LightningRpcError> {
+        let state = self.network.lock();
+
+        let outgoing = state
+            .payments
+            .iter()
+            .map(|(payment_hash, payment)| PaymentDetails {
+                payment_hash: Some(*payment_hash),
+                preimage: payment
+                    .preimage
+                    .as_ref()
+                    .map(|preimage| hex::encode(preimage.0)),
+                payment_kind: payment.kind.clone(),
+                amount: Amount::from_msats(payment.amount_msat),
+                direction: PaymentDirection::Outbound,
+                status: payment.status.clone(),
+                timestamp_secs: payment.timestamp_secs,
+            });
+
+        let incoming = state.invoices.iter().filter_map(|(payment_hash, invoice)| {
+            Some(PaymentDetails {
+                payment_hash: Some(*payment_hash),
+                preimage: invoice
+                    .preimage
+                    .as_ref()
+                    .map(|preimage| hex::encode(preimage.0)),
+                payment_kind: PaymentKind::Bolt11,
+                amount: Amount::from_msats(invoice.amount_msat),
+                direction: PaymentDirection::Inbound,
+                status: PaymentStatus::Succeeded,
+                timestamp_secs: invoice.paid_at_secs?,
+            })
+        });
+
+        let offers = state
+            .offer_payments
+            .iter()
+            .map(|(timestamp_secs, payment)| PaymentDetails {
+                payment_hash: Some(payment.payment_hash),
+                preimage: Some(hex::encode(payment.preimage.0)),
+                payment_kind: PaymentKind::Bolt12Offer,
+                amount: payment.amount,
+                direction: PaymentDirection::Inbound,
+                status: PaymentStatus::Succeeded,
+                timestamp_secs: *timestamp_secs,
+            });
+
+        let mut transactions = outgoing
+            .chain(incoming)
+            .chain(offers)
+            .filter(|payment| {
+                start_secs <= payment.timestamp_secs && payment.timestamp_secs < end_secs
+            })
+            .collect::<Vec<_>>();
+
+        transactions.sort_by_key(|payment| payment.timestamp_secs);
+
+        Ok(ListTransactionsResponse { transactions })
+    }
+
+    fn create_offer(
+        &self,
+        amount: Option<Amount>,
+        description: Option<String>,
+        expiry_secs: Option<u32>,
+        quantity: Option<u64>,
+    ) -> Result<String, LightningRpcError> {
+        let offer = self.network.build_offer(
+            self.network.inner.public_key,
+            amount,
+            description,
+            expiry_secs,
+            quantity,
+        )?;
+
+        self.network.lock().offers.insert(bolt12_offer_id(&offer)?);
+
+        Ok(offer)
+    }
+
+    async fn pay_offer(
+        &self,
+        offer: String,
+        quantity: Option<u64>,
+        amount: Option<Amount>,
+        _payer_note: Option<String>,
+    ) -> Result<Preimage, LightningRpcError> {
+        let offer_id = bolt12_offer_id(&offer)?;
+
+        let parsed = Offer::from_str(&offer).map_err(|_| LightningRpcError::Bolt12Error {
+            failure_reason: "Failed to parse Bolt12 Offer".to_string(),
+        })?;
+
+        let amount_msat = match (parsed.amount(), amount) {
+            (_, Some(amount)) => amount.msats,
+            (Some(OfferAmount::Bitcoin { amount_msats }), None) => {
+                amount_msats * quantity.unwrap_or(1)
+            }
+            _ => {
+                return Err(LightningRpcError::Bolt12Error {
+                    failure_reason: "Offer has no amount in bitcoin".to_string(),
+                });
+            }
+        };
+
+        let (is_own_offer, node_index, preimage) = {
+            let mut state = self.network.lock();
+
+            (
+                state.offers.contains(&offer_id),
+                state.node_offers.get(&offer_id).copied(),
+                state.random_preimage(),
+            )
+        };
+
+        let payment_hash = sha256::Hash::hash(&preimage.0);
+
+        // Paying an offer of the gateway simulates a virtual node paying it
+        if is_own_offer {
+            let (short_channel_id, _) = self
+                .network
+                .reserve_inbound(amount_msat)
+                .map_err(|failure_reason| LightningRpcError::FailedPayment { failure_reason })?;
+
+            self.network
+                .release_inbound(short_channel_id, amount_msat, true);
+
+            self.network.lock().offer_payments.push((
+                duration_since_epoch().as_secs(),
+                OfferPayment {
+                    offer_id,
+                    payment_hash,
+                    preimage: preimage.clone(),
+                    amount: Amount::from_msats(amount_msat),
+                },
+            ));
+
+            return Ok(preimage);
+        }
+
+        let node_index = node_index.ok_or_else(|| LightningRpcError::FailedPayment {
+            failure_reason: "Offer was not created by a virtual node".to_string(),
+        })?;
+
+        self.network
+            .pay_once(payment_hash, amount_msat, PaymentKind::Bolt12Offer, async {
+                self.network.send_to_node(node_index, amount_msat).await?;
+
+                Ok(preimage)
+            })
+            .await
+    }
+
+    async fn fetch_offer_invoice(
+        &self,
+        _offer: String,
+        _amount: Option<Amount>,
+        _payer_note: Option<String>,
+    ) -> Result<FetchOfferInvoiceResponse, LightningRpcError> {
+        Err(LightningRpcError::Bolt12Error {
+            failure_reason: "The simulated network does not issue offer invoices".to_string(),
+        })
+    }
+
+    async fn pay_offer_invoice(
+        &self,
+        _invoice: String,
+        _max_delay: u64,
+        _max_fee: Amount,
+    ) -> Result<PayInvoiceResponse, LightningRpcError> {
+        Err(LightningRpcError::Bolt12Error {
+            failure_reason: "The simulated network does not issue offer invoices".to_string(),
+        })
+    }
+
+    async fn list_offer_payments(
+        &self,
+        start_secs: u64,
+        end_secs: u64,
+    ) -> Result<Vec<OfferPayment>, LightningRpcError> {
+        Ok(self
+            .network
+            .lock()
+            .offer_payments
+            .iter()
+            .filter(|(timestamp_secs, _)| {
+                start_secs <= *timestamp_secs && *timestamp_secs < end_secs
+            })
+            .map(|(_, payment)| payment.clone())
+            .collect())
+    }
+
+    fn sync_wallet(&self) -> Result<(), LightningRpcError> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use bitcoin::Network;
+    use bitcoin::hashes::{Hash as _, sha256};
+    use fedimint_core::Amount;
+    use fedimint_core::task::TaskGroup;
+    use futures::StreamExt as _;
+
+    use super::{
+        GatewaySimulatedClient, SimulatedNetwork, SimulatedNetworkConfig, SimulatedOutcome,
+        SimulatedPaymentRule,
+    };
+    use crate::{
+        CreateInvoiceRequest, ILnRpcClient, InterceptPaymentResponse, PaymentAction, Preimage,
+    };
+
+    fn client() -> (GatewaySimulatedClient, SimulatedNetwork) {
+        let network = SimulatedNetwork::new(Network::Regtest, SimulatedNetworkConfig::default())
+            .expect("Regtest is supported");
+
+        (GatewaySimulatedClient::new(network.clone()), network)
+    }
+
+    #[tokio::test]
+    async fn pays_virtual_nodes_within_liquidity() {
+        let (client, network) = client();
+
+        let invoice = network
+            .create_node_invoice("alice", Amount::from_sats(1_000), String::new())
+            .expect("Alice exists");
+
+        let preimage = client
+            .pay(invoice.clone(), 1008, Amount::ZERO)
+            .await
+            .expect("Payment succeeds")
+            .preimage;
+
+        assert_eq!(sha256::Hash::hash(&preimage.0), *invoice.payment_hash());
+
+        // Paying the same invoice again returns the same preimage
+        let repeated = client
+            .pay(invoice, 1008, Amount::ZERO)
+            .await
+            .expect("Payment succeeded before");
+
+        assert_eq!(repeated.preimage, preimage);
+
+        let balances = client.get_balances().await.expect("Balances are known");
+        assert_eq!(balances.lightning_balance_msats, 1_549_000_000);
+
+        // Carol only has 50_000 sats of liquidity on the gateway's side
+        let invoice = network
+            .create_node_invoice("carol", Amount::from_sats(60_000), String::new())
+            .expect("Carol exists");
+
+        assert!(client.pay(invoice, 1008, Amount::ZERO).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn scripted_outcomes_apply_until_used_up() {
+        let (client, network) = client();
+
+        network.add_rule(SimulatedPaymentRule {
+            payee: Some("alice".to_string()),
+            min_amount_msat: None,
+            max_amount_msat: None,
+            outcome: SimulatedOutcome::Fail,
+            times: Some(1),
+        });
+
+        for succeeds in [false, true] {
+            let invoice = network
+                .create_node_invoice("alice", Amount::from_sats(1_000), String::new())
+                .expect("Alice exists");
+
+            assert_eq!(
+                client.pay(invoice, 1008, Amount::ZERO).await.is_ok(),
+                succeeds
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn payments_to_hold_invoices_are_intercepted() {
+        let (client, _) = client();
+
+        let preimage = Preimage([42; 32]);
+        let payment_hash = sha256::Hash::hash(&preimage.0);
+
+        let invoice = client
+            .create_invoice(CreateInvoiceRequest {
+                payment_hash: Some(payment_hash),
+                amount_msat: 10_000,
+                expiry_secs: 3600,
+                description: None,
+            })
+            .await
+            .expect("Invoice is created")
+            .invoice
+            .parse()
+            .expect("Invoice is valid");
+
+        let (mut stream, client) = Box::new(client)
+            .route_htlcs(&TaskGroup::new())
+            .await
+            .expect("HTLCs are routed");
+
+        let payer = Arc::clone(&client);
+        let payment = tokio::spawn(async move { payer.pay(invoice, 1008, Amount::ZERO).await });
+
+        let htlc = stream.next().await.expect("HTLC is intercepted");
+        assert_eq!(htlc.payment_hash, payment_hash);
+        assert_eq!(htlc.short_channel_id, None);
+
+        client
+            .complete_htlc(InterceptPaymentResponse {
+                incoming_chan_id: htlc.incoming_chan_id,
+                htlc_id: htlc.htlc_id,
+                payment_hash,
+                action: PaymentAction::Settle(preimage.clone()),
+            })
+            .await
+            .expect("HTLC is held");
+
+        let response = payment
+            .await
+            .expect("Payment task completes")
+            .expect("Payment succeeds");
+
+        assert_eq!(response.preimage, preimage);
+    }
+}
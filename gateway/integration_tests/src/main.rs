@@ -12,13 +12,13 @@
 use clap::{Parser, Subcommand};
 use devimint::cli::cleanup_on_exit;
 use devimint::envs::FM_DATA_DIR_ENV;
-use devimint::external::{Bitcoind, Esplora};
+use devimint::external::{Bitcoind, Cln, Esplora, open_channels_between_gateways};
 use devimint::federation::Federation;
 use devimint::util::{ProcessManager, almost_equal, poll, poll_with_timeout};
 use devimint::version_constants::{
     VERSION_0_10_0_ALPHA, VERSION_0_12_0_ALPHA, VERSION_0_13_0_ALPHA,
 };
-use devimint::{Gatewayd, LightningNode, cli, util};
+use devimint::{Gatewayd, LightningNode, cli, cmd, util};
 use fedimint_core::config::FederationId;
 use fedimint_core::time::now;
 use fedimint_core::{Amount, BitcoinAmountOrAll, bitcoin, default_esplora_server};
@@ -44,6 +44,7 @@ enum GatewayTest {
     BackupRestoreTest,
     LiquidityTest,
     EsploraTest,
+    ClnTest,
 }
 
 #[tokio::main]
@@ -54,6 +55,7 @@ async fn main() -> anyhow::Result<()> {
         GatewayTest::BackupRestoreTest => Box::pin(backup_restore_test()).await,
         GatewayTest::LiquidityTest => Box::pin(liquidity_test()).await,
         GatewayTest::EsploraTest => esplora_test().await,
+        GatewayTest::ClnTest => Box::pin(cln_test()).await,
     }
 }
 
@@ -178,6 +180,7 @@ async fn config_test(gw_type: LightningNodeType) -> anyhow::Result<()> {
                 let gw = match gw_type {
                     LightningNodeType::Lnd => dev_fed.gw_lnd_registered().await?,
                     LightningNodeType::Ldk => dev_fed.gw_ldk_connected().await?,
+                    LightningNodeType::Cln => anyhow::bail!("The dev federation has no CLN gateway"),
                 };
 
                 // Try to connect to already connected federation
@@ -612,6 +615,92 @@ async fn liquidity_test() -> anyhow::Result<()> {
         .await
 }
 
+/// Test that runs a gateway against Core Lightning and routes payments between
+/// it and the LDK gateway, both directly and via LNv2
+async fn cln_test() -> anyhow::Result<()> {
+    devimint::run_devfed_test()
+        .call(|dev_fed, process_mgr| async move {
+            if !devimint::util::supports_lnv2() {
+                info!(target: LOG_TEST, "LNv2 is not supported, which is necessary for the CLN test");
+                return Ok(());
+            }
+
+            let federation = dev_fed.fed().await?;
+            let bitcoind = dev_fed.bitcoind().await?;
+            let gw_ldk = dev_fed.gw_ldk_connected().await?;
+
+            // The CLN gateway takes over the iroh endpoint of the LND gateway
+            let gw_lnd = dev_fed.gw_lnd_registered().await?;
+            let gateway_index = gw_lnd.gateway_index;
+            gw_lnd.to_owned().terminate().await?;
+
+            info!(target: LOG_TEST, "Starting CLN gateway...");
+            let cln = Cln::new(&process_mgr, bitcoind.clone()).await?;
+            let gw_cln = Gatewayd::new(&process_mgr, LightningNode::Cln(cln), gateway_index).await?;
+            gw_cln.client().connect_fed(federation.invite_code()?).await?;
+
+            open_channels_between_gateways(bitcoind, &[(&gw_cln, "gw-cln"), (gw_ldk, "gw-ldk")]).await?;
+
+            info!(target: LOG_TEST, "Testing payments between CLN and LDK...");
+            let invoice = gw_ldk.client().create_invoice(1_000_000).await?;
+            gw_cln.client().pay_invoice(invoice).await?;
+
+            let invoice = gw_cln.client().create_invoice(1_000_000).await?;
+            gw_ldk.client().pay_invoice(invoice).await?;
+
+            let start = now() - Duration::from_mins(5);
+            let end = now() + Duration::from_mins(5);
+            // One inbound and one outbound transaction
+            assert_eq!(gw_cln.client().list_transactions(start, end).await?.len(), 2);
+
+            info!(target: LOG_TEST, "Testing LNv2 payments through the CLN gateway...");
+            federation.pegin_gateways(1_000_000, vec![&gw_cln]).await?;
+            let client = federation.new_joined_client("cln-test-client").await?;
+            federation.pegin_client(10_000, &client).await?;
+
+            // Receiving requires the plugin to intercept the HTLC paying the
+            // federation's invoice
+            let (invoice, receive_op): (String, String) = serde_json::from_value(
+                cmd!(client, "module", "lnv2", "receive", 100_000, "--gateway", gw_cln.address())
+                    .out_json()
+                    .await?,
+            )?;
+            gw_ldk.client().pay_invoice(invoice.parse()?).await?;
+            let receive_state = cmd!(client, "module", "lnv2", "await-receive", receive_op)
+                .out_json()
+                .await?;
+            assert_eq!(receive_state, serde_json::json!("Claimed"));
+
+            let invoice = gw_ldk.client().create_invoice(50_000).await?;
+            let send_op: String = serde_json::from_value(
+                cmd!(client, "module", "lnv2", "send", invoice, "--gateway", gw_cln.address())
+                    .out_json()
+                    .await?,
+            )?;
+            let send_state = cmd!(client, "module", "lnv2", "await-send", send_op)
+                .out_json()
+                .await?;
+            assert!(send_state.get("Success").is_some(), "unexpected send state: {send_state}");
+
+            info!(target: LOG_TEST, "Testing paying Bolt12 Offers...");
+            let offer = gw_cln.client().create_offer(Some(Amount::from_msats(10_000_000))).await?;
+            gw_ldk.client().pay_offer(offer, None).await?;
+            assert!(get_transaction(&gw_cln, PaymentKind::Bolt12Offer, Amount::from_msats(10_000_000), PaymentStatus::Succeeded).await.is_some());
+
+            let offer = gw_ldk.client().create_offer(None).await?;
+            gw_cln.client().pay_offer(offer, Some(Amount::from_msats(5_000_000))).await?;
+            assert!(get_transaction(gw_ldk, PaymentKind::Bolt12Offer, Amount::from_msats(5_000_000), PaymentStatus::Succeeded).await.is_some());
+
+            gw_cln.client()
+                .close_all_channels(true, Duration::from_secs(30))
+                .await?;
+
+            info!(target: LOG_TEST, "cln_test successful");
+            Ok(())
+        })
+        .await
+}
+
 async fn esplora_test() -> anyhow::Result<()> {
     let args = cli::CommonArgs::parse_from::<_, ffi::OsString>(vec![]);
     let (process_mgr, task_group) = cli::setup(args).await?;
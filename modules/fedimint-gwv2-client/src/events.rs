@@ -95,6 +95,27 @@ impl Event for IncomingPaymentStarted {
     const PERSISTENCE: EventPersistence = EventPersistence::Persistent;
 }
 
+/// Event that is emitted when the payment of a hold invoice has arrived and is
+/// held until the recipient settles or cancels it.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct IncomingPaymentHeld {
+    /// The payment image of the invoice that is held.
+    pub payment_image: PaymentImage,
+
+    /// The amount of the held payment.
+    pub invoice_amount: Amount,
+
+    /// The unix time in seconds at which the gateway cancels the payment if
+    /// the recipient has not settled it.
+    pub deadline_secs: u64,
+}
+
+impl Event for IncomingPaymentHeld {
+    const MODULE: Option<ModuleKind> = Some(fedimint_lnv2_common::KIND);
+    const KIND: EventKind = EventKind::from_static("incoming-payment-held");
+    const PERSISTENCE: EventPersistence = EventPersistence::Persistent;
+}
+
 /// Event that is emitted when an incoming payment attempt has succeeded.
 /// Includes both internal swaps and outside LN payments.
 #[derive(Serialize, Deserialize, Debug)]
@@ -13,7 +13,9 @@
 use async_trait::async_trait;
 use bitcoin::hashes::sha256;
 use bitcoin::secp256k1::Message;
-use events::{IncomingPaymentStarted, OutgoingPaymentStarted};
+use events::{
+    IncomingPaymentFailed, IncomingPaymentHeld, IncomingPaymentStarted, OutgoingPaymentStarted,
+};
 use fedimint_api_client::api::DynModuleApi;
 use fedimint_client::ClientHandleArc;
 use fedimint_client_module::module::init::{ClientModuleInit, ClientModuleInitArgs};
@@ -35,12 +37,13 @@
 use fedimint_core::time::now;
 use fedimint_core::util::Spanned;
 use fedimint_core::{Amount, PeerId, apply, async_trait_maybe_send, secp256k1};
-use fedimint_lightning::{InterceptPaymentResponse, LightningRpcError};
+use fedimint_lightning::{InterceptPaymentResponse, LightningRpcError, PaymentAction};
 use fedimint_lnv2_common::config::LightningClientConfig;
 use fedimint_lnv2_common::contracts::{IncomingContract, PaymentImage};
 use fedimint_lnv2_common::gateway_api::SendPaymentPayload;
 use fedimint_lnv2_common::{
-    LightningCommonInit, LightningInvoice, LightningModuleTypes, LightningOutput, LightningOutputV0,
+    Bolt12OfferInvoice, KeysendPayment, LightningCommonInit, LightningInvoice,
+    LightningModuleTypes, LightningOutput, LightningOutputV0,
 };
 use futures::StreamExt;
 use lightning_invoice::Bolt11Invoice;
@@ -428,17 +431,21 @@ pub async fn send_payment(
             "Contract Id returned by the federation does not match contract in request"
         );
 
-        let (payment_hash, amount) = match &payload.invoice {
-            LightningInvoice::Bolt11(invoice) => (
-                invoice.payment_hash(),
-                invoice
-                    .amount_milli_satoshis()
-                    .ok_or(anyhow!("Invoice is missing amount"))?,
-            ),
-        };
+        let amount = payload
+            .invoice
+            .amount()
+            .ok_or(anyhow!("Invoice is missing amount"))?
+            .msats;
+
+        if let LightningInvoice::Keysend(payment) = &payload.invoice {
+            ensure!(
+                payment.verify_custom_records(),
+                "The keysend payment has invalid custom records"
+            );
+        }
 
         ensure!(
-            PaymentImage::Hash(*payment_hash) == payload.contract.payment_image,
+            PaymentImage::Hash(payload.invoice.payment_hash()) == payload.contract.payment_image,
             "The invoices payment hash does not match the contracts payment hash"
         );
 
@@ -662,6 +669,78 @@ pub async fn relay_incoming_htlc(
         Ok(())
     }
 
+    /// Records that the payment of a hold invoice has arrived. The HTLC is
+    /// held without funding the incoming contract, such that the gateway can
+    /// still fail it back if the recipient cancels or misses `deadline_secs`.
+    pub async fn hold_incoming_htlc(
+        &self,
+        contract: &IncomingContract,
+        amount_msat: u64,
+        deadline_secs: u64,
+    ) {
+        let mut dbtx = self.client_ctx.module_db().begin_transaction().await;
+
+        self.client_ctx
+            .log_event(
+                &mut dbtx,
+                IncomingPaymentHeld {
+                    payment_image: contract.commitment.payment_image.clone(),
+                    invoice_amount: Amount::from_msats(amount_msat),
+                    deadline_secs,
+                },
+            )
+            .await;
+
+        dbtx.commit_tx().await;
+    }
+
+    /// Returns whether the gateway has started to fund the incoming contract,
+    /// in which case the HTLC is resolved by the circuit completion and must
+    /// not be failed back directly.
+    pub async fn incoming_contract_funded(&self, contract: &IncomingContract) -> bool {
+        self.client_ctx
+            .operation_exists(OperationId::from_encodable(contract))
+            .await
+    }
+
+    /// Fails a held HTLC back to the payer. Since the incoming contract of a
+    /// hold invoice is only funded once the recipient settles, the gateway
+    /// has nothing to refund.
+    pub async fn cancel_held_htlc(
+        &self,
+        payment_hash: sha256::Hash,
+        incoming_chan_id: u64,
+        htlc_id: u64,
+        contract: &IncomingContract,
+        reason: String,
+    ) -> anyhow::Result<()> {
+        self.gateway
+            .complete_htlc(InterceptPaymentResponse {
+                incoming_chan_id,
+                htlc_id,
+                payment_hash,
+                action: PaymentAction::Cancel,
+            })
+            .await
+            .map_err(|e| anyhow!("Failed to cancel held HTLC: {e:?}"))?;
+
+        let mut dbtx = self.client_ctx.module_db().begin_transaction().await;
+
+        self.client_ctx
+            .log_event(
+                &mut dbtx,
+                IncomingPaymentFailed {
+                    payment_image: contract.commitment.payment_image.clone(),
+                    error: reason,
+                },
+            )
+            .await;
+
+        dbtx.commit_tx().await;
+
+        Ok(())
+    }
+
     pub async fn relay_direct_swap(
         &self,
         contract: IncomingContract,
@@ -849,6 +928,33 @@ async fn pay(
         max_fee: Amount,
     ) -> Result<[u8; 32], LightningRpcError>;
 
+    /// Pays an invoice for a BOLT12 offer that was previously fetched by the
+    /// gateway, over the Lightning network.
+    async fn pay_offer_invoice(
+        &self,
+        invoice: Bolt12OfferInvoice,
+        max_delay: u64,
+        max_fee: Amount,
+    ) -> Result<[u8; 32], LightningRpcError>;
+
+    /// Pays `amount` of the invoice over the Lightning network as one part of
+    /// a multi-part payment whose other parts are sent by other gateways.
+    async fn pay_part(
+        &self,
+        invoice: Bolt11Invoice,
+        amount: Amount,
+        max_delay: u64,
+        max_fee: Amount,
+    ) -> Result<[u8; 32], LightningRpcError>;
+
+    /// Sends a spontaneous keysend payment over the Lightning network.
+    async fn pay_keysend(
+        &self,
+        payment: KeysendPayment,
+        max_delay: u64,
+        max_fee: Amount,
+    ) -> Result<[u8; 32], LightningRpcError>;
+
     /// Computes the minimum contract amount necessary for making an outgoing
     /// payment.
     ///
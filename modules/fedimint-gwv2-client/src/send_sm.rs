@@ -10,7 +10,9 @@
 use fedimint_core::secp256k1::Keypair;
 use fedimint_core::{Amount, OutPoint};
 use fedimint_lnv2_common::contracts::OutgoingContract;
-use fedimint_lnv2_common::{LightningInput, LightningInputV0, LightningInvoice, OutgoingWitness};
+use fedimint_lnv2_common::{
+    Bolt11InvoicePart, LightningInput, LightningInputV0, LightningInvoice, OutgoingWitness,
+};
 use serde::{Deserialize, Serialize};
 
 use super::FinalReceiveState;
@@ -157,12 +159,13 @@ async fn send_payment(
         invoice: LightningInvoice,
         contract: OutgoingContract,
     ) -> Result<PaymentResponse, Cancelled> {
-        let LightningInvoice::Bolt11(invoice) = invoice;
-
         // The following two checks may fail in edge cases since they have inherent
         // timing assumptions. Therefore, they may only be checked after we have created
         // the state machine such that we can cancel the contract.
-        if invoice.is_expired() {
+        if let LightningInvoice::Bolt11(invoice)
+        | LightningInvoice::Bolt11Part(Bolt11InvoicePart { invoice, .. }) = &invoice
+            && invoice.is_expired()
+        {
             return Err(Cancelled::InvoiceExpired);
         }
 
@@ -174,6 +177,53 @@ async fn send_payment(
             return Err(Cancelled::Underfunded);
         };
 
+        let invoice = match invoice {
+            LightningInvoice::Bolt11(invoice) => invoice,
+            // An invoice for an offer is neither an LNv1 invoice nor payable via a
+            // direct swap, since we cannot create invoices for offers on behalf of
+            // a federation.
+            LightningInvoice::Bolt12(invoice) => {
+                let preimage = context
+                    .gateway
+                    .pay_offer_invoice(invoice, max_delay, max_fee)
+                    .await
+                    .map_err(|e| Cancelled::LightningRpcError(e.to_string()))?;
+
+                return Ok(PaymentResponse {
+                    preimage,
+                    target_federation: None,
+                });
+            }
+            // A part of a multi-part payment is always paid over the Lightning
+            // network, since the remaining parts are sent by other gateways.
+            LightningInvoice::Bolt11Part(part) => {
+                let preimage = context
+                    .gateway
+                    .pay_part(part.invoice, part.amount, max_delay, max_fee)
+                    .await
+                    .map_err(|e| Cancelled::LightningRpcError(e.to_string()))?;
+
+                return Ok(PaymentResponse {
+                    preimage,
+                    target_federation: None,
+                });
+            }
+            // A keysend payment has no invoice we could have created on behalf of
+            // a federation, so it is always paid over the Lightning network.
+            LightningInvoice::Keysend(payment) => {
+                let preimage = context
+                    .gateway
+                    .pay_keysend(payment, max_delay, max_fee)
+                    .await
+                    .map_err(|e| Cancelled::LightningRpcError(e.to_string()))?;
+
+                return Ok(PaymentResponse {
+                    preimage,
+                    target_federation: None,
+                });
+            }
+        };
+
         // To make gateway operation easier, we check if the invoice was created using
         // the LNv1 protocol and if the gateway supports the target federation.
         // If it does, we can fund an LNv1 incoming contract to satisfy the LNv2
@@ -1,7 +1,10 @@
 use std::{ffi, iter};
 
+use bitcoin::hex::FromHex;
+use bitcoin::secp256k1::PublicKey;
 use clap::{Parser, Subcommand};
 use fedimint_core::core::OperationId;
+use fedimint_core::time::duration_since_epoch;
 use fedimint_core::util::SafeUrl;
 use fedimint_core::{Amount, PeerId};
 use lightning_invoice::Bolt11Invoice;
@@ -9,7 +12,7 @@
 use serde_json::Value;
 
 use crate::api::LightningFederationApi;
-use crate::{Bolt11InvoiceDescription, LightningClientModule};
+use crate::{Bolt11InvoiceDescription, LightningClientModule, RecurringPayment};
 
 #[derive(Parser, Serialize)]
 enum Opts {
@@ -20,7 +23,51 @@ enum Opts {
         #[arg(long)]
         gateway: Option<SafeUrl>,
     },
-    /// Await the final state of the send operation.
+    /// Estimate the fees and the chance of success of paying an invoice
+    /// without paying it.
+    PreviewSend {
+        invoice: Bolt11Invoice,
+        #[arg(long)]
+        gateway: Option<SafeUrl>,
+    },
+    /// Pay an invoice in parts over several gateways. Either specify the
+    /// gateways to split the payment over or the number of parts, in which
+    /// case the gateways are selected automatically. Gateways running the
+    /// embedded LDK node cannot pay parts.
+    SendSplit {
+        invoice: Bolt11Invoice,
+        #[arg(long, required_unless_present = "gateway")]
+        parts: Option<usize>,
+        #[arg(long, conflicts_with = "parts")]
+        gateway: Vec<SafeUrl>,
+    },
+    /// Pay a BOLT12 offer. The amount is required if the offer does not
+    /// specify one.
+    SendOffer {
+        offer: String,
+        #[arg(long)]
+        amount: Option<Amount>,
+        #[arg(long)]
+        payer_note: Option<String>,
+        #[arg(long)]
+        gateway: Option<SafeUrl>,
+    },
+    /// Send a spontaneous payment to a Lightning node without an invoice.
+    /// Custom records are given as `TYPE=HEX`. The gateway learns the
+    /// preimage before paying, so it has to be trusted with the amount. We
+    /// choose the preimage ourselves, so the preimage reported on success is
+    /// NOT a proof that the payee has been paid.
+    SendKeysend {
+        payee: PublicKey,
+        amount: Amount,
+        #[arg(long = "record", value_parser = parse_custom_record)]
+        records: Vec<(u64, Vec<u8>)>,
+        #[arg(long)]
+        trusted_gateway: SafeUrl,
+    },
+    /// Await the final state of the send operation. The preimage reported on
+    /// success proves the payment of an invoice, but not of a keysend
+    /// payment.
     AwaitSend { operation_id: OperationId },
     /// Request an invoice. For testing you can optionally specify a gateway to
     /// generate the invoice, otherwise a gateway will be selected
@@ -32,9 +79,32 @@ enum Opts {
     },
     /// Await the final state of the receive operation.
     AwaitReceive { operation_id: OperationId },
+    /// Request a hold invoice, the payment of which is held by the gateway
+    /// until it is settled or cancelled, for at most `hold_secs`.
+    ReceiveHold {
+        amount: Amount,
+        hold_secs: u32,
+        #[arg(long)]
+        gateway: Option<SafeUrl>,
+    },
+    /// Accept the held payment of a hold invoice.
+    SettleHold { operation_id: OperationId },
+    /// Return the held payment of a hold invoice to the payer.
+    CancelHold { operation_id: OperationId },
+    /// Request a reusable BOLT12 offer. Payments to the offer are received in
+    /// the background like payments to an lnurl.
+    CreateOffer {
+        #[arg(long)]
+        description: Option<String>,
+        #[arg(long)]
+        gateway: Option<SafeUrl>,
+    },
     /// Lnurl subcommands
     #[command(subcommand)]
     Lnurl(LnurlOpts),
+    /// Recurring payment subcommands
+    #[command(subcommand)]
+    Recurring(RecurringOpts),
     /// Gateway subcommands
     #[command(subcommand)]
     Gateways(GatewaysOpts),
@@ -48,6 +118,65 @@ enum LnurlOpts {
         #[arg(long)]
         gateway: Option<SafeUrl>,
     },
+    /// Generate a new lnurl served by a gateway, payments to which are
+    /// received even while this client is offline. The lnurl accepts the
+    /// given number of payments of the given amount, for each of which we
+    /// register an incoming contract with the gateway. Run again to register
+    /// more payments.
+    GenerateStatic {
+        amount: Amount,
+        #[arg(long, default_value_t = 10)]
+        payments: usize,
+        #[arg(long)]
+        gateway: Option<SafeUrl>,
+    },
+    /// Register a lightning address with a recurringd instance.
+    RegisterAddress {
+        recurringd: SafeUrl,
+        username: String,
+        #[arg(long)]
+        gateway: Option<SafeUrl>,
+    },
+    /// Redeem an lnurl withdraw voucher. Withdraws the maximum amount unless
+    /// an amount is specified.
+    Withdraw {
+        lnurl: String,
+        #[arg(long)]
+        amount: Option<Amount>,
+        #[arg(long)]
+        gateway: Option<SafeUrl>,
+    },
+    /// List the comments attached to payments to our lightning address.
+    Comments {
+        recurringd: SafeUrl,
+        username: String,
+        /// Only list comments of payments settled after this unix time.
+        #[arg(long, default_value_t = 0)]
+        since: u64,
+    },
+}
+
+#[derive(Clone, Subcommand, Serialize)]
+enum RecurringOpts {
+    /// Pay a lightning address or lnurl every `interval_secs` while this
+    /// client is running.
+    Schedule {
+        recipient: String,
+        amount: Amount,
+        interval_secs: u64,
+        /// Unix time of the first payment, defaults to now.
+        #[arg(long)]
+        start: Option<u64>,
+        #[arg(long)]
+        max_runs: Option<u64>,
+        /// Total amount including fees that may be spent over all payments.
+        #[arg(long)]
+        spending_limit: Option<Amount>,
+    },
+    /// Cancel a recurring payment before its next payment.
+    Cancel { operation_id: OperationId },
+    /// List the send operations of a recurring payment.
+    Runs { operation_id: OperationId },
 }
 
 #[derive(Clone, Subcommand, Serialize)]
@@ -70,6 +199,8 @@ enum GatewaysOpts {
     Add { gateway: SafeUrl },
     /// Remove a vetted gateway.
     Remove { gateway: SafeUrl },
+    /// Show the scores of all gateways this client has sent payments through.
+    Scores,
 }
 
 pub(crate) async fn handle_cli_command(
@@ -82,6 +213,47 @@ pub(crate) async fn handle_cli_command(
         Opts::Send { gateway, invoice } => {
             json(lightning.send(invoice, gateway, Value::Null).await?)
         }
+        Opts::PreviewSend { invoice, gateway } => {
+            json(lightning.preview_send(invoice, gateway).await?)
+        }
+        Opts::SendSplit {
+            invoice,
+            parts,
+            gateway,
+        } => {
+            let gateways = match parts {
+                Some(parts) => lightning.select_gateways(parts).await?,
+                None => gateway,
+            };
+
+            json(lightning.send_split(invoice, gateways, Value::Null).await?)
+        }
+        Opts::SendOffer {
+            offer,
+            amount,
+            payer_note,
+            gateway,
+        } => json(
+            lightning
+                .send_offer(offer, amount, payer_note, gateway, Value::Null)
+                .await?,
+        ),
+        Opts::SendKeysend {
+            payee,
+            amount,
+            records,
+            trusted_gateway,
+        } => json(
+            lightning
+                .send_keysend(
+                    payee,
+                    amount,
+                    records.into_iter().collect(),
+                    trusted_gateway,
+                    Value::Null,
+                )
+                .await?,
+        ),
         Opts::AwaitSend { operation_id } => json(
             lightning
                 .await_final_send_operation_state(operation_id)
@@ -103,17 +275,110 @@ pub(crate) async fn handle_cli_command(
                 .await_final_receive_operation_state(operation_id)
                 .await?,
         ),
+        Opts::ReceiveHold {
+            amount,
+            hold_secs,
+            gateway,
+        } => json(
+            lightning
+                .receive_hold(
+                    amount,
+                    3600,
+                    hold_secs,
+                    Bolt11InvoiceDescription::Direct(String::new()),
+                    gateway,
+                    Value::Null,
+                )
+                .await?,
+        ),
+        Opts::SettleHold { operation_id } => {
+            json(lightning.settle_hold_invoice(operation_id).await?)
+        }
+        Opts::CancelHold { operation_id } => {
+            json(lightning.cancel_hold_invoice(operation_id).await?)
+        }
+        Opts::CreateOffer {
+            description,
+            gateway,
+        } => json(lightning.create_offer(description, gateway).await?),
         Opts::Lnurl(lnurl_opts) => match lnurl_opts {
             LnurlOpts::Generate {
                 recurringd,
                 gateway,
             } => json(lightning.generate_lnurl(recurringd, gateway).await?),
+            LnurlOpts::GenerateStatic {
+                amount,
+                payments,
+                gateway,
+            } => json(
+                lightning
+                    .generate_static_lnurl(gateway, amount, payments)
+                    .await?,
+            ),
+            LnurlOpts::RegisterAddress {
+                recurringd,
+                username,
+                gateway,
+            } => json(
+                lightning
+                    .register_lightning_address(recurringd, username, gateway)
+                    .await?,
+            ),
+            LnurlOpts::Withdraw {
+                lnurl,
+                amount,
+                gateway,
+            } => json(
+                lightning
+                    .withdraw_lnurl(&lnurl, amount, gateway, Value::Null)
+                    .await?,
+            ),
+            LnurlOpts::Comments {
+                recurringd,
+                username,
+                since,
+            } => json(
+                lightning
+                    .lightning_address_comments(recurringd, username, since)
+                    .await?,
+            ),
+        },
+        Opts::Recurring(recurring_opts) => match recurring_opts {
+            RecurringOpts::Schedule {
+                recipient,
+                amount,
+                interval_secs,
+                start,
+                max_runs,
+                spending_limit,
+            } => json(
+                lightning
+                    .schedule_recurring_payment(
+                        RecurringPayment {
+                            recipient,
+                            amount,
+                            interval_secs,
+                            start_secs: start.unwrap_or_else(|| duration_since_epoch().as_secs()),
+                            max_runs,
+                            spending_limit,
+                        },
+                        Value::Null,
+                    )
+                    .await?,
+            ),
+            RecurringOpts::Cancel { operation_id } => {
+                json(lightning.cancel_recurring_payment(operation_id).await?)
+            }
+            RecurringOpts::Runs { operation_id } => {
+                json(lightning.recurring_payment_runs(operation_id).await)
+            }
         },
         Opts::Gateways(gateway_opts) => match gateway_opts {
             #[allow(clippy::unit_arg)]
             GatewaysOpts::Map => json(lightning.update_gateway_map().await),
             GatewaysOpts::Select { invoice } => json(lightning.select_gateway(invoice).await?.0),
             GatewaysOpts::List { peer } => json(lightning.list_gateways(peer).await?),
+            GatewaysOpts::Scores => json(lightning.gateway_scores().await),
             GatewaysOpts::Add { gateway } => {
                 let auth = lightning
                     .admin_auth
@@ -139,3 +404,11 @@ pub(crate) async fn handle_cli_command(
 fn json<T: Serialize>(value: T) -> Value {
     serde_json::to_value(value).expect("JSON serialization failed")
 }
+
+fn parse_custom_record(s: &str) -> anyhow::Result<(u64, Vec<u8>)> {
+    let (record_type, value) = s
+        .split_once('=')
+        .ok_or_else(|| anyhow::anyhow!("Custom records must be given as TYPE=HEX"))?;
+
+    Ok((record_type.parse()?, Vec::<u8>::from_hex(value)?))
+}
@@ -1,14 +1,23 @@
+use fedimint_core::core::OperationId;
 use fedimint_core::encoding::{Decodable, Encodable};
-use fedimint_core::impl_db_record;
 use fedimint_core::secp256k1::PublicKey;
 use fedimint_core::util::SafeUrl;
+use fedimint_core::{impl_db_lookup, impl_db_record};
+use fedimint_eventlog::EventLogId;
 use strum::EnumIter;
 
+use crate::GatewayScore;
+
 #[repr(u8)]
 #[derive(Clone, EnumIter, Debug)]
 pub enum DbKeyPrefix {
     Gateway = 0x41,
     IncomingContractStreamIndex = 0x42,
+    GatewayScore = 0x43,
+    GatewayScoreCursor = 0x44,
+    PendingSendScore = 0x45,
+    RecurringPaymentCancelled = 0x46,
+    RecurringPaymentRun = 0x47,
     /// Prefixes between 0xb0..=0xcf shall all be considered allocated for
     /// historical and future external use
     ExternalReservedStart = 0xb0,
@@ -35,3 +44,77 @@ pub enum DbKeyPrefix {
     value = u64,
     db_prefix = DbKeyPrefix::IncomingContractStreamIndex
 );
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct GatewayScoreKey(pub SafeUrl);
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct GatewayScorePrefix;
+
+impl_db_record!(
+    key = GatewayScoreKey,
+    value = GatewayScore,
+    db_prefix = DbKeyPrefix::GatewayScore,
+);
+
+impl_db_lookup!(key = GatewayScoreKey, query_prefix = GatewayScorePrefix);
+
+/// Position in the client event log up to which send payments have been
+/// accounted for in the gateway scores.
+#[derive(Debug, Encodable, Decodable)]
+pub struct GatewayScoreCursorKey;
+
+impl_db_record!(
+    key = GatewayScoreCursorKey,
+    value = EventLogId,
+    db_prefix = DbKeyPrefix::GatewayScoreCursor,
+);
+
+/// A send operation whose parts have not all reached a final state yet.
+#[derive(Debug, Encodable, Decodable)]
+pub struct PendingSendScoreKey(pub OperationId);
+
+#[derive(Debug, Clone, Encodable, Decodable)]
+pub struct PendingSendScore {
+    /// Timestamp of the [`crate::events::SendPaymentEvent`] in microseconds
+    /// since the unix epoch.
+    pub started_at_usecs: u64,
+    pub remaining_parts: u64,
+}
+
+impl_db_record!(
+    key = PendingSendScoreKey,
+    value = PendingSendScore,
+    db_prefix = DbKeyPrefix::PendingSendScore,
+);
+
+/// Marks a recurring payment as cancelled, which stops its state machine
+/// before the next run.
+#[derive(Debug, Encodable, Decodable)]
+pub struct RecurringPaymentCancelledKey(pub OperationId);
+
+impl_db_record!(
+    key = RecurringPaymentCancelledKey,
+    value = (),
+    db_prefix = DbKeyPrefix::RecurringPaymentCancelled,
+    notify_on_modify = true,
+);
+
+/// A send operation started by a recurring payment, keyed by the operation of
+/// the recurring payment, with the run it was paying as its value.
+#[derive(Debug, Encodable, Decodable)]
+pub struct RecurringPaymentRunKey(pub OperationId, pub OperationId);
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct RecurringPaymentRunPrefix(pub OperationId);
+
+impl_db_record!(
+    key = RecurringPaymentRunKey,
+    value = u64,
+    db_prefix = DbKeyPrefix::RecurringPaymentRun,
+);
+
+impl_db_lookup!(
+    key = RecurringPaymentRunKey,
+    query_prefix = RecurringPaymentRunPrefix
+);
@@ -1,5 +1,6 @@
 use fedimint_core::Amount;
 use fedimint_core::core::{ModuleKind, OperationId};
+use fedimint_core::util::SafeUrl;
 use fedimint_eventlog::{Event, EventKind, EventPersistence};
 use serde::{Deserialize, Serialize};
 
@@ -21,16 +22,38 @@ impl Event for SendPaymentEvent {
 #[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
 pub enum SendPaymentStatus {
     /// The payment was successful, includes the preimage as proof of payment.
+    /// The preimage of a keysend payment is chosen by the payer and proves
+    /// nothing.
     Success([u8; 32]),
     /// The payment has been refunded.
     Refunded,
 }
 
-/// Event emitted when a send operation reaches a final state.
+/// Event emitted when a send operation reaches a final state. A payment split
+/// over several gateways emits one event per part.
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 pub struct SendPaymentUpdateEvent {
     pub operation_id: OperationId,
     pub status: SendPaymentStatus,
+    /// The gateway that was instructed to pay the part. Absent for events
+    /// recorded before this field was added.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gateway: Option<SafeUrl>,
+    /// Why the part was refunded. Absent for parts that were paid and for
+    /// events recorded before this field was added.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub refund_reason: Option<SendRefundReason>,
+}
+
+/// Why the outgoing contract of a send operation was refunded.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Eq, PartialEq)]
+pub enum SendRefundReason {
+    /// The gateway cancelled the payment, for example because the payee could
+    /// not be reached or the invoice had expired.
+    Cancelled,
+    /// The gateway neither paid nor cancelled the payment before the contract
+    /// expired.
+    Expired,
 }
 
 impl Event for SendPaymentUpdateEvent {
@@ -12,15 +12,23 @@
 pub mod db;
 pub mod events;
 mod receive_sm;
+mod recurring_sm;
 mod send_sm;
 
+use std::cmp::Reverse;
 use std::collections::{BTreeMap, BTreeSet};
 use std::sync::Arc;
+use std::time::Duration;
 
+use anyhow::bail;
 use async_stream::stream;
 use bitcoin::hashes::{Hash, sha256};
 use bitcoin::secp256k1;
-use db::{DbKeyPrefix, GatewayKey, IncomingContractStreamIndexKey};
+use db::{
+    DbKeyPrefix, GatewayKey, GatewayScoreCursorKey, GatewayScoreKey, GatewayScorePrefix,
+    IncomingContractStreamIndexKey, PendingSendScore, PendingSendScoreKey,
+    RecurringPaymentCancelledKey, RecurringPaymentRunPrefix,
+};
 use fedimint_api_client::api::DynModuleApi;
 use fedimint_client_module::module::init::{ClientModuleInit, ClientModuleInitArgs};
 use fedimint_client_module::module::recovery::NoModuleBackup;
@@ -43,18 +51,24 @@
 use fedimint_core::task::TaskGroup;
 use fedimint_core::time::duration_since_epoch;
 use fedimint_core::util::SafeUrl;
-use fedimint_core::{Amount, PeerId, apply, async_trait_maybe_send};
+use fedimint_core::{Amount, OutPoint, PeerId, apply, async_trait_maybe_send};
 use fedimint_derive_secret::{ChildId, DerivableSecret};
+use fedimint_eventlog::Event as _;
 use fedimint_lnv2_common::config::LightningClientConfig;
-use fedimint_lnv2_common::contracts::{IncomingContract, OutgoingContract, PaymentImage};
+use fedimint_lnv2_common::contracts::{
+    IncomingContract, OutgoingContract, PaymentImage, fee_encoded_expiration,
+};
 use fedimint_lnv2_common::gateway_api::{
-    GatewayConnection, MAX_INVOICE_EXPIRY_SECS, PaymentFee, RealGatewayConnection, RoutingInfo,
+    GatewayConnection, MAX_HOLD_INVOICE_SECS, MAX_INVOICE_EXPIRY_SECS, MAX_STATIC_LNURL_CONTRACTS,
+    PaymentFee, RealGatewayConnection, RegisterStaticLnurlContractsPayload, RoutePreview,
+    RoutingInfo, register_static_lnurl_contracts_message, resolve_hold_invoice_message,
 };
 use fedimint_lnv2_common::{
-    Bolt11InvoiceDescription, GatewayApi, KIND, LightningCommonInit, LightningInvoice,
-    LightningModuleTypes, LightningOutput, LightningOutputV0, MINIMUM_INCOMING_CONTRACT_AMOUNT,
-    lnurl, tweak,
+    Bolt11InvoiceDescription, Bolt11InvoicePart, Bolt12InvoiceError, GatewayApi, KIND,
+    KeysendPayment, LightningCommonInit, LightningInvoice, LightningModuleTypes, LightningOutput,
+    LightningOutputV0, MINIMUM_INCOMING_CONTRACT_AMOUNT, lnurl, tweak,
 };
+use fedimint_logging::LOG_CLIENT_MODULE_LNV2;
 use futures::StreamExt;
 use lightning_invoice::{Bolt11Invoice, Currency};
 use secp256k1::{Keypair, PublicKey, Scalar, SecretKey, ecdh};
@@ -66,8 +80,11 @@
 use tracing::warn;
 
 use crate::api::LightningFederationApi;
-use crate::events::SendPaymentEvent;
+use crate::events::{
+    SendPaymentEvent, SendPaymentStatus, SendPaymentUpdateEvent, SendRefundReason,
+};
 use crate::receive_sm::{ReceiveSMCommon, ReceiveSMState, ReceiveStateMachine};
+use crate::recurring_sm::{RecurringPaymentSMCommon, RecurringPaymentStateMachine};
 use crate::send_sm::{SendSMCommon, SendSMState, SendStateMachine};
 
 /// Number of blocks until outgoing lightning contracts times out and user
@@ -77,12 +94,32 @@
 /// A two hour buffer in case either the client or gateway go offline
 const CONTRACT_CONFIRMATION_BUFFER: u64 = 12;
 
+/// Expiry of the invoices we hand to LNURL-withdraw services for payment
+const LNURL_WITHDRAW_INVOICE_EXPIRY_SECS: u32 = 60 * 60;
+
+/// Minimum interval between two runs of a recurring payment
+const MIN_RECURRING_PAYMENT_INTERVAL_SECS: u64 = 60 * 60;
+
+/// Number of consecutive refunds after which a gateway is only selected if no
+/// other gateway is online
+const GATEWAY_MAX_CONSECUTIVE_REFUNDS: u64 = 3;
+
+/// Time after the last refund for which a gateway that exceeded
+/// `GATEWAY_MAX_CONSECUTIVE_REFUNDS` stays deprioritized, such that it gets
+/// another chance once it has recovered
+const GATEWAY_DEMOTION_SECS: u64 = 60 * 60;
+
+/// Maximum number of event log entries accounted for in the gateway scores per
+/// database transaction
+const EVENT_LOG_PAGE_SIZE: u64 = 1000;
+
 #[allow(clippy::large_enum_variant)]
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum LightningOperationMeta {
     Send(SendOperationMeta),
     Receive(ReceiveOperationMeta),
     LnurlReceive(LnurlReceiveOperationMeta),
+    Recurring(RecurringPaymentOperationMeta),
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -91,18 +128,58 @@ pub struct SendOperationMeta {
     pub gateway: SafeUrl,
     pub contract: OutgoingContract,
     pub invoice: LightningInvoice,
+    /// The parts paid by further gateways if the payment was split via
+    /// [`LightningClientModule::send_split`], in which case `gateway` and
+    /// `contract` refer to the first part.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub additional_parts: Vec<SendPartMeta>,
+    /// The recurring payment this payment is a run of, if it was sent by a
+    /// schedule created via
+    /// [`LightningClientModule::schedule_recurring_payment`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub recurring_payment: Option<RecurringPaymentRun>,
     pub custom_meta: Value,
 }
 
 impl SendOperationMeta {
-    /// Calculate the absolute fee paid to the gateway on success.
+    /// Calculate the absolute fee paid to the gateways on success.
     pub fn gateway_fee(&self) -> Amount {
-        match &self.invoice {
-            LightningInvoice::Bolt11(invoice) => self.contract.amount.saturating_sub(
-                Amount::from_msats(invoice.amount_milli_satoshis().expect("Invoice has amount")),
-            ),
-        }
+        self.contract_amount()
+            .saturating_sub(self.invoice.amount().expect("Invoice has amount"))
+    }
+
+    /// Calculate the absolute fee paid to a single gateway on success. The fee
+    /// of a split payment is attributed to its parts in proportion to their
+    /// contract amounts.
+    pub fn gateway_part_fee(&self, gateway: &SafeUrl) -> Amount {
+        let part_amount = std::iter::once((&self.gateway, &self.contract))
+            .chain(
+                self.additional_parts
+                    .iter()
+                    .map(|part| (&part.gateway, &part.contract)),
+            )
+            .filter(|(part_gateway, _)| *part_gateway == gateway)
+            .fold(Amount::ZERO, |total, (_, contract)| total + contract.amount);
+
+        let share = u128::from(self.gateway_fee().msats) * u128::from(part_amount.msats)
+            / u128::from(self.contract_amount().msats.max(1));
+
+        Amount::from_msats(u64::try_from(share).expect("Share does not exceed the total fee"))
     }
+
+    fn contract_amount(&self) -> Amount {
+        self.additional_parts
+            .iter()
+            .fold(self.contract.amount, |total, part| {
+                total + part.contract.amount
+            })
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SendPartMeta {
+    pub gateway: SafeUrl,
+    pub contract: OutgoingContract,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -116,12 +193,10 @@ pub struct ReceiveOperationMeta {
 impl ReceiveOperationMeta {
     /// Calculate the absolute fee paid to the gateway on success.
     pub fn gateway_fee(&self) -> Amount {
-        match &self.invoice {
-            LightningInvoice::Bolt11(invoice) => {
-                Amount::from_msats(invoice.amount_milli_satoshis().expect("Invoice has amount"))
-                    .saturating_sub(self.contract.commitment.amount)
-            }
-        }
+        self.invoice
+            .amount()
+            .expect("Invoice has amount")
+            .saturating_sub(self.contract.commitment.amount)
     }
 }
 
@@ -131,6 +206,51 @@ pub struct LnurlReceiveOperationMeta {
     pub custom_meta: Value,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecurringPaymentOperationMeta {
+    pub schedule: RecurringPayment,
+    pub custom_meta: Value,
+}
+
+/// A payment of a fixed amount to a lightning address or LNURL that is
+/// repeated every `interval_secs`, starting at `start_secs`. Every run fetches
+/// a fresh invoice from the recipient.
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize, Encodable, Decodable)]
+pub struct RecurringPayment {
+    /// A lightning address or bech32-encoded LNURL
+    pub recipient: String,
+    /// The amount paid to the recipient per run, excluding fees
+    pub amount: Amount,
+    pub interval_secs: u64,
+    /// Unix timestamp of the first run
+    pub start_secs: u64,
+    /// Number of runs after which the recurring payment is completed
+    pub max_runs: Option<u64>,
+    /// Total amount including fees that may be spent over all runs
+    pub spending_limit: Option<Amount>,
+}
+
+impl RecurringPayment {
+    /// Unix timestamp at which the given run is due.
+    pub fn due_secs(&self, run: u64) -> u64 {
+        self.start_secs
+            .saturating_add(run.saturating_mul(self.interval_secs))
+    }
+
+    /// The LNURL-pay endpoint of the recipient.
+    pub fn pay_request_url(&self) -> Option<String> {
+        fedimint_lnurl::parse_address(&self.recipient)
+            .or_else(|| fedimint_lnurl::parse_lnurl(&self.recipient))
+    }
+}
+
+/// Links a send operation to the run of a recurring payment it pays.
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize, Encodable, Decodable)]
+pub struct RecurringPaymentRun {
+    pub operation_id: OperationId,
+    pub run: u64,
+}
+
 #[cfg_attr(doc, aquamarine::aquamarine)]
 /// The state of an operation sending a payment over lightning.
 ///
@@ -170,6 +290,8 @@ pub enum SendOperationState {
 pub enum FinalSendOperationState {
     /// The payment was successful. Carries the payment preimage proving
     /// the gateway settled the invoice, serialized as a lowercase hex string.
+    /// The preimage of a keysend payment is chosen by the payer and proves
+    /// nothing.
     Success(#[serde(with = "fedimint_core::hex::serde")] [u8; 32]),
     /// The payment has been refunded.
     Refunded,
@@ -188,6 +310,9 @@ pub enum FinalSendOperationState {
 ///
 ///     Pending -- payment is confirmed --> Claiming
 ///     Pending -- invoice expires --> Expired
+///     Pending -- payment of hold invoice is held --> Held
+///     Held -- hold invoice is settled --> Pending
+///     Held -- hold invoice is cancelled or deadline passes --> Rejected
 ///     Claiming -- ecash is minted --> Claimed
 ///     Claiming -- minting ecash fails --> Failure
 /// ```
@@ -197,6 +322,12 @@ pub enum ReceiveOperationState {
     Pending,
     /// The payment request has expired.
     Expired,
+    /// The gateway holds the payment of a hold invoice until we settle or
+    /// cancel it, or the deadline given as unix time passes.
+    Held { deadline_secs: u64 },
+    /// The hold invoice has been cancelled or has not been settled before the
+    /// deadline, and the payment has been returned to the payer.
+    Rejected,
     /// The payment has been confirmed and we are issuing the ecash.
     Claiming,
     /// The payment has been successful.
@@ -210,6 +341,9 @@ pub enum ReceiveOperationState {
 pub enum FinalReceiveOperationState {
     /// The payment request has expired.
     Expired,
+    /// The hold invoice has been cancelled or has not been settled before the
+    /// deadline.
+    Rejected,
     /// The payment has been successful.
     Claimed,
     /// Either a programming error has occurred or the federation is malicious.
@@ -318,6 +452,7 @@ pub struct LightningClientModule {
     module_api: DynModuleApi,
     keypair: Keypair,
     lnurl_keypair: Keypair,
+    keysend_secret: DerivableSecret,
     gateway_conn: Arc<dyn GatewayConnection + Send + Sync>,
     #[allow(unused)] // The field is only used by the cli feature
     admin_auth: Option<ApiAuth>,
@@ -395,6 +530,7 @@ fn new(
             lnurl_keypair: module_root_secret
                 .child_key(ChildId(1))
                 .to_secp_key(SECP256K1),
+            keysend_secret: module_root_secret.child_key(ChildId(2)),
             gateway_conn,
             admin_auth,
         };
@@ -403,6 +539,8 @@ fn new(
 
         module.spawn_gateway_map_update_task(task_group, client_span);
 
+        module.spawn_gateway_score_task(task_group, client_span);
+
         module
     }
 
@@ -442,15 +580,213 @@ async fn update_gateway_map(&self) {
             }
 
             if let Err(e) = dbtx.commit_tx_result().await {
-                warn!("Failed to commit the updated gateway mapping to the database: {e}");
+                warn!(
+                    target: LOG_CLIENT_MODULE_LNV2,
+                    "Failed to commit the updated gateway mapping to the database: {e}"
+                );
+            }
+        }
+    }
+
+    fn spawn_gateway_score_task(&self, task_group: &TaskGroup, client_span: &tracing::Span) {
+        let module = self.clone();
+
+        task_group.spawn_cancellable_with_span(
+            client_span.clone(),
+            "gateway_score_task",
+            async move {
+                let mut log_event_added_rx = module.client_ctx.log_event_added_rx();
+
+                loop {
+                    // Caught up with the log; wait for new events to be written.
+                    if !module.update_gateway_scores().await
+                        && log_event_added_rx.changed().await.is_err()
+                    {
+                        break;
+                    }
+                }
+            },
+        );
+    }
+
+    /// Accounts for the next page of the event log in the gateway scores and
+    /// returns whether there were any new events.
+    async fn update_gateway_scores(&self) -> bool {
+        let position = self
+            .client_ctx
+            .module_db()
+            .begin_transaction_nc()
+            .await
+            .get_value(&GatewayScoreCursorKey)
+            .await;
+
+        // A client that starts scoring gateways does not replay its history
+        let position = match position {
+            Some(position) => position,
+            None => {
+                let position = self.client_ctx.get_next_event_log_id().await;
+
+                let mut dbtx = self.client_ctx.module_db().begin_transaction().await;
+
+                dbtx.insert_entry(&GatewayScoreCursorKey, &position).await;
+
+                if let Err(e) = dbtx.commit_tx_result().await {
+                    warn!(
+                        target: LOG_CLIENT_MODULE_LNV2,
+                        "Failed to commit the gateway score cursor to the database: {e}"
+                    );
+
+                    return false;
+                }
+
+                position
+            }
+        };
+
+        let events = self
+            .client_ctx
+            .get_event_log(Some(position), EVENT_LOG_PAGE_SIZE)
+            .await;
+
+        let Some(last_entry) = events.last() else {
+            return false;
+        };
+
+        let mut dbtx = self.client_ctx.module_db().begin_transaction().await;
+
+        for entry in events
+            .iter()
+            .filter(|entry| entry.module_kind() == Some(&KIND))
+        {
+            if entry.kind == SendPaymentEvent::KIND
+                && let Some(event) = entry.to_event::<SendPaymentEvent>()
+                && let Some(meta) = self.send_operation_meta(event.operation_id).await
+            {
+                dbtx.insert_entry(
+                    &PendingSendScoreKey(event.operation_id),
+                    &PendingSendScore {
+                        started_at_usecs: entry.ts_usecs,
+                        remaining_parts: 1 + meta.additional_parts.len() as u64,
+                    },
+                )
+                .await;
+            }
+
+            if entry.kind == SendPaymentUpdateEvent::KIND
+                && let Some(event) = entry.to_event::<SendPaymentUpdateEvent>()
+            {
+                self.record_send_update(&mut dbtx.to_ref_nc(), event, entry.ts_usecs)
+                    .await;
+            }
+        }
+
+        dbtx.insert_entry(&GatewayScoreCursorKey, &last_entry.id().saturating_add(1))
+            .await;
+
+        if let Err(e) = dbtx.commit_tx_result().await {
+            warn!(
+                target: LOG_CLIENT_MODULE_LNV2,
+                "Failed to commit the updated gateway scores to the database: {e}"
+            );
+        }
+
+        true
+    }
+
+    async fn record_send_update(
+        &self,
+        dbtx: &mut DatabaseTransaction<'_>,
+        event: SendPaymentUpdateEvent,
+        ts_usecs: u64,
+    ) {
+        let key = PendingSendScoreKey(event.operation_id);
+
+        let Some(pending) = dbtx.get_value(&key).await else {
+            return;
+        };
+
+        let Some(meta) = self.send_operation_meta(event.operation_id).await else {
+            return;
+        };
+
+        let gateway = event.gateway.unwrap_or_else(|| meta.gateway.clone());
+
+        let mut score = dbtx
+            .get_value(&GatewayScoreKey(gateway.clone()))
+            .await
+            .unwrap_or_default();
+
+        match event.status {
+            SendPaymentStatus::Success(..) => {
+                score.successes += 1;
+                score.consecutive_refunds = 0;
+                score.total_latency_ms += ts_usecs.saturating_sub(pending.started_at_usecs) / 1000;
+                score.total_fees += meta.gateway_part_fee(&gateway);
+            }
+            // Only a gateway that let the contract expire is to blame for the
+            // refund, it cancels payments that fail on the payee's side
+            SendPaymentStatus::Refunded
+                if event.refund_reason == Some(SendRefundReason::Expired) =>
+            {
+                score.refunds += 1;
+                score.consecutive_refunds += 1;
+                score.last_refund_secs = Some(ts_usecs / 1_000_000);
             }
+            SendPaymentStatus::Refunded => {}
+        }
+
+        dbtx.insert_entry(&GatewayScoreKey(gateway), &score).await;
+
+        if pending.remaining_parts > 1 {
+            dbtx.insert_entry(
+                &key,
+                &PendingSendScore {
+                    remaining_parts: pending.remaining_parts - 1,
+                    ..pending
+                },
+            )
+            .await;
+        } else {
+            dbtx.remove_entry(&key).await;
         }
     }
 
+    async fn send_operation_meta(&self, operation_id: OperationId) -> Option<SendOperationMeta> {
+        match self
+            .client_ctx
+            .get_operation(operation_id)
+            .await
+            .ok()?
+            .try_meta::<LightningOperationMeta>()
+            .ok()?
+        {
+            LightningOperationMeta::Send(meta) => Some(meta),
+            _ => None,
+        }
+    }
+
+    /// Returns the scores of all gateways this client has sent payments
+    /// through. The scores are derived from the event log in the background,
+    /// so a payment that just reached its final state may not be reflected
+    /// yet.
+    pub async fn gateway_scores(&self) -> BTreeMap<SafeUrl, GatewayScore> {
+        self.client_ctx
+            .module_db()
+            .begin_transaction_nc()
+            .await
+            .find_by_prefix(&GatewayScorePrefix)
+            .await
+            .map(|(key, score)| (key.0, score))
+            .collect()
+            .await
+    }
+
     /// Selects an available gateway by querying the federation's registered
     /// gateways, checking if one of them match the invoice's payee public
     /// key, then queries the gateway for `RoutingInfo` to determine if it is
-    /// online.
+    /// online. Gateways are tried in the order of their
+    /// [`Self::gateway_scores`], and the payee's gateway is skipped if it has
+    /// failed repeatedly.
     pub async fn select_gateway(
         &self,
         invoice: Option<Bolt11Invoice>,
@@ -465,6 +801,12 @@ pub async fn select_gateway(
             return Err(SelectGatewayError::NoGatewaysAvailable);
         }
 
+        let scores = self.gateway_scores().await;
+
+        let now_secs = duration_since_epoch().as_secs();
+
+        let gateways = rank_gateways(gateways, &scores, now_secs);
+
         if let Some(invoice) = invoice
             && let Some(gateway) = self
                 .client_ctx
@@ -474,6 +816,9 @@ pub async fn select_gateway(
                 .get_value(&GatewayKey(invoice.recover_payee_pub_key()))
                 .await
                 .filter(|gateway| gateways.contains(gateway))
+            && !scores
+                .get(&gateway)
+                .is_some_and(|score| score.is_demoted(now_secs))
             && let Ok(Some(routing_info)) = self.routing_info(&gateway).await
         {
             return Ok((gateway, routing_info));
@@ -535,16 +880,25 @@ pub async fn routing_info(
     ///
     /// The absolute fee for a payment can be calculated from the operation meta
     /// to be shown to the user in the transaction history.
-    #[allow(clippy::too_many_lines)]
     pub async fn send(
         &self,
         invoice: Bolt11Invoice,
         gateway: Option<SafeUrl>,
         custom_meta: Value,
     ) -> Result<OperationId, SendPaymentError> {
-        let amount = invoice
-            .amount_milli_satoshis()
-            .ok_or(SendPaymentError::InvoiceMissingAmount)?;
+        self.send_invoice(invoice, gateway, custom_meta, None).await
+    }
+
+    async fn send_invoice(
+        &self,
+        invoice: Bolt11Invoice,
+        gateway: Option<SafeUrl>,
+        custom_meta: Value,
+        recurring_payment: Option<RecurringPaymentRun>,
+    ) -> Result<OperationId, SendPaymentError> {
+        if invoice.amount_milli_satoshis().is_none() {
+            return Err(SendPaymentError::InvoiceMissingAmount);
+        }
 
         if invoice.is_expired() {
             return Err(SendPaymentError::InvoiceExpired);
@@ -566,11 +920,48 @@ pub async fn send(
             return Err(SendPaymentError::DuplicatePaymentAttempt(operation_id));
         }
 
-        let (ephemeral_tweak, ephemeral_pk) = tweak::generate(self.keypair.public_key());
+        let (gateway_api, routing_info) = match gateway {
+            Some(gateway_api) => (
+                gateway_api.clone(),
+                self.routing_info(&gateway_api)
+                    .await
+                    .map_err(|e| SendPaymentError::FailedToConnectToGateway(e.to_string()))?
+                    .ok_or(SendPaymentError::FederationNotSupported)?,
+            ),
+            None => self
+                .select_gateway(Some(invoice.clone()))
+                .await
+                .map_err(SendPaymentError::SelectGateway)?,
+        };
+
+        self.fund_outgoing_contracts(
+            operation_id,
+            LightningInvoice::Bolt11(invoice.clone()),
+            vec![(gateway_api, routing_info, LightningInvoice::Bolt11(invoice))],
+            custom_meta,
+            recurring_payment,
+        )
+        .await
+    }
 
-        let refund_keypair = SecretKey::from_slice(&ephemeral_tweak)
-            .expect("32 bytes, within curve order")
-            .keypair(secp256k1::SECP256K1);
+    /// Previews the payment of an invoice without paying it. The gateway is
+    /// selected the same way as in [`Self::send`] and probes the Lightning
+    /// network for a route to the payee, reporting the routing fee within its
+    /// fee budget and, if its node provides one, the probability of success.
+    /// Gateways whose node cannot probe payments are previewed without a
+    /// route.
+    ///
+    /// The gateway fee already covers the routing fee, so the total cost of
+    /// the payment is the gateway fee plus the federation fee. The preview is
+    /// point-in-time and the eventual payment may still fail.
+    pub async fn preview_send(
+        &self,
+        invoice: Bolt11Invoice,
+        gateway: Option<SafeUrl>,
+    ) -> anyhow::Result<SendPreview> {
+        let amount = invoice
+            .amount_milli_satoshis()
+            .ok_or(SendPaymentError::InvoiceMissingAmount)?;
 
         let (gateway_api, routing_info) = match gateway {
             Some(gateway_api) => (
@@ -586,103 +977,484 @@ pub async fn send(
                 .map_err(SendPaymentError::SelectGateway)?,
         };
 
-        let (send_fee, expiration_delta) = routing_info.send_parameters(&invoice);
+        let (send_fee, _) = routing_info.send_parameters(&invoice);
 
         if !send_fee.is_within(&PaymentFee::SEND_FEE_LIMIT) {
-            return Err(SendPaymentError::GatewayFeeExceedsLimit);
+            return Err(SendPaymentError::GatewayFeeExceedsLimit.into());
         }
 
-        if EXPIRATION_DELTA_LIMIT < expiration_delta {
-            return Err(SendPaymentError::GatewayExpirationExceedsLimit);
-        }
+        let federation_fee = self
+            .send_fee_quote(send_fee.add_to(amount))
+            .await?
+            .total()
+            .get_bitcoin();
 
-        let consensus_block_count = self
-            .module_api
-            .consensus_block_count()
+        let route = self
+            .gateway_conn
+            .probe_payment(gateway_api.clone(), self.federation_id, invoice)
             .await
-            .map_err(|e| SendPaymentError::FailedToRequestBlockCount(e.to_string()))?;
+            .map_err(|e| SendPaymentError::FailedToConnectToGateway(e.to_string()))?;
+
+        Ok(SendPreview {
+            gateway: gateway_api,
+            gateway_fee: send_fee.fee(amount),
+            federation_fee,
+            route,
+        })
+    }
 
-        let contract = OutgoingContract {
-            payment_image: PaymentImage::Hash(*invoice.payment_hash()),
-            amount: send_fee.add_to(amount),
-            expiration: consensus_block_count + expiration_delta + CONTRACT_CONFIRMATION_BUFFER,
-            claim_pk: routing_info.module_public_key,
-            refund_pk: refund_keypair.public_key(),
-            ephemeral_pk,
+    /// Pay a BOLT12 offer. The gateway requests an invoice for the offer from
+    /// the payee, which we then pay like a BOLT11 invoice. The amount is
+    /// required for offers that do not specify one.
+    ///
+    /// The gateway returns the invoice signed by the payee, which we verify
+    /// against the offer, the requested amount and the payer note before
+    /// committing to its payment hash. Hence the gateway cannot claim the
+    /// contract without paying the payee the amount of the offer.
+    pub async fn send_offer(
+        &self,
+        offer: String,
+        amount: Option<Amount>,
+        payer_note: Option<String>,
+        gateway: Option<SafeUrl>,
+        custom_meta: Value,
+    ) -> Result<OperationId, SendPaymentError> {
+        let (gateway_api, routing_info) = match gateway {
+            Some(gateway_api) => (
+                gateway_api.clone(),
+                self.routing_info(&gateway_api)
+                    .await
+                    .map_err(|e| SendPaymentError::FailedToConnectToGateway(e.to_string()))?
+                    .ok_or(SendPaymentError::FederationNotSupported)?,
+            ),
+            None => self
+                .select_gateway(None)
+                .await
+                .map_err(SendPaymentError::SelectGateway)?,
         };
 
-        let contract_clone = contract.clone();
-        let gateway_api_clone = gateway_api.clone();
-        let invoice_clone = invoice.clone();
+        let invoice = self
+            .gateway_conn
+            .bolt12_invoice(
+                gateway_api.clone(),
+                self.federation_id,
+                offer.clone(),
+                amount,
+                payer_note.clone(),
+            )
+            .await
+            .map_err(|e| SendPaymentError::FailedToConnectToGateway(e.to_string()))?;
 
-        let client_output = ClientOutput::<LightningOutput> {
-            output: LightningOutput::V0(LightningOutputV0::Outgoing(contract.clone())),
-            amounts: Amounts::new_bitcoin(contract.amount),
-        };
+        if invoice.offer != offer {
+            return Err(SendPaymentError::InvalidOfferInvoice(
+                Bolt12InvoiceError::OfferMismatch,
+            ));
+        }
 
-        let client_output_sm = ClientOutputSM::<LightningClientStateMachines> {
-            state_machines: Arc::new(move |range: OutPointRange| {
-                vec![LightningClientStateMachines::Send(SendStateMachine {
-                    common: SendSMCommon {
-                        operation_id,
-                        outpoint: range.into_iter().next().unwrap(),
-                        contract: contract_clone.clone(),
-                        gateway_api: Some(gateway_api_clone.clone()),
-                        invoice: Some(LightningInvoice::Bolt11(invoice_clone.clone())),
-                        refund_keypair,
-                    },
-                    state: SendSMState::Funding,
-                })]
-            }),
+        invoice
+            .verify(self.cfg.network, amount, payer_note.as_deref())
+            .map_err(SendPaymentError::InvalidOfferInvoice)?;
+
+        let operation_id = OperationId::from_encodable(&invoice);
+
+        if self.client_ctx.operation_exists(operation_id).await {
+            return Err(SendPaymentError::DuplicatePaymentAttempt(operation_id));
+        }
+
+        self.fund_outgoing_contract(
+            operation_id,
+            gateway_api,
+            routing_info,
+            LightningInvoice::Bolt12(invoice),
+            custom_meta,
+        )
+        .await
+    }
+
+    /// Send a spontaneous payment to a Lightning node without an invoice, e.g.
+    /// for value-for-value streaming to a podcast. The custom records are
+    /// passed to the payee in the onion and have to be within the custom
+    /// range of record types.
+    ///
+    /// Since the gateway learns the preimage before paying the payee, it could
+    /// claim the outgoing contract without paying. Hence the payment is only
+    /// sent via `trusted_gateway`, which the caller has to trust with the
+    /// amount of the payment, not only the fee, and which has to have keysend
+    /// payments enabled. The preimage is derived from the client's secret and
+    /// the operation id, see [`Self::keysend_preimage`]. As the client chose
+    /// it, the preimage does not prove that the payee has been paid.
+    pub async fn send_keysend(
+        &self,
+        payee: PublicKey,
+        amount: Amount,
+        custom_records: BTreeMap<u64, Vec<u8>>,
+        trusted_gateway: SafeUrl,
+        custom_meta: Value,
+    ) -> Result<OperationId, SendPaymentError> {
+        let operation_id = OperationId::new_random();
+
+        let payment = KeysendPayment {
+            payee,
+            amount,
+            preimage: self.keysend_preimage(operation_id),
+            custom_records,
         };
 
-        let client_output = self.client_ctx.make_client_outputs(ClientOutputBundle::new(
-            vec![client_output],
-            vec![client_output_sm],
-        ));
+        if !payment.verify_custom_records() {
+            return Err(SendPaymentError::InvalidCustomRecords);
+        }
 
-        let transaction = TransactionBuilder::new().with_outputs(client_output);
+        let gateway_api = trusted_gateway;
 
-        self.client_ctx
-            .finalize_and_submit_transaction(
-                operation_id,
-                LightningCommonInit::KIND.as_str(),
-                move |change_outpoint_range| {
-                    LightningOperationMeta::Send(SendOperationMeta {
-                        change_outpoint_range,
-                        gateway: gateway_api.clone(),
-                        contract: contract.clone(),
-                        invoice: LightningInvoice::Bolt11(invoice.clone()),
-                        custom_meta: custom_meta.clone(),
-                    })
-                },
-                transaction,
-            )
+        let routing_info = self
+            .routing_info(&gateway_api)
             .await
-            .map_err(|e| SendPaymentError::FailedToFundPayment(e.to_string()))?;
-
-        let mut dbtx = self.client_ctx.module_db().begin_transaction().await;
+            .map_err(|e| SendPaymentError::FailedToConnectToGateway(e.to_string()))?
+            .ok_or(SendPaymentError::FederationNotSupported)?;
 
-        self.client_ctx
-            .log_event(
-                &mut dbtx,
-                SendPaymentEvent {
-                    operation_id,
-                    amount: Amount::from_msats(amount),
-                    fee: send_fee.fee(amount),
-                },
-            )
-            .await;
+        // A gateway that cannot send keysend payments would only cancel the
+        // contract after we have funded it, so we check before.
+        if !self
+            .gateway_conn
+            .supports_keysend(gateway_api.clone(), &self.federation_id)
+            .await
+            .map_err(|e| SendPaymentError::FailedToConnectToGateway(e.to_string()))?
+        {
+            return Err(SendPaymentError::KeysendNotSupported);
+        }
 
-        dbtx.commit_tx().await;
+        self.fund_outgoing_contract(
+            operation_id,
+            gateway_api,
+            routing_info,
+            LightningInvoice::Keysend(payment),
+            custom_meta,
+        )
+        .await
+    }
 
-        Ok(operation_id)
+    /// Derives the preimage of the keysend payment sent by the operation.
+    pub fn keysend_preimage(&self, operation_id: OperationId) -> [u8; 32] {
+        self.keysend_secret.tweak(&operation_id.0).to_random_bytes()
     }
 
-    /// The status of a previous [`Self::send`] for this invoice.
+    /// Pay an invoice in parts over several gateways, for payments too large
+    /// for the liquidity of a single gateway. The amount is split evenly
+    /// across the given gateways, each of which is paid the fee for its part
+    /// via a separate outgoing contract. All contracts are funded in a single
+    /// transaction and tracked under one operation, so
+    /// [`Self::subscribe_send_operation_state_updates`] reports the state of
+    /// the payment as a whole.
     ///
-    /// Callers deciding whether an invoice is safe to pay through another
-    /// mechanism must treat anything but [`InvoiceSendStatus::NotAttempted`]
+    /// The payee only releases the preimage once all parts have arrived, so
+    /// either every gateway is paid or every part is refunded. The invoice
+    /// must support multi-part payments and the gateways must be distinct,
+    /// since a gateway only pays a single contract per payment hash. Use
+    /// [`Self::select_gateways`] to pick the gateways automatically. Only
+    /// gateways backed by LND or CLN can pay parts, gateways running the
+    /// embedded LDK node are rejected and never selected.
+    pub async fn send_split(
+        &self,
+        invoice: Bolt11Invoice,
+        gateways: Vec<SafeUrl>,
+        custom_meta: Value,
+    ) -> Result<OperationId, SendPaymentError> {
+        let amount = invoice
+            .amount_milli_satoshis()
+            .ok_or(SendPaymentError::InvoiceMissingAmount)?;
+
+        if invoice.is_expired() {
+            return Err(SendPaymentError::InvoiceExpired);
+        }
+
+        if self.cfg.network != invoice.currency().into() {
+            return Err(SendPaymentError::WrongCurrency {
+                invoice_currency: invoice.currency(),
+                federation_currency: self.cfg.network.into(),
+            });
+        }
+
+        if !invoice.features().is_some_and(|f| f.supports_basic_mpp()) {
+            return Err(SendPaymentError::InvoiceDoesNotSupportMpp);
+        }
+
+        if gateways.len() < 2 || gateways.iter().collect::<BTreeSet<_>>().len() != gateways.len() {
+            return Err(SendPaymentError::InvalidSplit);
+        }
+
+        let num_parts = gateways.len() as u64;
+
+        if amount < num_parts {
+            return Err(SendPaymentError::InvalidSplit);
+        }
+
+        // The operation id is derived like for an unsplit payment, such that a
+        // duplicate payment is detected regardless of how the invoice was paid.
+        let operation_id = OperationId::from_encodable(&(invoice.clone(), 0u64));
+
+        if self.client_ctx.operation_exists(operation_id).await {
+            return Err(SendPaymentError::DuplicatePaymentAttempt(operation_id));
+        }
+
+        let mut parts = Vec::with_capacity(gateways.len());
+
+        for (index, gateway_api) in gateways.into_iter().enumerate() {
+            let routing_info = self
+                .routing_info(&gateway_api)
+                .await
+                .map_err(|e| SendPaymentError::FailedToConnectToGateway(e.to_string()))?
+                .ok_or(SendPaymentError::FederationNotSupported)?;
+
+            // A gateway that cannot pay a part would only cancel the contract
+            // after we have funded it, so we check before.
+            if !self
+                .gateway_conn
+                .supports_multi_part(gateway_api.clone(), &self.federation_id)
+                .await
+                .map_err(|e| SendPaymentError::FailedToConnectToGateway(e.to_string()))?
+            {
+                return Err(SendPaymentError::MultiPartNotSupported);
+            }
+
+            // The first part carries the remainder of the even split
+            let part_amount = if index == 0 {
+                amount / num_parts + amount % num_parts
+            } else {
+                amount / num_parts
+            };
+
+            parts.push((
+                gateway_api,
+                routing_info,
+                LightningInvoice::Bolt11Part(Bolt11InvoicePart {
+                    invoice: invoice.clone(),
+                    amount: Amount::from_msats(part_amount),
+                }),
+            ));
+        }
+
+        self.fund_outgoing_contracts(
+            operation_id,
+            LightningInvoice::Bolt11(invoice),
+            parts,
+            custom_meta,
+            None,
+        )
+        .await
+    }
+
+    /// Selects `count` distinct online gateways that can pay parts of a
+    /// multi-part payment to split a payment over via [`Self::send_split`],
+    /// preferring gateways with better scores.
+    pub async fn select_gateways(&self, count: usize) -> Result<Vec<SafeUrl>, SelectGatewayError> {
+        let gateways = self
+            .module_api
+            .gateways()
+            .await
+            .map_err(|e| SelectGatewayError::FailedToRequestGateways(e.to_string()))?;
+
+        if gateways.len() < count {
+            return Err(SelectGatewayError::NoGatewaysAvailable);
+        }
+
+        let scores = self.gateway_scores().await;
+
+        let gateways = rank_gateways(gateways, &scores, duration_since_epoch().as_secs());
+
+        let mut selected = Vec::with_capacity(count);
+
+        for gateway in gateways {
+            if selected.len() == count {
+                break;
+            }
+
+            if let Ok(Some(..)) = self.routing_info(&gateway).await
+                && let Ok(true) = self
+                    .gateway_conn
+                    .supports_multi_part(gateway.clone(), &self.federation_id)
+                    .await
+            {
+                selected.push(gateway);
+            }
+        }
+
+        if selected.len() < count {
+            return Err(SelectGatewayError::GatewaysUnresponsive);
+        }
+
+        Ok(selected)
+    }
+
+    /// Funds an outgoing contract for the invoice and starts the state machine
+    /// instructing the gateway to pay it.
+    async fn fund_outgoing_contract(
+        &self,
+        operation_id: OperationId,
+        gateway_api: SafeUrl,
+        routing_info: RoutingInfo,
+        invoice: LightningInvoice,
+        custom_meta: Value,
+    ) -> Result<OperationId, SendPaymentError> {
+        self.fund_outgoing_contracts(
+            operation_id,
+            invoice.clone(),
+            vec![(gateway_api, routing_info, invoice)],
+            custom_meta,
+            None,
+        )
+        .await
+    }
+
+    /// Funds an outgoing contract for every part of the payment in a single
+    /// transaction and starts the state machines instructing the gateways to
+    /// pay them.
+    #[allow(clippy::too_many_lines)]
+    async fn fund_outgoing_contracts(
+        &self,
+        operation_id: OperationId,
+        invoice: LightningInvoice,
+        parts: Vec<(SafeUrl, RoutingInfo, LightningInvoice)>,
+        custom_meta: Value,
+        recurring_payment: Option<RecurringPaymentRun>,
+    ) -> Result<OperationId, SendPaymentError> {
+        let amount = invoice
+            .amount()
+            .ok_or(SendPaymentError::InvoiceMissingAmount)?;
+
+        let consensus_block_count = self
+            .module_api
+            .consensus_block_count()
+            .await
+            .map_err(|e| SendPaymentError::FailedToRequestBlockCount(e.to_string()))?;
+
+        let mut fee = Amount::ZERO;
+        let mut contracts = Vec::with_capacity(parts.len());
+        let mut client_outputs = Vec::with_capacity(parts.len());
+        let mut client_output_sms = Vec::with_capacity(parts.len());
+
+        for (gateway_api, routing_info, part_invoice) in parts {
+            let part_amount = part_invoice
+                .amount()
+                .ok_or(SendPaymentError::InvoiceMissingAmount)?
+                .msats;
+
+            let (send_fee, expiration_delta) = match &part_invoice {
+                LightningInvoice::Bolt11(invoice) => routing_info.send_parameters(invoice),
+                // The gateway can neither pay an invoice for an offer, a part of
+                // a multi-part payment nor a keysend payment via a direct swap
+                LightningInvoice::Bolt12(..)
+                | LightningInvoice::Bolt11Part(..)
+                | LightningInvoice::Keysend(..) => (
+                    routing_info.send_fee_default_for(Amount::from_msats(part_amount)),
+                    routing_info.expiration_delta_default,
+                ),
+            };
+
+            if !send_fee.is_within(&PaymentFee::SEND_FEE_LIMIT) {
+                return Err(SendPaymentError::GatewayFeeExceedsLimit);
+            }
+
+            if EXPIRATION_DELTA_LIMIT < expiration_delta {
+                return Err(SendPaymentError::GatewayExpirationExceedsLimit);
+            }
+
+            let (ephemeral_tweak, ephemeral_pk) = tweak::generate(self.keypair.public_key());
+
+            let refund_keypair = SecretKey::from_slice(&ephemeral_tweak)
+                .expect("32 bytes, within curve order")
+                .keypair(secp256k1::SECP256K1);
+
+            let contract = OutgoingContract {
+                payment_image: PaymentImage::Hash(part_invoice.payment_hash()),
+                amount: send_fee.add_to(part_amount),
+                expiration: consensus_block_count + expiration_delta + CONTRACT_CONFIRMATION_BUFFER,
+                claim_pk: routing_info.module_public_key,
+                refund_pk: refund_keypair.public_key(),
+                ephemeral_pk,
+            };
+
+            fee += send_fee.fee(part_amount);
+
+            client_outputs.push(ClientOutput::<LightningOutput> {
+                output: LightningOutput::V0(LightningOutputV0::Outgoing(contract.clone())),
+                amounts: Amounts::new_bitcoin(contract.amount),
+            });
+
+            let contract_clone = contract.clone();
+            let gateway_api_clone = gateway_api.clone();
+
+            client_output_sms.push(ClientOutputSM::<LightningClientStateMachines> {
+                state_machines: Arc::new(move |range: OutPointRange| {
+                    vec![LightningClientStateMachines::Send(SendStateMachine {
+                        common: SendSMCommon {
+                            operation_id,
+                            outpoint: range.into_iter().next().unwrap(),
+                            contract: contract_clone.clone(),
+                            gateway_api: Some(gateway_api_clone.clone()),
+                            invoice: Some(part_invoice.clone()),
+                            refund_keypair,
+                        },
+                        state: SendSMState::Funding,
+                    })]
+                }),
+            });
+
+            contracts.push(SendPartMeta {
+                gateway: gateway_api,
+                contract,
+            });
+        }
+
+        let first_part = contracts.remove(0);
+
+        let client_output = self
+            .client_ctx
+            .make_client_outputs(ClientOutputBundle::new(client_outputs, client_output_sms));
+
+        let transaction = TransactionBuilder::new().with_outputs(client_output);
+
+        self.client_ctx
+            .finalize_and_submit_transaction(
+                operation_id,
+                LightningCommonInit::KIND.as_str(),
+                move |change_outpoint_range| {
+                    LightningOperationMeta::Send(SendOperationMeta {
+                        change_outpoint_range,
+                        gateway: first_part.gateway.clone(),
+                        contract: first_part.contract.clone(),
+                        invoice: invoice.clone(),
+                        additional_parts: contracts.clone(),
+                        recurring_payment: recurring_payment.clone(),
+                        custom_meta: custom_meta.clone(),
+                    })
+                },
+                transaction,
+            )
+            .await
+            .map_err(|e| SendPaymentError::FailedToFundPayment(e.to_string()))?;
+
+        let mut dbtx = self.client_ctx.module_db().begin_transaction().await;
+
+        self.client_ctx
+            .log_event(
+                &mut dbtx,
+                SendPaymentEvent {
+                    operation_id,
+                    amount,
+                    fee,
+                },
+            )
+            .await;
+
+        dbtx.commit_tx().await;
+
+        Ok(operation_id)
+    }
+
+    /// The status of a previous [`Self::send`] for this invoice.
+    ///
+    /// Callers deciding whether an invoice is safe to pay through another
+    /// mechanism must treat anything but [`InvoiceSendStatus::NotAttempted`]
     /// as a potential double payment.
     pub async fn get_invoice_send_status(
         &self,
@@ -728,7 +1500,9 @@ pub async fn get_invoice_send_status(
         Ok(InvoiceSendStatus::Failed(operation_id))
     }
 
-    /// Subscribe to all state updates of the send operation.
+    /// Subscribe to all state updates of the send operation. For a payment
+    /// split over several gateways the states of its parts are aggregated into
+    /// the state of the payment as a whole, see [`aggregate_send_state`].
     pub async fn subscribe_send_operation_state_updates(
         &self,
         operation_id: OperationId,
@@ -738,6 +1512,11 @@ pub async fn subscribe_send_operation_state_updates(
         let client_ctx = self.client_ctx.clone();
         let module_api = self.module_api.clone();
 
+        let num_parts = match operation.meta::<LightningOperationMeta>() {
+            LightningOperationMeta::Send(meta) => meta.additional_parts.len() + 1,
+            _ => bail!("Operation is not a send operation"),
+        };
+
         Ok(self.client_ctx.outcome_or_updates(&operation, operation_id, |state| match state {
                 SendOperationState::Funding
                 | SendOperationState::Funded
@@ -747,45 +1526,64 @@ pub async fn subscribe_send_operation_state_updates(
                 | SendOperationState::Failure => true,
             }, move || {
             stream! {
+                let mut parts = BTreeMap::new();
+                let mut last_state = None;
+
                 loop {
                     if let Some(LightningClientStateMachines::Send(state)) = stream.next().await {
-                        match state.state {
-                            SendSMState::Funding => yield SendOperationState::Funding,
-                            SendSMState::Funded => yield SendOperationState::Funded,
+                        let part_state = match state.state {
+                            SendSMState::Funding => SendOperationState::Funding,
+                            SendSMState::Funded => SendOperationState::Funded,
                             SendSMState::Success(preimage) => {
                                 // the preimage has been verified by the state machine previously
                                 assert!(state.common.contract.verify_preimage(&preimage));
 
-                                yield SendOperationState::Success(preimage);
-                                return;
+                                SendOperationState::Success(preimage)
                             },
                             SendSMState::Refunding(out_points) => {
-                                yield SendOperationState::Refunding;
+                                parts.insert(state.common.outpoint, SendOperationState::Refunding);
 
-                                if client_ctx.await_primary_module_outputs(operation_id, out_points.clone()).await.is_ok() {
-                                    yield SendOperationState::Refunded;
-                                    return;
+                                let aggregate = aggregate_send_state(&parts, num_parts);
+
+                                if last_state.as_ref() != Some(&aggregate) {
+                                    yield aggregate.clone();
+                                    last_state = Some(aggregate);
                                 }
 
-                                // The gateway may have incorrectly claimed the outgoing contract thereby causing
-                                // our refund transaction to be rejected. Therefore, we check one last time if
-                                // the preimage is available before we enter the failure state.
-                                if let Some(preimage) = module_api.await_preimage(
+                                if client_ctx.await_primary_module_outputs(operation_id, out_points.clone()).await.is_ok() {
+                                    SendOperationState::Refunded
+                                } else if let Some(preimage) = module_api.await_preimage(
                                     state.common.outpoint,
                                     0
                                 ).await
                                     && state.common.contract.verify_preimage(&preimage) {
-                                        yield SendOperationState::Success(preimage);
-                                        return;
-                                    }
-
-                                yield SendOperationState::Failure;
-                                return;
-                            },
-                            SendSMState::Rejected(..) => {
-                                yield SendOperationState::Failure;
-                                return;
+                                    // The gateway may have incorrectly claimed the outgoing contract thereby causing
+                                    // our refund transaction to be rejected. Therefore, we check one last time if
+                                    // the preimage is available before we enter the failure state.
+                                    SendOperationState::Success(preimage)
+                                } else {
+                                    SendOperationState::Failure
+                                }
                             },
+                            SendSMState::Rejected(..) => SendOperationState::Failure,
+                        };
+
+                        parts.insert(state.common.outpoint, part_state);
+
+                        let aggregate = aggregate_send_state(&parts, num_parts);
+
+                        if last_state.as_ref() != Some(&aggregate) {
+                            yield aggregate.clone();
+                            last_state = Some(aggregate.clone());
+                        }
+
+                        if matches!(
+                            aggregate,
+                            SendOperationState::Success(..)
+                                | SendOperationState::Refunded
+                                | SendOperationState::Failure
+                        ) {
+                            return;
                         }
                     }
                 }
@@ -847,6 +1645,7 @@ pub async fn receive(
                 expiry_secs,
                 description,
                 gateway,
+                None,
             )
             .await?;
 
@@ -860,6 +1659,55 @@ pub async fn receive(
                     invoice: LightningInvoice::Bolt11(invoice.clone()),
                     custom_meta,
                 }),
+                None,
+            )
+            .await
+            .expect("The contract has been generated with our public key");
+
+        Ok((invoice, operation_id))
+    }
+
+    /// Request a hold invoice. Once the invoice is paid the gateway holds the
+    /// payment for up to `hold_secs` instead of settling it, such that we can
+    /// decide whether to accept it via [`Self::settle_hold_invoice`] or return
+    /// it to the payer via [`Self::cancel_hold_invoice`]. The payment is
+    /// returned to the payer if we do not decide before the deadline reported
+    /// in [`ReceiveOperationState::Held`].
+    pub async fn receive_hold(
+        &self,
+        amount: Amount,
+        expiry_secs: u32,
+        hold_secs: u32,
+        description: Bolt11InvoiceDescription,
+        gateway: Option<SafeUrl>,
+        custom_meta: Value,
+    ) -> Result<(Bolt11Invoice, OperationId), ReceiveError> {
+        if hold_secs > MAX_HOLD_INVOICE_SECS {
+            return Err(ReceiveError::HoldTooLong);
+        }
+
+        let (gateway, contract, invoice) = self
+            .create_contract_and_fetch_invoice(
+                self.keypair.public_key(),
+                amount,
+                expiry_secs,
+                description,
+                gateway,
+                Some(hold_secs),
+            )
+            .await?;
+
+        let operation_id = self
+            .receive_incoming_contract(
+                self.keypair.secret_key(),
+                contract.clone(),
+                LightningOperationMeta::Receive(ReceiveOperationMeta {
+                    gateway: gateway.clone(),
+                    contract,
+                    invoice: LightningInvoice::Bolt11(invoice.clone()),
+                    custom_meta,
+                }),
+                Some(gateway),
             )
             .await
             .expect("The contract has been generated with our public key");
@@ -867,6 +1715,54 @@ pub async fn receive(
         Ok((invoice, operation_id))
     }
 
+    /// Accept the held payment of a hold invoice, upon which the gateway funds
+    /// the incoming contract and the payment is received like any other.
+    pub async fn settle_hold_invoice(&self, operation_id: OperationId) -> anyhow::Result<()> {
+        self.resolve_hold_invoice(operation_id, true).await
+    }
+
+    /// Return the held payment of a hold invoice to the payer. An unpaid hold
+    /// invoice can be cancelled as well, such that it can no longer be paid.
+    pub async fn cancel_hold_invoice(&self, operation_id: OperationId) -> anyhow::Result<()> {
+        self.resolve_hold_invoice(operation_id, false).await
+    }
+
+    async fn resolve_hold_invoice(
+        &self,
+        operation_id: OperationId,
+        settle: bool,
+    ) -> anyhow::Result<()> {
+        let LightningOperationMeta::Receive(meta) = self
+            .client_ctx
+            .get_operation(operation_id)
+            .await?
+            .meta::<LightningOperationMeta>()
+        else {
+            bail!("Operation is not a receive operation");
+        };
+
+        let (claim_keypair, _) = self
+            .recover_contract_keys(self.keypair.secret_key(), &meta.contract)
+            .ok_or_else(|| anyhow::anyhow!("The contract is not locked to our key"))?;
+
+        let contract_id = meta.contract.contract_id();
+
+        self.gateway_conn
+            .resolve_hold_invoice(
+                meta.gateway,
+                self.federation_id,
+                contract_id,
+                settle,
+                SECP256K1.sign_schnorr(
+                    &resolve_hold_invoice_message(&contract_id, settle),
+                    &claim_keypair,
+                ),
+            )
+            .await?;
+
+        Ok(())
+    }
+
     /// Computes the federation fee a `receive` of `amount` would incur, without
     /// submitting anything.
     ///
@@ -1001,6 +1897,7 @@ async fn create_contract_and_fetch_invoice(
         expiry_secs: u32,
         description: Bolt11InvoiceDescription,
         gateway: Option<SafeUrl>,
+        hold_secs: Option<u32>,
     ) -> Result<(SafeUrl, IncomingContract, Bolt11Invoice), ReceiveError> {
         if expiry_secs > MAX_INVOICE_EXPIRY_SECS {
             return Err(ReceiveError::InvoiceExpiryTooLong);
@@ -1043,9 +1940,13 @@ async fn create_contract_and_fetch_invoice(
             return Err(ReceiveError::AmountTooSmall);
         }
 
+        // The gateway funds the contract of a hold invoice only once we have
+        // settled the payment, which may be up to `hold_secs` after the invoice
+        // has been paid.
         let expiration = duration_since_epoch()
             .as_secs()
-            .saturating_add(u64::from(expiry_secs));
+            .saturating_add(u64::from(expiry_secs))
+            .saturating_add(u64::from(hold_secs.unwrap_or(0)));
 
         let claim_pk = recipient_static_pk
             .mul_tweak(
@@ -1066,18 +1967,34 @@ async fn create_contract_and_fetch_invoice(
             ephemeral_pk,
         );
 
-        let invoice = self
-            .gateway_conn
-            .bolt11_invoice(
-                gateway.clone(),
-                self.federation_id,
-                contract.clone(),
-                amount,
-                description,
-                expiry_secs,
-            )
-            .await
-            .map_err(|e| ReceiveError::FailedToConnectToGateway(e.to_string()))?;
+        let invoice = match hold_secs {
+            Some(hold_secs) => {
+                self.gateway_conn
+                    .hold_bolt11_invoice(
+                        gateway.clone(),
+                        self.federation_id,
+                        contract.clone(),
+                        amount,
+                        description,
+                        expiry_secs,
+                        hold_secs,
+                    )
+                    .await
+            }
+            None => {
+                self.gateway_conn
+                    .bolt11_invoice(
+                        gateway.clone(),
+                        self.federation_id,
+                        contract.clone(),
+                        amount,
+                        description,
+                        expiry_secs,
+                    )
+                    .await
+            }
+        }
+        .map_err(|e| ReceiveError::FailedToConnectToGateway(e.to_string()))?;
 
         if invoice.payment_hash() != &preimage.consensus_hash() {
             return Err(ReceiveError::InvalidInvoice);
@@ -1091,12 +2008,14 @@ async fn create_contract_and_fetch_invoice(
     }
 
     // Receive an incoming contract locked to a public key derived from our
-    // static module public key.
+    // static module public key. For hold invoices the gateway holding the
+    // payment is passed in, such that we can track the status of the payment.
     async fn receive_incoming_contract(
         &self,
         sk: SecretKey,
         contract: IncomingContract,
         operation_meta: LightningOperationMeta,
+        hold_gateway: Option<SafeUrl>,
     ) -> Option<OperationId> {
         let operation_id = OperationId::from_encodable(&contract.clone());
 
@@ -1109,7 +2028,7 @@ async fn receive_incoming_contract(
                 claim_keypair,
                 agg_decryption_key,
             },
-            state: ReceiveSMState::Pending,
+            state: hold_gateway.map_or(ReceiveSMState::Pending, ReceiveSMState::AwaitingHold),
         });
 
         // this may only fail if the operation id is already in use, in which case we
@@ -1169,8 +2088,11 @@ pub async fn subscribe_receive_operation_state_updates(
         let client_ctx = self.client_ctx.clone();
 
         Ok(self.client_ctx.outcome_or_updates(&operation, operation_id, |state| match state {
-                ReceiveOperationState::Pending | ReceiveOperationState::Claiming => false,
+                ReceiveOperationState::Pending
+                | ReceiveOperationState::Held { .. }
+                | ReceiveOperationState::Claiming => false,
                 ReceiveOperationState::Expired
+                | ReceiveOperationState::Rejected
                 | ReceiveOperationState::Claimed
                 | ReceiveOperationState::Failure => true,
             }, move || {
@@ -1178,7 +2100,16 @@ pub async fn subscribe_receive_operation_state_updates(
                 loop {
                     if let Some(LightningClientStateMachines::Receive(state)) = stream.next().await {
                         match state.state {
-                            ReceiveSMState::Pending => yield ReceiveOperationState::Pending,
+                            ReceiveSMState::Pending | ReceiveSMState::AwaitingHold(..) => {
+                                yield ReceiveOperationState::Pending;
+                            }
+                            ReceiveSMState::Held(_, deadline_secs) => {
+                                yield ReceiveOperationState::Held { deadline_secs };
+                            }
+                            ReceiveSMState::Rejected => {
+                                yield ReceiveOperationState::Rejected;
+                                return;
+                            }
                             ReceiveSMState::Claiming(out_points) => {
                                 yield ReceiveOperationState::Claiming;
 
@@ -1217,6 +2148,9 @@ pub async fn await_final_receive_operation_state(
                 ReceiveOperationState::Expired => {
                     final_state = Some(FinalReceiveOperationState::Expired);
                 }
+                ReceiveOperationState::Rejected => {
+                    final_state = Some(FinalReceiveOperationState::Rejected);
+                }
                 ReceiveOperationState::Claimed => {
                     final_state = Some(FinalReceiveOperationState::Claimed);
                 }
@@ -1237,37 +2171,444 @@ pub async fn generate_lnurl(
         recurringd: SafeUrl,
         gateway: Option<SafeUrl>,
     ) -> Result<String, GenerateLnurlError> {
-        let gateways = if let Some(gateway) = gateway {
-            vec![gateway]
-        } else {
-            let gateways = self
-                .module_api
-                .gateways()
-                .await
-                .map_err(|e| GenerateLnurlError::FailedToRequestGateways(e.to_string()))?;
+        let gateways = self.lnurl_gateways(gateway).await?;
 
-            if gateways.is_empty() {
-                return Err(GenerateLnurlError::NoGatewaysAvailable);
-            }
+        Ok(fedimint_lnurl::encode_lnurl(&format!(
+            "{recurringd}pay/{}",
+            self.lnurl_payload(gateways)
+        )))
+    }
+
+    async fn lnurl_gateways(
+        &self,
+        gateway: Option<SafeUrl>,
+    ) -> Result<Vec<SafeUrl>, GenerateLnurlError> {
+        if let Some(gateway) = gateway {
+            return Ok(vec![gateway]);
+        }
 
-            gateways
+        let gateways = self
+            .module_api
+            .gateways()
+            .await
+            .map_err(|e| GenerateLnurlError::FailedToRequestGateways(e.to_string()))?;
+
+        if gateways.is_empty() {
+            return Err(GenerateLnurlError::NoGatewaysAvailable);
+        }
+
+        Ok(gateways)
+    }
+
+    /// Generate a static lnurl served directly by a gateway, such that no
+    /// service besides the gateway is needed to receive payments. You can
+    /// optionally specify a gateway to use for testing purposes.
+    ///
+    /// Payments to the lnurl do not require this client to be online: we
+    /// register an incoming contract for each of the next `payments` payments
+    /// of `amount` with the gateway, which funds one of them per payment. The
+    /// contracts never expire and are claimed in the background the next time
+    /// the client is running, like any payment to an lnurl. Since we create
+    /// the contracts, the gateway never learns the preimage of a payment and
+    /// cannot settle it without funding its contract.
+    ///
+    /// The lnurl only accepts payments of `amount` and stops accepting
+    /// payments once all contracts are used up. Calling this again returns
+    /// the same lnurl and registers further contracts.
+    pub async fn generate_static_lnurl(
+        &self,
+        gateway: Option<SafeUrl>,
+        amount: Amount,
+        payments: usize,
+    ) -> Result<String, GenerateLnurlError> {
+        if payments > MAX_STATIC_LNURL_CONTRACTS {
+            return Err(GenerateLnurlError::TooManyPayments);
+        }
+
+        let (gateway, routing_info) = match gateway {
+            Some(gateway) => (
+                gateway.clone(),
+                self.routing_info(&gateway)
+                    .await
+                    .map_err(|e| GenerateLnurlError::FailedToConnectToGateway(e.to_string()))?
+                    .ok_or(GenerateLnurlError::FederationNotSupported)?,
+            ),
+            None => self.select_gateway(None).await.map_err(|e| match e {
+                SelectGatewayError::FailedToRequestGateways(e) => {
+                    GenerateLnurlError::FailedToRequestGateways(e)
+                }
+                SelectGatewayError::NoGatewaysAvailable
+                | SelectGatewayError::GatewaysUnresponsive => {
+                    GenerateLnurlError::NoGatewaysAvailable
+                }
+            })?,
         };
 
-        let payload = fedimint_core::base32::encode_prefixed(
-            fedimint_core::base32::FEDIMINT_PREFIX,
-            &lnurl::LnurlRequest {
-                federation_id: self.federation_id,
-                recipient_pk: self.lnurl_keypair.public_key(),
-                aggregate_pk: self.cfg.tpe_agg_pk,
-                gateways,
-            },
+        if !routing_info
+            .receive_fee
+            .is_within(&PaymentFee::RECEIVE_FEE_LIMIT)
+        {
+            return Err(GenerateLnurlError::GatewayFeeExceedsLimit);
+        }
+
+        let contract_amount = routing_info.receive_fee.subtract_from(amount.msats);
+
+        if contract_amount < MINIMUM_INCOMING_CONTRACT_AMOUNT {
+            return Err(GenerateLnurlError::AmountTooSmall);
+        }
+
+        let contracts = (0..payments)
+            .map(|_| {
+                self.static_lnurl_contract(
+                    contract_amount,
+                    amount.msats - contract_amount.msats,
+                    routing_info.module_public_key,
+                )
+            })
+            .collect::<Vec<_>>();
+
+        let signature = SECP256K1.sign_schnorr(
+            &register_static_lnurl_contracts_message(&self.federation_id, amount, &contracts),
+            &self.lnurl_keypair,
         );
 
+        self.gateway_conn
+            .register_static_lnurl_contracts(
+                gateway.clone(),
+                RegisterStaticLnurlContractsPayload {
+                    federation_id: self.federation_id,
+                    recipient_pk: self.lnurl_keypair.public_key(),
+                    amount,
+                    contracts,
+                    signature,
+                },
+            )
+            .await
+            .map_err(|e| GenerateLnurlError::FailedToConnectToGateway(e.to_string()))?;
+
         Ok(fedimint_lnurl::encode_lnurl(&format!(
-            "{recurringd}pay/{payload}"
+            "{gateway}lnurl/pay/{}",
+            self.lnurl_payload(vec![gateway.clone()])
         )))
     }
 
+    /// Creates an incoming contract for a payment to our static lnurl, locked
+    /// to a key derived from our lnurl key in the same way as the contracts
+    /// recurringd creates for payments to our lnurl. The gateway fee is
+    /// encoded in place of an expiration, such that the contract can be
+    /// claimed whenever we come online.
+    fn static_lnurl_contract(
+        &self,
+        contract_amount: Amount,
+        fee_msats: u64,
+        gateway_module_pk: PublicKey,
+    ) -> IncomingContract {
+        let (ephemeral_tweak, ephemeral_pk) = tweak::generate(self.lnurl_keypair.public_key());
+
+        let encryption_seed = ephemeral_tweak
+            .consensus_hash::<sha256::Hash>()
+            .to_byte_array();
+
+        let preimage = encryption_seed
+            .consensus_hash::<sha256::Hash>()
+            .to_byte_array();
+
+        let claim_pk = self
+            .lnurl_keypair
+            .public_key()
+            .mul_tweak(
+                secp256k1::SECP256K1,
+                &Scalar::from_be_bytes(ephemeral_tweak).expect("Within curve order"),
+            )
+            .expect("Tweak is valid");
+
+        IncomingContract::new(
+            self.cfg.tpe_agg_pk,
+            encryption_seed,
+            preimage,
+            PaymentImage::Hash(preimage.consensus_hash()),
+            contract_amount,
+            fee_encoded_expiration(fee_msats),
+            claim_pk,
+            gateway_module_pk,
+            ephemeral_pk,
+        )
+    }
+
+    fn lnurl_payload(&self, gateways: Vec<SafeUrl>) -> String {
+        fedimint_core::base32::encode_prefixed(
+            fedimint_core::base32::FEDIMINT_PREFIX,
+            &self.lnurl_request(gateways),
+        )
+    }
+
+    fn lnurl_request(&self, gateways: Vec<SafeUrl>) -> lnurl::LnurlRequest {
+        lnurl::LnurlRequest {
+            federation_id: self.federation_id,
+            recipient_pk: self.lnurl_keypair.public_key(),
+            aggregate_pk: self.cfg.tpe_agg_pk,
+            gateways,
+        }
+    }
+
+    /// Register a lightning address `username@domain` with a recurringd
+    /// instance serving the domain. Payments to the address are received in
+    /// the background like payments to our lnurl. Registering the same
+    /// username again updates its gateways. You can optionally specify a
+    /// gateway to use for testing purposes.
+    pub async fn register_lightning_address(
+        &self,
+        recurringd: SafeUrl,
+        username: String,
+        gateway: Option<SafeUrl>,
+    ) -> anyhow::Result<String> {
+        let username = username.to_lowercase();
+
+        anyhow::ensure!(
+            lnurl::is_valid_username(&username),
+            "Username may only contain a-z, 0-9, '-', '_' and '.'"
+        );
+
+        let request = self.lnurl_request(self.lnurl_gateways(gateway).await?);
+
+        let signature = SECP256K1.sign_schnorr(
+            &lnurl::register_address_message(&username, &request),
+            &self.lnurl_keypair,
+        );
+
+        reqwest::Client::new()
+            .post(recurringd.join_path("register").to_unsafe())
+            .json(&lnurl::RegisterAddressRequest {
+                username,
+                request,
+                signature,
+            })
+            .send()
+            .await?
+            .json::<fedimint_lnurl::LnurlResponse<String>>()
+            .await?
+            .into_result()
+            .map_err(|e| anyhow::anyhow!(e))
+    }
+
+    /// Fetch the comments payers attached to settled payments to our lightning
+    /// address since the given unix time.
+    pub async fn lightning_address_comments(
+        &self,
+        recurringd: SafeUrl,
+        username: String,
+        since_secs: u64,
+    ) -> anyhow::Result<Vec<lnurl::AddressComment>> {
+        let username = username.to_lowercase();
+
+        let timestamp_secs = duration_since_epoch().as_secs();
+
+        let signature = SECP256K1.sign_schnorr(
+            &lnurl::address_comments_message(&username, since_secs, timestamp_secs),
+            &self.lnurl_keypair,
+        );
+
+        reqwest::Client::new()
+            .post(recurringd.join_path("address/comments").to_unsafe())
+            .json(&lnurl::AddressCommentsRequest {
+                username,
+                since_secs,
+                timestamp_secs,
+                signature,
+            })
+            .send()
+            .await?
+            .json::<fedimint_lnurl::LnurlResponse<Vec<lnurl::AddressComment>>>()
+            .await?
+            .into_result()
+            .map_err(|e| anyhow::anyhow!(e))
+    }
+
+    /// Redeem an LNURL-withdraw voucher by requesting an invoice and handing it
+    /// to the withdraw service for payment. Withdraws the maximum amount the
+    /// voucher allows unless an amount is specified. You can optionally
+    /// specify a gateway to use for testing purposes.
+    ///
+    /// The returned receive operation completes once the service has paid the
+    /// invoice.
+    pub async fn withdraw_lnurl(
+        &self,
+        lnurl: &str,
+        amount: Option<Amount>,
+        gateway: Option<SafeUrl>,
+        custom_meta: Value,
+    ) -> anyhow::Result<OperationId> {
+        let url =
+            fedimint_lnurl::parse_lnurl(lnurl).ok_or_else(|| anyhow::anyhow!("Invalid lnurl"))?;
+
+        let response = fedimint_lnurl::request_withdraw(&url)
+            .await
+            .map_err(|e| anyhow::anyhow!(e))?;
+
+        let amount = amount.unwrap_or(Amount::from_msats(response.max_withdrawable));
+
+        anyhow::ensure!(
+            (response.min_withdrawable..=response.max_withdrawable).contains(&amount.msats),
+            "Amount must be between {} and {} msats",
+            response.min_withdrawable,
+            response.max_withdrawable
+        );
+
+        let (invoice, operation_id) = self
+            .receive(
+                amount,
+                LNURL_WITHDRAW_INVOICE_EXPIRY_SECS,
+                Bolt11InvoiceDescription::Direct(response.default_description.clone()),
+                gateway,
+                custom_meta,
+            )
+            .await?;
+
+        fedimint_lnurl::submit_withdraw_invoice(&response, &invoice)
+            .await
+            .map_err(|e| anyhow::anyhow!(e))?;
+
+        Ok(operation_id)
+    }
+
+    /// Request a reusable BOLT12 offer from a gateway. You can optionally
+    /// specify a gateway to use for testing purposes.
+    ///
+    /// The gateway funds an incoming contract locked to our lnurl key for every
+    /// payment to the offer, so payments are received in the background like
+    /// payments to our lnurl.
+    pub async fn create_offer(
+        &self,
+        description: Option<String>,
+        gateway: Option<SafeUrl>,
+    ) -> Result<String, ReceiveError> {
+        let (gateway, routing_info) = match gateway {
+            Some(gateway) => (
+                gateway.clone(),
+                self.routing_info(&gateway)
+                    .await
+                    .map_err(|e| ReceiveError::FailedToConnectToGateway(e.to_string()))?
+                    .ok_or(ReceiveError::FederationNotSupported)?,
+            ),
+            None => self
+                .select_gateway(None)
+                .await
+                .map_err(ReceiveError::SelectGateway)?,
+        };
+
+        if !routing_info
+            .receive_fee
+            .is_within(&PaymentFee::RECEIVE_FEE_LIMIT)
+        {
+            return Err(ReceiveError::GatewayFeeExceedsLimit);
+        }
+
+        self.gateway_conn
+            .bolt12_offer(
+                gateway,
+                self.federation_id,
+                self.lnurl_keypair.public_key(),
+                description,
+            )
+            .await
+            .map_err(|e| ReceiveError::FailedToConnectToGateway(e.to_string()))
+    }
+
+    /// Schedule a recurring payment to a lightning address or LNURL. The
+    /// payments are sent in the background as long as the client is running;
+    /// runs that were missed while the client was offline are not caught up,
+    /// only the latest due run is paid once the client is back online.
+    ///
+    /// Every run is paid via a send operation of its own, whose meta links it
+    /// to the returned operation id of the recurring payment.
+    pub async fn schedule_recurring_payment(
+        &self,
+        schedule: RecurringPayment,
+        custom_meta: Value,
+    ) -> anyhow::Result<OperationId> {
+        if schedule.pay_request_url().is_none() {
+            bail!("Recipient is neither a lightning address nor an LNURL");
+        }
+
+        if schedule.amount == Amount::ZERO {
+            bail!("Amount must not be zero");
+        }
+
+        if schedule.interval_secs < MIN_RECURRING_PAYMENT_INTERVAL_SECS {
+            bail!("Interval must be at least {MIN_RECURRING_PAYMENT_INTERVAL_SECS} seconds");
+        }
+
+        let operation_id = OperationId::new_random();
+
+        let state = RecurringPaymentStateMachine::next_run(&schedule, 0, Amount::ZERO);
+
+        let recurring_sm = LightningClientStateMachines::Recurring(RecurringPaymentStateMachine {
+            common: RecurringPaymentSMCommon {
+                operation_id,
+                schedule: schedule.clone(),
+            },
+            state,
+        });
+
+        self.client_ctx
+            .manual_operation_start(
+                operation_id,
+                LightningCommonInit::KIND.as_str(),
+                LightningOperationMeta::Recurring(RecurringPaymentOperationMeta {
+                    schedule,
+                    custom_meta,
+                }),
+                vec![self.client_ctx.make_dyn_state(recurring_sm)],
+            )
+            .await?;
+
+        Ok(operation_id)
+    }
+
+    /// Cancel a recurring payment before its next run. A run whose payment is
+    /// already in flight is completed.
+    pub async fn cancel_recurring_payment(&self, operation_id: OperationId) -> anyhow::Result<()> {
+        let LightningOperationMeta::Recurring(..) = self
+            .client_ctx
+            .get_operation(operation_id)
+            .await?
+            .meta::<LightningOperationMeta>()
+        else {
+            bail!("Operation is not a recurring payment");
+        };
+
+        let mut dbtx = self.client_ctx.module_db().begin_transaction().await;
+
+        dbtx.insert_entry(&RecurringPaymentCancelledKey(operation_id), &())
+            .await;
+
+        dbtx.commit_tx_result().await?;
+
+        Ok(())
+    }
+
+    /// Returns the send operations started by a recurring payment together
+    /// with the run they paid, in the order of the runs.
+    pub async fn recurring_payment_runs(
+        &self,
+        operation_id: OperationId,
+    ) -> Vec<(u64, OperationId)> {
+        let mut runs = self
+            .client_ctx
+            .module_db()
+            .begin_transaction_nc()
+            .await
+            .find_by_prefix(&RecurringPaymentRunPrefix(operation_id))
+            .await
+            .map(|(key, run)| (run, key.1))
+            .collect::<Vec<_>>()
+            .await;
+
+        runs.sort();
+
+        runs
+    }
+
     fn spawn_receive_lnurl_task(
         &self,
         custom_meta_fn: Arc<dyn Fn() -> Value + Send + Sync>,
@@ -1323,6 +2664,7 @@ async fn receive_lnurl(&self, custom_meta: Value) {
                         contract: contract.clone(),
                         custom_meta: custom_meta.clone(),
                     }),
+                    None,
                 )
                 .await
             {
@@ -1349,6 +2691,145 @@ async fn receive_lnurl(&self, custom_meta: Value) {
     }
 }
 
+/// Orders the gateways by their success rate, placing gateways that have failed
+/// repeatedly and recently last. Gateways with equal scores keep their relative
+/// order.
+fn rank_gateways(
+    mut gateways: Vec<SafeUrl>,
+    scores: &BTreeMap<SafeUrl, GatewayScore>,
+    now_secs: u64,
+) -> Vec<SafeUrl> {
+    gateways.sort_by_cached_key(|gateway| {
+        let score = scores.get(gateway).cloned().unwrap_or_default();
+
+        (
+            score.is_demoted(now_secs),
+            Reverse(score.success_rate_ppm()),
+        )
+    });
+
+    gateways
+}
+
+/// Aggregates the states of the parts of a send operation, keyed by the
+/// outpoint of their contract, into the state of the payment as a whole. The
+/// payment succeeds as soon as any part returns the preimage, since the payee
+/// only releases it once all parts have arrived, and is only final otherwise
+/// once every part is.
+fn aggregate_send_state(
+    parts: &BTreeMap<OutPoint, SendOperationState>,
+    num_parts: usize,
+) -> SendOperationState {
+    if let Some(preimage) = parts.values().find_map(|state| match state {
+        SendOperationState::Success(preimage) => Some(*preimage),
+        _ => None,
+    }) {
+        return SendOperationState::Success(preimage);
+    }
+
+    let num_final = parts
+        .values()
+        .filter(|state| {
+            matches!(
+                state,
+                SendOperationState::Refunded | SendOperationState::Failure
+            )
+        })
+        .count();
+
+    if num_final == num_parts {
+        if parts
+            .values()
+            .any(|state| *state == SendOperationState::Failure)
+        {
+            return SendOperationState::Failure;
+        }
+
+        return SendOperationState::Refunded;
+    }
+
+    if num_final > 0
+        || parts
+            .values()
+            .any(|state| *state == SendOperationState::Refunding)
+    {
+        return SendOperationState::Refunding;
+    }
+
+    // All contracts are funded by the same transaction
+    if parts
+        .values()
+        .any(|state| *state == SendOperationState::Funded)
+    {
+        return SendOperationState::Funded;
+    }
+
+    SendOperationState::Funding
+}
+
+/// Estimated cost and chance of success of paying an invoice, see
+/// [`LightningClientModule::preview_send`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SendPreview {
+    /// The gateway that would route the payment.
+    pub gateway: SafeUrl,
+    /// The fee the gateway charges on top of the invoice amount.
+    pub gateway_fee: Amount,
+    /// The federation fee of funding the outgoing contract.
+    pub federation_fee: Amount,
+    /// The route the gateway's Lightning node found for the payment, or `None`
+    /// if its node cannot probe payments. Either way the routing fee is
+    /// covered by the gateway fee.
+    pub route: Option<RoutePreview>,
+}
+
+/// Track record of a gateway derived from the outcome of the payments this
+/// client has sent through it, see [`LightningClientModule::gateway_scores`].
+/// Every part of a split payment counts as a payment of its own.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, Encodable, Decodable)]
+pub struct GatewayScore {
+    /// Number of payments the gateway has settled.
+    pub successes: u64,
+    /// Number of payments that were refunded because the gateway neither
+    /// settled nor cancelled them before they expired. Payments the gateway
+    /// cancelled, for example because the payee could not be reached, do not
+    /// count against it.
+    pub refunds: u64,
+    /// Number of refunds since the last settled payment.
+    pub consecutive_refunds: u64,
+    /// Time of the last refund in seconds since the unix epoch.
+    pub last_refund_secs: Option<u64>,
+    /// Time from funding to settlement summed over all settled payments.
+    pub total_latency_ms: u64,
+    /// Fees paid to the gateway summed over all settled payments.
+    pub total_fees: Amount,
+}
+
+impl GatewayScore {
+    /// Share of settled payments in parts per million. The estimate starts out
+    /// at one half for a gateway without any payments and converges to the
+    /// observed rate as payments are recorded.
+    pub fn success_rate_ppm(&self) -> u64 {
+        (self.successes + 1) * 1_000_000 / (self.successes + self.refunds + 2)
+    }
+
+    /// Average time from funding to settlement over all settled payments.
+    pub fn average_latency(&self) -> Option<Duration> {
+        self.total_latency_ms
+            .checked_div(self.successes)
+            .map(Duration::from_millis)
+    }
+
+    /// Whether the gateway has failed repeatedly and recently enough that it
+    /// should only be selected if no other gateway is online.
+    pub fn is_demoted(&self, now_secs: u64) -> bool {
+        self.consecutive_refunds >= GATEWAY_MAX_CONSECUTIVE_REFUNDS
+            && self
+                .last_refund_secs
+                .is_some_and(|last| now_secs < last + GATEWAY_DEMOTION_SECS)
+    }
+}
+
 #[derive(Error, Debug, Clone, Eq, PartialEq)]
 pub enum SelectGatewayError {
     #[error("Failed to request gateways")]
@@ -1401,6 +2882,18 @@ pub enum SendPaymentError {
         invoice_currency: Currency,
         federation_currency: Currency,
     },
+    #[error("Gateway returned an invalid invoice for the offer: {0}")]
+    InvalidOfferInvoice(Bolt12InvoiceError),
+    #[error("Invoice does not support multi-part payments")]
+    InvoiceDoesNotSupportMpp,
+    #[error("A payment can only be split over at least two distinct gateways")]
+    InvalidSplit,
+    #[error("Gateway does not support keysend payments")]
+    KeysendNotSupported,
+    #[error("Gateway does not support multi-part payments")]
+    MultiPartNotSupported,
+    #[error("Custom records must be in the custom range and fit into the onion")]
+    InvalidCustomRecords,
 }
 
 #[derive(Error, Debug, Clone, Eq, PartialEq)]
@@ -1421,6 +2914,8 @@ pub enum ReceiveError {
     IncorrectInvoiceAmount,
     #[error("Requested invoice expiry exceeds the maximum of one day")]
     InvoiceExpiryTooLong,
+    #[error("Requested hold time exceeds the maximum of ten minutes")]
+    HoldTooLong,
 }
 
 #[derive(Error, Debug, Clone, Eq, PartialEq)]
@@ -1429,6 +2924,16 @@ pub enum GenerateLnurlError {
     NoGatewaysAvailable,
     #[error("Failed to request gateways")]
     FailedToRequestGateways(String),
+    #[error("Failed to connect to gateway")]
+    FailedToConnectToGateway(String),
+    #[error("Gateway does not support this federation")]
+    FederationNotSupported,
+    #[error("Gateway fee exceeds the allowed limit")]
+    GatewayFeeExceedsLimit,
+    #[error("Amount is too small to cover fees")]
+    AmountTooSmall,
+    #[error("At most {MAX_STATIC_LNURL_CONTRACTS} payments can be registered")]
+    TooManyPayments,
 }
 
 #[derive(Error, Debug, Clone, Eq, PartialEq)]
@@ -1447,6 +2952,7 @@ pub enum RoutingInfoError {
 pub enum LightningClientStateMachines {
     Send(SendStateMachine),
     Receive(ReceiveStateMachine),
+    Recurring(RecurringPaymentStateMachine),
 }
 
 impl IntoDynInstance for LightningClientStateMachines {
@@ -1478,6 +2984,12 @@ fn transitions(
                     LightningClientStateMachines::Receive
                 )
             }
+            LightningClientStateMachines::Recurring(state) => {
+                sm_enum_variant_translation!(
+                    state.transitions(context, global_context),
+                    LightningClientStateMachines::Recurring
+                )
+            }
         }
     }
 
@@ -1485,6 +2997,7 @@ fn operation_id(&self) -> OperationId {
         match self {
             LightningClientStateMachines::Send(state) => state.operation_id(),
             LightningClientStateMachines::Receive(state) => state.operation_id(),
+            LightningClientStateMachines::Recurring(state) => state.operation_id(),
         }
     }
 }
@@ -1,3 +1,5 @@
+use std::time::Duration;
+
 use fedimint_client_module::DynGlobalClientContext;
 use fedimint_client_module::sm::{ClientSMDatabaseTransaction, State, StateTransition};
 use fedimint_client_module::transaction::{ClientInput, ClientInputBundle};
@@ -5,12 +7,16 @@
 use fedimint_core::encoding::{Decodable, Encodable};
 use fedimint_core::module::Amounts;
 use fedimint_core::secp256k1::Keypair;
+use fedimint_core::task::sleep;
+use fedimint_core::time::duration_since_epoch;
+use fedimint_core::util::SafeUrl;
 use fedimint_core::{Amount, OutPoint};
 use fedimint_lnv2_common::contracts::{IncomingContract, fee_from_expiration};
+use fedimint_lnv2_common::gateway_api::HoldInvoiceStatus;
 use fedimint_lnv2_common::{LightningInput, LightningInputV0};
 use fedimint_logging::LOG_CLIENT_MODULE_LNV2;
 use tpe::AggregateDecryptionKey;
-use tracing::instrument;
+use tracing::{instrument, warn};
 
 use crate::api::LightningFederationApi;
 use crate::events::ReceivePaymentEvent;
@@ -44,6 +50,14 @@ pub enum ReceiveSMState {
     Pending,
     Claiming(Vec<OutPoint>),
     Expired,
+    /// We are waiting for the payment of a hold invoice issued by the gateway.
+    AwaitingHold(SafeUrl),
+    /// The gateway holds the payment of a hold invoice until we settle or
+    /// cancel it, or the deadline passes.
+    Held(SafeUrl, u64),
+    /// The hold invoice has been cancelled by us or has not been settled before
+    /// the deadline.
+    Rejected,
 }
 
 #[cfg_attr(doc, aquamarine::aquamarine)]
@@ -55,6 +69,11 @@ pub enum ReceiveSMState {
 ///
 ///     Pending -- incoming contract is confirmed --> Claiming
 ///     Pending -- decryption contract expires --> Expired
+///     AwaitingHold -- gateway holds the payment --> Held
+///     AwaitingHold -- hold invoice is cancelled --> Rejected
+///     AwaitingHold -- decryption contract expires --> Expired
+///     Held -- hold invoice is settled --> Pending
+///     Held -- hold invoice is cancelled or deadline passes --> Rejected
 /// ```
 impl State for ReceiveStateMachine {
     type ModuleContext = LightningClientContext;
@@ -82,7 +101,24 @@ fn transitions(
                     },
                 )]
             }
-            ReceiveSMState::Claiming(..) | ReceiveSMState::Expired => {
+            ReceiveSMState::AwaitingHold(gateway) | ReceiveSMState::Held(gateway, ..) => {
+                let held = matches!(self.state, ReceiveSMState::Held(..));
+
+                vec![StateTransition::new(
+                    Self::await_hold_invoice_status(
+                        ctx.clone(),
+                        gateway.clone(),
+                        self.common.contract.clone(),
+                        held,
+                    ),
+                    move |_, status, old_state| {
+                        Box::pin(
+                            async move { Self::transition_hold_invoice_status(old_state, status) },
+                        )
+                    },
+                )]
+            }
+            ReceiveSMState::Claiming(..) | ReceiveSMState::Expired | ReceiveSMState::Rejected => {
                 vec![]
             }
         }
@@ -108,6 +144,67 @@ async fn await_incoming_contract(
             .await
     }
 
+    /// Polls the gateway until the status of the hold invoice changes. Returns
+    /// `None` once the contract has expired, as the gateway can no longer fund
+    /// it even if it is unreachable until then.
+    async fn await_hold_invoice_status(
+        context: LightningClientContext,
+        gateway: SafeUrl,
+        contract: IncomingContract,
+        held: bool,
+    ) -> Option<HoldInvoiceStatus> {
+        loop {
+            if contract.commitment.expiration_or_fee <= duration_since_epoch().as_secs() {
+                return None;
+            }
+
+            match context
+                .gateway_conn
+                .hold_invoice_status(
+                    gateway.clone(),
+                    context.federation_id,
+                    contract.contract_id(),
+                )
+                .await
+            {
+                Ok(HoldInvoiceStatus::Pending) => {}
+                Ok(HoldInvoiceStatus::Held { .. }) if held => {}
+                Ok(status) => return Some(status),
+                Err(err) => {
+                    warn!(target: LOG_CLIENT_MODULE_LNV2, %err, "Failed to request hold invoice status");
+                }
+            }
+
+            sleep(Duration::from_secs(5)).await;
+        }
+    }
+
+    fn transition_hold_invoice_status(
+        old_state: ReceiveStateMachine,
+        status: Option<HoldInvoiceStatus>,
+    ) -> ReceiveStateMachine {
+        let gateway = match &old_state.state {
+            ReceiveSMState::AwaitingHold(gateway) | ReceiveSMState::Held(gateway, ..) => {
+                gateway.clone()
+            }
+            _ => unreachable!("Only hold invoices await their status"),
+        };
+
+        match status {
+            None => old_state.update(ReceiveSMState::Expired),
+            Some(HoldInvoiceStatus::Pending) => {
+                old_state.update(ReceiveSMState::AwaitingHold(gateway))
+            }
+            Some(HoldInvoiceStatus::Held { deadline_secs }) => {
+                old_state.update(ReceiveSMState::Held(gateway, deadline_secs))
+            }
+            // The gateway funds the contract now, which we await like for any
+            // other invoice.
+            Some(HoldInvoiceStatus::Settled) => old_state.update(ReceiveSMState::Pending),
+            Some(HoldInvoiceStatus::Cancelled) => old_state.update(ReceiveSMState::Rejected),
+        }
+    }
+
     async fn transition_incoming_contract(
         dbtx: &mut ClientSMDatabaseTransaction<'_, '_>,
         old_state: ReceiveStateMachine,
@@ -0,0 +1,459 @@
+use std::time::Duration;
+
+use fedimint_client_module::DynGlobalClientContext;
+use fedimint_client_module::sm::{ClientSMDatabaseTransaction, State, StateTransition};
+use fedimint_core::Amount;
+use fedimint_core::core::OperationId;
+use fedimint_core::db::IDatabaseTransactionOpsCoreTyped;
+use fedimint_core::encoding::{Decodable, Encodable};
+use fedimint_core::task::sleep;
+use fedimint_core::time::duration_since_epoch;
+use fedimint_core::util::SafeUrl;
+use fedimint_logging::LOG_CLIENT_MODULE_LNV2;
+use lightning_invoice::Bolt11Invoice;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing::warn;
+
+use crate::db::{RecurringPaymentCancelledKey, RecurringPaymentRunKey};
+use crate::{
+    FinalSendOperationState, LightningClientContext, RecurringPayment, RecurringPaymentRun,
+    SendPaymentError,
+};
+
+/// Number of attempts to pay a run before it is skipped
+const MAX_RUN_ATTEMPTS: u32 = 5;
+
+/// Delay before the first retry of a failed run, doubled with every attempt
+const RETRY_BASE_DELAY_SECS: u64 = 60;
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Decodable, Encodable)]
+pub struct RecurringPaymentStateMachine {
+    pub common: RecurringPaymentSMCommon,
+    pub state: RecurringPaymentSMState,
+}
+
+impl RecurringPaymentStateMachine {
+    pub fn update(&self, state: RecurringPaymentSMState) -> Self {
+        Self {
+            common: self.common.clone(),
+            state,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Decodable, Encodable)]
+pub struct RecurringPaymentSMCommon {
+    pub operation_id: OperationId,
+    pub schedule: RecurringPayment,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Decodable, Encodable)]
+pub enum RecurringPaymentSMState {
+    Scheduled {
+        run: u64,
+        attempt: u32,
+        not_before_secs: u64,
+        spent: Amount,
+    },
+    Paying {
+        run: u64,
+        attempt: u32,
+        invoice: Bolt11Invoice,
+        spent: Amount,
+    },
+    Completed,
+    Cancelled,
+}
+
+#[cfg_attr(doc, aquamarine::aquamarine)]
+/// State machine that pays a recurring payment to a lightning address or
+/// LNURL. Every run fetches a fresh invoice from the recipient and pays it via
+/// a send operation of its own, which is linked to the recurring payment.
+///
+/// ```mermaid
+/// graph LR
+/// classDef virtual fill:#fff,stroke-dasharray: 5 5
+///
+///     Scheduled -- invoice is fetched --> Paying
+///     Scheduled -- fetching the invoice fails --> Scheduled
+///     Scheduled -- schedule is cancelled --> Cancelled
+///     Paying -- payment succeeds or fails --> Scheduled
+///     Paying -- last run is paid or spending limit is reached --> Completed
+/// ```
+impl State for RecurringPaymentStateMachine {
+    type ModuleContext = LightningClientContext;
+
+    fn transitions(
+        &self,
+        context: &Self::ModuleContext,
+        _global_context: &DynGlobalClientContext,
+    ) -> Vec<StateTransition<Self>> {
+        match &self.state {
+            RecurringPaymentSMState::Scheduled {
+                not_before_secs, ..
+            } => {
+                vec![
+                    StateTransition::new(
+                        Self::fetch_invoice(self.common.schedule.clone(), *not_before_secs),
+                        |_, result, old_state| {
+                            Box::pin(
+                                async move { Self::transition_fetch_invoice(result, &old_state) },
+                            )
+                        },
+                    ),
+                    StateTransition::new(
+                        Self::await_cancellation(context.clone(), self.common.operation_id),
+                        |_, (), old_state| {
+                            Box::pin(
+                                async move { old_state.update(RecurringPaymentSMState::Cancelled) },
+                            )
+                        },
+                    ),
+                ]
+            }
+            RecurringPaymentSMState::Paying {
+                run,
+                invoice,
+                spent,
+                ..
+            } => {
+                vec![StateTransition::new(
+                    Self::pay_invoice(
+                        context.clone(),
+                        RecurringPaymentRun {
+                            operation_id: self.common.operation_id,
+                            run: *run,
+                        },
+                        invoice.clone(),
+                        self.common
+                            .schedule
+                            .spending_limit
+                            .map(|limit| limit.saturating_sub(*spent)),
+                    ),
+                    |dbtx, result, old_state| {
+                        Box::pin(Self::transition_pay_invoice(dbtx, result, old_state))
+                    },
+                )]
+            }
+            RecurringPaymentSMState::Completed | RecurringPaymentSMState::Cancelled => {
+                vec![]
+            }
+        }
+    }
+
+    fn operation_id(&self) -> OperationId {
+        self.common.operation_id
+    }
+}
+
+impl RecurringPaymentStateMachine {
+    /// Returns the state scheduling the first run after `run` that is within
+    /// the limits of the schedule. A client that was offline for several
+    /// intervals only pays the latest of the missed runs rather than all of
+    /// them at once. The fees of a run are only known once its invoice has
+    /// been fetched, so they are checked against the spending limit right
+    /// before the invoice is paid.
+    pub fn next_run(
+        schedule: &RecurringPayment,
+        run: u64,
+        spent: Amount,
+    ) -> RecurringPaymentSMState {
+        let now_secs = duration_since_epoch().as_secs();
+
+        let latest_due_run = now_secs
+            .saturating_sub(schedule.start_secs)
+            .checked_div(schedule.interval_secs)
+            .unwrap_or(0);
+
+        let run = if now_secs < schedule.start_secs {
+            run
+        } else {
+            run.max(latest_due_run)
+        };
+
+        if schedule.max_runs.is_some_and(|max_runs| max_runs <= run) {
+            return RecurringPaymentSMState::Completed;
+        }
+
+        if schedule
+            .spending_limit
+            .is_some_and(|limit| limit < spent + schedule.amount)
+        {
+            return RecurringPaymentSMState::Completed;
+        }
+
+        RecurringPaymentSMState::Scheduled {
+            run,
+            attempt: 0,
+            not_before_secs: schedule.due_secs(run),
+            spent,
+        }
+    }
+
+    fn retry_run(
+        schedule: &RecurringPayment,
+        run: u64,
+        attempt: u32,
+        spent: Amount,
+    ) -> RecurringPaymentSMState {
+        if attempt + 1 >= MAX_RUN_ATTEMPTS {
+            warn!(
+                target: LOG_CLIENT_MODULE_LNV2,
+                run,
+                "Skipping recurring payment run after repeated failures"
+            );
+
+            return Self::next_run(schedule, run + 1, spent);
+        }
+
+        let delay_secs = RETRY_BASE_DELAY_SECS << attempt;
+
+        RecurringPaymentSMState::Scheduled {
+            run,
+            attempt: attempt + 1,
+            not_before_secs: duration_since_epoch().as_secs() + delay_secs,
+            spent,
+        }
+    }
+
+    async fn fetch_invoice(
+        schedule: RecurringPayment,
+        not_before_secs: u64,
+    ) -> Result<Bolt11Invoice, String> {
+        let now_secs = duration_since_epoch().as_secs();
+
+        sleep(Duration::from_secs(
+            not_before_secs.saturating_sub(now_secs),
+        ))
+        .await;
+
+        let url = schedule
+            .pay_request_url()
+            .ok_or("Recipient is neither a lightning address nor an LNURL".to_string())?;
+
+        let response = fedimint_lnurl::request(&url).await?;
+
+        let invoice = fedimint_lnurl::get_invoice(&response, schedule.amount.msats).await?;
+
+        Ok(invoice.pr)
+    }
+
+    fn transition_fetch_invoice(
+        result: Result<Bolt11Invoice, String>,
+        old_state: &RecurringPaymentStateMachine,
+    ) -> RecurringPaymentStateMachine {
+        let RecurringPaymentSMState::Scheduled {
+            run,
+            attempt,
+            spent,
+            ..
+        } = old_state.state
+        else {
+            panic!("Invalid prior state")
+        };
+
+        match result {
+            Ok(invoice) => old_state.update(RecurringPaymentSMState::Paying {
+                run,
+                attempt,
+                invoice,
+                spent,
+            }),
+            Err(error) => {
+                warn!(
+                    target: LOG_CLIENT_MODULE_LNV2,
+                    run,
+                    %error,
+                    "Failed to fetch invoice for recurring payment"
+                );
+
+                old_state.update(Self::retry_run(
+                    &old_state.common.schedule,
+                    run,
+                    attempt,
+                    spent,
+                ))
+            }
+        }
+    }
+
+    async fn await_cancellation(context: LightningClientContext, operation_id: OperationId) {
+        context
+            .client_ctx
+            .module_db()
+            .wait_key_exists(&RecurringPaymentCancelledKey(operation_id))
+            .await;
+    }
+
+    /// Pays the invoice of the run and waits for the payment to reach its final
+    /// state, returning the amount spent including the gateway and federation
+    /// fees if it was successful. The payment is only started if its total
+    /// cost is within the remaining spending limit. Since the operation id of
+    /// a send is derived from the invoice, a restarted client resumes the
+    /// payment it started before rather than paying the invoice again.
+    async fn pay_invoice(
+        context: LightningClientContext,
+        run: RecurringPaymentRun,
+        invoice: Bolt11Invoice,
+        remaining_limit: Option<Amount>,
+    ) -> RunOutcome {
+        let module = context.client_ctx.self_ref();
+
+        // Derived like in `send_invoice`, such that a payment started before a
+        // restart is resumed without checking the limit again.
+        let started = context
+            .client_ctx
+            .operation_exists(OperationId::from_encodable(&(invoice.clone(), 0u64)))
+            .await;
+
+        let gateway = match remaining_limit {
+            Some(remaining_limit) if !started => {
+                let (gateway, cost) = match Self::select_gateway(&context, &invoice).await {
+                    Ok(selection) => selection,
+                    Err(error) => {
+                        warn!(
+                            target: LOG_CLIENT_MODULE_LNV2,
+                            run = run.run,
+                            %error,
+                            "Failed to select gateway for recurring payment"
+                        );
+
+                        return RunOutcome::Failed(None);
+                    }
+                };
+
+                if remaining_limit < cost {
+                    return RunOutcome::ExceedsSpendingLimit;
+                }
+
+                Some(gateway)
+            }
+            _ => None,
+        };
+
+        let operation_id = match module
+            .send_invoice(invoice, gateway, Value::Null, Some(run.clone()))
+            .await
+        {
+            Ok(operation_id) | Err(SendPaymentError::DuplicatePaymentAttempt(operation_id)) => {
+                operation_id
+            }
+            Err(error) => {
+                warn!(
+                    target: LOG_CLIENT_MODULE_LNV2,
+                    run = run.run,
+                    %error,
+                    "Failed to send recurring payment"
+                );
+
+                return RunOutcome::Failed(None);
+            }
+        };
+
+        if !matches!(
+            module.await_final_send_operation_state(operation_id).await,
+            Ok(FinalSendOperationState::Success(..))
+        ) {
+            return RunOutcome::Failed(Some(operation_id));
+        }
+
+        let Some(meta) = module.send_operation_meta(operation_id).await else {
+            return RunOutcome::Failed(Some(operation_id));
+        };
+
+        let federation_fee = module
+            .send_fee_quote(meta.contract.amount)
+            .await
+            .map(|quote| quote.total().get_bitcoin())
+            .unwrap_or(Amount::ZERO);
+
+        RunOutcome::Paid(operation_id, meta.contract.amount + federation_fee)
+    }
+
+    /// Selects the gateway for the invoice and returns it together with the
+    /// total cost of paying the invoice through it.
+    async fn select_gateway(
+        context: &LightningClientContext,
+        invoice: &Bolt11Invoice,
+    ) -> anyhow::Result<(SafeUrl, Amount)> {
+        let module = context.client_ctx.self_ref();
+
+        let (gateway, routing_info) = module.select_gateway(Some(invoice.clone())).await?;
+
+        let amount = invoice
+            .amount_milli_satoshis()
+            .ok_or(SendPaymentError::InvoiceMissingAmount)?;
+
+        let contract_amount = routing_info.send_parameters(invoice).0.add_to(amount);
+
+        let federation_fee = module
+            .send_fee_quote(contract_amount)
+            .await?
+            .total()
+            .get_bitcoin();
+
+        Ok((gateway, contract_amount + federation_fee))
+    }
+
+    async fn transition_pay_invoice(
+        dbtx: &mut ClientSMDatabaseTransaction<'_, '_>,
+        result: RunOutcome,
+        old_state: RecurringPaymentStateMachine,
+    ) -> RecurringPaymentStateMachine {
+        let RecurringPaymentSMState::Paying {
+            run,
+            attempt,
+            spent,
+            ..
+        } = old_state.state
+        else {
+            panic!("Invalid prior state")
+        };
+
+        if let RunOutcome::Paid(payment_operation_id, _)
+        | RunOutcome::Failed(Some(payment_operation_id)) = result
+        {
+            dbtx.module_tx()
+                .insert_entry(
+                    &RecurringPaymentRunKey(old_state.common.operation_id, payment_operation_id),
+                    &run,
+                )
+                .await;
+        }
+
+        match result {
+            RunOutcome::Paid(_, amount) => old_state.update(Self::next_run(
+                &old_state.common.schedule,
+                run + 1,
+                spent + amount,
+            )),
+            RunOutcome::Failed(_) => old_state.update(Self::retry_run(
+                &old_state.common.schedule,
+                run,
+                attempt,
+                spent,
+            )),
+            RunOutcome::ExceedsSpendingLimit => {
+                warn!(
+                    target: LOG_CLIENT_MODULE_LNV2,
+                    run,
+                    "Completing recurring payment as the next run would exceed the spending limit"
+                );
+
+                old_state.update(RecurringPaymentSMState::Completed)
+            }
+        }
+    }
+}
+
+/// Outcome of paying the invoice of a run of a recurring payment.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+enum RunOutcome {
+    /// The payment succeeded, spending the amount including all fees.
+    Paid(OperationId, Amount),
+    /// The payment failed or could not be started.
+    Failed(Option<OperationId>),
+    /// Paying the invoice including fees would exceed the spending limit.
+    ExceedsSpendingLimit,
+}
@@ -19,7 +19,7 @@
 use tracing::instrument;
 
 use crate::api::LightningFederationApi;
-use crate::events::{SendPaymentStatus, SendPaymentUpdateEvent};
+use crate::events::{SendPaymentStatus, SendPaymentUpdateEvent, SendRefundReason};
 use crate::{LightningClientContext, LightningInvoice};
 
 #[derive(Debug, Clone, Eq, PartialEq, Hash, Decodable, Encodable)]
@@ -59,16 +59,19 @@ pub enum SendSMState {
 async fn send_update_event(
     context: LightningClientContext,
     dbtx: &mut ClientSMDatabaseTransaction<'_, '_>,
-    operation_id: OperationId,
+    common: &SendSMCommon,
     status: SendPaymentStatus,
+    refund_reason: Option<SendRefundReason>,
 ) {
     context
         .client_ctx
         .log_event(
             &mut dbtx.module_tx(),
             SendPaymentUpdateEvent {
-                operation_id,
+                operation_id: common.operation_id,
                 status,
+                gateway: common.gateway_api.clone(),
+                refund_reason,
             },
         )
         .await;
@@ -228,8 +231,9 @@ async fn transition_gateway_send_payment(
                 send_update_event(
                     context,
                     dbtx,
-                    old_state.common.operation_id,
+                    &old_state.common,
                     SendPaymentStatus::Success(preimage),
+                    None,
                 )
                 .await;
 
@@ -257,8 +261,9 @@ async fn transition_gateway_send_payment(
                 send_update_event(
                     context,
                     dbtx,
-                    old_state.common.operation_id,
+                    &old_state.common,
                     SendPaymentStatus::Refunded,
+                    Some(SendRefundReason::Cancelled),
                 )
                 .await;
 
@@ -298,8 +303,9 @@ async fn transition_preimage(
             send_update_event(
                 context,
                 dbtx,
-                old_state.common.operation_id,
+                &old_state.common,
                 SendPaymentStatus::Success(preimage),
+                None,
             )
             .await;
 
@@ -323,8 +329,9 @@ async fn transition_preimage(
         send_update_event(
             context,
             dbtx,
-            old_state.common.operation_id,
+            &old_state.common,
             SendPaymentStatus::Refunded,
+            Some(SendRefundReason::Expired),
         )
         .await;
 
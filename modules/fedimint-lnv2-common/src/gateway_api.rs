@@ -1,7 +1,8 @@
 use std::str::FromStr;
 
-use bitcoin::secp256k1::PublicKey;
+use bitcoin::hashes::{Hash, sha256};
 use bitcoin::secp256k1::schnorr::Signature;
+use bitcoin::secp256k1::{Message, PublicKey};
 use fedimint_connectors::error::ServerError;
 use fedimint_core::config::FederationId;
 use fedimint_core::encoding::{Decodable, Encodable};
@@ -15,9 +16,13 @@
 
 use crate::contracts::{IncomingContract, OutgoingContract};
 use crate::endpoint_constants::{
-    CREATE_BOLT11_INVOICE_ENDPOINT, ROUTING_INFO_ENDPOINT, SEND_PAYMENT_ENDPOINT,
+    CREATE_BOLT11_INVOICE_ENDPOINT, CREATE_BOLT12_OFFER_ENDPOINT,
+    CREATE_HOLD_BOLT11_INVOICE_ENDPOINT, FETCH_BOLT12_INVOICE_ENDPOINT,
+    HOLD_INVOICE_STATUS_ENDPOINT, PROBE_PAYMENT_ENDPOINT, REGISTER_STATIC_LNURL_CONTRACTS_ENDPOINT,
+    RESOLVE_HOLD_INVOICE_ENDPOINT, ROUTING_INFO_ENDPOINT, SEND_PAYMENT_ENDPOINT,
+    SUPPORTS_KEYSEND_ENDPOINT, SUPPORTS_MULTI_PART_ENDPOINT,
 };
-use crate::{Bolt11InvoiceDescription, LightningInvoice};
+use crate::{Bolt11InvoiceDescription, Bolt12OfferInvoice, ContractId, LightningInvoice};
 
 #[apply(async_trait_maybe_send!)]
 pub trait GatewayConnection: std::fmt::Debug {
@@ -37,6 +42,37 @@ async fn bolt11_invoice(
         expiry_secs: u32,
     ) -> Result<Bolt11Invoice, ServerError>;
 
+    /// Requests a hold invoice: the gateway holds the incoming HTLC until the
+    /// recipient settles or cancels the payment via `resolve_hold_invoice`
+    /// instead of funding the contract right away.
+    #[allow(clippy::too_many_arguments)]
+    async fn hold_bolt11_invoice(
+        &self,
+        gateway_api: SafeUrl,
+        federation_id: FederationId,
+        contract: IncomingContract,
+        amount: Amount,
+        description: Bolt11InvoiceDescription,
+        expiry_secs: u32,
+        hold_secs: u32,
+    ) -> Result<Bolt11Invoice, ServerError>;
+
+    async fn hold_invoice_status(
+        &self,
+        gateway_api: SafeUrl,
+        federation_id: FederationId,
+        contract_id: ContractId,
+    ) -> Result<HoldInvoiceStatus, ServerError>;
+
+    async fn resolve_hold_invoice(
+        &self,
+        gateway_api: SafeUrl,
+        federation_id: FederationId,
+        contract_id: ContractId,
+        settle: bool,
+        signature: Signature,
+    ) -> Result<(), ServerError>;
+
     async fn send_payment(
         &self,
         gateway_api: SafeUrl,
@@ -46,6 +82,60 @@ async fn send_payment(
         invoice: LightningInvoice,
         auth: Signature,
     ) -> Result<Result<[u8; 32], Signature>, ServerError>;
+
+    async fn bolt12_invoice(
+        &self,
+        gateway_api: SafeUrl,
+        federation_id: FederationId,
+        offer: String,
+        amount: Option<Amount>,
+        payer_note: Option<String>,
+    ) -> Result<Bolt12OfferInvoice, ServerError>;
+
+    async fn bolt12_offer(
+        &self,
+        gateway_api: SafeUrl,
+        federation_id: FederationId,
+        recipient_pk: PublicKey,
+        description: Option<String>,
+    ) -> Result<String, ServerError>;
+
+    /// Registers incoming contracts the gateway hands out, one per payment, to
+    /// payers of the recipient's static LNURL.
+    async fn register_static_lnurl_contracts(
+        &self,
+        gateway_api: SafeUrl,
+        payload: RegisterStaticLnurlContractsPayload,
+    ) -> Result<(), ServerError>;
+
+    /// Asks the gateway to find a route for paying the invoice without paying
+    /// it, such that the payment can be previewed before funds are committed
+    /// to an outgoing contract. Returns `None` if the gateway's Lightning node
+    /// cannot probe payments.
+    async fn probe_payment(
+        &self,
+        gateway_api: SafeUrl,
+        federation_id: FederationId,
+        invoice: Bolt11Invoice,
+    ) -> Result<Option<RoutePreview>, ServerError>;
+
+    /// Asks the gateway whether its Lightning node can send keysend payments
+    /// for the federation, which has to be checked before funding an outgoing
+    /// contract for one.
+    async fn supports_keysend(
+        &self,
+        gateway_api: SafeUrl,
+        federation_id: &FederationId,
+    ) -> Result<bool, ServerError>;
+
+    /// Asks the gateway whether its Lightning node can pay a part of a
+    /// multi-part payment for the federation, which has to be checked before
+    /// funding an outgoing contract for one.
+    async fn supports_multi_part(
+        &self,
+        gateway_api: SafeUrl,
+        federation_id: &FederationId,
+    ) -> Result<bool, ServerError>;
 }
 
 #[derive(Debug, Clone)]
@@ -95,6 +185,77 @@ async fn bolt11_invoice(
             .await
     }
 
+    async fn hold_bolt11_invoice(
+        &self,
+        gateway_api: SafeUrl,
+        federation_id: FederationId,
+        contract: IncomingContract,
+        amount: Amount,
+        description: Bolt11InvoiceDescription,
+        expiry_secs: u32,
+        hold_secs: u32,
+    ) -> Result<Bolt11Invoice, ServerError> {
+        self.api
+            .request(
+                &gateway_api,
+                Method::POST,
+                CREATE_HOLD_BOLT11_INVOICE_ENDPOINT,
+                Some(CreateHoldBolt11InvoicePayload {
+                    invoice: CreateBolt11InvoicePayload {
+                        federation_id,
+                        contract,
+                        amount,
+                        description,
+                        expiry_secs,
+                    },
+                    hold_secs,
+                }),
+            )
+            .await
+    }
+
+    async fn hold_invoice_status(
+        &self,
+        gateway_api: SafeUrl,
+        federation_id: FederationId,
+        contract_id: ContractId,
+    ) -> Result<HoldInvoiceStatus, ServerError> {
+        self.api
+            .request(
+                &gateway_api,
+                Method::POST,
+                HOLD_INVOICE_STATUS_ENDPOINT,
+                Some(HoldInvoiceStatusPayload {
+                    federation_id,
+                    contract_id,
+                }),
+            )
+            .await
+    }
+
+    async fn resolve_hold_invoice(
+        &self,
+        gateway_api: SafeUrl,
+        federation_id: FederationId,
+        contract_id: ContractId,
+        settle: bool,
+        signature: Signature,
+    ) -> Result<(), ServerError> {
+        self.api
+            .request(
+                &gateway_api,
+                Method::POST,
+                RESOLVE_HOLD_INVOICE_ENDPOINT,
+                Some(ResolveHoldInvoicePayload {
+                    federation_id,
+                    contract_id,
+                    settle,
+                    signature,
+                }),
+            )
+            .await
+    }
+
     async fn send_payment(
         &self,
         gateway_api: SafeUrl,
@@ -119,6 +280,114 @@ async fn send_payment(
             )
             .await
     }
+
+    async fn bolt12_invoice(
+        &self,
+        gateway_api: SafeUrl,
+        federation_id: FederationId,
+        offer: String,
+        amount: Option<Amount>,
+        payer_note: Option<String>,
+    ) -> Result<Bolt12OfferInvoice, ServerError> {
+        self.api
+            .request(
+                &gateway_api,
+                Method::POST,
+                FETCH_BOLT12_INVOICE_ENDPOINT,
+                Some(FetchBolt12InvoicePayload {
+                    federation_id,
+                    offer,
+                    amount,
+                    payer_note,
+                }),
+            )
+            .await
+    }
+
+    async fn bolt12_offer(
+        &self,
+        gateway_api: SafeUrl,
+        federation_id: FederationId,
+        recipient_pk: PublicKey,
+        description: Option<String>,
+    ) -> Result<String, ServerError> {
+        self.api
+            .request(
+                &gateway_api,
+                Method::POST,
+                CREATE_BOLT12_OFFER_ENDPOINT,
+                Some(CreateBolt12OfferPayload {
+                    federation_id,
+                    recipient_pk,
+                    description,
+                }),
+            )
+            .await
+    }
+
+    async fn register_static_lnurl_contracts(
+        &self,
+        gateway_api: SafeUrl,
+        payload: RegisterStaticLnurlContractsPayload,
+    ) -> Result<(), ServerError> {
+        self.api
+            .request(
+                &gateway_api,
+                Method::POST,
+                REGISTER_STATIC_LNURL_CONTRACTS_ENDPOINT,
+                Some(payload),
+            )
+            .await
+    }
+
+    async fn supports_keysend(
+        &self,
+        gateway_api: SafeUrl,
+        federation_id: &FederationId,
+    ) -> Result<bool, ServerError> {
+        self.api
+            .request(
+                &gateway_api,
+                Method::POST,
+                SUPPORTS_KEYSEND_ENDPOINT,
+                Some(federation_id),
+            )
+            .await
+    }
+
+    async fn supports_multi_part(
+        &self,
+        gateway_api: SafeUrl,
+        federation_id: &FederationId,
+    ) -> Result<bool, ServerError> {
+        self.api
+            .request(
+                &gateway_api,
+                Method::POST,
+                SUPPORTS_MULTI_PART_ENDPOINT,
+                Some(federation_id),
+            )
+            .await
+    }
+
+    async fn probe_payment(
+        &self,
+        gateway_api: SafeUrl,
+        federation_id: FederationId,
+        invoice: Bolt11Invoice,
+    ) -> Result<Option<RoutePreview>, ServerError> {
+        self.api
+            .request(
+                &gateway_api,
+                Method::POST,
+                PROBE_PAYMENT_ENDPOINT,
+                Some(ProbePaymentPayload {
+                    federation_id,
+                    invoice,
+                }),
+            )
+            .await
+    }
 }
 
 /// The maximum invoice expiry a client may request via
@@ -136,6 +405,140 @@ pub struct CreateBolt11InvoicePayload {
     pub expiry_secs: u32,
 }
 
+/// The maximum time a gateway holds an incoming HTLC for a hold invoice while
+/// waiting for the recipient to settle or cancel the payment. The HTLC has to
+/// be resolved well before its CLTV expiry, which the recipient cannot see.
+pub const MAX_HOLD_INVOICE_SECS: u32 = 60 * 10;
+
+/// Requests a hold invoice for an incoming contract. The contract's expiration
+/// has to leave room for the HTLC to be held for `hold_secs` after the invoice
+/// has been paid, since the gateway funds the contract only once the
+/// recipient settles the payment.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct CreateHoldBolt11InvoicePayload {
+    pub invoice: CreateBolt11InvoicePayload,
+    pub hold_secs: u32,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct HoldInvoiceStatusPayload {
+    pub federation_id: FederationId,
+    pub contract_id: ContractId,
+}
+
+/// Settles or cancels a hold invoice, signed with the claim key of the
+/// incoming contract such that only the recipient can resolve it.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct ResolveHoldInvoicePayload {
+    pub federation_id: FederationId,
+    pub contract_id: ContractId,
+    pub settle: bool,
+    pub signature: Signature,
+}
+
+/// The message signed by the claim key of the incoming contract to settle or
+/// cancel a hold invoice.
+pub fn resolve_hold_invoice_message(contract_id: &ContractId, settle: bool) -> Message {
+    let message = ("resolve-hold-invoice", contract_id, settle).consensus_hash::<sha256::Hash>();
+
+    Message::from_digest(message.to_byte_array())
+}
+
+/// The maximum number of unused contracts a recipient can have registered for
+/// its static LNURL at a gateway.
+pub const MAX_STATIC_LNURL_CONTRACTS: usize = 100;
+
+/// Registers incoming contracts for payments of `amount` to the static LNURL
+/// of `recipient_pk`. The recipient creates the contracts itself, such that
+/// the gateway never learns their preimages and cannot settle a payment
+/// without funding the contract. The payload is signed with the recipient's
+/// key, such that nobody else can register contracts on its behalf.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct RegisterStaticLnurlContractsPayload {
+    pub federation_id: FederationId,
+    pub recipient_pk: PublicKey,
+    /// The amount of the invoices paying the contracts.
+    pub amount: Amount,
+    pub contracts: Vec<IncomingContract>,
+    pub signature: Signature,
+}
+
+/// The message signed by the recipient's static LNURL key to register
+/// contracts for payments to its static LNURL.
+pub fn register_static_lnurl_contracts_message(
+    federation_id: &FederationId,
+    amount: Amount,
+    contracts: &[IncomingContract],
+) -> Message {
+    let message = (
+        "register-static-lnurl-contracts",
+        federation_id,
+        amount,
+        contracts,
+    )
+        .consensus_hash::<sha256::Hash>();
+
+    Message::from_digest(message.to_byte_array())
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Serialize, Deserialize, Encodable, Decodable)]
+pub enum HoldInvoiceStatus {
+    /// The invoice has not been paid yet.
+    Pending,
+    /// The gateway holds the HTLC paying the invoice until the recipient
+    /// settles or cancels the payment, or the deadline passes.
+    Held { deadline_secs: u64 },
+    /// The recipient has settled the payment and the gateway funds the
+    /// incoming contract.
+    Settled,
+    /// The payment has been cancelled by the recipient or has not been settled
+    /// before the deadline, and the HTLC has been failed back to the payer.
+    Cancelled,
+}
+
+/// Requests the gateway to fetch an invoice for a BOLT12 offer, which the
+/// client then pays via `send_payment` like a BOLT11 invoice.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct FetchBolt12InvoicePayload {
+    pub federation_id: FederationId,
+    pub offer: String,
+    /// Required for offers that do not specify an amount.
+    pub amount: Option<Amount>,
+    pub payer_note: Option<String>,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct ProbePaymentPayload {
+    pub federation_id: FederationId,
+    pub invoice: Bolt11Invoice,
+}
+
+/// The gateway's estimate of paying an invoice over Lightning. The routing fee
+/// is paid by the gateway out of its send fee, hence a route that is more
+/// expensive than the send fee will not be attempted.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RoutePreview {
+    /// The routing fee of the cheapest route found.
+    pub routing_fee: Amount,
+    /// The probability between zero and one that a payment along the route
+    /// succeeds, if the gateway's Lightning node estimates it.
+    pub success_probability: Option<f64>,
+    /// The number of blocks the route requires for the expiry of the payment,
+    /// which has to be within the expiration delta of the outgoing contract.
+    pub cltv_delta: u64,
+}
+
+/// Requests the gateway to create a reusable BOLT12 offer on behalf of the
+/// recipient. The gateway funds an incoming contract locked to a key derived
+/// from `recipient_pk` for every payment to the offer, which the recipient
+/// discovers via the federation's incoming contract stream.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct CreateBolt12OfferPayload {
+    pub federation_id: FederationId,
+    pub recipient_pk: PublicKey,
+    pub description: Option<String>,
+}
+
 #[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
 pub struct SendPaymentPayload {
     pub federation_id: FederationId,
@@ -178,6 +581,14 @@ pub struct RoutingInfo {
     pub expiration_delta_default: u64,
     /// This is the fee the gateway charges for an incoming payment.
     pub receive_fee: PaymentFee,
+    /// Lower default send fees the gateway recommends for larger payments over
+    /// lightning, sorted by their minimum amount. None of them exceeds
+    /// `send_fee_default`.
+    ///
+    /// This field is optional for backwards-compatibility with older gateways
+    /// and clients, which use `send_fee_default` for payments of any amount.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub send_fee_tiers: Vec<SendFeeTier>,
 }
 
 impl RoutingInfo {
@@ -185,9 +596,33 @@ pub fn send_parameters(&self, invoice: &Bolt11Invoice) -> (PaymentFee, u64) {
         if invoice.recover_payee_pub_key() == self.lightning_public_key {
             (self.send_fee_minimum, self.expiration_delta_minimum)
         } else {
-            (self.send_fee_default, self.expiration_delta_default)
+            let send_fee = invoice
+                .amount_milli_satoshis()
+                .map_or(self.send_fee_default, |msats| {
+                    self.send_fee_default_for(Amount::from_msats(msats))
+                });
+
+            (send_fee, self.expiration_delta_default)
         }
     }
+
+    /// Returns the default send fee the gateway recommends for a payment of
+    /// `amount` over lightning.
+    pub fn send_fee_default_for(&self, amount: Amount) -> PaymentFee {
+        self.send_fee_tiers
+            .iter()
+            .rev()
+            .find(|tier| tier.min_amount <= amount)
+            .map_or(self.send_fee_default, |tier| tier.send_fee_default)
+    }
+}
+
+/// A default send fee that replaces [`RoutingInfo::send_fee_default`] for
+/// payments of at least `min_amount`.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Serialize, Deserialize, Encodable, Decodable)]
+pub struct SendFeeTier {
+    pub min_amount: Amount,
+    pub send_fee_default: PaymentFee,
 }
 
 #[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize, Encodable, Decodable, Copy)]
@@ -327,10 +762,59 @@ fn from_str(s: &str) -> Result<Self, Self::Err> {
 
 #[cfg(test)]
 mod tests {
+    use std::str::FromStr as _;
+
+    use bitcoin::secp256k1::PublicKey;
     use fedimint_core::Amount;
     use lightning_invoice::RoutingFees;
 
-    use super::PaymentFee;
+    use super::{PaymentFee, RoutingInfo, SendFeeTier};
+
+    #[test]
+    fn send_fee_tiers_apply_from_their_minimum_amount() {
+        let public_key = PublicKey::from_str(
+            "0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798",
+        )
+        .expect("valid public key");
+        let fee = |parts_per_million| PaymentFee {
+            base: Amount::from_sats(1),
+            parts_per_million,
+        };
+
+        let routing_info = RoutingInfo {
+            lightning_public_key: public_key,
+            lightning_alias: None,
+            module_public_key: public_key,
+            send_fee_minimum: PaymentFee::TRANSACTION_FEE_DEFAULT,
+            send_fee_default: fee(10_000),
+            expiration_delta_minimum: 144,
+            expiration_delta_default: 500,
+            receive_fee: PaymentFee::TRANSACTION_FEE_DEFAULT,
+            send_fee_tiers: vec![
+                SendFeeTier {
+                    min_amount: Amount::from_sats(100_000),
+                    send_fee_default: fee(8_000),
+                },
+                SendFeeTier {
+                    min_amount: Amount::from_sats(1_000_000),
+                    send_fee_default: fee(5_000),
+                },
+            ],
+        };
+
+        assert_eq!(
+            routing_info.send_fee_default_for(Amount::from_sats(99_999)),
+            fee(10_000)
+        );
+        assert_eq!(
+            routing_info.send_fee_default_for(Amount::from_sats(100_000)),
+            fee(8_000)
+        );
+        assert_eq!(
+            routing_info.send_fee_default_for(Amount::from_sats(5_000_000)),
+            fee(5_000)
+        );
+    }
 
     /// A lower `base` must not let an over-limit `parts_per_million` through,
     /// which is what the lexicographic ordering used to allow.
@@ -16,7 +16,12 @@
 pub mod lnurl;
 pub mod tweak;
 
-use bitcoin::hashes::sha256;
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+use bitcoin::constants::ChainHash;
+use bitcoin::hashes::{Hash, sha256};
+use bitcoin::secp256k1::PublicKey;
 use bitcoin::secp256k1::schnorr::Signature;
 use config::LightningClientConfig;
 use fedimint_core::core::{Decoder, ModuleInstanceId, ModuleKind};
@@ -26,6 +31,8 @@
     Amount, OutPoint, extensible_associated_module_type, plugin_types_trait_impl_common,
 };
 pub use fedimint_ln_common::client::GatewayApi;
+use lightning::offers::invoice::Bolt12Invoice;
+use lightning::offers::offer::{Amount as OfferAmount, Offer};
 use lightning_invoice::Bolt11Invoice;
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
@@ -42,6 +49,192 @@ pub enum Bolt11InvoiceDescription {
 #[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize, Decodable, Encodable)]
 pub enum LightningInvoice {
     Bolt11(Bolt11Invoice),
+    Bolt12(Bolt12OfferInvoice),
+    Bolt11Part(Bolt11InvoicePart),
+    Keysend(KeysendPayment),
+}
+
+impl LightningInvoice {
+    pub fn payment_hash(&self) -> sha256::Hash {
+        match self {
+            LightningInvoice::Bolt11(invoice) => *invoice.payment_hash(),
+            LightningInvoice::Bolt12(invoice) => invoice.payment_hash,
+            LightningInvoice::Bolt11Part(part) => *part.invoice.payment_hash(),
+            LightningInvoice::Keysend(payment) => payment.payment_hash(),
+        }
+    }
+
+    /// Returns the amount to be paid, which is only missing for amountless
+    /// BOLT11 invoices. For a part of a multi-part payment this is the amount
+    /// of the part rather than of the invoice.
+    pub fn amount(&self) -> Option<Amount> {
+        match self {
+            LightningInvoice::Bolt11(invoice) => {
+                invoice.amount_milli_satoshis().map(Amount::from_msats)
+            }
+            LightningInvoice::Bolt12(invoice) => Some(invoice.amount),
+            LightningInvoice::Bolt11Part(part) => Some(part.amount),
+            LightningInvoice::Keysend(payment) => Some(payment.amount),
+        }
+    }
+}
+
+/// An invoice for a BOLT12 offer that the gateway requested from the payee on
+/// behalf of the client. The gateway returns the invoice as signed by the
+/// payee, such that the client can check it against the offer with
+/// [`Bolt12OfferInvoice::verify`] before committing to its payment hash and
+/// amount in the outgoing contract.
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize, Decodable, Encodable)]
+pub struct Bolt12OfferInvoice {
+    pub offer: String,
+    /// The TLV encoding of the invoice including the payee's signature.
+    pub invoice: Vec<u8>,
+    pub payment_hash: sha256::Hash,
+    pub amount: Amount,
+}
+
+impl Bolt12OfferInvoice {
+    /// Verifies that the invoice is signed by the issuer of the offer, was
+    /// requested for the given amount and payer note, and matches the payment
+    /// hash and amount the gateway reported. Without an amount the offer has
+    /// to state one in bitcoin which the invoice has to match.
+    pub fn verify(
+        &self,
+        network: bitcoin::Network,
+        amount: Option<Amount>,
+        payer_note: Option<&str>,
+    ) -> Result<(), Bolt12InvoiceError> {
+        let offer = Offer::from_str(&self.offer).map_err(|_| Bolt12InvoiceError::InvalidOffer)?;
+
+        // Parsing the invoice checks the signature against its signing key
+        let invoice = Bolt12Invoice::try_from(self.invoice.clone())
+            .map_err(|_| Bolt12InvoiceError::InvalidInvoice)?;
+
+        let signed_by_issuer = match offer.issuer_signing_pubkey() {
+            Some(issuer_pk) => invoice.signing_pubkey() == issuer_pk,
+            // Offers without a signing key are signed with the blinded node id
+            // of the payee in one of the offer's paths
+            None => offer.paths().iter().any(|path| {
+                path.blinded_hops()
+                    .last()
+                    .is_some_and(|hop| hop.blinded_node_id == invoice.signing_pubkey())
+            }),
+        };
+
+        if !signed_by_issuer
+            || invoice.offer_chains() != Some(offer.chains())
+            || invoice.metadata() != offer.metadata()
+            || invoice.amount() != offer.amount()
+            || invoice.description() != offer.description()
+            || invoice.issuer() != offer.issuer()
+            || invoice.issuer_signing_pubkey() != offer.issuer_signing_pubkey()
+            || invoice.message_paths() != offer.paths()
+        {
+            return Err(Bolt12InvoiceError::OfferMismatch);
+        }
+
+        if invoice.chain() != ChainHash::using_genesis_block(network) {
+            return Err(Bolt12InvoiceError::WrongNetwork);
+        }
+
+        let expected_amount = match (amount, offer.amount()) {
+            (Some(amount), _) => amount,
+            (None, Some(OfferAmount::Bitcoin { amount_msats })) => Amount::from_msats(amount_msats),
+            (None, _) => return Err(Bolt12InvoiceError::AmountMismatch),
+        };
+
+        if Amount::from_msats(invoice.amount_msats()) != expected_amount
+            || self.amount != expected_amount
+        {
+            return Err(Bolt12InvoiceError::AmountMismatch);
+        }
+
+        if invoice.payer_note().map(|note| note.0) != payer_note {
+            return Err(Bolt12InvoiceError::PayerNoteMismatch);
+        }
+
+        if sha256::Hash::from_byte_array(invoice.payment_hash().0) != self.payment_hash {
+            return Err(Bolt12InvoiceError::PaymentHashMismatch);
+        }
+
+        let expires_at = invoice.created_at() + invoice.relative_expiry();
+
+        if expires_at <= fedimint_core::time::duration_since_epoch() {
+            return Err(Bolt12InvoiceError::Expired);
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Error)]
+pub enum Bolt12InvoiceError {
+    #[error("The offer is invalid")]
+    InvalidOffer,
+    #[error("The invoice is invalid or its signature does not verify")]
+    InvalidInvoice,
+    #[error("The invoice was not issued for the offer")]
+    OfferMismatch,
+    #[error("The invoice is for a different network")]
+    WrongNetwork,
+    #[error("The invoice is for a different amount")]
+    AmountMismatch,
+    #[error("The invoice is for a different payer note")]
+    PayerNoteMismatch,
+    #[error("The payment hash does not match the invoice")]
+    PaymentHashMismatch,
+    #[error("The invoice has expired")]
+    Expired,
+}
+
+/// A part of a multi-part payment of a BOLT11 invoice. Every part is paid by a
+/// different gateway, and the payee only releases the preimage once the parts
+/// add up to the amount of the invoice.
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize, Decodable, Encodable)]
+pub struct Bolt11InvoicePart {
+    pub invoice: Bolt11Invoice,
+    pub amount: Amount,
+}
+
+/// A spontaneous payment to a Lightning node without an invoice. The
+/// preimage is chosen by the sender and handed to the payee by the gateway,
+/// which therefore learns it before paying. Unlike for an invoice the gateway
+/// could claim the outgoing contract without paying, so keysend payments
+/// require trusting the gateway with the amount as well as with the fee.
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize, Decodable, Encodable)]
+pub struct KeysendPayment {
+    pub payee: PublicKey,
+    pub amount: Amount,
+    pub preimage: [u8; 32],
+    /// Additional TLV records passed to the payee in the onion, e.g. the
+    /// metadata of a value-for-value payment to a podcast.
+    pub custom_records: BTreeMap<u64, Vec<u8>>,
+}
+
+/// The TLV record type the preimage of a keysend payment is sent in.
+pub const KEYSEND_RECORD_TYPE: u64 = 5_482_373_484;
+
+/// Record types below this are reserved for the Lightning protocol itself.
+pub const MIN_CUSTOM_RECORD_TYPE: u64 = 1 << 16;
+
+/// Limits the size of the custom records such that they fit into the onion
+/// alongside the payment data of the final hop.
+pub const MAX_CUSTOM_RECORDS_SIZE: usize = 1000;
+
+impl KeysendPayment {
+    pub fn payment_hash(&self) -> sha256::Hash {
+        sha256::Hash::hash(&self.preimage)
+    }
+
+    /// Checks that the custom records are within the custom range, do not
+    /// collide with the keysend record and fit into the onion.
+    pub fn verify_custom_records(&self) -> bool {
+        self.custom_records
+            .keys()
+            .all(|record_type| MIN_CUSTOM_RECORD_TYPE <= *record_type)
+            && !self.custom_records.contains_key(&KEYSEND_RECORD_TYPE)
+            && self.custom_records.values().map(Vec::len).sum::<usize>() <= MAX_CUSTOM_RECORDS_SIZE
+    }
 }
 
 pub const KIND: ModuleKind = ModuleKind::from_static_str("lnv2");
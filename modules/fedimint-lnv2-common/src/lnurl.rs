@@ -1,4 +1,5 @@
-use bitcoin::secp256k1::PublicKey;
+use bitcoin::hashes::{Hash, sha256};
+use bitcoin::secp256k1::{Message, PublicKey, schnorr};
 use fedimint_core::config::FederationId;
 use fedimint_core::encoding::{Decodable, Encodable};
 use fedimint_core::util::SafeUrl;
@@ -12,3 +13,65 @@ pub struct LnurlRequest {
     pub aggregate_pk: AggregatePublicKey,
     pub gateways: Vec<SafeUrl>,
 }
+
+/// Maximum length of the username of a lightning address.
+pub const MAX_USERNAME_LENGTH: usize = 64;
+
+/// Request to bind the username of a lightning address (LUD-16) to an lnurl
+/// payload. The signature by the recipient key of the payload proves that the
+/// username is registered by the recipient, so only the recipient can update
+/// it later.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RegisterAddressRequest {
+    pub username: String,
+    pub request: LnurlRequest,
+    pub signature: schnorr::Signature,
+}
+
+/// Request by the recipient of a lightning address for the comments (LUD-12)
+/// payers have attached to payments since the given time.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AddressCommentsRequest {
+    pub username: String,
+    pub since_secs: u64,
+    /// Time of the request, which has to be recent to prevent replays.
+    pub timestamp_secs: u64,
+    pub signature: schnorr::Signature,
+}
+
+/// A comment (LUD-12) a payer has attached to a payment to a lightning address.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Encodable, Decodable)]
+pub struct AddressComment {
+    pub payment_hash: sha256::Hash,
+    pub amount_msat: u64,
+    pub comment: String,
+    pub created_at_secs: u64,
+}
+
+/// Checks that the username is a valid lightning address username, which we
+/// restrict to lowercase ascii letters, digits and the characters `-_.`.
+pub fn is_valid_username(username: &str) -> bool {
+    !username.is_empty()
+        && username.len() <= MAX_USERNAME_LENGTH
+        && username
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || "-_.".contains(c))
+}
+
+/// The message signed by the recipient key to register a username.
+pub fn register_address_message(username: &str, request: &LnurlRequest) -> Message {
+    Message::from_digest(
+        ("register-address", username, request)
+            .consensus_hash::<sha256::Hash>()
+            .to_byte_array(),
+    )
+}
+
+/// The message signed by the recipient key to request its comments.
+pub fn address_comments_message(username: &str, since_secs: u64, timestamp_secs: u64) -> Message {
+    Message::from_digest(
+        ("address-comments", username, since_secs, timestamp_secs)
+            .consensus_hash::<sha256::Hash>()
+            .to_byte_array(),
+    )
+}
@@ -8,7 +8,7 @@
 use devimint::federation::{Client, Federation};
 use devimint::util::{ProcessManager, almost_equal};
 use devimint::version_constants::{
-    VERSION_0_10_0_ALPHA, VERSION_0_11_0_ALPHA, VERSION_0_12_0_ALPHA,
+    VERSION_0_10_0_ALPHA, VERSION_0_11_0_ALPHA, VERSION_0_12_0_ALPHA, VERSION_0_13_0_ALPHA,
 };
 use devimint::{Gatewayd, cmd, util};
 use fedimint_core::core::OperationId;
@@ -16,7 +16,7 @@
 use fedimint_core::task::{self};
 use fedimint_core::util::{backoff_util, retry, write_overwrite_async};
 use fedimint_lnurl::{LnurlResponse, VerifyResponse, parse_lnurl};
-use fedimint_lnv2_client::FinalSendOperationState;
+use fedimint_lnv2_client::{FinalReceiveOperationState, FinalSendOperationState};
 use lightning_invoice::Bolt11Invoice;
 use serde::Deserialize;
 use tokio::try_join;
@@ -62,6 +62,13 @@ enum Commands {
     LnurlPay,
     /// Test LNURL receives after recovery from seed
     LnurlRecovery,
+    /// Test receives via a static LNURL served by a gateway while the
+    /// recipient is offline
+    StaticLnurl,
+    /// Test payments with a comment to a lightning address
+    LightningAddress,
+    /// Test settling and cancelling the payment of a hold invoice
+    HoldInvoice,
     /// Two clients racing to pay the same invoice settle exactly once
     DuplicatePayment,
 }
@@ -101,6 +108,18 @@ async fn main() -> anyhow::Result<()> {
                     pegin_gateways(&dev_fed).await?;
                     test_lnurl_recovery(&dev_fed).await?;
                 }
+                Some(Commands::StaticLnurl) => {
+                    pegin_gateways(&dev_fed).await?;
+                    test_static_lnurl(&dev_fed).await?;
+                }
+                Some(Commands::LightningAddress) => {
+                    pegin_gateways(&dev_fed).await?;
+                    test_lightning_address(&dev_fed).await?;
+                }
+                Some(Commands::HoldInvoice) => {
+                    pegin_gateways(&dev_fed).await?;
+                    test_hold_invoice(&dev_fed).await?;
+                }
                 Some(Commands::DuplicatePayment) => {
                     pegin_gateways(&dev_fed).await?;
                     test_duplicate_payment(&dev_fed, &process_mgr).await?;
@@ -112,6 +131,9 @@ async fn main() -> anyhow::Result<()> {
                     test_duplicate_payment(&dev_fed, &process_mgr).await?;
                     test_lnurl_pay(&dev_fed).await?;
                     test_lnurl_recovery(&dev_fed).await?;
+                    test_static_lnurl(&dev_fed).await?;
+                    test_lightning_address(&dev_fed).await?;
+                    test_hold_invoice(&dev_fed).await?;
                 }
             }
 
@@ -922,6 +944,261 @@ async fn test_lnurl_recovery(dev_fed: &DevJitFed) -> anyhow::Result<()> {
     Ok(())
 }
 
+/// Tests receives via a static LNURL served by the gateway itself. The client
+/// CLI only runs for the duration of a command, so the recipient is offline
+/// while the payment is made and claims the funds on its next invocation.
+async fn test_static_lnurl(dev_fed: &DevJitFed) -> anyhow::Result<()> {
+    if util::FedimintCli::version_or_default().await < *VERSION_0_13_0_ALPHA {
+        return Ok(());
+    }
+
+    if util::Gatewayd::version_or_default().await < *VERSION_0_13_0_ALPHA {
+        return Ok(());
+    }
+
+    let federation = dev_fed.fed().await?;
+
+    let gw_lnd = dev_fed.gw_lnd().await?;
+    let gw_ldk = dev_fed.gw_ldk().await?;
+
+    let client = federation
+        .new_joined_client("lnv2-static-lnurl-test-client")
+        .await?;
+
+    let lnurl = cmd!(
+        client,
+        "module",
+        "lnv2",
+        "lnurl",
+        "generate-static",
+        "500000",
+        "--payments",
+        "1",
+        "--gateway",
+        &gw_ldk.addr,
+    )
+    .out_json()
+    .await?
+    .as_str()
+    .expect("lnurl is a string")
+    .to_owned();
+
+    let (invoice, verify_url) = fetch_invoice(lnurl.clone(), 500_000).await?;
+
+    assert!(!verify_payment(&verify_url).await?.settled);
+
+    gw_lnd.client().pay_invoice(invoice.clone()).await?;
+
+    let response = verify_payment(&verify_url).await?;
+
+    assert!(response.settled);
+
+    verify_preimage(&response, &invoice);
+
+    wait_for_lnurl_balance(&client, "static lnurl client", 475 * 1000).await?;
+
+    // The only registered contract has been used up
+    assert!(fetch_invoice(lnurl, 500_000).await.is_err());
+
+    Ok(())
+}
+
+/// Tests registering a lightning address with recurringdv2 and paying it with
+/// a comment, which the recipient can read once the payment has settled.
+async fn test_lightning_address(dev_fed: &DevJitFed) -> anyhow::Result<()> {
+    if util::FedimintCli::version_or_default().await < *VERSION_0_13_0_ALPHA {
+        return Ok(());
+    }
+
+    let federation = dev_fed.fed().await?;
+
+    let gw_lnd = dev_fed.gw_lnd().await?;
+    let gw_ldk = dev_fed.gw_ldk().await?;
+
+    let recurringd = dev_fed.recurringdv2().await?.api_url().to_string();
+
+    let client = federation
+        .new_joined_client("lnv2-lightning-address-test-client")
+        .await?;
+
+    let address = cmd!(
+        client,
+        "module",
+        "lnv2",
+        "lnurl",
+        "register-address",
+        &recurringd,
+        "alice",
+        "--gateway",
+        &gw_ldk.addr,
+    )
+    .out_json()
+    .await?
+    .as_str()
+    .expect("address is a string")
+    .to_owned();
+
+    assert!(address.starts_with("alice@"));
+
+    // Devimint serves recurringdv2 over http, so we cannot resolve the address
+    // via https as a wallet would and query the well-known endpoint directly.
+    let response = reqwest::get(format!("{recurringd}.well-known/lnurlp/alice"))
+        .await?
+        .json::<LnUrlPayResponse>()
+        .await?;
+
+    let response = reqwest::get(format!(
+        "{}?amount=500000&comment=thanks",
+        response.callback
+    ))
+    .await?
+    .json::<LnUrlPayInvoiceResponse>()
+    .await?;
+
+    gw_lnd.client().pay_invoice(response.pr.clone()).await?;
+
+    let verify = verify_payment(&response.verify).await?;
+
+    assert!(verify.settled);
+
+    verify_preimage(&verify, &response.pr);
+
+    wait_for_lnurl_balance(&client, "lightning address client", 475 * 1000).await?;
+
+    // The comment is recorded by recurringdv2 once it sees the payment settle.
+    retry(
+        "Waiting for the comment to be recorded".to_string(),
+        backoff_util::background_backoff(),
+        || async {
+            let comments = cmd!(
+                client,
+                "module",
+                "lnv2",
+                "lnurl",
+                "comments",
+                &recurringd,
+                "alice",
+            )
+            .out_json()
+            .await?;
+
+            ensure!(comments.as_array().is_some_and(|c| c.len() == 1));
+            ensure!(comments[0]["comment"] == "thanks");
+
+            Ok(())
+        },
+    )
+    .await?;
+
+    Ok(())
+}
+
+/// Tests that the payment of a hold invoice is only claimed once the recipient
+/// settles it and is failed back to the payer if the recipient cancels it.
+async fn test_hold_invoice(dev_fed: &DevJitFed) -> anyhow::Result<()> {
+    if util::FedimintCli::version_or_default().await < *VERSION_0_13_0_ALPHA {
+        return Ok(());
+    }
+
+    if util::Gatewayd::version_or_default().await < *VERSION_0_13_0_ALPHA {
+        return Ok(());
+    }
+
+    let federation = dev_fed.fed().await?;
+
+    let gw_lnd = dev_fed.gw_lnd().await?;
+    let gw_ldk = dev_fed.gw_ldk().await?;
+
+    let client = federation
+        .new_joined_client("lnv2-hold-invoice-test-client")
+        .await?;
+
+    info!("Testing settlement of a held payment...");
+
+    let (invoice, receive_op) = receive_hold(&client, &gw_ldk.addr, 500_000).await?;
+
+    // The payer only sees the payment succeed once the recipient has settled
+    // it, so the recipient settles while the payer is still waiting.
+    try_join!(
+        gw_lnd.client().pay_invoice(invoice),
+        retry(
+            "Waiting for the payment to be held".to_string(),
+            backoff_util::background_backoff(),
+            || resolve_hold(&client, receive_op, true),
+        ),
+    )?;
+
+    common::await_receive_claimed(&client, receive_op).await?;
+
+    info!("Testing cancellation of a hold invoice...");
+
+    let (invoice, receive_op) = receive_hold(&client, &gw_ldk.addr, 500_000).await?;
+
+    resolve_hold(&client, receive_op, false).await?;
+
+    assert!(gw_lnd.client().pay_invoice(invoice).await.is_err());
+
+    assert_eq!(
+        cmd!(
+            client,
+            "module",
+            "lnv2",
+            "await-receive",
+            operation_id_arg(receive_op)?
+        )
+        .out_json()
+        .await?,
+        serde_json::to_value(FinalReceiveOperationState::Rejected)?,
+    );
+
+    Ok(())
+}
+
+async fn receive_hold(
+    client: &Client,
+    gateway: &str,
+    amount: u64,
+) -> anyhow::Result<(Bolt11Invoice, OperationId)> {
+    Ok(serde_json::from_value(
+        cmd!(
+            client,
+            "module",
+            "lnv2",
+            "receive-hold",
+            amount,
+            60,
+            "--gateway",
+            gateway
+        )
+        .out_json()
+        .await?,
+    )?)
+}
+
+async fn resolve_hold(
+    client: &Client,
+    operation_id: OperationId,
+    settle: bool,
+) -> anyhow::Result<()> {
+    let command = if settle { "settle-hold" } else { "cancel-hold" };
+
+    cmd!(
+        client,
+        "module",
+        "lnv2",
+        command,
+        operation_id_arg(operation_id)?
+    )
+    .run()
+    .await
+}
+
+fn operation_id_arg(operation_id: OperationId) -> anyhow::Result<String> {
+    Ok(serde_json::to_string(&operation_id)?
+        .trim_matches('"')
+        .to_owned())
+}
+
 async fn generate_lnurl(
     client: &Client,
     recurringd_base_url: &str,
@@ -2,7 +2,7 @@
 
 use anyhow::anyhow;
 use bitcoin::hashes::{Hash, sha256};
-use bitcoin::secp256k1::{SECP256K1, SecretKey};
+use bitcoin::secp256k1::{PublicKey, SECP256K1, SecretKey};
 use fedimint_api_client::api::ServerError;
 use fedimint_core::config::FederationId;
 use fedimint_core::secp256k1::Keypair;
@@ -12,8 +12,13 @@
 use fedimint_core::{Amount, OutPoint, apply, async_trait_maybe_send};
 use fedimint_ln_common::bitcoin;
 use fedimint_lnv2_common::contracts::{IncomingContract, OutgoingContract, PaymentImage};
-use fedimint_lnv2_common::gateway_api::{GatewayConnection, PaymentFee, RoutingInfo};
-use fedimint_lnv2_common::{Bolt11InvoiceDescription, LightningInvoice};
+use fedimint_lnv2_common::gateway_api::{
+    GatewayConnection, HoldInvoiceStatus, PaymentFee, RegisterStaticLnurlContractsPayload,
+    RoutePreview, RoutingInfo,
+};
+use fedimint_lnv2_common::{
+    Bolt11InvoiceDescription, Bolt11InvoicePart, Bolt12OfferInvoice, ContractId, LightningInvoice,
+};
 use lightning_invoice::{
     Bolt11Invoice, Currency, DEFAULT_EXPIRY_TIME, InvoiceBuilder, PaymentSecret,
 };
@@ -32,6 +37,10 @@ pub fn gateway() -> SafeUrl {
     SafeUrl::parse("https://gateway.xyz").expect("Valid Url")
 }
 
+pub fn second_gateway() -> SafeUrl {
+    SafeUrl::parse("https://second-gateway.xyz").expect("Valid Url")
+}
+
 pub fn gateway_keypair() -> Keypair {
     SecretKey::from_slice(&GATEWAY_SECRET)
         .expect("32 bytes; within curve order")
@@ -60,6 +69,7 @@ pub fn crash_invoice() -> Bolt11Invoice {
         .current_timestamp()
         .min_final_cltv_expiry_delta(0)
         .payment_secret(PaymentSecret(payment_secret))
+        .basic_mpp()
         .amount_milli_satoshis(1_000_000)
         .expiry_time(Duration::from_secs(DEFAULT_EXPIRY_TIME))
         .build_signed(|m| SECP256K1.sign_ecdsa_recoverable(m, &sk))
@@ -99,6 +109,7 @@ async fn routing_info(
             expiration_delta_default: 500,
             expiration_delta_minimum: 144,
             receive_fee: PaymentFee::TRANSACTION_FEE_DEFAULT,
+            send_fee_tiers: Vec::new(),
         }))
     }
 
@@ -128,6 +139,45 @@ async fn bolt11_invoice(
             .unwrap())
     }
 
+    async fn hold_bolt11_invoice(
+        &self,
+        _gateway_api: SafeUrl,
+        _federation_id: FederationId,
+        _contract: IncomingContract,
+        _amount: Amount,
+        _description: Bolt11InvoiceDescription,
+        _expiry_secs: u32,
+        _hold_secs: u32,
+    ) -> Result<Bolt11Invoice, ServerError> {
+        Err(ServerError::InvalidRequest(anyhow!(
+            "Hold invoices are not supported by the mock gateway"
+        )))
+    }
+
+    async fn hold_invoice_status(
+        &self,
+        _gateway_api: SafeUrl,
+        _federation_id: FederationId,
+        _contract_id: ContractId,
+    ) -> Result<HoldInvoiceStatus, ServerError> {
+        Err(ServerError::InvalidRequest(anyhow!(
+            "Hold invoices are not supported by the mock gateway"
+        )))
+    }
+
+    async fn resolve_hold_invoice(
+        &self,
+        _gateway_api: SafeUrl,
+        _federation_id: FederationId,
+        _contract_id: ContractId,
+        _settle: bool,
+        _signature: Signature,
+    ) -> Result<(), ServerError> {
+        Err(ServerError::InvalidRequest(anyhow!(
+            "Hold invoices are not supported by the mock gateway"
+        )))
+    }
+
     async fn send_payment(
         &self,
         _gateway_api: SafeUrl,
@@ -138,7 +188,8 @@ async fn send_payment(
         _auth: Signature,
     ) -> Result<Result<[u8; 32], Signature>, ServerError> {
         match invoice {
-            LightningInvoice::Bolt11(invoice) => {
+            LightningInvoice::Bolt11(invoice)
+            | LightningInvoice::Bolt11Part(Bolt11InvoicePart { invoice, .. }) => {
                 if *invoice.payment_secret() == PaymentSecret(GATEWAY_CRASH_PAYMENT_SECRET) {
                     return Err(ServerError::InvalidRequest(anyhow!(
                         "Gateway crash payment secret"
@@ -151,6 +202,78 @@ async fn send_payment(
 
                 Ok(Ok(MOCK_INVOICE_PREIMAGE))
             }
+            LightningInvoice::Bolt12(..) => Ok(Ok(MOCK_INVOICE_PREIMAGE)),
+            LightningInvoice::Keysend(payment) => Ok(Ok(payment.preimage)),
         }
     }
+
+    async fn bolt12_invoice(
+        &self,
+        _gateway_api: SafeUrl,
+        _federation_id: FederationId,
+        _offer: String,
+        _amount: Option<Amount>,
+        _payer_note: Option<String>,
+    ) -> Result<Bolt12OfferInvoice, ServerError> {
+        Err(ServerError::InvalidRequest(anyhow!(
+            "Bolt12 offers are not supported by the mock gateway"
+        )))
+    }
+
+    async fn register_static_lnurl_contracts(
+        &self,
+        _gateway_api: SafeUrl,
+        _payload: RegisterStaticLnurlContractsPayload,
+    ) -> Result<(), ServerError> {
+        Err(ServerError::InvalidRequest(anyhow!(
+            "Static lnurls are not supported by the mock gateway"
+        )))
+    }
+
+    async fn bolt12_offer(
+        &self,
+        _gateway_api: SafeUrl,
+        _federation_id: FederationId,
+        _recipient_pk: PublicKey,
+        _description: Option<String>,
+    ) -> Result<String, ServerError> {
+        Err(ServerError::InvalidRequest(anyhow!(
+            "Bolt12 offers are not supported by the mock gateway"
+        )))
+    }
+
+    async fn probe_payment(
+        &self,
+        _gateway_api: SafeUrl,
+        _federation_id: FederationId,
+        invoice: Bolt11Invoice,
+    ) -> Result<Option<RoutePreview>, ServerError> {
+        if *invoice.payment_secret() == PaymentSecret(UNPAYABLE_PAYMENT_SECRET) {
+            return Err(ServerError::InvalidRequest(anyhow!(
+                "No route found for the payment"
+            )));
+        }
+
+        Ok(Some(RoutePreview {
+            routing_fee: Amount::ZERO,
+            success_probability: Some(1.0),
+            cltv_delta: invoice.min_final_cltv_expiry_delta(),
+        }))
+    }
+
+    async fn supports_keysend(
+        &self,
+        _gateway_api: SafeUrl,
+        _federation_id: &FederationId,
+    ) -> Result<bool, ServerError> {
+        Ok(true)
+    }
+
+    async fn supports_multi_part(
+        &self,
+        _gateway_api: SafeUrl,
+        _federation_id: &FederationId,
+    ) -> Result<bool, ServerError> {
+        Ok(true)
+    }
 }
@@ -1,7 +1,9 @@
 mod mock;
 
+use std::collections::BTreeMap;
 use std::pin::pin;
 use std::sync::Arc;
+use std::time::Duration;
 
 use async_stream::stream;
 use fedimint_client::ClientHandleArc;
@@ -9,6 +11,7 @@
 use fedimint_client_module::module::ClientModule;
 use fedimint_core::core::{IntoDynInstance, OperationId};
 use fedimint_core::module::{AmountUnit, Amounts};
+use fedimint_core::task::sleep_in_test;
 use fedimint_core::util::NextOrPending as _;
 use fedimint_core::{Amount, OutPoint, sats};
 use fedimint_dummy_client::{DummyClientInit, DummyClientModule};
@@ -16,13 +19,17 @@
 use fedimint_eventlog::{Event, EventLogEntry, EventLogId};
 use fedimint_lnv2_client::events::{
     ReceivePaymentEvent, SendPaymentEvent, SendPaymentStatus, SendPaymentUpdateEvent,
+    SendRefundReason,
 };
 use fedimint_lnv2_client::{
-    InvoiceSendStatus, LightningClientInit, LightningClientModule, LightningOperationMeta,
-    ReceiveOperationState, SendOperationState, SendPaymentError,
+    FinalSendOperationState, InvoiceSendStatus, LightningClientInit, LightningClientModule,
+    LightningOperationMeta, ReceiveOperationState, RecurringPayment, SendOperationState,
+    SendPaymentError,
 };
+use fedimint_lnv2_common::gateway_api::PaymentFee;
 use fedimint_lnv2_common::{
-    Bolt11InvoiceDescription, KIND, LightningInput, LightningInputV0, OutgoingWitness,
+    Bolt11InvoiceDescription, KEYSEND_RECORD_TYPE, KIND, LightningInput, LightningInputV0,
+    OutgoingWitness,
 };
 use fedimint_lnv2_server::LightningInit;
 use fedimint_logging::LOG_TEST;
@@ -224,6 +231,7 @@ async fn refund_failed_payment() -> anyhow::Result<()> {
     };
     assert_eq!(update.operation_id, operation_id);
     assert_eq!(update.status, SendPaymentStatus::Refunded);
+    assert_eq!(update.refund_reason, Some(SendRefundReason::Cancelled));
 
     assert_eq!(
         client
@@ -236,6 +244,280 @@ async fn refund_failed_payment() -> anyhow::Result<()> {
     Ok(())
 }
 
+#[tokio::test(flavor = "multi_thread")]
+async fn previews_payment_without_paying() -> anyhow::Result<()> {
+    let fixtures = fixtures();
+    let fed = fixtures.new_fed_degraded().await;
+    let client = fed.new_client().await;
+
+    client
+        .get_first_module::<DummyClientModule>()?
+        .mock_receive(sats(10_000), AmountUnit::BITCOIN)
+        .await?;
+
+    let lightning = client.get_first_module::<LightningClientModule>()?;
+    let invoice = mock::payable_invoice();
+
+    let preview = lightning
+        .preview_send(invoice.clone(), Some(mock::gateway()))
+        .await?;
+
+    assert_eq!(preview.gateway, mock::gateway());
+    assert_eq!(
+        preview.gateway_fee,
+        PaymentFee::TRANSACTION_FEE_DEFAULT.fee(invoice.amount_milli_satoshis().unwrap())
+    );
+    assert_eq!(
+        preview.route.and_then(|route| route.success_probability),
+        Some(1.0)
+    );
+
+    // Previewing a payment does not pay the invoice
+    assert_eq!(
+        lightning.get_invoice_send_status(&invoice).await?,
+        InvoiceSendStatus::NotAttempted,
+    );
+
+    assert!(
+        lightning
+            .preview_send(mock::unpayable_invoice(), Some(mock::gateway()))
+            .await
+            .is_err()
+    );
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn can_send_keysend_payment() -> anyhow::Result<()> {
+    let fixtures = fixtures();
+    let fed = fixtures.new_fed_degraded().await;
+    let client = fed.new_client().await;
+
+    client
+        .get_first_module::<DummyClientModule>()?
+        .mock_receive(sats(10_000), AmountUnit::BITCOIN)
+        .await?;
+
+    let lightning = client.get_first_module::<LightningClientModule>()?;
+    let payee = mock::gateway_keypair().public_key();
+
+    assert_eq!(
+        lightning
+            .send_keysend(
+                payee,
+                sats(1000),
+                BTreeMap::from([(KEYSEND_RECORD_TYPE, vec![0; 32])]),
+                mock::gateway(),
+                Value::Null,
+            )
+            .await,
+        Err(SendPaymentError::InvalidCustomRecords),
+    );
+
+    let operation_id = lightning
+        .send_keysend(
+            payee,
+            sats(1000),
+            BTreeMap::from([(7_629_169, b"podcast".to_vec())]),
+            mock::gateway(),
+            Value::Null,
+        )
+        .await?;
+
+    // The preimage the payee releases is the one derived by the client
+    assert_eq!(
+        lightning
+            .await_final_send_operation_state(operation_id)
+            .await?,
+        FinalSendOperationState::Success(lightning.keysend_preimage(operation_id)),
+    );
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn can_split_payment_over_gateways() -> anyhow::Result<()> {
+    let fixtures = fixtures();
+    let fed = fixtures.new_fed_degraded().await;
+    let client = fed.new_client().await;
+
+    // Give client initial balance
+    client
+        .get_first_module::<DummyClientModule>()?
+        .mock_receive(sats(10_000), AmountUnit::BITCOIN)
+        .await?;
+
+    let invoice = mock::payable_invoice();
+
+    let lightning = client.get_first_module::<LightningClientModule>()?;
+
+    assert_eq!(
+        lightning
+            .send_split(invoice.clone(), vec![mock::gateway(); 2], Value::Null)
+            .await,
+        Err(SendPaymentError::InvalidSplit),
+    );
+
+    let operation_id = lightning
+        .send_split(
+            invoice.clone(),
+            vec![mock::gateway(), mock::second_gateway()],
+            Value::Null,
+        )
+        .await?;
+
+    assert_eq!(
+        lightning
+            .send(invoice.clone(), Some(mock::gateway()), Value::Null)
+            .await,
+        Err(SendPaymentError::DuplicatePaymentAttempt(operation_id)),
+    );
+
+    let mut sub = lightning
+        .subscribe_send_operation_state_updates(operation_id)
+        .await?
+        .into_stream();
+
+    assert_eq!(sub.ok().await?, SendOperationState::Funding);
+    assert_eq!(sub.ok().await?, SendOperationState::Funded);
+    assert_eq!(
+        sub.ok().await?,
+        SendOperationState::Success(MOCK_INVOICE_PREIMAGE)
+    );
+
+    assert_eq!(
+        lightning.get_invoice_send_status(&invoice).await?,
+        InvoiceSendStatus::Succeeded(operation_id),
+    );
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn refund_failed_split_payment() -> anyhow::Result<()> {
+    let fixtures = fixtures();
+    let fed = fixtures.new_fed_degraded().await;
+    let client = fed.new_client().await;
+
+    // Give client initial balance
+    client
+        .get_first_module::<DummyClientModule>()?
+        .mock_receive(sats(10_000), AmountUnit::BITCOIN)
+        .await?;
+
+    let operation_id = client
+        .get_first_module::<LightningClientModule>()?
+        .send_split(
+            mock::unpayable_invoice(),
+            vec![mock::gateway(), mock::second_gateway()],
+            Value::Null,
+        )
+        .await?;
+
+    let mut sub = client
+        .get_first_module::<LightningClientModule>()?
+        .subscribe_send_operation_state_updates(operation_id)
+        .await?
+        .into_stream();
+
+    assert_eq!(sub.ok().await?, SendOperationState::Funding);
+    assert_eq!(sub.ok().await?, SendOperationState::Funded);
+    assert_eq!(sub.ok().await?, SendOperationState::Refunding);
+    assert_eq!(sub.ok().await?, SendOperationState::Refunded);
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn scores_gateways_by_payment_outcome() -> anyhow::Result<()> {
+    let fixtures = fixtures();
+    let fed = fixtures.new_fed_degraded().await;
+    let client = fed.new_client().await;
+
+    // Give client initial balance
+    client
+        .get_first_module::<DummyClientModule>()?
+        .mock_receive(sats(10_000), AmountUnit::BITCOIN)
+        .await?;
+
+    let lightning = client.get_first_module::<LightningClientModule>()?;
+
+    let operation_id = lightning
+        .send(mock::payable_invoice(), Some(mock::gateway()), Value::Null)
+        .await?;
+
+    assert_eq!(
+        lightning
+            .await_final_send_operation_state(operation_id)
+            .await?,
+        FinalSendOperationState::Success(MOCK_INVOICE_PREIMAGE),
+    );
+
+    for _ in 0..3 {
+        let operation_id = lightning
+            .send(
+                mock::unpayable_invoice(),
+                Some(mock::second_gateway()),
+                Value::Null,
+            )
+            .await?;
+
+        assert_eq!(
+            lightning
+                .await_final_send_operation_state(operation_id)
+                .await?,
+            FinalSendOperationState::Refunded,
+        );
+    }
+
+    // The second gateway cancelled the payments since the payee could not be
+    // reached, which does not count against it
+    let operation_id = lightning
+        .send(
+            mock::payable_invoice(),
+            Some(mock::second_gateway()),
+            Value::Null,
+        )
+        .await?;
+
+    assert_eq!(
+        lightning
+            .await_final_send_operation_state(operation_id)
+            .await?,
+        FinalSendOperationState::Success(MOCK_INVOICE_PREIMAGE),
+    );
+
+    // The scores are updated from the event log in the background
+    let scores = loop {
+        let scores = lightning.gateway_scores().await;
+
+        if scores
+            .get(&mock::second_gateway())
+            .is_some_and(|score| score.successes == 1)
+        {
+            break scores;
+        }
+
+        sleep_in_test("waiting for gateway scores", Duration::from_millis(100)).await;
+    };
+
+    let now_secs = fedimint_core::time::duration_since_epoch().as_secs();
+
+    let score = &scores[&mock::gateway()];
+    assert_eq!(score.successes, 1);
+    assert_eq!(score.refunds, 0);
+    assert!(score.total_fees > Amount::ZERO);
+    assert!(!score.is_demoted(now_secs));
+
+    let score = &scores[&mock::second_gateway()];
+    assert_eq!(score.refunds, 0);
+    assert_eq!(score.consecutive_refunds, 0);
+    assert!(!score.is_demoted(now_secs));
+
+    Ok(())
+}
+
 #[tokio::test(flavor = "multi_thread")]
 async fn unilateral_refund_of_outgoing_contracts() -> anyhow::Result<()> {
     if Fixtures::is_real_test() {
@@ -289,6 +571,25 @@ async fn unilateral_refund_of_outgoing_contracts() -> anyhow::Result<()> {
     };
     assert_eq!(update.operation_id, operation_id);
     assert_eq!(update.status, SendPaymentStatus::Refunded);
+    assert_eq!(update.refund_reason, Some(SendRefundReason::Expired));
+
+    // The gateway let the contract expire, which counts against it
+    loop {
+        let scores = client
+            .get_first_module::<LightningClientModule>()?
+            .gateway_scores()
+            .await;
+
+        if let Some(score) = scores.get(&mock::gateway())
+            && score.refunds == 1
+        {
+            assert_eq!(score.consecutive_refunds, 1);
+
+            break;
+        }
+
+        sleep_in_test("waiting for gateway scores", Duration::from_millis(100)).await;
+    }
 
     // Verify that fees were paid, which is always the case for LNv2
     let operation_fees = client
@@ -353,6 +654,7 @@ async fn claiming_outgoing_contract_triggers_success() -> anyhow::Result<()> {
         LightningOperationMeta::LnurlReceive(..) => {
             panic!("Operation Meta is a LnurlReceive variant")
         }
+        LightningOperationMeta::Recurring(..) => panic!("Operation Meta is a Recurring variant"),
     };
 
     let client_input = ClientInput::<LightningInput> {
@@ -452,6 +754,64 @@ async fn rejects_wrong_network_invoice() -> anyhow::Result<()> {
     Ok(())
 }
 
+#[tokio::test(flavor = "multi_thread")]
+async fn validates_and_cancels_recurring_payment() -> anyhow::Result<()> {
+    let fixtures = fixtures();
+    let fed = fixtures.new_fed_degraded().await;
+    let client = fed.new_client().await;
+    let lightning = client.get_first_module::<LightningClientModule>()?;
+
+    let schedule = RecurringPayment {
+        recipient: "alice@example.com".to_string(),
+        amount: sats(1000),
+        interval_secs: 24 * 60 * 60,
+        start_secs: u64::from(u32::MAX),
+        max_runs: Some(12),
+        spending_limit: None,
+    };
+
+    assert!(
+        lightning
+            .schedule_recurring_payment(
+                RecurringPayment {
+                    recipient: "not a recipient".to_string(),
+                    ..schedule.clone()
+                },
+                Value::Null,
+            )
+            .await
+            .is_err()
+    );
+
+    assert!(
+        lightning
+            .schedule_recurring_payment(
+                RecurringPayment {
+                    interval_secs: 1,
+                    ..schedule.clone()
+                },
+                Value::Null,
+            )
+            .await
+            .is_err()
+    );
+
+    let operation_id = lightning
+        .schedule_recurring_payment(schedule, Value::Null)
+        .await?;
+
+    lightning.cancel_recurring_payment(operation_id).await?;
+
+    assert!(
+        lightning
+            .recurring_payment_runs(operation_id)
+            .await
+            .is_empty()
+    );
+
+    Ok(())
+}
+
 mod db {
     use std::collections::BTreeMap;
 
@@ -875,6 +1235,14 @@ async fn test_client_db_migrations() -> anyhow::Result<()> {
                                 "the stream index must round-trip unchanged, got {index}"
                             );
                         }
+                        db::DbKeyPrefix::GatewayScore
+                        | db::DbKeyPrefix::GatewayScoreCursor
+                        | db::DbKeyPrefix::PendingSendScore
+                        | db::DbKeyPrefix::RecurringPaymentCancelled
+                        | db::DbKeyPrefix::RecurringPaymentRun => {
+                            // Introduced after the v0 snapshot was taken, so
+                            // there is no row to read back.
+                        }
                         db::DbKeyPrefix::ExternalReservedStart
                         | db::DbKeyPrefix::CoreInternalReservedStart
                         | db::DbKeyPrefix::CoreInternalReservedEnd => {
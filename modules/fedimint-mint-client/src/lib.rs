@@ -1970,6 +1970,18 @@ pub async fn send_fee_quote(&self, amount: Amount) -> anyhow::Result<FeeQuote> {
             .await
     }
 
+    /// Returns the id of the operation that
+    /// [`MintClientModule::reissue_external_notes`] reissues `oob_notes` in,
+    /// such that a reissue that was started before can be looked up again.
+    pub fn reissue_external_notes_operation_id(oob_notes: &OOBNotes) -> OperationId {
+        OperationId(
+            oob_notes
+                .notes()
+                .consensus_hash::<sha256t::Hash<OOBReissueTag>>()
+                .to_byte_array(),
+        )
+    }
+
     /// Try to reissue e-cash notes received from a third party to receive them
     /// in our wallet. The progress and outcome can be observed using
     /// [`MintClientModule::subscribe_reissue_external_notes`].
@@ -2000,11 +2012,7 @@ pub async fn reissue_external_notes<M: Serialize + Send>(
             bail!(ReissueExternalNotesError::WrongFederationId);
         }
 
-        let operation_id = OperationId(
-            notes
-                .consensus_hash::<sha256t::Hash<OOBReissueTag>>()
-                .to_byte_array(),
-        );
+        let operation_id = Self::reissue_external_notes_operation_id(&oob_notes);
 
         let amount = notes.total_amount();
         let mint_inputs = self.create_input_from_notes(notes)?;
@@ -5,9 +5,12 @@
 use fedimint_walletv2_common::endpoint_constants::{
     CONSENSUS_BLOCK_COUNT_ENDPOINT, CONSENSUS_FEERATE_ENDPOINT, FEDERATION_WALLET_ENDPOINT,
     OUTPUT_INFO_SLICE_ENDPOINT, PENDING_TRANSACTION_CHAIN_ENDPOINT, RECEIVE_FEE_ENDPOINT,
-    SEND_FEE_ENDPOINT, TRANSACTION_CHAIN_ENDPOINT, TRANSACTION_ID_ENDPOINT,
+    SEND_FEE_ENDPOINT, SEND_FEE_FOR_PRIORITY_ENDPOINT, TRANSACTION_CHAIN_ENDPOINT,
+    TRANSACTION_ID_ENDPOINT, WATCH_ONLY_EXPORT_ENDPOINT,
+};
+use fedimint_walletv2_common::{
+    FederationWallet, FeePriority, OutputInfo, TxInfo, WatchOnlyExport,
 };
-use fedimint_walletv2_common::{FederationWallet, OutputInfo, TxInfo};
 
 #[apply(async_trait_maybe_send!)]
 pub trait WalletFederationApi {
@@ -19,6 +22,11 @@ pub trait WalletFederationApi {
 
     async fn send_fee(&self) -> FederationResult<Option<bitcoin::Amount>>;
 
+    async fn send_fee_for_priority(
+        &self,
+        priority: FeePriority,
+    ) -> FederationResult<Option<bitcoin::Amount>>;
+
     async fn receive_fee(&self) -> FederationResult<Option<bitcoin::Amount>>;
 
     async fn pending_tx_chain(&self) -> FederationResult<Vec<TxInfo>>;
@@ -32,6 +40,8 @@ async fn output_info_slice(
     ) -> FederationResult<Vec<OutputInfo>>;
 
     async fn tx_id(&self, outpoint: OutPoint) -> Option<bitcoin::Txid>;
+
+    async fn watch_only_export(&self) -> FederationResult<WatchOnlyExport>;
 }
 
 #[apply(async_trait_maybe_send!)]
@@ -68,6 +78,17 @@ async fn send_fee(&self) -> FederationResult<Option<bitcoin::Amount>> {
             .await
     }
 
+    async fn send_fee_for_priority(
+        &self,
+        priority: FeePriority,
+    ) -> FederationResult<Option<bitcoin::Amount>> {
+        self.request_current_consensus(
+            SEND_FEE_FOR_PRIORITY_ENDPOINT.to_string(),
+            ApiRequestErased::new(priority),
+        )
+        .await
+    }
+
     async fn receive_fee(&self) -> FederationResult<Option<bitcoin::Amount>> {
         self.request_current_consensus(RECEIVE_FEE_ENDPOINT.to_string(), ApiRequestErased::new(()))
             .await
@@ -108,4 +129,12 @@ async fn tx_id(&self, outpoint: OutPoint) -> Option<bitcoin::Txid> {
         )
         .await
     }
+
+    async fn watch_only_export(&self) -> FederationResult<WatchOnlyExport> {
+        self.request_current_consensus(
+            WATCH_ONLY_EXPORT_ENDPOINT.to_string(),
+            ApiRequestErased::new(()),
+        )
+        .await
+    }
 }
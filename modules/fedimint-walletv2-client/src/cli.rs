@@ -5,6 +5,7 @@
 use clap::{Parser, Subcommand};
 use fedimint_core::BitcoinAmountOrAll;
 use fedimint_eventlog::EventLogId;
+use fedimint_walletv2_common::FeePriority;
 use serde::Serialize;
 use serde_json::Value;
 
@@ -16,7 +17,15 @@ enum Opts {
     #[command(subcommand)]
     Info(InfoOpts),
     /// Fetch the current fee required to send an onchain payment.
-    SendFee,
+    SendFee {
+        /// Fetch the fee for this priority instead: next-block, six-blocks or
+        /// economy.
+        #[arg(long, conflicts_with = "all_priorities")]
+        priority: Option<FeePriority>,
+        /// List the fees of all priorities the federation offers.
+        #[arg(long)]
+        all_priorities: bool,
+    },
     /// Fetch the current fee required to claim an onchain deposit (peg-in).
     ReceiveFee,
     /// Send an onchain payment.
@@ -24,15 +33,28 @@ enum Opts {
         address: Address<NetworkUnchecked>,
         /// Value to send, or "all" to sweep the entire balance.
         value: BitcoinAmountOrAll,
-        #[arg(long)]
+        #[arg(long, conflicts_with = "priority")]
         fee: Option<bitcoin::Amount>,
+        /// Pay the fee for this priority: next-block, six-blocks or economy.
+        #[arg(long)]
+        priority: Option<FeePriority>,
     },
     /// Return the next unused receive address.
     ///
     /// To wait for a payment to this address, read the current event log
     /// position with `dev next-event-log-id` *before* running this, then pass
     /// that position to `await-receive`.
-    Receive,
+    Receive {
+        /// Derive a fresh address attributed to this label instead.
+        #[arg(long)]
+        label: Option<String>,
+    },
+    /// List all receive addresses derived by the client.
+    ReceiveAddresses,
+    /// Show or set the number of unused receive addresses watched beyond the
+    /// last used one, e.g. before recovering a client that handed out many
+    /// addresses.
+    GapLimit { gap_limit: Option<u64> },
     /// Block until the next payment is received, starting from the given event
     /// log position. Returns the receive's final state and the event log
     /// position to pass to the following `await-receive`.
@@ -55,6 +77,8 @@ enum InfoOpts {
     PendingTxChain,
     /// Display the chain of bitcoin transactions.
     TxChain,
+    /// Export the descriptors of all federation UTXOs for a watch-only wallet.
+    WatchOnlyExport,
 }
 
 pub(crate) async fn handle_cli_command(
@@ -70,21 +94,35 @@ pub(crate) async fn handle_cli_command(
             InfoOpts::Feerate => json(wallet.feerate().await?),
             InfoOpts::PendingTxChain => json(wallet.pending_tx_chain().await?),
             InfoOpts::TxChain => json(wallet.tx_chain().await?),
+            InfoOpts::WatchOnlyExport => json(wallet.watch_only_export().await?),
         },
-        Opts::SendFee => json(wallet.send_fee().await?),
+        Opts::SendFee {
+            priority: None,
+            all_priorities: false,
+        } => json(wallet.send_fee().await?),
+        Opts::SendFee {
+            priority: Some(priority),
+            ..
+        } => json(wallet.send_fee_for_priority(priority).await?),
+        Opts::SendFee {
+            priority: None,
+            all_priorities: true,
+        } => json(wallet.send_fee_options().await?),
         Opts::ReceiveFee => json(wallet.receive_fee().await?),
         Opts::Send {
             address,
             value,
             fee,
+            priority,
         } => {
             // Resolve the on-chain fee up front so the same value sizes the
             // sweep and funds the send: the required feerate rises with each
             // pending federation transaction, and a value computed against a
             // stale fee would be rejected.
-            let fee = match fee {
-                Some(fee) => fee,
-                None => wallet.send_fee().await?,
+            let fee = match (fee, priority) {
+                (Some(fee), _) => fee,
+                (None, Some(priority)) => wallet.send_fee_for_priority(priority).await?,
+                (None, None) => wallet.send_fee().await?,
             };
 
             let value = match value {
@@ -108,7 +146,17 @@ pub(crate) async fn handle_cli_command(
                     .await?,
             )
         }
-        Opts::Receive => json(wallet.receive().await),
+        Opts::Receive { label: None } => json(wallet.receive().await),
+        Opts::Receive { label: Some(label) } => json(wallet.receive_with_label(label).await?),
+        Opts::ReceiveAddresses => json(wallet.receive_addresses().await),
+        Opts::GapLimit { gap_limit: None } => json(wallet.gap_limit().await),
+        Opts::GapLimit {
+            gap_limit: Some(gap_limit),
+        } => {
+            wallet.set_gap_limit(gap_limit).await?;
+
+            json(gap_limit)
+        }
         Opts::AwaitReceive { position } => json(wallet.await_receive(position).await?),
     };
 
@@ -8,6 +8,10 @@
 pub enum DbKeyPrefix {
     NextOutputIndex = 0x31,
     ValidAddressIndex = 0x32,
+    AddressLabel = 0x33,
+    UsedAddressIndex = 0x34,
+    ReceivedOutput = 0x35,
+    GapLimit = 0x36,
 }
 
 impl std::fmt::Display for DbKeyPrefix {
@@ -41,3 +45,62 @@ fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
     key = ValidAddressIndexKey,
     query_prefix = ValidAddressIndexPrefix
 );
+
+/// Label attached to a receive address handed out via
+/// [`crate::WalletClientModule::receive_with_label`].
+#[derive(Clone, Debug, Encodable, Decodable, Serialize)]
+pub struct AddressLabelKey(pub u64);
+
+impl_db_record!(
+    key = AddressLabelKey,
+    value = String,
+    db_prefix = DbKeyPrefix::AddressLabel
+);
+
+#[derive(Clone, Debug, Encodable, Decodable, Serialize)]
+pub struct AddressLabelPrefix;
+
+impl_db_lookup!(key = AddressLabelKey, query_prefix = AddressLabelPrefix);
+
+/// Address indices that have received at least one deposit.
+#[derive(Clone, Debug, Encodable, Decodable, Serialize)]
+pub struct UsedAddressIndexKey(pub u64);
+
+impl_db_record!(
+    key = UsedAddressIndexKey,
+    value = (),
+    db_prefix = DbKeyPrefix::UsedAddressIndex
+);
+
+#[derive(Clone, Debug, Encodable, Decodable, Serialize)]
+pub struct UsedAddressIndexPrefix;
+
+impl_db_lookup!(
+    key = UsedAddressIndexKey,
+    query_prefix = UsedAddressIndexPrefix
+);
+
+/// Maps the federation's output index of a deposit to the address index it
+/// was received on.
+#[derive(Clone, Debug, Encodable, Decodable, Serialize)]
+pub struct ReceivedOutputKey(pub u64);
+
+impl_db_record!(
+    key = ReceivedOutputKey,
+    value = u64,
+    db_prefix = DbKeyPrefix::ReceivedOutput
+);
+
+#[derive(Clone, Debug, Encodable, Decodable, Serialize)]
+pub struct ReceivedOutputPrefix;
+
+impl_db_lookup!(key = ReceivedOutputKey, query_prefix = ReceivedOutputPrefix);
+
+#[derive(Clone, Debug, Encodable, Decodable, Serialize)]
+pub struct GapLimitKey;
+
+impl_db_record!(
+    key = GapLimitKey,
+    value = u64,
+    db_prefix = DbKeyPrefix::GapLimit
+);
@@ -49,6 +49,15 @@ pub struct ReceivePaymentEvent {
     pub fee: bitcoin::Amount,
     pub address: Address<NetworkUnchecked>,
     pub outpoint: Option<bitcoin::OutPoint>,
+    /// Index of the receive address the deposit was sent to.
+    #[serde(default)]
+    pub address_index: Option<u64>,
+    /// Label of the receive address the deposit was sent to, if any.
+    #[serde(default)]
+    pub label: Option<String>,
+    /// The receive address had already received a previous deposit.
+    #[serde(default)]
+    pub address_reused: bool,
 }
 
 impl Event for ReceivePaymentEvent {
@@ -18,11 +18,14 @@
 use std::sync::Arc;
 use std::time::Duration;
 
-use anyhow::anyhow;
+use anyhow::{anyhow, ensure};
 use api::WalletFederationApi;
 use bitcoin::address::NetworkUnchecked;
 use bitcoin::{Address, ScriptBuf};
-use db::{NextOutputIndexKey, ValidAddressIndexKey, ValidAddressIndexPrefix};
+use db::{
+    AddressLabelKey, AddressLabelPrefix, GapLimitKey, NextOutputIndexKey, ReceivedOutputKey,
+    UsedAddressIndexKey, UsedAddressIndexPrefix, ValidAddressIndexKey, ValidAddressIndexPrefix,
+};
 use events::{ReceivePaymentEvent, SendPaymentEvent};
 use fedimint_api_client::api::{DynModuleApi, FederationResult};
 use fedimint_client::DynGlobalClientContext;
@@ -51,8 +54,9 @@
 use fedimint_logging::LOG_CLIENT_MODULE_WALLETV2;
 use fedimint_walletv2_common::config::WalletClientConfig;
 use fedimint_walletv2_common::{
-    KIND, OutputInfo, StandardScript, TxInfo, WalletCommonInit, WalletInput, WalletInputV0,
-    WalletModuleTypes, WalletOutput, WalletOutputV0, descriptor, is_potential_receive,
+    FeePriority, KIND, OutputInfo, StandardScript, TxInfo, WalletCommonInit, WalletInput,
+    WalletInputV0, WalletModuleTypes, WalletOutput, WalletOutputV0, WatchOnlyExport, descriptor,
+    is_potential_receive,
 };
 use futures::StreamExt;
 use receive_sm::{ReceiveSMCommon, ReceiveSMState, ReceiveStateMachine};
@@ -69,6 +73,11 @@
 /// Number of event log entries to read per batch.
 const EVENT_LOG_PAGE_SIZE: u64 = 1000;
 
+/// Number of valid receive addresses beyond the highest address that has
+/// received a deposit the client watches by default. Since only ~1/65536
+/// indices are valid, every additional address costs considerable CPU time.
+pub const DEFAULT_GAP_LIMIT: u64 = 1;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum WalletOperationMeta {
     Send(SendMeta),
@@ -92,6 +101,20 @@ pub struct ReceiveMeta {
     pub fee: bitcoin::Amount,
     pub address: Option<Address<NetworkUnchecked>>,
     pub outpoint: Option<bitcoin::OutPoint>,
+    #[serde(default)]
+    pub address_index: Option<u64>,
+    #[serde(default)]
+    pub label: Option<String>,
+}
+
+/// A receive address derived by the client.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReceiveAddress {
+    pub index: u64,
+    pub address: Address<NetworkUnchecked>,
+    pub label: Option<String>,
+    /// The address has received at least one deposit.
+    pub used: bool,
 }
 
 /// The final state of an operation sending bitcoin onchain.
@@ -122,6 +145,7 @@ pub struct WalletClientModule {
     client_ctx: ClientContext<Self>,
     db: Database,
     module_api: DynModuleApi,
+    task_handle: TaskHandle,
 }
 
 #[derive(Debug, Clone)]
@@ -208,6 +232,7 @@ async fn init(&self, args: &ClientModuleInitArgs<Self>) -> anyhow::Result<Self::
             client_ctx: args.context(),
             db: args.db().clone(),
             module_api: args.module_api().clone(),
+            task_handle: args.task_group().make_handle(),
         };
 
         module.spawn_output_scanner(args.task_group(), args.client_span());
@@ -258,6 +283,12 @@ pub async fn tx_chain(&self) -> FederationResult<Vec<TxInfo>> {
         self.module_api.tx_chain().await
     }
 
+    /// Fetch the output descriptors of all bitcoin controlled by the federation
+    /// for import into a watch-only wallet such as Bitcoin Core or BDK.
+    pub async fn watch_only_export(&self) -> FederationResult<WatchOnlyExport> {
+        self.module_api.watch_only_export().await
+    }
+
     /// Fetch the current fee required to send an onchain payment.
     pub async fn send_fee(&self) -> Result<bitcoin::Amount, SendError> {
         self.module_api
@@ -267,6 +298,54 @@ pub async fn send_fee(&self) -> Result<bitcoin::Amount, SendError> {
             .ok_or(SendError::NoConsensusFeerateAvailable)
     }
 
+    /// Fetch the current fee required to send an onchain payment that should
+    /// confirm within the target of the given priority.
+    pub async fn send_fee_for_priority(
+        &self,
+        priority: FeePriority,
+    ) -> Result<bitcoin::Amount, SendError> {
+        // The fee of the highest priority is the send fee of federations that
+        // predate fee priorities
+        if priority == FeePriority::NextBlock {
+            return self.send_fee().await;
+        }
+
+        self.module_api
+            .send_fee_for_priority(priority)
+            .await
+            .map_err(|e| SendError::FederationError(e.to_string()))?
+            .ok_or(SendError::NoConsensusFeerateAvailable)
+    }
+
+    /// Fetch the current fee for every priority such that the user can trade
+    /// off confirmation time against cost before sending. Federations that
+    /// predate fee priorities only offer the fee of the highest priority.
+    pub async fn send_fee_options(
+        &self,
+    ) -> Result<BTreeMap<FeePriority, bitcoin::Amount>, SendError> {
+        let mut options = BTreeMap::new();
+
+        for priority in FeePriority::ALL {
+            match self.send_fee_for_priority(priority).await {
+                Ok(fee) => {
+                    options.insert(priority, fee);
+                }
+                Err(SendError::FederationError(error)) if priority != FeePriority::NextBlock => {
+                    debug!(
+                        target: LOG_CLIENT_MODULE_WALLETV2,
+                        %error,
+                        "Federation does not offer fee priorities"
+                    );
+
+                    break;
+                }
+                Err(error) => return Err(error),
+            }
+        }
+
+        Ok(options)
+    }
+
     /// Computes the federation fee an onchain send of an output worth `amount`
     /// (the payment amount plus the on-chain miner fee) would incur, without
     /// submitting anything.
@@ -550,8 +629,8 @@ pub async fn await_final_receive_operation_state(
         Ok(final_state.expect("Stream contains one final state"))
     }
 
-    /// Returns the highest valid receive address index that the background
-    /// scanner has derived so far, or `None` if it has not derived one yet.
+    /// Returns the highest valid receive address index that has been derived
+    /// so far, or `None` if none has been derived yet.
     async fn valid_index(&self) -> Option<u64> {
         self.db
             .begin_transaction_nc()
@@ -574,17 +653,144 @@ async fn valid_index(&self) -> Option<u64> {
     /// If the background scanner has already derived a valid address index this
     /// returns immediately. Otherwise it blocks, letting the scanner grind
     /// until it finds the next valid index, and returns once one is
-    /// available.
+    /// available. Addresses handed out via [`Self::receive_with_label`] are
+    /// never returned.
     pub async fn receive(&self) -> Address {
         loop {
-            if let Some(index) = self.valid_index().await {
-                return self.derive_address(index);
+            if self.valid_index().await.is_some() {
+                if let Some(index) = self.unissued_index().await {
+                    return self.derive_address(index);
+                }
+
+                if let Some(index) = self.allocate_index(None).await {
+                    return self.derive_address(index);
+                }
             }
 
             sleep(Duration::from_secs(1)).await;
         }
     }
 
+    /// Derives a fresh receive address that has not been handed out before and
+    /// attaches the label to it. Deposits to the address are attributed to the
+    /// label in the operation log and the [`ReceivePaymentEvent`].
+    ///
+    /// This is CPU-bound, as only ~1/65536 address indices are valid.
+    pub async fn receive_with_label(&self, label: String) -> anyhow::Result<Address> {
+        let index = self
+            .allocate_index(Some(label))
+            .await
+            .ok_or(anyhow!("Client is shutting down"))?;
+
+        Ok(self.derive_address(index))
+    }
+
+    /// Lists all receive addresses derived by the client.
+    pub async fn receive_addresses(&self) -> Vec<ReceiveAddress> {
+        let mut dbtx = self.db.begin_transaction_nc().await;
+
+        let labels: BTreeMap<u64, String> = dbtx
+            .find_by_prefix(&AddressLabelPrefix)
+            .await
+            .map(|(key, label)| (key.0, label))
+            .collect()
+            .await;
+
+        let used: BTreeSet<u64> = dbtx
+            .find_by_prefix(&UsedAddressIndexPrefix)
+            .await
+            .map(|(key, ())| key.0)
+            .collect()
+            .await;
+
+        dbtx.find_by_prefix(&ValidAddressIndexPrefix)
+            .await
+            .map(|(key, ())| ReceiveAddress {
+                index: key.0,
+                address: self.derive_address(key.0).as_unchecked().clone(),
+                label: labels.get(&key.0).cloned(),
+                used: used.contains(&key.0),
+            })
+            .collect()
+            .await
+    }
+
+    /// Returns the number of unused receive addresses the client watches
+    /// beyond the highest address that has received a deposit.
+    pub async fn gap_limit(&self) -> u64 {
+        self.db
+            .begin_transaction_nc()
+            .await
+            .get_value(&GapLimitKey)
+            .await
+            .unwrap_or(DEFAULT_GAP_LIMIT)
+    }
+
+    /// Sets the gap limit, see [`Self::gap_limit`]. When recovering from a
+    /// seed, set this to at least the number of consecutive addresses that may
+    /// have been handed out without receiving a deposit. Raising the gap limit
+    /// rescans all deposits the federation has seen for the new addresses.
+    pub async fn set_gap_limit(&self, gap_limit: u64) -> anyhow::Result<()> {
+        ensure!(gap_limit != 0, "The gap limit has to be at least one");
+
+        let mut dbtx = self.db.begin_transaction().await;
+
+        let previous = dbtx
+            .insert_entry(&GapLimitKey, &gap_limit)
+            .await
+            .unwrap_or(DEFAULT_GAP_LIMIT);
+
+        if previous < gap_limit {
+            dbtx.remove_entry(&NextOutputIndexKey).await;
+        }
+
+        dbtx.commit_tx_result().await
+    }
+
+    /// Returns the highest valid address index that has neither been labeled
+    /// nor received a deposit.
+    async fn unissued_index(&self) -> Option<u64> {
+        self.receive_addresses()
+            .await
+            .into_iter()
+            .rev()
+            .find(|address| address.label.is_none() && !address.used)
+            .map(|address| address.index)
+    }
+
+    /// Derives the next valid address index beyond all indices derived so far
+    /// and attaches the label to it. Returns `None` if the client is shutting
+    /// down.
+    async fn allocate_index(&self, label: Option<String>) -> Option<u64> {
+        loop {
+            let start_index = self.valid_index().await.map_or(0, |index| index + 1);
+
+            let index = self
+                .next_valid_index(start_index, &self.task_handle)
+                .await?;
+
+            let mut dbtx = self.db.begin_transaction().await;
+
+            // The background scanner may have derived the same index
+            // concurrently, in which case it could already have been handed out.
+            if dbtx
+                .insert_entry(&ValidAddressIndexKey(index), &())
+                .await
+                .is_some()
+            {
+                continue;
+            }
+
+            if let Some(label) = &label {
+                dbtx.insert_new_entry(&AddressLabelKey(index), label).await;
+            }
+
+            if dbtx.commit_tx_result().await.is_ok() {
+                return Some(index);
+            }
+        }
+    }
+
     /// Block until the next on-chain payment recorded at or after `position` is
     /// received and successfully claimed by the federation.
     ///
@@ -722,11 +928,19 @@ async fn receive_output(
         output_index: u64,
         value: bitcoin::Amount,
         address_index: u64,
+        address_reused: bool,
         fee: bitcoin::Amount,
         outpoint: Option<bitcoin::OutPoint>,
     ) -> Option<(OperationId, TransactionId)> {
         let operation_id = OperationId::new_random();
 
+        let label = self
+            .db
+            .begin_transaction_nc()
+            .await
+            .get_value(&AddressLabelKey(address_index))
+            .await;
+
         let client_input = ClientInput::<WalletInput> {
             input: WalletInput::V0(WalletInputV0 {
                 output_index,
@@ -759,6 +973,7 @@ async fn receive_output(
         let address = self.derive_address(address_index).as_unchecked().clone();
 
         let meta_address = address.clone();
+        let meta_label = label.clone();
         let range = self
             .client_ctx
             .finalize_and_submit_transaction(
@@ -771,6 +986,8 @@ async fn receive_output(
                         fee,
                         address: Some(meta_address.clone()),
                         outpoint,
+                        address_index: Some(address_index),
+                        label: meta_label.clone(),
                     })
                 },
                 TransactionBuilder::new().with_inputs(client_input_bundle),
@@ -789,6 +1006,9 @@ async fn receive_output(
                     fee,
                     address,
                     outpoint,
+                    address_index: Some(address_index),
+                    label,
+                    address_reused,
                 },
             )
             .await;
@@ -803,25 +1023,6 @@ fn spawn_output_scanner(&self, task_group: &TaskGroup, client_span: &tracing::Sp
         let handle = task_group.make_handle();
 
         task_group.spawn_cancellable_with_span(client_span.clone(), "output-scanner", async move {
-            let mut dbtx = module.db.begin_transaction().await;
-
-            if dbtx
-                .find_by_prefix(&ValidAddressIndexPrefix)
-                .await
-                .next()
-                .await
-                .is_none()
-            {
-                let Some(index) = module.next_valid_index(0, &handle).await else {
-                    return;
-                };
-
-                dbtx.insert_new_entry(&ValidAddressIndexKey(index), &())
-                    .await;
-            }
-
-            dbtx.commit_tx().await;
-
             loop {
                 match module.check_outputs(&handle).await {
                     Ok(skip_wait) => {
@@ -840,22 +1041,21 @@ fn spawn_output_scanner(&self, task_group: &TaskGroup, client_span: &tracing::Sp
     }
 
     async fn check_outputs(&self, handle: &TaskHandle) -> anyhow::Result<bool> {
+        if self.fill_address_gap(handle).await?.is_none() {
+            return Ok(false);
+        }
+
         let mut dbtx = self.db.begin_transaction_nc().await;
 
         let next_output_index = dbtx.get_value(&NextOutputIndexKey).await.unwrap_or(0);
 
-        let mut valid_indices: Vec<u64> = dbtx
+        let mut address_map: BTreeMap<ScriptBuf, u64> = dbtx
             .find_by_prefix(&ValidAddressIndexPrefix)
             .await
-            .map(|entry| entry.0.0)
+            .map(|entry| (self.derive_address(entry.0.0).script_pubkey(), entry.0.0))
             .collect()
             .await;
 
-        let mut address_map: BTreeMap<ScriptBuf, u64> = valid_indices
-            .iter()
-            .map(|&i| (self.derive_address(i).script_pubkey(), i))
-            .collect();
-
         let outputs = self
             .module_api
             .output_info_slice(next_output_index, next_output_index + SLICE_SIZE)
@@ -868,34 +1068,26 @@ async fn check_outputs(&self, handle: &TaskHandle) -> anyhow::Result<bool> {
             if let Some(&address_index) = address_map.get(&output.script) {
                 matched_num += 1;
 
+                let address_reused = self.record_received_output(output, address_index).await?;
+
                 // Claim before extending the valid index list: the index search
                 // below is CPU-bound and can take longer than a short-lived
                 // client process (e.g. a cli invocation) lives. The claim is
                 // quick and the extension can be retried on the next scan.
-                if !output.spent && !self.process_unspent_output(output, address_index).await? {
+                if !output.spent
+                    && !self
+                        .process_unspent_output(output, address_index, address_reused)
+                        .await?
+                {
                     return Ok(false);
                 }
 
-                let next_address_index = valid_indices
-                    .last()
-                    .copied()
-                    .expect("we have at least one address index");
-
-                // If we used the highest valid index, add the next valid one
-                if address_index == next_address_index {
-                    let Some(index) = self.next_valid_index(next_address_index + 1, handle).await
-                    else {
-                        return Ok(false);
-                    };
-
-                    let mut dbtx = self.db.begin_transaction().await;
-
-                    dbtx.insert_entry(&ValidAddressIndexKey(index), &()).await;
-
-                    dbtx.commit_tx_result().await?;
-
-                    valid_indices.push(index);
+                // Keep watching gap limit many unused addresses beyond this one
+                let Some(new_indices) = self.fill_address_gap(handle).await? else {
+                    return Ok(false);
+                };
 
+                for index in new_indices {
                     address_map.insert(self.derive_address(index).script_pubkey(), index);
                 }
             }
@@ -913,17 +1105,106 @@ async fn check_outputs(&self, handle: &TaskHandle) -> anyhow::Result<bool> {
             next_output_index,
             returned_num,
             matched_num,
-            valid_indices_num = valid_indices.len(),
+            valid_indices_num = address_map.len(),
             "Scanning for outputs"
         );
 
         Ok(!outputs.is_empty())
     }
 
+    /// Derives valid address indices until at least gap limit many of them lie
+    /// beyond the highest index that has received a deposit. Returns the newly
+    /// derived indices, or `None` if the client is shutting down.
+    async fn fill_address_gap(&self, handle: &TaskHandle) -> anyhow::Result<Option<Vec<u64>>> {
+        let gap_limit = self.gap_limit().await;
+
+        let mut dbtx = self.db.begin_transaction_nc().await;
+
+        let highest_used_index = dbtx
+            .find_by_prefix_sorted_descending(&UsedAddressIndexPrefix)
+            .await
+            .next()
+            .await
+            .map(|entry| entry.0.0);
+
+        let valid_indices: Vec<u64> = dbtx
+            .find_by_prefix(&ValidAddressIndexPrefix)
+            .await
+            .map(|entry| entry.0.0)
+            .collect()
+            .await;
+
+        let mut unused_num = valid_indices
+            .iter()
+            .filter(|index| Some(**index) > highest_used_index)
+            .count() as u64;
+
+        let mut start_index = valid_indices.last().map_or(0, |index| index + 1);
+
+        let mut new_indices = vec![];
+
+        while unused_num < gap_limit {
+            let Some(index) = self.next_valid_index(start_index, handle).await else {
+                return Ok(None);
+            };
+
+            let mut dbtx = self.db.begin_transaction().await;
+
+            dbtx.insert_entry(&ValidAddressIndexKey(index), &()).await;
+
+            dbtx.commit_tx_result().await?;
+
+            new_indices.push(index);
+
+            unused_num += 1;
+
+            start_index = index + 1;
+        }
+
+        Ok(Some(new_indices))
+    }
+
+    /// Records which address index a deposit was received on and returns
+    /// whether that address has already received a different deposit.
+    async fn record_received_output(
+        &self,
+        output: &OutputInfo,
+        address_index: u64,
+    ) -> anyhow::Result<bool> {
+        let mut dbtx = self.db.begin_transaction().await;
+
+        // A deposit that is recorded again does not count as a reuse of its address
+        let recorded_before = dbtx
+            .insert_entry(&ReceivedOutputKey(output.index), &address_index)
+            .await
+            .is_some();
+
+        let address_used_before = dbtx
+            .insert_entry(&UsedAddressIndexKey(address_index), &())
+            .await
+            .is_some();
+
+        let address_reused = address_used_before && !recorded_before;
+
+        dbtx.commit_tx_result().await?;
+
+        if address_reused {
+            warn!(
+                target: LOG_CLIENT_MODULE_WALLETV2,
+                output_index = output.index,
+                address_index,
+                "Received a deposit on a receive address that has been used before"
+            );
+        }
+
+        Ok(address_reused)
+    }
+
     async fn process_unspent_output(
         &self,
         output: &OutputInfo,
         address_index: u64,
+        address_reused: bool,
     ) -> anyhow::Result<bool> {
         debug!(
             target: LOG_CLIENT_MODULE_WALLETV2,
@@ -958,6 +1239,7 @@ async fn process_unspent_output(
                 output.index,
                 output.value,
                 address_index,
+                address_reused,
                 receive_fee,
                 output.outpoint,
             )
@@ -26,7 +26,10 @@ pub struct WalletConfig {
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct WalletConfigPrivate {
-    pub bitcoin_sk: SecretKey,
+    /// Our key for the bitcoin multisig. Guardians signing through an external
+    /// signer can remove it from the config once the signer holds it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bitcoin_sk: Option<SecretKey>,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize, Encodable, Decodable)]
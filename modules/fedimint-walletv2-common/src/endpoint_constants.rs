@@ -3,7 +3,9 @@
 pub const FEDERATION_WALLET_ENDPOINT: &str = "federation_wallet";
 pub const RECEIVE_FEE_ENDPOINT: &str = "receive_fee";
 pub const SEND_FEE_ENDPOINT: &str = "send_fee";
+pub const SEND_FEE_FOR_PRIORITY_ENDPOINT: &str = "send_fee_for_priority";
 pub const TRANSACTION_ID_ENDPOINT: &str = "transaction_id";
 pub const OUTPUT_INFO_SLICE_ENDPOINT: &str = "output_info_slice";
 pub const PENDING_TRANSACTION_CHAIN_ENDPOINT: &str = "pending_transaction_chain";
 pub const TRANSACTION_CHAIN_ENDPOINT: &str = "transaction_chain";
+pub const WATCH_ONLY_EXPORT_ENDPOINT: &str = "watch_only_export";
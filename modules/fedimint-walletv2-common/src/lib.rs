@@ -18,6 +18,7 @@
 use fedimint_core::{
     NumPeersExt, PeerId, extensible_associated_module_type, plugin_types_trait_impl_common,
 };
+use miniscript::Descriptor;
 use miniscript::descriptor::Wsh;
 use secp256k1::ecdsa::Signature;
 use secp256k1::{PublicKey, Scalar, XOnlyPublicKey};
@@ -29,7 +30,17 @@
 
 pub const KIND: ModuleKind = ModuleKind::from_static_str("walletv2");
 
-pub const MODULE_CONSENSUS_VERSION: ModuleConsensusVersion = ModuleConsensusVersion::new(1, 0);
+pub const MODULE_CONSENSUS_VERSION: ModuleConsensusVersion = ModuleConsensusVersion::new(1, 1);
+
+/// The module consensus version of a federation that has not voted on any
+/// version yet.
+pub const INITIAL_MODULE_CONSENSUS_VERSION: ModuleConsensusVersion =
+    ModuleConsensusVersion::new(1, 0);
+
+/// From this module consensus version on, the guardians vote on a feerate per
+/// [`FeePriority`] and a send is accepted if it pays the fee of any priority.
+pub const FEE_PRIORITY_MODULE_CONSENSUS_VERSION: ModuleConsensusVersion =
+    ModuleConsensusVersion::new(1, 1);
 
 /// Returns a sleep duration of 1 second in test environments or 60 seconds in
 /// production. Used for polling intervals where faster feedback is needed
@@ -52,6 +63,12 @@ pub fn descriptor(pks: &BTreeMap<PeerId, PublicKey>, tweak: &sha256::Hash) -> Ws
     .expect("Failed to construct Descriptor")
 }
 
+/// Returns the output descriptor of a federation controlled UTXO in the format
+/// accepted by Bitcoin Core's `importdescriptors` and BDK, including checksum.
+pub fn watch_only_descriptor(pks: &BTreeMap<PeerId, PublicKey>, tweak: &sha256::Hash) -> String {
+    Descriptor::Wsh(descriptor(pks, tweak)).to_string()
+}
+
 pub fn tweak_public_key(pk: &PublicKey, tweak: &sha256::Hash) -> PublicKey {
     pk.add_exp_tweak(
         secp256k1::SECP256K1,
@@ -71,6 +88,73 @@ pub fn is_potential_receive(script_pubkey: &ScriptBuf, pks_hash: &sha256::Hash)
         .all(|b| *b == 0)
 }
 
+/// How fast an onchain payment should confirm. Every priority has its own
+/// consensus feerate, allowing users to trade speed for cost.
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Eq,
+    PartialEq,
+    PartialOrd,
+    Ord,
+    Hash,
+    Serialize,
+    Deserialize,
+    Encodable,
+    Decodable,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum FeePriority {
+    /// Confirm in the next block. The consensus feerate for this priority is
+    /// voted on via [`WalletConsensusItem::Feerate`].
+    NextBlock,
+    /// Confirm within six blocks.
+    SixBlocks,
+    /// Confirm within a day.
+    Economy,
+}
+
+impl FeePriority {
+    pub const ALL: [FeePriority; 3] = [
+        FeePriority::NextBlock,
+        FeePriority::SixBlocks,
+        FeePriority::Economy,
+    ];
+
+    /// The number of blocks the payment should confirm within.
+    pub fn confirmation_target(self) -> u16 {
+        match self {
+            FeePriority::NextBlock => 1,
+            FeePriority::SixBlocks => 6,
+            FeePriority::Economy => 144,
+        }
+    }
+}
+
+impl std::fmt::Display for FeePriority {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FeePriority::NextBlock => write!(f, "next-block"),
+            FeePriority::SixBlocks => write!(f, "six-blocks"),
+            FeePriority::Economy => write!(f, "economy"),
+        }
+    }
+}
+
+impl std::str::FromStr for FeePriority {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        FeePriority::ALL
+            .into_iter()
+            .find(|priority| priority.to_string() == s)
+            .ok_or(anyhow::anyhow!(
+                "Unknown fee priority, expected next-block, six-blocks or economy"
+            ))
+    }
+}
+
 #[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize, Encodable, Decodable)]
 pub struct FederationWallet {
     pub value: bitcoin::Amount,
@@ -78,6 +162,37 @@ pub struct FederationWallet {
     pub tweak: sha256::Hash,
 }
 
+/// Everything an external auditor needs to watch the federation's on-chain
+/// holdings with a watch-only wallet and reconcile them against the audit.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct WatchOnlyExport {
+    pub network: bitcoin::Network,
+    /// The untweaked public keys of the guardians' bitcoin multisig.
+    pub bitcoin_pks: BTreeMap<PeerId, PublicKey>,
+    /// Number of signatures required to spend a federation UTXO.
+    pub threshold: usize,
+    /// The federation controlled UTXOs, starting with the federation wallet.
+    pub utxos: Vec<WatchOnlyUtxo>,
+}
+
+/// A federation controlled UTXO together with its tweaked descriptor.
+///
+/// Every public key in the descriptor is the guardian's key tweaked by adding
+/// `tweak * G`. For a peg-in the tweak is the consensus hash of the client's
+/// tweak public key, for the federation's change output it is the consensus
+/// hash of the previous [`FederationWallet`].
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct WatchOnlyUtxo {
+    pub descriptor: String,
+    pub tweak: sha256::Hash,
+    pub outpoint: bitcoin::OutPoint,
+    pub value: bitcoin::Amount,
+    /// The pending federation transaction spending this UTXO, if any. Its value
+    /// is not part of the audit anymore, as it has been consolidated into the
+    /// federation wallet.
+    pub pending_spend: Option<Txid>,
+}
+
 #[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize, Encodable, Decodable)]
 pub struct TxInfo {
     pub index: u64,
@@ -137,6 +252,10 @@ pub enum WalletConsensusItem {
     BlockCount(u64),
     Feerate(Option<u64>),
     Signatures(Txid, Vec<Signature>),
+    /// Feerate vote for a priority other than [`FeePriority::NextBlock`].
+    PriorityFeerate(FeePriority, Option<u64>),
+    /// Vote for the highest module consensus version this guardian supports.
+    ModuleConsensusVersion(ModuleConsensusVersion),
     #[encodable_default]
     Default {
         variant: u64,
@@ -156,6 +275,12 @@ fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
             WalletConsensusItem::Signatures(..) => {
                 write!(f, "Wallet Signatures")
             }
+            WalletConsensusItem::PriorityFeerate(priority, feerate) => {
+                write!(f, "Wallet Feerate Vote {feerate:?} for {priority}")
+            }
+            WalletConsensusItem::ModuleConsensusVersion(version) => {
+                write!(f, "Wallet Consensus Version Vote {version}")
+            }
             WalletConsensusItem::Default { variant, .. } => {
                 write!(f, "Unknown Wallet CI variant={variant}")
             }
@@ -369,3 +494,21 @@ fn test_standard_script_unknown_witness_version() {
 
     assert!(StandardScript::from_address(&address).is_none());
 }
+
+#[test]
+fn test_fee_priority_roundtrip() {
+    for priority in FeePriority::ALL {
+        assert_eq!(
+            priority.to_string().parse::<FeePriority>().ok(),
+            Some(priority)
+        );
+
+        assert_eq!(
+            serde_json::to_value(priority).expect("Priority serializes"),
+            serde_json::Value::String(priority.to_string()),
+            "serde and Display names of a priority match"
+        );
+    }
+
+    assert!("fast".parse::<FeePriority>().is_err());
+}
@@ -0,0 +1,44 @@
+//! Stand-in for the external signer of a guardian's peg-out signatures.
+//!
+//! Usage: `walletv2-stand-in-signer <signer-dir> <bitcoin-secret-key-file>`
+//!
+//! Polls the directory configured via `FM_WALLETV2_EXTERNAL_SIGNER_DIR` and
+//! signs every PSBT the walletv2 module drops there. The key file holds the
+//! hex encoded secret key, such that it does not show up in the process list.
+//! On a real deployment this process would run on an air-gapped device
+//! holding the guardian's key.
+
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+
+use anyhow::Context;
+use fedimint_walletv2_server::external_signer::DirectorySigner;
+use secp256k1::SecretKey;
+
+fn main() -> anyhow::Result<()> {
+    let mut args = std::env::args().skip(1);
+
+    let dir = PathBuf::from(args.next().context("Missing signer directory argument")?);
+
+    let key_file = PathBuf::from(args.next().context("Missing secret key file argument")?);
+
+    let sk = SecretKey::from_str(
+        std::fs::read_to_string(&key_file)
+            .context("Failed to read secret key file")?
+            .trim(),
+    )
+    .context("Invalid secret key")?;
+
+    let signer = DirectorySigner::new(dir)?;
+
+    loop {
+        let signed = signer.sign_pending(&[sk])?;
+
+        if signed != 0 {
+            println!("Signed {signed} PSBTs");
+        }
+
+        std::thread::sleep(Duration::from_secs(1));
+    }
+}
@@ -1,7 +1,8 @@
 use bitcoin::{TxOut, Txid};
 use fedimint_core::encoding::{Decodable, Encodable};
+use fedimint_core::module::ModuleConsensusVersion;
 use fedimint_core::{PeerId, impl_db_lookup, impl_db_record};
-use fedimint_walletv2_common::TxInfo;
+use fedimint_walletv2_common::{FeePriority, TxInfo};
 use secp256k1::ecdsa::Signature;
 use serde::Serialize;
 use strum_macros::EnumIter;
@@ -21,6 +22,8 @@ pub enum DbKeyPrefix {
     Signatures = 0x37,
     UnconfirmedTx = 0x38,
     FederationWallet = 0x39,
+    PriorityFeeRateVote = 0x3a,
+    ConsensusVersionVote = 0x3b,
 }
 
 impl std::fmt::Display for DbKeyPrefix {
@@ -179,3 +182,45 @@ fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
 );
 
 impl_db_lookup!(key = FeeRateVoteKey, query_prefix = FeeRateVotePrefix);
+
+#[derive(Clone, Debug, Encodable, Decodable, Serialize)]
+pub struct PriorityFeeRateVoteKey(pub FeePriority, pub PeerId);
+
+#[derive(Clone, Debug, Encodable, Decodable)]
+pub struct PriorityFeeRateVotePrefix;
+
+#[derive(Clone, Debug, Encodable, Decodable)]
+pub struct PriorityFeeRateVotePriorityPrefix(pub FeePriority);
+
+impl_db_record!(
+    key = PriorityFeeRateVoteKey,
+    value = Option<u64>,
+    db_prefix = DbKeyPrefix::PriorityFeeRateVote
+);
+
+impl_db_lookup!(
+    key = PriorityFeeRateVoteKey,
+    query_prefix = PriorityFeeRateVotePrefix
+);
+
+impl_db_lookup!(
+    key = PriorityFeeRateVoteKey,
+    query_prefix = PriorityFeeRateVotePriorityPrefix
+);
+
+#[derive(Clone, Debug, Encodable, Decodable, Serialize)]
+pub struct ConsensusVersionVoteKey(pub PeerId);
+
+#[derive(Clone, Debug, Encodable, Decodable)]
+pub struct ConsensusVersionVotePrefix;
+
+impl_db_record!(
+    key = ConsensusVersionVoteKey,
+    value = ModuleConsensusVersion,
+    db_prefix = DbKeyPrefix::ConsensusVersionVote
+);
+
+impl_db_lookup!(
+    key = ConsensusVersionVoteKey,
+    query_prefix = ConsensusVersionVotePrefix
+);
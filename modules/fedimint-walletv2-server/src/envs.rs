@@ -0,0 +1,11 @@
+use std::path::PathBuf;
+
+/// Directory used to hand the guardian's share of peg-out signing to an
+/// external signer instead of signing with the hot key in the config.
+pub const FM_WALLETV2_EXTERNAL_SIGNER_DIR_ENV: &str = "FM_WALLETV2_EXTERNAL_SIGNER_DIR";
+
+pub fn get_external_signer_dir() -> Option<PathBuf> {
+    std::env::var_os(FM_WALLETV2_EXTERNAL_SIGNER_DIR_ENV)
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+}
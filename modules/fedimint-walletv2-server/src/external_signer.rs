@@ -0,0 +1,334 @@
+//! Directory drop interface to an external signer, such that a guardian can
+//! keep its share of the federation's bitcoin key on an air-gapped device.
+//!
+//! For every unsigned federation transaction the module writes a PSBT to
+//! `<dir>/unsigned/<txid>.psbt`. The signer adds its partial signatures and
+//! places the PSBT at `<dir>/signed/<txid>.psbt`, from where the module picks
+//! up the signatures and proposes them as [`WalletConsensusItem::Signatures`].
+//!
+//! Since the federation does not use BIP32, every input carries the tweak
+//! added to the guardian's key in a proprietary field, see
+//! [`tweak_proprietary_key`].
+//!
+//! Once the signer holds the guardian's key, `bitcoin_sk` can be removed from
+//! the module's private config such that the key is not kept on the server.
+//!
+//! [`WalletConsensusItem::Signatures`]: fedimint_walletv2_common::WalletConsensusItem::Signatures
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, ensure};
+use bitcoin::Txid;
+use bitcoin::hashes::{Hash, sha256};
+use bitcoin::psbt::Psbt;
+use bitcoin::psbt::raw::ProprietaryKey;
+use bitcoin::sighash::{EcdsaSighashType, SighashCache};
+use fedimint_core::util::FmtCompactAnyhow as _;
+use fedimint_logging::LOG_MODULE_WALLETV2;
+use fedimint_walletv2_common::tweak_public_key;
+use secp256k1::ecdsa::Signature;
+use secp256k1::{PublicKey, Scalar, SecretKey};
+use tracing::warn;
+
+const UNSIGNED_DIR: &str = "unsigned";
+const SIGNED_DIR: &str = "signed";
+
+/// The proprietary PSBT input field holding the tweak of the input's keys.
+pub fn tweak_proprietary_key() -> ProprietaryKey {
+    ProprietaryKey {
+        prefix: b"fedimint".to_vec(),
+        subtype: 0,
+        key: vec![],
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct DirectorySigner {
+    dir: PathBuf,
+}
+
+impl DirectorySigner {
+    pub fn new(dir: PathBuf) -> anyhow::Result<Self> {
+        fs::create_dir_all(dir.join(UNSIGNED_DIR))
+            .context("Failed to create unsigned PSBT directory")?;
+
+        fs::create_dir_all(dir.join(SIGNED_DIR))
+            .context("Failed to create signed PSBT directory")?;
+
+        Ok(Self { dir })
+    }
+
+    fn path(&self, subdir: &str, txid: Txid) -> PathBuf {
+        self.dir.join(subdir).join(format!("{txid}.psbt"))
+    }
+
+    /// Hands the PSBT to the signer unless we have done so already.
+    pub fn request_signatures(&self, psbt: &Psbt) -> anyhow::Result<()> {
+        let path = self.path(UNSIGNED_DIR, psbt.unsigned_tx.compute_txid());
+
+        if path.exists() {
+            return Ok(());
+        }
+
+        write_atomically(&path, &psbt.serialize())
+    }
+
+    /// Returns the PSBT returned by the signer, if it is available yet.
+    pub fn signed_psbt(&self, txid: Txid) -> anyhow::Result<Option<Psbt>> {
+        let path = self.path(SIGNED_DIR, txid);
+
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        let psbt = Psbt::deserialize(&fs::read(&path)?).context("Failed to parse signed PSBT")?;
+
+        ensure!(
+            psbt.unsigned_tx.compute_txid() == txid,
+            "Signed PSBT is for a different transaction"
+        );
+
+        Ok(Some(psbt))
+    }
+
+    /// Hands the PSBTs of all pending transactions to the signer, removes the
+    /// PSBTs of transactions that are not pending anymore and returns the
+    /// signed PSBTs that are available so far. Does blocking file I/O.
+    pub fn exchange(&self, psbts: &[Psbt]) -> BTreeMap<Txid, anyhow::Result<Option<Psbt>>> {
+        let pending = psbts
+            .iter()
+            .map(|psbt| psbt.unsigned_tx.compute_txid())
+            .collect::<Vec<Txid>>();
+
+        if let Err(err) = self.prune(&pending) {
+            warn!(
+                target: LOG_MODULE_WALLETV2,
+                err = %err.fmt_compact_anyhow(),
+                "Failed to prune PSBTs of the external signer"
+            );
+        }
+
+        psbts
+            .iter()
+            .map(|psbt| {
+                let txid = psbt.unsigned_tx.compute_txid();
+
+                let signed_psbt = self
+                    .request_signatures(psbt)
+                    .and_then(|()| self.signed_psbt(txid));
+
+                (txid, signed_psbt)
+            })
+            .collect()
+    }
+
+    /// Removes the PSBTs of all transactions that are not pending signatures
+    /// anymore.
+    pub fn prune(&self, pending: &[Txid]) -> anyhow::Result<()> {
+        for subdir in [UNSIGNED_DIR, SIGNED_DIR] {
+            for entry in fs::read_dir(self.dir.join(subdir))? {
+                let path = entry?.path();
+
+                let is_pending = path
+                    .file_stem()
+                    .and_then(|stem| stem.to_str())
+                    .and_then(|stem| stem.parse::<Txid>().ok())
+                    .is_some_and(|txid| pending.contains(&txid));
+
+                if !is_pending {
+                    fs::remove_file(path)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Signs all unsigned PSBTs in the directory that have not been signed
+    /// yet with every key in `sks`. This is a stand-in for the external signer
+    /// on the air-gapped device and returns the number of PSBTs signed.
+    pub fn sign_pending(&self, sks: &[SecretKey]) -> anyhow::Result<usize> {
+        let mut signed = 0;
+
+        for entry in fs::read_dir(self.dir.join(UNSIGNED_DIR))? {
+            let path = entry?.path();
+
+            let Some(file_name) = path.file_name() else {
+                continue;
+            };
+
+            let signed_path = self.dir.join(SIGNED_DIR).join(file_name);
+
+            if signed_path.exists() {
+                continue;
+            }
+
+            let mut psbt =
+                Psbt::deserialize(&fs::read(&path)?).context("Failed to parse unsigned PSBT")?;
+
+            for sk in sks {
+                sign_psbt(&mut psbt, sk)?;
+            }
+
+            write_atomically(&signed_path, &psbt.serialize())?;
+
+            signed += 1;
+        }
+
+        Ok(signed)
+    }
+}
+
+fn write_atomically(path: &Path, bytes: &[u8]) -> anyhow::Result<()> {
+    let tmp_path = path.with_extension("tmp");
+
+    fs::write(&tmp_path, bytes)?;
+
+    fs::rename(tmp_path, path)?;
+
+    Ok(())
+}
+
+fn input_tweak(psbt: &Psbt, index: usize) -> anyhow::Result<sha256::Hash> {
+    let tweak = psbt.inputs[index]
+        .proprietary
+        .get(&tweak_proprietary_key())
+        .context("PSBT input is missing the tweak")?;
+
+    sha256::Hash::from_slice(tweak).context("PSBT input has an invalid tweak")
+}
+
+/// Signs every input of a federation PSBT with the tweaked secret key.
+pub fn sign_psbt(psbt: &mut Psbt, sk: &SecretKey) -> anyhow::Result<()> {
+    let mut sighash_cache = SighashCache::new(psbt.unsigned_tx.clone());
+
+    for index in 0..psbt.inputs.len() {
+        let tweak = input_tweak(psbt, index)?;
+
+        let input = &psbt.inputs[index];
+
+        let utxo = input
+            .witness_utxo
+            .as_ref()
+            .context("PSBT input is missing the witness utxo")?;
+
+        let witness_script = input
+            .witness_script
+            .as_ref()
+            .context("PSBT input is missing the witness script")?;
+
+        let p2wsh_sighash = sighash_cache.p2wsh_signature_hash(
+            index,
+            witness_script,
+            utxo.value,
+            EcdsaSighashType::All,
+        )?;
+
+        let sk = sk.add_tweak(
+            &Scalar::from_be_bytes(tweak.to_byte_array()).expect("Hash is within field order"),
+        )?;
+
+        let signature = secp256k1::SECP256K1.sign_ecdsa(&p2wsh_sighash.into(), &sk);
+
+        psbt.inputs[index].partial_sigs.insert(
+            bitcoin::PublicKey::new(sk.public_key(secp256k1::SECP256K1)),
+            bitcoin::ecdsa::Signature::sighash_all(signature),
+        );
+    }
+
+    Ok(())
+}
+
+/// Extracts the signatures for the untweaked public key from a signed PSBT in
+/// input order. The signatures still need to be verified by the caller.
+pub fn partial_signatures(psbt: &Psbt, pk: &PublicKey) -> anyhow::Result<Vec<Signature>> {
+    (0..psbt.inputs.len())
+        .map(|index| {
+            let pk = tweak_public_key(pk, &input_tweak(psbt, index)?);
+
+            let signature = psbt.inputs[index]
+                .partial_sigs
+                .get(&bitcoin::PublicKey::new(pk))
+                .context("PSBT input is missing our signature")?;
+
+            ensure!(
+                signature.sighash_type == EcdsaSighashType::All,
+                "Signature has the wrong sighash type"
+            );
+
+            Ok(signature.signature)
+        })
+        .collect()
+}
+
+#[test]
+fn test_sign_psbt_roundtrip() {
+    use std::collections::BTreeMap;
+
+    use bitcoin::absolute::LockTime;
+    use bitcoin::transaction::Version;
+    use bitcoin::{Amount, OutPoint, Sequence, Transaction, TxIn, TxOut, Witness};
+    use fedimint_core::PeerId;
+    use fedimint_walletv2_common::descriptor;
+
+    let (sk, pk) = secp256k1::generate_keypair(&mut secp256k1::rand::thread_rng());
+
+    let tweak = sha256::Hash::hash(b"tweak");
+
+    let descriptor = descriptor(&BTreeMap::from([(PeerId::from(0), pk)]), &tweak);
+
+    let tx = Transaction {
+        version: Version(2),
+        lock_time: LockTime::ZERO,
+        input: vec![TxIn {
+            previous_output: OutPoint::null(),
+            script_sig: bitcoin::ScriptBuf::new(),
+            sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+            witness: Witness::new(),
+        }],
+        output: vec![TxOut {
+            value: Amount::from_sat(90_000),
+            script_pubkey: descriptor.script_pubkey(),
+        }],
+    };
+
+    let mut psbt = Psbt::from_unsigned_tx(tx).expect("Transaction is unsigned");
+
+    psbt.inputs[0].witness_utxo = Some(TxOut {
+        value: Amount::from_sat(100_000),
+        script_pubkey: descriptor.script_pubkey(),
+    });
+    psbt.inputs[0].witness_script = Some(descriptor.inner_script());
+    psbt.inputs[0]
+        .proprietary
+        .insert(tweak_proprietary_key(), tweak.to_byte_array().to_vec());
+
+    let psbt = Psbt::deserialize(&psbt.serialize()).expect("Failed to roundtrip PSBT");
+
+    let mut signed = psbt.clone();
+
+    sign_psbt(&mut signed, &sk).expect("Failed to sign PSBT");
+
+    let signatures = partial_signatures(&signed, &pk).expect("Failed to extract signatures");
+
+    let sighash = SighashCache::new(psbt.unsigned_tx.clone())
+        .p2wsh_signature_hash(
+            0,
+            &descriptor.inner_script(),
+            Amount::from_sat(100_000),
+            EcdsaSighashType::All,
+        )
+        .expect("Failed to compute sighash");
+
+    secp256k1::SECP256K1
+        .verify_ecdsa(
+            &sighash.into(),
+            &signatures[0],
+            &tweak_public_key(&pk, &tweak),
+        )
+        .expect("Signature is invalid");
+
+    assert!(partial_signatures(&psbt, &pk).is_err());
+}
@@ -11,12 +11,15 @@
 #![allow(clippy::too_many_lines)]
 
 pub mod db;
+pub mod envs;
+pub mod external_signer;
 
 use std::collections::{BTreeMap, BTreeSet};
 
 use anyhow::{Context, anyhow, bail, ensure};
 use bitcoin::absolute::LockTime;
 use bitcoin::hashes::{Hash, sha256};
+use bitcoin::psbt::Psbt;
 use bitcoin::secp256k1::Secp256k1;
 use bitcoin::sighash::{EcdsaSighashType, SighashCache};
 use bitcoin::transaction::Version;
@@ -51,7 +54,7 @@
 #[cfg(not(target_family = "wasm"))]
 use fedimint_core::task::TaskGroup;
 use fedimint_core::task::sleep;
-use fedimint_core::util::FmtCompactAnyhow as _;
+use fedimint_core::util::{FmtCompact as _, FmtCompactAnyhow as _};
 use fedimint_core::{
     InPoint, NumPeersExt, OutPoint, PeerId, apply, async_trait_maybe_send, push_db_pair_items, util,
 };
@@ -69,11 +72,14 @@
 use fedimint_walletv2_common::endpoint_constants::{
     CONSENSUS_BLOCK_COUNT_ENDPOINT, CONSENSUS_FEERATE_ENDPOINT, FEDERATION_WALLET_ENDPOINT,
     OUTPUT_INFO_SLICE_ENDPOINT, PENDING_TRANSACTION_CHAIN_ENDPOINT, RECEIVE_FEE_ENDPOINT,
-    SEND_FEE_ENDPOINT, TRANSACTION_CHAIN_ENDPOINT, TRANSACTION_ID_ENDPOINT,
+    SEND_FEE_ENDPOINT, SEND_FEE_FOR_PRIORITY_ENDPOINT, TRANSACTION_CHAIN_ENDPOINT,
+    TRANSACTION_ID_ENDPOINT, WATCH_ONLY_EXPORT_ENDPOINT,
 };
 use fedimint_walletv2_common::{
-    FederationWallet, MODULE_CONSENSUS_VERSION, TxInfo, WalletInputError, WalletOutputError,
-    descriptor, is_potential_receive, tweak_public_key,
+    FEE_PRIORITY_MODULE_CONSENSUS_VERSION, FederationWallet, FeePriority,
+    INITIAL_MODULE_CONSENSUS_VERSION, MODULE_CONSENSUS_VERSION, TxInfo, WalletInputError,
+    WalletOutputError, WatchOnlyExport, WatchOnlyUtxo, descriptor, is_potential_receive,
+    tweak_public_key, watch_only_descriptor,
 };
 use futures::StreamExt;
 use miniscript::descriptor::Wsh;
@@ -82,12 +88,15 @@
 use secp256k1::{PublicKey, Scalar, SecretKey};
 use serde::{Deserialize, Serialize};
 use strum::IntoEnumIterator;
-use tracing::{debug, info};
+use tracing::{debug, info, warn};
 
 use crate::db::{
-    BlockCountVoteKey, BlockCountVotePrefix, FeeRateVoteKey, FeeRateVotePrefix, TxInfoKey,
-    TxInfoPrefix, UnconfirmedTxKey, UnconfirmedTxPrefix, UnsignedTxKey, UnsignedTxPrefix,
+    BlockCountVoteKey, BlockCountVotePrefix, ConsensusVersionVoteKey, ConsensusVersionVotePrefix,
+    FeeRateVoteKey, FeeRateVotePrefix, PriorityFeeRateVoteKey, PriorityFeeRateVotePrefix,
+    PriorityFeeRateVotePriorityPrefix, TxInfoKey, TxInfoPrefix, UnconfirmedTxKey,
+    UnconfirmedTxPrefix, UnsignedTxKey, UnsignedTxPrefix,
 };
+use crate::external_signer::{DirectorySigner, partial_signatures, tweak_proprietary_key};
 
 /// Number of confirmations required for a transaction to be considered as
 /// final by the federation. The block that mines the transaction does
@@ -138,6 +147,41 @@ async fn pending_txs_unordered(dbtx: &mut DatabaseTransaction<'_>) -> Vec<Federa
     unsigned.into_iter().chain(unconfirmed).collect()
 }
 
+/// Every transaction spends the change of its predecessor, such that a slow
+/// transaction holds up the whole chain. Hence a lower priority than the next
+/// block is only available while no transaction is pending.
+fn available_priority(priority: FeePriority, pending_txs: usize) -> FeePriority {
+    if pending_txs == 0 {
+        priority
+    } else {
+        FeePriority::NextBlock
+    }
+}
+
+/// The fee of a transaction with `tx_vbytes` at `feerate`, or the fee that
+/// lifts the stack of pending transactions given as `(vbytes, fee)` to
+/// `feerate` if that is higher. The latter pays for a pending transaction of
+/// a lower priority.
+fn stack_fee(tx_vbytes: u64, feerate: u64, pending_txs: &[(u64, Amount)]) -> Amount {
+    let tx_fee = tx_vbytes.saturating_mul(feerate).saturating_div(1000);
+
+    let stack_vbytes = pending_txs
+        .iter()
+        .map(|(vbytes, _)| *vbytes)
+        .try_fold(tx_vbytes, u64::checked_add)
+        .expect("Stack vbytes overflow with at most 32 pending txs");
+
+    let stack_fee = stack_vbytes.saturating_mul(feerate).saturating_div(1000);
+
+    // Deduct the fees already paid by currently pending transactions
+    let stack_fee = pending_txs
+        .iter()
+        .map(|(_, fee)| fee.to_sat())
+        .fold(stack_fee, u64::saturating_sub);
+
+    Amount::from_sat(tx_fee.max(stack_fee))
+}
+
 #[derive(Debug, Clone)]
 pub struct WalletInit;
 
@@ -257,6 +301,26 @@ async fn dump_database(
                         "Federation Wallet"
                     );
                 }
+                DbKeyPrefix::PriorityFeeRateVote => {
+                    push_db_pair_items!(
+                        dbtx,
+                        PriorityFeeRateVotePrefix,
+                        PriorityFeeRateVoteKey,
+                        Option<u64>,
+                        wallet,
+                        "Wallet Priority Fee Rate Votes"
+                    );
+                }
+                DbKeyPrefix::ConsensusVersionVote => {
+                    push_db_pair_items!(
+                        dbtx,
+                        ConsensusVersionVotePrefix,
+                        ConsensusVersionVoteKey,
+                        ModuleConsensusVersion,
+                        wallet,
+                        "Wallet Consensus Version Votes"
+                    );
+                }
             }
         }
 
@@ -277,18 +341,45 @@ fn is_enabled_by_default(&self) -> bool {
     }
 
     fn get_documented_env_vars(&self) -> Vec<EnvVarDoc> {
-        vec![EnvVarDoc {
-            name: FM_ENABLE_MODULE_WALLETV2_ENV,
-            description: "Set to 0/false to disable the WalletV2 module. Enabled by default.",
-        }]
+        vec![
+            EnvVarDoc {
+                name: FM_ENABLE_MODULE_WALLETV2_ENV,
+                description: "Set to 0/false to disable the WalletV2 module. Enabled by default.",
+            },
+            EnvVarDoc {
+                name: envs::FM_WALLETV2_EXTERNAL_SIGNER_DIR_ENV,
+                description: "Directory through which PSBTs are exchanged with an external signer. If set, the guardian's bitcoin signatures are taken from the signer instead of the key in the config.",
+            },
+        ]
     }
 
     async fn init(&self, args: &ServerModuleInitArgs<Self>) -> anyhow::Result<Self::Module> {
+        let cfg: WalletConfig = args.cfg().to_typed()?;
+
+        let bitcoin_pk = *cfg
+            .consensus
+            .bitcoin_pks
+            .get(&args.our_peer_id())
+            .context("No public key for our identity")?;
+
+        let external_signer = envs::get_external_signer_dir()
+            .map(DirectorySigner::new)
+            .transpose()?;
+
+        ensure!(
+            cfg.private.bitcoin_sk.is_some() || external_signer.is_some(),
+            "The config holds no bitcoin secret key, set {} to sign through an external signer",
+            envs::FM_WALLETV2_EXTERNAL_SIGNER_DIR_ENV
+        );
+
         Ok(Wallet::new(
-            args.cfg().to_typed()?,
+            cfg,
+            args.our_peer_id(),
+            bitcoin_pk,
             args.db(),
             args.task_group(),
             args.server_bitcoin_rpc_monitor(),
+            external_signer,
         ))
     }
 
@@ -313,7 +404,9 @@ fn trusted_dealer_gen(
             .into_iter()
             .map(|(peer, bitcoin_sk)| {
                 let config = WalletConfig {
-                    private: WalletConfigPrivate { bitcoin_sk },
+                    private: WalletConfigPrivate {
+                        bitcoin_sk: Some(bitcoin_sk),
+                    },
                     consensus: WalletConfigConsensus::new(
                         bitcoin_pks.clone(),
                         fee_consensus.clone(),
@@ -342,7 +435,9 @@ async fn distributed_gen(
             .collect();
 
         let config = WalletConfig {
-            private: WalletConfigPrivate { bitcoin_sk },
+            private: WalletConfigPrivate {
+                bitcoin_sk: Some(bitcoin_sk),
+            },
             consensus: WalletConfigConsensus::new(bitcoin_pks, fee_consensus, args.network),
         };
 
@@ -352,15 +447,19 @@ async fn distributed_gen(
     fn validate_config(&self, identity: &PeerId, config: ServerModuleConfig) -> anyhow::Result<()> {
         let config = config.to_typed::<WalletConfig>()?;
 
-        ensure!(
-            config
-                .consensus
-                .bitcoin_pks
-                .get(identity)
-                .ok_or(anyhow::anyhow!("No public key for our identity"))?
-                == &config.private.bitcoin_sk.public_key(secp256k1::SECP256K1),
-            "Bitcoin wallet private key doesn't match multisig pubkey"
-        );
+        let bitcoin_pk = config
+            .consensus
+            .bitcoin_pks
+            .get(identity)
+            .ok_or(anyhow::anyhow!("No public key for our identity"))?;
+
+        // Without the secret key in the config the external signer holds it
+        if let Some(bitcoin_sk) = config.private.bitcoin_sk {
+            ensure!(
+                bitcoin_pk == &bitcoin_sk.public_key(secp256k1::SECP256K1),
+                "Bitcoin wallet private key doesn't match multisig pubkey"
+            );
+        }
 
         Ok(())
     }
@@ -403,23 +502,48 @@ async fn consensus_proposal<'a>(
         &'a self,
         dbtx: &mut DatabaseTransaction<'_>,
     ) -> Vec<WalletConsensusItem> {
-        let mut items = dbtx
+        let unsigned_txs = dbtx
             .find_by_prefix(&UnsignedTxPrefix)
             .await
-            .map(|(key, unsigned_tx)| {
-                let signatures = self.sign_tx(&unsigned_tx);
+            .map(|(key, unsigned_tx)| (key.0, unsigned_tx))
+            .collect::<Vec<(Txid, FederationTx)>>()
+            .await;
 
-                self.verify_signatures(
-                    &unsigned_tx,
-                    &signatures,
-                    self.cfg.private.bitcoin_sk.public_key(secp256k1::SECP256K1),
-                )
-                .expect("Our signatures failed verification against our private key");
+        let mut items = match (&self.external_signer, &self.cfg.private.bitcoin_sk) {
+            (Some(signer), _) => self.external_signatures(signer, &unsigned_txs).await,
+            (None, Some(bitcoin_sk)) => unsigned_txs
+                .into_iter()
+                .map(|(txid, unsigned_tx)| {
+                    let signatures = self.sign_tx(bitcoin_sk, &unsigned_tx);
 
-                WalletConsensusItem::Signatures(key.0, signatures)
-            })
-            .collect::<Vec<WalletConsensusItem>>()
-            .await;
+                    self.verify_signatures(&unsigned_tx, &signatures, self.bitcoin_pk)
+                        .expect("Our signatures failed verification against our private key");
+
+                    WalletConsensusItem::Signatures(txid, signatures)
+                })
+                .collect(),
+            (None, None) => unreachable!("Checked when the module is initialized"),
+        };
+
+        // A new version activates once a threshold of guardians voted for it,
+        // guardians still running the previous version discard the vote
+        if self
+            .module_consensus_version_vote(dbtx, self.our_peer_id)
+            .await
+            < MODULE_CONSENSUS_VERSION
+        {
+            items.push(WalletConsensusItem::ModuleConsensusVersion(
+                MODULE_CONSENSUS_VERSION,
+            ));
+        }
+
+        let priority_votes = if FEE_PRIORITY_MODULE_CONSENSUS_VERSION
+            <= self.consensus_module_consensus_version(dbtx).await
+        {
+            vec![FeePriority::SixBlocks, FeePriority::Economy]
+        } else {
+            vec![]
+        };
 
         if let Some(status) = self.btc_rpc.status() {
             assert_eq!(status.network, self.cfg.consensus.network);
@@ -449,9 +573,22 @@ async fn consensus_proposal<'a>(
                 .max(MIN_FEERATE_VOTE_SATS_PER_KVB);
 
             items.push(WalletConsensusItem::Feerate(Some(feerate_vote)));
+
+            for priority in priority_votes {
+                let feerate_vote = status
+                    .fee_rate_by_target
+                    .get(&priority.confirmation_target())
+                    .map(|fee_rate| fee_rate.sats_per_kvb.max(MIN_FEERATE_VOTE_SATS_PER_KVB));
+
+                items.push(WalletConsensusItem::PriorityFeerate(priority, feerate_vote));
+            }
         } else {
-            // Bitcoin backend not connected, retract fee rate vote
+            // Bitcoin backend not connected, retract fee rate votes
             items.push(WalletConsensusItem::Feerate(None));
+
+            for priority in priority_votes {
+                items.push(WalletConsensusItem::PriorityFeerate(priority, None));
+            }
         }
 
         items
@@ -477,6 +614,44 @@ async fn process_consensus_item<'a, 'b>(
             WalletConsensusItem::Signatures(txid, signatures) => {
                 self.process_signatures(dbtx, txid, signatures, peer).await
             }
+            WalletConsensusItem::PriorityFeerate(priority, feerate) => {
+                ensure!(
+                    FEE_PRIORITY_MODULE_CONSENSUS_VERSION
+                        <= self.consensus_module_consensus_version(dbtx).await,
+                    "Priority fee rates are not active in this consensus version"
+                );
+
+                ensure!(
+                    priority != FeePriority::NextBlock,
+                    "Next block fee rate is voted on via the Feerate item"
+                );
+
+                if Some(feerate)
+                    == dbtx
+                        .insert_entry(&PriorityFeeRateVoteKey(priority, peer), &feerate)
+                        .await
+                {
+                    return Err(anyhow!("Priority fee rate vote is redundant"));
+                }
+
+                Ok(())
+            }
+            WalletConsensusItem::ModuleConsensusVersion(module_consensus_version) => {
+                ensure!(
+                    self.module_consensus_version_vote(dbtx, peer).await < module_consensus_version,
+                    "Module consensus version vote is redundant"
+                );
+
+                dbtx.insert_entry(&ConsensusVersionVoteKey(peer), &module_consensus_version)
+                    .await;
+
+                assert!(
+                    self.consensus_module_consensus_version(dbtx).await <= MODULE_CONSENSUS_VERSION,
+                    "Wallet module does not support new consensus version, please upgrade the module"
+                );
+
+                Ok(())
+            }
             WalletConsensusItem::Default { variant, .. } => Err(anyhow!(
                 "Received wallet consensus item with unknown variant {variant}"
             )),
@@ -656,10 +831,14 @@ async fn process_output<'a, 'b>(
             .await
             .ok_or(WalletOutputError::NoFederationUTXO)?;
 
-        let consensus_send_fee = self
-            .send_fee(dbtx)
-            .await
-            .ok_or(WalletOutputError::NoConsensusFeerateAvailable)?;
+        let consensus_send_fee = if FEE_PRIORITY_MODULE_CONSENSUS_VERSION
+            <= self.consensus_module_consensus_version(dbtx).await
+        {
+            self.min_send_fee(dbtx).await
+        } else {
+            self.send_fee(dbtx).await
+        }
+        .ok_or(WalletOutputError::NoConsensusFeerateAvailable)?;
 
         // We allow for a higher fee such that a guardian could construct a CPFP
         // transaction. This is the last line of defense should the federations
@@ -831,6 +1010,15 @@ fn api_endpoints(&self) -> Vec<ApiEndpoint<Self>> {
                     Ok(module.send_fee(&mut dbtx).await)
                 }
             },
+            public_api_endpoint! {
+                SEND_FEE_FOR_PRIORITY_ENDPOINT,
+                ApiVersion::new(0, 0),
+                async |module: &Wallet, context, params: FeePriority| -> Option<Amount> {
+                    let db = context.db();
+                    let mut dbtx = db.begin_transaction_nc().await;
+                    Ok(module.send_fee_for_priority(&mut dbtx, params).await)
+                }
+            },
             public_api_endpoint! {
                 RECEIVE_FEE_ENDPOINT,
                 ApiVersion::new(0, 0),
@@ -876,6 +1064,15 @@ fn api_endpoints(&self) -> Vec<ApiEndpoint<Self>> {
                     Ok(module.tx_chain(&mut dbtx).await)
                 }
             },
+            public_api_endpoint! {
+                WATCH_ONLY_EXPORT_ENDPOINT,
+                ApiVersion::new(0, 0),
+                async |module: &Wallet, context, _params: ()| -> WatchOnlyExport {
+                    let db = context.db();
+                    let mut dbtx = db.begin_transaction_nc().await;
+                    Ok(module.watch_only_export(&mut dbtx).await)
+                }
+            },
         ]
     }
 
@@ -888,23 +1085,33 @@ fn supported_api_versions(&self) -> MultiApiVersion {
 #[derive(Debug)]
 pub struct Wallet {
     cfg: WalletConfig,
+    our_peer_id: PeerId,
+    /// Our public key for the bitcoin multisig.
+    bitcoin_pk: PublicKey,
     db: Database,
     btc_rpc: ServerBitcoinRpcMonitor,
+    external_signer: Option<DirectorySigner>,
 }
 
 impl Wallet {
     fn new(
         cfg: WalletConfig,
+        our_peer_id: PeerId,
+        bitcoin_pk: PublicKey,
         db: &Database,
         task_group: &TaskGroup,
         btc_rpc: ServerBitcoinRpcMonitor,
+        external_signer: Option<DirectorySigner>,
     ) -> Wallet {
         Self::spawn_broadcast_unconfirmed_txs_task(btc_rpc.clone(), db.clone(), task_group);
 
         Wallet {
             cfg,
+            our_peer_id,
+            bitcoin_pk,
             btc_rpc,
             db: db.clone(),
+            external_signer,
         }
     }
 
@@ -1168,6 +1375,41 @@ pub async fn consensus_block_count(&self, dbtx: &mut DatabaseTransaction<'_>) ->
         counts.get(num_peers.threshold() - 1).copied().unwrap_or(0)
     }
 
+    async fn module_consensus_version_vote(
+        &self,
+        dbtx: &mut DatabaseTransaction<'_>,
+        peer: PeerId,
+    ) -> ModuleConsensusVersion {
+        dbtx.get_value(&ConsensusVersionVoteKey(peer))
+            .await
+            .unwrap_or(INITIAL_MODULE_CONSENSUS_VERSION)
+    }
+
+    /// The highest module consensus version a threshold of guardians voted for.
+    async fn consensus_module_consensus_version(
+        &self,
+        dbtx: &mut DatabaseTransaction<'_>,
+    ) -> ModuleConsensusVersion {
+        let num_peers = self.cfg.consensus.bitcoin_pks.to_num_peers();
+
+        let mut versions = dbtx
+            .find_by_prefix(&ConsensusVersionVotePrefix)
+            .await
+            .map(|entry| entry.1)
+            .collect::<Vec<ModuleConsensusVersion>>()
+            .await;
+
+        while versions.len() < num_peers.total() {
+            versions.push(INITIAL_MODULE_CONSENSUS_VERSION);
+        }
+
+        assert_eq!(versions.len(), num_peers.total());
+
+        versions.sort_unstable();
+
+        versions[num_peers.max_evil()]
+    }
+
     pub async fn consensus_feerate(&self, dbtx: &mut DatabaseTransaction<'_>) -> Option<u64> {
         let num_peers = self.cfg.consensus.bitcoin_pks.to_num_peers();
 
@@ -1187,10 +1429,42 @@ pub async fn consensus_feerate(&self, dbtx: &mut DatabaseTransaction<'_>) -> Opt
         rates.get(num_peers.threshold() - 1).copied()
     }
 
+    /// Returns the consensus feerate for the given priority. Until a threshold
+    /// of peers has voted on a priority we fall back to the next block
+    /// feerate.
+    pub async fn consensus_priority_feerate(
+        &self,
+        dbtx: &mut DatabaseTransaction<'_>,
+        priority: FeePriority,
+    ) -> Option<u64> {
+        if priority == FeePriority::NextBlock {
+            return self.consensus_feerate(dbtx).await;
+        }
+
+        let num_peers = self.cfg.consensus.bitcoin_pks.to_num_peers();
+
+        let mut rates = dbtx
+            .find_by_prefix(&PriorityFeeRateVotePriorityPrefix(priority))
+            .await
+            .filter_map(|entry| async move { entry.1 })
+            .collect::<Vec<u64>>()
+            .await;
+
+        assert!(rates.len() <= num_peers.total());
+
+        rates.sort_unstable();
+
+        match rates.get(num_peers.threshold() - 1).copied() {
+            Some(feerate) => Some(feerate),
+            None => self.consensus_feerate(dbtx).await,
+        }
+    }
+
     pub async fn consensus_fee(
         &self,
         dbtx: &mut DatabaseTransaction<'_>,
         tx_vbytes: u64,
+        priority: FeePriority,
     ) -> Option<Amount> {
         // The minimum feerate is a protection against a catastrophic error in the
         // feerate estimation and limits the length of the pending transaction stack.
@@ -1200,44 +1474,58 @@ pub async fn consensus_fee(
         assert!(pending_txs.len() <= 32);
 
         let feerate = self
-            .consensus_feerate(dbtx)
+            .consensus_priority_feerate(dbtx, available_priority(priority, pending_txs.len()))
             .await?
             .max(self.cfg.consensus.feerate_base << pending_txs.len());
 
-        let tx_fee = tx_vbytes.saturating_mul(feerate).saturating_div(1000);
-
-        let stack_vbytes = pending_txs
+        let pending_txs = pending_txs
             .iter()
-            .map(|t| t.vbytes)
-            .try_fold(tx_vbytes, u64::checked_add)
-            .expect("Stack vbytes overflow with at most 32 pending txs");
+            .map(|t| (t.vbytes, t.fee))
+            .collect::<Vec<_>>();
 
-        let stack_fee = stack_vbytes.saturating_mul(feerate).saturating_div(1000);
-
-        // Deduct the fees already paid by currently pending transactions
-        let stack_fee = pending_txs
-            .iter()
-            .map(|t| t.fee.to_sat())
-            .fold(stack_fee, u64::saturating_sub);
-
-        Some(Amount::from_sat(tx_fee.max(stack_fee)))
+        Some(stack_fee(tx_vbytes, feerate, &pending_txs))
     }
 
     pub async fn send_fee(&self, dbtx: &mut DatabaseTransaction<'_>) -> Option<Amount> {
-        self.consensus_fee(dbtx, self.cfg.consensus.send_tx_vbytes)
+        self.send_fee_for_priority(dbtx, FeePriority::NextBlock)
             .await
     }
 
-    pub async fn receive_fee(&self, dbtx: &mut DatabaseTransaction<'_>) -> Option<Amount> {
-        self.consensus_fee(dbtx, self.cfg.consensus.receive_tx_vbytes)
+    pub async fn send_fee_for_priority(
+        &self,
+        dbtx: &mut DatabaseTransaction<'_>,
+        priority: FeePriority,
+    ) -> Option<Amount> {
+        self.consensus_fee(dbtx, self.cfg.consensus.send_tx_vbytes, priority)
             .await
     }
 
+    /// The lowest send fee over all priorities, which is the minimum fee a
+    /// send has to pay to be accepted.
+    async fn min_send_fee(&self, dbtx: &mut DatabaseTransaction<'_>) -> Option<Amount> {
+        let mut fees = vec![];
+
+        for priority in FeePriority::ALL {
+            fees.extend(self.send_fee_for_priority(dbtx, priority).await);
+        }
+
+        fees.into_iter().min()
+    }
+
+    pub async fn receive_fee(&self, dbtx: &mut DatabaseTransaction<'_>) -> Option<Amount> {
+        self.consensus_fee(
+            dbtx,
+            self.cfg.consensus.receive_tx_vbytes,
+            FeePriority::NextBlock,
+        )
+        .await
+    }
+
     fn descriptor(&self, tweak: &sha256::Hash) -> Wsh<secp256k1::PublicKey> {
         descriptor(&self.cfg.consensus.bitcoin_pks, tweak)
     }
 
-    fn sign_tx(&self, unsigned_tx: &FederationTx) -> Vec<Signature> {
+    fn sign_tx(&self, bitcoin_sk: &SecretKey, unsigned_tx: &FederationTx) -> Vec<Signature> {
         let mut sighash_cache = SighashCache::new(unsigned_tx.tx.clone());
 
         unsigned_tx
@@ -1254,10 +1542,7 @@ fn sign_tx(&self, unsigned_tx: &FederationTx) -> Vec<Signature> {
                 let scalar = &Scalar::from_be_bytes(utxo.tweak.to_byte_array())
                     .expect("Hash is within field order");
 
-                let sk = self
-                    .cfg
-                    .private
-                    .bitcoin_sk
+                let sk = bitcoin_sk
                     .add_tweak(scalar)
                     .expect("Failed to tweak bitcoin secret key");
 
@@ -1266,6 +1551,109 @@ fn sign_tx(&self, unsigned_tx: &FederationTx) -> Vec<Signature> {
             .collect()
     }
 
+    /// Creates a PSBT for an unsigned federation transaction which contains
+    /// everything the external signer needs to compute our signatures.
+    fn psbt(&self, unsigned_tx: &FederationTx) -> Psbt {
+        let mut psbt = Psbt::from_unsigned_tx(unsigned_tx.tx.clone())
+            .expect("Federation transaction is unsigned");
+
+        for (input, utxo) in psbt.inputs.iter_mut().zip(&unsigned_tx.spent_tx_outs) {
+            let descriptor = self.descriptor(&utxo.tweak);
+
+            input.witness_utxo = Some(TxOut {
+                value: utxo.value,
+                script_pubkey: descriptor.script_pubkey(),
+            });
+
+            input.witness_script = Some(descriptor.inner_script());
+
+            input.sighash_type = Some(EcdsaSighashType::All.into());
+
+            input
+                .proprietary
+                .insert(tweak_proprietary_key(), utxo.tweak.to_byte_array().to_vec());
+        }
+
+        psbt
+    }
+
+    /// Hands every unsigned transaction to the external signer and returns the
+    /// signatures it has produced so far. Invalid signatures are not proposed,
+    /// the signer has to overwrite the signed PSBT to recover.
+    async fn external_signatures(
+        &self,
+        signer: &DirectorySigner,
+        unsigned_txs: &[(Txid, FederationTx)],
+    ) -> Vec<WalletConsensusItem> {
+        let psbts = unsigned_txs
+            .iter()
+            .map(|(_, unsigned_tx)| self.psbt(unsigned_tx))
+            .collect::<Vec<Psbt>>();
+
+        // The signer directory may be on a slow or remote volume, which must
+        // not stall the consensus
+        let signer = signer.clone();
+        let mut signed_psbts =
+            match tokio::task::spawn_blocking(move || signer.exchange(&psbts)).await {
+                Ok(signed_psbts) => signed_psbts,
+                Err(err) => {
+                    warn!(
+                        target: LOG_MODULE_WALLETV2,
+                        err = %err.fmt_compact(),
+                        "Failed to exchange PSBTs with the external signer"
+                    );
+                    return vec![];
+                }
+            };
+
+        let mut items = vec![];
+
+        for (txid, unsigned_tx) in unsigned_txs {
+            let signed_psbt = signed_psbts
+                .remove(txid)
+                .expect("Every pending transaction was exchanged");
+
+            match self.external_signatures_for_tx(signed_psbt, unsigned_tx) {
+                Ok(Some(signatures)) => {
+                    items.push(WalletConsensusItem::Signatures(*txid, signatures));
+                }
+                Ok(None) => {
+                    debug!(
+                        target: LOG_MODULE_WALLETV2,
+                        %txid,
+                        "Waiting for the external signer"
+                    );
+                }
+                Err(err) => {
+                    warn!(
+                        target: LOG_MODULE_WALLETV2,
+                        %txid,
+                        err = %err.fmt_compact_anyhow(),
+                        "Failed to obtain signatures from the external signer"
+                    );
+                }
+            }
+        }
+
+        items
+    }
+
+    fn external_signatures_for_tx(
+        &self,
+        signed_psbt: anyhow::Result<Option<Psbt>>,
+        unsigned_tx: &FederationTx,
+    ) -> anyhow::Result<Option<Vec<Signature>>> {
+        let Some(psbt) = signed_psbt? else {
+            return Ok(None);
+        };
+
+        let signatures = partial_signatures(&psbt, &self.bitcoin_pk)?;
+
+        self.verify_signatures(unsigned_tx, &signatures, self.bitcoin_pk)?;
+
+        Ok(Some(signatures))
+    }
+
     fn verify_signatures(
         &self,
         unsigned_tx: &FederationTx,
@@ -1389,6 +1777,44 @@ async fn tx_chain(&self, dbtx: &mut DatabaseTransaction<'_>) -> Vec<TxInfo> {
             .await
     }
 
+    /// Collects the federation wallet UTXO and all UTXOs spent by pending
+    /// federation transactions, as the latter remain on chain until the
+    /// spending transaction confirms.
+    async fn watch_only_export(&self, dbtx: &mut DatabaseTransaction<'_>) -> WatchOnlyExport {
+        let mut utxos = Vec::new();
+
+        if let Some(wallet) = dbtx.get_value(&FederationWalletKey).await {
+            utxos.push(WatchOnlyUtxo {
+                descriptor: watch_only_descriptor(&self.cfg.consensus.bitcoin_pks, &wallet.tweak),
+                tweak: wallet.tweak,
+                outpoint: wallet.outpoint,
+                value: wallet.value,
+                pending_spend: None,
+            });
+        }
+
+        for pending_tx in pending_txs_unordered(dbtx).await {
+            let txid = pending_tx.tx.compute_txid();
+
+            for (tx_in, utxo) in pending_tx.tx.input.iter().zip(pending_tx.spent_tx_outs) {
+                utxos.push(WatchOnlyUtxo {
+                    descriptor: watch_only_descriptor(&self.cfg.consensus.bitcoin_pks, &utxo.tweak),
+                    tweak: utxo.tweak,
+                    outpoint: tx_in.previous_output,
+                    value: utxo.value,
+                    pending_spend: Some(txid),
+                });
+            }
+        }
+
+        WatchOnlyExport {
+            network: self.cfg.consensus.network,
+            bitcoin_pks: self.cfg.consensus.bitcoin_pks.clone(),
+            threshold: self.cfg.consensus.bitcoin_pks.to_num_peers().threshold(),
+            utxos,
+        }
+    }
+
     async fn total_txs(&self, dbtx: &mut DatabaseTransaction<'_>) -> u64 {
         dbtx.find_by_prefix_sorted_descending(&TxInfoPrefix)
             .await
@@ -1449,8 +1875,11 @@ pub async fn tx_chain_ui(&self) -> Vec<TxInfo> {
     }
 
     /// Export recovery keys for federation shutdown. Returns None if the
-    /// federation wallet has not been initialized yet.
+    /// federation wallet has not been initialized yet or our key is held by
+    /// an external signer.
     pub async fn recovery_keys_ui(&self) -> Option<(BTreeMap<PeerId, String>, String)> {
+        let bitcoin_sk = self.cfg.private.bitcoin_sk?;
+
         let wallet = self.federation_wallet_ui().await?;
 
         let pks = self
@@ -1464,10 +1893,7 @@ pub async fn recovery_keys_ui(&self) -> Option<(BTreeMap<PeerId, String>, String
         let tweak = &Scalar::from_be_bytes(wallet.tweak.to_byte_array())
             .expect("Hash is within field order");
 
-        let sk = self
-            .cfg
-            .private
-            .bitcoin_sk
+        let sk = bitcoin_sk
             .add_tweak(tweak)
             .expect("Failed to tweak bitcoin secret key");
 
@@ -1476,3 +1902,36 @@ pub async fn recovery_keys_ui(&self) -> Option<(BTreeMap<PeerId, String>, String
         Some((pks, sk))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::Amount;
+    use fedimint_walletv2_common::FeePriority;
+
+    use super::{available_priority, stack_fee};
+
+    #[test]
+    fn lower_priorities_require_an_empty_chain() {
+        for priority in FeePriority::ALL {
+            assert_eq!(available_priority(priority, 0), priority);
+            assert_eq!(available_priority(priority, 1), FeePriority::NextBlock);
+        }
+    }
+
+    #[test]
+    fn next_transaction_pays_for_a_pending_economy_transaction() {
+        assert_eq!(stack_fee(200, 3000, &[]), Amount::from_sat(600));
+
+        // The economy transaction paid 1 sat/vB, lifting it to 3 sat/vB costs 400 sats
+        assert_eq!(
+            stack_fee(200, 3000, &[(200, Amount::from_sat(200))]),
+            Amount::from_sat(1000)
+        );
+
+        // A pending transaction that already pays the feerate is not paid for again
+        assert_eq!(
+            stack_fee(200, 3000, &[(200, Amount::from_sat(600))]),
+            Amount::from_sat(600)
+        );
+    }
+}
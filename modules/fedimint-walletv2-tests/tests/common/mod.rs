@@ -0,0 +1,91 @@
+//! Helpers shared by the test binaries of the walletv2 module
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use fedimint_client::ClientHandleArc;
+use fedimint_core::task::sleep_in_test;
+use fedimint_dummy_client::DummyClientInit;
+use fedimint_dummy_server::DummyInit;
+use fedimint_testing::btc::BitcoinTest;
+use fedimint_testing::fixtures::Fixtures;
+use fedimint_walletv2_client::{WalletClientInit, WalletClientModule};
+use fedimint_walletv2_server::{CONFIRMATION_FINALITY_DELAY, WalletInit};
+use tracing::info;
+
+pub fn fixtures() -> Fixtures {
+    Fixtures::new_primary(DummyClientInit, DummyInit).with_module(WalletClientInit, WalletInit)
+}
+
+// We need the consensus block count to reach a non-zero value before we send in
+// any funds such that the UTXO is tracked by the federation.
+pub async fn initialize_consensus(
+    client: &ClientHandleArc,
+    bitcoin: &Arc<dyn BitcoinTest>,
+) -> anyhow::Result<()> {
+    info!("Wait for the consensus to reach block count one");
+
+    bitcoin.mine_blocks(1 + CONFIRMATION_FINALITY_DELAY).await;
+
+    await_consensus_block_count(client, 1).await
+}
+
+pub async fn await_finality_delay(
+    client: &ClientHandleArc,
+    bitcoin: &Arc<dyn BitcoinTest>,
+) -> anyhow::Result<()> {
+    info!("Wait for the finality delay of six blocks...");
+
+    let current_consensus = client
+        .get_first_module::<WalletClientModule>()?
+        .block_count()
+        .await?;
+
+    bitcoin.mine_blocks(CONFIRMATION_FINALITY_DELAY).await;
+
+    await_consensus_block_count(client, current_consensus + CONFIRMATION_FINALITY_DELAY).await
+}
+
+pub async fn await_consensus_block_count(
+    client: &ClientHandleArc,
+    block_count: u64,
+) -> anyhow::Result<()> {
+    loop {
+        if client
+            .get_first_module::<WalletClientModule>()?
+            .block_count()
+            .await?
+            >= block_count
+        {
+            return Ok(());
+        }
+
+        sleep_in_test(
+            format!("Waiting for consensus to reach block count {block_count}"),
+            Duration::from_secs(1),
+        )
+        .await;
+    }
+}
+
+pub async fn await_federation_total_value(
+    client: &ClientHandleArc,
+    min_value: bitcoin::Amount,
+) -> anyhow::Result<()> {
+    loop {
+        let current_value = client
+            .get_first_module::<WalletClientModule>()?
+            .total_value()
+            .await?;
+
+        if current_value >= min_value {
+            return Ok(());
+        }
+
+        sleep_in_test(
+            format!("Waiting for federation total value of {current_value} to reach {min_value}"),
+            Duration::from_secs(1),
+        )
+        .await;
+    }
+}
@@ -0,0 +1,107 @@
+//! The external signer is configured through the environment of the guardian
+//! process, hence this test runs in a binary of its own where every guardian
+//! hands its signing over to the signer.
+
+mod common;
+
+use std::time::Duration;
+
+use anyhow::Context as _;
+use bitcoin::Amount;
+use fedimint_core::task::sleep_in_test;
+use fedimint_walletv2_client::{FinalSendOperationState, WalletClientModule};
+use fedimint_walletv2_common::KIND;
+use fedimint_walletv2_common::config::WalletConfig;
+use fedimint_walletv2_server::envs::FM_WALLETV2_EXTERNAL_SIGNER_DIR_ENV;
+use fedimint_walletv2_server::external_signer::DirectorySigner;
+use tracing::info;
+
+use crate::common::{
+    await_federation_total_value, await_finality_delay, fixtures, initialize_consensus,
+};
+
+#[tokio::test(flavor = "multi_thread")]
+async fn external_signer_signs_claims_and_pegouts() -> anyhow::Result<()> {
+    let signer_dir = tempfile::tempdir()?;
+
+    // SAFETY: this is the only test of the binary and the variable is set before
+    // the guardians are started
+    unsafe { std::env::set_var(FM_WALLETV2_EXTERNAL_SIGNER_DIR_ENV, signer_dir.path()) };
+
+    let fixtures = fixtures();
+
+    let fed = fixtures.new_fed_not_degraded().await;
+
+    let sks = fed
+        .server_configs()
+        .values()
+        .map(|cfg| {
+            let module_id = cfg.get_module_id_by_kind(KIND)?;
+
+            cfg.get_module_config_typed::<WalletConfig>(module_id)?
+                .private
+                .bitcoin_sk
+                .context("Test configs hold the bitcoin secret key")
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    let signer = DirectorySigner::new(signer_dir.path().to_path_buf())?;
+
+    let signer_task = tokio::spawn(async move {
+        loop {
+            signer
+                .sign_pending(&sks)
+                .expect("Failed to sign pending PSBTs");
+
+            sleep_in_test("Waiting for PSBTs to sign", Duration::from_millis(100)).await;
+        }
+    });
+
+    let client = fed.new_client().await;
+
+    let bitcoin = fixtures.bitcoin();
+
+    initialize_consensus(&client, &bitcoin).await?;
+
+    info!("Deposit funds into the federation...");
+
+    let federation_address = client
+        .get_first_module::<WalletClientModule>()?
+        .receive()
+        .await;
+
+    bitcoin
+        .send_and_mine_block(&federation_address, Amount::from_int_btc(1))
+        .await;
+
+    await_finality_delay(&client, &bitcoin).await?;
+
+    info!("Wait for the claim to be signed by the external signer...");
+
+    await_federation_total_value(&client, Amount::from_sat(99_000_000)).await?;
+
+    info!("Send funds out of the federation...");
+
+    let address = bitcoin.get_new_address().await.as_unchecked().clone();
+
+    let send_op = client
+        .get_first_module::<WalletClientModule>()?
+        .send(
+            address,
+            Amount::from_sat(100_000),
+            None,
+            serde_json::Value::Null,
+        )
+        .await?;
+
+    let state = client
+        .get_first_module::<WalletClientModule>()?
+        .await_final_send_operation_state(send_op)
+        .await?;
+
+    assert!(matches!(state, FinalSendOperationState::Success(_)));
+
+    signer_task.abort();
+
+    Ok(())
+}
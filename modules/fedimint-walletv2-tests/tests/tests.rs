@@ -1,16 +1,13 @@
+mod common;
+
 use std::pin::pin;
-use std::sync::Arc;
 use std::time::Duration;
 
 use async_stream::stream;
 use bitcoin::Amount;
 use fedimint_client::ClientHandleArc;
 use fedimint_core::task::sleep_in_test;
-use fedimint_dummy_client::DummyClientInit;
-use fedimint_dummy_server::DummyInit;
 use fedimint_eventlog::{Event, EventLogEntry, EventLogId};
-use fedimint_testing::btc::BitcoinTest;
-use fedimint_testing::fixtures::Fixtures;
 use fedimint_walletv2_client::events::{
     ReceivePaymentEvent, ReceivePaymentUpdateEvent, SendPaymentEvent, SendPaymentStatus,
     SendPaymentUpdateEvent,
@@ -18,11 +15,15 @@
 use fedimint_walletv2_client::{
     FinalSendOperationState, SendError, WalletClientInit, WalletClientModule,
 };
-use fedimint_walletv2_common::KIND;
-use fedimint_walletv2_server::{CONFIRMATION_FINALITY_DELAY, WalletInit};
+use fedimint_walletv2_common::{FeePriority, KIND};
+use fedimint_walletv2_server::WalletInit;
 use futures::StreamExt;
 use tracing::info;
 
+use crate::common::{
+    await_federation_total_value, await_finality_delay, fixtures, initialize_consensus,
+};
+
 #[derive(Debug)]
 enum WalletEvent {
     Send(SendPaymentEvent),
@@ -77,83 +78,6 @@ fn try_parse_wallet_event(entry: &EventLogEntry) -> Option<WalletEvent> {
     None
 }
 
-fn fixtures() -> Fixtures {
-    Fixtures::new_primary(DummyClientInit, DummyInit).with_module(WalletClientInit, WalletInit)
-}
-
-// We need the consensus block count to reach a non-zero value before we send in
-// any funds such that the UTXO is tracked by the federation.
-async fn initialize_consensus(
-    client: &ClientHandleArc,
-    bitcoin: &Arc<dyn BitcoinTest>,
-) -> anyhow::Result<()> {
-    info!("Wait for the consensus to reach block count one");
-
-    bitcoin.mine_blocks(1 + CONFIRMATION_FINALITY_DELAY).await;
-
-    await_consensus_block_count(client, 1).await
-}
-
-async fn await_finality_delay(
-    client: &ClientHandleArc,
-    bitcoin: &Arc<dyn BitcoinTest>,
-) -> anyhow::Result<()> {
-    info!("Wait for the finality delay of six blocks...");
-
-    let current_consensus = client
-        .get_first_module::<WalletClientModule>()?
-        .block_count()
-        .await?;
-
-    bitcoin.mine_blocks(CONFIRMATION_FINALITY_DELAY).await;
-
-    await_consensus_block_count(client, current_consensus + CONFIRMATION_FINALITY_DELAY).await
-}
-
-async fn await_consensus_block_count(
-    client: &ClientHandleArc,
-    block_count: u64,
-) -> anyhow::Result<()> {
-    loop {
-        if client
-            .get_first_module::<WalletClientModule>()?
-            .block_count()
-            .await?
-            >= block_count
-        {
-            return Ok(());
-        }
-
-        sleep_in_test(
-            format!("Waiting for consensus to reach block count {block_count}"),
-            Duration::from_secs(1),
-        )
-        .await;
-    }
-}
-
-async fn await_federation_total_value(
-    client: &ClientHandleArc,
-    min_value: bitcoin::Amount,
-) -> anyhow::Result<()> {
-    loop {
-        let current_value = client
-            .get_first_module::<WalletClientModule>()?
-            .total_value()
-            .await?;
-
-        if current_value >= min_value {
-            return Ok(());
-        }
-
-        sleep_in_test(
-            format!("Waiting for federation total value of {current_value} to reach {min_value}"),
-            Duration::from_secs(1),
-        )
-        .await;
-    }
-}
-
 #[tokio::test(flavor = "multi_thread")]
 async fn fee_exceeds_one_bitcoin_with_many_pending_txs() -> anyhow::Result<()> {
     let fixtures = fixtures();
@@ -238,6 +162,170 @@ async fn fee_exceeds_one_bitcoin_with_many_pending_txs() -> anyhow::Result<()> {
     panic!("Transaction fee did not exceed one bitcoin")
 }
 
+#[tokio::test(flavor = "multi_thread")]
+async fn watch_only_export_covers_federation_wallet() -> anyhow::Result<()> {
+    let fixtures = fixtures();
+    let fed = fixtures.new_fed_not_degraded().await;
+    let client = fed.new_client().await;
+    let bitcoin = fixtures.bitcoin();
+
+    initialize_consensus(&client, &bitcoin).await?;
+
+    let module = client.get_first_module::<WalletClientModule>()?;
+
+    assert!(module.watch_only_export().await?.utxos.is_empty());
+
+    let federation_address = module.receive().await;
+
+    bitcoin
+        .send_and_mine_block(&federation_address, Amount::from_sat(1_000_000))
+        .await;
+
+    await_finality_delay(&client, &bitcoin).await?;
+
+    await_federation_total_value(&client, Amount::from_sat(900_000)).await?;
+
+    let export = module.watch_only_export().await?;
+
+    assert_eq!(export.network, bitcoin::Network::Regtest);
+
+    let federation_wallet = export
+        .utxos
+        .iter()
+        .find(|utxo| utxo.pending_spend.is_none())
+        .expect("Federation wallet UTXO is exported");
+
+    assert_eq!(federation_wallet.value, module.total_value().await?);
+    assert!(federation_wallet.descriptor.starts_with("wsh(sortedmulti("));
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn send_fee_options_trade_speed_for_cost() -> anyhow::Result<()> {
+    let fixtures = fixtures();
+    let fed = fixtures.new_fed_not_degraded().await;
+    let client = fed.new_client().await;
+    let bitcoin = fixtures.bitcoin();
+
+    initialize_consensus(&client, &bitcoin).await?;
+
+    let module = client.get_first_module::<WalletClientModule>()?;
+
+    info!("Deposit funds into the federation...");
+
+    bitcoin
+        .send_and_mine_block(&module.receive().await, Amount::from_int_btc(1))
+        .await;
+
+    await_finality_delay(&client, &bitcoin).await?;
+
+    await_federation_total_value(&client, Amount::from_sat(99_000_000)).await?;
+
+    info!("Wait for the claim to confirm, until then only the next block fee is offered...");
+
+    while !module.pending_tx_chain().await?.is_empty() {
+        bitcoin.mine_blocks(1).await;
+
+        sleep_in_test(
+            "Waiting for the pending transaction chain to confirm",
+            Duration::from_secs(1),
+        )
+        .await;
+    }
+
+    let options = module.send_fee_options().await?;
+
+    assert_eq!(
+        options.keys().copied().collect::<Vec<_>>(),
+        FeePriority::ALL
+    );
+    assert_eq!(options[&FeePriority::NextBlock], module.send_fee().await?);
+    assert!(options[&FeePriority::SixBlocks] <= options[&FeePriority::NextBlock]);
+    assert!(options[&FeePriority::Economy] <= options[&FeePriority::SixBlocks]);
+
+    info!("Send paying the economy fee...");
+
+    let address = bitcoin.get_new_address().await.as_unchecked().clone();
+
+    let send_op = module
+        .send(
+            address,
+            Amount::from_sat(100_000),
+            Some(options[&FeePriority::Economy]),
+            serde_json::Value::Null,
+        )
+        .await?;
+
+    assert!(matches!(
+        module.await_final_send_operation_state(send_op).await?,
+        FinalSendOperationState::Success(_)
+    ));
+
+    info!("A pending economy send must not hold up the next transaction...");
+
+    assert!(!module.pending_tx_chain().await?.is_empty());
+
+    let next_block_fee = module.send_fee().await?;
+
+    for fee in module.send_fee_options().await?.values() {
+        assert_eq!(*fee, next_block_fee);
+    }
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn labeled_receive_attributes_deposits_and_detects_reuse() -> anyhow::Result<()> {
+    let fixtures = fixtures();
+    let fed = fixtures.new_fed_not_degraded().await;
+    let client = fed.new_client().await;
+    let bitcoin = fixtures.bitcoin();
+
+    initialize_consensus(&client, &bitcoin).await?;
+
+    let module = client.get_first_module::<WalletClientModule>()?;
+
+    let address = module.receive_with_label("customer-1".to_string()).await?;
+
+    assert_ne!(module.receive().await, address);
+
+    let mut events = pin!(wallet_event_stream(&client));
+
+    for address_reused in [false, true] {
+        bitcoin
+            .send_and_mine_block(&address, Amount::from_sat(1_000_000))
+            .await;
+
+        await_finality_delay(&client, &bitcoin).await?;
+
+        let Some(WalletEvent::Receive(receive)) = events.next().await else {
+            panic!("Expected Receive event");
+        };
+
+        assert_eq!(receive.address, address.as_unchecked().clone());
+        assert_eq!(receive.label.as_deref(), Some("customer-1"));
+        assert_eq!(receive.address_reused, address_reused);
+
+        let Some(WalletEvent::ReceiveStatus(status)) = events.next().await else {
+            panic!("Expected ReceiveStatus event");
+        };
+        assert_eq!(status.operation_id, receive.operation_id);
+    }
+
+    let labeled = module
+        .receive_addresses()
+        .await
+        .into_iter()
+        .find(|receive_address| receive_address.label.is_some())
+        .expect("Labeled address is listed");
+
+    assert_eq!(labeled.address, address.as_unchecked().clone());
+    assert!(labeled.used);
+
+    Ok(())
+}
+
 #[tokio::test(flavor = "multi_thread")]
 async fn send_to_a_mainnet_address_is_rejected() -> anyhow::Result<()> {
     let fixtures = fixtures();
@@ -670,6 +758,8 @@ async fn test_server_db_migrations() -> anyhow::Result<()> {
                             "the federation wallet must round-trip unchanged"
                         );
                     }
+                    // Introduced after the v0 snapshot, nothing was seeded
+                    DbKeyPrefix::PriorityFeeRateVote | DbKeyPrefix::ConsensusVersionVote => {}
                 }
             }
 
@@ -743,6 +833,11 @@ async fn test_client_db_migrations() -> anyhow::Result<()> {
                                  got {indices:?}"
                             );
                         }
+                        // Introduced after the v0 snapshot, nothing was seeded
+                        db::DbKeyPrefix::AddressLabel
+                        | db::DbKeyPrefix::UsedAddressIndex
+                        | db::DbKeyPrefix::ReceivedOutput
+                        | db::DbKeyPrefix::GapLimit => {}
                     }
                 }
 
@@ -271,6 +271,7 @@ let
         bitcoind
         jq
         lnd
+        clightning
         netcat
         perl
         esplora-electrs
@@ -965,6 +966,10 @@ in
       pkg = gateway-pkgs;
       bin = "gateway-cli";
     };
+    fedimint-cln-plugin = pickBinary {
+      pkg = gateway-pkgs;
+      bin = "fedimint-cln-plugin";
+    };
 
     fedimint-recoverytool = pickBinary {
       pkg = fedimint-pkgs;
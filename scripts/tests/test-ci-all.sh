@@ -66,6 +66,12 @@ function reconnect_test() {
 }
 export -f reconnect_test
 
+function bitcoin_p2p_backend_test() {
+  # takes a peer offline on its own, so we need to override FM_OFFLINE_NODES
+  fm-run-test "${FUNCNAME[0]}" env FM_OFFLINE_NODES=0 ./scripts/tests/bitcoin-p2p-backend-test.sh
+}
+export -f bitcoin_p2p_backend_test
+
 function ln_reconnect_test() {
   fm-run-test "${FUNCNAME[0]}" ./scripts/tests/lightning-reconnect-test.sh
 }
@@ -113,6 +119,14 @@ function gw_liquidity_test_mintv2() {
 }
 export -f gw_liquidity_test_mintv2
 
+function gw_cln_test() {
+  # older gatewayd binaries don't support Core Lightning, so we skip for backwards-compatibility tests
+  if [ -z "${FM_BACKWARDS_COMPATIBILITY_TEST:-}" ]; then
+    fm-run-test "${FUNCNAME[0]}" ./scripts/tests/gateway-module-test.sh cln-test
+  fi
+}
+export -f gw_cln_test
+
 function gw_esplora_test() {
   fm-run-test "${FUNCNAME[0]}" ./scripts/tests/gateway-module-test.sh esplora-test
 }
@@ -473,6 +487,7 @@ tests_to_run_in_parallel+=(
   "latency_fm_pay"
   "latency_restore"
   "reconnect_test"
+  "bitcoin_p2p_backend_test"
   "ln_reconnect_test"
   "gw_reboot_test"
   "gw_config_test_lnd"
@@ -480,6 +495,7 @@ tests_to_run_in_parallel+=(
   "gw_liquidity_test"
   "gw_liquidity_test_walletv2"
   "gw_liquidity_test_mintv2"
+  "gw_cln_test"
   "lnv2_module_gateway_registration"
   "lnv2_module_payments"
   "lnv2_module_duplicate_payment"